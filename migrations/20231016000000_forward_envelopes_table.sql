@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS forward_envelopes (
+  id              INTEGER PRIMARY KEY AUTOINCREMENT,
+  received_at     INTEGER, -- milliseconds since epoch
+  organization_id INTEGER,
+  project_id      INTEGER,
+  project_key     TEXT,
+  key_id          INTEGER,
+  envelope        BLOB
+);
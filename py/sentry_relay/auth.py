@@ -102,14 +102,35 @@ def create_register_challenge(data, signature, secret, max_age=60):
     }
 
 
-def validate_register_response(data, signature, secret, max_age=60):
-    response_json = rustcall(
-        lib.relay_validate_register_response,
-        make_buf(data),
-        encode_str(signature),
-        encode_str(secret),
-        max_age,
-    )
+def validate_register_response(
+    data, signature, secret, max_age=60, nonce_store=None, nonce_capacity=100000
+):
+    """
+    Validates a register response.
+
+    If a path to a `nonce_store` is given, the nonce of the response is recorded in the store and
+    responses that have been used before are rejected with `UnpackErrorReplayed`. If the store holds
+    `nonce_capacity` nonces that are younger than `max_age`, responses are rejected with
+    `UnpackErrorNonceStoreFull`.
+    """
+    if nonce_store is None:
+        response_json = rustcall(
+            lib.relay_validate_register_response,
+            make_buf(data),
+            encode_str(signature),
+            encode_str(secret),
+            max_age,
+        )
+    else:
+        response_json = rustcall(
+            lib.relay_validate_register_response_once,
+            make_buf(data),
+            encode_str(signature),
+            encode_str(secret),
+            max_age,
+            encode_str(nonce_store),
+            nonce_capacity,
+        )
 
     response = json.loads(decode_str(response_json, free=True))
     return {
@@ -24,6 +24,11 @@ class DataCategory(IntEnum):
     PROFILE_INDEXED = 11
     SPAN = 12
     MONITOR_SEAT = 13
+    METRIC_BUCKET = 14
+    LOG = 15
+    PROFILE_DURATION = 16
+    LOG_BYTE = 17
+    FEEDBACK = 18
     UNKNOWN = -1
     # end generated
 
@@ -17,6 +17,7 @@ __all__ = [
     "split_chunks",
     "meta_with_chunks",
     "StoreNormalizer",
+    "normalize_span",
     "GeoIpLookup",
     "is_glob_match",
     "is_codeowners_path_match",
@@ -24,10 +25,14 @@ __all__ = [
     "validate_pii_config",
     "convert_datascrubbing_config",
     "pii_strip_event",
+    "pii_scrub_attachment",
     "pii_selector_suggestions_from_event",
     "VALID_PLATFORMS",
     "validate_sampling_condition",
     "validate_sampling_configuration",
+    "evaluate_sampling",
+    "validate_sampling_rules",
+    "validate_metric_extraction_config",
     "validate_project_config",
     "normalize_global_config",
 ]
@@ -118,6 +123,20 @@ class StoreNormalizer(RustObject):
         return json.loads(decode_str(rv, free=True))
 
 
+def normalize_span(span, config=None):
+    """
+    Normalize a single span with Relay's span normalization.
+
+    This scrubs the span description, infers the span status from its HTTP
+    status code, and extracts span tags into the span's ``data``. The optional
+    config accepts ``maxTagValueLength`` and ``spanDescriptionRules``.
+    """
+    raw_config = encode_str("" if config is None else json.dumps(config))
+    raw_span = encode_str(json.dumps(span))
+    raw_rv = rustcall(lib.relay_normalize_span, raw_config, raw_span)
+    return json.loads(decode_str(raw_rv, free=True))
+
+
 def _serialize_event(event):
     raw_event = json.dumps(event, ensure_ascii=False)
     if isinstance(raw_event, str):
@@ -200,6 +219,30 @@ def pii_strip_event(config, event):
     return json.loads(decode_str(raw_rv, free=True))
 
 
+def pii_scrub_attachment(config, data, filename="", content_type=""):
+    """
+    Scrub an attachment using new PII stripping config.
+
+    Attachments with the ``application/x-dmp`` content type are scrubbed as
+    minidumps. Returns a tuple of the scrubbed bytes and a report of the
+    changes.
+    """
+    buf = bytearray(data)
+    raw_data = ffi.new("RelayBuf *")
+    raw_data.data = ffi.from_buffer(buf)
+    raw_data.len = len(buf)
+
+    raw_rv = rustcall(
+        lib.relay_pii_scrub_attachment,
+        encode_str(json.dumps(config)),
+        encode_str(filename),
+        encode_str(content_type),
+        raw_data,
+    )
+    report = json.loads(decode_str(raw_rv, free=True))
+    return bytes(buf), report
+
+
 def pii_selector_suggestions_from_event(event):
     """
     Walk through the event and collect selectors that can be applied to it in a
@@ -249,6 +292,58 @@ def validate_sampling_configuration(condition):
         raise ValueError(error)
 
 
+def validate_sampling_rules(config):
+    """
+    Validate a dynamic sampling config with all its rules.
+
+    The parameter is a string containing the config as JSON. Returns a list of
+    errors, each with the ``path`` to the invalid field and a ``message``. The
+    list is empty if the config is valid.
+    """
+    assert isinstance(config, str)
+    raw_rv = rustcall(lib.relay_validate_sampling_rules, encode_str(config))
+    return json.loads(decode_str(raw_rv, free=True))
+
+
+def validate_metric_extraction_config(config):
+    """
+    Validate a metric extraction config, including conditions, globs, and tag
+    specs.
+
+    The parameter is a string containing the config as JSON. Returns a list of
+    errors, each with the ``path`` to the invalid field and a ``message``. The
+    list is empty if the config is valid.
+    """
+    assert isinstance(config, str)
+    raw_rv = rustcall(
+        lib.relay_validate_metric_extraction_config, encode_str(config)
+    )
+    return json.loads(decode_str(raw_rv, free=True))
+
+
+def evaluate_sampling(config=None, root_config=None, dsc=None, event=None):
+    """
+    Evaluate dynamic sampling rules with the same matching logic as Relay.
+
+    Transaction rules from ``config`` are matched against the event, trace rules
+    from ``root_config`` against the dynamic sampling context. Returns a dict
+    with the ``sample_rate`` and ``matched_rule_ids``, or ``None`` if no rule
+    matches.
+    """
+
+    def encode_optional(value):
+        return encode_str("" if value is None else json.dumps(value))
+
+    raw_rv = rustcall(
+        lib.relay_evaluate_sampling,
+        encode_optional(config),
+        encode_optional(root_config),
+        encode_optional(dsc),
+        encode_optional(event),
+    )
+    return json.loads(decode_str(raw_rv, free=True))
+
+
 def validate_project_config(config, strict: bool):
     """Validate the whole project config.
 
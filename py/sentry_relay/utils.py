@@ -31,9 +31,13 @@ def rustcall(func, *args):
     msg = lib.relay_err_get_last_message()
     cls = exceptions_by_code.get(err, RelayError)
     exc = cls(decode_str(msg, free=True))
-    backtrace = decode_str(lib.relay_err_get_backtrace(), free=True)
-    if backtrace:
-        exc.rust_info = backtrace
+    panic_backtrace = decode_str(lib.relay_err_get_panic_backtrace(), free=True)
+    if panic_backtrace:
+        exc.rust_info = f"stacktrace: {panic_backtrace}"
+    else:
+        backtrace = decode_str(lib.relay_err_get_backtrace(), free=True)
+        if backtrace:
+            exc.rust_info = backtrace
     raise exc
 
 
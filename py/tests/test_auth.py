@@ -77,6 +77,27 @@ def test_register_response():
     assert resp["version"] == RELAY_VERSION
 
 
+def test_register_response_replay(tmp_path):
+    nonce_store = str(tmp_path / "nonces.json")
+    resp = sentry_relay.validate_register_response(
+        RESPONSE,
+        RESPONSE_SIG,
+        UPSTREAM_SECRET,
+        max_age=0,
+        nonce_store=nonce_store,
+    )
+    assert resp["token"] == TOKEN
+
+    with pytest.raises(sentry_relay.UnpackErrorReplayed):
+        sentry_relay.validate_register_response(
+            RESPONSE,
+            RESPONSE_SIG,
+            UPSTREAM_SECRET,
+            max_age=0,
+            nonce_store=nonce_store,
+        )
+
+
 def test_is_version_supported():
     assert sentry_relay.is_version_supported("99.99.99")
 
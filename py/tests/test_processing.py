@@ -83,6 +83,19 @@ def test_basic_store_normalization():
     assert "received" in event
 
 
+def test_normalize_span():
+    span = sentry_relay.normalize_span(
+        {
+            "op": "http.client",
+            "description": "GET https://example.com/users/123",
+            "data": {"status_code": "503", "http.method": "GET"},
+        }
+    )
+    assert span["status"] == "unavailable"
+    assert span["data"]["description.scrubbed"] == "GET https://example.com"
+    assert span["data"]["span.module"] == "http"
+
+
 def test_legacy_json():
     normalizer = sentry_relay.StoreNormalizer(project_id=1)
     event = normalizer.normalize_event(raw_event='{"extra":{"x":NaN}}')
@@ -171,6 +184,25 @@ def test_pii_strip_event():
     assert sentry_relay.pii_strip_event({}, event) == event
 
 
+def test_pii_scrub_attachment():
+    config = {"applications": {"$binary": ["@ip:mask"]}}
+    data, report = sentry_relay.pii_scrub_attachment(
+        config, b"from 127.0.0.1", filename="log.txt"
+    )
+    assert data == b"from *********"
+    assert report == {"changed": True, "minidump": False}
+
+
+def test_pii_scrub_attachment_invalid_minidump():
+    data, report = sentry_relay.pii_scrub_attachment(
+        {}, b"hello", content_type="application/x-dmp"
+    )
+    assert data == b"hello"
+    assert not report["changed"]
+    assert not report["minidump"]
+    assert report["minidump_error"]
+
+
 def test_pii_selector_suggestions_from_event():
     event = {"logentry": {"formatted": "hi"}}
     assert sentry_relay.pii_selector_suggestions_from_event(event) == [
@@ -271,6 +303,72 @@ def test_validate_sampling_configuration():
     sentry_relay.validate_sampling_configuration(config)
 
 
+def test_validate_sampling_rules():
+    config = {
+        "rulesV2": [
+            {
+                "type": "trace",
+                "samplingValue": {"type": "sampleRate", "value": 2.0},
+                "condition": {"op": "and", "inner": []},
+                "id": 1,
+            }
+        ]
+    }
+    errors = sentry_relay.validate_sampling_rules(json.dumps(config))
+    assert errors == [
+        {
+            "path": "rulesV2.0.samplingValue.value",
+            "message": "sample rate must be between 0 and 1",
+        }
+    ]
+
+    config["rulesV2"][0]["samplingValue"]["value"] = 0.5
+    assert sentry_relay.validate_sampling_rules(json.dumps(config)) == []
+
+
+def test_validate_metric_extraction_config():
+    config = {
+        "version": 1,
+        "metrics": [
+            {
+                "category": "transaction",
+                "mri": "c:transactions/count_per_root_project@none",
+                "tags": [{"key": "decision", "value": "keep"}],
+            }
+        ],
+    }
+    assert sentry_relay.validate_metric_extraction_config(json.dumps(config)) == []
+
+    config["metrics"][0]["mri"] = "invalid"
+    errors = sentry_relay.validate_metric_extraction_config(json.dumps(config))
+    assert [e["path"] for e in errors] == ["metrics.0.mri"]
+
+
+def test_evaluate_sampling():
+    root_config = {
+        "rulesV2": [
+            {
+                "type": "trace",
+                "samplingValue": {"type": "sampleRate", "value": 0.5},
+                "condition": {"op": "eq", "name": "trace.release", "value": ["1.0"]},
+                "id": 1,
+            }
+        ]
+    }
+    dsc = {
+        "trace_id": "67e5504410b1426f9247bb680e5fe0c8",
+        "public_key": "a94ae32be2584e0bbd7a4cbb95971fee",
+        "release": "1.0",
+    }
+
+    result = sentry_relay.evaluate_sampling(root_config=root_config, dsc=dsc)
+    assert result["sample_rate"] == 0.5
+    assert result["matched_rule_ids"] == [1]
+
+    dsc["release"] = "2.0"
+    assert sentry_relay.evaluate_sampling(root_config=root_config, dsc=dsc) is None
+
+
 def test_validate_project_config():
     config = {"allowedDomains": ["*"], "trustedRelays": [], "piiConfig": None}
     # Does not raise:
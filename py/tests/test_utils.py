@@ -1,10 +1,24 @@
 import pytest
 
 from sentry_relay._lowlevel import lib
-from sentry_relay.utils import rustcall
-from sentry_relay.exceptions import Panic
+from sentry_relay.utils import encode_str, rustcall
+from sentry_relay.exceptions import InvalidVersionError, Panic
 
 
 def test_panic():
     with pytest.raises(Panic):
         rustcall(lib.relay_test_panic)
+
+
+def test_panic_backtrace():
+    # `RUST_BACKTRACE` is enabled when the library is loaded.
+    with pytest.raises(Panic) as e:
+        rustcall(lib.relay_test_panic)
+    assert e.value.code == lib.RELAY_ERROR_CODE_PANIC
+    assert e.value.rust_info.startswith("stacktrace:")
+
+
+def test_error_code():
+    with pytest.raises(InvalidVersionError) as e:
+        rustcall(lib.relay_compare_versions, encode_str("x"), encode_str("1.0"))
+    assert e.value.code == lib.RELAY_ERROR_CODE_INVALID_VERSION_ERROR
@@ -0,0 +1,289 @@
+use data_encoding::BASE64URL_NOPAD;
+use ed25519_dalek::Verifier;
+use hmac::{Hmac, Mac};
+use relay_common::time::UnixTimestamp;
+use serde::{Deserialize, Deserializer};
+use sha2::Sha256;
+
+use crate::PublicKey;
+
+/// A key to verify the signature of a JSON Web Token.
+#[derive(Clone, Debug)]
+pub enum JwtKey {
+    /// A shared secret for tokens signed with `HS256`.
+    Hmac(Vec<u8>),
+    /// An Ed25519 public key for tokens signed with `EdDSA`.
+    Ed25519(PublicKey),
+}
+
+impl JwtKey {
+    /// Returns `true` if this key can verify signatures of the given algorithm.
+    fn supports(&self, algorithm: &str) -> bool {
+        matches!(
+            (self, algorithm),
+            (Self::Hmac(_), "HS256") | (Self::Ed25519(_), "EdDSA")
+        )
+    }
+
+    /// Verifies the signature over the signing input.
+    fn verify(&self, input: &[u8], signature: &[u8]) -> bool {
+        match self {
+            Self::Hmac(secret) => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes variable keys");
+                mac.update(input);
+                mac.verify_slice(signature).is_ok()
+            }
+            Self::Ed25519(key) => ed25519_dalek::Signature::from_slice(signature)
+                .map_or(false, |sig| key.inner.verify(input, &sig).is_ok()),
+        }
+    }
+}
+
+/// Raised if a JSON Web Token cannot be validated.
+#[derive(Debug, Eq, PartialEq, thiserror::Error)]
+pub enum JwtError {
+    /// The token is not made up of three base64url encoded segments.
+    #[error("malformed token")]
+    Malformed,
+    /// The token header declares an algorithm that is not supported or not configured.
+    #[error("unsupported signature algorithm")]
+    UnsupportedAlgorithm,
+    /// None of the configured keys matches the signature.
+    #[error("invalid signature")]
+    BadSignature,
+    /// The token does not contain an expiry or is expired.
+    #[error("token expired")]
+    Expired,
+    /// The token is not valid yet.
+    #[error("token not valid yet")]
+    NotYetValid,
+    /// The token is not issued for the expected audience.
+    #[error("invalid audience")]
+    BadAudience,
+}
+
+/// Options for [`decode_jwt`].
+#[derive(Clone, Copy, Debug)]
+pub struct JwtValidation<'a> {
+    /// The audience that must be listed in the `aud` claim.
+    pub audience: &'a str,
+    /// Tolerance in seconds for clock skew when checking `exp` and `nbf`.
+    pub leeway: u64,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+/// Registered and custom claims of a validated JSON Web Token.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct JwtClaims {
+    /// The subject of the token, usually the user or service it was issued for.
+    #[serde(default)]
+    pub sub: Option<String>,
+    /// The audiences this token was issued for.
+    #[serde(default, deserialize_with = "deserialize_audience")]
+    pub aud: Vec<String>,
+    /// The expiry of the token as UNIX timestamp.
+    #[serde(default)]
+    pub exp: Option<u64>,
+    /// The time before which the token must not be accepted as UNIX timestamp.
+    #[serde(default)]
+    pub nbf: Option<u64>,
+    /// Space-separated list of scopes granted to this token.
+    #[serde(default)]
+    pub scope: String,
+}
+
+impl JwtClaims {
+    /// Returns `true` if the token was granted the given scope.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.split_whitespace().any(|s| s == scope)
+    }
+}
+
+/// The `aud` claim is either a single string or a list of strings.
+fn deserialize_audience<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Audience {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Audience::deserialize(deserializer)? {
+        Audience::One(aud) => vec![aud],
+        Audience::Many(aud) => aud,
+    })
+}
+
+/// Validates a JSON Web Token and returns its claims.
+///
+/// The signature must be valid for one of the given keys. Supported algorithms are `HS256` and
+/// `EdDSA` with Ed25519 keys. Tokens must carry an `exp` claim and list the expected audience in
+/// `aud`.
+pub fn decode_jwt(
+    token: &str,
+    keys: &[JwtKey],
+    validation: JwtValidation<'_>,
+) -> Result<JwtClaims, JwtError> {
+    let mut segments = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) = (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) else {
+        return Err(JwtError::Malformed);
+    };
+
+    let decode = |segment: &str| {
+        BASE64URL_NOPAD
+            .decode(segment.as_bytes())
+            .map_err(|_| JwtError::Malformed)
+    };
+
+    let header: JwtHeader =
+        serde_json::from_slice(&decode(header)?).map_err(|_| JwtError::Malformed)?;
+
+    let mut keys = keys
+        .iter()
+        .filter(|key| key.supports(&header.alg))
+        .peekable();
+    if keys.peek().is_none() {
+        return Err(JwtError::UnsupportedAlgorithm);
+    }
+
+    let signing_input = &token[..token.rfind('.').unwrap_or_default()];
+    let signature = decode(signature)?;
+    if !keys.any(|key| key.verify(signing_input.as_bytes(), &signature)) {
+        return Err(JwtError::BadSignature);
+    }
+
+    let claims: JwtClaims =
+        serde_json::from_slice(&decode(payload)?).map_err(|_| JwtError::Malformed)?;
+
+    let now = UnixTimestamp::now().as_secs();
+    match claims.exp {
+        Some(exp) if exp.saturating_add(validation.leeway) > now => (),
+        _ => return Err(JwtError::Expired),
+    }
+
+    if let Some(nbf) = claims.nbf {
+        if nbf > now.saturating_add(validation.leeway) {
+            return Err(JwtError::NotYetValid);
+        }
+    }
+
+    if !claims.aud.iter().any(|aud| aud == validation.audience) {
+        return Err(JwtError::BadAudience);
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::Signer;
+    use serde_json::json;
+
+    use super::*;
+
+    const VALIDATION: JwtValidation<'static> = JwtValidation {
+        audience: "relay-admin",
+        leeway: 0,
+    };
+
+    fn encode(alg: &str, claims: serde_json::Value, sign: impl Fn(&[u8]) -> Vec<u8>) -> String {
+        let header =
+            BASE64URL_NOPAD.encode(json!({"alg": alg, "typ": "JWT"}).to_string().as_bytes());
+        let payload = BASE64URL_NOPAD.encode(claims.to_string().as_bytes());
+        let input = format!("{header}.{payload}");
+        let signature = BASE64URL_NOPAD.encode(&sign(input.as_bytes()));
+        format!("{input}.{signature}")
+    }
+
+    fn hs256(secret: &[u8], claims: serde_json::Value) -> String {
+        encode("HS256", claims, |input| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+            mac.update(input);
+            mac.finalize().into_bytes().to_vec()
+        })
+    }
+
+    fn future() -> u64 {
+        UnixTimestamp::now().as_secs() + 3600
+    }
+
+    #[test]
+    fn test_decode_hs256() {
+        let keys = [JwtKey::Hmac(b"secret".to_vec())];
+        let claims = json!({
+            "sub": "ops",
+            "aud": "relay-admin",
+            "exp": future(),
+            "scope": "admin:read admin:write",
+        });
+
+        let decoded = decode_jwt(&hs256(b"secret", claims.clone()), &keys, VALIDATION).unwrap();
+        assert_eq!(decoded.sub.as_deref(), Some("ops"));
+        assert!(decoded.has_scope("admin:write"));
+        assert!(!decoded.has_scope("admin"));
+
+        assert_eq!(
+            decode_jwt(&hs256(b"wrong", claims), &keys, VALIDATION),
+            Err(JwtError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn test_decode_eddsa() {
+        let (secret, public) = crate::generate_key_pair();
+        let keys = [JwtKey::Ed25519(public)];
+        let claims = json!({"aud": ["other", "relay-admin"], "exp": future()});
+        let token = encode("EdDSA", claims, |input| {
+            secret.inner.sign(input).to_bytes().to_vec()
+        });
+
+        assert!(decode_jwt(&token, &keys, VALIDATION).is_ok());
+
+        // Keys for a different algorithm cannot verify the token.
+        let hmac_keys = [JwtKey::Hmac(b"secret".to_vec())];
+        assert_eq!(
+            decode_jwt(&token, &hmac_keys, VALIDATION),
+            Err(JwtError::UnsupportedAlgorithm)
+        );
+    }
+
+    #[test]
+    fn test_decode_claims() {
+        let keys = [JwtKey::Hmac(b"secret".to_vec())];
+        let decode = |claims| decode_jwt(&hs256(b"secret", claims), &keys, VALIDATION);
+
+        assert_eq!(
+            decode(json!({"aud": "relay-admin"})),
+            Err(JwtError::Expired)
+        );
+        assert_eq!(
+            decode(json!({"aud": "relay-admin", "exp": 1})),
+            Err(JwtError::Expired)
+        );
+        assert_eq!(
+            decode(json!({"aud": "relay-admin", "exp": future(), "nbf": future()})),
+            Err(JwtError::NotYetValid)
+        );
+        assert_eq!(
+            decode(json!({"aud": "sentry", "exp": future()})),
+            Err(JwtError::BadAudience)
+        );
+        assert_eq!(
+            decode_jwt("not-a-token", &keys, VALIDATION),
+            Err(JwtError::Malformed)
+        );
+    }
+}
@@ -5,7 +5,8 @@
 //! [`RelayId`], which is included in the request signature and headers.
 //!
 //! Relay uses Ed25519 at the moment. This is considered an implementation detail and is subject to
-//! change at any time. Do not rely on a specific signing mechanism.
+//! change at any time. Do not rely on a specific signing mechanism. The algorithm of a signature is
+//! declared in its header, see [`SignatureAlgorithm`].
 //!
 //! # Generating Credentials
 //!
@@ -34,9 +35,15 @@ use rand::{thread_rng, RngCore};
 use relay_common::time::UnixTimestamp;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use sha2::Sha512;
+use sha2::{Digest, Sha512};
 use uuid::Uuid;
 
+mod jwt;
+mod nonce;
+
+pub use self::jwt::*;
+pub use self::nonce::*;
+
 include!(concat!(env!("OUT_DIR"), "/constants.gen.rs"));
 
 /// The latest Relay version known to this Relay. This is the current version.
@@ -145,6 +152,39 @@ pub enum UnpackError {
     /// Raised on unpacking if the data is too old.
     #[error("signature is too old")]
     SignatureExpired,
+    /// Raised if a register response has been used before.
+    #[error("register state was already used")]
+    Replayed,
+    /// Raised if the nonce store is full of nonces that have not expired yet.
+    #[error("nonce store is full")]
+    NonceStoreFull,
+    /// Raised if the nonce store could not be persisted.
+    #[error("could not write nonce store")]
+    NonceStore(#[source] std::io::Error),
+}
+
+/// The algorithm used to sign data.
+///
+/// The algorithm is declared in the [`SignatureHeader`], which versions the signature scheme. Both
+/// algorithms use the same Ed25519 key pairs, so a Relay can switch algorithms without generating
+/// new credentials. Verifiers reject signatures with algorithms they do not know.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureAlgorithm {
+    /// Ed25519 signature over the header and the data.
+    ///
+    /// This is the original signature scheme and is omitted from the header.
+    #[default]
+    Ed25519,
+    /// Ed25519ph signature over the SHA-512 digest of the header and the data, see RFC 8032.
+    Ed25519ph,
+}
+
+impl SignatureAlgorithm {
+    /// Returns `true` if this is the default algorithm.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
 }
 
 /// A wrapper around packed data that adds a timestamp.
@@ -155,9 +195,26 @@ pub struct SignatureHeader {
     /// The timestamp of when the data was packed and signed.
     #[serde(rename = "t", skip_serializing_if = "Option::is_none")]
     pub timestamp: Option<DateTime<Utc>>,
+    /// The algorithm of the signature.
+    ///
+    /// Headers without an algorithm are signed with [`SignatureAlgorithm::Ed25519`].
+    #[serde(
+        rename = "alg",
+        default,
+        skip_serializing_if = "SignatureAlgorithm::is_default"
+    )]
+    pub algorithm: SignatureAlgorithm,
 }
 
 impl SignatureHeader {
+    /// Creates a header with the current timestamp for the given algorithm.
+    pub fn with_algorithm(algorithm: SignatureAlgorithm) -> Self {
+        SignatureHeader {
+            algorithm,
+            ..Default::default()
+        }
+    }
+
     /// Checks if the signature expired.
     pub fn expired(&self, max_age: Duration) -> bool {
         if let Some(ts) = self.timestamp {
@@ -172,6 +229,7 @@ impl Default for SignatureHeader {
     fn default() -> SignatureHeader {
         SignatureHeader {
             timestamp: Some(Utc::now()),
+            algorithm: SignatureAlgorithm::default(),
         }
     }
 }
@@ -204,14 +262,22 @@ impl SecretKey {
     /// then returns the signature.
     ///
     /// The default behavior is to attach the timestamp in the header to the
-    /// signature so that old signatures on verification can be rejected.
+    /// signature so that old signatures on verification can be rejected. The
+    /// signature is computed with the algorithm declared in the header.
     pub fn sign_with_header(&self, data: &[u8], header: &SignatureHeader) -> String {
+        let algorithm = header.algorithm;
         let mut header =
             serde_json::to_vec(&header).expect("attempted to pack non json safe header");
         let header_encoded = BASE64URL_NOPAD.encode(&header);
         header.push(b'\x00');
         header.extend_from_slice(data);
-        let sig = self.inner.sign(&header);
+        let sig = match algorithm {
+            SignatureAlgorithm::Ed25519 => self.inner.sign(&header),
+            SignatureAlgorithm::Ed25519ph => self
+                .inner
+                .sign_prehashed(Sha512::new().chain_update(&header), None)
+                .expect("Ed25519ph signing without context cannot fail"),
+        };
         let mut sig_encoded = BASE64URL_NOPAD.encode(&sig.to_bytes());
         sig_encoded.push('.');
         sig_encoded.push_str(&header_encoded);
@@ -302,6 +368,8 @@ pub struct PublicKey {
 impl PublicKey {
     /// Verifies the signature and returns the embedded signature
     /// header.
+    ///
+    /// The signature is verified with the algorithm declared in the header.
     pub fn verify_meta(&self, data: &[u8], sig: &str) -> Option<SignatureHeader> {
         let mut iter = sig.splitn(2, '.');
         let sig_bytes = match iter.next() {
@@ -314,14 +382,20 @@ impl PublicKey {
             Some(header_encoded) => BASE64URL_NOPAD.decode(header_encoded.as_bytes()).ok()?,
             None => return None,
         };
-        let mut to_verify = header.clone();
+        let parsed: SignatureHeader = serde_json::from_slice(&header).ok()?;
+
+        let mut to_verify = header;
         to_verify.push(b'\x00');
         to_verify.extend_from_slice(data);
-        if self.inner.verify(&to_verify, &sig).is_ok() {
-            serde_json::from_slice(&header).ok()
-        } else {
-            None
-        }
+        let valid = match parsed.algorithm {
+            SignatureAlgorithm::Ed25519 => self.inner.verify(&to_verify, &sig).is_ok(),
+            SignatureAlgorithm::Ed25519ph => self
+                .inner
+                .verify_prehashed(Sha512::new().chain_update(&to_verify), None, &sig)
+                .is_ok(),
+        };
+
+        valid.then_some(parsed)
     }
 
     /// Verifies a signature but discards the header.
@@ -402,6 +476,80 @@ impl fmt::Debug for PublicKey {
 
 relay_common::impl_str_serde!(PublicKey, "a public key");
 
+/// A primary public key together with secondary keys accepted during a key rotation.
+///
+/// When a Relay rotates its key pair, instances that have not picked up the new credentials yet
+/// continue to sign requests with the previous key. Listing the previous key as secondary key
+/// allows both keys to be verified until the rotation is complete.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PublicKeySet {
+    primary: PublicKey,
+    secondary: Vec<PublicKey>,
+}
+
+impl PublicKeySet {
+    /// Creates a new key set with the given primary key and secondary keys.
+    pub fn new(primary: PublicKey, secondary: Vec<PublicKey>) -> Self {
+        Self { primary, secondary }
+    }
+
+    /// Returns the primary public key.
+    pub fn primary(&self) -> &PublicKey {
+        &self.primary
+    }
+
+    /// Returns the secondary public keys accepted during a key rotation.
+    pub fn secondary(&self) -> &[PublicKey] {
+        &self.secondary
+    }
+
+    /// Iterates over all keys in this set, starting with the primary key.
+    pub fn iter(&self) -> impl Iterator<Item = &PublicKey> {
+        std::iter::once(&self.primary).chain(&self.secondary)
+    }
+
+    /// Returns `true` if the given key is the primary or one of the secondary keys.
+    pub fn contains(&self, key: &PublicKey) -> bool {
+        self.iter().any(|k| k == key)
+    }
+
+    /// Verifies the signature with any key in this set and returns the embedded signature header.
+    pub fn verify_meta(&self, data: &[u8], sig: &str) -> Option<SignatureHeader> {
+        self.iter().find_map(|key| key.verify_meta(data, sig))
+    }
+
+    /// Verifies a signature with any key in this set but discards the header.
+    pub fn verify(&self, data: &[u8], sig: &str) -> bool {
+        self.verify_meta(data, sig).is_some()
+    }
+
+    /// Unpacks data signed by any key in this set and verifies that it's not too old.
+    ///
+    /// If no `max_age` is set, the embedded timestamp does not get validated.
+    pub fn unpack<D: DeserializeOwned>(
+        &self,
+        data: &[u8],
+        signature: &str,
+        max_age: Option<Duration>,
+    ) -> Result<D, UnpackError> {
+        let header = self
+            .verify_meta(data, signature)
+            .ok_or(UnpackError::BadSignature)?;
+
+        if max_age.map_or(false, |max_age| header.expired(max_age)) {
+            return Err(UnpackError::SignatureExpired);
+        }
+
+        serde_json::from_slice(data).map_err(UnpackError::BadPayload)
+    }
+}
+
+impl From<PublicKey> for PublicKeySet {
+    fn from(primary: PublicKey) -> Self {
+        Self::new(primary, Vec::new())
+    }
+}
+
 /// Generates an Relay ID.
 pub fn generate_relay_id() -> RelayId {
     Uuid::new_v4()
@@ -535,6 +683,11 @@ impl RegisterState {
     pub fn public_key(&self) -> &PublicKey {
         &self.public_key
     }
+
+    /// Returns the random nonce that identifies this challenge.
+    pub fn nonce(&self) -> &str {
+        &self.rand
+    }
 }
 
 /// Generates a new random token for the register state.
@@ -671,6 +824,23 @@ impl RegisterResponse {
         Ok((response, state))
     }
 
+    /// Unpacks the register response like [`unpack`](Self::unpack) and rejects replays.
+    ///
+    /// The nonce of the register state is recorded in the given store, so that every challenge can
+    /// only be answered once. Returns [`UnpackError::Replayed`] if the response has been used
+    /// before.
+    pub fn unpack_once(
+        data: &[u8],
+        signature: &str,
+        secret: &[u8],
+        max_age: Option<Duration>,
+        nonces: &mut NonceStore,
+    ) -> Result<(Self, RegisterState), UnpackError> {
+        let (response, state) = Self::unpack(data, signature, secret, max_age)?;
+        nonces.insert(state.nonce(), state.timestamp(), max_age)?;
+        Ok((response, state))
+    }
+
     /// Returns the Relay ID of the registering Relay.
     pub fn relay_id(&self) -> RelayId {
         self.relay_id
@@ -760,6 +930,59 @@ mod tests {
         assert!(!pk.verify(data, bad_sig));
     }
 
+    #[test]
+    fn test_signature_algorithms() {
+        let (sk, pk) = generate_key_pair();
+        let data = b"Hello World!";
+
+        let header = SignatureHeader::with_algorithm(SignatureAlgorithm::Ed25519ph);
+        let sig = sk.sign_with_header(data, &header);
+        let verified = pk.verify_meta(data, &sig).unwrap();
+        assert_eq!(verified.algorithm, SignatureAlgorithm::Ed25519ph);
+
+        // The default algorithm is not declared, so that older Relays can verify the signature.
+        let sig = sk.sign(data);
+        let header = BASE64URL_NOPAD
+            .decode(sig.split('.').nth(1).unwrap().as_bytes())
+            .unwrap();
+        assert!(!String::from_utf8(header).unwrap().contains("alg"));
+
+        // Signatures are rejected if the declared algorithm does not match.
+        let (sig, _) = sig.split_once('.').unwrap();
+        let forged = BASE64URL_NOPAD.encode(br#"{"alg":"ed25519ph"}"#);
+        assert!(!pk.verify(data, &format!("{sig}.{forged}")));
+
+        let unknown = BASE64URL_NOPAD.encode(br#"{"alg":"rsa"}"#);
+        assert!(!pk.verify(data, &format!("{sig}.{unknown}")));
+    }
+
+    #[test]
+    fn test_key_set() {
+        let (old_sk, old_pk) = generate_key_pair();
+        let (new_sk, new_pk) = generate_key_pair();
+        let (other_sk, _) = generate_key_pair();
+
+        let keys = PublicKeySet::new(new_pk.clone(), vec![old_pk.clone()]);
+        assert!(keys.contains(&old_pk));
+        assert_eq!(keys.iter().collect::<Vec<_>>(), [&new_pk, &old_pk]);
+
+        for sk in [&new_sk, &old_sk] {
+            let (data, signature) = sk.pack(vec![1, 2, 3]);
+            let unpacked: Vec<u8> = keys.unpack(&data, &signature, None).unwrap();
+            assert_eq!(unpacked, [1, 2, 3]);
+        }
+
+        let (data, signature) = other_sk.pack(vec![1, 2, 3]);
+        assert!(matches!(
+            keys.unpack::<Vec<u8>>(&data, &signature, None),
+            Err(UnpackError::BadSignature)
+        ));
+
+        let only_new = PublicKeySet::from(new_pk);
+        let (data, signature) = old_sk.pack(vec![1, 2, 3]);
+        assert!(!only_new.verify(&data, &signature));
+    }
+
     #[test]
     fn test_registration() {
         let max_age = Duration::minutes(15);
@@ -810,6 +1033,20 @@ mod tests {
         assert_eq!(response.relay_id(), relay_id);
         assert_eq!(response.token(), challenge_token);
         assert_eq!(response.version, LATEST_VERSION);
+
+        // the same response can only be used once with a nonce store
+        let mut nonces = NonceStore::new(100);
+        let unpack = |nonces: &mut NonceStore| {
+            RegisterResponse::unpack_once(
+                &response_bytes,
+                &response_sig,
+                upstream_secret,
+                Some(max_age),
+                nonces,
+            )
+        };
+        assert!(unpack(&mut nonces).is_ok());
+        assert!(matches!(unpack(&mut nonces), Err(UnpackError::Replayed)));
     }
 
     /// This is a pseudo-test to easily generate the strings used by test_auth.py
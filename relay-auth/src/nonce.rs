@@ -0,0 +1,383 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Duration;
+use relay_common::time::UnixTimestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::UnpackError;
+
+/// Minimum number of lines in the nonce log before it is compacted.
+const MIN_COMPACT_LINES: usize = 1024;
+
+/// A line in the persisted nonce log.
+#[derive(Debug, Deserialize, Serialize)]
+struct NonceEntry {
+    nonce: String,
+    timestamp: UnixTimestamp,
+}
+
+/// A bounded store of nonces that have been used to register Relays.
+///
+/// The upstream records the nonce of every accepted register response, so that a captured response
+/// cannot be replayed within its validity window. Nonces are pruned once they are older than the
+/// `max_age` of the register state, since expired states are rejected anyway. If the store is full
+/// of nonces that are still valid, new nonces are rejected with [`UnpackError::NonceStoreFull`]
+/// rather than evicting a nonce that could still be replayed.
+///
+/// A store created with [`NonceStore::open`] appends every new nonce to a log file, so that it
+/// survives restarts. The log is rewritten once it holds mostly expired nonces. Concurrent writers
+/// to the same file are not coordinated.
+#[derive(Debug)]
+pub struct NonceStore {
+    path: Option<PathBuf>,
+    capacity: usize,
+    nonces: HashMap<String, UnixTimestamp>,
+    by_time: BTreeSet<(UnixTimestamp, String)>,
+    log_lines: usize,
+}
+
+impl NonceStore {
+    /// Creates an in-memory store holding at most `capacity` nonces.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            path: None,
+            capacity,
+            nonces: HashMap::new(),
+            by_time: BTreeSet::new(),
+            log_lines: 0,
+        }
+    }
+
+    /// Opens a store persisted at the given path, holding at most `capacity` nonces.
+    ///
+    /// If the file does not exist yet, the store is empty and the file is created on the first
+    /// insert. A truncated last line, for instance after a crash during a write, is removed from
+    /// the file so that the next append starts on a new line.
+    pub fn open(path: impl AsRef<Path>, capacity: usize) -> io::Result<Self> {
+        let mut store = Self::new(capacity);
+        let path = path.as_ref().to_owned();
+
+        let file = match File::open(&path) {
+            Ok(file) => Some(file),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error),
+        };
+
+        if let Some(file) = file {
+            let mut reader = BufReader::new(file);
+            let mut line = Vec::new();
+            let mut complete_len = 0;
+
+            loop {
+                line.clear();
+                if reader.read_until(b'\n', &mut line)? == 0 {
+                    break;
+                }
+
+                // A line without a trailing newline was torn by an interrupted write.
+                if line.last() != Some(&b'\n') {
+                    OpenOptions::new()
+                        .write(true)
+                        .open(&path)?
+                        .set_len(complete_len)?;
+                    break;
+                }
+
+                let entry = serde_json::from_slice::<NonceEntry>(&line)?;
+                store.add(&entry.nonce, entry.timestamp);
+                store.log_lines += 1;
+                complete_len += line.len() as u64;
+            }
+        }
+
+        store.path = Some(path);
+        Ok(store)
+    }
+
+    /// Returns the number of nonces in the store.
+    pub fn len(&self) -> usize {
+        self.nonces.len()
+    }
+
+    /// Returns `true` if the store does not contain any nonces.
+    pub fn is_empty(&self) -> bool {
+        self.nonces.is_empty()
+    }
+
+    /// Records a nonce and returns [`UnpackError::Replayed`] if it has been used before.
+    ///
+    /// Returns [`UnpackError::NonceStoreFull`] if the store is at capacity and none of its nonces
+    /// have expired yet. Without a `max_age`, nonces never expire and the oldest nonce is evicted
+    /// instead once the store is full.
+    pub fn insert(
+        &mut self,
+        nonce: &str,
+        timestamp: UnixTimestamp,
+        max_age: Option<Duration>,
+    ) -> Result<(), UnpackError> {
+        self.prune(max_age);
+
+        if self.nonces.contains_key(nonce) {
+            return Err(UnpackError::Replayed);
+        }
+
+        if self.capacity > 0 && self.nonces.len() >= self.capacity {
+            if max_age.is_some() {
+                return Err(UnpackError::NonceStoreFull);
+            }
+
+            // Without an expiry, there is no way to free space other than evicting.
+            while self.nonces.len() >= self.capacity {
+                let Some((_, oldest)) = self.by_time.pop_first() else {
+                    break;
+                };
+                self.nonces.remove(&oldest);
+            }
+        }
+
+        self.add(nonce, timestamp);
+        self.persist(nonce, timestamp)
+            .map_err(UnpackError::NonceStore)
+    }
+
+    /// Adds a nonce to the in-memory indexes, replacing a previous timestamp.
+    fn add(&mut self, nonce: &str, timestamp: UnixTimestamp) {
+        if let Some(previous) = self.nonces.insert(nonce.to_owned(), timestamp) {
+            self.by_time.remove(&(previous, nonce.to_owned()));
+        }
+        self.by_time.insert((timestamp, nonce.to_owned()));
+    }
+
+    /// Removes nonces that are older than `max_age`.
+    fn prune(&mut self, max_age: Option<Duration>) {
+        let Some(max_age) = max_age else { return };
+        let now = UnixTimestamp::now().as_secs() as i64;
+
+        while let Some((timestamp, _)) = self.by_time.first() {
+            if timestamp.as_secs() as i64 + max_age.num_seconds() >= now {
+                break;
+            }
+
+            if let Some((_, nonce)) = self.by_time.pop_first() {
+                self.nonces.remove(&nonce);
+            }
+        }
+    }
+
+    /// Appends a nonce to the log file, if the store is persisted.
+    ///
+    /// Once the log holds more than twice as many lines as there are live nonces, it is compacted.
+    fn persist(&mut self, nonce: &str, timestamp: UnixTimestamp) -> io::Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+
+        if self.log_lines >= MIN_COMPACT_LINES && self.log_lines >= 2 * self.nonces.len() {
+            return self.compact();
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let entry = NonceEntry {
+            nonce: nonce.to_owned(),
+            timestamp,
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        self.log_lines += 1;
+        Ok(())
+    }
+
+    /// Rewrites the log file with only the live nonces.
+    fn compact(&mut self) -> io::Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+
+        // Write to a temporary file and move it into place, so that the log is never truncated.
+        let temp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        for (timestamp, nonce) in &self.by_time {
+            let entry = NonceEntry {
+                nonce: nonce.clone(),
+                timestamp: *timestamp,
+            };
+            serde_json::to_writer(&mut writer, &entry)?;
+            writer.write_all(b"\n")?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&temp_path, path)?;
+
+        self.log_lines = self.by_time.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let mut store = NonceStore::new(10);
+        let now = UnixTimestamp::now();
+
+        assert!(store.insert("a", now, None).is_ok());
+        assert!(matches!(
+            store.insert("a", now, None),
+            Err(UnpackError::Replayed)
+        ));
+        assert!(store.insert("b", now, None).is_ok());
+    }
+
+    #[test]
+    fn test_full_rejects() {
+        let mut store = NonceStore::new(2);
+        let now = UnixTimestamp::now();
+        let max_age = Some(Duration::seconds(60));
+
+        store.insert("a", now, max_age).unwrap();
+        store.insert("b", now, max_age).unwrap();
+
+        // Both nonces are still valid, so neither may be evicted.
+        assert!(matches!(
+            store.insert("c", now, max_age),
+            Err(UnpackError::NonceStoreFull)
+        ));
+        assert!(matches!(
+            store.insert("a", now, max_age),
+            Err(UnpackError::Replayed)
+        ));
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn test_full_prunes_expired() {
+        let mut store = NonceStore::new(2);
+        let now = UnixTimestamp::now().as_secs();
+
+        store
+            .insert("a", UnixTimestamp::from_secs(now - 120), None)
+            .unwrap();
+        store
+            .insert("b", UnixTimestamp::from_secs(now), None)
+            .unwrap();
+
+        // The expired nonce makes room for the new one.
+        store
+            .insert(
+                "c",
+                UnixTimestamp::from_secs(now),
+                Some(Duration::seconds(60)),
+            )
+            .unwrap();
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn test_prune_expired() {
+        let mut store = NonceStore::new(10);
+        let old = UnixTimestamp::from_secs(UnixTimestamp::now().as_secs() - 120);
+
+        store.insert("a", old, None).unwrap();
+        store
+            .insert("b", UnixTimestamp::now(), Some(Duration::seconds(60)))
+            .unwrap();
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_persisted() {
+        let path = std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+
+        let mut store = NonceStore::open(&path, 10).unwrap();
+        store.insert("a", UnixTimestamp::now(), None).unwrap();
+        store.insert("b", UnixTimestamp::now(), None).unwrap();
+
+        // Every insert appends a single line to the log.
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        let mut reopened = NonceStore::open(&path, 10).unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert!(matches!(
+            reopened.insert("a", UnixTimestamp::now(), None),
+            Err(UnpackError::Replayed)
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_persisted_truncated() {
+        let path = std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+
+        let mut store = NonceStore::open(&path, 10).unwrap();
+        store.insert("a", UnixTimestamp::now(), None).unwrap();
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"nonce":"b","time"#).unwrap();
+
+        let mut reopened = NonceStore::open(&path, 10).unwrap();
+        assert_eq!(reopened.len(), 1);
+
+        // The torn line is removed, so the next append does not corrupt the log.
+        reopened.insert("c", UnixTimestamp::now(), None).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        let reopened = NonceStore::open(&path, 10).unwrap();
+        assert_eq!(reopened.len(), 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_full_evicts_without_max_age() {
+        let mut store = NonceStore::new(2);
+        let now = UnixTimestamp::now().as_secs();
+
+        store
+            .insert("a", UnixTimestamp::from_secs(now - 1), None)
+            .unwrap();
+        store
+            .insert("b", UnixTimestamp::from_secs(now), None)
+            .unwrap();
+        store
+            .insert("c", UnixTimestamp::from_secs(now), None)
+            .unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert!(store
+            .insert("a", UnixTimestamp::from_secs(now), None)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_persisted_compact() {
+        let path = std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let now = UnixTimestamp::now().as_secs();
+        let max_age = Some(Duration::seconds(60));
+
+        let mut store = NonceStore::open(&path, 0).unwrap();
+        for i in 0..MIN_COMPACT_LINES {
+            let timestamp = UnixTimestamp::from_secs(now - 120);
+            store.insert(&i.to_string(), timestamp, None).unwrap();
+        }
+
+        // All previous nonces are expired, so the log is rewritten with only the new one.
+        store
+            .insert("new", UnixTimestamp::from_secs(now), max_age)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        let reopened = NonceStore::open(&path, 0).unwrap();
+        assert_eq!(reopened.len(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
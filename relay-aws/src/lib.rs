@@ -0,0 +1,21 @@
+//! Request signing and credentials for AWS APIs.
+//!
+//! This crate is shared by all Relay components that talk to AWS, such as the Kinesis and SQS
+//! store sink, AWS Secrets Manager, and Kafka producers authenticating with AWS MSK IAM.
+//!
+//! Requests are signed with [AWS Signature Version 4], either through headers with
+//! [`signed_headers`] or as a presigned URL with [`presigned_url`]. Credentials are resolved by
+//! the [`AwsCredentialsProvider`] like the standard AWS credentials chain.
+//!
+//! [AWS Signature Version 4]: https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_aws-signing.html
+#![warn(missing_docs)]
+#![doc(
+    html_logo_url = "https://raw.githubusercontent.com/getsentry/relay/master/artwork/relay-icon.png",
+    html_favicon_url = "https://raw.githubusercontent.com/getsentry/relay/master/artwork/relay-icon.png"
+)]
+
+mod credentials;
+mod signing;
+
+pub use self::credentials::*;
+pub use self::signing::*;
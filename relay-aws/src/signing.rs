@@ -0,0 +1,358 @@
+//! Signing of requests to AWS APIs with AWS Signature Version 4.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::AwsCredentials;
+
+/// The signing algorithm of AWS Signature Version 4.
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// An action of an AWS API that uses the JSON protocol.
+#[derive(Clone, Copy, Debug)]
+pub struct AwsAction<'a> {
+    /// The signing name of the service, such as `"secretsmanager"`.
+    pub service: &'a str,
+    /// The value of the `X-Amz-Target` header, such as `"secretsmanager.GetSecretValue"`.
+    pub target: &'a str,
+    /// The content type of the request body, such as `"application/x-amz-json-1.1"`.
+    pub content_type: &'a str,
+}
+
+impl AwsAction<'_> {
+    /// Returns the regional endpoint of the service, unless a custom endpoint is given.
+    pub fn endpoint(&self, region: &str, endpoint: Option<&str>) -> anyhow::Result<url::Url> {
+        Ok(match endpoint {
+            Some(endpoint) => endpoint.parse()?,
+            None => format!("https://{}.{region}.amazonaws.com/", self.service).parse()?,
+        })
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Derives the AWS Signature Version 4 signing key for a day, region, and service.
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(
+        format!("AWS4{secret_access_key}").as_bytes(),
+        date.as_bytes(),
+    );
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// The credential scope of a signature, which is the day, region, and service.
+struct Scope<'a> {
+    date: String,
+    timestamp: String,
+    region: &'a str,
+    service: &'a str,
+}
+
+impl<'a> Scope<'a> {
+    fn new(region: &'a str, service: &'a str, now: DateTime<Utc>) -> Self {
+        Self {
+            date: now.format("%Y%m%d").to_string(),
+            timestamp: now.format("%Y%m%dT%H%M%SZ").to_string(),
+            region,
+            service,
+        }
+    }
+
+    /// Returns the scope in the format `date/region/service/aws4_request`.
+    fn to_scope_string(&self) -> String {
+        format!(
+            "{}/{}/{}/aws4_request",
+            self.date, self.region, self.service
+        )
+    }
+
+    /// Signs a canonical request and returns the hex-encoded signature.
+    fn sign(&self, credentials: &AwsCredentials, canonical_request: &str) -> String {
+        let string_to_sign = format!(
+            "{ALGORITHM}\n{}\n{}\n{}",
+            self.timestamp,
+            self.to_scope_string(),
+            HEXLOWER.encode(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = signing_key(
+            &credentials.secret_access_key,
+            &self.date,
+            self.region,
+            self.service,
+        );
+        HEXLOWER.encode(&hmac_sha256(&key, string_to_sign.as_bytes()))
+    }
+}
+
+/// Percent-encodes a value for a canonical AWS query string.
+fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => write!(encoded, "%{byte:02X}").unwrap(),
+        }
+    }
+    encoded
+}
+
+/// Computes the headers of a signed AWS request.
+///
+/// This signs a `POST` request to the root path of `host` through the `Authorization` header. The
+/// returned headers do not include the host header, which is set by the HTTP client.
+pub fn signed_headers(
+    credentials: &AwsCredentials,
+    action: AwsAction<'_>,
+    region: &str,
+    host: &str,
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Vec<(&'static str, String)> {
+    let scope = Scope::new(region, action.service, now);
+
+    // Headers must be sorted by name for the canonical request.
+    let mut headers = vec![
+        ("content-type", action.content_type.to_owned()),
+        ("host", host.to_owned()),
+        ("x-amz-date", scope.timestamp.clone()),
+    ];
+    if let Some(ref token) = credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    headers.push(("x-amz-target", action.target.to_owned()));
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{value}\n"))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "POST\n/\n\n{canonical_headers}\n{signed_headers}\n{}",
+        HEXLOWER.encode(&Sha256::digest(body))
+    );
+    let signature = scope.sign(credentials, &canonical_request);
+
+    let authorization = format!(
+        "{ALGORITHM} Credential={}/{}, SignedHeaders={signed_headers}, Signature={signature}",
+        credentials.access_key_id,
+        scope.to_scope_string(),
+    );
+
+    headers.retain(|(name, _)| *name != "host");
+    headers.push(("authorization", authorization));
+    headers
+}
+
+/// Creates a presigned URL for a `GET` request to the root path of `host`.
+///
+/// The signature is passed in the query string along with `params`, so the URL can be handed to
+/// another party that performs the request within `expires`.
+pub fn presigned_url(
+    credentials: &AwsCredentials,
+    service: &str,
+    region: &str,
+    host: &str,
+    params: &[(&str, &str)],
+    expires: Duration,
+    now: DateTime<Utc>,
+) -> String {
+    let scope = Scope::new(region, service, now);
+
+    let mut params: Vec<(&str, String)> = params
+        .iter()
+        .map(|(key, value)| (*key, (*value).to_owned()))
+        .collect();
+    params.extend([
+        ("X-Amz-Algorithm", ALGORITHM.to_owned()),
+        (
+            "X-Amz-Credential",
+            format!("{}/{}", credentials.access_key_id, scope.to_scope_string()),
+        ),
+        ("X-Amz-Date", scope.timestamp.clone()),
+        ("X-Amz-Expires", expires.as_secs().to_string()),
+        ("X-Amz-SignedHeaders", "host".to_owned()),
+    ]);
+    if let Some(ref session_token) = credentials.session_token {
+        params.push(("X-Amz-Security-Token", session_token.clone()));
+    }
+    params.sort();
+
+    let query = params
+        .iter()
+        .map(|(key, value)| format!("{}={}", uri_encode(key), uri_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let payload_hash = HEXLOWER.encode(&Sha256::digest(b""));
+    let canonical_request = format!("GET\n/\n{query}\nhost:{host}\n\nhost\n{payload_hash}");
+    let signature = scope.sign(credentials, &canonical_request);
+
+    format!("https://{host}/?{query}&X-Amz-Signature={signature}")
+}
+
+/// Sends a signed request to an AWS API and returns the response body.
+///
+/// Fails if the response has an error status.
+pub async fn send_aws_request(
+    client: &reqwest::Client,
+    credentials: &AwsCredentials,
+    action: AwsAction<'_>,
+    region: &str,
+    url: &url::Url,
+    body: Vec<u8>,
+) -> anyhow::Result<Bytes> {
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_owned(),
+        (None, _) => anyhow::bail!("invalid AWS endpoint {url}"),
+    };
+
+    let headers = signed_headers(credentials, action, region, &host, &body, Utc::now());
+
+    let mut request = client.post(url.clone());
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+
+    let response = request
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn credentials(session_token: Option<&str>) -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_owned(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned(),
+            session_token: session_token.map(str::to_owned),
+            expires: None,
+        }
+    }
+
+    #[test]
+    fn test_signing_key() {
+        // Example from the AWS Signature Version 4 documentation.
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+
+        assert_eq!(
+            HEXLOWER.encode(&key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(
+            uri_encode("kafka-cluster:Connect"),
+            "kafka-cluster%3AConnect"
+        );
+        assert_eq!(uri_encode("a/b c~"), "a%2Fb%20c~");
+    }
+
+    #[test]
+    fn test_signed_headers() {
+        let action = AwsAction {
+            service: "secretsmanager",
+            target: "secretsmanager.GetSecretValue",
+            content_type: "application/x-amz-json-1.1",
+        };
+
+        let now = Utc.with_ymd_and_hms(2023, 10, 1, 12, 0, 0).unwrap();
+        let headers = signed_headers(
+            &credentials(None),
+            action,
+            "us-east-1",
+            "secretsmanager.us-east-1.amazonaws.com",
+            br#"{"SecretId":"relay"}"#,
+            now,
+        );
+
+        let names: Vec<_> = headers.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            [
+                "content-type",
+                "x-amz-date",
+                "x-amz-target",
+                "authorization"
+            ]
+        );
+
+        let authorization = &headers[3].1;
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20231001/us-east-1/secretsmanager/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date;x-amz-target, Signature="
+        ));
+    }
+
+    #[test]
+    fn test_presigned_url() {
+        let now = Utc.with_ymd_and_hms(2023, 10, 1, 12, 0, 0).unwrap();
+        let url = presigned_url(
+            &credentials(Some("session")),
+            "kafka-cluster",
+            "us-east-1",
+            "kafka.us-east-1.amazonaws.com",
+            &[("Action", "kafka-cluster:Connect")],
+            Duration::from_secs(900),
+            now,
+        );
+
+        assert!(url.starts_with(
+            "https://kafka.us-east-1.amazonaws.com/?Action=kafka-cluster%3AConnect&X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential=AKIDEXAMPLE%2F20231001%2Fus-east-1%2Fkafka-cluster%2Faws4_request&X-Amz-Date=20231001T120000Z&X-Amz-Expires=900&X-Amz-Security-Token=session&X-Amz-SignedHeaders=host&X-Amz-Signature="
+        ));
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let action = AwsAction {
+            service: "kinesis",
+            target: "Kinesis_20131202.PutRecords",
+            content_type: "application/x-amz-json-1.1",
+        };
+
+        let url = action.endpoint("eu-west-1", None).unwrap();
+        assert_eq!(url.as_str(), "https://kinesis.eu-west-1.amazonaws.com/");
+
+        let url = action
+            .endpoint("eu-west-1", Some("http://localstack:4566"))
+            .unwrap();
+        assert_eq!(url.as_str(), "http://localstack:4566/");
+    }
+}
@@ -4,12 +4,15 @@ use std::convert::TryInto;
 use std::fmt;
 use std::str::FromStr;
 
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::events::EventType;
 
 /// Classifies the type of data that is being ingested.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(rename_all = "snake_case")]
 #[repr(i8)]
 pub enum DataCategory {
@@ -57,6 +60,30 @@ pub enum DataCategory {
     /// but we define it here to prevent clashing values since this data category enumeration
     /// is also used outside of Relay via the Python package.
     MonitorSeat = 13,
+    /// Metric buckets.
+    ///
+    /// Quantity is the number of buckets. Quotas for this category are usually restricted to a
+    /// metric namespace.
+    MetricBucket = 14,
+    /// Log records.
+    ///
+    /// Used for logs sent in standalone `log` envelope items.
+    Log = 15,
+    /// Profile duration of continuous profiling.
+    ///
+    /// Quantity is the duration of a profile chunk in milliseconds, rather than the number of
+    /// chunks.
+    ProfileDuration = 16,
+    /// Bytes of log records.
+    ///
+    /// Quantity is the size of the log payload in bytes. Used for rate limiting logs sent in
+    /// standalone `log` envelope items.
+    LogByte = 17,
+    /// User feedback.
+    ///
+    /// Used for feedback sent in `feedback` envelope items, including legacy user reports that
+    /// were converted to feedback.
+    Feedback = 18,
     //
     // IMPORTANT: After adding a new entry to DataCategory, go to the `relay-cabi` subfolder and run
     // `make header` to regenerate the C-binding. This allows using the data category from Python.
@@ -86,6 +113,11 @@ impl DataCategory {
             "monitor" => Self::Monitor,
             "span" => Self::Span,
             "monitor_seat" => Self::MonitorSeat,
+            "metric_bucket" => Self::MetricBucket,
+            "log" => Self::Log,
+            "profile_duration" => Self::ProfileDuration,
+            "log_byte" => Self::LogByte,
+            "feedback" => Self::Feedback,
             _ => Self::Unknown,
         }
     }
@@ -108,6 +140,11 @@ impl DataCategory {
             Self::Monitor => "monitor",
             Self::Span => "span",
             Self::MonitorSeat => "monitor_seat",
+            Self::MetricBucket => "metric_bucket",
+            Self::Log => "log",
+            Self::ProfileDuration => "profile_duration",
+            Self::LogByte => "log_byte",
+            Self::Feedback => "feedback",
             Self::Unknown => "unknown",
         }
     }
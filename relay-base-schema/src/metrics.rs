@@ -1,5 +1,6 @@
 //! Type definitions for Sentry metrics.
 
+use std::convert::Infallible;
 use std::fmt;
 
 use relay_protocol::{Annotated, Empty, ErrorKind, FromValue, IntoValue, SkipSerialization, Value};
@@ -347,6 +348,93 @@ impl std::ops::Deref for CustomUnit {
     }
 }
 
+/// The namespace of a metric.
+///
+/// Namespaces allow to identify the product entity that the metric got extracted from, and identify
+/// the use case that the metric belongs to. These namespaces cannot be defined freely, instead they
+/// are defined by Sentry. Over time, there will be more namespaces as we introduce new
+/// metrics-based functionality.
+///
+/// # Parsing
+///
+/// Parsing a metric namespace from strings is infallible. Unknown strings are mapped to
+/// [`MetricNamespace::Unsupported`]. Metrics with such a namespace will be dropped.
+///
+/// # Ingestion
+///
+/// During ingestion, the metric namespace is validated against a list of known and enabled
+/// namespaces. Metrics in disabled namespaces are dropped during ingestion.
+///
+/// At a later stage, namespaces are used to route metrics to their associated infra structure and
+/// enforce usecase-specific configuration.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MetricNamespace {
+    /// Metrics extracted from sessions.
+    Sessions,
+    /// Metrics extracted from transaction events.
+    Transactions,
+    /// Metrics extracted from spans.
+    Spans,
+    /// User-defined metrics directly sent by SDKs and applications.
+    Custom,
+    /// An unknown and unsupported metric.
+    ///
+    /// Metrics that Relay either doesn't know or recognize the namespace of will be dropped before
+    /// aggregating. For instance, an MRI of `c:something_new/foo@none` has the namespace
+    /// `something_new`, but as Relay doesn't support that namespace, it gets deserialized into
+    /// this variant.
+    ///
+    /// Relay currently drops all metrics whose namespace ends up being deserialized as
+    /// `unsupported`. We may revise that in the future.
+    Unsupported,
+}
+
+impl MetricNamespace {
+    /// Returns the string representation for this metric type.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetricNamespace::Sessions => "sessions",
+            MetricNamespace::Transactions => "transactions",
+            MetricNamespace::Spans => "spans",
+            MetricNamespace::Custom => "custom",
+            MetricNamespace::Unsupported => "unsupported",
+        }
+    }
+}
+
+impl std::str::FromStr for MetricNamespace {
+    type Err = Infallible;
+
+    fn from_str(ns: &str) -> Result<Self, Self::Err> {
+        match ns {
+            "sessions" => Ok(MetricNamespace::Sessions),
+            "transactions" => Ok(MetricNamespace::Transactions),
+            "spans" => Ok(MetricNamespace::Spans),
+            "custom" => Ok(MetricNamespace::Custom),
+            _ => Ok(MetricNamespace::Unsupported),
+        }
+    }
+}
+
+relay_common::impl_str_serde!(MetricNamespace, "a valid metric namespace");
+
+#[cfg(feature = "jsonschema")]
+impl schemars::JsonSchema for MetricNamespace {
+    fn schema_name() -> String {
+        std::any::type_name::<Self>().to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl fmt::Display for MetricNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
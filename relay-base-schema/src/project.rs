@@ -9,6 +9,8 @@ use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
 
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// Raised if a project ID cannot be parsed from a string.
@@ -33,6 +35,7 @@ impl Error for ParseProjectIdError {}
 
 /// The unique identifier of a Sentry project.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct ProjectId(u64);
 
 impl ProjectId {
@@ -90,6 +93,17 @@ pub struct ProjectKey([u8; 32]);
 
 relay_common::impl_str_serde!(ProjectKey, "a project key string");
 
+#[cfg(feature = "jsonschema")]
+impl JsonSchema for ProjectKey {
+    fn schema_name() -> String {
+        std::any::type_name::<Self>().to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
 impl ProjectKey {
     /// Parses a `ProjectKey` from a string.
     pub fn parse(key: &str) -> Result<Self, ParseProjectKeyError> {
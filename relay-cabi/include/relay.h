@@ -88,6 +88,40 @@ enum RelayDataCategory {
    * is also used outside of Relay via the Python package.
    */
   RELAY_DATA_CATEGORY_MONITOR_SEAT = 13,
+  /**
+   * Metric buckets.
+   *
+   * Quantity is the number of buckets. Quotas for this category are usually restricted to a
+   * metric namespace.
+   */
+  RELAY_DATA_CATEGORY_METRIC_BUCKET = 14,
+  /**
+   * Log records.
+   *
+   * Used for logs sent in standalone `log` envelope items.
+   */
+  RELAY_DATA_CATEGORY_LOG = 15,
+  /**
+   * Profile duration of continuous profiling.
+   *
+   * Quantity is the duration of a profile chunk in milliseconds, rather than the number of
+   * chunks.
+   */
+  RELAY_DATA_CATEGORY_PROFILE_DURATION = 16,
+  /**
+   * Bytes of log records.
+   *
+   * Quantity is the size of the log payload in bytes. Used for rate limiting logs sent in
+   * standalone `log` envelope items.
+   */
+  RELAY_DATA_CATEGORY_LOG_BYTE = 17,
+  /**
+   * User feedback.
+   *
+   * Used for feedback sent in `feedback` envelope items, including legacy user reports that
+   * were converted to feedback.
+   */
+  RELAY_DATA_CATEGORY_FEEDBACK = 18,
   /**
    * Any other data category not known by this Relay.
    */
@@ -126,17 +160,24 @@ enum RelayErrorCode {
   RELAY_ERROR_CODE_PANIC = 1,
   RELAY_ERROR_CODE_UNKNOWN = 2,
   RELAY_ERROR_CODE_INVALID_JSON_ERROR = 101,
+  RELAY_ERROR_CODE_INVALID_UTF8_ERROR = 102,
+  RELAY_ERROR_CODE_IO_ERROR = 103,
   RELAY_ERROR_CODE_KEY_PARSE_ERROR_BAD_ENCODING = 1000,
   RELAY_ERROR_CODE_KEY_PARSE_ERROR_BAD_KEY = 1001,
   RELAY_ERROR_CODE_UNPACK_ERROR_BAD_SIGNATURE = 1003,
   RELAY_ERROR_CODE_UNPACK_ERROR_BAD_PAYLOAD = 1004,
   RELAY_ERROR_CODE_UNPACK_ERROR_SIGNATURE_EXPIRED = 1005,
   RELAY_ERROR_CODE_UNPACK_ERROR_BAD_ENCODING = 1006,
+  RELAY_ERROR_CODE_UNPACK_ERROR_REPLAYED = 1007,
+  RELAY_ERROR_CODE_UNPACK_ERROR_NONCE_STORE = 1008,
+  RELAY_ERROR_CODE_UNPACK_ERROR_NONCE_STORE_FULL = 1009,
   RELAY_ERROR_CODE_PROCESSING_ERROR_INVALID_TRANSACTION = 2001,
   RELAY_ERROR_CODE_PROCESSING_ERROR_INVALID_GEO_IP = 2002,
   RELAY_ERROR_CODE_INVALID_RELEASE_ERROR_TOO_LONG = 3001,
   RELAY_ERROR_CODE_INVALID_RELEASE_ERROR_RESTRICTED_NAME = 3002,
   RELAY_ERROR_CODE_INVALID_RELEASE_ERROR_BAD_CHARACTERS = 3003,
+  RELAY_ERROR_CODE_INVALID_VERSION_ERROR = 3101,
+  RELAY_ERROR_CODE_PII_CONFIG_ERROR_REGEX_ERROR = 4001,
 };
 typedef uint32_t RelayErrorCode;
 
@@ -411,6 +452,20 @@ struct RelayStr relay_validate_register_response(const struct RelayBuf *data,
                                                  const struct RelayStr *secret,
                                                  uint32_t max_age);
 
+/**
+ * Validates a register response and rejects replays.
+ *
+ * The nonce of the response is recorded in the nonce store persisted at `nonce_store`, which
+ * holds at most `nonce_capacity` nonces. Once the store is full of nonces younger than `max_age`,
+ * all responses are rejected.
+ */
+struct RelayStr relay_validate_register_response_once(const struct RelayBuf *data,
+                                                      const struct RelayStr *signature,
+                                                      const struct RelayStr *secret,
+                                                      uint32_t max_age,
+                                                      const struct RelayStr *nonce_store,
+                                                      uint32_t nonce_capacity);
+
 /**
  * Returns true if the given version is supported by this library.
  */
@@ -496,6 +551,15 @@ struct RelayStr relay_err_get_last_message(void);
  */
 struct RelayStr relay_err_get_backtrace(void);
 
+/**
+ * Returns the backtrace captured when the last error was caused by a panic.
+ *
+ * Backtraces are only captured if `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set. If the last
+ * error is not a panic or no backtrace was captured, an empty string is returned. This allocates
+ * new memory that needs to be freed with `relay_str_free`.
+ */
+struct RelayStr relay_err_get_panic_backtrace(void);
+
 /**
  * Clears the last error.
  */
@@ -539,6 +603,15 @@ void relay_store_normalizer_free(struct RelayStoreNormalizer *normalizer);
 struct RelayStr relay_store_normalizer_normalize_event(struct RelayStoreNormalizer *normalizer,
                                                        const struct RelayStr *event);
 
+/**
+ * Normalizes a single span given as JSON.
+ *
+ * This scrubs the span description, infers the span status from its HTTP status code, and
+ * extracts span tags into the span's data. The config is a JSON object with the optional fields
+ * `maxTagValueLength` and `spanDescriptionRules`, or an empty string to use defaults.
+ */
+struct RelayStr relay_normalize_span(const struct RelayStr *config, const struct RelayStr *span);
+
 /**
  * Replaces invalid JSON generated by Python.
  */
@@ -560,6 +633,18 @@ struct RelayStr relay_convert_datascrubbing_config(const struct RelayStr *config
 struct RelayStr relay_pii_strip_event(const struct RelayStr *config,
                                       const struct RelayStr *event);
 
+/**
+ * Scrub an attachment in place using a PII config and return a JSON report of the changes.
+ *
+ * Attachments with the `application/x-dmp` content type are scrubbed as minidumps. If the
+ * minidump cannot be parsed, it is scrubbed as plain attachment, like Relay does during event
+ * ingestion. Scrubbing never changes the length of the attachment.
+ */
+struct RelayStr relay_pii_scrub_attachment(const struct RelayStr *config,
+                                           const struct RelayStr *filename,
+                                           const struct RelayStr *content_type,
+                                           struct RelayBuf *data);
+
 /**
  * Walk through the event and collect selectors that can be applied to it in a PII config. This
  * function is used in the UI to provide auto-completion of selectors.
@@ -603,6 +688,19 @@ struct RelayStr relay_validate_sampling_condition(const struct RelayStr *value);
  */
 struct RelayStr relay_validate_sampling_configuration(const struct RelayStr *value);
 
+/**
+ * Evaluate dynamic sampling rules against a dynamic sampling context and an event.
+ *
+ * Trace rules are taken from `root_config` and matched against the DSC, transaction rules are
+ * taken from `config` and matched against the event. Any of the arguments can be an empty string
+ * if it is not available. Returns the JSON-serialized sampling match with the sample rate and the
+ * matched rule ids, or `null` if no rule matches.
+ */
+struct RelayStr relay_evaluate_sampling(const struct RelayStr *config,
+                                        const struct RelayStr *root_config,
+                                        const struct RelayStr *dsc,
+                                        const struct RelayStr *event);
+
 /**
  * Validate entire project config.
  *
@@ -626,4 +724,20 @@ struct RelayStr run_dynamic_sampling(const struct RelayStr *sampling_config,
                                      const struct RelayStr *dsc,
                                      const struct RelayStr *event);
 
+/**
+ * Validate a dynamic sampling config with all its rules.
+ *
+ * Returns a JSON list of errors with the path to the invalid field and an error message. The list
+ * is empty if the config is valid.
+ */
+struct RelayStr relay_validate_sampling_rules(const struct RelayStr *value);
+
+/**
+ * Validate a metric extraction config, including conditions, globs, and tag specs.
+ *
+ * Returns a JSON list of errors with the path to the invalid field and an error message. The list
+ * is empty if the config is valid.
+ */
+struct RelayStr relay_validate_metric_extraction_config(const struct RelayStr *value);
+
 #endif /* RELAY_H_INCLUDED */
@@ -1,7 +1,7 @@
 use chrono::Duration;
 use relay_auth::{
-    generate_key_pair, generate_relay_id, PublicKey, RegisterRequest, RegisterResponse, RelayId,
-    RelayVersion, SecretKey,
+    generate_key_pair, generate_relay_id, NonceStore, PublicKey, RegisterRequest, RegisterResponse,
+    RelayId, RelayVersion, SecretKey,
 };
 use serde::Serialize;
 
@@ -194,6 +194,46 @@ pub unsafe extern "C" fn relay_validate_register_response(
     RelayStr::from_string(json)
 }
 
+/// Validates a register response and rejects replays.
+///
+/// The nonce of the response is recorded in the nonce store persisted at `nonce_store`, which
+/// holds at most `nonce_capacity` nonces. Once the store is full of nonces younger than `max_age`,
+/// all responses are rejected.
+#[no_mangle]
+#[relay_ffi::catch_unwind]
+pub unsafe extern "C" fn relay_validate_register_response_once(
+    data: *const RelayBuf,
+    signature: *const RelayStr,
+    secret: *const RelayStr,
+    max_age: u32,
+    nonce_store: *const RelayStr,
+    nonce_capacity: u32,
+) -> RelayStr {
+    let max_age = match max_age {
+        0 => None,
+        m => Some(Duration::seconds(i64::from(m))),
+    };
+
+    let mut nonces = NonceStore::open((*nonce_store).as_str(), nonce_capacity as usize)?;
+    let (response, state) = RegisterResponse::unpack_once(
+        (*data).as_bytes(),
+        (*signature).as_str(),
+        (*secret).as_str().as_bytes(),
+        max_age,
+        &mut nonces,
+    )?;
+
+    let relay_response = RelayRegisterResponse {
+        relay_id: response.relay_id(),
+        token: response.token(),
+        public_key: state.public_key(),
+        version: response.version(),
+    };
+
+    let json = serde_json::to_string(&relay_response)?;
+    RelayStr::from_string(json)
+}
+
 /// Returns true if the given version is supported by this library.
 #[no_mangle]
 #[relay_ffi::catch_unwind]
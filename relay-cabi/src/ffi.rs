@@ -1,8 +1,12 @@
+use std::io;
+use std::str::Utf8Error;
+
 use relay_auth::{KeyParseError, UnpackError};
 use relay_event_normalization::GeoIpError;
 use relay_event_schema::processor::ProcessingAction;
 use relay_ffi::Panic;
-use sentry_release_parser::InvalidRelease;
+use relay_pii::PiiConfigError;
+use sentry_release_parser::{InvalidRelease, InvalidVersion};
 
 use crate::core::RelayStr;
 
@@ -15,6 +19,8 @@ pub enum RelayErrorCode {
     Unknown = 2,
 
     InvalidJsonError = 101, // serde_json::Error
+    InvalidUtf8Error = 102, // std::str::Utf8Error
+    IoError = 103,          // std::io::Error
 
     // relay_auth::KeyParseError
     KeyParseErrorBadEncoding = 1000,
@@ -25,6 +31,9 @@ pub enum RelayErrorCode {
     UnpackErrorBadPayload = 1004,
     UnpackErrorSignatureExpired = 1005,
     UnpackErrorBadEncoding = 1006,
+    UnpackErrorReplayed = 1007,
+    UnpackErrorNonceStore = 1008,
+    UnpackErrorNonceStoreFull = 1009,
 
     // relay_protocol::annotated::ProcessingAction
     ProcessingErrorInvalidTransaction = 2001,
@@ -34,6 +43,12 @@ pub enum RelayErrorCode {
     InvalidReleaseErrorTooLong = 3001,
     InvalidReleaseErrorRestrictedName = 3002,
     InvalidReleaseErrorBadCharacters = 3003,
+
+    // sentry_release_parser::InvalidVersion
+    InvalidVersionError = 3101,
+
+    // relay_pii::PiiConfigError
+    PiiConfigErrorRegexError = 4001,
 }
 
 impl RelayErrorCode {
@@ -46,6 +61,12 @@ impl RelayErrorCode {
             if cause.downcast_ref::<serde_json::Error>().is_some() {
                 return RelayErrorCode::InvalidJsonError;
             }
+            if cause.downcast_ref::<Utf8Error>().is_some() {
+                return RelayErrorCode::InvalidUtf8Error;
+            }
+            if cause.downcast_ref::<io::Error>().is_some() {
+                return RelayErrorCode::IoError;
+            }
             if cause.downcast_ref::<GeoIpError>().is_some() {
                 return RelayErrorCode::ProcessingErrorInvalidGeoIp;
             }
@@ -61,6 +82,9 @@ impl RelayErrorCode {
                     UnpackError::BadPayload(..) => RelayErrorCode::UnpackErrorBadPayload,
                     UnpackError::SignatureExpired => RelayErrorCode::UnpackErrorSignatureExpired,
                     UnpackError::BadEncoding => RelayErrorCode::UnpackErrorBadEncoding,
+                    UnpackError::Replayed => RelayErrorCode::UnpackErrorReplayed,
+                    UnpackError::NonceStore(_) => RelayErrorCode::UnpackErrorNonceStore,
+                    UnpackError::NonceStoreFull => RelayErrorCode::UnpackErrorNonceStoreFull,
                 };
             }
             if let Some(err) = cause.downcast_ref::<ProcessingAction>() {
@@ -82,6 +106,14 @@ impl RelayErrorCode {
                     }
                 };
             }
+            if cause.downcast_ref::<InvalidVersion>().is_some() {
+                return RelayErrorCode::InvalidVersionError;
+            }
+            if let Some(err) = cause.downcast_ref::<PiiConfigError>() {
+                return match err {
+                    PiiConfigError::RegexError(_) => RelayErrorCode::PiiConfigErrorRegexError,
+                };
+            }
         }
         RelayErrorCode::Unknown
     }
@@ -130,6 +162,27 @@ pub extern "C" fn relay_err_get_backtrace() -> RelayStr {
     }
 }
 
+/// Returns the backtrace captured when the last error was caused by a panic.
+///
+/// Backtraces are only captured if `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set. If the last
+/// error is not a panic or no backtrace was captured, an empty string is returned. This allocates
+/// new memory that needs to be freed with `relay_str_free`.
+#[no_mangle]
+pub extern "C" fn relay_err_get_panic_backtrace() -> RelayStr {
+    let backtrace = relay_ffi::with_last_error(|error| {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Panic>())
+            .and_then(Panic::backtrace)
+            .map(|backtrace| backtrace.to_string())
+    });
+
+    match backtrace.flatten() {
+        Some(backtrace) => RelayStr::from_string(backtrace),
+        None => RelayStr::default(),
+    }
+}
+
 /// Clears the last error.
 #[no_mangle]
 pub extern "C" fn relay_err_clear() {
@@ -103,6 +103,7 @@ mod constants;
 mod core;
 mod ffi;
 mod processing;
+mod validation;
 
 pub use crate::auth::*;
 pub use crate::codeowners::*;
@@ -110,3 +111,4 @@ pub use crate::constants::*;
 pub use crate::core::*;
 pub use crate::ffi::*;
 pub use crate::processing::*;
+pub use crate::validation::*;
@@ -8,21 +8,28 @@ use std::ffi::CStr;
 use std::os::raw::c_char;
 use std::slice;
 
+use chrono::Utc;
 use once_cell::sync::OnceCell;
 use relay_common::glob::{glob_match_bytes, GlobOptions};
 use relay_dynamic_config::{normalize_json, validate_json, GlobalConfig, ProjectConfig};
+use relay_event_normalization::span::standalone::{
+    normalize_standalone_span, StandaloneSpanConfig,
+};
 use relay_event_normalization::{
-    light_normalize_event, GeoIpLookup, LightNormalizationConfig, RawUserAgentInfo, StoreConfig,
-    StoreProcessor,
+    light_normalize_event, GeoIpLookup, LightNormalizationConfig, RawUserAgentInfo,
+    SpanDescriptionRule, StoreConfig, StoreProcessor,
 };
 use relay_event_schema::processor::{process_value, split_chunks, ProcessingState};
-use relay_event_schema::protocol::{Event, VALID_PLATFORMS};
+use relay_event_schema::protocol::{Event, Span, VALID_PLATFORMS};
 use relay_pii::{
-    selector_suggestions_from_value, DataScrubbingConfig, PiiConfig, PiiConfigError, PiiProcessor,
+    selector_suggestions_from_value, DataScrubbingConfig, PiiAttachmentsProcessor, PiiConfig,
+    PiiConfigError, PiiProcessor,
 };
 use relay_protocol::{Annotated, Remark};
 use relay_sampling::condition::RuleCondition;
-use relay_sampling::SamplingConfig;
+use relay_sampling::evaluation::merge_configs_and_match;
+use relay_sampling::{DynamicSamplingContext, SamplingConfig};
+use serde::{Deserialize, Serialize};
 
 use crate::core::{RelayBuf, RelayStr};
 
@@ -114,6 +121,7 @@ pub unsafe extern "C" fn relay_store_normalizer_normalize_event(
     let config = (*processor).config();
     let light_normalization_config = LightNormalizationConfig {
         client_ip: config.client_ip.as_ref(),
+        ipv6_prefix: None, // only supported in relay
         user_agent: RawUserAgentInfo {
             user_agent: config.user_agent.as_deref(),
             client_hints: config.client_hints.as_deref(),
@@ -141,6 +149,45 @@ pub unsafe extern "C" fn relay_store_normalizer_normalize_event(
     RelayStr::from_string(event.to_json()?)
 }
 
+/// Configuration for [`relay_normalize_span`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct SpanNormalizationConfig {
+    /// The maximum length of extracted tag values in bytes.
+    max_tag_value_length: Option<usize>,
+    /// Rules to scrub identifiers from span descriptions.
+    span_description_rules: Vec<SpanDescriptionRule>,
+}
+
+/// Normalizes a single span given as JSON.
+///
+/// This scrubs the span description, infers the span status from its HTTP status code, and
+/// extracts span tags into the span's data. The config is a JSON object with the optional fields
+/// `maxTagValueLength` and `spanDescriptionRules`, or an empty string to use defaults.
+#[no_mangle]
+#[relay_ffi::catch_unwind]
+pub unsafe extern "C" fn relay_normalize_span(
+    config: *const RelayStr,
+    span: *const RelayStr,
+) -> RelayStr {
+    let config = parse_optional::<SpanNormalizationConfig>((*config).as_str())?.unwrap_or_default();
+    let mut span = Annotated::<Span>::from_json((*span).as_str())?;
+
+    if let Some(span) = span.value_mut() {
+        let mut span_config = StandaloneSpanConfig {
+            span_description_rules: &config.span_description_rules,
+            ..Default::default()
+        };
+        if let Some(max_tag_value_length) = config.max_tag_value_length {
+            span_config.max_tag_value_length = max_tag_value_length;
+        }
+
+        normalize_standalone_span(span, &span_config);
+    }
+
+    RelayStr::from_string(span.to_json()?)
+}
+
 /// Replaces invalid JSON generated by Python.
 #[no_mangle]
 #[relay_ffi::catch_unwind]
@@ -192,6 +239,58 @@ pub unsafe extern "C" fn relay_pii_strip_event(
     RelayStr::from_string(event.to_json()?)
 }
 
+/// The content type of minidump attachments.
+const MINIDUMP_CONTENT_TYPE: &str = "application/x-dmp";
+
+/// The result of scrubbing an attachment with [`relay_pii_scrub_attachment`].
+#[derive(Debug, Default, Serialize)]
+struct AttachmentScrubReport {
+    /// `true` if the attachment was modified.
+    changed: bool,
+    /// `true` if the attachment was scrubbed as a minidump.
+    minidump: bool,
+    /// The error encountered while parsing a minidump, if it was scrubbed as plain attachment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minidump_error: Option<String>,
+}
+
+/// Scrub an attachment in place using a PII config and return a JSON report of the changes.
+///
+/// Attachments with the `application/x-dmp` content type are scrubbed as minidumps. If the
+/// minidump cannot be parsed, it is scrubbed as plain attachment, like Relay does during event
+/// ingestion. Scrubbing never changes the length of the attachment.
+#[no_mangle]
+#[relay_ffi::catch_unwind]
+pub unsafe extern "C" fn relay_pii_scrub_attachment(
+    config: *const RelayStr,
+    filename: *const RelayStr,
+    content_type: *const RelayStr,
+    data: *mut RelayBuf,
+) -> RelayStr {
+    let config = serde_json::from_str::<PiiConfig>((*config).as_str())?;
+    let processor = PiiAttachmentsProcessor::new(config.compiled());
+
+    let filename = (*filename).as_str();
+    let data = slice::from_raw_parts_mut((*data).data, (*data).len);
+
+    let mut report = AttachmentScrubReport::default();
+    if (*content_type).as_str() == MINIDUMP_CONTENT_TYPE {
+        match processor.scrub_minidump(filename, data) {
+            Ok(changed) => {
+                report.changed = changed;
+                report.minidump = true;
+            }
+            Err(error) => report.minidump_error = Some(error.to_string()),
+        }
+    }
+
+    if !report.minidump {
+        report.changed = processor.scrub_attachment(filename, data);
+    }
+
+    RelayStr::from_string(serde_json::to_string(&report)?)
+}
+
 /// Walk through the event and collect selectors that can be applied to it in a PII config. This
 /// function is used in the UI to provide auto-completion of selectors.
 #[no_mangle]
@@ -309,6 +408,51 @@ pub unsafe extern "C" fn relay_validate_sampling_configuration(value: *const Rel
     }
 }
 
+/// Evaluate dynamic sampling rules against a dynamic sampling context and an event.
+///
+/// Trace rules are taken from `root_config` and matched against the DSC, transaction rules are
+/// taken from `config` and matched against the event. Any of the arguments can be an empty string
+/// if it is not available. Returns the JSON-serialized sampling match with the sample rate and the
+/// matched rule ids, or `null` if no rule matches.
+#[no_mangle]
+#[relay_ffi::catch_unwind]
+pub unsafe extern "C" fn relay_evaluate_sampling(
+    config: *const RelayStr,
+    root_config: *const RelayStr,
+    dsc: *const RelayStr,
+    event: *const RelayStr,
+) -> RelayStr {
+    let config = parse_optional::<SamplingConfig>((*config).as_str())?;
+    let root_config = parse_optional::<SamplingConfig>((*root_config).as_str())?;
+    let dsc = parse_optional::<DynamicSamplingContext>((*dsc).as_str())?;
+
+    let event = match (*event).as_str() {
+        "" => None,
+        json => Annotated::<Event>::from_json(json)?.into_value(),
+    };
+
+    let sampling_match = merge_configs_and_match(
+        true,
+        config.as_ref(),
+        root_config.as_ref(),
+        dsc.as_ref(),
+        event.as_ref(),
+        Utc::now(),
+    );
+
+    RelayStr::from_string(serde_json::to_string(&sampling_match)?)
+}
+
+/// Parses a JSON value, treating an empty string as missing value.
+fn parse_optional<T: serde::de::DeserializeOwned>(
+    json: &str,
+) -> Result<Option<T>, serde_json::Error> {
+    match json {
+        "" => Ok(None),
+        json => serde_json::from_str(json).map(Some),
+    }
+}
+
 /// Validate entire project config.
 ///
 /// If `strict` is true, checks for unknown fields in the input.
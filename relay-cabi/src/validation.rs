@@ -0,0 +1,298 @@
+use std::collections::HashSet;
+
+use relay_dynamic_config::{MetricExtractionConfig, TagSource, TagSpec};
+use relay_metrics::{MetricResourceIdentifier, MetricType};
+use relay_sampling::condition::RuleCondition;
+use relay_sampling::config::{DecayingFunction, RuleType, SamplingMode, SamplingValue};
+use relay_sampling::SamplingConfig;
+use serde::Serialize;
+
+use crate::core::RelayStr;
+
+/// An error in a configuration, pointing at the invalid field.
+#[derive(Debug, PartialEq, Serialize)]
+struct ValidationError {
+    /// Dot-separated path to the invalid field, or empty if the entire config is invalid.
+    path: String,
+    /// A human readable description of the error.
+    message: String,
+}
+
+/// Collects validation errors.
+#[derive(Debug, Default)]
+struct Errors(Vec<ValidationError>);
+
+impl Errors {
+    fn push(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ValidationError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn to_json(&self) -> RelayStr {
+        RelayStr::from_string(serde_json::to_string(&self.0).unwrap_or_default())
+    }
+}
+
+/// Checks that a condition is supported and compiles all of its glob patterns.
+fn validate_condition(errors: &mut Errors, path: &str, condition: &RuleCondition) {
+    if !condition.supported() {
+        errors.push(path, "unsupported condition");
+    } else if let Err(pattern) = condition.compile_globs() {
+        errors.push(path, format!("invalid glob pattern `{pattern}`"));
+    }
+}
+
+fn validate_sampling_config(json: &str) -> Errors {
+    let mut errors = Errors::default();
+
+    let config = match serde_json::from_str::<SamplingConfig>(json) {
+        Ok(config) => config,
+        Err(e) => {
+            errors.push("", e.to_string());
+            return errors;
+        }
+    };
+
+    if config.mode == SamplingMode::Unsupported {
+        errors.push("mode", "unsupported sampling mode");
+    }
+
+    let mut ids = HashSet::new();
+    for (index, rule) in config.rules_v2.iter().enumerate() {
+        let path = format!("rulesV2.{index}");
+
+        if rule.ty == RuleType::Unsupported {
+            errors.push(format!("{path}.type"), "unsupported rule type");
+        }
+
+        validate_condition(&mut errors, &format!("{path}.condition"), &rule.condition);
+
+        match rule.sampling_value {
+            SamplingValue::SampleRate { value } if !(0.0..=1.0).contains(&value) => {
+                errors.push(
+                    format!("{path}.samplingValue.value"),
+                    "sample rate must be between 0 and 1",
+                );
+            }
+            SamplingValue::Factor { value } if value < 0.0 => {
+                errors.push(
+                    format!("{path}.samplingValue.value"),
+                    "factor must not be negative",
+                );
+            }
+            _ => (),
+        }
+
+        if !ids.insert(rule.id) {
+            errors.push(format!("{path}.id"), "duplicate rule id");
+        }
+
+        if let (Some(start), Some(end)) = (rule.time_range.start, rule.time_range.end) {
+            if start >= end {
+                errors.push(format!("{path}.timeRange"), "start must be before end");
+            }
+        }
+
+        if let DecayingFunction::Linear { decayed_value } = rule.decaying_fn {
+            if rule.time_range.start.is_none() || rule.time_range.end.is_none() {
+                errors.push(
+                    format!("{path}.decayingFn"),
+                    "decaying rules require a closed time range",
+                );
+            }
+
+            if !(0.0..=1.0).contains(&decayed_value) {
+                errors.push(
+                    format!("{path}.decayingFn.decayedValue"),
+                    "decayed value must be between 0 and 1",
+                );
+            }
+        }
+    }
+
+    errors
+}
+
+fn validate_tags(errors: &mut Errors, path: &str, tags: &[TagSpec]) {
+    for (index, tag) in tags.iter().enumerate() {
+        let path = format!("{path}.{index}");
+
+        if tag.key.is_empty() {
+            errors.push(format!("{path}.key"), "tag key must not be empty");
+        }
+
+        if tag.field.is_some() && tag.value.is_some() {
+            errors.push(&path, "field and value are mutually exclusive");
+        } else if matches!(tag.source(), TagSource::Unknown) {
+            errors.push(&path, "either field or value is required");
+        }
+
+        if let Some(ref condition) = tag.condition {
+            validate_condition(errors, &format!("{path}.condition"), condition);
+        }
+    }
+}
+
+fn validate_metric_extraction_config(json: &str) -> Errors {
+    let mut errors = Errors::default();
+
+    let config = match serde_json::from_str::<MetricExtractionConfig>(json) {
+        Ok(config) => config,
+        Err(e) => {
+            errors.push("", e.to_string());
+            return errors;
+        }
+    };
+
+    if !config.is_supported() {
+        errors.push("version", "unsupported version");
+    }
+
+    for (index, metric) in config.metrics.iter().enumerate() {
+        let path = format!("metrics.{index}");
+
+        match MetricResourceIdentifier::parse(&metric.mri) {
+            Ok(mri) if mri.ty != MetricType::Counter && metric.field.is_none() => {
+                errors.push(
+                    format!("{path}.field"),
+                    "field is required for metrics other than counters",
+                );
+            }
+            Ok(_) => (),
+            Err(_) => errors.push(format!("{path}.mri"), "invalid metric resource identifier"),
+        }
+
+        if let Some(ref condition) = metric.condition {
+            validate_condition(&mut errors, &format!("{path}.condition"), condition);
+        }
+
+        validate_tags(&mut errors, &format!("{path}.tags"), &metric.tags);
+    }
+
+    for (index, mapping) in config.tags.iter().enumerate() {
+        let path = format!("tags.{index}");
+
+        if mapping.metrics.is_empty() {
+            errors.push(format!("{path}.metrics"), "at least one metric is required");
+        }
+
+        validate_tags(&mut errors, &format!("{path}.tags"), &mapping.tags);
+    }
+
+    errors
+}
+
+/// Validate a dynamic sampling config with all its rules.
+///
+/// Returns a JSON list of errors with the path to the invalid field and an error message. The list
+/// is empty if the config is valid.
+#[no_mangle]
+#[relay_ffi::catch_unwind]
+pub unsafe extern "C" fn relay_validate_sampling_rules(value: *const RelayStr) -> RelayStr {
+    validate_sampling_config((*value).as_str()).to_json()
+}
+
+/// Validate a metric extraction config, including conditions, globs, and tag specs.
+///
+/// Returns a JSON list of errors with the path to the invalid field and an error message. The list
+/// is empty if the config is valid.
+#[no_mangle]
+#[relay_ffi::catch_unwind]
+pub unsafe extern "C" fn relay_validate_metric_extraction_config(
+    value: *const RelayStr,
+) -> RelayStr {
+    validate_metric_extraction_config((*value).as_str()).to_json()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(errors: Errors) -> Vec<String> {
+        errors.0.into_iter().map(|e| e.path).collect()
+    }
+
+    #[test]
+    fn test_sampling_config_valid() {
+        let json = r#"{
+            "rulesV2": [{
+                "type": "trace",
+                "samplingValue": {"type": "sampleRate", "value": 0.5},
+                "condition": {"op": "and", "inner": []},
+                "id": 1
+            }]
+        }"#;
+
+        assert_eq!(paths(validate_sampling_config(json)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_sampling_config_invalid() {
+        let json = r#"{
+            "rulesV2": [{
+                "type": "trace",
+                "samplingValue": {"type": "sampleRate", "value": 1.5},
+                "condition": {"op": "unknown"},
+                "id": 1
+            }, {
+                "type": "other",
+                "samplingValue": {"type": "factor", "value": 2.0},
+                "condition": {"op": "and", "inner": []},
+                "id": 1
+            }, {
+                "type": "transaction",
+                "samplingValue": {"type": "sampleRate", "value": 0.5},
+                "condition": {"op": "glob", "name": "event.release", "value": ["1.[0-9"]},
+                "id": 2
+            }]
+        }"#;
+
+        assert_eq!(
+            paths(validate_sampling_config(json)),
+            [
+                "rulesV2.0.condition",
+                "rulesV2.0.samplingValue.value",
+                "rulesV2.1.type",
+                "rulesV2.1.id",
+                "rulesV2.2.condition",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sampling_config_unparsable() {
+        assert_eq!(paths(validate_sampling_config("{}")), [""]);
+    }
+
+    #[test]
+    fn test_metric_extraction_config_invalid() {
+        let json = r#"{
+            "version": 1,
+            "metrics": [{
+                "category": "transaction",
+                "mri": "d:transactions/duration@millisecond",
+                "tags": [{"key": "foo"}]
+            }, {
+                "category": "transaction",
+                "mri": "invalid"
+            }],
+            "tags": [{
+                "metrics": [],
+                "tags": [{"key": "bar", "field": "event.release", "value": "baz"}]
+            }]
+        }"#;
+
+        assert_eq!(
+            paths(validate_metric_extraction_config(json)),
+            [
+                "metrics.0.field",
+                "metrics.0.tags.0",
+                "metrics.1.mri",
+                "tags.0.metrics",
+                "tags.0.tags.0",
+            ]
+        );
+    }
+}
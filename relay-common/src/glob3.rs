@@ -12,6 +12,20 @@ fn is_match(globs: &[Regex], message: &[u8]) -> bool {
     globs.iter().any(|regex| regex.is_match(message.as_ref()))
 }
 
+/// Parses a glob pattern into a regex, or returns `None` if the pattern is invalid.
+fn parse_glob(pattern: &str) -> Option<Regex> {
+    let glob = GlobBuilder::new(pattern)
+        .case_insensitive(true)
+        .backslash_escape(true)
+        .build()
+        .ok()?;
+
+    RegexBuilder::new(glob.regex())
+        .dot_matches_new_line(true)
+        .build()
+        .ok()
+}
+
 /// A list of patterns for glob matching.
 #[derive(Clone, Default)]
 pub struct GlobPatterns {
@@ -49,28 +63,25 @@ impl GlobPatterns {
         is_match(globs, message)
     }
 
-    /// Parses valid patterns from the list.
-    fn parse_globs(&self) -> Vec<Regex> {
-        let mut globs = Vec::with_capacity(self.patterns.len());
-
-        for pattern in &self.patterns {
-            let glob_result = GlobBuilder::new(pattern)
-                .case_insensitive(true)
-                .backslash_escape(true)
-                .build();
-
-            if let Ok(glob) = glob_result {
-                let regex_result = RegexBuilder::new(glob.regex())
-                    .dot_matches_new_line(true)
-                    .build();
-
-                if let Ok(regex) = regex_result {
-                    globs.push(regex);
-                }
-            }
+    /// Compiles all patterns and returns the first pattern that is not a valid glob.
+    ///
+    /// Otherwise, patterns are compiled when they are first matched and invalid patterns are
+    /// ignored.
+    pub fn compile(&self) -> Result<(), &str> {
+        let globs = self.globs.get_or_init(|| self.parse_globs());
+        if globs.len() == self.patterns.len() {
+            return Ok(());
+        }
+
+        match self.patterns.iter().find(|p| parse_glob(p).is_none()) {
+            Some(pattern) => Err(pattern),
+            None => Ok(()),
         }
+    }
 
-        globs
+    /// Parses valid patterns from the list.
+    fn parse_globs(&self) -> Vec<Regex> {
+        self.patterns.iter().filter_map(|p| parse_glob(p)).collect()
     }
 }
 
@@ -182,6 +193,15 @@ mod tests {
         assert!(globs.is_match("1.18.5.2153-2aa83397b"));
     }
 
+    #[test]
+    fn test_compile() {
+        assert_eq!(globs!("foo*", "1.18.[0-4].*").compile(), Ok(()));
+
+        let globs = globs!("foo*", "foo[", "{bar");
+        assert_eq!(globs.compile(), Err("foo["));
+        assert!(globs.is_match("foobar"));
+    }
+
     #[test]
     fn test_match_neg_unsupported() {
         // this is not necessarily desirable behavior, but it is our current one: negation (!)
@@ -122,6 +122,27 @@ impl fmt::Debug for ByteSize {
     }
 }
 
+#[cfg(feature = "jsonschema")]
+impl schemars::JsonSchema for ByteSize {
+    fn schema_name() -> String {
+        std::any::type_name::<Self>().to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{SchemaObject, SubschemaValidation};
+
+        // Either a number of bytes, or a string with a unit such as `"10MiB"`.
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![u32::json_schema(gen), String::json_schema(gen)]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 impl Serialize for ByteSize {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
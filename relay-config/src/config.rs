@@ -1,27 +1,47 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::TryInto;
 use std::error::Error;
 use std::io::Write;
 use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
-use std::{env, fmt, fs, io};
+use std::{env, fmt, fs};
 
 use anyhow::Context;
-use relay_auth::{generate_key_pair, generate_relay_id, PublicKey, RelayId, SecretKey};
+use ipnetwork::IpNetwork;
+use relay_auth::{
+    generate_key_pair, generate_relay_id, JwtKey, JwtValidation, PublicKey, PublicKeySet, RelayId,
+    SecretKey, SignatureAlgorithm,
+};
+use relay_base_schema::project::{ProjectId, ProjectKey};
 use relay_common::Dsn;
+use relay_dynamic_config::TransactionMetricsOverrides;
 use relay_kafka::{
-    ConfigError as KafkaConfigError, KafkaConfig, KafkaConfigParam, KafkaTopic, TopicAssignments,
+    ConfigError as KafkaConfigError, DeliveryMode, KafkaConfig, KafkaConfigParam, KafkaOAuthConfig,
+    KafkaTopic, PartitionStrategy, SchemaRegistryConfig, TopicAssignment, TopicAssignments,
 };
 use relay_metrics::{AggregatorConfig, Condition, Field, MetricNamespace, ScopedAggregatorConfig};
+use relay_quotas::{DataCategory, Quota};
 use relay_redis::RedisConfig;
+use relay_sampling::condition::RuleCondition;
+use relay_statsd::RelabelRule;
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
 use serde::de::{DeserializeOwned, Unexpected, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use uuid::Uuid;
 
 use crate::byte_size::ByteSize;
-use crate::upstream::UpstreamDescriptor;
+use crate::include::resolve_includes;
+use crate::interpolate::interpolate_env;
+use crate::profile::{active_profile, resolve_profile};
+use crate::reload::{ReloadReport, Reloadable};
+use crate::secrets::resolve_secrets;
+use crate::spki_pin::SpkiPin;
+use crate::upstream::{UpstreamDescriptor, UpstreamRoute, Upstreams};
+use crate::validate::{check_config, check_values, deserialize_document, Diagnostic};
 
 const DEFAULT_NETWORK_OUTAGE_GRACE_PERIOD: u64 = 10;
 
@@ -42,6 +62,16 @@ pub enum ConfigErrorKind {
     BadYaml,
     /// Parsing JSON failed.
     BadJson,
+    /// Parsing TOML failed.
+    BadToml,
+    /// Substituting environment variables failed.
+    BadEnvVar,
+    /// Loading a secret from a file failed.
+    BadSecret,
+    /// Including config fragments failed.
+    BadInclude,
+    /// Applying the active config profile failed.
+    BadProfile,
     /// Invalid config value
     InvalidValue,
     /// The user attempted to run Relay with processing enabled, but uses a binary that was
@@ -56,6 +86,11 @@ impl fmt::Display for ConfigErrorKind {
             Self::CouldNotWriteFile => write!(f, "could not write config file"),
             Self::BadYaml => write!(f, "could not parse yaml config file"),
             Self::BadJson => write!(f, "could not parse json config file"),
+            Self::BadToml => write!(f, "could not parse toml config file"),
+            Self::BadEnvVar => write!(f, "could not substitute environment variables"),
+            Self::BadSecret => write!(f, "could not load secret"),
+            Self::BadInclude => write!(f, "could not include config fragments"),
+            Self::BadProfile => write!(f, "could not apply config profile"),
             Self::InvalidValue => write!(f, "invalid config value"),
             Self::ProcessingNotAvailable => write!(
                 f,
@@ -111,9 +146,9 @@ impl ConfigError {
     }
 
     #[inline]
-    fn field(field: &'static str) -> Self {
+    fn field(field: impl Into<String>) -> Self {
         Self {
-            source: ConfigErrorSource::FieldOverride(field.to_owned()),
+            source: ConfigErrorSource::FieldOverride(field.into()),
             kind: ConfigErrorKind::InvalidValue,
         }
     }
@@ -140,9 +175,10 @@ impl fmt::Display for ConfigError {
 
 impl Error for ConfigError {}
 
-enum ConfigFormat {
+pub(crate) enum ConfigFormat {
     Yaml,
     Json,
+    Toml,
 }
 
 impl ConfigFormat {
@@ -150,6 +186,35 @@ impl ConfigFormat {
         match self {
             ConfigFormat::Yaml => "yml",
             ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+        }
+    }
+
+    /// Returns the error kind for parse errors in this format.
+    fn error_kind(&self) -> ConfigErrorKind {
+        match self {
+            ConfigFormat::Yaml => ConfigErrorKind::BadYaml,
+            ConfigFormat::Json => ConfigErrorKind::BadJson,
+            ConfigFormat::Toml => ConfigErrorKind::BadToml,
+        }
+    }
+
+    /// Parses a config file in this format.
+    pub(crate) fn parse<T: DeserializeOwned>(&self, contents: &str) -> anyhow::Result<T> {
+        Ok(match self {
+            ConfigFormat::Yaml => serde_yaml::from_str(contents)?,
+            ConfigFormat::Json => serde_json::from_str(contents)?,
+            ConfigFormat::Toml => toml::from_str(contents)?,
+        })
+    }
+
+    /// Detects the format of a config file from its extension.
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "yml" | "yaml" => Some(ConfigFormat::Yaml),
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            _ => None,
         }
     }
 }
@@ -161,24 +226,78 @@ trait ConfigObject: DeserializeOwned + Serialize {
     /// The basename of the config file.
     fn name() -> &'static str;
 
+    /// The name of the active profile, see [`resolve_profile`].
+    fn profile() -> Option<String> {
+        None
+    }
+
     /// The full filename of the config file, including the file extension.
     fn path(base: &Path) -> PathBuf {
         base.join(format!("{}.{}", Self::name(), Self::format().extension()))
     }
 
-    /// Loads the config file from a file within the given directory location.
-    fn load(base: &Path) -> anyhow::Result<Self> {
+    /// Reads the contents of the config file.
+    fn read(base: &Path) -> anyhow::Result<(PathBuf, ConfigFormat, String)> {
         let path = Self::path(base);
 
-        let f = fs::File::open(&path)
+        let contents = fs::read_to_string(&path)
             .with_context(|| ConfigError::file(ConfigErrorKind::CouldNotOpenFile, &path))?;
 
-        match Self::format() {
-            ConfigFormat::Yaml => serde_yaml::from_reader(io::BufReader::new(f))
-                .with_context(|| ConfigError::file(ConfigErrorKind::BadYaml, &path)),
-            ConfigFormat::Json => serde_json::from_reader(io::BufReader::new(f))
-                .with_context(|| ConfigError::file(ConfigErrorKind::BadJson, &path)),
+        let format = ConfigFormat::from_path(&path).unwrap_or_else(Self::format);
+        Ok((path, format, contents))
+    }
+
+    /// Loads the config file into a generic document with all secrets resolved.
+    fn load_document(base: &Path) -> anyhow::Result<serde_json::Value> {
+        let (path, format, contents) = Self::read(base)?;
+
+        let mut document = format
+            .parse::<serde_json::Value>(&contents)
+            .with_context(|| ConfigError::file(format.error_kind(), &path))?;
+        interpolate_env(&mut document)
+            .with_context(|| ConfigError::file(ConfigErrorKind::BadEnvVar, &path))?;
+        resolve_includes(&mut document, base)
+            .with_context(|| ConfigError::file(ConfigErrorKind::BadInclude, &path))?;
+        resolve_profile(&mut document, Self::profile().as_deref())
+            .with_context(|| ConfigError::file(ConfigErrorKind::BadProfile, &path))?;
+        resolve_secrets(&mut document, base)
+            .with_context(|| ConfigError::file(ConfigErrorKind::BadSecret, &path))?;
+
+        Ok(document)
+    }
+
+    /// Loads the config file from a file within the given directory location.
+    ///
+    /// References to environment variables in the form of `${VAR}` or `${VAR:-fallback}` are
+    /// substituted in string values after parsing the file. Secret fields can be loaded from files by appending
+    /// `_file` to their name, see [`resolve_secrets`]. Additional fragments listed in `include` are
+    /// merged below the file, see [`resolve_includes`], followed by the active profile, see
+    /// [`resolve_profile`].
+    fn load(base: &Path) -> anyhow::Result<Self> {
+        let (path, format, contents) = Self::read(base)?;
+        let error_kind = format.error_kind();
+
+        // Environment variables, includes, profiles, and secrets are resolved on the generic
+        // document. If there are none, the file is parsed directly to retain the location of errors.
+        if let Ok(mut document) = format.parse::<serde_json::Value>(&contents) {
+            let interpolated = interpolate_env(&mut document)
+                .with_context(|| ConfigError::file(ConfigErrorKind::BadEnvVar, &path))?;
+            let included = resolve_includes(&mut document, base)
+                .with_context(|| ConfigError::file(ConfigErrorKind::BadInclude, &path))?;
+            let profiled = resolve_profile(&mut document, Self::profile().as_deref())
+                .with_context(|| ConfigError::file(ConfigErrorKind::BadProfile, &path))?;
+            let resolved = resolve_secrets(&mut document, base)
+                .with_context(|| ConfigError::file(ConfigErrorKind::BadSecret, &path))?;
+
+            if interpolated || included || profiled || resolved {
+                return serde_json::from_value(document)
+                    .with_context(|| ConfigError::file(error_kind, &path));
+            }
         }
+
+        format
+            .parse(&contents)
+            .with_context(|| ConfigError::file(error_kind, &path))
     }
 
     /// Writes the configuration to a file within the given directory location.
@@ -198,7 +317,7 @@ trait ConfigObject: DeserializeOwned + Serialize {
             .open(&path)
             .with_context(|| ConfigError::file(ConfigErrorKind::CouldNotWriteFile, &path))?;
 
-        match Self::format() {
+        match ConfigFormat::from_path(&path).unwrap_or_else(Self::format) {
             ConfigFormat::Yaml => {
                 f.write_all(CONFIG_YAML_HEADER.as_bytes())?;
                 serde_yaml::to_writer(&mut f, self)
@@ -206,6 +325,12 @@ trait ConfigObject: DeserializeOwned + Serialize {
             }
             ConfigFormat::Json => serde_json::to_writer_pretty(&mut f, self)
                 .with_context(|| ConfigError::file(ConfigErrorKind::CouldNotWriteFile, &path))?,
+            ConfigFormat::Toml => {
+                let contents = toml::to_string_pretty(self).with_context(|| {
+                    ConfigError::file(ConfigErrorKind::CouldNotWriteFile, &path)
+                })?;
+                f.write_all(contents.as_bytes())?
+            }
         }
 
         f.write_all(b"\n").ok();
@@ -246,6 +371,8 @@ pub struct OverridableConfig {
     pub shutdown_timeout: Option<String>,
     /// AWS Extensions API URL
     pub aws_runtime_api: Option<String>,
+    /// "true" if envelope inspection is enabled
+    pub inspect: Option<String>,
 }
 
 /// The relay credentials
@@ -271,6 +398,20 @@ impl Credentials {
         }
     }
 
+    /// Generates a new key pair for the same relay ID.
+    ///
+    /// Until all instances sharing these credentials have picked up the rotated ones, the upstream
+    /// should accept the previous public key as secondary key.
+    pub fn rotate(&self) -> Self {
+        relay_log::info!("rotating relay credentials");
+        let (sk, pk) = generate_key_pair();
+        Self {
+            secret_key: sk,
+            public_key: pk,
+            id: self.id,
+        }
+    }
+
     /// Serializes this configuration to JSON.
     pub fn to_json_string(&self) -> anyhow::Result<String> {
         serde_json::to_string(self)
@@ -294,6 +435,10 @@ pub struct RelayInfo {
     /// The public key that this Relay uses to authenticate and sign requests.
     pub public_key: PublicKey,
 
+    /// Previous public keys of this Relay that are still accepted during a key rotation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secondary_public_keys: Vec<PublicKey>,
+
     /// Marks an internal relay that has privileged access to more project configuration.
     #[serde(default)]
     pub internal: bool,
@@ -304,13 +449,20 @@ impl RelayInfo {
     pub fn new(public_key: PublicKey) -> Self {
         Self {
             public_key,
+            secondary_public_keys: Vec::new(),
             internal: false,
         }
     }
+
+    /// Returns all public keys that are accepted for signatures of this Relay.
+    pub fn public_keys(&self) -> PublicKeySet {
+        PublicKeySet::new(self.public_key.clone(), self.secondary_public_keys.clone())
+    }
 }
 
 /// The operation mode of a relay.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub enum RelayMode {
     /// This relay acts as a proxy for all requests and events.
@@ -393,6 +545,30 @@ fn is_docker() -> bool {
     fs::read_to_string("/proc/self/cgroup").map_or(false, |s| s.contains("/docker"))
 }
 
+/// Checks if the given proxy URL can be parsed and uses a supported scheme.
+pub(crate) fn is_valid_proxy_url(url: &str) -> bool {
+    match url::Url::parse(url) {
+        Ok(url) => matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h"),
+        Err(_) => false,
+    }
+}
+
+/// Checks if a custom response header has a valid name and value.
+///
+/// Names must be non-empty HTTP tokens, and values must not contain control characters other than
+/// horizontal tabs.
+pub(crate) fn is_valid_response_header(name: &str, value: &str) -> bool {
+    let valid_name = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    let valid_value = value
+        .bytes()
+        .all(|b| b == b'\t' || (b' '..=b'~').contains(&b));
+
+    valid_name && valid_value
+}
+
 /// Default value for the "bind" configuration.
 fn default_host() -> IpAddr {
     if is_docker() {
@@ -407,6 +583,7 @@ fn default_host() -> IpAddr {
 ///
 /// Independent of the the readiness condition, shutdown always switches Relay into unready state.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum ReadinessCondition {
     /// (default) Relay is ready when authenticated and connected to the upstream.
@@ -430,12 +607,16 @@ impl Default for ReadinessCondition {
 
 /// Relay specific configuration values.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 pub struct Relay {
     /// The operation mode of this relay.
     pub mode: RelayMode,
     /// The upstream relay or sentry instance.
-    pub upstream: UpstreamDescriptor<'static>,
+    ///
+    /// This can also be a list of upstreams in order of priority. Relay sends requests to the first
+    /// reachable upstream and fails over to the next one during network outages.
+    pub upstream: Upstreams,
     /// The host the relay should bind to (network interface).
     pub host: IpAddr,
     /// The port to bind for the unencrypted relay HTTP server.
@@ -452,25 +633,34 @@ pub struct Relay {
     /// Validation of project identifiers can be safely skipped in these cases.
     #[serde(skip_serializing_if = "is_default")]
     pub override_project_ids: bool,
+    /// Routes that send the traffic of specific projects to other upstreams than `upstream`.
+    ///
+    /// This allows a single Relay to front multiple Sentry organizations or regions. Relay
+    /// authenticates with the upstreams of every route separately. The first matching route
+    /// applies.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<UpstreamRoute>,
 }
 
 impl Default for Relay {
     fn default() -> Self {
         Relay {
             mode: RelayMode::Managed,
-            upstream: "https://sentry.io/".parse().unwrap(),
+            upstream: Upstreams::default(),
             host: default_host(),
             port: 3000,
             tls_port: None,
             tls_identity_path: None,
             tls_identity_password: None,
             override_project_ids: false,
+            routes: Vec::new(),
         }
     }
 }
 
 /// Control the metrics.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 struct Metrics {
     /// Hostname and port of the statsd server.
@@ -494,6 +684,11 @@ struct Metrics {
     /// For example, a value of `0.3` means that only 30% of the emitted metrics will be sent.
     /// Defaults to `1.0` (100%).
     sample_rate: f32,
+    /// Rules to rewrite emitted metrics before they are sent to statsd.
+    ///
+    /// Rules can drop tags, rename metrics, and add static tags. All rules matching a metric are
+    /// applied in order. Defaults to no rules.
+    relabel: Vec<RelabelRule>,
 }
 
 impl Default for Metrics {
@@ -505,12 +700,14 @@ impl Default for Metrics {
             hostname_tag: None,
             buffering: true,
             sample_rate: 1.0,
+            relabel: Vec::new(),
         }
     }
 }
 
 /// Controls various limits
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 struct Limits {
     /// How many requests can be sent concurrently from Relay to the upstream before Relay starts
@@ -522,36 +719,42 @@ struct Limits {
     /// The concurrency of queries is additionally constrained by `max_concurrent_requests`.
     max_concurrent_queries: usize,
     /// The maximum payload size for events.
-    max_event_size: ByteSize,
+    max_event_size: Reloadable<ByteSize>,
     /// The maximum size for each attachment.
-    max_attachment_size: ByteSize,
+    max_attachment_size: Reloadable<ByteSize>,
     /// The maximum combined size for all attachments in an envelope or request.
-    max_attachments_size: ByteSize,
+    max_attachments_size: Reloadable<ByteSize>,
+    /// The maximum size for each trace attachment.
+    max_trace_attachment_size: Reloadable<ByteSize>,
     /// The maximum combined size for all client reports in an envelope or request.
-    max_client_reports_size: ByteSize,
+    max_client_reports_size: Reloadable<ByteSize>,
     /// The maximum payload size for a monitor check-in.
-    max_check_in_size: ByteSize,
+    max_check_in_size: Reloadable<ByteSize>,
     /// The maximum payload size for an entire envelopes. Individual limits still apply.
-    max_envelope_size: ByteSize,
+    max_envelope_size: Reloadable<ByteSize>,
     /// The maximum number of session items per envelope.
-    max_session_count: usize,
+    max_session_count: Reloadable<usize>,
     /// The maximum payload size for general API requests.
-    max_api_payload_size: ByteSize,
+    max_api_payload_size: Reloadable<ByteSize>,
     /// The maximum payload size for file uploads and chunks.
-    max_api_file_upload_size: ByteSize,
+    max_api_file_upload_size: Reloadable<ByteSize>,
     /// The maximum payload size for chunks
-    max_api_chunk_upload_size: ByteSize,
+    max_api_chunk_upload_size: Reloadable<ByteSize>,
     /// The maximum payload size for a profile
-    max_profile_size: ByteSize,
+    max_profile_size: Reloadable<ByteSize>,
     /// The maximum payload size for a span.
-    max_span_size: ByteSize,
+    max_span_size: Reloadable<ByteSize>,
+    /// The maximum payload size for a log.
+    max_log_size: Reloadable<ByteSize>,
     /// The maximum payload size for a compressed replay.
-    max_replay_compressed_size: ByteSize,
+    max_replay_compressed_size: Reloadable<ByteSize>,
     /// The maximum payload size for an uncompressed replay.
     #[serde(alias = "max_replay_size")]
-    max_replay_uncompressed_size: ByteSize,
+    max_replay_uncompressed_size: Reloadable<ByteSize>,
     /// The maximum size for a replay recording Kafka message.
-    max_replay_message_size: ByteSize,
+    max_replay_message_size: Reloadable<ByteSize>,
+    /// The maximum payload size for a mobile replay video.
+    max_replay_video_size: Reloadable<ByteSize>,
     /// The maximum number of threads to spawn for CPU and web work, each.
     ///
     /// The total number of threads spawned will roughly be `2 * max_thread_count + 1`. Defaults to
@@ -567,6 +770,8 @@ struct Limits {
     ///
     /// By default keep-alive is set to a 5 seconds.
     keepalive_timeout: u64,
+    /// Scheduling of work in the envelope processor across lanes.
+    processor_lanes: ProcessorLanes,
 }
 
 impl Default for Limits {
@@ -574,31 +779,133 @@ impl Default for Limits {
         Limits {
             max_concurrent_requests: 100,
             max_concurrent_queries: 5,
-            max_event_size: ByteSize::mebibytes(1),
-            max_attachment_size: ByteSize::mebibytes(100),
-            max_attachments_size: ByteSize::mebibytes(100),
-            max_client_reports_size: ByteSize::kibibytes(4),
-            max_check_in_size: ByteSize::kibibytes(100),
-            max_envelope_size: ByteSize::mebibytes(100),
-            max_session_count: 100,
-            max_api_payload_size: ByteSize::mebibytes(20),
-            max_api_file_upload_size: ByteSize::mebibytes(40),
-            max_api_chunk_upload_size: ByteSize::mebibytes(100),
-            max_profile_size: ByteSize::mebibytes(50),
-            max_span_size: ByteSize::mebibytes(1),
-            max_replay_compressed_size: ByteSize::mebibytes(10),
-            max_replay_uncompressed_size: ByteSize::mebibytes(100),
-            max_replay_message_size: ByteSize::mebibytes(15),
+            max_event_size: ByteSize::mebibytes(1).into(),
+            max_attachment_size: ByteSize::mebibytes(100).into(),
+            max_attachments_size: ByteSize::mebibytes(100).into(),
+            max_trace_attachment_size: ByteSize::mebibytes(20).into(),
+            max_client_reports_size: ByteSize::kibibytes(4).into(),
+            max_check_in_size: ByteSize::kibibytes(100).into(),
+            max_envelope_size: ByteSize::mebibytes(100).into(),
+            max_session_count: 100.into(),
+            max_api_payload_size: ByteSize::mebibytes(20).into(),
+            max_api_file_upload_size: ByteSize::mebibytes(40).into(),
+            max_api_chunk_upload_size: ByteSize::mebibytes(100).into(),
+            max_profile_size: ByteSize::mebibytes(50).into(),
+            max_span_size: ByteSize::mebibytes(1).into(),
+            max_log_size: ByteSize::mebibytes(1).into(),
+            max_replay_compressed_size: ByteSize::mebibytes(10).into(),
+            max_replay_uncompressed_size: ByteSize::mebibytes(100).into(),
+            max_replay_message_size: ByteSize::mebibytes(15).into(),
+            max_replay_video_size: ByteSize::mebibytes(10).into(),
             max_thread_count: num_cpus::get(),
             query_timeout: 30,
             shutdown_timeout: 10,
             keepalive_timeout: 5,
+            processor_lanes: ProcessorLanes::default(),
+        }
+    }
+}
+
+/// Scheduling options for a lane of the envelope processor.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct ProcessorLane {
+    /// The share of processing threads of this lane relative to other lanes with pending work.
+    ///
+    /// Defaults to `1`.
+    pub weight: u32,
+    /// The maximum number of threads that process work of this lane concurrently.
+    ///
+    /// Defaults to `limits.max_thread_count`.
+    pub max_concurrency: Option<usize>,
+}
+
+impl ProcessorLane {
+    fn with_weight(weight: u32) -> Self {
+        Self {
+            weight,
+            max_concurrency: None,
+        }
+    }
+}
+
+impl Default for ProcessorLane {
+    fn default() -> Self {
+        Self::with_weight(1)
+    }
+}
+
+/// Lanes of the envelope processor.
+///
+/// Work in the envelope processor is split into lanes, which share the processing threads by their
+/// weight. Threads that are not needed by a lane without pending work process work of other lanes.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct ProcessorLanes {
+    /// Envelopes with events, transactions, sessions, and other items without a dedicated lane.
+    pub normalization: ProcessorLane,
+    /// Envelopes with replay recordings, which are dominated by PII scrubbing.
+    pub pii: ProcessorLane,
+    /// Envelopes with attachments or Unreal crash reports.
+    pub attachments: ProcessorLane,
+    /// Parsing and rate limiting of metrics.
+    pub metrics: ProcessorLane,
+    /// Compression of envelopes sent to the upstream.
+    pub encoding: ProcessorLane,
+}
+
+impl Default for ProcessorLanes {
+    fn default() -> Self {
+        Self {
+            normalization: ProcessorLane::with_weight(4),
+            pii: ProcessorLane::with_weight(1),
+            attachments: ProcessorLane::with_weight(1),
+            metrics: ProcessorLane::with_weight(2),
+            encoding: ProcessorLane::with_weight(2),
         }
     }
 }
 
+impl Limits {
+    /// Applies the reloadable fields of a newly loaded configuration.
+    fn reload_from(&self, new: &Self) {
+        self.max_event_size.reload_from(&new.max_event_size);
+        self.max_attachment_size
+            .reload_from(&new.max_attachment_size);
+        self.max_attachments_size
+            .reload_from(&new.max_attachments_size);
+        self.max_trace_attachment_size
+            .reload_from(&new.max_trace_attachment_size);
+        self.max_client_reports_size
+            .reload_from(&new.max_client_reports_size);
+        self.max_check_in_size.reload_from(&new.max_check_in_size);
+        self.max_span_size.reload_from(&new.max_span_size);
+        self.max_log_size.reload_from(&new.max_log_size);
+        self.max_envelope_size.reload_from(&new.max_envelope_size);
+        self.max_session_count.reload_from(&new.max_session_count);
+        self.max_api_payload_size
+            .reload_from(&new.max_api_payload_size);
+        self.max_api_file_upload_size
+            .reload_from(&new.max_api_file_upload_size);
+        self.max_api_chunk_upload_size
+            .reload_from(&new.max_api_chunk_upload_size);
+        self.max_profile_size.reload_from(&new.max_profile_size);
+        self.max_replay_compressed_size
+            .reload_from(&new.max_replay_compressed_size);
+        self.max_replay_uncompressed_size
+            .reload_from(&new.max_replay_uncompressed_size);
+        self.max_replay_message_size
+            .reload_from(&new.max_replay_message_size);
+        self.max_replay_video_size
+            .reload_from(&new.max_replay_video_size);
+    }
+}
+
 /// Controls traffic steering.
 #[derive(Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 pub struct Routing {
     /// Accept and forward unknown Envelope items to the upstream.
@@ -613,8 +920,37 @@ pub struct Routing {
     accept_unknown_items: Option<bool>,
 }
 
+/// Strategy for distributing stateless requests across upstreams.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancing {
+    /// All requests are sent to the active upstream.
+    #[default]
+    Disabled,
+    /// Requests are sent to all reachable upstreams in turn.
+    RoundRobin,
+    /// Requests are distributed with weights inversely proportional to upstream response times.
+    Latency,
+}
+
+/// Method by which a managed Relay authenticates with its upstream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamAuthentication {
+    /// Registers with the upstream through the challenge-response flow using the credentials.
+    #[default]
+    Challenge,
+    /// Authenticates with the client certificate configured in `http.client_certificate`.
+    ///
+    /// The upstream maps the certificate to the relay ID, so registration is skipped.
+    Mtls,
+}
+
 /// Http content encoding for both incoming and outgoing web requests.
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum HttpEncoding {
     /// Identity function without no compression.
@@ -637,6 +973,10 @@ pub enum HttpEncoding {
     Gzip,
     /// A format using the [Brotli](https://en.wikipedia.org/wiki/Brotli) algorithm.
     Br,
+    /// A format using the [Zstandard](https://en.wikipedia.org/wiki/Zstd) algorithm.
+    ///
+    /// This format is defined in [RFC 8878](https://datatracker.ietf.org/doc/html/rfc8878).
+    Zstd,
 }
 
 impl HttpEncoding {
@@ -649,6 +989,8 @@ impl HttpEncoding {
             Self::Gzip
         } else if str.eq_ignore_ascii_case("deflate") {
             Self::Deflate
+        } else if str.eq_ignore_ascii_case("zstd") {
+            Self::Zstd
         } else {
             Self::Identity
         }
@@ -663,6 +1005,7 @@ impl HttpEncoding {
             Self::Deflate => Some("deflate"),
             Self::Gzip => Some("gzip"),
             Self::Br => Some("br"),
+            Self::Zstd => Some("zstd"),
         }
     }
 }
@@ -675,6 +1018,7 @@ impl Default for HttpEncoding {
 
 /// Controls authentication with upstream.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 struct Http {
     /// Timeout for upstream requests in seconds.
@@ -688,8 +1032,62 @@ struct Http {
     /// This includes SSL handshakes. Relay reuses connections when the upstream supports connection
     /// keep-alive. Connections are retained for a maximum 75 seconds, or 15 seconds of inactivity.
     connection_timeout: u32,
+    /// Maximum number of idle connections kept open per upstream host.
+    ///
+    /// The total number of concurrent requests is limited by `limits.max_concurrent_requests`.
+    /// Defaults to no limit.
+    pool_max_idle_per_host: Option<usize>,
+    /// Time in seconds after which idle connections to the upstream are closed.
+    ///
+    /// Defaults to `90`.
+    pool_idle_timeout: u64,
+    /// Interval in seconds between TCP keepalive probes on upstream connections.
+    ///
+    /// Keepalive probes prevent firewalls and load balancers from silently dropping idle
+    /// connections. Defaults to no keepalive.
+    tcp_keepalive: Option<u64>,
+    /// Maximum rate of request bodies sent to the upstream, in bytes per second.
+    ///
+    /// Requests are delayed once the rate is exceeded. This prevents Relay from saturating the
+    /// network link, for instance when replaying a backlog after a network outage. Defaults to no
+    /// limit.
+    max_egress_rate: Option<ByteSize>,
+    /// Number of bytes that may be sent in a burst above `max_egress_rate`.
+    ///
+    /// Defaults to one second worth of `max_egress_rate`.
+    egress_burst: Option<ByteSize>,
     /// Maximum interval between failed request retries in seconds.
-    max_retry_interval: u32,
+    ///
+    /// This is the ceiling of the exponential backoff when reconnecting to the upstream.
+    max_retry_interval: Reloadable<u32>,
+    /// Initial interval of the exponential backoff between retries in milliseconds.
+    ///
+    /// The interval grows by 50% with every attempt up to `max_retry_interval`. Defaults to `1000`.
+    retry_backoff_base: Reloadable<u64>,
+    /// Randomization factor applied to the retry backoff, between `0.0` and `1.0`.
+    ///
+    /// A value of `0.5` randomizes every interval by up to 50% in either direction. Defaults to
+    /// `0.0` (no jitter).
+    retry_jitter: Reloadable<f64>,
+    /// Maximum number of times a request is retried after a network error.
+    ///
+    /// Once exhausted, the request fails with the last error. Defaults to no limit.
+    max_retries: Reloadable<Option<usize>>,
+    /// Status codes of upstream responses that are retried like network errors.
+    ///
+    /// Responses with these status codes also count towards network outages. Defaults to `[502,
+    /// 503, 504]`.
+    retry_status_codes: Reloadable<Vec<u16>>,
+    /// Number of consecutive 5xx responses from the upstream after which the circuit breaker opens.
+    ///
+    /// While the circuit breaker is open, queued requests fail immediately without being sent to
+    /// the upstream. Defaults to `0`, which disables the circuit breaker.
+    circuit_breaker_threshold: u32,
+    /// Time in seconds that the circuit breaker stays open before requests are sent again.
+    ///
+    /// If the next response is another 5xx error, the circuit breaker opens again. Defaults to
+    /// `30`.
+    circuit_breaker_timeout: u64,
     /// The custom HTTP Host header to send to the upstream.
     host_header: Option<String>,
     /// The interval in seconds at which Relay attempts to reauthenticate with the upstream server.
@@ -706,6 +1104,24 @@ struct Http {
     /// During a network outage relay will try to reconnect and will buffer all upstream messages
     /// until it manages to reconnect.
     outage_grace_period: u64,
+    /// The interval in seconds at which Relay probes higher-priority upstreams after failing over.
+    ///
+    /// This only applies if multiple upstreams are configured. Relay switches back as soon as a
+    /// higher-priority upstream is reachable again. The actual interval is randomized by up to 50%
+    /// to avoid synchronized probing across Relays.
+    ///
+    /// Defaults to `60` (1 minute).
+    failback_interval: u64,
+    /// Distributes stateless requests across all upstreams configured in `relay.upstream`.
+    ///
+    /// Stateless requests include envelopes and outcomes. Registration, project configs and all
+    /// other requests are always sent to a single upstream. Available options are:
+    ///
+    ///  - `disabled` (default): All requests are sent to a single upstream.
+    ///  - `round_robin`: Stateless requests are sent to all reachable upstreams in turn.
+    ///  - `latency`: Stateless requests are distributed with weights inversely proportional to
+    ///    the response times of the upstreams.
+    load_balancing: LoadBalancing,
     /// Content encoding to apply to upstream store requests.
     ///
     /// By default, Relay applies `gzip` content encoding to compress upstream requests. Compression
@@ -720,7 +1136,49 @@ struct Http {
     ///  - `deflate`: Compression using a zlib header with deflate encoding.
     ///  - `gzip` (default): Compression using gzip.
     ///  - `br`: Compression using the brotli algorithm.
+    ///  - `zstd`: Compression using the zstd algorithm. This is only used for upstreams that
+    ///    advertise support for zstd in the `accept-encoding` response header. Other upstreams
+    ///    receive `gzip` instead.
     encoding: HttpEncoding,
+    /// Compression level for the content encoding of upstream store requests.
+    ///
+    /// The range of supported levels depends on the encoding: `0` to `9` for `deflate` and `gzip`,
+    /// `0` to `11` for `br`, and `1` to `22` for `zstd`. Higher values are clamped to the maximum.
+    /// Defaults to the default level of the respective encoding.
+    encoding_level: Option<u32>,
+    /// URL of a proxy through which all outbound requests are sent.
+    ///
+    /// Supported schemes are `http`, `https`, `socks5` and `socks5h`. With `socks5h`, host names
+    /// are resolved by the proxy. Defaults to no proxy.
+    proxy_url: Option<String>,
+    /// Username for authenticating with the proxy configured in `proxy_url`.
+    proxy_username: Option<String>,
+    /// Password for authenticating with the proxy configured in `proxy_url`.
+    proxy_password: Option<String>,
+    /// Path to a PEM file with additional CA certificates to trust for upstream connections.
+    ///
+    /// This is required if the upstream uses certificates issued by a private CA. Certificates in
+    /// this file are trusted in addition to the system's root certificates.
+    ca_file: Option<PathBuf>,
+    /// Pins of public keys that the upstream certificate chain must contain.
+    ///
+    /// Each pin is the base64-encoded SHA-256 hash of a DER-encoded `SubjectPublicKeyInfo`,
+    /// optionally prefixed with `sha256/`. If set, connections are rejected unless the certificate
+    /// of the upstream or one of its intermediates matches a pin. Defaults to no pinning.
+    spki_pins: Vec<SpkiPin>,
+    /// Path to a PEM file with the client certificate chain and private key for upstream
+    /// connections.
+    ///
+    /// The certificate is presented to the upstream during the TLS handshake. Defaults to no client
+    /// certificate.
+    client_certificate: Option<PathBuf>,
+    /// How Relay authenticates with the upstream in managed mode.
+    ///
+    ///  - `challenge` (default): Relay registers with its credentials through a challenge-response
+    ///    flow and renews the registration every `auth_interval`.
+    ///  - `mtls`: The upstream identifies Relay by its client certificate. This requires
+    ///    `client_certificate` and skips registration.
+    authentication: UpstreamAuthentication,
 }
 
 impl Default for Http {
@@ -728,15 +1186,47 @@ impl Default for Http {
         Http {
             timeout: 5,
             connection_timeout: 3,
-            max_retry_interval: 60, // 1 minute
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: 90,
+            tcp_keepalive: None,
+            max_egress_rate: None,
+            egress_burst: None,
+            max_retry_interval: 60.into(),   // 1 minute
+            retry_backoff_base: 1000.into(), // 1 second
+            retry_jitter: 0.0.into(),
+            max_retries: None.into(),
+            retry_status_codes: vec![502, 503, 504].into(),
+            circuit_breaker_threshold: 0,
+            circuit_breaker_timeout: 30,
             host_header: None,
             auth_interval: Some(600), // 10 minutes
             outage_grace_period: DEFAULT_NETWORK_OUTAGE_GRACE_PERIOD,
+            failback_interval: 60,
+            load_balancing: LoadBalancing::default(),
             encoding: HttpEncoding::Gzip,
+            encoding_level: None,
+            proxy_url: None,
+            proxy_username: None,
+            proxy_password: None,
+            ca_file: None,
+            spki_pins: Vec::new(),
+            client_certificate: None,
+            authentication: UpstreamAuthentication::default(),
         }
     }
 }
 
+impl Http {
+    /// Applies the reloadable fields of a newly loaded configuration.
+    fn reload_from(&self, new: &Self) {
+        self.max_retry_interval.reload_from(&new.max_retry_interval);
+        self.retry_backoff_base.reload_from(&new.retry_backoff_base);
+        self.retry_jitter.reload_from(&new.retry_jitter);
+        self.max_retries.reload_from(&new.max_retries);
+        self.retry_status_codes.reload_from(&new.retry_status_codes);
+    }
+}
+
 /// Default for max memory size, 500 MB.
 fn spool_envelopes_max_memory_size() -> ByteSize {
     ByteSize::mebibytes(500)
@@ -757,8 +1247,19 @@ fn spool_envelopes_max_connections() -> u32 {
     20
 }
 
+/// Default interval for spool health checks and compaction, 5 minutes.
+fn spool_envelopes_compaction_interval() -> u64 {
+    300
+}
+
+/// Default fraction of free pages at which the spool is compacted.
+fn spool_envelopes_compaction_threshold() -> f64 {
+    0.5
+}
+
 /// Persistent buffering configuration for incoming envelopes.
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct EnvelopeSpool {
     /// The path to the persistent spool file.
     ///
@@ -780,6 +1281,17 @@ pub struct EnvelopeSpool {
     /// This is a hard upper bound and defaults to 524288000 bytes (500MB).
     #[serde(default = "spool_envelopes_max_memory_size")]
     max_memory_size: ByteSize,
+    /// Interval in seconds for reporting spool health metrics and compacting the spool file.
+    ///
+    /// Must be greater than 0. Defaults to 300 seconds (5 minutes).
+    #[serde(default = "spool_envelopes_compaction_interval")]
+    compaction_interval: u64,
+    /// The fraction of free pages in the spool file at which it is compacted.
+    ///
+    /// Compaction rebuilds the spool file and returns free pages to the filesystem. Set to a value
+    /// above `1.0` to disable compaction. Defaults to `0.5`.
+    #[serde(default = "spool_envelopes_compaction_threshold")]
+    compaction_threshold: f64,
 }
 
 impl Default for EnvelopeSpool {
@@ -790,19 +1302,104 @@ impl Default for EnvelopeSpool {
             min_connections: spool_envelopes_min_connections(),
             max_disk_size: spool_envelopes_max_disk_size(),
             max_memory_size: spool_envelopes_max_memory_size(),
+            compaction_interval: spool_envelopes_compaction_interval(),
+            compaction_threshold: spool_envelopes_compaction_threshold(),
+        }
+    }
+}
+
+/// Persistent store-and-forward configuration for outgoing envelopes.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct ForwardSpool {
+    /// The path to the persistent spool file.
+    ///
+    /// If set, this enables store-and-forward mode. While the upstream is unreachable, Relay keeps
+    /// accepting envelopes and writes them to this file instead of holding them in memory. Once
+    /// the upstream is reachable again, spooled envelopes are sent in the order they were written.
+    path: Option<PathBuf>,
+    /// The maximum size of the spool file, in bytes.
+    ///
+    /// Envelopes are rejected once the spool is full. Defaults to no limit.
+    max_disk_size: Option<ByteSize>,
+    /// The maximum number of spooled envelopes to send to the upstream per second.
+    ///
+    /// This limits the load on the upstream when replaying a large backlog. Defaults to `100`.
+    drain_rate: u32,
+}
+
+impl Default for ForwardSpool {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_disk_size: None,
+            drain_rate: 100,
+        }
+    }
+}
+
+/// Persistent buffering configuration for messages that could not be produced to Kafka.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct KafkaSpool {
+    /// The path to the persistent spool file.
+    ///
+    /// If set, messages that cannot be produced because the producer queue is full or the brokers
+    /// are unreachable are written to this file and produced again once Kafka recovers. Only
+    /// applies in processing mode with the Kafka store sink.
+    path: Option<PathBuf>,
+    /// The maximum size of the spool file, in bytes.
+    ///
+    /// Messages are dropped once the spool is full. Defaults to 524288000 bytes (500MB).
+    max_disk_size: ByteSize,
+    /// The number of times a spooled message is produced again before it is dropped.
+    ///
+    /// Defaults to `10`.
+    max_retries: u32,
+    /// Interval in seconds between attempts to produce spooled messages.
+    ///
+    /// Defaults to 5 seconds.
+    retry_interval: u64,
+    /// The maximum number of spooled messages read from or written to disk at once.
+    ///
+    /// Defaults to `1000`.
+    batch_size: u32,
+    /// The maximum number of failed messages waiting to be written to the spool.
+    ///
+    /// Messages are dropped if the spool cannot keep up. Defaults to `10000`.
+    max_queue_size: usize,
+}
+
+impl Default for KafkaSpool {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_disk_size: ByteSize::mebibytes(500),
+            max_retries: 10,
+            retry_interval: 5,
+            batch_size: 1000,
+            max_queue_size: default_sink_max_queue_size(),
         }
     }
 }
 
 /// Persistent buffering configuration.
 #[derive(Debug, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct Spool {
     #[serde(default)]
     envelopes: EnvelopeSpool,
+    #[serde(default)]
+    forward: ForwardSpool,
+    #[serde(default)]
+    kafka: KafkaSpool,
 }
 
 /// Controls internal caching behavior.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 struct Cache {
     /// The cache timeout for project configurations in seconds.
@@ -822,6 +1419,12 @@ struct Cache {
     /// The maximum amount of envelopes to queue before dropping them.
     #[serde(alias = "event_buffer_size")]
     envelope_buffer_size: u32,
+    /// The maximum total size of envelopes to queue before dropping them.
+    ///
+    /// When the queue is close to this size, each project can only queue up to its fair share
+    /// of the budget, so that a single project cannot cause drops for all others. Unlimited by
+    /// default.
+    envelope_buffer_memory: Option<ByteSize>,
     /// The cache timeout for non-existing entries.
     miss_expiry: u32,
     /// The buffer timeout for batched queries before sending them upstream in ms.
@@ -835,7 +1438,28 @@ struct Cache {
     /// Interval for evicting outdated project configs from memory.
     eviction_interval: u32,
     /// Interval for fetching new global configs from the upstream, in seconds.
+    ///
+    /// Relay polls at this interval after startup and after errors. While the global config does
+    /// not change, the interval doubles up to `global_config_max_fetch_interval`.
     global_config_fetch_interval: u32,
+    /// The maximum interval for fetching global configs from the upstream, in seconds.
+    ///
+    /// Defaults to 120 seconds (2 minutes).
+    global_config_max_fetch_interval: u32,
+    /// Randomization factor applied to the global config fetch interval, between `0.0` and `1.0`.
+    ///
+    /// A value of `0.1` randomizes every interval by up to 10% in either direction. Defaults to
+    /// `0.1`.
+    global_config_fetch_jitter: f64,
+    /// Path to a file in which project configs are persisted across restarts.
+    ///
+    /// If set, Relay writes all cached project configs to this file on shutdown and loads them on
+    /// startup. Loaded configs are used right away while fresh configs are fetched.
+    snapshot_path: Option<PathBuf>,
+    /// The maximum age of project configs loaded from `snapshot_path`, in seconds.
+    ///
+    /// Older configs are discarded and fetched again. Defaults to 3600 seconds (1 hour).
+    snapshot_max_age: u32,
 }
 
 impl Default for Cache {
@@ -846,12 +1470,17 @@ impl Default for Cache {
             relay_expiry: 3600,   // 1 hour
             envelope_expiry: 600, // 10 minutes
             envelope_buffer_size: 1000,
+            envelope_buffer_memory: None,
             miss_expiry: 60,     // 1 minute
             batch_interval: 100, // 100ms
             batch_size: 500,
-            file_interval: 10,                // 10 seconds
-            eviction_interval: 60,            // 60 seconds
-            global_config_fetch_interval: 10, // 10 seconds
+            file_interval: 10,                     // 10 seconds
+            eviction_interval: 60,                 // 60 seconds
+            global_config_fetch_interval: 10,      // 10 seconds
+            global_config_max_fetch_interval: 120, // 2 minutes
+            global_config_fetch_jitter: 0.1,
+            snapshot_path: None,
+            snapshot_max_age: 3600, // 1 hour
         }
     }
 }
@@ -883,6 +1512,7 @@ fn default_max_rate_limit() -> Option<u32> {
 
 /// Controls Sentry-internal event processing.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct Processing {
     /// True if the Relay should do processing. Defaults to `false`.
     pub enabled: bool,
@@ -921,12 +1551,141 @@ pub struct Processing {
     /// Then metrics will be produced to an entirely different Kafka cluster.
     #[serde(default)]
     pub secondary_kafka_configs: BTreeMap<String, Vec<KafkaConfigParam>>,
+    /// Source of tokens for Kafka producers that use the `OAUTHBEARER` SASL mechanism.
+    ///
+    /// This applies to the default and all secondary Kafka configs that set `sasl.mechanism` to
+    /// `OAUTHBEARER`:
+    ///
+    /// ```yaml
+    /// kafka_config:
+    ///   - name: 'security.protocol'
+    ///     value: 'SASL_SSL'
+    ///   - name: 'sasl.mechanism'
+    ///     value: 'OAUTHBEARER'
+    ///
+    /// kafka_oauth:
+    ///   type: aws_msk_iam
+    ///   region: us-east-1
+    /// ```
+    #[serde(default)]
+    pub kafka_oauth: Option<KafkaOAuthConfig>,
+    /// Confluent Schema Registry for topics that are produced with registered schemas.
+    ///
+    /// ```yaml
+    /// schema_registry:
+    ///   url: 'http://schema-registry:8081'
+    ///   topics:
+    ///     ingest-sessions:
+    ///       format: avro
+    ///       subject_strategy: topic_name
+    /// ```
+    #[serde(default)]
+    pub schema_registry: Option<SchemaRegistryConfig>,
+    /// Routing headers attached to all messages produced to Kafka.
+    ///
+    /// Headers allow consumers to route and filter messages without deserializing their payloads.
+    /// Defaults to no headers.
+    ///
+    /// ```yaml
+    /// kafka_headers:
+    ///   - org_id
+    ///   - data_category
+    /// ```
+    #[serde(default)]
+    pub kafka_headers: BTreeSet<KafkaHeader>,
+    /// Delivery guarantees of Kafka producers.
+    ///
+    /// Defaults to `at_least_once`, where retries of the producer can write duplicate messages.
+    /// With `idempotent`, the brokers deduplicate retries. With `transactional`, the messages of
+    /// each envelope are additionally produced in one transaction, which requires
+    /// `kafka_transactional_id`:
+    ///
+    /// ```yaml
+    /// kafka_delivery: transactional
+    /// kafka_transactional_id: relay-0
+    /// ```
+    ///
+    /// Both modes require `acks: all`, which is the default. Relay fails to start if a Kafka config
+    /// sets incompatible parameters. Transactional delivery does not spool messages to disk, and
+    /// dead letters are aborted along with the rest of their envelope. Outcomes are produced
+    /// idempotently, but never in transactions.
+    #[serde(default)]
+    pub kafka_delivery: DeliveryMode,
+    /// Prefix of the transactional IDs of Kafka producers, required for transactional delivery.
+    ///
+    /// Must be unique per Relay instance and stable across restarts.
+    #[serde(default)]
+    pub kafka_transactional_id: Option<String>,
+    /// The destination of processed envelopes and metrics.
+    ///
+    /// Defaults to Kafka, which is configured with `kafka_config` and `topics`.
+    ///
+    /// ```yaml
+    /// sink:
+    ///   type: pubsub
+    ///   project: my-gcp-project
+    ///   topics:
+    ///     error: ingest-events
+    ///     transaction: ingest-transactions
+    /// ```
+    #[serde(default)]
+    pub sink: StoreSinkConfig,
     /// Kafka topic names.
     #[serde(default)]
     pub topics: TopicAssignments,
+    /// Rules that send matching messages to different Kafka topics.
+    ///
+    /// See [`TopicRoute`] for the available fields. Routes are evaluated in order, and the first
+    /// matching route of a topic is used. Messages that match no route are sent to the topic
+    /// configured in `topics`:
+    ///
+    /// ```yaml
+    /// topic_routes:
+    ///   - topic: transactions
+    ///     condition:
+    ///       op: range
+    ///       name: org_id
+    ///       gte: 1000
+    ///       lt: 2000
+    ///     target:
+    ///       name: ingest-transactions-dedicated
+    ///       config: dedicated
+    /// ```
+    #[serde(default)]
+    pub topic_routes: Vec<TopicRoute>,
+    /// Partition strategies of Kafka topics.
+    ///
+    /// Topics that are not listed are partitioned by the key of their messages, such as the event
+    /// ID. Example:
+    ///
+    /// ```yaml
+    /// partitioning:
+    ///   spans: trace_id
+    ///   metrics_generic: round_robin
+    ///   profiles: sticky
+    /// ```
+    #[serde(default)]
+    pub partitioning: BTreeMap<KafkaTopic, PartitionStrategy>,
+    /// Configuration of the Kafka dead-letter topic.
+    #[serde(default)]
+    pub dead_letters: DeadLetters,
     /// Redis hosts to connect to for storing state for rate limits.
     #[serde(default)]
     pub redis: Option<RedisConfig>,
+    /// In-process caching of hot Redis reads.
+    #[serde(default)]
+    pub redis_cache: RedisCache,
+    /// Prefix of all keys that Relay reads and writes in Redis. Defaults to no prefix.
+    ///
+    /// This allows multiple environments to share one Redis deployment. The prefix is prepended
+    /// to quota counters and project config keys, and must not contain braces. Existing keys are
+    /// not migrated: quota counters start over in the new namespace, and project configs must be
+    /// written to the prefixed keys.
+    #[serde(default)]
+    pub redis_key_prefix: String,
+    /// Memcached servers to track quotas in, instead of Redis.
+    #[serde(default)]
+    pub memcached: Option<MemcachedConfig>,
     /// Maximum chunk size of attachments for Kafka.
     #[serde(default = "default_chunk_size")]
     pub attachment_chunk_size: ByteSize,
@@ -936,6 +1695,18 @@ pub struct Processing {
     /// Maximum rate limit to report to clients.
     #[serde(default = "default_max_rate_limit")]
     pub max_rate_limit: Option<u32>,
+    /// Merging of metric buckets across projects before producing them to Kafka.
+    #[serde(default)]
+    pub org_metrics_aggregation: OrgMetricsAggregation,
+    /// Normalization of monitor check-ins.
+    #[serde(default)]
+    pub monitors: Monitors,
+    /// Converts legacy user reports into feedback items. Defaults to `false`.
+    ///
+    /// When enabled, `user_report` items are replaced with `feedback` items, so that downstream
+    /// consumers only have to handle the feedback format.
+    #[serde(default)]
+    pub convert_user_reports: bool,
 }
 
 impl Default for Processing {
@@ -949,69 +1720,521 @@ impl Default for Processing {
             max_session_secs_in_past: default_max_session_secs_in_past(),
             kafka_config: Vec::new(),
             secondary_kafka_configs: BTreeMap::new(),
+            kafka_oauth: None,
+            schema_registry: None,
+            kafka_headers: BTreeSet::new(),
+            kafka_delivery: DeliveryMode::default(),
+            kafka_transactional_id: None,
+            sink: StoreSinkConfig::default(),
             topics: TopicAssignments::default(),
+            topic_routes: Vec::new(),
+            partitioning: BTreeMap::new(),
+            dead_letters: DeadLetters::default(),
             redis: None,
+            redis_cache: RedisCache::default(),
+            redis_key_prefix: String::new(),
+            memcached: None,
             attachment_chunk_size: default_chunk_size(),
             projectconfig_cache_prefix: default_projectconfig_cache_prefix(),
             max_rate_limit: default_max_rate_limit(),
+            org_metrics_aggregation: OrgMetricsAggregation::default(),
+            monitors: Monitors::default(),
+            convert_user_reports: false,
         }
     }
 }
 
-/// Configuration values for the outcome aggregator
+/// A rule that sends matching messages of a topic to a different Kafka topic.
+///
+/// The condition uses the grammar of dynamic sampling rules and can refer to the fields:
+///  - `org_id`: The organization ID.
+///  - `project_id`: The project ID.
+///  - `category`: The data category, such as `"error"` or `"transaction"`.
+///  - `platform`: The platform of events.
+///  - `size`: The size of the serialized message in bytes.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct TopicRoute {
+    /// The topic whose messages are routed.
+    pub topic: KafkaTopic,
+    /// The condition that messages must match.
+    #[cfg_attr(feature = "jsonschema", schemars(with = "serde_json::Value"))]
+    pub condition: RuleCondition,
+    /// The Kafka topic and config to send matching messages to.
+    pub target: TopicAssignment,
+}
+
+/// In-process cache in front of Redis.
+///
+/// Caches project configs read from Redis and quotas that have been exhausted, so that hot keys
+/// do not cause a Redis round-trip for every envelope.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
-pub struct OutcomeAggregatorConfig {
-    /// Defines the width of the buckets into which outcomes are aggregated, in seconds.
-    pub bucket_interval: u64,
-    /// Defines how often all buckets are flushed, in seconds.
-    pub flush_interval: u64,
+pub struct RedisCache {
+    /// Caches Redis reads in memory. Defaults to `true`.
+    pub enabled: bool,
+    /// The time in seconds for which entries are cached. Defaults to `5`.
+    pub ttl: u64,
+    /// The maximum number of entries in each cache. Defaults to `10000`.
+    pub max_entries: u64,
 }
 
-impl Default for OutcomeAggregatorConfig {
+impl Default for RedisCache {
     fn default() -> Self {
         Self {
-            bucket_interval: 60,
-            flush_interval: 120,
+            enabled: true,
+            ttl: 5,
+            max_entries: 10_000,
         }
     }
 }
 
-/// Determines how to emit outcomes.
-/// For compatibility reasons, this can either be true, false or AsClientReports
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+fn default_memcached_timeout() -> u64 {
+    1
+}
 
-pub enum EmitOutcomes {
-    /// Do not emit any outcomes
-    None,
-    /// Emit outcomes as client reports
-    AsClientReports,
-    /// Emit outcomes as outcomes
-    AsOutcomes,
+/// Memcached servers to track quotas in.
+///
+/// Quotas are tracked in memcached instead of Redis if this is configured. Redis is still used
+/// for reading project configs.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct MemcachedConfig {
+    /// URLs of the memcached servers, such as `memcache://127.0.0.1:11211`.
+    pub servers: Vec<String>,
+    /// Timeout in seconds for reads and writes. Defaults to `1`.
+    #[serde(default = "default_memcached_timeout")]
+    pub timeout: u64,
 }
 
-impl EmitOutcomes {
-    /// Returns true of outcomes are emitted via http, kafka, or client reports.
-    pub fn any(&self) -> bool {
-        !matches!(self, EmitOutcomes::None)
-    }
+/// Configuration of the Kafka dead-letter topic.
+///
+/// Messages that cannot be serialized or exceed the maximum message size are wrapped with their
+/// metadata and the error, and produced to the `dead_letters` topic.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct DeadLetters {
+    /// Produces failed messages to the dead-letter topic. Defaults to `false`.
+    pub enabled: bool,
+    /// The maximum size of the original payload included in a dead letter, in bytes.
+    ///
+    /// Longer payloads are truncated. Defaults to 65536 bytes (64KiB).
+    pub max_payload_size: ByteSize,
 }
 
-impl Serialize for EmitOutcomes {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        // For compatibility, serialize None and AsOutcomes as booleans.
-        match self {
-            Self::None => serializer.serialize_bool(false),
-            Self::AsClientReports => serializer.serialize_str("as_client_reports"),
-            Self::AsOutcomes => serializer.serialize_bool(true),
+impl Default for DeadLetters {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_payload_size: ByteSize::kibibytes(64),
         }
     }
 }
 
-struct EmitOutcomesVisitor;
+/// The destination of processed envelopes and metrics.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StoreSinkConfig {
+    /// Produces messages to the configured Kafka topics.
+    #[default]
+    Kafka,
+    /// Publishes messages to Google Cloud Pub/Sub topics.
+    #[serde(rename = "pubsub")]
+    PubSub(PubSubConfig),
+    /// Sends messages to AWS Kinesis data streams and SQS queues.
+    Aws(AwsSinkConfig),
+    /// Publishes messages to NATS JetStream subjects.
+    Nats(NatsSinkConfig),
+}
+
+fn default_pubsub_max_batch_messages() -> usize {
+    100
+}
+
+fn default_pubsub_max_batch_size() -> ByteSize {
+    ByteSize::mebibytes(1)
+}
+
+fn default_sink_max_batch_delay() -> u64 {
+    10
+}
+
+fn default_sink_max_queue_size() -> usize {
+    10_000
+}
+
+fn default_sink_max_retries() -> u32 {
+    10
+}
+
+/// Configuration for publishing messages to Google Cloud Pub/Sub.
+///
+/// Requests are authenticated with the service account of the GCE metadata server, unless a
+/// custom `endpoint` such as the Pub/Sub emulator is configured.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct PubSubConfig {
+    /// The Google Cloud project that contains the topics.
+    pub project: String,
+    /// Topic names by data category.
+    ///
+    /// Metric buckets are published with the `metric_bucket` category.
+    #[serde(default)]
+    pub topics: BTreeMap<DataCategory, String>,
+    /// Topic for all data categories that are not listed in `topics`.
+    ///
+    /// Messages of unlisted categories are dropped if this is not set.
+    #[serde(default)]
+    pub default_topic: Option<String>,
+    /// Publishes messages with an ordering key derived from the message key.
+    ///
+    /// Message ordering must be enabled on the subscriptions. Defaults to `false`.
+    #[serde(default)]
+    pub ordering_keys: bool,
+    /// The maximum number of messages in a single publish request.
+    ///
+    /// Defaults to `100`. Pub/Sub accepts at most `1000` messages.
+    #[serde(default = "default_pubsub_max_batch_messages")]
+    pub max_batch_messages: usize,
+    /// The maximum total size of messages in a single publish request.
+    ///
+    /// Defaults to `1MiB`. Pub/Sub accepts at most `10MB`.
+    #[serde(default = "default_pubsub_max_batch_size")]
+    pub max_batch_size: ByteSize,
+    /// The maximum time in milliseconds that messages are held back to fill a batch.
+    ///
+    /// Must be greater than `0`. Defaults to `10`.
+    #[serde(default = "default_sink_max_batch_delay")]
+    pub max_batch_delay: u64,
+    /// The maximum number of messages waiting to be published, including failed batches.
+    ///
+    /// Once the queue is full, new messages are rejected. Defaults to `10000`.
+    #[serde(default = "default_sink_max_queue_size")]
+    pub max_queue_size: usize,
+    /// The number of times a failed batch is published again before its messages are dropped.
+    ///
+    /// Retries are delayed with exponential backoff. Defaults to `10`.
+    #[serde(default = "default_sink_max_retries")]
+    pub max_retries: u32,
+    /// Base URL of the Pub/Sub API, for example of the Pub/Sub emulator.
+    ///
+    /// Defaults to `https://pubsub.googleapis.com`.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+impl PubSubConfig {
+    /// Returns the topic name for messages of the given data category.
+    pub fn topic(&self, category: DataCategory) -> Option<&str> {
+        self.topics
+            .get(&category)
+            .or(self.default_topic.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// The value used as Kinesis partition key and SQS FIFO message group.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AwsPartitionKey {
+    /// The partitioning key of the message, which is also used for Kafka.
+    ///
+    /// This distributes messages evenly across shards.
+    #[default]
+    MessageKey,
+    /// The organization ID of the message.
+    Organization,
+    /// The project ID of the message.
+    Project,
+}
+
+/// Configuration for sending messages to AWS.
+///
+/// Every data category is sent either to a Kinesis data stream, which is intended for
+/// high-volume categories, or to an SQS queue. Requests are signed with credentials from the
+/// standard AWS credentials chain: the `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` environment
+/// variables, a web identity token such as IAM roles for service accounts, the ECS container
+/// credentials, or the EC2 instance profile. Temporary credentials are refreshed before they
+/// expire.
+///
+/// Messages are batched up to the limits of the `PutRecords` and `SendMessageBatch` actions.
+/// Messages that exceed the size limit of a single record or SQS message are dropped. Records that
+/// fail are sent again with exponential backoff, up to `max_retries` times.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct AwsSinkConfig {
+    /// The AWS region of the streams and queues.
+    pub region: String,
+    /// Kinesis data stream names by data category.
+    #[serde(default)]
+    pub streams: BTreeMap<DataCategory, String>,
+    /// SQS queue URLs by data category.
+    ///
+    /// Messages to FIFO queues are sent with the partition key as message group.
+    #[serde(default)]
+    pub queues: BTreeMap<DataCategory, String>,
+    /// The value used as partition key. Defaults to `message_key`.
+    #[serde(default)]
+    pub partition_key: AwsPartitionKey,
+    /// The maximum time in milliseconds that messages are held back to fill a batch.
+    ///
+    /// Must be greater than `0`. Defaults to `10`.
+    #[serde(default = "default_sink_max_batch_delay")]
+    pub max_batch_delay: u64,
+    /// The maximum number of messages waiting to be sent, including failed records.
+    ///
+    /// Once the queue is full, new messages are rejected. Defaults to `10000`.
+    #[serde(default = "default_sink_max_queue_size")]
+    pub max_queue_size: usize,
+    /// The number of times a failed record or SQS message is sent again before it is dropped.
+    ///
+    /// Retries are delayed with exponential backoff. Defaults to `10`.
+    #[serde(default = "default_sink_max_retries")]
+    pub max_retries: u32,
+    /// Base URL of the Kinesis API, for example of a local emulator.
+    #[serde(default)]
+    pub kinesis_endpoint: Option<String>,
+    /// Base URL of the SQS API, for example of a local emulator.
+    #[serde(default)]
+    pub sqs_endpoint: Option<String>,
+}
+
+fn default_nats_max_in_flight() -> usize {
+    1000
+}
+
+fn default_nats_max_retries() -> u32 {
+    3
+}
+
+/// Configuration for publishing messages to NATS JetStream.
+///
+/// Messages are published with at-least-once delivery: every message is published again until the
+/// JetStream server acknowledges it or `max_retries` is exhausted, after which it is dropped with
+/// an outcome. Consumers must tolerate duplicates.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct NatsSinkConfig {
+    /// The URL of the NATS server, such as `nats://localhost:4222`.
+    pub url: String,
+    /// Subject templates by data category.
+    ///
+    /// Templates can contain the placeholders `{category}`, `{org_id}`, and `{project_id}`, for
+    /// example `ingest.{category}.{org_id}`. The subjects must be bound to a JetStream stream.
+    #[serde(default)]
+    pub subjects: BTreeMap<DataCategory, String>,
+    /// Subject template for all data categories that are not listed in `subjects`.
+    ///
+    /// Messages of unlisted categories are dropped if this is not set.
+    #[serde(default)]
+    pub default_subject: Option<String>,
+    /// Path to a NATS credentials file for authentication.
+    #[serde(default)]
+    pub credentials_file: Option<PathBuf>,
+    /// The maximum number of published messages waiting for an acknowledgement.
+    ///
+    /// Defaults to `1000`.
+    #[serde(default = "default_nats_max_in_flight")]
+    pub max_in_flight: usize,
+    /// The maximum number of messages waiting to be published, including failed messages.
+    ///
+    /// Once the queue is full, new messages are rejected. Defaults to `10000`.
+    #[serde(default = "default_sink_max_queue_size")]
+    pub max_queue_size: usize,
+    /// The number of times a message is published again if it is not acknowledged.
+    ///
+    /// Retries and reconnects to the server are delayed with exponential backoff. Defaults to
+    /// `3`.
+    #[serde(default = "default_nats_max_retries")]
+    pub max_retries: u32,
+}
+
+impl NatsSinkConfig {
+    /// Returns the subject template for messages of the given data category.
+    pub fn subject(&self, category: DataCategory) -> Option<&str> {
+        self.subjects
+            .get(&category)
+            .or(self.default_subject.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// A routing header attached to messages produced to Kafka.
+///
+/// The header key is the snake case name of the variant. Values are encoded as strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum KafkaHeader {
+    /// The organization ID of the message.
+    OrgId,
+    /// The project ID of the message.
+    ProjectId,
+    /// The data category of the message, such as `error` or `transaction`.
+    DataCategory,
+    /// The ID of the Relay that produced the message, if it has credentials.
+    RelayInstance,
+    /// The UNIX timestamp in seconds at which the message was produced.
+    IngestTimestamp,
+}
+
+impl KafkaHeader {
+    /// Returns the key of this header in Kafka messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OrgId => "org_id",
+            Self::ProjectId => "project_id",
+            Self::DataCategory => "data_category",
+            Self::RelayInstance => "relay_instance",
+            Self::IngestTimestamp => "ingest_timestamp",
+        }
+    }
+}
+
+/// Controls the merging of metric buckets across projects before producing them to Kafka.
+///
+/// Counter and gauge buckets in the configured namespaces that share a name, tags, and timestamp
+/// are merged across all projects of an organization and produced as a single message. This
+/// reduces the number of Kafka messages for Relays that receive the same metrics from many
+/// projects. Merged buckets can no longer be attributed to a project, so they are produced with
+/// project ID `0`. Only enable this for namespaces that are queried per organization.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct OrgMetricsAggregation {
+    /// Metric namespaces whose counter and gauge buckets are merged across projects.
+    ///
+    /// Defaults to no namespaces, which disables the merging.
+    pub namespaces: BTreeSet<MetricNamespace>,
+    /// Interval in seconds at which merged buckets are produced.
+    ///
+    /// Defaults to `10` seconds.
+    pub flush_interval: u64,
+}
+
+impl Default for OrgMetricsAggregation {
+    fn default() -> Self {
+        Self {
+            namespaces: BTreeSet::new(),
+            flush_interval: 10,
+        }
+    }
+}
+
+/// Defaults for the monitor config of check-ins.
+///
+/// Check-ins can upsert their monitor with a monitor config. If the config does not specify a
+/// margin or a maximum runtime, these values are filled in before the check-in is produced.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct Monitors {
+    /// Default check-in margin in minutes. Defaults to leaving the margin unset.
+    pub checkin_margin: Option<u64>,
+    /// Default maximum runtime in minutes. Defaults to leaving the runtime unset.
+    pub max_runtime: Option<u64>,
+}
+
+/// Configuration values for the outcome aggregator
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct OutcomeAggregatorConfig {
+    /// Defines the width of the buckets into which outcomes are aggregated, in seconds.
+    pub bucket_interval: u64,
+    /// Defines how often all buckets are flushed, in seconds.
+    pub flush_interval: u64,
+}
+
+impl Default for OutcomeAggregatorConfig {
+    fn default() -> Self {
+        Self {
+            bucket_interval: 60,
+            flush_interval: 120,
+        }
+    }
+}
+
+/// Parameters for emitting outcomes as client reports.
+///
+/// This only applies if `emit_outcomes` is set to `as_client_reports`.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct ClientReportsConfig {
+    /// The interval in seconds over which outcomes are aggregated into client reports.
+    ///
+    /// Defaults to the flush interval of the outcome aggregator.
+    pub flush_interval: Option<u64>,
+    /// Whether outcomes with different timestamps are sent in separate client reports.
+    ///
+    /// If disabled, all outcomes of a project key within one flush interval are merged into a
+    /// single client report. Defaults to `true`.
+    pub group_by_timestamp: bool,
+    /// Whether outcomes without a dedicated client report field are re-mapped to discarded events.
+    ///
+    /// If enabled, outcomes for invalid data are reported as `discarded_events` with their discard
+    /// reason. Otherwise, they are not reported. Defaults to `false`.
+    pub remap_reasons: bool,
+    /// Whether client reports from downstream Relays and SDKs are folded into the client reports
+    /// of this Relay.
+    ///
+    /// If enabled, received client reports are merged with locally generated outcomes instead of
+    /// being forwarded separately, so that every discarded item is only reported once. Defaults to
+    /// `false`.
+    pub fold_downstream: bool,
+}
+
+impl Default for ClientReportsConfig {
+    fn default() -> Self {
+        Self {
+            flush_interval: None,
+            group_by_timestamp: true,
+            remap_reasons: false,
+            fold_downstream: false,
+        }
+    }
+}
+
+/// Determines how to emit outcomes.
+/// For compatibility reasons, this can either be true, false or AsClientReports
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+
+pub enum EmitOutcomes {
+    /// Do not emit any outcomes
+    None,
+    /// Emit outcomes as client reports
+    AsClientReports,
+    /// Emit outcomes as outcomes
+    AsOutcomes,
+}
+
+impl EmitOutcomes {
+    /// Returns true of outcomes are emitted via http, kafka, or client reports.
+    pub fn any(&self) -> bool {
+        !matches!(self, EmitOutcomes::None)
+    }
+}
+
+impl Serialize for EmitOutcomes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // For compatibility, serialize None and AsOutcomes as booleans.
+        match self {
+            Self::None => serializer.serialize_bool(false),
+            Self::AsClientReports => serializer.serialize_str("as_client_reports"),
+            Self::AsOutcomes => serializer.serialize_bool(true),
+        }
+    }
+}
+
+struct EmitOutcomesVisitor;
 
 impl<'de> Visitor<'de> for EmitOutcomesVisitor {
     type Value = EmitOutcomes;
@@ -1052,8 +2275,35 @@ impl<'de> Deserialize<'de> for EmitOutcomes {
     }
 }
 
+#[cfg(feature = "jsonschema")]
+impl JsonSchema for EmitOutcomes {
+    fn schema_name() -> String {
+        std::any::type_name::<Self>().to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{InstanceType, SchemaObject, SubschemaValidation};
+
+        let client_reports = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(vec!["as_client_reports".into()]),
+            ..Default::default()
+        };
+
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![bool::json_schema(gen), client_reports.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 /// Outcome generation specific configuration values.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 pub struct Outcomes {
     /// Controls whether outcomes will be emitted when processing is disabled.
@@ -1073,6 +2323,16 @@ pub struct Outcomes {
     pub source: Option<String>,
     /// Configures the outcome aggregator.
     pub aggregator: OutcomeAggregatorConfig,
+    /// Configures how outcomes are emitted as client reports.
+    pub client_reports: ClientReportsConfig,
+    /// Sends outcomes to an HTTP webhook in addition to the configured outcome destination.
+    pub webhook: Option<OutcomeWebhook>,
+    /// A dedicated upstream for outcomes and client reports.
+    ///
+    /// If set, outcomes and envelopes containing only client reports are sent to this upstream
+    /// instead of `relay.upstream`, with a separate request queue and retries. In managed mode,
+    /// Relay authenticates with this upstream separately. Defaults to `relay.upstream`.
+    pub upstream: Option<Upstreams>,
 }
 
 impl Default for Outcomes {
@@ -1084,10 +2344,222 @@ impl Default for Outcomes {
             batch_interval: 500,
             source: None,
             aggregator: OutcomeAggregatorConfig::default(),
+            client_reports: ClientReportsConfig::default(),
+            webhook: None,
+            upstream: None,
+        }
+    }
+}
+
+/// Configuration for sending outcomes to an HTTP webhook.
+///
+/// Outcomes are batched and sent as JSON via `POST` requests in the same format that Relay uses
+/// to send outcomes to its upstream. Failed requests are retried with exponential backoff.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct OutcomeWebhook {
+    /// The URL that outcome batches are posted to.
+    pub url: String,
+    /// The maximum number of outcomes that are batched into a single request.
+    pub batch_size: usize,
+    /// The maximum time interval (in milliseconds) that an outcome may be batched.
+    pub batch_interval: u64,
+    /// The number of times a failed request is retried before the batch is dropped.
+    pub max_retries: usize,
+    /// The maximum interval (in seconds) between two retries of the same batch.
+    pub max_backoff: u64,
+    /// The maximum number of requests to the webhook in flight at the same time.
+    pub max_concurrent_requests: usize,
+    /// The maximum number of batches that are being sent or waiting for a retry.
+    ///
+    /// New batches are dropped while this many batches are pending.
+    pub max_pending_batches: usize,
+}
+
+impl Default for OutcomeWebhook {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            batch_size: 1000,
+            batch_interval: 1000,
+            max_retries: 5,
+            max_backoff: 60,
+            max_concurrent_requests: 4,
+            max_pending_batches: 100,
+        }
+    }
+}
+
+/// Local adaptive sampling towards a target volume.
+///
+/// This is intended for proxy and static Relays, which do not receive dynamic sampling rules and
+/// biases computed by Sentry. It is ignored in managed mode.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct TargetSampling {
+    /// The number of transactions per minute and project that Relay should forward.
+    ///
+    /// Adaptive sampling is disabled if this is not set.
+    pub events_per_minute: Option<u64>,
+    /// Interval in seconds after which the sample rate of a project is recomputed.
+    ///
+    /// Defaults to `60`.
+    pub adjustment_interval: u64,
+    /// The lowest sample rate that adaptive sampling may apply, between `0.0` and `1.0`.
+    ///
+    /// Defaults to `0.0`.
+    pub min_sample_rate: f64,
+}
+
+impl Default for TargetSampling {
+    fn default() -> Self {
+        Self {
+            events_per_minute: None,
+            adjustment_interval: 60,
+            min_sample_rate: 0.0,
+        }
+    }
+}
+
+/// Sampling options applied locally by this Relay.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct Sampling {
+    /// Configures local adaptive sampling towards a target volume.
+    pub target: TargetSampling,
+}
+
+/// Rate limiting without Redis.
+///
+/// Processing Relays enforce quotas in Redis. Other Relays can enable an in-memory rate limiter
+/// instead, which enforces quotas approximately, since every Relay instance counts separately.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct RateLimiting {
+    /// Enables the in-memory rate limiter.
+    ///
+    /// The rate limiter enforces quotas from project configs, as well as the `quotas` configured
+    /// below. Defaults to `false`.
+    pub local: bool,
+    /// Additional quotas enforced for all projects.
+    ///
+    /// These use the same format as quotas in project configs. The `scope` and `scopeId` fields
+    /// restrict a quota to an organization, a project, or a single DSN key.
+    pub quotas: Reloadable<Vec<Quota>>,
+}
+
+/// Debug output of processed envelopes.
+///
+/// When enabled, Relay writes a sample of envelopes after processing as pretty-printed JSON,
+/// together with the filters, sampling rules, and rate limits that applied to them. Only the
+/// payload of the event, which has passed data scrubbing, is written. Other items are listed with
+/// their type and size. Envelopes that are dropped during processing are written without their
+/// items.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct Inspect {
+    /// Enables envelope inspection. Defaults to `false`.
+    pub enabled: bool,
+    /// Writes every N-th processed envelope.
+    ///
+    /// Defaults to `1000`. A value of `1` writes every envelope.
+    pub sample_every: u64,
+    /// Directory into which envelopes are written, one file per envelope.
+    ///
+    /// Required if inspection is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// The maximum number of files written into the directory.
+    ///
+    /// Once reached, no further envelopes are written until Relay restarts. Defaults to `1000`.
+    pub max_files: usize,
+}
+
+impl Default for Inspect {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_every: 1000,
+            path: None,
+            max_files: 1000,
         }
     }
 }
 
+/// Recording of incoming requests for replay with `relay replay`.
+///
+/// Requests to ingestion endpoints are recorded with their headers and raw body for a bounded
+/// window after startup. Headers with credentials, such as `Authorization`, `Cookie`, and
+/// `X-Sentry-Auth`, are not recorded. Recorded files are only readable by the user running Relay.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct Capture {
+    /// Directory into which requests are recorded.
+    ///
+    /// Capturing is disabled if this is not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Number of seconds after startup during which requests are recorded.
+    ///
+    /// The window starts when Relay starts and is not restarted when the config is reloaded.
+    ///
+    /// Defaults to `300`.
+    pub duration: u64,
+    /// The maximum number of recorded requests.
+    ///
+    /// Defaults to `10000`.
+    pub max_requests: usize,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self {
+            path: None,
+            duration: 300,
+            max_requests: 10_000,
+        }
+    }
+}
+
+/// Local additions to the PII configs of projects.
+///
+/// The directory contains PII configs named `<project_id>.json`. Their rules and applications are
+/// added to the PII config of the project received from the upstream, and cannot remove or replace
+/// rules configured there. Files are reloaded in the same interval as local project configs.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct PiiOverrides {
+    /// Directory containing the PII configs.
+    ///
+    /// Relative paths are resolved against the config directory. Disabled if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+/// Normalization of client IP addresses.
+///
+/// Applies to the IP address used to resolve `{{auto}}`, and to the IP address of the user in
+/// events. Normalized addresses are used for inbound filters and PII scrubbing.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct IpNormalization {
+    /// The length of the network prefix to which IPv6 addresses are truncated.
+    ///
+    /// For example, with a prefix of `64`, all addresses of a `/64` network map to the same
+    /// address. Must be at most `128`. IPv6 addresses are only converted to their canonical form
+    /// if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6_prefix: Option<u8>,
+}
+
 /// Minimal version of a config for dumping out.
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct MinimalConfig {
@@ -1125,8 +2597,16 @@ mod config_relay_info {
 
     // Uses snake_case as opposed to camelCase.
     #[derive(Debug, Serialize, Deserialize, Clone)]
-    struct RelayInfoConfig {
+    #[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+    pub(super) struct RelayInfoConfig {
+        /// The public key that this Relay uses to authenticate and sign requests.
+        #[cfg_attr(feature = "jsonschema", schemars(with = "String"))]
         public_key: PublicKey,
+        /// Previous public keys that are still accepted during a key rotation.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        #[cfg_attr(feature = "jsonschema", schemars(with = "Vec<String>"))]
+        secondary_public_keys: Vec<PublicKey>,
+        /// Marks an internal relay that has privileged access to more project configuration.
         #[serde(default)]
         internal: bool,
     }
@@ -1135,6 +2615,7 @@ mod config_relay_info {
         fn from(v: RelayInfoConfig) -> Self {
             RelayInfo {
                 public_key: v.public_key,
+                secondary_public_keys: v.secondary_public_keys,
                 internal: v.internal,
             }
         }
@@ -1144,6 +2625,7 @@ mod config_relay_info {
         fn from(v: RelayInfo) -> Self {
             RelayInfoConfig {
                 public_key: v.public_key,
+                secondary_public_keys: v.secondary_public_keys,
                 internal: v.internal,
             }
         }
@@ -1171,8 +2653,40 @@ mod config_relay_info {
     }
 }
 
+/// Authentication of downstream Relays with client certificates.
+///
+/// Relay does not terminate TLS. A proxy in front of Relay must verify client certificates and
+/// forward the verified certificate in a request header, for example nginx with
+/// `proxy_set_header X-SSL-Client-Cert $ssl_client_escaped_cert`. The header is only honored on
+/// connections from one of the `trusted_proxies`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct ClientCertificateAuth {
+    /// Name of the header with the URL-encoded PEM certificate forwarded by the proxy.
+    ///
+    /// Authentication with client certificates is disabled unless this is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    /// IP addresses or networks of the proxies that forward client certificates.
+    ///
+    /// The header is ignored on connections from all other peers. Required if `header` is set.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "jsonschema", schemars(with = "Vec<String>"))]
+    pub trusted_proxies: Vec<IpNetwork>,
+    /// Maps SPKI pins of client certificates to relay IDs.
+    ///
+    /// Each pin is the base64-encoded SHA-256 hash of the certificate's DER-encoded
+    /// `SubjectPublicKeyInfo`, optionally prefixed with `sha256/`. The relay IDs must be listed in
+    /// `static_relays`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[cfg_attr(feature = "jsonschema", schemars(with = "BTreeMap<String, String>"))]
+    pub relays: BTreeMap<SpkiPin, RelayId>,
+}
+
 /// Authentication options.
 #[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct AuthConfig {
     /// Controls responses from the readiness health check endpoint based on authentication.
     #[serde(default, skip_serializing_if = "is_default")]
@@ -1180,11 +2694,27 @@ pub struct AuthConfig {
 
     /// Statically authenticated downstream relays.
     #[serde(default, with = "config_relay_info")]
+    #[cfg_attr(
+        feature = "jsonschema",
+        schemars(with = "HashMap<String, config_relay_info::RelayInfoConfig>")
+    )]
     pub static_relays: HashMap<RelayId, RelayInfo>,
+
+    /// Authentication of downstream Relays with client certificates instead of signatures.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub client_certificates: ClientCertificateAuth,
+
+    /// The algorithm to sign requests to the upstream with.
+    ///
+    /// Defaults to `ed25519`. Use `ed25519ph` only if the upstream supports it.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[cfg_attr(feature = "jsonschema", schemars(with = "String"))]
+    pub signature_algorithm: SignatureAlgorithm,
 }
 
 /// AWS extension config.
 #[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct AwsConfig {
     /// The host and port of the AWS lambda extensions API.
     ///
@@ -1195,12 +2725,313 @@ pub struct AwsConfig {
 
 /// GeoIp database configuration options.
 #[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct GeoIpConfig {
     /// The path to GeoIP database.
     path: Option<PathBuf>,
 }
 
+fn default_vault_mount() -> String {
+    "secret".to_owned()
+}
+
+/// An external secrets store from which Relay fetches its credentials.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SecretsProvider {
+    /// A secret in the KV version 2 secrets engine of HashiCorp Vault.
+    Vault {
+        /// The address of the Vault server, for example `https://vault.example.com:8200`.
+        address: String,
+        /// The token used to authenticate with Vault.
+        ///
+        /// Use `token_file` to read the token from a file.
+        token: String,
+        /// The mount path of the KV secrets engine. Defaults to `secret`.
+        #[serde(default = "default_vault_mount")]
+        mount: String,
+        /// The path of the secret within the secrets engine.
+        path: String,
+        /// The Vault Enterprise namespace of the secret.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
+    },
+    /// A secret in AWS Secrets Manager.
+    ///
+    /// AWS credentials are resolved like the standard AWS credentials chain, from environment
+    /// variables, a web identity token, the ECS container credentials, or the EC2 instance profile.
+    AwsSecretsManager {
+        /// The AWS region of the secret, for example `us-east-1`.
+        region: String,
+        /// The name or ARN of the secret.
+        secret_id: String,
+        /// A custom endpoint URL. Defaults to the regional Secrets Manager endpoint.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        endpoint: Option<String>,
+    },
+}
+
+/// Controls fetching credentials from an external secrets store.
+///
+/// The secret must be a JSON object. It can contain the Relay credentials with the same fields as
+/// `credentials.json`, as well as credentials for Redis and Kafka, see [`ProvidedSecrets`].
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct Secrets {
+    /// The secrets store. Defaults to none.
+    pub provider: Option<SecretsProvider>,
+    /// The interval in seconds at which secrets are fetched again to pick up rotations.
+    ///
+    /// Rotated Relay credentials are used for the next authentication with the upstream. Changed
+    /// Redis and Kafka credentials require a restart. Set to `0` to only fetch secrets at startup.
+    /// Defaults to `300`.
+    pub refresh_interval: u64,
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            refresh_interval: 300,
+        }
+    }
+}
+
+/// Secrets fetched from a [`SecretsProvider`].
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ProvidedSecrets {
+    /// The globally unique ID of the relay.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<RelayId>,
+    /// The public key of the relay.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<PublicKey>,
+    /// The secret key of the relay.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<SecretKey>,
+    /// The URL of the Redis server, replacing `processing.redis`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redis: Option<String>,
+    /// Kafka producer parameters added to `processing.kafka_config`, such as `sasl.password`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub kafka_config: BTreeMap<String, String>,
+}
+
+impl ProvidedSecrets {
+    /// Returns the Relay credentials if the secret contains all of them.
+    pub fn credentials(&self) -> Option<Credentials> {
+        Some(Credentials {
+            secret_key: self.secret_key.clone()?,
+            public_key: self.public_key.clone()?,
+            id: self.id?,
+        })
+    }
+}
+
+/// Controls the admin API for changing settings at runtime.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct Admin {
+    /// The bearer token required to access the admin API.
+    ///
+    /// The admin API is disabled unless a token is configured. The token can be loaded from a file
+    /// with `token_file`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Keys to accept signed JSON Web Tokens as bearer tokens.
+    pub jwt: AdminJwt,
+    /// Enables endpoints to record CPU and heap profiles.
+    ///
+    /// Requires Relay to be built with the `profiling` feature. Defaults to `false`.
+    pub profiling: bool,
+}
+
+/// Controls the audit log of configuration changes.
+///
+/// When enabled, Relay records an entry whenever the global config, project configs, quotas,
+/// credentials, or its own configuration change at runtime. Entries list the paths of changed
+/// fields, but never their values.
 #[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct Audit {
+    /// Path to a file to which audit entries are appended as JSON lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// URL to which audit entries are sent as JSON in `POST` requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+}
+
+/// Thresholds reported by the detailed health check.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct Health {
+    /// The fraction of system memory above which Relay reports high memory usage.
+    ///
+    /// Defaults to `0.95`.
+    pub max_memory_percent: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            max_memory_percent: 0.95,
+        }
+    }
+}
+
+/// Verification of JSON Web Tokens for the admin API.
+///
+/// Tokens must be signed with `HS256` using one of the `secrets` or with `EdDSA` using one of the
+/// `public_keys`. Reading settings requires the `admin:read` or `admin:write` scope, changing them
+/// requires the `admin:write` scope.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct AdminJwt {
+    /// Shared secrets to verify tokens signed with `HS256`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub secrets: Vec<String>,
+    /// Ed25519 public keys to verify tokens signed with `EdDSA`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "jsonschema", schemars(with = "Vec<String>"))]
+    pub public_keys: Vec<PublicKey>,
+    /// The audience that must be listed in the `aud` claim of tokens.
+    pub audience: String,
+    /// Tolerance in seconds for clock skew when checking the `exp` and `nbf` claims.
+    pub leeway: u64,
+}
+
+impl Default for AdminJwt {
+    fn default() -> Self {
+        Self {
+            secrets: Vec::new(),
+            public_keys: Vec::new(),
+            audience: "relay-admin".to_owned(),
+            leeway: 60,
+        }
+    }
+}
+
+fn serialize_level<S>(level: &Option<relay_log::Level>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match level {
+        Some(level) => serializer.collect_str(level),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_level<'de, D>(deserializer: D) -> Result<Option<relay_log::Level>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let Some(level) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    match level.parse() {
+        Ok(level) => Ok(Some(level)),
+        Err(_) => Err(D::Error::invalid_value(
+            Unexpected::Str(&level),
+            &"a log level",
+        )),
+    }
+}
+
+fn serialize_levels<S>(
+    filters: &Option<BTreeMap<String, relay_log::Level>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match filters {
+        Some(filters) => serializer.collect_map(filters.iter().map(|(m, l)| (m, l.as_str()))),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_levels<'de, D>(
+    deserializer: D,
+) -> Result<Option<BTreeMap<String, relay_log::Level>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let Some(filters) = Option::<BTreeMap<String, String>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    filters
+        .into_iter()
+        .map(|(module, level)| match level.parse() {
+            Ok(level) => Ok((module, level)),
+            Err(_) => Err(D::Error::invalid_value(
+                Unexpected::Str(&level),
+                &"a log level",
+            )),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Settings changed at runtime through the admin API.
+///
+/// Overrides take precedence over the config file and are persisted to `runtime_overrides.json`
+/// in the config folder, so that they survive restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeOverrides {
+    /// Overrides `logging.level`.
+    #[serde(
+        serialize_with = "serialize_level",
+        deserialize_with = "deserialize_level",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub log_level: Option<relay_log::Level>,
+    /// Overrides `logging.filters`, the log levels of individual modules.
+    #[serde(
+        serialize_with = "serialize_levels",
+        deserialize_with = "deserialize_levels",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub log_filters: Option<BTreeMap<String, relay_log::Level>>,
+    /// Overrides `logging.format`, for example to switch to JSON output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_format: Option<relay_log::LogFormat>,
+    /// Overrides `metrics.sample_rate`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics_sample_rate: Option<f32>,
+    /// Envelope item types that are dropped as soon as Relay receives them, for example
+    /// `attachment`.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub disabled_item_types: BTreeSet<String>,
+}
+
+impl ConfigObject for RuntimeOverrides {
+    fn format() -> ConfigFormat {
+        ConfigFormat::Json
+    }
+
+    fn name() -> &'static str {
+        "runtime_overrides"
+    }
+}
+
+/// The contents of the Relay config file.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 struct ConfigValues {
     #[serde(default)]
     relay: Relay,
@@ -1213,7 +3044,7 @@ struct ConfigValues {
     #[serde(default)]
     limits: Limits,
     #[serde(default)]
-    logging: relay_log::LogConfig,
+    logging: Reloadable<relay_log::LogConfig>,
     #[serde(default)]
     routing: Routing,
     #[serde(default)]
@@ -1225,15 +3056,40 @@ struct ConfigValues {
     #[serde(default)]
     outcomes: Outcomes,
     #[serde(default)]
+    sampling: Sampling,
+    #[serde(default)]
+    rate_limiting: RateLimiting,
+    #[serde(default)]
+    inspect: Inspect,
+    #[serde(default)]
+    capture: Capture,
+    #[serde(default)]
+    pii_overrides: PiiOverrides,
+    #[serde(default)]
+    ip_normalization: IpNormalization,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    response_headers: BTreeMap<String, String>,
+    #[serde(default)]
     aggregator: AggregatorConfig,
     #[serde(default)]
     secondary_aggregators: Vec<ScopedAggregatorConfig>,
     #[serde(default)]
+    #[cfg_attr(feature = "jsonschema", schemars(with = "serde_json::Value"))]
+    transaction_metrics: TransactionMetricsOverrides,
+    #[serde(default)]
     auth: AuthConfig,
     #[serde(default)]
     aws: AwsConfig,
     #[serde(default)]
     geoip: GeoIpConfig,
+    #[serde(default)]
+    secrets: Secrets,
+    #[serde(default)]
+    admin: Admin,
+    #[serde(default)]
+    health: Health,
+    #[serde(default)]
+    audit: Audit,
 }
 
 impl ConfigObject for ConfigValues {
@@ -1244,13 +3100,35 @@ impl ConfigObject for ConfigValues {
     fn name() -> &'static str {
         "config"
     }
+
+    /// Returns the profile selected with the `RELAY_PROFILE` environment variable.
+    fn profile() -> Option<String> {
+        active_profile()
+    }
+
+    /// Returns `config.yml`, or `config.toml` if only the TOML file exists.
+    fn path(base: &Path) -> PathBuf {
+        let yaml = base.join("config.yml");
+        let toml = base.join("config.toml");
+        if !yaml.exists() && toml.exists() {
+            toml
+        } else {
+            yaml
+        }
+    }
 }
 
 /// Config struct.
 pub struct Config {
     values: ConfigValues,
-    credentials: Option<Credentials>,
+    /// Shared with the configs of upstream routes, so that rotated credentials apply to all.
+    credentials: Arc<Reloadable<Option<Credentials>>>,
+    runtime_overrides: Reloadable<RuntimeOverrides>,
     path: PathBuf,
+    /// The serialized contents of the config file without overrides, used to detect changes.
+    file_values: Mutex<serde_json::Value>,
+    /// Copies of this config for upstream routes, which receive reloaded values as well.
+    nested: Mutex<Vec<Weak<Config>>>,
 }
 
 impl fmt::Debug for Config {
@@ -1269,23 +3147,97 @@ impl Config {
             .map(|x| x.join(path.as_ref()))
             .unwrap_or_else(|_| path.as_ref().to_path_buf());
 
+        let values = ConfigValues::load(&path)?;
+        let file_values = serde_json::to_value(&values)
+            .with_context(|| ConfigError::file(ConfigErrorKind::BadJson, &path))?;
+
         let config = Config {
-            values: ConfigValues::load(&path)?,
+            values,
             credentials: if Credentials::path(&path).exists() {
-                Some(Credentials::load(&path)?)
+                Arc::new(Some(Credentials::load(&path)?).into())
+            } else {
+                Arc::default()
+            },
+            runtime_overrides: if RuntimeOverrides::path(&path).exists() {
+                RuntimeOverrides::load(&path)?.into()
             } else {
-                None
+                Reloadable::default()
             },
             path: path.clone(),
+            file_values: Mutex::new(file_values),
+            nested: Mutex::default(),
         };
 
         if cfg!(not(feature = "processing")) && config.processing_enabled() {
             return Err(ConfigError::file(ConfigErrorKind::ProcessingNotAvailable, &path).into());
         }
 
+        if let Some(diagnostic) = config.first_invalid_value() {
+            return Err(ConfigError::file(ConfigErrorKind::InvalidValue, &path))
+                .context(diagnostic);
+        }
+
         Ok(config)
     }
 
+    /// Returns the first error reported by [`check_values`] for this config.
+    fn first_invalid_value(&self) -> Option<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        check_values(self, &mut diagnostics);
+        diagnostics.into_iter().find(Diagnostic::is_error)
+    }
+
+    /// Validates the config in the given config folder without starting Relay.
+    ///
+    /// In addition to the checks performed by [`from_path`](Self::from_path), this reports unknown
+    /// fields and combinations of values that conflict with each other. Errors in the config file
+    /// are returned as [`Diagnostic`]s with the path of the affected field. An error is only
+    /// returned if the config file cannot be read or parsed at all.
+    pub fn validate<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<Diagnostic>> {
+        let path = env::current_dir()
+            .map(|x| x.join(path.as_ref()))
+            .unwrap_or_else(|_| path.as_ref().to_path_buf());
+
+        let mut diagnostics = Vec::new();
+        let document = ConfigValues::load_document(&path)?;
+        let Some(values) = deserialize_document(document, &mut diagnostics) else {
+            return Ok(diagnostics);
+        };
+
+        let credentials = if Credentials::path(&path).exists() {
+            match Credentials::load(&path) {
+                Ok(credentials) => Some(credentials),
+                Err(error) => {
+                    diagnostics.push(Diagnostic::error("", format!("{error:#}")));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let config = Config {
+            values,
+            credentials: Arc::new(credentials.into()),
+            runtime_overrides: Reloadable::default(),
+            path,
+            file_values: Mutex::default(),
+            nested: Mutex::default(),
+        };
+
+        check_config(&config, &mut diagnostics);
+        Ok(diagnostics)
+    }
+
+    /// Returns the JSON Schema of the config file.
+    ///
+    /// The schema describes all fields of `config.yml` including their documentation and defaults.
+    /// It does not cover the credentials file.
+    #[cfg(feature = "jsonschema")]
+    pub fn json_schema() -> schemars::schema::RootSchema {
+        schemars::schema_for!(ConfigValues)
+    }
+
     /// Creates a config from a JSON value.
     ///
     /// This is mostly useful for tests.
@@ -1293,8 +3245,11 @@ impl Config {
         Ok(Config {
             values: serde_json::from_value(value)
                 .with_context(|| ConfigError::new(ConfigErrorKind::BadJson))?,
-            credentials: None,
+            credentials: Arc::default(),
+            runtime_overrides: Reloadable::default(),
             path: PathBuf::new(),
+            file_values: Mutex::default(),
+            nested: Mutex::default(),
         })
     }
 
@@ -1314,12 +3269,12 @@ impl Config {
 
         if let Some(upstream) = overrides.upstream {
             relay.upstream = upstream
-                .parse::<UpstreamDescriptor>()
+                .parse::<Upstreams>()
                 .with_context(|| ConfigError::field("upstream"))?;
         } else if let Some(upstream_dsn) = overrides.upstream_dsn {
             relay.upstream = upstream_dsn
                 .parse::<Dsn>()
-                .map(|dsn| UpstreamDescriptor::from_dsn(&dsn).into_owned())
+                .map(|dsn| UpstreamDescriptor::from_dsn(&dsn).into_owned().into())
                 .with_context(|| ConfigError::field("upstream_dsn"))?;
         }
 
@@ -1393,7 +3348,8 @@ impl Config {
             outcomes.source = overrides.outcome_source.take();
         }
 
-        if let Some(credentials) = &mut self.credentials {
+        let mut current = self.credentials();
+        if let Some(credentials) = current.as_mut() {
             //we have existing credentials we may override some entries
             if let Some(id) = id {
                 credentials.id = id;
@@ -1408,7 +3364,7 @@ impl Config {
             //no existing credentials we may only create the full credentials
             match (id, public_key, secret_key) {
                 (Some(id), Some(public_key), Some(secret_key)) => {
-                    self.credentials = Some(Credentials {
+                    current = Some(Credentials {
                         secret_key,
                         public_key,
                         id,
@@ -1423,30 +3379,180 @@ impl Config {
                 }
             }
         }
+        self.credentials.replace(Arc::new(current));
 
         let limits = &mut self.values.limits;
         if let Some(shutdown_timeout) = overrides.shutdown_timeout {
             if let Ok(shutdown_timeout) = shutdown_timeout.parse::<u64>() {
                 limits.shutdown_timeout = shutdown_timeout;
             }
-        }
+        }
+
+        let aws = &mut self.values.aws;
+        if let Some(aws_runtime_api) = overrides.aws_runtime_api {
+            aws.runtime_api = Some(aws_runtime_api);
+        }
+
+        let inspect = &mut self.values.inspect;
+        if let Some(enabled) = overrides.inspect {
+            match enabled.to_lowercase().as_str() {
+                "true" | "1" => inspect.enabled = true,
+                "false" | "0" | "" => inspect.enabled = false,
+                _ => return Err(ConfigError::field("inspect").into()),
+            }
+        }
+        if let Some(diagnostic) = self.first_invalid_value() {
+            return Err(ConfigError::field(diagnostic.path)).context(diagnostic.message);
+        }
+
+        Ok(self)
+    }
+
+    /// Checks if the config is already initialized.
+    pub fn config_exists<P: AsRef<Path>>(path: P) -> bool {
+        fs::metadata(ConfigValues::path(path.as_ref())).is_ok()
+    }
+
+    /// Returns the filename of the config file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the full path of the config file within the config folder.
+    ///
+    /// This is `config.yml`, or `config.toml` if only the TOML file exists.
+    pub fn config_file_path(&self) -> PathBuf {
+        ConfigValues::path(&self.path)
+    }
+
+    /// Reloads the configuration from the config folder.
+    ///
+    /// Only the fields that can be changed at runtime are applied: the log level, the size limits,
+    /// the retry policy for upstream requests, and the static quotas of the local rate limiter. The
+    /// returned report lists all fields that changed in the config file since it was last loaded,
+    /// split by whether they have been applied or require a restart.
+    ///
+    /// Body limits of the HTTP server are set at startup, so raising a size limit above its
+    /// initial value only takes full effect after a restart.
+    pub fn reload(&self) -> anyhow::Result<ReloadReport> {
+        let values = ConfigValues::load(&self.path)?;
+        let file_values = serde_json::to_value(&values)
+            .with_context(|| ConfigError::file(ConfigErrorKind::BadJson, &self.path))?;
+
+        let mut previous = self.file_values.lock().unwrap_or_else(|e| e.into_inner());
+        let report = ReloadReport::diff(&previous, &file_values);
+        *previous = file_values;
+
+        self.reload_from(&values);
+
+        Ok(report)
+    }
+
+    /// Applies the reloadable fields of newly loaded values to this config and its nested configs.
+    fn reload_from(&self, values: &ConfigValues) {
+        let mut logging = relay_log::LogConfig::clone(&self.values.logging.get());
+        let file_logging = values.logging.get();
+        logging.level = file_logging.level;
+        logging.filters = file_logging.filters.clone();
+        logging.format = file_logging.format;
+        self.values.logging.replace(Arc::new(logging));
+
+        self.values.limits.reload_from(&values.limits);
+        self.values.http.reload_from(&values.http);
+        self.values
+            .rate_limiting
+            .quotas
+            .reload_from(&values.rate_limiting.quotas);
+
+        let mut nested = self.nested.lock().unwrap_or_else(|e| e.into_inner());
+        nested.retain(|config| match config.upgrade() {
+            Some(config) => {
+                config.reload_from(values);
+                true
+            }
+            None => false,
+        });
+    }
 
-        let aws = &mut self.values.aws;
-        if let Some(aws_runtime_api) = overrides.aws_runtime_api {
-            aws.runtime_api = Some(aws_runtime_api);
+    /// Returns the static bearer token of the admin API.
+    pub fn admin_token(&self) -> Option<&str> {
+        self.values.admin.token.as_deref().filter(|t| !t.is_empty())
+    }
+
+    /// Returns the keys to verify JSON Web Tokens for the admin API.
+    pub fn admin_jwt_keys(&self) -> Vec<JwtKey> {
+        let jwt = &self.values.admin.jwt;
+        let secrets = jwt
+            .secrets
+            .iter()
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| JwtKey::Hmac(secret.as_bytes().to_vec()));
+        let public_keys = jwt.public_keys.iter().cloned().map(JwtKey::Ed25519);
+        secrets.chain(public_keys).collect()
+    }
+
+    /// Returns the audience and leeway to validate JSON Web Tokens for the admin API.
+    pub fn admin_jwt_validation(&self) -> JwtValidation<'_> {
+        JwtValidation {
+            audience: &self.values.admin.jwt.audience,
+            leeway: self.values.admin.jwt.leeway,
         }
+    }
 
-        Ok(self)
+    /// Returns `true` if the admin API is enabled.
+    ///
+    /// The admin API is enabled if a static token or keys for JSON Web Tokens are configured.
+    pub fn admin_enabled(&self) -> bool {
+        self.admin_token().is_some() || !self.admin_jwt_keys().is_empty()
     }
 
-    /// Checks if the config is already initialized.
-    pub fn config_exists<P: AsRef<Path>>(path: P) -> bool {
-        fs::metadata(ConfigValues::path(path.as_ref())).is_ok()
+    /// Returns the path of the audit log file, if configured.
+    pub fn audit_log_path(&self) -> Option<&Path> {
+        self.values.audit.path.as_deref()
     }
 
-    /// Returns the filename of the config file.
-    pub fn path(&self) -> &Path {
-        &self.path
+    /// Returns the URL to which audit entries are sent, if configured.
+    pub fn audit_webhook_url(&self) -> Option<&str> {
+        self.values.audit.webhook_url.as_deref()
+    }
+
+    /// Returns `true` if changes are recorded in an audit log.
+    pub fn audit_enabled(&self) -> bool {
+        self.audit_log_path().is_some() || self.audit_webhook_url().is_some()
+    }
+
+    /// Returns the fraction of system memory above which Relay reports high memory usage.
+    pub fn health_max_memory_percent(&self) -> f32 {
+        self.values.health.max_memory_percent
+    }
+
+    /// Returns `true` if the admin API may record CPU and heap profiles.
+    pub fn admin_profiling_enabled(&self) -> bool {
+        self.values.admin.profiling
+    }
+
+    /// Returns the settings that have been changed at runtime through the admin API.
+    pub fn runtime_overrides(&self) -> Arc<RuntimeOverrides> {
+        self.runtime_overrides.get()
+    }
+
+    /// Replaces the runtime overrides.
+    ///
+    /// This also writes the overrides to `runtime_overrides.json` in the config folder, unless the
+    /// config was not loaded from a folder. Callers are responsible for applying the logging settings
+    /// and the metrics sample rate.
+    pub fn set_runtime_overrides(&self, overrides: RuntimeOverrides) -> anyhow::Result<()> {
+        if !self.path.as_os_str().is_empty() {
+            overrides.save(&self.path)?;
+        }
+
+        self.runtime_overrides.replace(Arc::new(overrides));
+        Ok(())
+    }
+
+    /// Returns `true` if items of the given type have been disabled through runtime overrides.
+    pub fn item_type_disabled(&self, ty: &str) -> bool {
+        self.runtime_overrides().disabled_item_types.contains(ty)
     }
 
     /// Dumps out a YAML string of the values.
@@ -1463,13 +3569,15 @@ impl Config {
         if save {
             creds.save(&self.path)?;
         }
-        self.credentials = Some(creds);
+        self.credentials.replace(Arc::new(Some(creds)));
         Ok(())
     }
 
     /// Return the current credentials
-    pub fn credentials(&self) -> Option<&Credentials> {
-        self.credentials.as_ref()
+    ///
+    /// Credentials can change at runtime if they are fetched from a secrets provider.
+    pub fn credentials(&self) -> Option<Credentials> {
+        Option::clone(&self.credentials.get())
     }
 
     /// Set new credentials.
@@ -1479,7 +3587,7 @@ impl Config {
         &mut self,
         credentials: Option<Credentials>,
     ) -> anyhow::Result<bool> {
-        if self.credentials == credentials {
+        if *self.credentials.get() == credentials {
             return Ok(false);
         }
 
@@ -1497,28 +3605,28 @@ impl Config {
             }
         }
 
-        self.credentials = credentials;
+        self.credentials.replace(Arc::new(credentials));
         Ok(true)
     }
 
     /// Returns `true` if the config is ready to use.
     pub fn has_credentials(&self) -> bool {
-        self.credentials.is_some()
+        self.credentials.get().is_some()
     }
 
     /// Returns the secret key if set.
-    pub fn secret_key(&self) -> Option<&SecretKey> {
-        self.credentials.as_ref().map(|x| &x.secret_key)
+    pub fn secret_key(&self) -> Option<SecretKey> {
+        self.credentials().map(|x| x.secret_key)
     }
 
     /// Returns the public key if set.
-    pub fn public_key(&self) -> Option<&PublicKey> {
-        self.credentials.as_ref().map(|x| &x.public_key)
+    pub fn public_key(&self) -> Option<PublicKey> {
+        self.credentials().map(|x| x.public_key)
     }
 
     /// Returns the relay ID.
-    pub fn relay_id(&self) -> Option<&RelayId> {
-        self.credentials.as_ref().map(|x| &x.id)
+    pub fn relay_id(&self) -> Option<RelayId> {
+        self.credentials().map(|x| x.id)
     }
 
     /// Returns the relay mode.
@@ -1526,11 +3634,82 @@ impl Config {
         self.values.relay.mode
     }
 
-    /// Returns the upstream target as descriptor.
+    /// Returns the primary upstream target as descriptor.
     pub fn upstream_descriptor(&self) -> &UpstreamDescriptor<'_> {
+        self.values.relay.upstream.primary()
+    }
+
+    /// Returns all configured upstream targets in order of priority.
+    pub fn upstreams(&self) -> &Upstreams {
         &self.values.relay.upstream
     }
 
+    /// Returns the routes of projects to dedicated upstreams.
+    pub fn upstream_routes(&self) -> &[UpstreamRoute] {
+        &self.values.relay.routes
+    }
+
+    /// Returns the index of the route for the given project, if any.
+    ///
+    /// Returns `None` if the project is sent to the default upstream.
+    pub fn upstream_route(
+        &self,
+        public_key: Option<ProjectKey>,
+        project_id: Option<ProjectId>,
+    ) -> Option<usize> {
+        self.values
+            .relay
+            .routes
+            .iter()
+            .position(|route| route.matches(public_key, project_id))
+    }
+
+    /// Returns a copy of this configuration that sends all traffic to the upstream of a route.
+    ///
+    /// The returned configuration has no routes of its own. Credentials and all other settings
+    /// are retained, and [reloads](Self::reload) of this configuration apply to the copy.
+    pub fn for_upstream_route(&self, index: usize) -> anyhow::Result<Arc<Config>> {
+        let route = self
+            .upstream_routes()
+            .get(index)
+            .with_context(|| ConfigError::field("routes"))?;
+
+        self.with_upstream(route.upstream.clone())
+    }
+
+    /// Returns a copy of this configuration that sends all traffic to `outcomes.upstream`.
+    ///
+    /// Returns `Ok(None)` if no dedicated outcomes upstream is configured.
+    pub fn for_outcomes_upstream(&self) -> anyhow::Result<Option<Arc<Config>>> {
+        match self.values.outcomes.upstream {
+            Some(ref upstream) => self.with_upstream(upstream.clone()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns a copy of this configuration with a different upstream.
+    ///
+    /// Routes and the outcomes upstream are removed from the copy.
+    fn with_upstream(&self, upstream: Upstreams) -> anyhow::Result<Arc<Config>> {
+        let value = serde_json::to_value(&self.values)
+            .with_context(|| ConfigError::new(ConfigErrorKind::BadJson))?;
+        let mut config = Config::from_json_value(value)?;
+
+        config.values.relay.upstream = upstream;
+        config.values.relay.routes = Vec::new();
+        config.values.outcomes.upstream = None;
+        config.credentials = self.credentials.clone();
+        config.path = self.path.clone();
+
+        let config = Arc::new(config);
+        self.nested
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Arc::downgrade(&config));
+
+        Ok(config)
+    }
+
     /// Returns the custom HTTP "Host" header.
     pub fn http_host_header(&self) -> Option<&str> {
         self.values.http.host_header.as_deref()
@@ -1598,11 +3777,55 @@ impl Config {
         Duration::from_secs(self.values.http.outage_grace_period)
     }
 
+    /// Returns the interval at which higher-priority upstreams are probed after a failover.
+    pub fn http_failback_interval(&self) -> Duration {
+        Duration::from_secs(self.values.http.failback_interval)
+    }
+
+    /// Returns the strategy for distributing stateless requests across upstreams.
+    pub fn http_load_balancing(&self) -> LoadBalancing {
+        self.values.http.load_balancing
+    }
+
     /// Content encoding of upstream requests.
     pub fn http_encoding(&self) -> HttpEncoding {
         self.values.http.encoding
     }
 
+    /// Compression level of the content encoding of upstream requests.
+    ///
+    /// Returns `None` if the default level of the encoding should be used.
+    pub fn http_encoding_level(&self) -> Option<u32> {
+        self.values.http.encoding_level
+    }
+
+    /// Returns custom headers added to all responses of ingestion endpoints.
+    ///
+    /// Headers with the same name set by Relay, such as `Server`, are replaced. This can be used to
+    /// add security headers like `Strict-Transport-Security` or to identify the instance that
+    /// responded to a request.
+    pub fn response_headers(&self) -> &BTreeMap<String, String> {
+        &self.values.response_headers
+    }
+
+    /// Returns the URL of the proxy for outbound requests, if configured.
+    pub fn http_proxy_url(&self) -> Option<&str> {
+        self.values
+            .http
+            .proxy_url
+            .as_deref()
+            .filter(|url| !url.is_empty())
+    }
+
+    /// Returns the username and password for the outbound proxy, if configured.
+    ///
+    /// The password defaults to an empty string if only a username is set.
+    pub fn http_proxy_auth(&self) -> Option<(&str, &str)> {
+        let username = self.values.http.proxy_username.as_deref()?;
+        let password = self.values.http.proxy_password.as_deref().unwrap_or("");
+        Some((username, password))
+    }
+
     /// Returns whether this Relay should emit outcomes.
     ///
     /// This is `true` either if `outcomes.emit_outcomes` is explicitly enabled, or if this Relay is
@@ -1647,9 +3870,106 @@ impl Config {
         &self.values.outcomes.aggregator
     }
 
+    /// Returns the configuration for emitting outcomes as client reports.
+    pub fn client_reports(&self) -> &ClientReportsConfig {
+        &self.values.outcomes.client_reports
+    }
+
+    /// Returns the interval over which outcomes are aggregated into client reports.
+    pub fn client_reports_flush_interval(&self) -> Duration {
+        let outcomes = &self.values.outcomes;
+        let interval = outcomes
+            .client_reports
+            .flush_interval
+            .unwrap_or(outcomes.aggregator.flush_interval);
+
+        Duration::from_secs(interval)
+    }
+
+    /// Returns `true` if client reports from downstream should be folded into the client reports
+    /// generated by this Relay.
+    ///
+    /// This requires that this Relay emits outcomes as client reports.
+    pub fn fold_client_reports(&self) -> bool {
+        self.emit_outcomes() == EmitOutcomes::AsClientReports
+            && self.values.outcomes.client_reports.fold_downstream
+    }
+
+    /// Returns the configuration of the outcome webhook, if one is configured.
+    ///
+    /// Webhooks without a URL are ignored.
+    pub fn outcome_webhook(&self) -> Option<&OutcomeWebhook> {
+        self.values
+            .outcomes
+            .webhook
+            .as_ref()
+            .filter(|webhook| !webhook.url.is_empty())
+    }
+
+    /// Returns the configuration for local adaptive sampling.
+    ///
+    /// Returns `None` if adaptive sampling is not configured or if this Relay runs in managed mode,
+    /// where sampling rules are provided by the upstream.
+    pub fn target_sampling(&self) -> Option<&TargetSampling> {
+        let target = &self.values.sampling.target;
+        match (self.relay_mode(), target.events_per_minute) {
+            (RelayMode::Managed, _) | (_, None) => None,
+            (_, Some(_)) => Some(target),
+        }
+    }
+
+    /// Returns the configuration for envelope inspection, if it is enabled.
+    pub fn inspect(&self) -> Option<&Inspect> {
+        let inspect = &self.values.inspect;
+        inspect.enabled.then_some(inspect)
+    }
+
+    /// Returns the directory into which incoming requests are recorded, if configured.
+    pub fn capture_path(&self) -> Option<&Path> {
+        self.values.capture.path.as_deref()
+    }
+
+    /// Returns the window after startup during which incoming requests are recorded.
+    pub fn capture_duration(&self) -> Duration {
+        Duration::from_secs(self.values.capture.duration)
+    }
+
+    /// Returns the maximum number of recorded requests.
+    pub fn capture_max_requests(&self) -> usize {
+        self.values.capture.max_requests
+    }
+
     /// Returns logging configuration.
-    pub fn logging(&self) -> &relay_log::LogConfig {
-        &self.values.logging
+    pub fn logging(&self) -> Arc<relay_log::LogConfig> {
+        self.values.logging.get()
+    }
+
+    /// Returns the log level, taking [runtime overrides](Self::runtime_overrides) into account.
+    pub fn log_level(&self) -> relay_log::Level {
+        self.runtime_overrides()
+            .log_level
+            .unwrap_or_else(|| self.logging().level)
+    }
+
+    /// Returns the logging configuration with [runtime overrides](Self::runtime_overrides)
+    /// applied.
+    ///
+    /// Pass this to [`relay_log::reconfigure`] after changing the overrides or reloading the config.
+    pub fn effective_logging(&self) -> relay_log::LogConfig {
+        let overrides = self.runtime_overrides();
+        let mut logging = relay_log::LogConfig::clone(&self.logging());
+
+        if let Some(level) = overrides.log_level {
+            logging.level = level;
+        }
+        if let Some(ref filters) = overrides.log_filters {
+            logging.filters = filters.clone();
+        }
+        if let Some(format) = overrides.log_format {
+            logging.format = format;
+        }
+
+        logging
     }
 
     /// Returns logging configuration.
@@ -1694,8 +4014,17 @@ impl Config {
     }
 
     /// Returns the global sample rate for all metrics.
+    ///
+    /// This takes [runtime overrides](Self::runtime_overrides) into account.
     pub fn metrics_sample_rate(&self) -> f32 {
-        self.values.metrics.sample_rate
+        self.runtime_overrides()
+            .metrics_sample_rate
+            .unwrap_or(self.values.metrics.sample_rate)
+    }
+
+    /// Returns the rules to rewrite emitted metrics.
+    pub fn metrics_relabel_rules(&self) -> &[RelabelRule] {
+        &self.values.metrics.relabel
     }
 
     /// Returns the default timeout for all upstream HTTP requests.
@@ -1708,9 +4037,110 @@ impl Config {
         Duration::from_secs(self.values.http.connection_timeout.into())
     }
 
+    /// Returns the path to the PEM file with additional CA certificates for upstream connections.
+    pub fn http_ca_file(&self) -> Option<&Path> {
+        self.values.http.ca_file.as_deref()
+    }
+
+    /// Returns the SPKI pins for upstream connections.
+    pub fn http_spki_pins(&self) -> &[SpkiPin] {
+        &self.values.http.spki_pins
+    }
+
+    /// Returns the path to the PEM file with the client certificate for upstream connections.
+    pub fn http_client_certificate(&self) -> Option<&Path> {
+        self.values.http.client_certificate.as_deref()
+    }
+
+    /// Returns how Relay authenticates with the upstream in managed mode.
+    pub fn http_authentication(&self) -> UpstreamAuthentication {
+        self.values.http.authentication
+    }
+
+    /// Returns `true` if Relay has to register with the upstream before sending requests.
+    ///
+    /// This is the case for managed Relays, unless they authenticate with a client certificate.
+    pub fn requires_registration(&self) -> bool {
+        self.relay_mode() == RelayMode::Managed
+            && self.http_authentication() == UpstreamAuthentication::Challenge
+    }
+
+    /// Returns the maximum number of idle connections per upstream host, if limited.
+    pub fn http_pool_max_idle_per_host(&self) -> Option<usize> {
+        self.values.http.pool_max_idle_per_host
+    }
+
+    /// Returns the timeout after which idle upstream connections are closed.
+    pub fn http_pool_idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.values.http.pool_idle_timeout)
+    }
+
+    /// Returns the interval of TCP keepalive probes on upstream connections, if enabled.
+    pub fn http_tcp_keepalive(&self) -> Option<Duration> {
+        self.values.http.tcp_keepalive.map(Duration::from_secs)
+    }
+
+    /// Returns the maximum rate of upstream request bodies in bytes per second, if limited.
+    pub fn http_max_egress_rate(&self) -> Option<usize> {
+        self.values
+            .http
+            .max_egress_rate
+            .as_ref()
+            .map(ByteSize::as_bytes)
+            .filter(|rate| *rate > 0)
+    }
+
+    /// Returns the number of bytes that may be sent to the upstream in a burst.
+    ///
+    /// Defaults to the egress rate, and is zero if the egress rate is not limited.
+    pub fn http_egress_burst(&self) -> usize {
+        let rate = self.http_max_egress_rate().unwrap_or_default();
+        match self.values.http.egress_burst {
+            Some(ref burst) => burst.as_bytes(),
+            None => rate,
+        }
+    }
+
     /// Returns the failed upstream request retry interval.
     pub fn http_max_retry_interval(&self) -> Duration {
-        Duration::from_secs(self.values.http.max_retry_interval.into())
+        Duration::from_secs((*self.values.http.max_retry_interval.get()).into())
+    }
+
+    /// Returns the initial interval of the retry backoff for upstream requests.
+    pub fn http_retry_backoff_base(&self) -> Duration {
+        Duration::from_millis(*self.values.http.retry_backoff_base.get())
+    }
+
+    /// Returns the randomization factor of the retry backoff, clamped to `[0, 1]`.
+    pub fn http_retry_jitter(&self) -> f64 {
+        let jitter = *self.values.http.retry_jitter.get();
+        if jitter.is_finite() {
+            jitter.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the maximum number of retries of upstream requests, if limited.
+    pub fn http_max_retries(&self) -> Option<usize> {
+        *self.values.http.max_retries.get()
+    }
+
+    /// Returns the status codes of upstream responses that are retried.
+    pub fn http_retry_status_codes(&self) -> Arc<Vec<u16>> {
+        self.values.http.retry_status_codes.get()
+    }
+
+    /// Returns the number of consecutive 5xx responses that open the circuit breaker.
+    ///
+    /// A value of `0` disables the circuit breaker.
+    pub fn http_circuit_breaker_threshold(&self) -> u32 {
+        self.values.http.circuit_breaker_threshold
+    }
+
+    /// Returns the time that the circuit breaker stays open.
+    pub fn http_circuit_breaker_timeout(&self) -> Duration {
+        Duration::from_secs(self.values.http.circuit_breaker_timeout)
     }
 
     /// Returns the expiry timeout for cached projects.
@@ -1732,6 +4162,15 @@ impl Config {
             .unwrap_or(usize::MAX)
     }
 
+    /// Returns the maximum total size of buffered envelopes in bytes, if configured.
+    pub fn envelope_buffer_memory(&self) -> Option<usize> {
+        self.values
+            .cache
+            .envelope_buffer_memory
+            .as_ref()
+            .map(ByteSize::as_bytes)
+    }
+
     /// Returns the expiry timeout for cached misses before trying to refetch.
     pub fn cache_miss_expiry(&self) -> Duration {
         Duration::from_secs(self.values.cache.miss_expiry.into())
@@ -1765,6 +4204,34 @@ impl Config {
         Duration::from_secs(self.values.cache.global_config_fetch_interval.into())
     }
 
+    /// Returns the maximum interval in which global configs are fetched from upstream.
+    ///
+    /// This is never shorter than [`global_config_fetch_interval`](Self::global_config_fetch_interval).
+    pub fn global_config_max_fetch_interval(&self) -> Duration {
+        let max = Duration::from_secs(self.values.cache.global_config_max_fetch_interval.into());
+        max.max(self.global_config_fetch_interval())
+    }
+
+    /// Returns the randomization factor of the global config fetch interval.
+    pub fn global_config_fetch_jitter(&self) -> f64 {
+        let jitter = self.values.cache.global_config_fetch_jitter;
+        if jitter.is_finite() {
+            jitter.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the path of the project cache snapshot, if configured.
+    pub fn project_cache_snapshot_path(&self) -> Option<&Path> {
+        self.values.cache.snapshot_path.as_deref()
+    }
+
+    /// Returns the maximum age of project configs restored from the snapshot.
+    pub fn project_cache_snapshot_max_age(&self) -> Duration {
+        Duration::from_secs(self.values.cache.snapshot_max_age.into())
+    }
+
     /// Returns the path of the buffer file if the `cache.persistent_envelope_buffer.path` is configured.
     pub fn spool_envelopes_path(&self) -> Option<PathBuf> {
         self.values
@@ -1795,77 +4262,175 @@ impl Config {
         self.values.spool.envelopes.max_memory_size.as_bytes()
     }
 
+    /// Returns the interval for spool health metrics and compaction.
+    pub fn spool_envelopes_compaction_interval(&self) -> Duration {
+        Duration::from_secs(self.values.spool.envelopes.compaction_interval)
+    }
+
+    /// Returns the fraction of free pages at which the spool file is compacted.
+    pub fn spool_envelopes_compaction_threshold(&self) -> f64 {
+        self.values.spool.envelopes.compaction_threshold
+    }
+
+    /// Returns the path of the store-and-forward spool file, if enabled.
+    ///
+    /// Store-and-forward mode is not available in processing mode.
+    pub fn spool_forward_path(&self) -> Option<PathBuf> {
+        if self.processing_enabled() {
+            return None;
+        }
+
+        self.values.spool.forward.path.clone()
+    }
+
+    /// The maximum size of the store-and-forward spool, in bytes, if limited.
+    pub fn spool_forward_max_disk_size(&self) -> Option<usize> {
+        self.values
+            .spool
+            .forward
+            .max_disk_size
+            .as_ref()
+            .map(ByteSize::as_bytes)
+    }
+
+    /// The maximum number of spooled envelopes to send to the upstream per second.
+    pub fn spool_forward_drain_rate(&self) -> u32 {
+        self.values.spool.forward.drain_rate.max(1)
+    }
+
+    /// Returns the path of the spool file for messages that could not be produced to Kafka.
+    ///
+    /// The Kafka spool is only available in processing mode with the Kafka store sink.
+    pub fn spool_kafka_path(&self) -> Option<PathBuf> {
+        if !self.processing_enabled() || !matches!(self.store_sink(), StoreSinkConfig::Kafka) {
+            return None;
+        }
+
+        self.values.spool.kafka.path.clone()
+    }
+
+    /// The maximum size of the Kafka spool, in bytes.
+    pub fn spool_kafka_max_disk_size(&self) -> usize {
+        self.values.spool.kafka.max_disk_size.as_bytes()
+    }
+
+    /// The number of times a spooled Kafka message is produced again before it is dropped.
+    pub fn spool_kafka_max_retries(&self) -> u32 {
+        self.values.spool.kafka.max_retries
+    }
+
+    /// Returns the interval between attempts to produce spooled Kafka messages.
+    pub fn spool_kafka_retry_interval(&self) -> Duration {
+        Duration::from_secs(self.values.spool.kafka.retry_interval.max(1))
+    }
+
+    /// The maximum number of spooled Kafka messages read from or written to disk at once.
+    pub fn spool_kafka_batch_size(&self) -> u32 {
+        self.values.spool.kafka.batch_size.max(1)
+    }
+
+    /// The maximum number of failed Kafka messages waiting to be written to the spool.
+    pub fn spool_kafka_max_queue_size(&self) -> usize {
+        self.values.spool.kafka.max_queue_size.max(1)
+    }
+
     /// Returns the maximum size of an event payload in bytes.
     pub fn max_event_size(&self) -> usize {
-        self.values.limits.max_event_size.as_bytes()
+        self.values.limits.max_event_size.get().as_bytes()
     }
 
     /// Returns the maximum size of each attachment.
     pub fn max_attachment_size(&self) -> usize {
-        self.values.limits.max_attachment_size.as_bytes()
+        self.values.limits.max_attachment_size.get().as_bytes()
+    }
+
+    /// Returns the maximum size of each trace attachment.
+    pub fn max_trace_attachment_size(&self) -> usize {
+        self.values
+            .limits
+            .max_trace_attachment_size
+            .get()
+            .as_bytes()
     }
 
     /// Returns the maximum combined size of attachments or payloads containing attachments
     /// (minidump, unreal, standalone attachments) in bytes.
     pub fn max_attachments_size(&self) -> usize {
-        self.values.limits.max_attachments_size.as_bytes()
+        self.values.limits.max_attachments_size.get().as_bytes()
     }
 
     /// Returns the maximum combined size of client reports in bytes.
     pub fn max_client_reports_size(&self) -> usize {
-        self.values.limits.max_client_reports_size.as_bytes()
+        self.values.limits.max_client_reports_size.get().as_bytes()
     }
 
     /// Returns the maximum payload size of a monitor check-in in bytes.
     pub fn max_check_in_size(&self) -> usize {
-        self.values.limits.max_check_in_size.as_bytes()
+        self.values.limits.max_check_in_size.get().as_bytes()
     }
 
     /// Returns the maximum payload size of a span in bytes.
     pub fn max_span_size(&self) -> usize {
-        self.values.limits.max_span_size.as_bytes()
+        self.values.limits.max_span_size.get().as_bytes()
+    }
+
+    /// Returns the maximum payload size of a log in bytes.
+    pub fn max_log_size(&self) -> usize {
+        self.values.limits.max_log_size.get().as_bytes()
     }
 
     /// Returns the maximum size of an envelope payload in bytes.
     ///
     /// Individual item size limits still apply.
     pub fn max_envelope_size(&self) -> usize {
-        self.values.limits.max_envelope_size.as_bytes()
+        self.values.limits.max_envelope_size.get().as_bytes()
     }
 
     /// Returns the maximum number of sessions per envelope.
     pub fn max_session_count(&self) -> usize {
-        self.values.limits.max_session_count
+        *self.values.limits.max_session_count.get()
     }
 
     /// Returns the maximum payload size for general API requests.
     pub fn max_api_payload_size(&self) -> usize {
-        self.values.limits.max_api_payload_size.as_bytes()
+        self.values.limits.max_api_payload_size.get().as_bytes()
     }
 
     /// Returns the maximum payload size for file uploads and chunks.
     pub fn max_api_file_upload_size(&self) -> usize {
-        self.values.limits.max_api_file_upload_size.as_bytes()
+        self.values.limits.max_api_file_upload_size.get().as_bytes()
     }
 
     /// Returns the maximum payload size for chunks
     pub fn max_api_chunk_upload_size(&self) -> usize {
-        self.values.limits.max_api_chunk_upload_size.as_bytes()
+        self.values
+            .limits
+            .max_api_chunk_upload_size
+            .get()
+            .as_bytes()
     }
 
     /// Returns the maximum payload size for a profile
     pub fn max_profile_size(&self) -> usize {
-        self.values.limits.max_profile_size.as_bytes()
+        self.values.limits.max_profile_size.get().as_bytes()
     }
 
     /// Returns the maximum payload size for a compressed replay.
     pub fn max_replay_compressed_size(&self) -> usize {
-        self.values.limits.max_replay_compressed_size.as_bytes()
+        self.values
+            .limits
+            .max_replay_compressed_size
+            .get()
+            .as_bytes()
     }
 
     /// Returns the maximum payload size for an uncompressed replay.
     pub fn max_replay_uncompressed_size(&self) -> usize {
-        self.values.limits.max_replay_uncompressed_size.as_bytes()
+        self.values
+            .limits
+            .max_replay_uncompressed_size
+            .get()
+            .as_bytes()
     }
 
     /// Returns the maximum message size for an uncompressed replay.
@@ -1874,7 +4439,12 @@ impl Config {
     /// it can include additional metadata about the replay in
     /// addition to the recording.
     pub fn max_replay_message_size(&self) -> usize {
-        self.values.limits.max_replay_message_size.as_bytes()
+        self.values.limits.max_replay_message_size.get().as_bytes()
+    }
+
+    /// Returns the maximum payload size for a mobile replay video.
+    pub fn max_replay_video_size(&self) -> usize {
+        self.values.limits.max_replay_video_size.get().as_bytes()
     }
 
     /// Returns the maximum number of active requests
@@ -1910,6 +4480,11 @@ impl Config {
         self.values.limits.max_thread_count
     }
 
+    /// Returns the scheduling options of the envelope processor lanes.
+    pub fn processor_lanes(&self) -> &ProcessorLanes {
+        &self.values.limits.processor_lanes
+    }
+
     /// Returns the maximum size of a project config query.
     pub fn query_batch_size(&self) -> usize {
         self.values.cache.batch_size
@@ -1920,6 +4495,17 @@ impl Config {
         self.path.join("projects")
     }
 
+    /// Returns the directory of local PII config additions, if configured.
+    pub fn pii_overrides_path(&self) -> Option<PathBuf> {
+        let path = self.values.pii_overrides.path.as_ref()?;
+        Some(self.path.join(path))
+    }
+
+    /// Returns the length of the network prefix to which client IPv6 addresses are truncated.
+    pub fn ipv6_prefix(&self) -> Option<u8> {
+        self.values.ip_normalization.ipv6_prefix
+    }
+
     /// True if the Relay should do processing.
     pub fn processing_enabled(&self) -> bool {
         self.values.processing.enabled
@@ -1959,14 +4545,102 @@ impl Config {
         )
     }
 
+    /// Rules that send matching messages to different Kafka topics.
+    pub fn topic_routes(&self) -> &[TopicRoute] {
+        &self.values.processing.topic_routes
+    }
+
+    /// Returns `true` if failed messages are produced to the Kafka dead-letter topic.
+    pub fn kafka_dead_letters_enabled(&self) -> bool {
+        self.values.processing.dead_letters.enabled
+    }
+
+    /// The maximum size of the original payload included in a dead letter, in bytes.
+    pub fn kafka_dead_letter_max_payload_size(&self) -> usize {
+        self.values
+            .processing
+            .dead_letters
+            .max_payload_size
+            .as_bytes()
+    }
+
+    /// Returns the partition strategy of a Kafka topic.
+    pub fn kafka_partition_strategy(&self, topic: KafkaTopic) -> PartitionStrategy {
+        self.values
+            .processing
+            .partitioning
+            .get(&topic)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Configuration name and list of Kafka configuration parameters for the target of a route.
+    pub fn kafka_route_config(&self, route: &TopicRoute) -> Result<KafkaConfig, KafkaConfigError> {
+        route.target.kafka_config(
+            &self.values.processing.kafka_config,
+            &self.values.processing.secondary_kafka_configs,
+        )
+    }
+
+    /// Source of tokens for Kafka producers that authenticate with `OAUTHBEARER`.
+    pub fn kafka_oauth(&self) -> Option<&KafkaOAuthConfig> {
+        self.values.processing.kafka_oauth.as_ref()
+    }
+
+    /// Returns the destination of processed envelopes and metrics.
+    pub fn store_sink(&self) -> &StoreSinkConfig {
+        &self.values.processing.sink
+    }
+
+    /// Routing headers attached to all messages produced to Kafka.
+    pub fn kafka_headers(&self) -> &BTreeSet<KafkaHeader> {
+        &self.values.processing.kafka_headers
+    }
+
+    /// Delivery guarantees of Kafka producers.
+    pub fn kafka_delivery_mode(&self) -> DeliveryMode {
+        self.values.processing.kafka_delivery
+    }
+
+    /// Prefix of the transactional IDs of Kafka producers.
+    pub fn kafka_transactional_id(&self) -> Option<&str> {
+        self.values.processing.kafka_transactional_id.as_deref()
+    }
+
+    /// Schema registry for Kafka topics with registered schemas.
+    pub fn kafka_schema_registry(&self) -> Option<&SchemaRegistryConfig> {
+        self.values.processing.schema_registry.as_ref()
+    }
+
     /// Redis servers to connect to, for rate limiting.
     pub fn redis(&self) -> Option<&RedisConfig> {
         self.values.processing.redis.as_ref()
     }
 
-    /// Chunk size of attachments in bytes.
-    pub fn attachment_chunk_size(&self) -> usize {
-        self.values.processing.attachment_chunk_size.as_bytes()
+    /// Chunk size of attachments in bytes.
+    pub fn attachment_chunk_size(&self) -> usize {
+        self.values.processing.attachment_chunk_size.as_bytes()
+    }
+
+    /// Returns the time to cache Redis reads in memory, or `None` if the cache is disabled.
+    pub fn redis_cache_ttl(&self) -> Option<Duration> {
+        let cache = &self.values.processing.redis_cache;
+        (cache.enabled && cache.ttl > 0).then(|| Duration::from_secs(cache.ttl))
+    }
+
+    /// Returns the prefix of all Redis keys.
+    pub fn redis_key_prefix(&self) -> &str {
+        &self.values.processing.redis_key_prefix
+    }
+
+    /// Returns the memcached servers to track quotas in, if configured.
+    pub fn memcached(&self) -> Option<&MemcachedConfig> {
+        self.values.processing.memcached.as_ref()
+    }
+
+    /// Returns the maximum number of entries in each in-process Redis cache.
+    pub fn redis_cache_max_entries(&self) -> u64 {
+        self.values.processing.redis_cache.max_entries
     }
 
     /// Default prefix to use when looking up project configs in Redis. This is only done when
@@ -1980,6 +4654,50 @@ impl Config {
         self.values.processing.max_rate_limit.map(u32::into)
     }
 
+    /// Returns the metric namespaces whose buckets are merged across projects before producing.
+    pub fn org_metrics_namespaces(&self) -> &BTreeSet<MetricNamespace> {
+        &self.values.processing.org_metrics_aggregation.namespaces
+    }
+
+    /// Returns the interval at which buckets merged across projects are produced.
+    pub fn org_metrics_flush_interval(&self) -> Duration {
+        Duration::from_secs(
+            self.values
+                .processing
+                .org_metrics_aggregation
+                .flush_interval
+                .max(1),
+        )
+    }
+
+    /// Returns the default check-in margin in minutes for monitor configs that do not set one.
+    pub fn monitor_checkin_margin(&self) -> Option<u64> {
+        self.values.processing.monitors.checkin_margin
+    }
+
+    /// Returns the default maximum runtime in minutes for monitor configs that do not set one.
+    pub fn monitor_max_runtime(&self) -> Option<u64> {
+        self.values.processing.monitors.max_runtime
+    }
+
+    /// Returns `true` if legacy user reports should be converted into feedback items.
+    pub fn convert_user_reports(&self) -> bool {
+        self.values.processing.convert_user_reports
+    }
+
+    /// Returns the static quotas of the in-memory rate limiter, if it is enabled.
+    ///
+    /// Returns `None` if the local rate limiter is disabled or if processing is enabled, since
+    /// processing Relays enforce quotas in Redis.
+    pub fn local_rate_limiting(&self) -> Option<Arc<Vec<Quota>>> {
+        let rate_limiting = &self.values.rate_limiting;
+        if !rate_limiting.local || self.processing_enabled() {
+            return None;
+        }
+
+        Some(rate_limiting.quotas.get())
+    }
+
     /// Returns configuration for the metrics [aggregator](relay_metrics::Aggregator).
     pub fn aggregator_config(&self) -> &AggregatorConfig {
         &self.values.aggregator
@@ -2001,11 +4719,45 @@ impl Config {
         &self.values.aggregator
     }
 
+    /// Returns local overrides for the transaction metrics extraction config of all projects.
+    pub fn transaction_metrics_overrides(&self) -> &TransactionMetricsOverrides {
+        &self.values.transaction_metrics
+    }
+
     /// Return the statically configured Relays.
     pub fn static_relays(&self) -> &HashMap<RelayId, RelayInfo> {
         &self.values.auth.static_relays
     }
 
+    /// Returns the header that contains client certificates of downstream Relays.
+    ///
+    /// Returns `None` if authentication with client certificates is disabled.
+    pub fn client_certificate_header(&self) -> Option<&str> {
+        self.values.auth.client_certificates.header.as_deref()
+    }
+
+    /// Returns the relay IDs of downstream Relays by the SPKI pins of their client certificates.
+    pub fn client_certificate_relays(&self) -> &BTreeMap<SpkiPin, RelayId> {
+        &self.values.auth.client_certificates.relays
+    }
+
+    /// Returns the networks of proxies that may forward client certificates.
+    pub fn client_certificate_proxies(&self) -> &[IpNetwork] {
+        &self.values.auth.client_certificates.trusted_proxies
+    }
+
+    /// Returns `true` if the peer may forward client certificates of downstream Relays.
+    pub fn is_client_certificate_proxy(&self, peer: IpAddr) -> bool {
+        self.client_certificate_proxies()
+            .iter()
+            .any(|network| network.contains(peer))
+    }
+
+    /// Returns the algorithm to sign requests to the upstream with.
+    pub fn signature_algorithm(&self) -> SignatureAlgorithm {
+        self.values.auth.signature_algorithm
+    }
+
     /// Returns `true` if unknown items should be accepted and forwarded.
     pub fn accept_unknown_items(&self) -> bool {
         let forward = self.values.routing.accept_unknown_items;
@@ -2016,22 +4768,87 @@ impl Config {
     pub fn aws_runtime_api(&self) -> Option<&str> {
         self.values.aws.runtime_api.as_deref()
     }
+
+    /// Returns the external secrets store, if configured.
+    pub fn secrets_provider(&self) -> Option<&SecretsProvider> {
+        self.values.secrets.provider.as_ref()
+    }
+
+    /// Returns the interval at which secrets are fetched again, if enabled.
+    pub fn secrets_refresh_interval(&self) -> Option<Duration> {
+        match self.values.secrets.refresh_interval {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// Applies secrets fetched from the secrets provider at startup.
+    ///
+    /// Credentials, Redis, and Kafka settings from the secret take precedence over the config file.
+    pub fn apply_provided_secrets(&mut self, secrets: &ProvidedSecrets) {
+        if let Some(credentials) = secrets.credentials() {
+            self.credentials.replace(Arc::new(Some(credentials)));
+        }
+
+        let processing = &mut self.values.processing;
+        if let Some(ref redis) = secrets.redis {
+            processing.redis = Some(RedisConfig::Single(redis.clone()));
+        }
+
+        for (name, value) in &secrets.kafka_config {
+            let kafka_config = &mut processing.kafka_config;
+            match kafka_config.iter_mut().find(|param| param.name == *name) {
+                Some(param) => param.value = value.clone(),
+                None => kafka_config.push(KafkaConfigParam {
+                    name: name.clone(),
+                    value: value.clone(),
+                }),
+            }
+        }
+    }
+
+    /// Replaces the credentials with credentials fetched from the secrets provider at runtime.
+    ///
+    /// Unlike [`replace_credentials`](Self::replace_credentials), this does not write the
+    /// credentials to disk. Returns `true` if the credentials changed.
+    pub fn rotate_credentials(&self, credentials: Credentials) -> bool {
+        if self.credentials.get().as_ref() == Some(&credentials) {
+            return false;
+        }
+
+        self.credentials.replace(Arc::new(Some(credentials)));
+        true
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             values: ConfigValues::default(),
-            credentials: None,
+            credentials: Arc::default(),
+            runtime_overrides: Reloadable::default(),
             path: PathBuf::new(),
+            file_values: Mutex::default(),
+            nested: Mutex::default(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::testutils::TempDir;
+    use crate::validate::Severity;
+
     use super::*;
 
+    /// Creates a config from YAML values without loading files.
+    fn config_from_yaml(yaml: &str) -> Config {
+        Config {
+            values: serde_yaml::from_str(yaml).unwrap(),
+            ..Default::default()
+        }
+    }
+
     /// Regression test for renaming the envelope buffer flags.
     #[test]
     fn test_event_buffer_size() {
@@ -2059,6 +4876,644 @@ cache:
         }
     }
 
+    #[test]
+    fn test_target_sampling() {
+        let yaml = r###"
+relay:
+    mode: proxy
+sampling:
+    target:
+        events_per_minute: 1000
+"###;
+
+        let mut config = config_from_yaml(yaml);
+
+        let target = config.target_sampling().unwrap();
+        assert_eq!(target.events_per_minute, Some(1000));
+        assert_eq!(target.adjustment_interval, 60);
+
+        config.values.relay.mode = RelayMode::Managed;
+        assert!(config.target_sampling().is_none());
+    }
+
+    #[test]
+    fn test_local_rate_limiting() {
+        let yaml = r###"
+relay:
+    mode: static
+rate_limiting:
+    local: true
+    quotas:
+        - id: errors
+          categories: [error]
+          scope: key
+          limit: 100
+          window: 60
+          reasonCode: local_errors
+"###;
+
+        let mut config = config_from_yaml(yaml);
+
+        let quotas = config.local_rate_limiting().unwrap();
+        assert_eq!(quotas.len(), 1);
+        assert_eq!(quotas[0].limit, Some(100));
+
+        config.values.processing.enabled = true;
+        assert!(config.local_rate_limiting().is_none());
+
+        config.values.processing.enabled = false;
+        config.values.rate_limiting.local = false;
+        assert!(config.local_rate_limiting().is_none());
+    }
+
+    #[test]
+    fn test_client_reports() {
+        let yaml = r###"
+outcomes:
+    emit_outcomes: as_client_reports
+    aggregator:
+        flush_interval: 30
+    client_reports:
+        fold_downstream: true
+"###;
+
+        let mut config = config_from_yaml(yaml);
+
+        assert_eq!(
+            config.client_reports_flush_interval(),
+            Duration::from_secs(30)
+        );
+        assert!(config.client_reports().group_by_timestamp);
+        assert!(config.fold_client_reports());
+
+        config.values.outcomes.client_reports.flush_interval = Some(10);
+        assert_eq!(
+            config.client_reports_flush_interval(),
+            Duration::from_secs(10)
+        );
+
+        config.values.outcomes.emit_outcomes = EmitOutcomes::AsOutcomes;
+        assert!(!config.fold_client_reports());
+    }
+
+    #[test]
+    fn test_outcome_webhook() {
+        let yaml = r###"
+outcomes:
+    webhook:
+        url: https://billing.example.com/outcomes
+        max_retries: 3
+"###;
+
+        let mut config = config_from_yaml(yaml);
+
+        let webhook = config.outcome_webhook().unwrap();
+        assert_eq!(webhook.url, "https://billing.example.com/outcomes");
+        assert_eq!(webhook.max_retries, 3);
+        assert_eq!(webhook.batch_size, 1000);
+
+        config.values.outcomes.webhook.as_mut().unwrap().url = String::new();
+        assert!(config.outcome_webhook().is_none());
+    }
+
+    #[test]
+    fn test_http_proxy() {
+        let yaml = r###"
+http:
+    proxy_url: socks5h://proxy.example.com:1080
+    proxy_username: relay
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        assert_eq!(
+            config.http_proxy_url(),
+            Some("socks5h://proxy.example.com:1080")
+        );
+        assert_eq!(config.http_proxy_auth(), Some(("relay", "")));
+        assert!(Config::default().http_proxy_url().is_none());
+        assert!(Config::default().http_proxy_auth().is_none());
+    }
+
+    #[test]
+    fn test_valid_response_header() {
+        assert!(is_valid_response_header("X-Relay-Instance", "pop-eu-1"));
+        assert!(is_valid_response_header(
+            "Strict-Transport-Security",
+            "max-age=31536000; includeSubDomains"
+        ));
+        assert!(!is_valid_response_header("", "value"));
+        assert!(!is_valid_response_header("X Relay", "value"));
+        assert!(!is_valid_response_header("X-Relay", "a\r\nSet-Cookie: b"));
+    }
+
+    #[test]
+    fn test_valid_proxy_url() {
+        assert!(is_valid_proxy_url("http://proxy.example.com:3128"));
+        assert!(is_valid_proxy_url("socks5://127.0.0.1:1080"));
+        assert!(!is_valid_proxy_url("ftp://proxy.example.com"));
+        assert!(!is_valid_proxy_url("proxy.example.com:3128"));
+    }
+
+    #[test]
+    fn test_http_pool() {
+        let yaml = r###"
+http:
+    tcp_keepalive: 30
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        assert_eq!(config.http_pool_idle_timeout(), Duration::from_secs(90));
+        assert_eq!(config.http_tcp_keepalive(), Some(Duration::from_secs(30)));
+        assert_eq!(Config::default().http_tcp_keepalive(), None);
+    }
+
+    #[test]
+    fn test_upstream_routes() {
+        let yaml = r###"
+relay:
+    upstream: https://default.example.com/
+    routes:
+      - upstream: https://eu.example.com/
+        project_ids: [42]
+        public_keys: [a94ae32be2584e0bbd7a4cbb95971fee]
+      - upstream: [https://us.example.com/, https://us-fallback.example.com/]
+        project_ids: [43]
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        let key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let other_key = ProjectKey::parse("b94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+
+        assert_eq!(config.upstream_route(Some(key), None), Some(0));
+        assert_eq!(
+            config.upstream_route(Some(other_key), Some(ProjectId::new(42))),
+            Some(0)
+        );
+        assert_eq!(
+            config.upstream_route(Some(other_key), Some(ProjectId::new(43))),
+            Some(1)
+        );
+        assert_eq!(
+            config.upstream_route(Some(other_key), Some(ProjectId::new(44))),
+            None
+        );
+        assert_eq!(config.upstream_route(Some(other_key), None), None);
+        assert_eq!(
+            config.upstream_route(None, Some(ProjectId::new(43))),
+            Some(1)
+        );
+
+        let routed = config.for_upstream_route(1).unwrap();
+        assert!(routed.upstream_routes().is_empty());
+        assert_eq!(routed.upstreams().as_slice().len(), 2);
+        assert_eq!(routed.upstream_descriptor().host(), "us.example.com");
+        assert!(config.for_upstream_route(2).is_err());
+    }
+
+    #[test]
+    fn test_outcomes_upstream() {
+        let yaml = r###"
+relay:
+    upstream: https://default.example.com/
+outcomes:
+    upstream: https://billing.example.com/
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        let outcomes = config.for_outcomes_upstream().unwrap().unwrap();
+        assert_eq!(outcomes.upstream_descriptor().host(), "billing.example.com");
+        assert!(outcomes.for_outcomes_upstream().unwrap().is_none());
+        assert!(Config::default().for_outcomes_upstream().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_http_egress_rate() {
+        let yaml = r###"
+http:
+    max_egress_rate: 1MiB
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        assert_eq!(config.http_max_egress_rate(), Some(1024 * 1024));
+        assert_eq!(config.http_egress_burst(), 1024 * 1024);
+        assert_eq!(Config::default().http_max_egress_rate(), None);
+    }
+
+    #[test]
+    fn test_http_encoding_zstd() {
+        let yaml = r###"
+http:
+    encoding: zstd
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        assert!(matches!(config.http_encoding(), HttpEncoding::Zstd));
+        assert_eq!(HttpEncoding::parse("ZSTD").name(), Some("zstd"));
+    }
+
+    #[test]
+    fn test_http_retry_policy() {
+        let yaml = r###"
+http:
+    retry_jitter: 1.5
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        // The randomization factor is clamped to `[0, 1]`.
+        assert_eq!(config.http_retry_jitter(), 1.0);
+        assert_eq!(config.http_retry_backoff_base(), Duration::from_secs(1));
+        assert_eq!(
+            config.http_circuit_breaker_timeout(),
+            Duration::from_secs(30)
+        );
+
+        let config = Config::default();
+        assert_eq!(config.http_max_retries(), None);
+        assert_eq!(*config.http_retry_status_codes(), [502, 503, 504]);
+        assert_eq!(config.http_circuit_breaker_threshold(), 0);
+    }
+
+    #[test]
+    fn test_spool_forward() {
+        let yaml = r###"
+spool:
+    forward:
+        path: /var/lib/relay/forward.db
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        assert_eq!(
+            config.spool_forward_path(),
+            Some(PathBuf::from("/var/lib/relay/forward.db"))
+        );
+        assert_eq!(config.spool_forward_drain_rate(), 100);
+        assert!(Config::default().spool_forward_path().is_none());
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = TempDir::new();
+        let path = dir.path();
+
+        let write_config = |max_event_size: &str, port: u16| {
+            let yaml = format!(
+                "relay:\n  port: {port}\n  routes:\n    - upstream: https://eu.example.com/\n      project_ids: [42]\nlimits:\n  max_event_size: {max_event_size}\n"
+            );
+            dir.write("config.yml", yaml);
+        };
+
+        write_config("1MB", 3000);
+        let config = Config::from_path(path).unwrap();
+        let routed = config.for_upstream_route(0).unwrap();
+        assert_eq!(config.max_event_size(), 1_000_000);
+        assert!(config.reload().unwrap().is_empty());
+
+        write_config("2MB", 3001);
+        let report = config.reload().unwrap();
+        assert_eq!(report.applied, ["limits.max_event_size"]);
+        assert_eq!(report.restart_required, ["relay.port"]);
+        assert_eq!(config.max_event_size(), 2_000_000);
+        assert_eq!(config.listen_addr().port(), 3000);
+
+        // Nested configs of upstream routes are reloaded as well.
+        assert_eq!(routed.max_event_size(), 2_000_000);
+    }
+
+    #[test]
+    fn test_env_substitution() {
+        let dir = TempDir::new();
+        let path = dir.path();
+
+        // Tests run in parallel, so the variable name must be unique to this test.
+        let port_var = format!("RELAY_TEST_PORT_{}", Uuid::new_v4().simple());
+        let yaml =
+            format!("relay:\n  port: ${{{port_var}}}\n  host: ${{RELAY_TEST_HOST:-127.0.0.2}}\n");
+        dir.write("config.yml", yaml);
+
+        let error = Config::from_path(path).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ConfigError>().unwrap().kind(),
+            ConfigErrorKind::BadEnvVar
+        );
+
+        env::set_var(&port_var, "3001");
+        let config = Config::from_path(path).unwrap();
+        assert_eq!(config.listen_addr(), "127.0.0.2:3001".parse().unwrap());
+        env::remove_var(&port_var);
+    }
+
+    #[test]
+    fn test_toml_config() {
+        let dir = TempDir::new();
+        let path = dir.path();
+
+        let toml = r###"
+[relay]
+mode = "static"
+port = 3001
+
+[limits]
+max_event_size = "2MB"
+"###;
+
+        dir.write("config.toml", toml);
+        let config = Config::from_path(path).unwrap();
+        assert_eq!(config.config_file_path(), path.join("config.toml"));
+        assert_eq!(config.relay_mode(), RelayMode::Static);
+        assert_eq!(config.listen_addr().port(), 3001);
+        assert_eq!(config.max_event_size(), 2_000_000);
+
+        dir.write("config.toml", "[relay\n");
+        let error = Config::from_path(path).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ConfigError>().unwrap().kind(),
+            ConfigErrorKind::BadToml
+        );
+    }
+
+    #[test]
+    fn test_secret_files() {
+        let dir = TempDir::new();
+        let path = dir.path();
+
+        let yaml = r###"
+http:
+    proxy_url: http://proxy:3128
+    proxy_username: relay
+    proxy_password_file: proxy-password
+"###;
+
+        dir.write("config.yml", yaml);
+        dir.write("proxy-password", "hunter2\n");
+
+        let config = Config::from_path(path).unwrap();
+        assert_eq!(config.http_proxy_auth(), Some(("relay", "hunter2")));
+
+        fs::remove_file(path.join("proxy-password")).unwrap();
+        let error = config.reload().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ConfigError>().unwrap().kind(),
+            ConfigErrorKind::BadSecret
+        );
+    }
+
+    #[test]
+    fn test_includes() {
+        let dir = TempDir::new();
+        let path = dir.path();
+
+        let yaml = r###"
+include:
+    - conf.d/*.yml
+limits:
+    max_event_size: 1MB
+"###;
+
+        dir.write("config.yml", yaml);
+        dir.write(
+            "conf.d/limits.yml",
+            "limits:\n    max_event_size: 2MB\n    max_attachment_size: 2MB\n",
+        );
+
+        // The config file is applied last and overrides values of fragments.
+        let config = Config::from_path(path).unwrap();
+        assert_eq!(config.max_event_size(), 1_000_000);
+        assert_eq!(config.max_attachment_size(), 2_000_000);
+    }
+
+    #[test]
+    fn test_upstream_authentication() {
+        let yaml = r###"
+relay:
+    mode: managed
+http:
+    authentication: mtls
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        assert_eq!(config.http_authentication(), UpstreamAuthentication::Mtls);
+        assert!(!config.requires_registration());
+
+        let mut diagnostics = Vec::new();
+        check_config(&config, &mut diagnostics);
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.is_error() && d.path == "http.client_certificate"));
+
+        let config = config_from_yaml("relay:\n    mode: managed\n");
+        assert!(config.requires_registration());
+    }
+
+    #[test]
+    fn test_runtime_overrides() {
+        let dir = TempDir::new();
+        let path = dir.path();
+
+        let yaml = r###"
+logging:
+    level: info
+metrics:
+    sample_rate: 1.0
+"###;
+
+        dir.write("config.yml", yaml);
+
+        let config = Config::from_path(path).unwrap();
+        assert_eq!(config.log_level(), relay_log::Level::INFO);
+        assert!(!config.item_type_disabled("attachment"));
+
+        let overrides = RuntimeOverrides {
+            log_level: Some(relay_log::Level::DEBUG),
+            log_filters: Some([("relay_server".to_owned(), relay_log::Level::TRACE)].into()),
+            log_format: Some(relay_log::LogFormat::Json),
+            metrics_sample_rate: Some(0.25),
+            disabled_item_types: ["attachment".to_owned()].into(),
+        };
+        config.set_runtime_overrides(overrides.clone()).unwrap();
+
+        assert_eq!(config.log_level(), relay_log::Level::DEBUG);
+        let logging = config.effective_logging();
+        assert_eq!(logging.level, relay_log::Level::DEBUG);
+        assert_eq!(logging.filters["relay_server"], relay_log::Level::TRACE);
+        assert_eq!(logging.format, relay_log::LogFormat::Json);
+        assert_eq!(config.metrics_sample_rate(), 0.25);
+        assert!(config.item_type_disabled("attachment"));
+        assert!(!config.item_type_disabled("event"));
+
+        // Overrides are persisted and loaded again on startup.
+        let config = Config::from_path(path).unwrap();
+        assert_eq!(*config.runtime_overrides(), overrides);
+    }
+
+    #[test]
+    fn test_override_validated() {
+        let yaml = r###"
+http:
+    proxy_url: ftp://proxy.example.com
+"###;
+        let mut config = config_from_yaml(yaml);
+        let error = config.apply_override(Default::default()).unwrap_err();
+        assert!(format!("{error:#}").contains("http.proxy_url"));
+
+        let mut config = Config::default();
+        let overrides = OverridableConfig {
+            inspect: Some("true".to_owned()),
+            ..Default::default()
+        };
+        let error = config.apply_override(overrides).unwrap_err();
+        assert!(format!("{error:#}").contains("inspect.path"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let dir = TempDir::new();
+        let path = dir.path();
+
+        let yaml = r###"
+relay:
+    mode: proxy
+    tls_port: 3443
+    prot: 3001
+processing:
+    enabled: true
+    topics:
+        metrics:
+            name: ingest-metrics
+            config: missing
+spool:
+    envelopes:
+        compaction_interval: 0
+inspect:
+    enabled: true
+"###;
+
+        dir.write("config.yml", yaml);
+        let diagnostics = Config::validate(path).unwrap();
+
+        let has = |severity, field: &str| {
+            diagnostics
+                .iter()
+                .any(|d| d.severity == severity && d.path == field)
+        };
+
+        assert!(has(Severity::Error, "relay.prot"));
+        assert!(has(Severity::Error, "relay.tls_identity_path"));
+        assert!(has(Severity::Error, "processing.enabled"));
+        assert!(has(Severity::Error, "processing.kafka_config"));
+        assert!(has(Severity::Error, "processing.topics"));
+        assert!(has(Severity::Warning, "processing.redis"));
+        assert!(has(Severity::Error, "spool.envelopes.compaction_interval"));
+        assert!(has(Severity::Error, "inspect.path"));
+        assert!(!has(Severity::Error, "relay.mode"));
+
+        dir.write("config.yml", "relay:\n    port: invalid\n");
+        let diagnostics = Config::validate(path).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, "relay.port");
+    }
+
+    #[test]
+    #[cfg(feature = "jsonschema")]
+    fn test_json_schema() {
+        let schema = serde_json::to_value(Config::json_schema()).unwrap();
+
+        let properties = &schema["properties"];
+        assert!(properties["relay"].is_object());
+        assert!(properties["processing"].is_object());
+
+        let limits = &schema["definitions"]["Limits"]["properties"];
+        assert_eq!(limits["max_event_size"]["default"], "1MiB");
+        assert!(limits["max_event_size"]["description"].is_string());
+    }
+
+    #[test]
+    fn test_secrets_provider() {
+        let yaml = r###"
+processing:
+    kafka_config:
+        - {name: "bootstrap.servers", value: "kafka:9092"}
+        - {name: "sasl.password", value: "old"}
+secrets:
+    provider:
+        type: vault
+        address: https://vault:8200
+        token: s.token
+        path: relay/prod
+"###;
+
+        let mut config = config_from_yaml(yaml);
+
+        assert_eq!(
+            config.secrets_provider(),
+            Some(&SecretsProvider::Vault {
+                address: "https://vault:8200".to_owned(),
+                token: "s.token".to_owned(),
+                mount: "secret".to_owned(),
+                path: "relay/prod".to_owned(),
+                namespace: None,
+            })
+        );
+        assert_eq!(
+            config.secrets_refresh_interval(),
+            Some(Duration::from_secs(300))
+        );
+
+        let credentials = Credentials::generate();
+        let secrets = ProvidedSecrets {
+            id: Some(credentials.id),
+            public_key: Some(credentials.public_key.clone()),
+            secret_key: Some(credentials.secret_key.clone()),
+            redis: Some("redis://redis:6379".to_owned()),
+            kafka_config: [("sasl.password".to_owned(), "new".to_owned())].into(),
+        };
+
+        config.apply_provided_secrets(&secrets);
+        assert_eq!(config.credentials(), Some(credentials));
+        assert!(
+            matches!(config.redis(), Some(RedisConfig::Single(url)) if url == "redis://redis:6379")
+        );
+
+        let kafka_config = &config.values.processing.kafka_config;
+        assert_eq!(kafka_config.len(), 2);
+        assert_eq!(kafka_config[1].value, "new");
+
+        assert!(!config.rotate_credentials(config.credentials().unwrap()));
+        assert!(config.rotate_credentials(Credentials::generate()));
+    }
+
+    #[test]
+    fn test_pubsub_sink() {
+        let yaml = r###"
+processing:
+    enabled: true
+    kafka_config: []
+    sink:
+        type: pubsub
+        project: relay
+        topics:
+            error: ingest-events
+        default_topic: ingest-other
+"###;
+
+        let config = config_from_yaml(yaml);
+
+        let StoreSinkConfig::PubSub(pubsub) = config.store_sink() else {
+            panic!("expected pubsub sink");
+        };
+        assert_eq!(pubsub.max_batch_messages, 100);
+        assert_eq!(pubsub.topic(DataCategory::Error), Some("ingest-events"));
+        assert_eq!(pubsub.topic(DataCategory::Session), Some("ingest-other"));
+    }
+
     #[test]
     fn test_emit_outcomes_invalid() {
         assert!(serde_json::from_str::<EmitOutcomes>("asdf").is_err());
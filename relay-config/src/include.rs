@@ -0,0 +1,234 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::{fs, io, mem};
+
+use relay_common::glob::{glob_match, GlobOptions};
+use serde_json::Value;
+
+use crate::config::ConfigFormat;
+use crate::interpolate::{interpolate_env, InterpolationError};
+
+/// Name of the field that lists config fragments to include.
+pub(crate) const INCLUDE_FIELD: &str = "include";
+
+/// Raised if config fragments cannot be included.
+#[derive(Debug, thiserror::Error)]
+pub enum IncludeError {
+    /// The `include` field is not a list of paths.
+    #[error("`include` must be a list of paths")]
+    InvalidField,
+    /// A pattern contains wildcards outside of the file name.
+    #[error("invalid include pattern `{0}`, only the file name may contain wildcards")]
+    InvalidPattern(String),
+    /// A fragment or directory could not be read.
+    #[error("could not read {}", .0.display())]
+    Io(PathBuf, #[source] io::Error),
+    /// Environment variables could not be substituted in a fragment.
+    #[error("could not substitute environment variables in {}", .0.display())]
+    EnvVar(PathBuf, #[source] InterpolationError),
+    /// A fragment could not be parsed.
+    #[error("could not parse {}", .0.display())]
+    Parse(PathBuf, #[source] Box<dyn Error + Send + Sync>),
+    /// A fragment does not contain a mapping at the top level.
+    #[error("{} must contain a mapping", .0.display())]
+    NotAMapping(PathBuf),
+    /// A fragment includes further fragments.
+    #[error("{} cannot include other files", .0.display())]
+    Nested(PathBuf),
+}
+
+/// Returns `true` if the path contains glob wildcards.
+fn has_wildcards(path: &str) -> bool {
+    path.contains(['*', '?', '[', '{'])
+}
+
+/// Expands an include pattern to a lexically sorted list of files.
+///
+/// Relative patterns are resolved relative to the config folder. Patterns without wildcards must
+/// reference an existing file, while patterns with wildcards may not match any files.
+fn expand_pattern(base: &Path, pattern: &str) -> Result<Vec<PathBuf>, IncludeError> {
+    let path = base.join(pattern);
+
+    if !has_wildcards(pattern) {
+        return Ok(vec![path]);
+    }
+
+    let (Some(directory), Some(file_pattern)) = (path.parent(), path.file_name()) else {
+        return Err(IncludeError::InvalidPattern(pattern.to_owned()));
+    };
+
+    let pattern_directory = Path::new(pattern).parent().unwrap_or(Path::new(""));
+    if has_wildcards(&pattern_directory.to_string_lossy()) {
+        return Err(IncludeError::InvalidPattern(pattern.to_owned()));
+    }
+
+    let file_pattern = file_pattern.to_string_lossy();
+
+    let options = GlobOptions {
+        double_star: true,
+        ..Default::default()
+    };
+
+    let entries = fs::read_dir(directory).map_err(|e| IncludeError::Io(directory.to_owned(), e))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| IncludeError::Io(directory.to_owned(), e))?;
+        let name = entry.file_name();
+        let is_file = entry.file_type().map_or(false, |t| t.is_file());
+
+        if is_file && glob_match(&name.to_string_lossy(), &file_pattern, options) {
+            files.push(entry.path());
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Reads and parses a single config fragment.
+fn load_fragment(path: &Path) -> Result<Value, IncludeError> {
+    let contents = fs::read_to_string(path).map_err(|e| IncludeError::Io(path.to_owned(), e))?;
+
+    let format = ConfigFormat::from_path(path).unwrap_or(ConfigFormat::Yaml);
+    let mut fragment: Value = format
+        .parse(&contents)
+        .map_err(|e| IncludeError::Parse(path.to_owned(), e.into()))?;
+    interpolate_env(&mut fragment).map_err(|e| IncludeError::EnvVar(path.to_owned(), e))?;
+
+    match fragment {
+        Value::Object(ref map) if map.contains_key(INCLUDE_FIELD) => {
+            Err(IncludeError::Nested(path.to_owned()))
+        }
+        Value::Object(_) => Ok(fragment),
+        // An empty YAML file parses as `null`.
+        Value::Null => Ok(Value::Object(Default::default())),
+        _ => Err(IncludeError::NotAMapping(path.to_owned())),
+    }
+}
+
+/// Recursively merges `other` into `value`.
+///
+/// Mappings are merged key by key. All other values, including lists, are replaced.
+pub(crate) fn merge(value: &mut Value, other: Value) {
+    match (value, other) {
+        (Value::Object(map), Value::Object(other)) => {
+            for (key, other) in other {
+                match map.get_mut(&key) {
+                    Some(value) => merge(value, other),
+                    None => {
+                        map.insert(key, other);
+                    }
+                }
+            }
+        }
+        (value, other) => *value = other,
+    }
+}
+
+/// Merges config fragments listed in the `include` field into the document.
+///
+/// Fragments are merged in the order of the patterns, and files matching a single pattern are
+/// merged in lexical order. Values in later fragments take precedence, and the document itself is
+/// merged last, so that its values override all fragments. Returns `true` if the document
+/// contained an `include` field.
+pub fn resolve_includes(document: &mut Value, base: &Path) -> Result<bool, IncludeError> {
+    let Some(include) = document
+        .as_object_mut()
+        .and_then(|m| m.remove(INCLUDE_FIELD))
+    else {
+        return Ok(false);
+    };
+
+    let patterns = match include {
+        Value::Null => Vec::new(),
+        Value::String(pattern) => vec![Value::String(pattern)],
+        Value::Array(patterns) => patterns,
+        _ => return Err(IncludeError::InvalidField),
+    };
+
+    let mut merged = Value::Object(Default::default());
+    for pattern in patterns {
+        let Value::String(pattern) = pattern else {
+            return Err(IncludeError::InvalidField);
+        };
+
+        for path in expand_pattern(base, &pattern)? {
+            merge(&mut merged, load_fragment(&path)?);
+        }
+    }
+
+    merge(&mut merged, mem::take(document));
+    *document = merged;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::testutils::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut value = json!({
+            "limits": {"max_event_size": "1MB", "max_thread_count": 4},
+            "quotas": [1, 2],
+        });
+
+        merge(
+            &mut value,
+            json!({"limits": {"max_event_size": "2MB"}, "quotas": [3], "new": true}),
+        );
+
+        assert_eq!(
+            value,
+            json!({
+                "limits": {"max_event_size": "2MB", "max_thread_count": 4},
+                "quotas": [3],
+                "new": true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_resolve_includes() {
+        let dir = TempDir::new();
+        let base = dir.path();
+        dir.write("conf.d/20-limits.yml", "limits:\n  max_event_size: 2MB\n");
+        dir.write("conf.d/10-limits.yml", "limits:\n  max_event_size: 1MB\n");
+        dir.write("conf.d/ignored.txt", "invalid: [");
+        dir.write("kafka.json", r#"{"processing": {"enabled": true}}"#);
+
+        let mut document = json!({
+            "include": ["conf.d/*.yml", "kafka.json", "missing/*.yml"],
+            "processing": {"enabled": false, "redis": "redis://"},
+        });
+
+        fs::create_dir_all(base.join("missing")).unwrap();
+        assert!(resolve_includes(&mut document, base).unwrap());
+        assert_eq!(
+            document,
+            json!({
+                "limits": {"max_event_size": "2MB"},
+                // Values of the config file take precedence over fragments.
+                "processing": {"enabled": false, "redis": "redis://"},
+            })
+        );
+
+        let mut missing = json!({"include": ["missing.yml"]});
+        assert!(matches!(
+            resolve_includes(&mut missing, base),
+            Err(IncludeError::Io(..))
+        ));
+
+        let mut invalid = json!({"include": ["*/limits.yml"]});
+        assert!(matches!(
+            resolve_includes(&mut invalid, base),
+            Err(IncludeError::InvalidPattern(_))
+        ));
+    }
+}
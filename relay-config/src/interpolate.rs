@@ -0,0 +1,210 @@
+use std::borrow::Cow;
+
+use serde_json::Value;
+
+/// Raised if environment variables cannot be substituted in a config file.
+#[derive(Debug, Eq, PartialEq, thiserror::Error)]
+pub enum InterpolationError {
+    /// A referenced variable is not set and has no default.
+    #[error("environment variable `{0}` is not set")]
+    Unset(String),
+    /// A variable reference is not a valid name.
+    #[error("invalid environment variable name `{0}`")]
+    InvalidName(String),
+    /// A variable reference is missing its closing brace.
+    #[error("unterminated environment variable reference")]
+    Unterminated,
+}
+
+/// Returns `true` if `name` is a valid environment variable name.
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Substitutes variable references in `input` with values returned by `lookup`.
+///
+/// The following syntax is supported:
+///  - `${VAR}` is replaced with the value of `VAR`. It is an error if `VAR` is not set.
+///  - `${VAR:-fallback}` is replaced with `fallback` if `VAR` is not set or empty.
+///  - `$${` is replaced with a literal `${`.
+///
+/// All other occurrences of `$` are left untouched.
+fn interpolate<F>(input: &str, lookup: F) -> Result<Cow<'_, str>, InterpolationError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if !input.contains("${") {
+        return Ok(Cow::Borrowed(input));
+    }
+
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(index) = rest.find('$') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(remaining) = rest.strip_prefix("$${") {
+            output.push_str("${");
+            rest = remaining;
+            continue;
+        }
+
+        let Some(remaining) = rest.strip_prefix("${") else {
+            output.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let end = remaining
+            .find('}')
+            .ok_or(InterpolationError::Unterminated)?;
+        let reference = &remaining[..end];
+        rest = &remaining[end + 1..];
+
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+
+        if !is_valid_name(name) {
+            return Err(InterpolationError::InvalidName(name.to_owned()));
+        }
+
+        match (lookup(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => output.push_str(default),
+            (Some(value), _) => output.push_str(&value),
+            (None, Some(default)) => output.push_str(default),
+            (None, None) => return Err(InterpolationError::Unset(name.to_owned())),
+        }
+    }
+
+    output.push_str(rest);
+    Ok(Cow::Owned(output))
+}
+
+/// Converts an interpolated string to a boolean or number if it is one, for example `"3000"`.
+fn to_scalar(value: String) -> Value {
+    match serde_yaml::from_str::<Value>(&value) {
+        Ok(scalar @ (Value::Bool(_) | Value::Number(_))) => scalar,
+        _ => Value::String(value),
+    }
+}
+
+/// Substitutes variable references in all string values of `document`.
+///
+/// Returns `true` if any value has changed.
+fn interpolate_document<F>(document: &mut Value, lookup: &F) -> Result<bool, InterpolationError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut changed = false;
+
+    match document {
+        Value::String(value) => {
+            let interpolated = match interpolate(value, lookup)? {
+                Cow::Owned(interpolated) => interpolated,
+                Cow::Borrowed(_) => return Ok(false),
+            };
+            *document = to_scalar(interpolated);
+            changed = true;
+        }
+        Value::Array(items) => {
+            for item in items {
+                changed |= interpolate_document(item, lookup)?;
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                changed |= interpolate_document(value, lookup)?;
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => (),
+    }
+
+    Ok(changed)
+}
+
+/// Substitutes environment variable references in the values of a parsed config document.
+///
+/// Substitution happens after parsing, so values of variables are never interpreted as part of the
+/// file's syntax. Only string values are substituted, keys are left untouched. If the result is a
+/// boolean or number, the value is converted accordingly, so that `port: "${PORT}"` yields a
+/// number. See [`interpolate`] for the supported syntax.
+///
+/// Returns `true` if any value has been substituted.
+pub fn interpolate_env(document: &mut Value) -> Result<bool, InterpolationError> {
+    interpolate_document(document, &|name| std::env::var(name).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("sentry.example.com".to_owned()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let input = "upstream: https://${HOST}/\nport: ${PORT:-3000}\nhost: ${EMPTY:-0.0.0.0}";
+        assert_eq!(
+            interpolate(input, lookup).unwrap(),
+            "upstream: https://sentry.example.com/\nport: 3000\nhost: 0.0.0.0"
+        );
+
+        assert_eq!(interpolate("a: ${EMPTY}", lookup).unwrap(), "a: ");
+        assert_eq!(
+            interpolate("a: $${HOST} $5", lookup).unwrap(),
+            "a: ${HOST} $5"
+        );
+        assert!(matches!(
+            interpolate("no variables", lookup).unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_interpolate_document() {
+        let mut document = serde_json::json!({
+            "relay": {"upstream": "https://${HOST}/", "port": "${PORT:-3000}"},
+            "${HOST}": ["${EMPTY}", "${EMPTY:-true}", "a: ${HOST}", 42],
+        });
+
+        assert!(interpolate_document(&mut document, &lookup).unwrap());
+        assert_eq!(
+            document,
+            serde_json::json!({
+                "relay": {"upstream": "https://sentry.example.com/", "port": 3000},
+                "${HOST}": ["", true, "a: sentry.example.com", 42],
+            })
+        );
+
+        // Without references, the document is unchanged.
+        assert!(!interpolate_document(&mut document, &lookup).unwrap());
+    }
+
+    #[test]
+    fn test_interpolate_errors() {
+        assert_eq!(
+            interpolate("${MISSING}", lookup),
+            Err(InterpolationError::Unset("MISSING".to_owned()))
+        );
+        assert_eq!(
+            interpolate("${1ABC}", lookup),
+            Err(InterpolationError::InvalidName("1ABC".to_owned()))
+        );
+        assert_eq!(
+            interpolate("${HOST", lookup),
+            Err(InterpolationError::Unterminated)
+        );
+    }
+}
@@ -8,8 +8,20 @@
 
 mod byte_size;
 mod config;
+mod include;
+mod interpolate;
+mod profile;
+mod reload;
+mod secrets;
+mod spki_pin;
+#[cfg(test)]
+mod testutils;
 mod upstream;
+mod validate;
 
 pub use crate::byte_size::*;
 pub use crate::config::*;
+pub use crate::reload::*;
+pub use crate::spki_pin::*;
 pub use crate::upstream::*;
+pub use crate::validate::*;
@@ -0,0 +1,133 @@
+use std::env;
+
+use serde_json::Value;
+
+use crate::include::{merge, INCLUDE_FIELD};
+
+/// Name of the field that contains the config profiles.
+const PROFILES_FIELD: &str = "profiles";
+
+/// Name of the environment variable that selects the active profile.
+const PROFILE_ENV_VAR: &str = "RELAY_PROFILE";
+
+/// Raised if the active config profile cannot be applied.
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileError {
+    /// The `profiles` field is not a mapping of profile names to config values.
+    #[error("`profiles` must be a mapping of profile names to config values")]
+    InvalidField,
+    /// The selected profile is not defined in the config file.
+    #[error("unknown config profile `{0}`")]
+    Unknown(String),
+    /// A profile does not contain a mapping.
+    #[error("config profile `{0}` must contain a mapping")]
+    NotAMapping(String),
+    /// A profile contains fields that cannot be overridden by profiles.
+    #[error("config profile `{0}` cannot contain `profiles` or `include`")]
+    Nested(String),
+}
+
+/// Returns the name of the active profile from the `RELAY_PROFILE` environment variable.
+pub fn active_profile() -> Option<String> {
+    env::var(PROFILE_ENV_VAR).ok().filter(|p| !p.is_empty())
+}
+
+/// Merges the values of the active profile into the document.
+///
+/// Profiles are listed by name in the `profiles` field and contain overrides for the rest of the
+/// file. The values of the active profile are deep-merged into the document and take precedence.
+/// The `profiles` field is removed in any case. Returns `true` if the document contained profiles.
+pub fn resolve_profile(document: &mut Value, profile: Option<&str>) -> Result<bool, ProfileError> {
+    let Some(profiles) = document
+        .as_object_mut()
+        .and_then(|m| m.remove(PROFILES_FIELD))
+    else {
+        return match profile {
+            Some(profile) => Err(ProfileError::Unknown(profile.to_owned())),
+            None => Ok(false),
+        };
+    };
+
+    let mut profiles = match profiles {
+        Value::Null => Default::default(),
+        Value::Object(profiles) => profiles,
+        _ => return Err(ProfileError::InvalidField),
+    };
+
+    let Some(profile) = profile else {
+        return Ok(true);
+    };
+
+    let values = match profiles.remove(profile) {
+        Some(Value::Object(values)) => values,
+        // An empty profile parses as `null`.
+        Some(Value::Null) => return Ok(true),
+        Some(_) => return Err(ProfileError::NotAMapping(profile.to_owned())),
+        None => return Err(ProfileError::Unknown(profile.to_owned())),
+    };
+
+    if values.contains_key(PROFILES_FIELD) || values.contains_key(INCLUDE_FIELD) {
+        return Err(ProfileError::Nested(profile.to_owned()));
+    }
+
+    merge(document, Value::Object(values));
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_profile() {
+        let document = json!({
+            "relay": {"upstream": "https://sentry.io/", "port": 3000},
+            "profiles": {
+                "staging": {"relay": {"upstream": "https://staging.example.com/"}},
+                "production": null,
+            },
+        });
+
+        let mut staging = document.clone();
+        assert!(resolve_profile(&mut staging, Some("staging")).unwrap());
+        assert_eq!(
+            staging,
+            json!({"relay": {"upstream": "https://staging.example.com/", "port": 3000}})
+        );
+
+        let mut production = document.clone();
+        assert!(resolve_profile(&mut production, Some("production")).unwrap());
+        assert_eq!(
+            production,
+            json!({"relay": {"upstream": "https://sentry.io/", "port": 3000}})
+        );
+
+        let mut none = document.clone();
+        assert!(resolve_profile(&mut none, None).unwrap());
+        assert_eq!(none, production);
+
+        let mut unknown = document;
+        assert!(matches!(
+            resolve_profile(&mut unknown, Some("development")),
+            Err(ProfileError::Unknown(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_profile_missing() {
+        let mut document = json!({"relay": {"port": 3000}});
+        assert!(!resolve_profile(&mut document, None).unwrap());
+        assert!(matches!(
+            resolve_profile(&mut document, Some("staging")),
+            Err(ProfileError::Unknown(_))
+        ));
+
+        let mut nested = json!({"profiles": {"staging": {"include": ["staging.yml"]}}});
+        assert!(matches!(
+            resolve_profile(&mut nested, Some("staging")),
+            Err(ProfileError::Nested(_))
+        ));
+    }
+}
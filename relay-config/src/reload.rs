@@ -0,0 +1,242 @@
+use std::fmt;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Paths of configuration fields that can be changed without restarting Relay.
+///
+/// Changes to all other fields are reported by [`Config::reload`](crate::Config::reload), but
+/// only take effect after a restart.
+pub(crate) const RELOADABLE_FIELDS: &[&str] = &[
+    "logging.level",
+    "limits.max_event_size",
+    "limits.max_attachment_size",
+    "limits.max_attachments_size",
+    "limits.max_client_reports_size",
+    "limits.max_check_in_size",
+    "limits.max_span_size",
+    "limits.max_envelope_size",
+    "limits.max_session_count",
+    "limits.max_api_payload_size",
+    "limits.max_api_file_upload_size",
+    "limits.max_api_chunk_upload_size",
+    "limits.max_profile_size",
+    "limits.max_replay_compressed_size",
+    "limits.max_replay_uncompressed_size",
+    "limits.max_replay_message_size",
+    "http.max_retry_interval",
+    "http.retry_backoff_base",
+    "http.retry_jitter",
+    "http.max_retries",
+    "http.retry_status_codes",
+    "rate_limiting.quotas",
+];
+
+/// A configuration value that can be replaced while Relay is running.
+///
+/// The value is serialized and deserialized transparently. Reading the value returns a shared
+/// snapshot, which is not affected by subsequent reloads. Reads do not take a lock, since values
+/// are read on every request.
+pub struct Reloadable<T>(ArcSwap<T>);
+
+impl<T> Reloadable<T> {
+    /// Creates a new reloadable value.
+    pub fn new(value: T) -> Self {
+        Self(ArcSwap::from_pointee(value))
+    }
+
+    /// Returns a snapshot of the current value.
+    pub fn get(&self) -> Arc<T> {
+        self.0.load_full()
+    }
+
+    /// Replaces the current value.
+    pub(crate) fn replace(&self, value: Arc<T>) {
+        self.0.store(value);
+    }
+
+    /// Replaces the current value with the value of `other`.
+    pub(crate) fn reload_from(&self, other: &Self) {
+        self.replace(other.get());
+    }
+}
+
+impl<T: Default> Default for Reloadable<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for Reloadable<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Reloadable<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+impl<T: Serialize> Serialize for Reloadable<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.get().serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Reloadable<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize(deserializer).map(Self::new)
+    }
+}
+
+#[cfg(feature = "jsonschema")]
+impl<T: schemars::JsonSchema> schemars::JsonSchema for Reloadable<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        gen.subschema_for::<T>()
+    }
+}
+
+/// The result of [`Config::reload`](crate::Config::reload).
+#[derive(Debug, Default)]
+pub struct ReloadReport {
+    /// Fields that changed and have been applied.
+    pub applied: Vec<String>,
+    /// Fields that changed, but only take effect after a restart.
+    pub restart_required: Vec<String>,
+}
+
+impl ReloadReport {
+    /// Creates a report by comparing two serialized configurations.
+    pub(crate) fn diff(old: &serde_json::Value, new: &serde_json::Value) -> Self {
+        let (applied, restart_required) = changed_paths(old, new)
+            .into_iter()
+            .partition(|path| RELOADABLE_FIELDS.iter().any(|f| is_within(path, f)));
+
+        Self {
+            applied,
+            restart_required,
+        }
+    }
+
+    /// Returns `true` if no fields have changed.
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.restart_required.is_empty()
+    }
+}
+
+/// Returns the paths of all leaf values that differ between two serialized structures.
+///
+/// Objects are compared recursively and paths are joined with `.`. Arrays are compared as a whole.
+/// The returned paths never contain the values themselves, so they can be logged even if the
+/// structures contain secrets.
+pub fn changed_paths(old: &serde_json::Value, new: &serde_json::Value) -> Vec<String> {
+    let mut changed = Vec::new();
+    collect_changes(String::new(), old, new, &mut changed);
+    changed
+}
+
+/// Returns `true` if `path` is `field` or one of its children.
+fn is_within(path: &str, field: &str) -> bool {
+    path.strip_prefix(field)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Collects the paths of all leaf values that differ between `old` and `new`.
+///
+/// Arrays are compared as a whole.
+fn collect_changes(
+    path: String,
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+    changed: &mut Vec<String>,
+) {
+    use serde_json::Value;
+
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        if old != new {
+            changed.push(path);
+        }
+        return;
+    };
+
+    let mut keys: Vec<_> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    for key in keys {
+        let child = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{path}.{key}")
+        };
+
+        let old = old.get(key).unwrap_or(&Value::Null);
+        let new = new.get(key).unwrap_or(&Value::Null);
+        collect_changes(child, old, new, changed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_reloadable() {
+        let value = Reloadable::new(vec![1]);
+        let snapshot = value.get();
+
+        value.replace(Arc::new(vec![1, 2]));
+        assert_eq!(*snapshot, vec![1]);
+        assert_eq!(*value.get(), vec![1, 2]);
+
+        value.reload_from(&Reloadable::new(vec![3]));
+        assert_eq!(*value.get(), vec![3]);
+        assert_eq!(serde_json::to_value(&value).unwrap(), json!([3]));
+    }
+
+    #[test]
+    fn test_reload_report() {
+        let old = json!({
+            "limits": {"max_event_size": "1MB", "max_thread_count": 4},
+            "logging": {"level": "info"},
+            "http": {"retry_status_codes": [502]},
+        });
+
+        let new = json!({
+            "limits": {"max_event_size": "2MB", "max_thread_count": 8},
+            "logging": {"level": "info"},
+            "http": {"retry_status_codes": [502, 503]},
+            "relay": {"port": 3001},
+        });
+
+        let report = ReloadReport::diff(&old, &new);
+        assert_eq!(
+            report.applied,
+            ["http.retry_status_codes", "limits.max_event_size"]
+        );
+        assert_eq!(
+            report.restart_required,
+            ["limits.max_thread_count", "relay"]
+        );
+        assert!(ReloadReport::diff(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use serde_json::Value;
+
+/// Paths of secret-bearing fields that can be loaded from a file.
+///
+/// To load a secret from a file, append `_file` to the field name and set it to the path of the
+/// file, for example `proxy_password_file: /run/secrets/proxy`. Segments are separated by `.`, and
+/// `*` matches any array index or object key.
+const SECRET_FIELDS: &[&str] = &[
+    // credentials.json
+    "secret_key",
+    "relay.tls_identity_password",
+    "http.proxy_password",
+    "processing.redis",
+    // Parameter values, e.g. for `sasl.password`
+    "processing.kafka_config.*.value",
+    "processing.secondary_kafka_configs.*.*.value",
+    // May contain access tokens
+    "outcomes.webhook.url",
+    "sentry.dsn",
+    "secrets.provider.token",
+    "admin.token",
+];
+
+/// Suffix of fields referencing a file that contains a secret.
+const FILE_SUFFIX: &str = "_file";
+
+/// Raised if a secret cannot be loaded from a file.
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    /// The secret file could not be read.
+    #[error("could not read secret file {}", .0.display())]
+    Io(PathBuf, #[source] io::Error),
+    /// Both the field and its file reference are set.
+    #[error("`{0}` and `{0}_file` cannot both be set")]
+    Conflict(&'static str),
+    /// The file reference is not a string.
+    #[error("`{0}_file` must be a path")]
+    InvalidPath(&'static str),
+}
+
+/// Resolves the path of a secret file.
+///
+/// Relative paths are resolved within `$CREDENTIALS_DIRECTORY` if it is set, which allows to use
+/// credentials passed by systemd. Otherwise, they are resolved relative to the config folder.
+fn secret_path(base: &Path, path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return path.to_owned();
+    }
+
+    match env::var_os("CREDENTIALS_DIRECTORY") {
+        Some(directory) => Path::new(&directory).join(path),
+        None => base.join(path),
+    }
+}
+
+/// Reads a secret from a file, ignoring trailing newlines.
+fn read_secret(path: &Path) -> Result<String, SecretError> {
+    let contents = fs::read_to_string(path).map_err(|e| SecretError::Io(path.to_owned(), e))?;
+    Ok(contents
+        .trim_end_matches(|c| c == '\r' || c == '\n')
+        .to_owned())
+}
+
+/// Returns the name of the field within the object at `path` if `secret` refers to it.
+fn secret_field<'a>(secret: &'a str, path: &[String]) -> Option<&'a str> {
+    let mut segments = secret.split('.');
+    for segment in path {
+        match segments.next()? {
+            "*" => continue,
+            expected if expected == segment => continue,
+            _ => return None,
+        }
+    }
+
+    let field = segments.next()?;
+    segments.next().is_none().then_some(field)
+}
+
+/// Replaces file references of secret fields with the contents of the referenced files.
+///
+/// This walks the entire document and replaces every field listed in [`SECRET_FIELDS`] that has
+/// the `_file` suffix. Fields with the same name at other paths are not changed. Returns `true` if
+/// any secrets were loaded.
+pub fn resolve_secrets(value: &mut Value, base: &Path) -> Result<bool, SecretError> {
+    resolve_secrets_at(value, base, &mut Vec::new())
+}
+
+/// Replaces file references of secret fields within the value at `path`.
+fn resolve_secrets_at(
+    value: &mut Value,
+    base: &Path,
+    path: &mut Vec<String>,
+) -> Result<bool, SecretError> {
+    let mut resolved = false;
+
+    match value {
+        Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                path.push(index.to_string());
+                resolved |= resolve_secrets_at(item, base, path)?;
+                path.pop();
+            }
+        }
+        Value::Object(map) => {
+            for &secret in SECRET_FIELDS {
+                let Some(field) = secret_field(secret, path) else {
+                    continue;
+                };
+
+                let Some(reference) = map.remove(&format!("{field}{FILE_SUFFIX}")) else {
+                    continue;
+                };
+
+                if map.contains_key(field) {
+                    return Err(SecretError::Conflict(secret));
+                }
+
+                let Value::String(file) = reference else {
+                    return Err(SecretError::InvalidPath(secret));
+                };
+
+                let contents = read_secret(&secret_path(base, &file))?;
+                map.insert(field.to_owned(), Value::String(contents));
+                resolved = true;
+            }
+
+            for (key, child) in map.iter_mut() {
+                path.push(key.clone());
+                resolved |= resolve_secrets_at(child, base, path)?;
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::testutils::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_secrets() {
+        let dir = TempDir::new();
+        let base = dir.path();
+        dir.write("proxy", "hunter2\n");
+        dir.write("sasl", "swordfish");
+
+        let mut value = json!({
+            "http": {"proxy_password_file": "proxy"},
+            "processing": {
+                "kafka_config": [
+                    {"name": "sasl.username", "value": "relay"},
+                    {"name": "sasl.password", "value_file": base.join("sasl")},
+                ],
+            },
+        });
+
+        assert!(resolve_secrets(&mut value, base).unwrap());
+        assert_eq!(
+            value,
+            json!({
+                "http": {"proxy_password": "hunter2"},
+                "processing": {
+                    "kafka_config": [
+                        {"name": "sasl.username", "value": "relay"},
+                        {"name": "sasl.password", "value": "swordfish"},
+                    ],
+                },
+            })
+        );
+
+        assert!(!resolve_secrets(&mut value, base).unwrap());
+
+        // Fields with the name of a secret at other paths are not resolved.
+        let mut unrelated = json!({"upstream": {"url_file": "proxy"}, "value_file": "proxy"});
+        assert!(!resolve_secrets(&mut unrelated, base).unwrap());
+        assert_eq!(
+            unrelated,
+            json!({"upstream": {"url_file": "proxy"}, "value_file": "proxy"})
+        );
+
+        let mut conflict = json!({"http": {"proxy_password": "a", "proxy_password_file": "proxy"}});
+        assert!(matches!(
+            resolve_secrets(&mut conflict, base),
+            Err(SecretError::Conflict("http.proxy_password"))
+        ));
+
+        let mut missing = json!({"http": {"proxy_password_file": "missing"}});
+        assert!(matches!(
+            resolve_secrets(&mut missing, base),
+            Err(SecretError::Io(..))
+        ));
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+use std::str::FromStr;
+
+use data_encoding::BASE64;
+use serde::ser::Serializer;
+use serde::{de, Serialize};
+
+/// Prefix of SPKI pins in the format used by HTTP public key pinning.
+const PIN_PREFIX: &str = "sha256/";
+
+/// An error returned when parsing an [`SpkiPin`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("invalid SPKI pin, expected a base64-encoded SHA-256 hash")]
+pub struct ParseSpkiPinError;
+
+/// A SHA-256 hash of a DER-encoded `SubjectPublicKeyInfo`, used to pin TLS certificates.
+///
+/// Pins are parsed from their base64 representation, optionally prefixed with `sha256/`, and are
+/// always serialized with the prefix.
+///
+/// # Examples
+///
+/// ```
+/// use relay_config::SpkiPin;
+///
+/// let pin: SpkiPin = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".parse().unwrap();
+/// assert_eq!(pin.to_string(), "sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpkiPin([u8; 32]);
+
+impl SpkiPin {
+    /// Creates a pin from a SHA-256 hash.
+    pub fn from_hash(hash: [u8; 32]) -> Self {
+        Self(hash)
+    }
+}
+
+impl FromStr for SpkiPin {
+    type Err = ParseSpkiPinError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let encoded = value.strip_prefix(PIN_PREFIX).unwrap_or(value);
+        let decoded = BASE64
+            .decode(encoded.as_bytes())
+            .map_err(|_| ParseSpkiPinError)?;
+
+        match decoded.try_into() {
+            Ok(hash) => Ok(Self(hash)),
+            Err(_) => Err(ParseSpkiPinError),
+        }
+    }
+}
+
+impl fmt::Display for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{PIN_PREFIX}{}", BASE64.encode(&self.0))
+    }
+}
+
+#[cfg(feature = "jsonschema")]
+impl schemars::JsonSchema for SpkiPin {
+    fn schema_name() -> String {
+        std::any::type_name::<Self>().to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl Serialize for SpkiPin {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for SpkiPin {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct V;
+
+        impl<'de> de::Visitor<'de> for V {
+            type Value = SpkiPin;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("base64-encoded SHA-256 hash")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<SpkiPin, E>
+            where
+                E: de::Error,
+            {
+                value
+                    .parse()
+                    .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_str(V)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spki_pin() {
+        let pin = "sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+        let expected: SpkiPin = pin.parse().unwrap();
+
+        assert_eq!(pin[7..].parse::<SpkiPin>().unwrap(), expected);
+        assert_eq!(expected.to_string(), pin);
+        assert!("sha256/AAAA".parse::<SpkiPin>().is_err());
+        assert!("not base64".parse::<SpkiPin>().is_err());
+    }
+
+    #[test]
+    fn test_deserialize_spki_pin() {
+        let pin = "sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+        let pins: Vec<SpkiPin> = serde_json::from_value(serde_json::json!([pin])).unwrap();
+        assert_eq!(
+            serde_json::to_value(pins).unwrap(),
+            serde_json::json!([pin])
+        );
+
+        assert!(serde_json::from_value::<SpkiPin>(serde_json::json!("sha256/AAAA")).is_err());
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use relay_kafka::{KafkaConfig, KafkaTopic};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Config, RelayMode, StoreSinkConfig, UpstreamAuthentication};
+
+/// The severity of a [`Diagnostic`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The configuration works, but is likely not what was intended.
+    Warning,
+    /// The configuration is invalid and Relay will not start or not work correctly.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found by [`Config::validate`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Diagnostic {
+    /// The severity of the problem.
+    pub severity: Severity,
+    /// Path of the affected field in the config file, for example `processing.kafka_config`.
+    ///
+    /// Array elements are addressed by their index. The path is empty if the problem concerns the
+    /// entire file.
+    pub path: String,
+    /// A human readable description of the problem.
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Creates a new error diagnostic.
+    pub fn error(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Creates a new warning diagnostic.
+    pub fn warning(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Returns `true` if this diagnostic is an error.
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}: {}", self.severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", self.severity, self.path, self.message)
+        }
+    }
+}
+
+/// Formats the path of an ignored field in the same notation as `serde_path_to_error`.
+fn ignored_path(path: &serde_ignored::Path<'_>) -> String {
+    use serde_ignored::Path;
+
+    let (parent, segment) = match path {
+        Path::Root => return String::new(),
+        Path::Seq { parent, index } => (parent, index.to_string()),
+        Path::Map { parent, key } => (parent, key.clone()),
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => return ignored_path(parent),
+    };
+
+    match ignored_path(parent) {
+        parent if parent.is_empty() => segment,
+        parent => format!("{parent}.{segment}"),
+    }
+}
+
+/// Deserializes a config document and records unknown fields and type errors.
+///
+/// Returns `None` if the document cannot be deserialized. In this case, `diagnostics` contains an
+/// error at the path of the invalid value.
+pub(crate) fn deserialize_document<T: DeserializeOwned>(
+    document: serde_json::Value,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<T> {
+    let mut unknown = Vec::new();
+    let deserializer = serde_ignored::Deserializer::new(document, |path| {
+        unknown.push(Diagnostic::error(ignored_path(&path), "unknown field"));
+    });
+
+    let result = serde_path_to_error::deserialize(deserializer);
+    diagnostics.append(&mut unknown);
+
+    match result {
+        Ok(value) => Some(value),
+        Err(error) => {
+            let path = error.path().to_string();
+            let path = if path == "." { String::new() } else { path };
+            diagnostics.push(Diagnostic::error(path, error.into_inner().to_string()));
+            None
+        }
+    }
+}
+
+/// Checks config values that Relay cannot start with.
+///
+/// These checks apply whenever a config is loaded or overridden, see [`Config::from_path`] and
+/// [`Config::apply_override`]. [`check_config`] reports them along with further checks.
+pub(crate) fn check_values(config: &Config, diagnostics: &mut Vec<Diagnostic>) {
+    if let Some(proxy_url) = config.http_proxy_url() {
+        if !crate::config::is_valid_proxy_url(proxy_url) {
+            diagnostics.push(Diagnostic::error(
+                "http.proxy_url",
+                "must be an http, https, socks5, or socks5h URL",
+            ));
+        }
+    }
+
+    for (name, value) in config.response_headers() {
+        if !crate::config::is_valid_response_header(name, value) {
+            diagnostics.push(Diagnostic::error(
+                format!("response_headers.{name}"),
+                "must be a valid HTTP header name and value",
+            ));
+        }
+    }
+
+    if config.ipv6_prefix().map_or(false, |prefix| prefix > 128) {
+        diagnostics.push(Diagnostic::error(
+            "ip_normalization.ipv6_prefix",
+            "must be at most 128",
+        ));
+    }
+
+    if config.spool_envelopes_compaction_interval().is_zero() {
+        diagnostics.push(Diagnostic::error(
+            "spool.envelopes.compaction_interval",
+            "must be greater than 0",
+        ));
+    }
+
+    if config
+        .inspect()
+        .map_or(false, |inspect| inspect.path.is_none())
+    {
+        diagnostics.push(Diagnostic::error(
+            "inspect.path",
+            "required when `inspect.enabled` is set",
+        ));
+    }
+
+    if config.client_certificate_header().is_some()
+        && config.client_certificate_proxies().is_empty()
+    {
+        diagnostics.push(Diagnostic::error(
+            "auth.client_certificates.trusted_proxies",
+            "required when `auth.client_certificates.header` is set",
+        ));
+    }
+
+    let max_batch_delay = match config.store_sink() {
+        StoreSinkConfig::PubSub(pubsub) => Some(pubsub.max_batch_delay),
+        StoreSinkConfig::Aws(aws) => Some(aws.max_batch_delay),
+        StoreSinkConfig::Kafka | StoreSinkConfig::Nats(_) => None,
+    };
+    if max_batch_delay == Some(0) {
+        diagnostics.push(Diagnostic::error(
+            "processing.sink.max_batch_delay",
+            "must be greater than 0",
+        ));
+    }
+}
+
+/// Checks combinations of config values that are valid individually, but not together.
+///
+/// This includes all checks of [`check_values`].
+pub(crate) fn check_config(config: &Config, diagnostics: &mut Vec<Diagnostic>) {
+    check_values(config, diagnostics);
+
+    let mode = config.relay_mode();
+
+    if mode == RelayMode::Managed
+        && !config.has_credentials()
+        && config.secrets_provider().is_none()
+    {
+        diagnostics.push(Diagnostic::error(
+            "relay.mode",
+            "managed mode requires credentials, generate them with `relay credentials generate`",
+        ));
+    }
+
+    if config.tls_listen_addr().is_some() && config.tls_identity_path().is_none() {
+        diagnostics.push(Diagnostic::error(
+            "relay.tls_identity_path",
+            "required when `relay.tls_port` is set",
+        ));
+    } else if config.tls_listen_addr().is_none() && config.tls_identity_path().is_some() {
+        diagnostics.push(Diagnostic::warning(
+            "relay.tls_port",
+            "`relay.tls_identity_path` is ignored without `relay.tls_port`",
+        ));
+    }
+
+    if config.http_authentication() == UpstreamAuthentication::Mtls
+        && config.http_client_certificate().is_none()
+    {
+        diagnostics.push(Diagnostic::error(
+            "http.client_certificate",
+            "required when `http.authentication` is `mtls`",
+        ));
+    }
+
+    let certificate_relays = config.client_certificate_relays();
+    if !certificate_relays.is_empty() && config.client_certificate_header().is_none() {
+        diagnostics.push(Diagnostic::warning(
+            "auth.client_certificates.header",
+            "client certificates are ignored without a header",
+        ));
+    }
+
+    for (pin, relay_id) in certificate_relays {
+        if !config.static_relays().contains_key(relay_id) {
+            diagnostics.push(Diagnostic::error(
+                format!("auth.client_certificates.relays.{pin}"),
+                format!("relay {relay_id} must be listed in `auth.static_relays`"),
+            ));
+        }
+    }
+
+    if !config.processing_enabled() {
+        return;
+    }
+
+    if cfg!(not(feature = "processing")) {
+        diagnostics.push(Diagnostic::error(
+            "processing.enabled",
+            "relay was not compiled with processing support",
+        ));
+    }
+
+    if mode == RelayMode::Proxy {
+        diagnostics.push(Diagnostic::error(
+            "processing.enabled",
+            "processing cannot be enabled in proxy mode",
+        ));
+    }
+
+    // Topics share Kafka configs, so report every missing config only once.
+    let mut missing_configs = BTreeSet::new();
+    for &topic in KafkaTopic::iter() {
+        let params = match config.kafka_config(topic) {
+            Ok(KafkaConfig::Single { params }) => vec![params],
+            Ok(KafkaConfig::Sharded { configs, .. }) => configs.into_values().collect(),
+            Ok(KafkaConfig::Clustered { clusters, .. }) => clusters,
+            Err(error) => {
+                diagnostics.push(Diagnostic::error(
+                    "processing.topics",
+                    format!("{error} (topic {topic:?})"),
+                ));
+                continue;
+            }
+        };
+
+        for params in params.into_iter().filter(|p| p.params.is_empty()) {
+            missing_configs.insert(params.config_name);
+        }
+    }
+
+    for config_name in missing_configs {
+        diagnostics.push(match config_name {
+            None => Diagnostic::error(
+                "processing.kafka_config",
+                "required when processing is enabled",
+            ),
+            Some(name) => Diagnostic::error(
+                format!("processing.secondary_kafka_configs.{name}"),
+                "must not be empty",
+            ),
+        });
+    }
+
+    if let Some(registry) = config.kafka_schema_registry() {
+        if registry.refresh_interval == 0 {
+            diagnostics.push(Diagnostic::error(
+                "processing.schema_registry.refresh_interval",
+                "must be greater than zero",
+            ));
+        }
+    }
+
+    if config.redis().is_none() {
+        diagnostics.push(Diagnostic::warning(
+            "processing.redis",
+            "rate limits and project config caching are disabled without redis",
+        ));
+    }
+
+    if config.redis_key_prefix().contains(['{', '}']) {
+        diagnostics.push(Diagnostic::error(
+            "processing.redis_key_prefix",
+            "must not contain braces",
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize)]
+    #[allow(dead_code)]
+    struct Inner {
+        port: u16,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[allow(dead_code)]
+    struct Outer {
+        relay: Option<Inner>,
+        #[serde(default)]
+        items: Vec<Inner>,
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let document = serde_json::json!({
+            "relay": {"port": 3000, "prot": 3001},
+            "items": [{"port": 1}, {"port": 2, "extra": true}],
+            "unknown": {},
+        });
+
+        let mut diagnostics = Vec::new();
+        let value = deserialize_document::<Outer>(document, &mut diagnostics);
+        assert!(value.is_some());
+
+        let paths: Vec<_> = diagnostics.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, ["relay.prot", "items.1.extra", "unknown"]);
+        assert!(diagnostics.iter().all(Diagnostic::is_error));
+    }
+
+    #[test]
+    fn test_type_error() {
+        let document = serde_json::json!({
+            "items": [{"port": 1}, {"port": "invalid"}],
+        });
+
+        let mut diagnostics = Vec::new();
+        let value = deserialize_document::<Outer>(document, &mut diagnostics);
+        assert!(value.is_none());
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, "items.1.port");
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error: items.1.port: invalid type: string \"invalid\", expected u16"
+        );
+    }
+}
@@ -53,6 +53,7 @@ pub struct CrashHandler<'a> {
     transport: Option<Transport>,
     release: Option<&'a str>,
     environment: Option<&'a str>,
+    attachments: Vec<&'a str>,
 }
 
 impl<'a> fmt::Debug for CrashHandler<'a> {
@@ -68,6 +69,7 @@ impl<'a> fmt::Debug for CrashHandler<'a> {
             .field("transport", &format_args!("{transport}"))
             .field("release", &self.release)
             .field("environment", &self.environment)
+            .field("attachments", &self.attachments)
             .finish()
     }
 }
@@ -83,6 +85,7 @@ impl<'a> CrashHandler<'a> {
             transport: None,
             release: None,
             environment: None,
+            attachments: Vec::new(),
         }
     }
 
@@ -112,6 +115,15 @@ impl<'a> CrashHandler<'a> {
         self
     }
 
+    /// Adds a file that is attached to crash reports.
+    ///
+    /// The file is read when the crash occurs, so it can be updated while the process is running.
+    /// Panics if there are non UTF-8 characters in the path.
+    pub fn attachment(&mut self, path: &'a Path) -> &mut Self {
+        self.attachments.push(path.to_str().unwrap());
+        self
+    }
+
     /// Installs the crash handler in the process if a Sentry DSN is set.
     #[cfg(unix)]
     pub fn install(&self) {
@@ -136,6 +148,11 @@ impl<'a> CrashHandler<'a> {
                 native::sentry_options_set_environment(options, env_cstr.as_ptr());
             }
 
+            for attachment in &self.attachments {
+                let path_cstr = CString::new(*attachment).unwrap();
+                native::sentry_options_add_attachment(options, path_cstr.as_ptr());
+            }
+
             if let Some(f) = self.transport {
                 let tx = native::sentry_new_function_transport(Some(transport_proxy), f as _);
                 native::sentry_options_set_transport(options, tx);
@@ -11,6 +11,11 @@ pub enum Feature {
     /// Enables data scrubbing of replay recording payloads.
     #[serde(rename = "organizations:session-replay-recording-scrubbing")]
     SessionReplayRecordingScrubbing,
+    /// Enables ingestion of mobile replay videos.
+    ///
+    /// Requires [`Feature::SessionReplay`] in addition.
+    #[serde(rename = "organizations:session-replay-video")]
+    SessionReplayVideo,
     /// Enables device.class synthesis
     ///
     /// Enables device.class tag synthesis on mobile events.
@@ -25,6 +30,14 @@ pub enum Feature {
     /// Enable extracting spans for all modules.
     #[serde(rename = "projects:span-metrics-extraction-all-modules")]
     SpanMetricsExtractionAllModules,
+    /// Enables ingestion of spans sent outside of a transaction.
+    #[serde(rename = "organizations:standalone-span-ingestion")]
+    StandaloneSpanIngestion,
+    /// Enables storing logs sent in standalone `log` envelope items.
+    ///
+    /// Without this feature, metrics are still extracted from logs, but the logs are dropped.
+    #[serde(rename = "organizations:ourlogs-ingestion")]
+    OurLogsIngestion,
 
     /// Deprecated, still forwarded for older downstream Relays.
     #[serde(rename = "organizations:transaction-name-mark-scrubbed-as-sanitized")]
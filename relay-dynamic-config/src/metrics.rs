@@ -130,6 +130,51 @@ impl TransactionMetricsConfig {
     }
 }
 
+/// Local overrides for the transaction metrics extraction config provided by the upstream.
+///
+/// Overrides are configured in the static Relay config and applied when metrics are extracted from
+/// a transaction. They only apply to projects that have transaction metrics extraction enabled, and
+/// they are not part of project configs forwarded to downstream Relays.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransactionMetricsOverrides {
+    /// Additional event tags that are transferred from the transaction to metrics.
+    pub extract_custom_tags: BTreeSet<String>,
+    /// MRIs of transaction metrics that are not extracted.
+    ///
+    /// This does not apply to metrics required for dynamic sampling, which are always extracted.
+    pub disabled_metrics: BTreeSet<String>,
+    /// Additional rules for setting tags on transaction metrics depending on the event's content.
+    ///
+    /// Rules use the same format as `metricConditionalTagging` in project configs.
+    pub conditional_tagging: Vec<TaggingRule>,
+}
+
+impl TransactionMetricsOverrides {
+    /// Returns `true` if no overrides are configured.
+    pub fn is_empty(&self) -> bool {
+        self.extract_custom_tags.is_empty()
+            && self.disabled_metrics.is_empty()
+            && self.conditional_tagging.is_empty()
+    }
+
+    /// Returns `true` if the metric with the given MRI should be extracted.
+    pub fn is_metric_enabled(&self, mri: &str) -> bool {
+        !self.disabled_metrics.contains(mri)
+    }
+
+    /// Converts the conditional tagging rules into tag mappings for metrics extraction.
+    ///
+    /// This is computed once when the config is loaded, since it clones all rules.
+    pub fn tag_mappings(&self) -> Vec<TagMapping> {
+        TaggingRuleConverter {
+            rules: self.conditional_tagging.iter().cloned().peekable(),
+            tags: Vec::new(),
+        }
+        .collect()
+    }
+}
+
 /// Configuration for generic extraction of metrics from all data categories.
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -431,4 +476,31 @@ mod tests {
         let mapping: TagMapping = serde_json::from_str(json).unwrap();
         assert!(mapping.metrics[0].compiled().is_match("d:spans/foo"));
     }
+
+    #[test]
+    fn test_transaction_metrics_overrides() {
+        let overrides: TransactionMetricsOverrides = serde_json::from_value(serde_json::json!({
+            "disabled_metrics": ["s:transactions/user@none"],
+            "conditional_tagging": [{
+                "condition": {"op": "gte", "name": "event.duration", "value": 1000},
+                "targetMetrics": ["d:transactions/duration@millisecond"],
+                "targetTag": "satisfaction",
+                "tagValue": "frustrated"
+            }, {
+                "condition": {"op": "lt", "name": "event.duration", "value": 1000},
+                "targetMetrics": ["d:transactions/duration@millisecond"],
+                "targetTag": "satisfaction",
+                "tagValue": "satisfied"
+            }]
+        }))
+        .unwrap();
+
+        assert!(!overrides.is_metric_enabled("s:transactions/user@none"));
+        assert!(overrides.is_metric_enabled("d:transactions/duration@millisecond"));
+
+        // Consecutive rules for the same metrics are merged into a single mapping.
+        let mappings = overrides.tag_mappings();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].tags.len(), 2);
+    }
 }
@@ -21,6 +21,7 @@ mod clock_drift;
 mod event_error;
 mod geo;
 mod legacy;
+pub mod log;
 mod normalize;
 mod regexes;
 mod remove_other;
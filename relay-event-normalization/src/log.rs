@@ -0,0 +1,135 @@
+//! Validation and normalization of [`Log`] records.
+
+use relay_event_schema::processor::{self, ProcessingState};
+use relay_event_schema::protocol::Log;
+use relay_protocol::{Annotated, Value};
+
+use crate::schema::SchemaProcessor;
+
+/// Log validation error.
+///
+/// This error is returned from [`validate`].
+#[derive(Debug, thiserror::Error)]
+pub enum LogError {
+    /// The log was parsed but did not contain any data.
+    #[error("no data found")]
+    NoContent,
+
+    /// The log contains invalid data or is missing a required field.
+    #[error("invalid payload {0}")]
+    InvalidPayload(String),
+}
+
+/// Checks the log against its schema.
+///
+/// Trims strings, removes empty values, and returns `Err(LogError::InvalidPayload)` if a required
+/// field such as the timestamp is missing.
+pub fn validate(log: &mut Annotated<Log>) -> Result<(), LogError> {
+    processor::process_value(log, &mut SchemaProcessor, ProcessingState::root())
+        .map_err(|error| LogError::InvalidPayload(error.to_string()))?;
+
+    let log = log.value().ok_or(LogError::NoContent)?;
+    if log.timestamp.value().is_none() {
+        return Err(LogError::InvalidPayload("missing timestamp".to_owned()));
+    }
+
+    Ok(())
+}
+
+/// Normalizes the severity and attributes of a log into their standard representation.
+pub fn normalize(log: &mut Log) {
+    normalize_severity(log);
+    normalize_attributes(log);
+}
+
+/// Lowercases the severity and maps common aliases to the levels used by Sentry.
+///
+/// Logs without a severity default to `info`.
+fn normalize_severity(log: &mut Log) {
+    let severity = log.severity.get_or_insert_with(|| "info".to_owned());
+    let normalized = match severity.to_lowercase().as_str() {
+        "warn" => "warning".to_owned(),
+        "err" => "error".to_owned(),
+        "critical" | "crit" | "alert" | "emergency" => "fatal".to_owned(),
+        "notice" => "info".to_owned(),
+        lowercase => lowercase.to_owned(),
+    };
+    *severity = normalized;
+}
+
+/// Trims attribute keys and removes attributes without a key or value.
+fn normalize_attributes(log: &mut Log) {
+    let Some(attributes) = log.attributes.value_mut() else {
+        return;
+    };
+
+    *attributes = std::mem::take(attributes)
+        .into_iter()
+        .filter(|(_, value)| !matches!(value.value(), None | Some(Value::Null)))
+        .map(|(key, value)| (key.trim().to_owned(), value))
+        .filter(|(key, _)| !key.is_empty())
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_protocol::get_value;
+
+    use super::*;
+
+    #[test]
+    fn test_validate_missing_timestamp() {
+        let mut log = Annotated::<Log>::from_json(r#"{"body": "hello"}"#).unwrap();
+        assert!(matches!(
+            validate(&mut log),
+            Err(LogError::InvalidPayload(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_trims_body() {
+        let json = r#"{"timestamp": 1694732408.3145, "body": "  hello  "}"#;
+        let mut log = Annotated::<Log>::from_json(json).unwrap();
+
+        validate(&mut log).unwrap();
+        assert_eq!(get_value!(log.body!), "hello");
+    }
+
+    #[test]
+    fn test_normalize_severity() {
+        for (severity, expected) in [
+            (Some("WARN"), "warning"),
+            (Some("Err"), "error"),
+            (Some("critical"), "fatal"),
+            (Some("debug"), "debug"),
+            (None, "info"),
+        ] {
+            let mut log = Log {
+                severity: Annotated::from(severity.map(str::to_owned)),
+                ..Default::default()
+            };
+            normalize(&mut log);
+            assert_eq!(log.severity.as_str(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_normalize_attributes() {
+        let json = r#"{
+            "timestamp": 1694732408.3145,
+            "attributes": {
+                " duration ": 12.5,
+                "empty": null,
+                " ": "no key"
+            }
+        }"#;
+        let mut log = Annotated::<Log>::from_json(json).unwrap();
+        normalize(log.value_mut().as_mut().unwrap());
+
+        let attributes = get_value!(log.attributes!);
+        assert_eq!(
+            attributes.keys().collect::<Vec<_>>(),
+            vec![&"duration".to_owned()]
+        );
+    }
+}
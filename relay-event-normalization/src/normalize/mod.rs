@@ -768,6 +768,49 @@ pub fn normalize_ip_addresses(
     }
 }
 
+/// Returns the canonical form of an IP address, optionally truncating IPv6 addresses.
+///
+/// IPv4-mapped IPv6 addresses such as `::ffff:1.2.3.4` are converted to IPv4. If `ipv6_prefix` is
+/// set, all bits of IPv6 addresses after the prefix are cleared, so that all addresses of a network
+/// map to the same value. Mobile carriers commonly assign a `/64` network to every device and
+/// rotate the host bits, which makes a single user appear as many distinct IP addresses.
+pub fn normalize_ip(ip: std::net::IpAddr, ipv6_prefix: Option<u8>) -> std::net::IpAddr {
+    let ip = match ip {
+        std::net::IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => return std::net::IpAddr::V4(v4),
+            None => v6,
+        },
+        v4 => return v4,
+    };
+
+    let Some(prefix) = ipv6_prefix else {
+        return std::net::IpAddr::V6(ip);
+    };
+
+    let mask = u128::MAX
+        .checked_shl(128 - u32::from(prefix.min(128)))
+        .unwrap_or(0);
+    std::net::IpAddr::V6((u128::from(ip) & mask).into())
+}
+
+/// Replaces the IP address of the user with its canonical form.
+///
+/// See [`normalize_ip`]. Values that are not valid IP addresses, such as `{{auto}}`, are left
+/// unchanged.
+fn normalize_user_ip(user: &mut Annotated<User>, ipv6_prefix: Option<u8>) {
+    let Some(ip_address) = user
+        .value_mut()
+        .as_mut()
+        .and_then(|user| user.ip_address.value_mut().as_mut())
+    else {
+        return;
+    };
+
+    if let Ok(ip) = ip_address.as_str().parse() {
+        *ip_address = IpAddr(normalize_ip(ip, ipv6_prefix).to_string());
+    }
+}
+
 fn normalize_logentry(logentry: &mut Annotated<LogEntry>, _meta: &mut Meta) -> ProcessingResult {
     processor::apply(logentry, logentry::normalize_logentry)
 }
@@ -823,6 +866,12 @@ pub struct LightNormalizationConfig<'a> {
     /// `request` context, this IP address gets added to the `user` context.
     pub client_ip: Option<&'a IpAddr>,
 
+    /// The length of the network prefix to which IPv6 addresses are truncated.
+    ///
+    /// The IP address of the user is converted to its canonical form and, if set, truncated to the
+    /// prefix. See [`normalize_ip`].
+    pub ipv6_prefix: Option<u8>,
+
     /// The user-agent and client hints obtained from the submission request headers.
     ///
     /// Client hints are the preferred way to infer device, operating system, and browser
@@ -913,6 +962,7 @@ impl Default for LightNormalizationConfig<'_> {
     fn default() -> Self {
         Self {
             client_ip: Default::default(),
+            ipv6_prefix: Default::default(),
             user_agent: Default::default(),
             received_at: Default::default(),
             max_secs_in_past: Default::default(),
@@ -1031,6 +1081,7 @@ pub fn light_normalize_event(
             event.platform.as_str(),
             config.client_ip,
         );
+        normalize_user_ip(&mut event.user, config.ipv6_prefix);
 
         if let Some(geoip_lookup) = config.geoip_lookup {
             if let Some(user) = event.user.value_mut() {
@@ -1763,6 +1814,49 @@ mod tests {
         assert!(user.geo.value().is_none());
     }
 
+    #[test]
+    fn test_normalize_ip() {
+        let ip = |s: &str| s.parse::<std::net::IpAddr>().unwrap();
+
+        assert_eq!(normalize_ip(ip("1.2.3.4"), Some(64)), ip("1.2.3.4"));
+        assert_eq!(normalize_ip(ip("::ffff:1.2.3.4"), None), ip("1.2.3.4"));
+        assert_eq!(
+            normalize_ip(ip("2001:DB8:0:0:1:0:0:1"), None).to_string(),
+            "2001:db8::1:0:0:1"
+        );
+        assert_eq!(
+            normalize_ip(ip("2001:db8:1:2:3:4:5:6"), Some(64)),
+            ip("2001:db8:1:2::")
+        );
+        assert_eq!(normalize_ip(ip("2001:db8::1"), Some(0)), ip("::"));
+        assert_eq!(
+            normalize_ip(ip("2001:db8::1"), Some(128)),
+            ip("2001:db8::1")
+        );
+    }
+
+    #[test]
+    fn test_user_ip_normalized() {
+        let mut event = Annotated::new(Event {
+            user: Annotated::new(User {
+                ip_address: Annotated::new(IpAddr::auto()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let ip_address = IpAddr::parse("2001:db8:1:2:3:4:5:6").unwrap();
+        let config = LightNormalizationConfig {
+            client_ip: Some(&ip_address),
+            ipv6_prefix: Some(64),
+            ..Default::default()
+        };
+        light_normalize_event(&mut event, config).unwrap();
+
+        let ip_addr = get_value!(event.user.ip_address!);
+        assert_eq!(ip_addr, &IpAddr("2001:db8:1:2::".to_string()));
+    }
+
     #[test]
     fn test_event_level_defaulted() {
         let processor = &mut NormalizeProcessor::default();
@@ -19,7 +19,7 @@ use crate::transactions::SpanDescriptionRule;
 ///
 /// The resulting scrubbed description is stored in `data.description.scrubbed`, and serves as input
 /// for the span group hash.
-pub(crate) fn scrub_span_description(span: &mut Span, rules: &Vec<SpanDescriptionRule>) {
+pub(crate) fn scrub_span_description(span: &mut Span, rules: &[SpanDescriptionRule]) {
     let Some(description) = span.description.as_str() else {
         return;
     };
@@ -139,7 +139,7 @@ fn scrub_resource_identifiers(string: &str) -> Option<String> {
 /// For now, rules are only generated from transaction names, and the
 /// scrubbed value is stored in `span.data[description.scrubbed]` instead of
 /// `span.description` (which remains intact).
-fn apply_span_rename_rules(span: &mut Span, rules: &Vec<SpanDescriptionRule>) -> ProcessingResult {
+fn apply_span_rename_rules(span: &mut Span, rules: &[SpanDescriptionRule]) -> ProcessingResult {
     if let Some(op) = span.op.value() {
         if !op.starts_with("http") {
             return Ok(());
@@ -2,4 +2,5 @@
 
 pub mod attributes;
 pub mod description;
+pub mod standalone;
 pub mod tag_extraction;
@@ -0,0 +1,142 @@
+//! Normalization of individual spans outside of a transaction.
+
+use relay_event_schema::protocol::{Span, SpanStatus};
+use relay_protocol::Annotated;
+
+use crate::span::description::scrub_span_description;
+use crate::span::tag_extraction::{self, extract_tags};
+use crate::utils::http_status_code_from_span;
+use crate::SpanDescriptionRule;
+
+/// Configuration for [`normalize_standalone_span`].
+#[derive(Clone, Debug)]
+pub struct StandaloneSpanConfig<'a> {
+    /// The maximum length of extracted tag values in bytes. Longer values are cropped.
+    pub max_tag_value_length: usize,
+    /// Rules to scrub identifiers from span descriptions.
+    pub span_description_rules: &'a [SpanDescriptionRule],
+}
+
+impl Default for StandaloneSpanConfig<'_> {
+    fn default() -> Self {
+        Self {
+            max_tag_value_length: usize::MAX,
+            span_description_rules: &[],
+        }
+    }
+}
+
+/// Normalizes a single span as if it was part of a transaction.
+///
+/// This applies the subset of transaction normalization that does not depend on the containing
+/// event:
+///
+///  - Defaults the span operation to `"default"`.
+///  - Infers a missing status from the HTTP status code of the span.
+///  - Scrubs identifiers from the description into `data["description.scrubbed"]`.
+///  - Extracts span tags into [`Span::data`], excluding tags shared with the transaction.
+pub fn normalize_standalone_span(span: &mut Span, config: &StandaloneSpanConfig<'_>) {
+    span.op.get_or_insert_with(|| "default".to_owned());
+
+    if span.status.value().is_none() {
+        let status = http_status_code_from_span(span)
+            .and_then(|code| code.parse().ok())
+            .map(span_status_from_http_status_code);
+
+        if let Some(status) = status {
+            span.status.set_value(Some(status));
+        }
+    }
+
+    scrub_span_description(span, config.span_description_rules);
+
+    let tags = extract_tags(
+        span,
+        &tag_extraction::Config {
+            max_tag_value_size: config.max_tag_value_length,
+        },
+    );
+
+    let data = span.data.value_mut().get_or_insert_with(Default::default);
+    data.extend(
+        tags.into_iter()
+            .map(|(k, v)| (k.to_string(), Annotated::new(v))),
+    );
+}
+
+/// Maps an HTTP status code to a span status, following the conventions of Sentry SDKs.
+fn span_status_from_http_status_code(code: u16) -> SpanStatus {
+    match code {
+        100..=399 => SpanStatus::Ok,
+        401 => SpanStatus::Unauthenticated,
+        403 => SpanStatus::PermissionDenied,
+        404 => SpanStatus::NotFound,
+        409 => SpanStatus::AlreadyExists,
+        413 => SpanStatus::FailedPrecondition,
+        429 => SpanStatus::ResourceExhausted,
+        400..=499 => SpanStatus::InvalidArgument,
+        501 => SpanStatus::Unimplemented,
+        503 => SpanStatus::Unavailable,
+        504 => SpanStatus::DeadlineExceeded,
+        500..=599 => SpanStatus::InternalError,
+        _ => SpanStatus::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_protocol::{get_value, Value};
+
+    use super::*;
+
+    #[test]
+    fn test_normalize_http_span() {
+        let json = r#"{
+            "op": "http.client",
+            "description": "GET https://example.com/users/123",
+            "data": {"status_code": "404", "http.method": "get"}
+        }"#;
+
+        let mut span = Annotated::<Span>::from_json(json).unwrap();
+        normalize_standalone_span(span.value_mut().as_mut().unwrap(), &Default::default());
+
+        assert_eq!(get_value!(span.status!), &SpanStatus::NotFound);
+        let data = get_value!(span.data!);
+        let data = |key: &str| {
+            data.get(key)
+                .and_then(|v| v.value())
+                .and_then(Value::as_str)
+        };
+        assert_eq!(
+            data("description.scrubbed"),
+            Some("GET https://example.com")
+        );
+        assert_eq!(data("span.module"), Some("http"));
+        assert_eq!(data("span.action"), Some("GET"));
+    }
+
+    #[test]
+    fn test_normalize_keeps_status() {
+        let json = r#"{"status": "cancelled", "data": {"status_code": "200"}}"#;
+
+        let mut span = Annotated::<Span>::from_json(json).unwrap();
+        normalize_standalone_span(span.value_mut().as_mut().unwrap(), &Default::default());
+
+        assert_eq!(get_value!(span.status!), &SpanStatus::Cancelled);
+        assert_eq!(get_value!(span.op!), "default");
+    }
+
+    #[test]
+    fn test_status_from_http_status_code() {
+        assert_eq!(span_status_from_http_status_code(204), SpanStatus::Ok);
+        assert_eq!(
+            span_status_from_http_status_code(418),
+            SpanStatus::InvalidArgument
+        );
+        assert_eq!(
+            span_status_from_http_status_code(502),
+            SpanStatus::InternalError
+        );
+        assert_eq!(span_status_from_http_status_code(600), SpanStatus::Unknown);
+    }
+}
@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use relay_event_schema::protocol::Event;
+use relay_protocol::Annotated;
+use serde_json::json;
+
+/// Creates a transaction payload with the given number of spans.
+fn transaction(spans: usize) -> Vec<u8> {
+    let spans: Vec<_> = (0..spans)
+        .map(|i| {
+            json!({
+                "op": "db.sql.query",
+                "description": format!("SELECT * FROM users WHERE id = {i}"),
+                "span_id": format!("{i:016x}"),
+                "parent_span_id": "fa90fdead5f74052",
+                "trace_id": "4c79f60c11214eb38604f4ae0781bfb2",
+                "start_timestamp": 1597976300.0000000,
+                "timestamp": 1597976302.0000000,
+                "data": {"db.system": "postgresql", "db.operation": "SELECT"},
+            })
+        })
+        .collect();
+
+    let event = json!({
+        "type": "transaction",
+        "transaction": "/api/users/{id}",
+        "start_timestamp": 1597976300.0000000,
+        "timestamp": 1597976302.0000000,
+        "contexts": {
+            "trace": {
+                "trace_id": "4c79f60c11214eb38604f4ae0781bfb2",
+                "span_id": "fa90fdead5f74052",
+                "op": "http.server",
+                "status": "ok",
+            }
+        },
+        "spans": spans,
+    });
+
+    serde_json::to_vec(&event).unwrap()
+}
+
+fn bench_parse_event(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_event");
+
+    for spans in [0, 100, 1000] {
+        let payload = transaction(spans);
+        group.throughput(Throughput::Bytes(payload.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("serde_json", spans), &payload, |b, p| {
+            b.iter(|| Annotated::<Event>::from_json_bytes(p).unwrap());
+        });
+
+        group.bench_with_input(BenchmarkId::new("simd_json", spans), &payload, |b, p| {
+            b.iter(|| Annotated::<Event>::from_json_bytes_simd(p).unwrap());
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse_event);
+criterion_main!(benches);
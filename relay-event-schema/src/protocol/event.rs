@@ -4,7 +4,9 @@ use std::str::FromStr;
 use relay_common::time;
 #[cfg(feature = "jsonschema")]
 use relay_jsonschema_derive::JsonSchema;
-use relay_protocol::{Annotated, Array, Empty, FromValue, Getter, IntoValue, Object, Val, Value};
+use relay_protocol::{
+    Annotated, Array, Empty, FromValue, Getter, GetterIter, IntoValue, Object, Val, Value,
+};
 #[cfg(feature = "jsonschema")]
 use schemars::{gen::SchemaGenerator, schema::Schema};
 use sentry_release_parser::Release as ParsedRelease;
@@ -705,6 +707,13 @@ impl Getter for Event {
             }
         })
     }
+
+    fn get_iter(&self, path: &str) -> Option<GetterIter<'_>> {
+        Some(match path.strip_prefix("event.")? {
+            "spans" => GetterIter::new_annotated(self.spans.value()?),
+            _ => return None,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -1084,4 +1093,38 @@ mod tests {
         assert_eq!(None, event.get_value("event.user.segment"));
         assert_eq!(None, event.get_value("event.transaction"));
     }
+
+    #[test]
+    fn test_field_iter_provider_spans() {
+        let event = Event {
+            spans: Annotated::new(vec![
+                Annotated::new(Span {
+                    op: Annotated::new("db.redis".to_owned()),
+                    ..Default::default()
+                }),
+                Annotated::empty(),
+                Annotated::new(Span {
+                    op: Annotated::new("http.client".to_owned()),
+                    ..Default::default()
+                }),
+            ]),
+            ..Default::default()
+        };
+
+        let ops: Vec<_> = event
+            .get_iter("event.spans")
+            .unwrap()
+            .map(|span| span.get_value("span.op"))
+            .collect();
+
+        assert_eq!(
+            ops,
+            vec![
+                Some(Val::String("db.redis")),
+                Some(Val::String("http.client"))
+            ]
+        );
+        assert!(event.get_iter("event.breadcrumbs").is_none());
+        assert!(Event::default().get_iter("event.spans").is_none());
+    }
 }
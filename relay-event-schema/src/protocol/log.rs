@@ -0,0 +1,99 @@
+#[cfg(feature = "jsonschema")]
+use relay_jsonschema_derive::JsonSchema;
+use relay_protocol::{Annotated, Empty, FromValue, Getter, IntoValue, Object, Val, Value};
+
+use crate::processor::ProcessValue;
+use crate::protocol::{Timestamp, TraceId};
+
+/// A log record sent in a standalone `log` envelope item.
+#[derive(Clone, Debug, Default, PartialEq, Empty, FromValue, IntoValue, ProcessValue)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct Log {
+    /// Timestamp when the log was recorded.
+    #[metastructure(required = "true")]
+    pub timestamp: Annotated<Timestamp>,
+
+    /// The severity of the log, such as `info` or `error`.
+    #[metastructure(max_chars = "enumlike")]
+    pub severity: Annotated<String>,
+
+    /// The log message.
+    #[metastructure(pii = "true")]
+    pub body: Annotated<String>,
+
+    /// The ID of the trace the log was recorded in.
+    pub trace_id: Annotated<TraceId>,
+
+    /// Arbitrary structured attributes of the log.
+    #[metastructure(pii = "true")]
+    pub attributes: Annotated<Object<Value>>,
+
+    /// Additional arbitrary fields for forwards compatibility.
+    #[metastructure(additional_properties, retain = "true", pii = "maybe")]
+    pub other: Object<Value>,
+}
+
+impl Getter for Log {
+    fn get_value(&self, path: &str) -> Option<Val<'_>> {
+        Some(match path.strip_prefix("log.")? {
+            "severity" => self.severity.as_str()?.into(),
+            "body" => self.body.as_str()?.into(),
+            "trace_id" => self.trace_id.as_str()?.into(),
+            path => {
+                let key = path.strip_prefix("attributes.")?;
+                let escaped = key.replace("\\.", "\0");
+                let mut path = escaped.split('.').map(|s| s.replace('\0', "."));
+                let root = path.next()?;
+
+                let mut val = self.attributes.value()?.get(&root)?.value()?;
+                for part in path {
+                    // While there is path segments left, `val` has to be an Object.
+                    let Value::Object(map) = val else {
+                        return None;
+                    };
+                    val = map.get(&part)?.value()?;
+                }
+                val.into()
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_getter() {
+        let json = r#"{
+            "timestamp": 1694732408.3145,
+            "severity": "error",
+            "body": "request failed",
+            "attributes": {
+                "duration": 12.5,
+                "http": {"status_code": 500},
+                "user.id": "42"
+            }
+        }"#;
+        let log = Annotated::<Log>::from_json(json)
+            .unwrap()
+            .into_value()
+            .unwrap();
+
+        assert_eq!(log.get_value("log.severity"), Some(Val::String("error")));
+        assert_eq!(
+            log.get_value("log.attributes.duration"),
+            Some(Val::F64(12.5))
+        );
+        assert_eq!(
+            log.get_value("log.attributes.http.status_code"),
+            Some(Val::I64(500))
+        );
+        assert_eq!(
+            log.get_value("log.attributes.user\\.id"),
+            Some(Val::String("42"))
+        );
+        assert_eq!(log.get_value("log.attributes.missing"), None);
+        assert_eq!(log.get_value("span.op"), None);
+    }
+}
@@ -12,6 +12,7 @@ mod device_class;
 mod event;
 mod exception;
 mod fingerprint;
+mod log;
 mod logentry;
 mod measurements;
 mod mechanism;
@@ -48,6 +49,7 @@ pub use self::device_class::*;
 pub use self::event::*;
 pub use self::exception::*;
 pub use self::fingerprint::*;
+pub use self::log::*;
 pub use self::logentry::*;
 pub use self::measurements::*;
 pub use self::mechanism::*;
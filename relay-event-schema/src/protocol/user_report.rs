@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::protocol::utils::null_to_default;
 use crate::protocol::EventId;
@@ -30,3 +31,98 @@ pub struct UserReport {
     #[serde(default, deserialize_with = "null_to_default")]
     pub comments: String,
 }
+
+/// User feedback as sent in `feedback` envelope items.
+///
+/// This is the format of the feedback product. Legacy [`UserReport`]s can be converted into this
+/// format, so that Sentry only has to handle one format.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Feedback {
+    /// Unique identifier of this feedback.
+    pub event_id: EventId,
+    /// Unix timestamp in seconds at which the feedback was submitted.
+    pub timestamp: f64,
+    /// Contexts of the feedback, which carry the submitted feedback.
+    pub contexts: FeedbackContexts,
+}
+
+/// Contexts of [`Feedback`].
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FeedbackContexts {
+    /// The feedback submitted by the user.
+    pub feedback: FeedbackContext,
+}
+
+/// The feedback submitted by a user.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FeedbackContext {
+    /// The message written by the user.
+    pub message: String,
+    /// The user's email address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact_email: Option<String>,
+    /// The user's name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The event for which this feedback was submitted, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub associated_event_id: Option<EventId>,
+}
+
+impl Feedback {
+    /// Converts a legacy user report into feedback submitted at the given Unix timestamp.
+    ///
+    /// The event of the user report becomes the associated event of the feedback. The ID of the
+    /// feedback is derived from that event, so that converting the same report twice yields the
+    /// same feedback. Empty names and email addresses are omitted.
+    pub fn from_user_report(report: UserReport, timestamp: f64) -> Self {
+        let non_empty = |value: String| (!value.is_empty()).then_some(value);
+
+        Self {
+            event_id: EventId(Uuid::new_v5(&report.event_id.0, b"feedback")),
+            timestamp,
+            contexts: FeedbackContexts {
+                feedback: FeedbackContext {
+                    message: report.comments,
+                    contact_email: non_empty(report.email),
+                    name: non_empty(report.name),
+                    associated_event_id: Some(report.event_id),
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feedback_from_user_report() {
+        let json = r#"{
+            "event_id": "52df9022835246eeb317dbd739ccd059",
+            "name": "",
+            "email": "jane@example.org",
+            "comments": "It broke."
+        }"#;
+        let report = serde_json::from_str::<UserReport>(json).unwrap();
+        let feedback = Feedback::from_user_report(report, 1700000000.5);
+
+        let value = serde_json::to_value(&feedback).unwrap();
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "event_id": "578830d62e5c5b1a90ca7d628fffeb66",
+                "timestamp": 1700000000.5,
+                "contexts": {
+                    "feedback": {
+                        "message": "It broke.",
+                        "contact_email": "jane@example.org",
+                        "associated_event_id": "52df9022835246eeb317dbd739ccd059"
+                    }
+                }
+            })
+        );
+    }
+}
@@ -106,6 +106,7 @@
 )]
 #![allow(clippy::derive_partial_eq_without_eq)]
 
+use std::backtrace::{Backtrace, BacktraceStatus};
 use std::cell::RefCell;
 use std::error::Error;
 use std::{fmt, panic, thread};
@@ -228,7 +229,10 @@ pub fn take_last_error() -> Option<anyhow::Error> {
 /// });
 /// ```
 #[derive(Debug)]
-pub struct Panic(String);
+pub struct Panic {
+    description: String,
+    backtrace: Backtrace,
+}
 
 impl Panic {
     fn new(info: &panic::PanicInfo) -> Self {
@@ -252,13 +256,27 @@ impl Panic {
             None => format!("thread '{thread}' panicked with '{message}'"),
         };
 
-        Self(description)
+        Self {
+            description,
+            backtrace: Backtrace::capture(),
+        }
     }
 
     /// Returns a description containing the location and message of the panic.
     #[inline]
     pub fn description(&self) -> &str {
-        &self.0
+        &self.description
+    }
+
+    /// Returns the backtrace of the panicking thread, if it was captured.
+    ///
+    /// Backtraces are only captured if enabled through the `RUST_BACKTRACE` or
+    /// `RUST_LIB_BACKTRACE` environment variables.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self.backtrace.status() {
+            BacktraceStatus::Captured => Some(&self.backtrace),
+            _ => None,
+        }
     }
 }
 
@@ -5,9 +5,14 @@
 //! - [`TopicAssignment::Secondary`] - used to configure any additional kafka topic,
 //! - [`TopicAssignment::Sharded`] - if we want to configure multiple kafka clusters,
 //! we can create a mapping of the range of logical shards to the kafka configuration.
+//! - [`TopicAssignment::Clustered`] - distributes organizations across several kafka clusters
+//! with consistent hashing, optionally failing over to healthy clusters.
 
 use std::collections::BTreeMap;
+use std::path::PathBuf;
 
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -20,10 +25,24 @@ pub enum ConfigError {
     /// The user did not configure 0 shard
     #[error("invalid kafka shard configuration: must have shard with index 0")]
     InvalidShard,
+    /// The user did not configure any cluster for a clustered topic.
+    #[error("invalid kafka cluster configuration: must have at least one cluster")]
+    NoClusters,
+    /// The subject name strategy requires a record name, but none was configured.
+    #[error("missing record name for schema subject of topic {0}")]
+    MissingRecordName(String),
+    /// A producer parameter conflicts with idempotent delivery.
+    #[error("kafka config `{0}: {1}` is incompatible with idempotent delivery")]
+    IncompatibleDeliveryParam(String, String),
+    /// Transactional delivery is enabled, but no transactional ID was configured.
+    #[error("transactional kafka delivery requires a transactional id")]
+    MissingTransactionalId,
 }
 
 /// Define the topics over which Relay communicates with Sentry.
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
 pub enum KafkaTopic {
     /// Simple events (without attachments) topic.
     Events,
@@ -43,6 +62,14 @@ pub enum KafkaTopic {
     MetricsGeneric,
     /// Profiles
     Profiles,
+    /// Chunks of continuous profiles.
+    ProfileChunks,
+    /// Logs sent in standalone `log` envelope items.
+    Logs,
+    /// User feedback, including user reports converted to feedback.
+    Feedback,
+    /// Attachments that belong to a trace rather than a single event.
+    TraceAttachments,
     /// ReplayEvents, breadcrumb + session updates for replays
     ReplayEvents,
     /// ReplayRecordings, large blobs sent by the replay sdk
@@ -51,6 +78,10 @@ pub enum KafkaTopic {
     Monitors,
     /// Standalone spans without a transaction.
     Spans,
+    /// Additional metadata for metrics, such as code locations.
+    MetricsMeta,
+    /// Messages that could not be serialized or exceeded the size limit of the brokers.
+    DeadLetters,
 }
 
 impl KafkaTopic {
@@ -58,7 +89,7 @@ impl KafkaTopic {
     /// It will have to be adjusted if the new variants are added.
     pub fn iter() -> std::slice::Iter<'static, Self> {
         use KafkaTopic::*;
-        static TOPICS: [KafkaTopic; 13] = [
+        static TOPICS: [KafkaTopic; 19] = [
             Events,
             Attachments,
             Transactions,
@@ -68,10 +99,16 @@ impl KafkaTopic {
             MetricsSessions,
             MetricsGeneric,
             Profiles,
+            ProfileChunks,
+            Logs,
+            Feedback,
+            TraceAttachments,
             ReplayEvents,
             ReplayRecordings,
             Monitors,
             Spans,
+            MetricsMeta,
+            DeadLetters,
         ];
         TOPICS.iter()
     }
@@ -79,6 +116,7 @@ impl KafkaTopic {
 
 /// Configuration for topics.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 pub struct TopicAssignments {
     /// Simple events topic name.
@@ -103,6 +141,14 @@ pub struct TopicAssignments {
     pub metrics_generic: TopicAssignment,
     /// Stacktrace topic name
     pub profiles: TopicAssignment,
+    /// Continuous profiling chunks topic name.
+    pub profile_chunks: TopicAssignment,
+    /// Logs topic name.
+    pub logs: TopicAssignment,
+    /// Feedback topic name.
+    pub feedback: TopicAssignment,
+    /// Trace attachments topic name.
+    pub trace_attachments: TopicAssignment,
     /// Replay Events topic name.
     pub replay_events: TopicAssignment,
     /// Recordings topic name.
@@ -111,6 +157,10 @@ pub struct TopicAssignments {
     pub monitors: TopicAssignment,
     /// Standalone spans without a transaction.
     pub spans: TopicAssignment,
+    /// Additional metadata for metrics, such as code locations.
+    pub metrics_meta: TopicAssignment,
+    /// Messages that could not be produced to their topic, see `processing.dead_letters`.
+    pub dead_letters: TopicAssignment,
 }
 
 impl TopicAssignments {
@@ -127,10 +177,16 @@ impl TopicAssignments {
             KafkaTopic::MetricsSessions => self.metrics_sessions.as_ref().unwrap_or(&self.metrics),
             KafkaTopic::MetricsGeneric => &self.metrics_generic,
             KafkaTopic::Profiles => &self.profiles,
+            KafkaTopic::ProfileChunks => &self.profile_chunks,
+            KafkaTopic::Logs => &self.logs,
+            KafkaTopic::Feedback => &self.feedback,
+            KafkaTopic::TraceAttachments => &self.trace_attachments,
             KafkaTopic::ReplayEvents => &self.replay_events,
             KafkaTopic::ReplayRecordings => &self.replay_recordings,
             KafkaTopic::Monitors => &self.monitors,
             KafkaTopic::Spans => &self.spans,
+            KafkaTopic::MetricsMeta => &self.metrics_meta,
+            KafkaTopic::DeadLetters => &self.dead_letters,
         }
     }
 }
@@ -148,10 +204,16 @@ impl Default for TopicAssignments {
             metrics_sessions: None,
             metrics_generic: "ingest-performance-metrics".to_owned().into(),
             profiles: "profiles".to_owned().into(),
+            profile_chunks: "ingest-profile-chunks".to_owned().into(),
+            logs: "ingest-logs".to_owned().into(),
+            feedback: "ingest-feedback-events".to_owned().into(),
+            trace_attachments: "ingest-trace-attachments".to_owned().into(),
             replay_events: "ingest-replay-events".to_owned().into(),
             replay_recordings: "ingest-replay-recordings".to_owned().into(),
             monitors: "ingest-monitors".to_owned().into(),
             spans: "ingest-spans".to_owned().into(),
+            metrics_meta: "ingest-metrics-meta".to_owned().into(),
+            dead_letters: "ingest-dead-letters".to_owned().into(),
         }
     }
 }
@@ -164,6 +226,7 @@ impl Default for TopicAssignments {
 ///
 /// See documentation for `secondary_kafka_configs` for more information.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(untagged)]
 pub enum TopicAssignment {
     /// String containing the kafka topic name. In this case the default kafka cluster configured
@@ -176,10 +239,13 @@ pub enum TopicAssignment {
     /// If we want to configure multiple kafka clusters, we can create a mapping of the
     /// range of logical shards to the kafka configuration.
     Sharded(Sharded),
+    /// Distributes organizations across several kafka clusters with consistent hashing.
+    Clustered(Clustered),
 }
 
 /// Configuration for topic
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct KafkaTopicConfig {
     /// The topic name to use.
     #[serde(rename = "name")]
@@ -213,6 +279,7 @@ pub struct KafkaTopicConfig {
 /// index of the shard and the range is last till the next index or the maximum shard defined in
 /// the `shards` option. The first index must always start with 0.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct Sharded {
     /// The number of shards used for this topic.
     shards: u64,
@@ -220,6 +287,35 @@ pub struct Sharded {
     mapping: BTreeMap<u64, KafkaTopicConfig>,
 }
 
+/// Configuration for distributing a topic across several Kafka clusters.
+///
+/// The configuration for this should look like:
+///
+/// ```ignore
+/// transactions:
+///    clusters:
+///      - name: "ingest-transactions"
+///        config: "cluster_1"
+///      - name: "ingest-transactions"
+///        config: "cluster_2"
+///    failover: true
+/// ```
+///
+/// Each organization is assigned to one of the `clusters` with rendezvous hashing on its ID, so
+/// adding or removing a cluster only moves the organizations of that cluster. If `failover` is
+/// enabled, messages are sent to the next cluster of the organization while its assigned cluster
+/// is unavailable. Since this changes the order in which consumers receive messages, only enable
+/// failover for topics that do not require ordering.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct Clustered {
+    /// The Kafka clusters of this topic.
+    clusters: Vec<KafkaTopicConfig>,
+    /// Sends messages to another cluster while the assigned cluster is unavailable.
+    #[serde(default)]
+    failover: bool,
+}
+
 /// Describes Kafka config, with all the parameters extracted, which will be used for creating the
 /// kafka producer.
 #[derive(Debug)]
@@ -237,6 +333,14 @@ pub enum KafkaConfig<'a> {
         /// The list of the sharded Kafka configs.
         configs: BTreeMap<u64, KafkaParams<'a>>,
     },
+
+    /// The list of Kafka clusters that organizations are distributed across.
+    Clustered {
+        /// The Kafka configs of all clusters.
+        clusters: Vec<KafkaParams<'a>>,
+        /// Whether messages are sent to another cluster while the assigned one is unavailable.
+        failover: bool,
+    },
 }
 
 /// Sharded Kafka config.
@@ -307,14 +411,268 @@ impl TopicAssignment {
                     configs: kafka_params,
                 }
             }
+            Self::Clustered(Clustered { clusters, failover }) => {
+                if clusters.is_empty() {
+                    return Err(ConfigError::NoClusters);
+                }
+
+                let clusters = clusters
+                    .iter()
+                    .map(|cluster| {
+                        Ok(KafkaParams {
+                            topic_name: cluster.topic_name.as_str(),
+                            config_name: Some(cluster.kafka_config_name.as_str()),
+                            params: secondary_configs
+                                .get(cluster.kafka_config_name.as_str())
+                                .ok_or(ConfigError::UnknownKafkaConfigName)?,
+                        })
+                    })
+                    .collect::<Result<_, ConfigError>>()?;
+
+                KafkaConfig::Clustered {
+                    clusters,
+                    failover: *failover,
+                }
+            }
         };
 
         Ok(kafka_config)
     }
 }
 
+/// Source of tokens for the `OAUTHBEARER` SASL mechanism.
+///
+/// Tokens are only requested by producers that set `sasl.mechanism` to `OAUTHBEARER` in their
+/// Kafka config. librdkafka requests a new token before the previous one expires.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KafkaOAuthConfig {
+    /// Reads the token from a file on every refresh.
+    ///
+    /// This works with tokens that are rotated by an external process, such as a sidecar or a
+    /// projected service account token.
+    File {
+        /// Path to the file containing the token.
+        path: PathBuf,
+        /// The principal name of the token.
+        principal: String,
+        /// The lifetime of a token read from the file in seconds. Defaults to `300`.
+        #[serde(default = "default_oauth_token_lifetime")]
+        lifetime: u64,
+    },
+    /// Generates tokens for AWS MSK IAM authentication.
+    ///
+    /// Tokens are signed with credentials from the standard AWS credentials chain, the same as
+    /// the AWS store sink: environment variables, web identity tokens, the ECS container
+    /// credentials endpoint, or the EC2 instance profile. Temporary credentials are refreshed
+    /// before they expire.
+    AwsMskIam {
+        /// The AWS region of the MSK cluster, for example `us-east-1`.
+        region: String,
+    },
+}
+
+fn default_oauth_token_lifetime() -> u64 {
+    300
+}
+
+/// Connection to a Confluent Schema Registry and the topics that use registered schemas.
+///
+/// Messages produced to the listed topics are encoded with the latest schema registered for the
+/// topic's subject and framed in the Confluent wire format. All other topics are produced as
+/// before. Schemas are loaded at startup and refreshed periodically afterwards.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct SchemaRegistryConfig {
+    /// The URL of the schema registry, for example `http://schema-registry:8081`.
+    pub url: String,
+    /// The username for basic authentication.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// The password for basic authentication.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// The timeout for requests to the schema registry in seconds. Defaults to `10`.
+    #[serde(default = "default_registry_timeout")]
+    pub timeout: u64,
+    /// The timeout for connecting to the schema registry in seconds. Defaults to `5`.
+    #[serde(default = "default_registry_connect_timeout")]
+    pub connect_timeout: u64,
+    /// The interval at which the latest schemas are fetched again in seconds. Defaults to `300`.
+    ///
+    /// If a refresh fails, the previously loaded schemas remain in use.
+    #[serde(default = "default_registry_refresh_interval")]
+    pub refresh_interval: u64,
+    /// Schema settings by Kafka topic name.
+    #[serde(default)]
+    pub topics: BTreeMap<String, TopicSchemaConfig>,
+}
+
+fn default_registry_timeout() -> u64 {
+    10
+}
+
+fn default_registry_connect_timeout() -> u64 {
+    5
+}
+
+fn default_registry_refresh_interval() -> u64 {
+    300
+}
+
+/// Determines how messages of a topic are distributed across its partitions.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionStrategy {
+    /// Partitions by the key of the message, such as the event ID.
+    #[default]
+    Message,
+    /// Sends all messages of a project to the same partition.
+    Project,
+    /// Sends all messages of a trace to the same partition.
+    ///
+    /// Messages without a trace ID are partitioned by their message key.
+    TraceId,
+    /// Sends messages to all partitions of the topic in turn.
+    ///
+    /// The partition count is fetched from the brokers in the background. Until it is known, the
+    /// producer chooses the partition.
+    RoundRobin,
+    /// Sends messages without a key, so the producer fills batches for one partition at a time.
+    ///
+    /// The time the producer sticks to a partition is controlled by the
+    /// `sticky.partitioning.linger.ms` producer setting.
+    Sticky,
+}
+
+/// Delivery guarantees of Kafka producers.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryMode {
+    /// Messages are delivered at least once. Retries of the producer can write duplicates.
+    #[default]
+    AtLeastOnce,
+    /// The brokers deduplicate retries of the producer, so messages are written exactly once per
+    /// partition.
+    Idempotent,
+    /// Idempotent delivery where the messages of an envelope are produced in one transaction.
+    ///
+    /// Transactions do not span Kafka clusters. If the messages of an envelope go to several
+    /// clusters, each cluster commits its share of the messages separately.
+    Transactional,
+}
+
+impl DeliveryMode {
+    /// Returns `true` if the brokers deduplicate retries of the producer.
+    pub fn is_idempotent(self) -> bool {
+        matches!(self, Self::Idempotent | Self::Transactional)
+    }
+
+    /// Checks that producer parameters do not conflict with this delivery mode.
+    ///
+    /// Idempotent producers require `acks: all`, at most 5 in-flight requests per connection, and
+    /// retries. Parameters that are not set explicitly default to compatible values.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError::IncompatibleDeliveryParam`] for the first conflicting parameter.
+    pub fn validate(self, params: &[KafkaConfigParam]) -> Result<(), ConfigError> {
+        if !self.is_idempotent() {
+            return Ok(());
+        }
+
+        for param in params {
+            let value = param.value.trim();
+            let compatible = match param.name.as_str() {
+                "enable.idempotence" => value == "true",
+                "acks" | "request.required.acks" => value == "all" || value == "-1",
+                "max.in.flight.requests.per.connection" | "max.in.flight" => {
+                    matches!(value.parse::<u32>(), Ok(1..=5))
+                }
+                "retries" | "message.send.max.retries" => {
+                    matches!(value.parse::<u32>(), Ok(1..))
+                }
+                "queuing.strategy" => value == "fifo",
+                // Relay assigns transactional IDs per Kafka config.
+                "transactional.id" => false,
+                _ => true,
+            };
+
+            if !compatible {
+                return Err(ConfigError::IncompatibleDeliveryParam(
+                    param.name.clone(),
+                    param.value.clone(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The encoding of messages with registered schemas.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaFormat {
+    /// Apache Avro binary encoding.
+    #[default]
+    Avro,
+}
+
+/// Determines the schema registry subject of a topic's message values.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SubjectNameStrategy {
+    /// The subject is `<topic>-value`.
+    #[default]
+    TopicName,
+    /// The subject is the fully-qualified record name.
+    RecordName,
+    /// The subject is `<topic>-<record name>`.
+    TopicRecordName,
+}
+
+/// Schema settings of a single Kafka topic.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct TopicSchemaConfig {
+    /// The encoding of messages. Defaults to `avro`.
+    pub format: SchemaFormat,
+    /// The subject naming strategy. Defaults to `topic_name`.
+    pub subject_strategy: SubjectNameStrategy,
+    /// The fully-qualified record name, required by the record name strategies.
+    pub record_name: Option<String>,
+}
+
+impl TopicSchemaConfig {
+    /// Returns the schema registry subject for message values of the given topic.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError::MissingRecordName`] if the strategy requires a record name that is
+    /// not configured.
+    pub fn subject(&self, topic_name: &str) -> Result<String, ConfigError> {
+        let record_name = || {
+            self.record_name
+                .as_deref()
+                .ok_or_else(|| ConfigError::MissingRecordName(topic_name.to_owned()))
+        };
+
+        Ok(match self.subject_strategy {
+            SubjectNameStrategy::TopicName => format!("{topic_name}-value"),
+            SubjectNameStrategy::RecordName => record_name()?.to_owned(),
+            SubjectNameStrategy::TopicRecordName => format!("{topic_name}-{}", record_name()?),
+        })
+    }
+}
+
 /// A name value pair of Kafka config parameter.
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct KafkaConfigParam {
     /// Name of the Kafka config parameter.
     pub name: String,
@@ -409,4 +767,117 @@ metrics:
         assert_eq!(shards, 65000);
         assert_eq!(3, mapping.len());
     }
+
+    #[test]
+    fn test_kafka_config_clustered() {
+        let yaml = r#"
+transactions:
+  clusters:
+    - name: "ingest-transactions"
+      config: "cluster_1"
+    - name: "ingest-transactions-2"
+      config: "cluster_2"
+  failover: true
+spans:
+  clusters:
+    - name: "ingest-spans"
+      config: "unknown"
+"#;
+
+        let params = || {
+            vec![KafkaConfigParam {
+                name: "test".to_string(),
+                value: "test-value".to_string(),
+            }]
+        };
+        let def_config = params();
+        let second_config = BTreeMap::from([
+            ("cluster_1".to_string(), params()),
+            ("cluster_2".to_string(), params()),
+        ]);
+
+        let topics: TopicAssignments = serde_yaml::from_str(yaml).unwrap();
+        assert!(matches!(topics.transactions, TopicAssignment::Clustered(_)));
+
+        let config = topics
+            .transactions
+            .kafka_config(&def_config, &second_config)
+            .unwrap();
+        let KafkaConfig::Clustered { clusters, failover } = config else {
+            panic!("expected clustered config");
+        };
+        assert!(failover);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[1].topic_name, "ingest-transactions-2");
+        assert_eq!(clusters[1].config_name, Some("cluster_2"));
+
+        assert!(matches!(
+            topics.spans.kafka_config(&def_config, &second_config),
+            Err(ConfigError::UnknownKafkaConfigName)
+        ));
+    }
+
+    #[test]
+    fn test_delivery_mode_validate() {
+        let param = |name: &str, value: &str| KafkaConfigParam {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+
+        let compatible = [
+            param("bootstrap.servers", "localhost:9092"),
+            param("acks", "all"),
+            param("max.in.flight.requests.per.connection", "5"),
+        ];
+        assert!(DeliveryMode::Idempotent.validate(&compatible).is_ok());
+        assert!(DeliveryMode::Transactional.validate(&compatible).is_ok());
+
+        let incompatible = [param("acks", "1")];
+        assert!(DeliveryMode::AtLeastOnce.validate(&incompatible).is_ok());
+        assert!(matches!(
+            DeliveryMode::Idempotent.validate(&incompatible),
+            Err(ConfigError::IncompatibleDeliveryParam(name, value)) if name == "acks" && value == "1"
+        ));
+
+        for (name, value) in [
+            ("max.in.flight.requests.per.connection", "10"),
+            ("retries", "0"),
+            ("enable.idempotence", "false"),
+            ("transactional.id", "relay"),
+        ] {
+            assert!(
+                DeliveryMode::Transactional
+                    .validate(&[param(name, value)])
+                    .is_err(),
+                "{name}: {value}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_subject() {
+        let yaml = r#"
+url: "http://schema-registry:8081"
+topics:
+  ingest-events: {}
+  ingest-sessions:
+    subject_strategy: topic_record_name
+    record_name: "io.sentry.Session"
+  ingest-metrics:
+    subject_strategy: record_name
+"#;
+        let config: SchemaRegistryConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.refresh_interval, 300);
+        let subject = |topic: &str| config.topics[topic].subject(topic);
+
+        assert_eq!(subject("ingest-events").unwrap(), "ingest-events-value");
+        assert_eq!(
+            subject("ingest-sessions").unwrap(),
+            "ingest-sessions-io.sentry.Session"
+        );
+        assert!(matches!(
+            subject("ingest-metrics"),
+            Err(ConfigError::MissingRecordName(_))
+        ));
+    }
 }
@@ -0,0 +1,220 @@
+//! Distribution of messages across several Kafka clusters.
+//!
+//! Organizations are assigned to clusters with rendezvous hashing: every cluster is ranked by a
+//! hash of the organization ID and the cluster's name, and messages go to the highest-ranked
+//! cluster. Adding or removing a cluster only moves the organizations assigned to that cluster.
+//! With failover enabled, the next-ranked cluster is used while a cluster is unavailable.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use relay_statsd::metric;
+
+use crate::producer::utils::ThreadedProducer;
+use crate::producer::ClientError;
+use crate::statsd::KafkaCounters;
+
+/// The time a cluster is skipped after it failed to accept a message.
+const UNHEALTHY_DURATION: Duration = Duration::from_secs(30);
+
+/// Hashes bytes with 64-bit FNV-1a.
+///
+/// This hash is stable across platforms and Rust versions, so all Relays assign organizations to
+/// the same clusters.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Mixes the bits of a 64-bit integer with the SplitMix64 finalizer.
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// A single Kafka cluster of a [`ClusteredProducer`].
+pub struct Cluster {
+    /// The name of the topic in this cluster.
+    pub topic_name: String,
+    /// The producer connected to this cluster.
+    pub producer: Arc<ThreadedProducer>,
+    /// Hash of the cluster's config and topic name, used to rank clusters per organization.
+    seed: u64,
+    /// The time until which this cluster is skipped, if it failed recently.
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Cluster {
+    /// Creates a cluster for the given Kafka config name and topic.
+    pub fn new(config_name: &str, topic_name: String, producer: Arc<ThreadedProducer>) -> Self {
+        let seed = fnv1a(format!("{config_name}/{topic_name}").as_bytes());
+
+        Self {
+            topic_name,
+            producer,
+            seed,
+            unhealthy_until: Mutex::new(None),
+        }
+    }
+
+    /// Returns the rank of this cluster for an organization. Higher ranks are preferred.
+    fn rank(&self, organization_id: u64) -> u64 {
+        mix(self.seed ^ mix(organization_id))
+    }
+
+    /// Returns `true` unless this cluster failed to accept a message recently.
+    fn is_healthy(&self) -> bool {
+        let unhealthy_until = self
+            .unhealthy_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        unhealthy_until.map_or(true, |until| until <= Instant::now())
+    }
+
+    /// Skips this cluster for [`UNHEALTHY_DURATION`].
+    fn mark_unhealthy(&self) {
+        *self
+            .unhealthy_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Instant::now() + UNHEALTHY_DURATION);
+    }
+}
+
+impl fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cluster")
+            .field("topic_name", &self.topic_name)
+            .field("producer", &"<ThreadedProducer>")
+            .field("healthy", &self.is_healthy())
+            .finish()
+    }
+}
+
+/// Producer that distributes organizations across several Kafka clusters.
+#[derive(Debug)]
+pub struct ClusteredProducer {
+    clusters: Vec<Cluster>,
+    failover: bool,
+}
+
+impl ClusteredProducer {
+    /// Creates a producer for the given clusters.
+    ///
+    /// If `failover` is enabled, messages are sent to the next cluster of an organization while its
+    /// assigned cluster is unavailable.
+    pub fn new(clusters: Vec<Cluster>, failover: bool) -> Self {
+        Self { clusters, failover }
+    }
+
+    /// Returns all clusters.
+    pub fn clusters(&self) -> &[Cluster] {
+        &self.clusters
+    }
+
+    /// Returns the clusters ordered by their rank for an organization, highest rank first.
+    fn ranked(&self, organization_id: u64) -> Vec<&Cluster> {
+        let mut clusters: Vec<_> = self.clusters.iter().collect();
+        clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.rank(organization_id)));
+        clusters
+    }
+
+    /// Returns the cluster assigned to an organization, regardless of its health.
+    ///
+    /// # Errors
+    /// Returns [`ClientError::InvalidShard`] if there are no clusters.
+    pub fn assigned(&self, organization_id: u64) -> Result<&Cluster, ClientError> {
+        self.clusters
+            .iter()
+            .max_by_key(|cluster| cluster.rank(organization_id))
+            .ok_or(ClientError::InvalidShard)
+    }
+
+    /// Sends a message of an organization with the given function.
+    ///
+    /// Without failover, the message is only sent to the assigned cluster. With failover, healthy
+    /// clusters are tried in the order of their rank, followed by the unhealthy ones, until one
+    /// accepts the message. Clusters that fail with a retryable error are marked as unhealthy.
+    pub fn send<F>(&self, organization_id: u64, mut send: F) -> Result<(), ClientError>
+    where
+        F: FnMut(&str, &Arc<ThreadedProducer>) -> Result<(), ClientError>,
+    {
+        if !self.failover {
+            let cluster = self.assigned(organization_id)?;
+            return send(&cluster.topic_name, &cluster.producer);
+        }
+
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) = self
+            .ranked(organization_id)
+            .into_iter()
+            .partition(|cluster| cluster.is_healthy());
+
+        let mut result = Err(ClientError::InvalidShard);
+        for cluster in healthy.into_iter().chain(unhealthy) {
+            result = send(&cluster.topic_name, &cluster.producer);
+            match result {
+                Err(ref error) if error.is_retryable() => {
+                    cluster.mark_unhealthy();
+                    metric!(
+                        counter(KafkaCounters::ClusterFailover) += 1,
+                        topic = cluster.topic_name.as_str()
+                    );
+                }
+                _ => break,
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the index of the highest-ranked of `count` clusters for an organization.
+    fn assigned_index(count: usize, organization_id: u64) -> usize {
+        (0..count)
+            .max_by_key(|i| {
+                let seed = fnv1a(format!("cluster_{i}/ingest-events").as_bytes());
+                mix(seed ^ mix(organization_id))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_rendezvous_distribution() {
+        let mut counts = [0; 4];
+        for organization_id in 0..10_000 {
+            counts[assigned_index(4, organization_id)] += 1;
+        }
+
+        for count in counts {
+            assert!(
+                (2000..3000).contains(&count),
+                "uneven distribution: {counts:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rendezvous_stability() {
+        // Adding a cluster only moves organizations to the new cluster.
+        for organization_id in 0..10_000 {
+            let before = assigned_index(3, organization_id);
+            let after = assigned_index(4, organization_id);
+            assert!(after == before || after == 3);
+        }
+    }
+}
@@ -5,22 +5,41 @@
 //! - [`ShardedProducer`] - which expects to have at least one shard configured, and depending on
 //! the shard number the different messages will be sent to different topics using the configured
 //! producer for the this exact shard.
+//! - [`ClusteredProducer`] - which distributes organizations across several Kafka clusters with
+//! consistent hashing.
 
+use std::any::Any;
 use std::collections::{BTreeMap, HashMap};
 use std::fmt;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::BaseRecord;
+use rdkafka::types::RDKafkaErrorCode;
 use rdkafka::ClientConfig;
 use relay_statsd::metric;
 use thiserror::Error;
 
-use crate::config::{KafkaConfig, KafkaParams, KafkaTopic};
-use crate::statsd::KafkaHistograms;
+use crate::config::{
+    ConfigError, DeliveryMode, KafkaConfig, KafkaOAuthConfig, KafkaParams, KafkaTopic,
+    PartitionStrategy,
+};
+use crate::statsd::{KafkaCounters, KafkaHistograms};
 
+mod cluster;
+mod oauth;
+mod registry;
 mod utils;
-use utils::{CaptureErrorContext, ThreadedProducer};
+use cluster::{Cluster, ClusteredProducer};
+use registry::SchemaEncoder;
+pub use registry::{to_avro_value, RegisteredSchema};
+pub use utils::{DeliveryFailure, DeliveryFailureHandler};
+use utils::{DeliveryFailures, ThreadedProducer, TrackedMessage};
+
+/// A value encoded with a registered Avro schema. See [`Message::avro_value`].
+pub use apache_avro::types::Value as AvroValue;
 
 #[cfg(feature = "schemas")]
 mod schemas;
@@ -40,6 +59,14 @@ pub enum ClientError {
     #[error("failed to create kafka producer: invalid kafka config")]
     InvalidConfig(#[source] rdkafka::error::KafkaError),
 
+    /// The kafka config conflicts with the configured delivery mode.
+    #[error("failed to create kafka producer: invalid delivery config")]
+    InvalidDelivery(#[source] ConfigError),
+
+    /// Failed to initialize, begin, commit, or abort a transaction.
+    #[error("kafka transaction failed")]
+    TransactionFailed(#[source] rdkafka::error::KafkaError),
+
     /// Failed to serialize the message.
     #[error("failed to serialize kafka message")]
     InvalidMsgPack(#[source] rmp_serde::encode::Error),
@@ -48,6 +75,10 @@ pub enum ClientError {
     #[error("failed to serialize json message")]
     InvalidJson(#[source] serde_json::Error),
 
+    /// Failed to parse a registered schema or to encode the message with it.
+    #[error("failed to encode avro message")]
+    InvalidAvro(#[source] apache_avro::Error),
+
     /// Failed to run schema validation on message.
     #[cfg(feature = "schemas")]
     #[error("failed to run schema validation on message")]
@@ -58,11 +89,94 @@ pub enum ClientError {
     InvalidShard,
 }
 
+impl ClientError {
+    /// Returns `true` if sending the message may succeed when it is retried later.
+    ///
+    /// This is the case if the local producer queue is full or the brokers cannot be reached.
+    pub fn is_retryable(&self) -> bool {
+        let Self::SendFailed(error) = self else {
+            return false;
+        };
+
+        matches!(
+            error.rdkafka_error_code(),
+            Some(
+                RDKafkaErrorCode::QueueFull
+                    | RDKafkaErrorCode::AllBrokersDown
+                    | RDKafkaErrorCode::BrokerTransportFailure
+                    | RDKafkaErrorCode::MessageTimedOut
+                    | RDKafkaErrorCode::BrokerNotAvailable
+                    | RDKafkaErrorCode::LeaderNotAvailable
+                    | RDKafkaErrorCode::NotLeaderForPartition
+                    | RDKafkaErrorCode::RequestTimedOut
+                    | RDKafkaErrorCode::NetworkException
+            )
+        )
+    }
+
+    /// Returns `true` if the message exceeds the maximum message size of the producer.
+    pub fn is_too_large(&self) -> bool {
+        let Self::SendFailed(error) = self else {
+            return false;
+        };
+
+        matches!(
+            error.rdkafka_error_code(),
+            Some(RDKafkaErrorCode::MessageSizeTooLarge)
+        )
+    }
+
+    /// Returns `true` if the message could not be serialized or encoded.
+    pub fn is_serialization_error(&self) -> bool {
+        match self {
+            Self::InvalidMsgPack(_) | Self::InvalidJson(_) | Self::InvalidAvro(_) => true,
+            #[cfg(feature = "schemas")]
+            Self::SchemaValidationFailed(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A serialized message along with its destination, ready to be sent with
+/// [`KafkaClient::send_encoded`].
+///
+/// Encoded messages can be stored and sent again if the producer fails to send them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodedMessage {
+    /// The topic of the message.
+    pub topic: KafkaTopic,
+    /// The index of the topic route, see [`KafkaClientBuilder::add_kafka_route`].
+    pub route: Option<usize>,
+    /// The organization ID used to select the shard of sharded topics.
+    pub organization_id: u64,
+    /// The partitioning key of the message, see [`Message::partition_key`].
+    pub key: Option<[u8; 16]>,
+    /// The headers of the message.
+    pub headers: Option<BTreeMap<String, String>>,
+    /// The variant name of the message, see [`Message::variant`].
+    pub variant: String,
+    /// The serialized payload.
+    pub payload: Vec<u8>,
+}
+
 /// Describes the type which can be sent using kafka producer provided by this crate.
 pub trait Message {
     /// Returns the partitioning key for this kafka message determining.
     fn key(&self) -> [u8; 16];
 
+    /// Returns the partitioning key of this message for the partition strategy of its topic.
+    ///
+    /// Returns `None` if the message is sent without a key, which leaves the choice of partition
+    /// to the producer. By default, all strategies based on message contents use [`key`](Self::key).
+    fn partition_key(&self, strategy: PartitionStrategy) -> Option<[u8; 16]> {
+        match strategy {
+            PartitionStrategy::RoundRobin | PartitionStrategy::Sticky => None,
+            PartitionStrategy::Message
+            | PartitionStrategy::Project
+            | PartitionStrategy::TraceId => Some(self.key()),
+        }
+    }
+
     /// Returns the type of the message.
     fn variant(&self) -> &'static str;
 
@@ -75,6 +189,127 @@ pub trait Message {
     /// Returns the [`ClientError::InvalidMsgPack`] or [`ClientError::InvalidJson`] if the
     /// serialization failed.
     fn serialize(&self) -> Result<Vec<u8>, ClientError>;
+
+    /// Converts the message into an Avro value.
+    ///
+    /// This is used instead of [`serialize`](Self::serialize) for topics with a registered
+    /// schema. See [`to_avro_value`] to convert serializable types.
+    ///
+    /// # Errors
+    /// Returns [`ClientError::InvalidAvro`] if the conversion failed.
+    fn avro_value(&self) -> Result<AvroValue, ClientError>;
+}
+
+/// The maximum time to wait for the partition count of a topic.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The time after which the partition count of a topic is fetched again.
+const PARTITION_COUNT_TTL: Duration = Duration::from_secs(60);
+
+/// The time after which a failed request for the partition count of a topic is retried.
+const PARTITION_COUNT_ERROR_TTL: Duration = Duration::from_secs(5);
+
+/// The maximum time to wait for the brokers to initialize, commit, or abort a transaction.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A cached partition count of a topic.
+#[derive(Debug)]
+struct PartitionCount {
+    /// The number of partitions, or `None` if the last request failed.
+    count: Option<i32>,
+    /// The time at which the count expires.
+    expires: Instant,
+    /// Whether a request for the partition count is in flight.
+    refreshing: bool,
+}
+
+/// Assigns partitions to messages of topics with [`PartitionStrategy::RoundRobin`].
+#[derive(Debug, Default)]
+struct RoundRobin {
+    /// The number of messages sent so far.
+    counter: AtomicU32,
+    /// Cached partition counts by topic name.
+    partitions: Arc<Mutex<HashMap<String, PartitionCount>>>,
+}
+
+impl RoundRobin {
+    /// Returns the next partition of the given topic.
+    ///
+    /// Partition counts are fetched from the brokers on a background thread, so this never
+    /// blocks on the network. Returns `None` while the partition count of the topic is not known
+    /// yet, or if it cannot be fetched from the brokers.
+    fn next_partition(&self, topic_name: &str, producer: &Arc<ThreadedProducer>) -> Option<i32> {
+        let partitions = {
+            let mut cache = self
+                .partitions
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+
+            let entry = cache
+                .entry(topic_name.to_owned())
+                .or_insert_with(|| PartitionCount {
+                    count: None,
+                    expires: Instant::now(),
+                    refreshing: false,
+                });
+
+            let now = Instant::now();
+            if !entry.refreshing && entry.expires <= now {
+                entry.refreshing = self.refresh(topic_name, producer);
+                if !entry.refreshing {
+                    entry.expires = now + PARTITION_COUNT_ERROR_TTL;
+                }
+            }
+
+            entry.count?
+        };
+
+        if partitions <= 0 {
+            return None;
+        }
+
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed);
+        Some((counter % partitions as u32) as i32)
+    }
+
+    /// Fetches the partition count of a topic on a background thread.
+    ///
+    /// Until the request completes, the previous count remains in use. Failed requests are cached
+    /// for a shorter time, so that they are not retried for every message. Returns `false` if the
+    /// thread could not be spawned.
+    fn refresh(&self, topic_name: &str, producer: &Arc<ThreadedProducer>) -> bool {
+        let cache = Arc::clone(&self.partitions);
+        let producer = Arc::clone(producer);
+        let topic_name = topic_name.to_owned();
+
+        let spawned = std::thread::Builder::new()
+            .name("kafka-metadata".to_owned())
+            .spawn(move || {
+                let count = producer.partition_count(&topic_name, METADATA_TIMEOUT);
+                let ttl = match count {
+                    Some(_) => PARTITION_COUNT_TTL,
+                    None => PARTITION_COUNT_ERROR_TTL,
+                };
+
+                let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Some(entry) = cache.get_mut(&topic_name) {
+                    // Keep the previous count if the request failed.
+                    entry.count = count.or(entry.count);
+                    entry.expires = Instant::now() + ttl;
+                    entry.refreshing = false;
+                }
+            });
+
+        if let Err(error) = spawned {
+            relay_log::error!(
+                error = &error as &dyn std::error::Error,
+                "failed to spawn kafka metadata thread"
+            );
+            return false;
+        }
+
+        true
+    }
 }
 
 /// Single kafka producer config with assigned topic.
@@ -114,7 +349,7 @@ impl ShardedProducer {
     pub fn get_producer(
         &self,
         sharding_key: u64,
-    ) -> Result<(&str, &ThreadedProducer), ClientError> {
+    ) -> Result<(&str, &Arc<ThreadedProducer>), ClientError> {
         let shard = sharding_key % self.shards;
         let (topic_name, producer) = self
             .producers
@@ -146,6 +381,15 @@ impl fmt::Debug for ShardedProducer {
 #[derive(Debug)]
 pub struct KafkaClient {
     producers: HashMap<KafkaTopic, Producer>,
+    /// Alternative destinations of topics, see [`KafkaClientBuilder::add_kafka_route`].
+    routes: HashMap<KafkaTopic, Vec<Producer>>,
+    /// Partition strategies of topics, see [`KafkaClientBuilder::partition_strategy`].
+    strategies: HashMap<KafkaTopic, PartitionStrategy>,
+    round_robin: RoundRobin,
+    /// Encoders for topics with registered schemas, by topic name.
+    encoders: HashMap<String, SchemaEncoder>,
+    /// Producers that send messages in transactions, if the delivery mode is transactional.
+    transactional: Transaction,
     #[cfg(feature = "schemas")]
     schema_validator: std::cell::RefCell<schemas::Validator>,
 }
@@ -156,6 +400,23 @@ impl KafkaClient {
         KafkaClientBuilder::default()
     }
 
+    /// Registers a schema for messages produced to the given Kafka topic name.
+    ///
+    /// Messages sent to this topic are encoded with the schema instead of being serialized with
+    /// [`Message::serialize`].
+    ///
+    /// # Errors
+    /// Returns [`ClientError::InvalidAvro`] if the schema cannot be parsed.
+    pub fn register_schema(
+        &mut self,
+        topic_name: &str,
+        schema: &RegisteredSchema,
+    ) -> Result<(), ClientError> {
+        let encoder = SchemaEncoder::new(schema)?;
+        self.encoders.insert(topic_name.to_owned(), encoder);
+        Ok(())
+    }
+
     /// Sends message to the provided kafka topic.
     pub fn send_message(
         &self,
@@ -163,23 +424,156 @@ impl KafkaClient {
         organization_id: u64,
         message: &impl Message,
     ) -> Result<(), ClientError> {
-        let serialized = message.serialize()?;
-        #[cfg(feature = "schemas")]
-        self.schema_validator
-            .borrow_mut()
-            .validate_message_schema(topic, &serialized)
-            .map_err(ClientError::SchemaValidationFailed)?;
-        let key = message.key();
-        self.send(
+        self.send_message_with_route(topic, None, organization_id, message)
+    }
+
+    /// Sends message to a route of the provided kafka topic.
+    ///
+    /// If `route` is `None`, this is equivalent to [`send_message`](Self::send_message).
+    /// Otherwise, the message is sent to the route with the given index, see
+    /// [`KafkaClientBuilder::add_kafka_route`].
+    pub fn send_message_with_route(
+        &self,
+        topic: KafkaTopic,
+        route: Option<usize>,
+        organization_id: u64,
+        message: &impl Message,
+    ) -> Result<(), ClientError> {
+        let encoded = self.encode_message(topic, route, organization_id, message)?;
+        self.send_encoded(&encoded)
+    }
+
+    /// Serializes a message for a route of the provided kafka topic without sending it.
+    ///
+    /// The message is encoded with the registered schema of its topic, if any. Send the result
+    /// with [`send_encoded`](Self::send_encoded).
+    pub fn encode_message(
+        &self,
+        topic: KafkaTopic,
+        route: Option<usize>,
+        organization_id: u64,
+        message: &impl Message,
+    ) -> Result<EncodedMessage, ClientError> {
+        let producer = self.producer(topic, route)?;
+        let encoder = if self.encoders.is_empty() {
+            None
+        } else {
+            let topic_name = producer.topic_name(organization_id)?;
+            self.encoders.get(topic_name)
+        };
+
+        let serialized = match encoder {
+            Some(encoder) => encoder.encode(message.avro_value()?)?,
+            None => {
+                let serialized = message.serialize()?;
+                #[cfg(feature = "schemas")]
+                self.schema_validator
+                    .borrow_mut()
+                    .validate_message_schema(topic, &serialized)
+                    .map_err(ClientError::SchemaValidationFailed)?;
+                serialized
+            }
+        };
+
+        Ok(EncodedMessage {
             topic,
+            route,
             organization_id,
-            &key,
-            message.headers(),
-            message.variant(),
-            &serialized,
+            key: message.partition_key(self.partition_strategy(topic)),
+            headers: message.headers().cloned(),
+            variant: message.variant().to_owned(),
+            payload: serialized,
+        })
+    }
+
+    /// Sends a message that was serialized with [`encode_message`](Self::encode_message).
+    pub fn send_encoded(&self, message: &EncodedMessage) -> Result<(), ClientError> {
+        self.send_encoded_inner(message, None)
+    }
+
+    /// Sends an encoded message and reports it to the delivery failure handler if the producer
+    /// fails to deliver it after accepting it.
+    ///
+    /// The `tag` is passed back in the [`DeliveryFailure`]. See
+    /// [`KafkaClientBuilder::delivery_failures`].
+    pub fn send_encoded_tracked(
+        &self,
+        message: &EncodedMessage,
+        tag: Arc<dyn Any + Send + Sync>,
+    ) -> Result<(), ClientError> {
+        let tracked = TrackedMessage {
+            message: EncodedMessage {
+                topic: message.topic,
+                route: message.route,
+                organization_id: message.organization_id,
+                key: message.key,
+                headers: message.headers.clone(),
+                variant: message.variant.clone(),
+                payload: Vec::new(),
+            },
+            tag,
+        };
+
+        self.send_encoded_inner(message, Some(&tracked))
+    }
+
+    fn send_encoded_inner(
+        &self,
+        message: &EncodedMessage,
+        tracked: Option<&TrackedMessage>,
+    ) -> Result<(), ClientError> {
+        let producer = self.producer(message.topic, message.route)?;
+        let round_robin = match self.partition_strategy(message.topic) {
+            PartitionStrategy::RoundRobin if message.key.is_none() => Some(&self.round_robin),
+            _ => None,
+        };
+
+        producer.send(
+            message.organization_id,
+            message.key.as_ref(),
+            message.headers.as_ref(),
+            &message.variant,
+            &message.payload,
+            round_robin,
+            tracked,
         )
     }
 
+    /// Returns the name of the Kafka topic that an encoded message is sent to.
+    pub fn topic_name(&self, message: &EncodedMessage) -> Result<&str, ClientError> {
+        self.producer(message.topic, message.route)?
+            .topic_name(message.organization_id)
+    }
+
+    /// Returns the route of a topic that sends messages of the organization to `topic_name`.
+    ///
+    /// Returns `Some(None)` if the default producer of the topic matches, and `None` if no
+    /// configured producer sends to `topic_name`. This restores the route of stored messages after
+    /// the order of routes has changed.
+    pub fn find_route(
+        &self,
+        topic: KafkaTopic,
+        organization_id: u64,
+        topic_name: &str,
+    ) -> Option<Option<usize>> {
+        let matches = |producer: &Producer| {
+            producer
+                .topic_name(organization_id)
+                .map_or(false, |name| name == topic_name)
+        };
+
+        if self.producers.get(&topic).map_or(false, matches) {
+            return Some(None);
+        }
+
+        self.routes.get(&topic)?.iter().position(matches).map(Some)
+    }
+
+    /// Returns the partition strategy of a topic.
+    pub fn partition_strategy(&self, topic: KafkaTopic) -> PartitionStrategy {
+        self.strategies.get(&topic).copied().unwrap_or_default()
+    }
+
     /// Sends the payload to the correct producer for the current topic.
     pub fn send(
         &self,
@@ -190,13 +584,142 @@ impl KafkaClient {
         variant: &str,
         payload: &[u8],
     ) -> Result<(), ClientError> {
-        let producer = self.producers.get(&topic).ok_or_else(|| {
+        let producer = self.producer(topic, None)?;
+        producer.send(
+            organization_id,
+            Some(key),
+            headers,
+            variant,
+            payload,
+            None,
+            None,
+        )
+    }
+
+    /// Returns the producer for a topic or one of its routes.
+    fn producer(&self, topic: KafkaTopic, route: Option<usize>) -> Result<&Producer, ClientError> {
+        let producer = match route {
+            Some(route) => self.routes.get(&topic).and_then(|routes| routes.get(route)),
+            None => self.producers.get(&topic),
+        };
+
+        producer.ok_or_else(|| {
             relay_log::error!(
                 "attempted to send message to {topic:?} using an unconfigured kafka producer",
             );
             ClientError::InvalidTopicName
-        })?;
-        producer.send(organization_id, key, headers, variant, payload)
+        })
+    }
+
+    /// Checks whether all configured producers can reach their Kafka brokers.
+    ///
+    /// This requests cluster metadata from every producer and blocks until all brokers have
+    /// responded or the `timeout` has elapsed for each of them.
+    pub fn is_connected(&self, timeout: Duration) -> bool {
+        self.producers
+            .values()
+            .chain(self.routes.values().flatten())
+            .flat_map(Producer::threaded_producers)
+            .all(|producer| producer.fetch_metadata(timeout).is_ok())
+    }
+
+    /// Returns `true` if messages must be sent within a transaction.
+    ///
+    /// See [`KafkaClientBuilder::delivery`].
+    pub fn is_transactional(&self) -> bool {
+        !self.transactional.is_empty()
+    }
+
+    /// Begins a transaction on all producers.
+    ///
+    /// This is a no-op unless the client [is transactional](Self::is_transactional). Every
+    /// transaction must be ended with [`commit_transaction`](Self::commit_transaction) or
+    /// [`abort_transaction`](Self::abort_transaction).
+    pub fn begin_transaction(&self) -> Result<(), ClientError> {
+        let producers = &self.transactional.producers;
+        for (index, producer) in producers.iter().enumerate() {
+            if let Err(error) = producer.begin_transaction() {
+                for producer in &producers[..index] {
+                    producer.abort_transaction(TRANSACTION_TIMEOUT).ok();
+                }
+                return Err(ClientError::TransactionFailed(error));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Commits the current transaction of all producers.
+    ///
+    /// This blocks until the brokers have confirmed the commit. See [`Transaction::commit`].
+    pub fn commit_transaction(&self) -> Result<(), ClientError> {
+        self.transactional.commit()
+    }
+
+    /// Aborts the current transaction of all producers, discarding the messages sent within it.
+    ///
+    /// This blocks until the brokers have confirmed the abort. See [`Transaction::abort`].
+    pub fn abort_transaction(&self) -> Result<(), ClientError> {
+        self.transactional.abort()
+    }
+
+    /// Returns a handle to end the current transaction.
+    ///
+    /// Ending a transaction blocks until the brokers respond. The handle can be moved to a
+    /// blocking thread to end the transaction from async code.
+    pub fn transaction(&self) -> Transaction {
+        self.transactional.clone()
+    }
+}
+
+/// The transactional producers of a [`KafkaClient`].
+///
+/// Obtained from [`KafkaClient::transaction`] to commit or abort the current transaction.
+#[derive(Clone, Debug, Default)]
+pub struct Transaction {
+    producers: Vec<Arc<ThreadedProducer>>,
+}
+
+impl Transaction {
+    /// Returns `true` if the client does not produce transactionally.
+    fn is_empty(&self) -> bool {
+        self.producers.is_empty()
+    }
+
+    /// Commits the current transaction of all producers.
+    ///
+    /// Producers commit one after another. If a commit fails, the transactions of the remaining
+    /// producers are aborted, but transactions that have already been committed remain visible.
+    pub fn commit(&self) -> Result<(), ClientError> {
+        for (index, producer) in self.producers.iter().enumerate() {
+            if let Err(error) = producer.commit_transaction(TRANSACTION_TIMEOUT) {
+                for producer in &self.producers[index..] {
+                    producer.abort_transaction(TRANSACTION_TIMEOUT).ok();
+                }
+                metric!(counter(KafkaCounters::TransactionAborted) += 1);
+                return Err(ClientError::TransactionFailed(error));
+            }
+        }
+
+        if !self.is_empty() {
+            metric!(counter(KafkaCounters::TransactionCommitted) += 1);
+        }
+        Ok(())
+    }
+
+    /// Aborts the current transaction of all producers, discarding the messages sent within it.
+    pub fn abort(&self) -> Result<(), ClientError> {
+        let mut result = Ok(());
+        for producer in &self.producers {
+            if let Err(error) = producer.abort_transaction(TRANSACTION_TIMEOUT) {
+                result = Err(ClientError::TransactionFailed(error));
+            }
+        }
+
+        if !self.is_empty() {
+            metric!(counter(KafkaCounters::TransactionAborted) += 1);
+        }
+        result
     }
 }
 
@@ -205,6 +728,12 @@ impl KafkaClient {
 pub struct KafkaClientBuilder {
     reused_producers: BTreeMap<Option<String>, Arc<ThreadedProducer>>,
     producers: HashMap<KafkaTopic, Producer>,
+    routes: HashMap<KafkaTopic, Vec<Producer>>,
+    strategies: HashMap<KafkaTopic, PartitionStrategy>,
+    oauth: Option<KafkaOAuthConfig>,
+    delivery: DeliveryMode,
+    transactional_id: Option<String>,
+    failures: DeliveryFailures,
 }
 
 impl KafkaClientBuilder {
@@ -213,6 +742,34 @@ impl KafkaClientBuilder {
         Self::default()
     }
 
+    /// Sets the source of tokens for producers that use the `OAUTHBEARER` SASL mechanism.
+    ///
+    /// This must be called before adding topic configs.
+    pub fn oauth(mut self, oauth: Option<KafkaOAuthConfig>) -> Self {
+        self.oauth = oauth;
+        self
+    }
+
+    /// Sets the handler for messages that the producers fail to deliver after accepting them.
+    ///
+    /// Only failures of messages sent with [`KafkaClient::send_encoded_tracked`] are reported.
+    /// This must be called before adding topic configs.
+    pub fn delivery_failures(mut self, handler: DeliveryFailureHandler) -> Self {
+        self.failures = DeliveryFailures(Some(handler));
+        self
+    }
+
+    /// Sets the delivery guarantees of all producers.
+    ///
+    /// Transactional delivery requires a `transactional_id`. Each producer uses this ID suffixed
+    /// with its Kafka config name, so it must be unique per Relay instance and stable across
+    /// restarts. This must be called before adding topic configs.
+    pub fn delivery(mut self, mode: DeliveryMode, transactional_id: Option<String>) -> Self {
+        self.delivery = mode;
+        self.transactional_id = transactional_id;
+        self
+    }
+
     /// Adds topic configuration to the current [`KafkaClientBuilder`], which in return assigns
     /// dedicates producer to the topic which can will be used to send the messages.
     ///
@@ -224,89 +781,145 @@ impl KafkaClientBuilder {
         topic: KafkaTopic,
         config: &KafkaConfig,
     ) -> Result<Self, ClientError> {
-        let mut client_config = ClientConfig::new();
-        match config {
-            KafkaConfig::Single { params } => {
-                let KafkaParams {
-                    topic_name,
-                    config_name,
-                    params,
-                } = params;
-
-                let config_name = config_name.map(str::to_string);
-
-                if let Some(producer) = self.reused_producers.get(&config_name) {
-                    self.producers.insert(
-                        topic,
-                        Producer::Single(SingleProducer {
-                            topic_name: (*topic_name).to_string(),
-                            producer: Arc::clone(producer),
-                        }),
-                    );
-                    return Ok(self);
-                }
+        let producer = self.create_producer(config)?;
+        self.producers.insert(topic, producer);
+        Ok(self)
+    }
 
-                for config_p in *params {
-                    client_config.set(config_p.name.as_str(), config_p.value.as_str());
-                }
+    /// Adds an alternative destination for messages of a topic.
+    ///
+    /// Routes of a topic are numbered in the order they are added, starting at `0`. Messages are
+    /// sent to a route with [`KafkaClient::send_message_with_route`].
+    ///
+    /// # Errors
+    /// Returns [`ClientError::InvalidConfig`] error if the provided configuration is wrong and
+    /// the producer could not be created.
+    pub fn add_kafka_route(
+        mut self,
+        topic: KafkaTopic,
+        config: &KafkaConfig,
+    ) -> Result<Self, ClientError> {
+        let producer = self.create_producer(config)?;
+        self.routes.entry(topic).or_default().push(producer);
+        Ok(self)
+    }
+
+    /// Sets the partition strategy of a topic, which applies to all of its routes.
+    ///
+    /// Topics use [`PartitionStrategy::Message`] by default.
+    pub fn partition_strategy(mut self, topic: KafkaTopic, strategy: PartitionStrategy) -> Self {
+        self.strategies.insert(topic, strategy);
+        self
+    }
 
-                let producer = Arc::new(
-                    client_config
-                        .create_with_context(CaptureErrorContext)
-                        .map_err(ClientError::InvalidConfig)?,
-                );
-
-                self.reused_producers
-                    .insert(config_name, Arc::clone(&producer));
-                self.producers.insert(
-                    topic,
-                    Producer::Single(SingleProducer {
-                        topic_name: (*topic_name).to_string(),
-                        producer,
-                    }),
-                );
-                Ok(self)
+    /// Creates a producer for the given config, reusing producers of the same Kafka config name.
+    fn create_producer(&mut self, config: &KafkaConfig) -> Result<Producer, ClientError> {
+        match config {
+            KafkaConfig::Single { params } => {
+                let producer = self.reused_producer(params)?;
+                Ok(Producer::Single(SingleProducer {
+                    topic_name: params.topic_name.to_string(),
+                    producer,
+                }))
             }
             KafkaConfig::Sharded { shards, configs } => {
                 let mut producers = BTreeMap::new();
                 for (shard, kafka_params) in configs {
-                    let config_name = kafka_params.config_name.map(str::to_string);
-                    if let Some(producer) = self.reused_producers.get(&config_name) {
-                        let cached_producer = Arc::clone(producer);
-                        producers.insert(
-                            *shard,
-                            (kafka_params.topic_name.to_string(), cached_producer),
-                        );
-                        continue;
-                    }
-                    for config_p in kafka_params.params {
-                        client_config.set(config_p.name.as_str(), config_p.value.as_str());
-                    }
-                    let producer = Arc::new(
-                        client_config
-                            .create_with_context(CaptureErrorContext)
-                            .map_err(ClientError::InvalidConfig)?,
-                    );
-                    self.reused_producers
-                        .insert(config_name, Arc::clone(&producer));
+                    let producer = self.reused_producer(kafka_params)?;
                     producers.insert(*shard, (kafka_params.topic_name.to_string(), producer));
                 }
-                self.producers.insert(
-                    topic,
-                    Producer::Sharded(ShardedProducer {
-                        shards: *shards,
-                        producers,
-                    }),
-                );
-                Ok(self)
+                Ok(Producer::Sharded(ShardedProducer {
+                    shards: *shards,
+                    producers,
+                }))
+            }
+            KafkaConfig::Clustered { clusters, failover } => {
+                let clusters = clusters
+                    .iter()
+                    .map(|params| {
+                        let producer = self.reused_producer(params)?;
+                        let config_name = params.config_name.unwrap_or_default();
+                        Ok(Cluster::new(
+                            config_name,
+                            params.topic_name.to_owned(),
+                            producer,
+                        ))
+                    })
+                    .collect::<Result<_, ClientError>>()?;
+
+                Ok(Producer::Clustered(ClusteredProducer::new(
+                    clusters, *failover,
+                )))
             }
         }
     }
 
+    /// Returns the producer for the Kafka config name of the given parameters, creating it if
+    /// needed.
+    fn reused_producer(
+        &mut self,
+        params: &KafkaParams,
+    ) -> Result<Arc<ThreadedProducer>, ClientError> {
+        let config_name = params.config_name.map(str::to_string);
+        if let Some(producer) = self.reused_producers.get(&config_name) {
+            return Ok(Arc::clone(producer));
+        }
+
+        self.delivery
+            .validate(params.params)
+            .map_err(ClientError::InvalidDelivery)?;
+
+        let mut client_config = ClientConfig::new();
+        for config_p in params.params {
+            client_config.set(config_p.name.as_str(), config_p.value.as_str());
+        }
+
+        if self.delivery.is_idempotent() {
+            client_config.set("enable.idempotence", "true");
+        }
+
+        let transactional = self.delivery == DeliveryMode::Transactional;
+        if transactional {
+            let transactional_id =
+                self.transactional_id
+                    .as_deref()
+                    .ok_or(ClientError::InvalidDelivery(
+                        ConfigError::MissingTransactionalId,
+                    ))?;
+            let suffix = params.config_name.unwrap_or("default");
+            client_config.set("transactional.id", format!("{transactional_id}-{suffix}"));
+        }
+
+        let producer = Arc::new(
+            ThreadedProducer::create(&client_config, self.oauth.as_ref(), self.failures.clone())
+                .map_err(ClientError::InvalidConfig)?,
+        );
+
+        if transactional {
+            producer
+                .init_transactions(TRANSACTION_TIMEOUT)
+                .map_err(ClientError::TransactionFailed)?;
+        }
+
+        self.reused_producers
+            .insert(config_name, Arc::clone(&producer));
+        Ok(producer)
+    }
+
     /// Consumes self and returns the built [`KafkaClient`].
     pub fn build(self) -> KafkaClient {
+        let producers = match self.delivery {
+            DeliveryMode::Transactional => self.reused_producers.into_values().collect(),
+            DeliveryMode::AtLeastOnce | DeliveryMode::Idempotent => Vec::new(),
+        };
+
         KafkaClient {
             producers: self.producers,
+            routes: self.routes,
+            strategies: self.strategies,
+            round_robin: RoundRobin::default(),
+            encoders: HashMap::new(),
+            transactional: Transaction { producers },
             #[cfg(feature = "schemas")]
             schema_validator: schemas::Validator::default().into(),
         }
@@ -318,6 +931,10 @@ impl fmt::Debug for KafkaClientBuilder {
         f.debug_struct("KafkaClientBuilder")
             .field("reused_producers", &"<CachedProducers>")
             .field("producers", &self.producers)
+            .field("routes", &self.routes)
+            .field("strategies", &self.strategies)
+            .field("oauth", &self.oauth)
+            .field("failures", &self.failures)
             .finish()
     }
 }
@@ -330,51 +947,129 @@ enum Producer {
     /// Configuration variant for sharded kafka producer, when one topic has different producers
     /// dedicated to the range of the shards.
     Sharded(ShardedProducer),
+    /// Configuration variant for a topic distributed across several kafka clusters.
+    Clustered(ClusteredProducer),
 }
 
 impl Producer {
+    /// Returns all underlying Kafka producers.
+    fn threaded_producers(&self) -> Vec<&ThreadedProducer> {
+        match self {
+            Self::Single(single) => vec![single.producer.as_ref()],
+            Self::Sharded(sharded) => sharded
+                .producers
+                .values()
+                .map(|(_, producer)| producer.as_ref())
+                .collect(),
+            Self::Clustered(clustered) => clustered
+                .clusters()
+                .iter()
+                .map(|cluster| cluster.producer.as_ref())
+                .collect(),
+        }
+    }
+
+    /// Returns the name of the Kafka topic that messages of the given organization are sent to.
+    fn topic_name(&self, organization_id: u64) -> Result<&str, ClientError> {
+        match self {
+            Self::Single(single) => Ok(&single.topic_name),
+            Self::Sharded(sharded) => Ok(sharded.get_producer(organization_id)?.0),
+            Self::Clustered(clustered) => Ok(&clustered.assigned(organization_id)?.topic_name),
+        }
+    }
+
     /// Sends the payload to the correct producer for the current topic.
+    ///
+    /// Messages without a key are assigned a partition by `round_robin`, if given. Otherwise, the
+    /// producer chooses their partition.
+    #[allow(clippy::too_many_arguments)]
     fn send(
         &self,
         organization_id: u64,
-        key: &[u8; 16],
+        key: Option<&[u8; 16]>,
         headers: Option<&BTreeMap<String, String>>,
         variant: &str,
         payload: &[u8],
+        round_robin: Option<&RoundRobin>,
+        tracked: Option<&TrackedMessage>,
     ) -> Result<(), ClientError> {
         metric!(
             histogram(KafkaHistograms::KafkaMessageSize) = payload.len() as u64,
             variant = variant
         );
-        let (topic_name, producer) = match self {
-            Self::Single(SingleProducer {
+
+        let send = |topic_name: &str, producer: &Arc<ThreadedProducer>| {
+            send_record(
                 topic_name,
                 producer,
-            }) => (topic_name.as_str(), producer.as_ref()),
-
-            Self::Sharded(sharded) => sharded.get_producer(organization_id)?,
+                key,
+                headers,
+                variant,
+                payload,
+                round_robin,
+                tracked,
+            )
         };
-        let mut record = BaseRecord::to(topic_name).key(key).payload(payload);
-
-        // Make sure to set the headers if provided.
-        if let Some(headers) = headers {
-            let mut kafka_headers = OwnedHeaders::new();
-            for (key, value) in headers {
-                kafka_headers = kafka_headers.insert(Header {
-                    key,
-                    value: Some(value),
-                });
+
+        match self {
+            Self::Single(SingleProducer {
+                topic_name,
+                producer,
+            }) => send(topic_name.as_str(), producer),
+            Self::Sharded(sharded) => {
+                let (topic_name, producer) = sharded.get_producer(organization_id)?;
+                send(topic_name, producer)
             }
-            record = record.headers(kafka_headers);
+            Self::Clustered(clustered) => clustered.send(organization_id, send),
         }
+    }
+}
 
-        producer.send(record).map_err(|(error, _message)| {
-            relay_log::error!(
-                error = &error as &dyn std::error::Error,
-                tags.variant = variant,
-                "error sending kafka message"
-            );
-            ClientError::SendFailed(error)
-        })
+/// Sends a single record to a topic with the given producer.
+///
+/// Messages without a key are assigned a partition by `round_robin`, if given. Otherwise, the
+/// producer chooses their partition. If the message is `tracked`, its delivery failure is reported
+/// to the handler of the producer.
+#[allow(clippy::too_many_arguments)]
+fn send_record(
+    topic_name: &str,
+    producer: &Arc<ThreadedProducer>,
+    key: Option<&[u8; 16]>,
+    headers: Option<&BTreeMap<String, String>>,
+    variant: &str,
+    payload: &[u8],
+    round_robin: Option<&RoundRobin>,
+    tracked: Option<&TrackedMessage>,
+) -> Result<(), ClientError> {
+    let opaque = Box::new(tracked.cloned());
+    let mut record =
+        BaseRecord::<[u8], [u8], _>::with_opaque_to(topic_name, opaque).payload(payload);
+    if let Some(key) = key {
+        record = record.key(key.as_slice());
+    } else if let Some(partition) =
+        round_robin.and_then(|round_robin| round_robin.next_partition(topic_name, producer))
+    {
+        record = record.partition(partition);
     }
+
+    // Make sure to set the headers if provided.
+    if let Some(headers) = headers {
+        let mut kafka_headers = OwnedHeaders::new();
+        for (key, value) in headers {
+            kafka_headers = kafka_headers.insert(Header {
+                key,
+                value: Some(value),
+            });
+        }
+        record = record.headers(kafka_headers);
+    }
+
+    producer.send(record).map_err(|(error, _message)| {
+        relay_log::error!(
+            error = &error as &dyn std::error::Error,
+            tags.variant = variant,
+            "error sending kafka message"
+        );
+        ClientError::SendFailed(error)
+    })
 }
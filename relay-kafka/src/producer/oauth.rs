@@ -0,0 +1,203 @@
+//! Generation of tokens for the `OAUTHBEARER` SASL mechanism.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use once_cell::sync::OnceCell;
+use rdkafka::client::OAuthToken;
+use relay_aws::{AwsCredentials, AwsCredentialsProvider};
+use thiserror::Error;
+
+use crate::config::KafkaOAuthConfig;
+
+/// The service name of MSK in AWS signatures.
+const MSK_SERVICE: &str = "kafka-cluster";
+
+/// The lifetime of signed MSK IAM tokens.
+const MSK_TOKEN_EXPIRY: Duration = Duration::from_secs(900);
+
+/// Errors when generating `OAUTHBEARER` tokens.
+#[derive(Debug, Error)]
+pub enum OAuthError {
+    /// The token file could not be read.
+    #[error("failed to read kafka oauth token file")]
+    Io(#[from] std::io::Error),
+
+    /// The token file does not contain a token.
+    #[error("kafka oauth token file is empty")]
+    EmptyToken,
+
+    /// AWS credentials could not be loaded from the credentials chain.
+    #[error("failed to load AWS credentials")]
+    AwsCredentials(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<anyhow::Error> for OAuthError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::AwsCredentials(error.into())
+    }
+}
+
+/// Loads AWS credentials from the synchronous token refresh callback.
+///
+/// librdkafka requests tokens on its own background thread, so the credentials provider runs on a
+/// dedicated single-threaded runtime.
+#[derive(Debug)]
+struct BlockingCredentials {
+    provider: AwsCredentialsProvider,
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl BlockingCredentials {
+    fn from_env() -> Result<Self, OAuthError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|error| OAuthError::AwsCredentials(error.into()))?;
+
+        Ok(Self {
+            provider: AwsCredentialsProvider::from_env(reqwest::Client::new())?,
+            runtime: Some(runtime),
+        })
+    }
+
+    fn credentials(&self) -> Result<AwsCredentials, OAuthError> {
+        let runtime = self
+            .runtime
+            .as_ref()
+            .expect("runtime is only taken on drop");
+        Ok(runtime.block_on(self.provider.credentials())?)
+    }
+}
+
+impl Drop for BlockingCredentials {
+    fn drop(&mut self) {
+        // Dropping a runtime waits for its tasks, which panics if the producer is dropped from
+        // within an async context.
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Generates `OAUTHBEARER` tokens from the configured source.
+#[derive(Debug)]
+pub struct TokenGenerator {
+    config: KafkaOAuthConfig,
+    aws: OnceCell<BlockingCredentials>,
+}
+
+impl TokenGenerator {
+    /// Creates a token generator for the given source.
+    ///
+    /// AWS credentials are loaded on first use, so creating a generator never fails.
+    pub fn new(config: KafkaOAuthConfig) -> Self {
+        Self {
+            config,
+            aws: OnceCell::new(),
+        }
+    }
+
+    /// Generates a new token.
+    pub fn generate(&self) -> Result<OAuthToken, OAuthError> {
+        let now = Utc::now();
+
+        match self.config {
+            KafkaOAuthConfig::File {
+                ref path,
+                ref principal,
+                lifetime,
+            } => {
+                let token = std::fs::read_to_string(path)?.trim().to_owned();
+                if token.is_empty() {
+                    return Err(OAuthError::EmptyToken);
+                }
+
+                Ok(OAuthToken {
+                    token,
+                    principal_name: principal.clone(),
+                    lifetime_ms: expires_at(now, Duration::from_secs(lifetime)),
+                })
+            }
+            KafkaOAuthConfig::AwsMskIam { ref region } => {
+                let aws = self.aws.get_or_try_init(BlockingCredentials::from_env)?;
+                Ok(msk_iam_token(region, &aws.credentials()?, now))
+            }
+        }
+    }
+}
+
+/// Returns the expiry time in milliseconds since the UNIX epoch.
+fn expires_at(now: DateTime<Utc>, lifetime: Duration) -> i64 {
+    now.timestamp_millis() + lifetime.as_millis() as i64
+}
+
+/// Creates an AWS MSK IAM token.
+///
+/// The token is a presigned `kafka-cluster:Connect` request URL, encoded with URL-safe base64.
+fn msk_iam_token(region: &str, credentials: &AwsCredentials, now: DateTime<Utc>) -> OAuthToken {
+    let host = format!("kafka.{region}.amazonaws.com");
+    let url = relay_aws::presigned_url(
+        credentials,
+        MSK_SERVICE,
+        region,
+        &host,
+        &[("Action", "kafka-cluster:Connect")],
+        MSK_TOKEN_EXPIRY,
+        now,
+    );
+    let url = format!("{url}&User-Agent=relay");
+
+    OAuthToken {
+        token: BASE64URL_NOPAD.encode(url.as_bytes()),
+        principal_name: credentials.access_key_id.clone(),
+        lifetime_ms: expires_at(now, MSK_TOKEN_EXPIRY),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_msk_iam_token() {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_owned(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned(),
+            session_token: Some("session".to_owned()),
+            expires: None,
+        };
+        let now = Utc.with_ymd_and_hms(2023, 10, 1, 12, 0, 0).unwrap();
+
+        let token = msk_iam_token("us-east-1", &credentials, now);
+        let url = String::from_utf8(BASE64URL_NOPAD.decode(token.token.as_bytes()).unwrap());
+        let url = url.unwrap();
+
+        assert!(url.starts_with(
+            "https://kafka.us-east-1.amazonaws.com/?Action=kafka-cluster%3AConnect&X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential=AKIDEXAMPLE%2F20231001%2Fus-east-1%2Fkafka-cluster%2Faws4_request&X-Amz-Date=20231001T120000Z&X-Amz-Expires=900&X-Amz-Security-Token=session&X-Amz-SignedHeaders=host&X-Amz-Signature="
+        ));
+        assert!(url.ends_with("&User-Agent=relay"));
+        assert_eq!(token.principal_name, "AKIDEXAMPLE");
+        assert_eq!(token.lifetime_ms, now.timestamp_millis() + 900_000);
+    }
+
+    #[test]
+    fn test_file_token() {
+        let path = std::env::temp_dir().join(format!("relay-kafka-token-{}", std::process::id()));
+        std::fs::write(&path, "secret-token\n").unwrap();
+
+        let config = KafkaOAuthConfig::File {
+            path: path.clone(),
+            principal: "relay".to_owned(),
+            lifetime: 60,
+        };
+        let token = TokenGenerator::new(config).generate().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(token.token, "secret-token");
+        assert_eq!(token.principal_name, "relay");
+    }
+}
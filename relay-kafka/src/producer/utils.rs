@@ -1,36 +1,270 @@
+use std::any::Any;
 use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
-use rdkafka::producer::{DeliveryResult, ProducerContext};
-use rdkafka::{ClientContext, Message};
+use rdkafka::client::OAuthToken;
+use rdkafka::error::{KafkaError, KafkaResult};
+use rdkafka::message::ToBytes;
+use rdkafka::metadata::Metadata;
+use rdkafka::producer::{BaseRecord, DeliveryResult, Producer as _, ProducerContext};
+use rdkafka::{ClientConfig, ClientContext, Message};
 use relay_statsd::metric;
 
+use crate::config::KafkaOAuthConfig;
+use crate::producer::oauth::TokenGenerator;
+use crate::producer::{ClientError, EncodedMessage};
 use crate::statsd::KafkaCounters;
 
+/// A message that the producer accepted but failed to deliver to the brokers.
+///
+/// See [`KafkaClientBuilder::delivery_failures`](crate::KafkaClientBuilder::delivery_failures).
+#[derive(Debug)]
+pub struct DeliveryFailure {
+    /// The message that failed, including its payload.
+    pub message: EncodedMessage,
+    /// The error reported by the producer.
+    pub error: ClientError,
+    /// The tag passed to [`KafkaClient::send_encoded_tracked`](crate::KafkaClient::send_encoded_tracked).
+    pub tag: Arc<dyn Any + Send + Sync>,
+}
+
+/// Receives messages that the producer failed to deliver after it accepted them.
+///
+/// This is called from the polling thread of the producer and must not block.
+pub type DeliveryFailureHandler = Arc<dyn Fn(DeliveryFailure) + Send + Sync>;
+
+/// The metadata of a message whose delivery failure is reported to the [`DeliveryFailureHandler`].
+///
+/// The payload of the message is left empty and restored from the failed record.
+#[derive(Clone, Debug)]
+pub struct TrackedMessage {
+    pub message: EncodedMessage,
+    pub tag: Arc<dyn Any + Send + Sync>,
+}
+
+/// The delivery opaque of all records, which is only set for tracked messages.
+pub type DeliveryOpaque = Box<Option<TrackedMessage>>;
+
+/// The optional [`DeliveryFailureHandler`] of a producer context.
+#[derive(Clone, Default)]
+pub struct DeliveryFailures(pub Option<DeliveryFailureHandler>);
+
+impl fmt::Debug for DeliveryFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("DeliveryFailures(Some(<handler>))"),
+            None => f.write_str("DeliveryFailures(None)"),
+        }
+    }
+}
+
+impl DeliveryFailures {
+    /// Logs errors reported by the delivery callback of a producer.
+    ///
+    /// Failures of tracked messages are also reported to the handler.
+    fn capture(&self, result: &DeliveryResult, opaque: DeliveryOpaque) {
+        let Err((error, message)) = result else {
+            return;
+        };
+
+        relay_log::error!(
+            error = error as &dyn Error,
+            payload_len = message.payload_len(),
+            tags.topic = message.topic(),
+            "failed to produce message to Kafka (delivery callback)",
+        );
+
+        metric!(counter(KafkaCounters::ProcessingProduceError) += 1);
+
+        if let (Some(handler), Some(tracked)) = (&self.0, *opaque) {
+            let mut failed = tracked.message;
+            failed.payload = message.payload().unwrap_or_default().to_vec();
+            handler(DeliveryFailure {
+                message: failed,
+                error: ClientError::SendFailed(error.clone()),
+                tag: tracked.tag,
+            });
+        }
+    }
+}
+
 /// Kafka producer context that logs producer errors.
 #[derive(Debug)]
-pub struct CaptureErrorContext;
+pub struct CaptureErrorContext {
+    failures: DeliveryFailures,
+}
 
 impl ClientContext for CaptureErrorContext {}
 
 impl ProducerContext for CaptureErrorContext {
-    type DeliveryOpaque = ();
+    type DeliveryOpaque = DeliveryOpaque;
 
     /// This method is called after attempting to send a message to Kafka.
     /// It's called asynchronously for every message, so we want to handle errors explicitly here.
-    fn delivery(&self, result: &DeliveryResult, _delivery_opaque: Self::DeliveryOpaque) {
-        if let Err((error, message)) = result {
+    fn delivery(&self, result: &DeliveryResult, delivery_opaque: Self::DeliveryOpaque) {
+        self.failures.capture(result, delivery_opaque);
+    }
+}
+
+/// Kafka producer context that logs producer errors and provides `OAUTHBEARER` tokens.
+#[derive(Debug)]
+pub struct OAuthContext {
+    tokens: TokenGenerator,
+    failures: DeliveryFailures,
+}
+
+impl ClientContext for OAuthContext {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = true;
+
+    fn generate_oauth_token(
+        &self,
+        _oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn Error>> {
+        self.tokens.generate().map_err(|error| {
             relay_log::error!(
-                error = error as &dyn Error,
-                payload_len = message.payload_len(),
-                tags.topic = message.topic(),
-                "failed to produce message to Kafka (delivery callback)",
+                error = &error as &dyn Error,
+                "failed to generate kafka oauth token"
             );
+            metric!(counter(KafkaCounters::OAuthTokenError) += 1);
+            Box::new(error) as Box<dyn Error>
+        })
+    }
+}
 
-            metric!(counter(KafkaCounters::ProcessingProduceError) += 1);
-        }
+impl ProducerContext for OAuthContext {
+    type DeliveryOpaque = DeliveryOpaque;
+
+    fn delivery(&self, result: &DeliveryResult, delivery_opaque: Self::DeliveryOpaque) {
+        self.failures.capture(result, delivery_opaque);
     }
 }
 
 /// The wrapper type around the kafka [`rdkafka::producer::ThreadedProducer`] with our own
-/// [`CaptureErrorContext`] context.
-pub type ThreadedProducer = rdkafka::producer::ThreadedProducer<CaptureErrorContext>;
+/// contexts.
+///
+/// Producers that authenticate with `OAUTHBEARER` use the [`OAuthContext`] to refresh their
+/// tokens. All other producers use the [`CaptureErrorContext`].
+pub enum ThreadedProducer {
+    /// Producer without token refreshes.
+    Plain(rdkafka::producer::ThreadedProducer<CaptureErrorContext>),
+    /// Producer that refreshes `OAUTHBEARER` tokens.
+    OAuth(rdkafka::producer::ThreadedProducer<OAuthContext>),
+}
+
+impl fmt::Debug for ThreadedProducer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plain(_) => f.write_str("ThreadedProducer::Plain"),
+            Self::OAuth(_) => f.write_str("ThreadedProducer::OAuth"),
+        }
+    }
+}
+
+impl ThreadedProducer {
+    /// Creates a producer from the given client config.
+    ///
+    /// If the config uses the `OAUTHBEARER` SASL mechanism and a token source is given, the
+    /// producer requests tokens from that source. Delivery failures of tracked messages are
+    /// reported to `failures`.
+    pub fn create(
+        client_config: &ClientConfig,
+        oauth: Option<&KafkaOAuthConfig>,
+        failures: DeliveryFailures,
+    ) -> KafkaResult<Self> {
+        let is_oauth = client_config
+            .get("sasl.mechanism")
+            .map_or(false, |mechanism| {
+                mechanism.eq_ignore_ascii_case("OAUTHBEARER")
+            });
+
+        match oauth {
+            Some(config) if is_oauth => {
+                let context = OAuthContext {
+                    tokens: TokenGenerator::new(config.clone()),
+                    failures,
+                };
+                client_config.create_with_context(context).map(Self::OAuth)
+            }
+            _ => client_config
+                .create_with_context(CaptureErrorContext { failures })
+                .map(Self::Plain),
+        }
+    }
+
+    /// Sends a message to Kafka. See [`rdkafka::producer::ThreadedProducer::send`].
+    pub fn send<'a, K, P>(
+        &self,
+        record: BaseRecord<'a, K, P, DeliveryOpaque>,
+    ) -> Result<(), (KafkaError, BaseRecord<'a, K, P, DeliveryOpaque>)>
+    where
+        K: ToBytes + ?Sized,
+        P: ToBytes + ?Sized,
+    {
+        match self {
+            Self::Plain(producer) => producer.send(record),
+            Self::OAuth(producer) => producer.send(record),
+        }
+    }
+
+    /// Requests cluster metadata from the brokers.
+    pub fn fetch_metadata(&self, timeout: Duration) -> KafkaResult<Metadata> {
+        match self {
+            Self::Plain(producer) => producer.client().fetch_metadata(None, timeout),
+            Self::OAuth(producer) => producer.client().fetch_metadata(None, timeout),
+        }
+    }
+
+    /// Initializes transactions of a producer with a `transactional.id`.
+    ///
+    /// See [`rdkafka::producer::Producer::init_transactions`].
+    pub fn init_transactions(&self, timeout: Duration) -> KafkaResult<()> {
+        match self {
+            Self::Plain(producer) => producer.init_transactions(timeout),
+            Self::OAuth(producer) => producer.init_transactions(timeout),
+        }
+    }
+
+    /// Begins a new transaction. See [`rdkafka::producer::Producer::begin_transaction`].
+    pub fn begin_transaction(&self) -> KafkaResult<()> {
+        match self {
+            Self::Plain(producer) => producer.begin_transaction(),
+            Self::OAuth(producer) => producer.begin_transaction(),
+        }
+    }
+
+    /// Commits the current transaction. See [`rdkafka::producer::Producer::commit_transaction`].
+    pub fn commit_transaction(&self, timeout: Duration) -> KafkaResult<()> {
+        match self {
+            Self::Plain(producer) => producer.commit_transaction(timeout),
+            Self::OAuth(producer) => producer.commit_transaction(timeout),
+        }
+    }
+
+    /// Aborts the current transaction. See [`rdkafka::producer::Producer::abort_transaction`].
+    pub fn abort_transaction(&self, timeout: Duration) -> KafkaResult<()> {
+        match self {
+            Self::Plain(producer) => producer.abort_transaction(timeout),
+            Self::OAuth(producer) => producer.abort_transaction(timeout),
+        }
+    }
+
+    /// Requests the number of partitions of a topic from the brokers.
+    ///
+    /// Returns `None` if the request fails or the topic does not exist.
+    pub fn partition_count(&self, topic: &str, timeout: Duration) -> Option<i32> {
+        let metadata = match self {
+            Self::Plain(producer) => producer.client().fetch_metadata(Some(topic), timeout),
+            Self::OAuth(producer) => producer.client().fetch_metadata(Some(topic), timeout),
+        };
+
+        let metadata = metadata.ok()?;
+        let topic = metadata.topics().iter().find(|t| t.name() == topic)?;
+        if topic.error().is_some() {
+            return None;
+        }
+
+        Some(topic.partitions().len() as i32)
+    }
+}
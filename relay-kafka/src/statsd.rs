@@ -8,12 +8,35 @@ pub enum KafkaCounters {
     /// accept the requests over a certain size, which is usually due to invalid or inconsistent
     /// broker/producer configurations.
     ProcessingProduceError,
+
+    /// Number of failures to generate a token for `OAUTHBEARER` authentication.
+    ///
+    /// librdkafka retries token generation, and producers cannot connect to the brokers until
+    /// a token has been generated.
+    OAuthTokenError,
+
+    /// Number of messages that a Kafka cluster failed to accept, so they were sent to another
+    /// cluster of a topic with failover enabled.
+    ///
+    /// This metric is tagged with:
+    ///  - `topic`: The name of the topic in the cluster that failed.
+    ClusterFailover,
+
+    /// Number of committed transactions with transactional delivery.
+    TransactionCommitted,
+
+    /// Number of aborted transactions with transactional delivery, including failed commits.
+    TransactionAborted,
 }
 
 impl CounterMetric for KafkaCounters {
     fn name(&self) -> &'static str {
         match self {
             Self::ProcessingProduceError => "processing.produce.error",
+            Self::OAuthTokenError => "kafka.oauth_token.error",
+            Self::ClusterFailover => "kafka.cluster.failover",
+            Self::TransactionCommitted => "kafka.transaction.committed",
+            Self::TransactionAborted => "kafka.transaction.aborted",
         }
     }
 }
@@ -0,0 +1,222 @@
+//! Native crash handling with last-breath reports.
+//!
+//! When Relay crashes, the native crash handler writes a minidump to the configured crash
+//! directory. Along with the minidump, it stores a last-breath report with the release, custom
+//! context set through [`set_context`], and the most recent log messages. Crash reports are
+//! uploaded to the configured DSN when Relay starts the next time, unless disabled with
+//! [`SentryConfig::upload_crashes`].
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tracing::Subscriber;
+use tracing_subscriber::Layer;
+
+use crate::setup::{SentryConfig, RELEASE};
+
+/// The number of log messages kept for the last-breath report.
+const MAX_LOG_LINES: usize = 100;
+
+/// The file name of the last-breath report in the crash directory.
+const REPORT_FILE_NAME: &str = "last-breath.json";
+
+/// The interval in which the last-breath report is written to disk.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The most recent log messages.
+static LOGS: Lazy<Mutex<VecDeque<String>>> =
+    Lazy::new(|| Mutex::new(VecDeque::with_capacity(MAX_LOG_LINES)));
+
+/// Custom context added to the last-breath report.
+static CONTEXT: Lazy<Mutex<BTreeMap<String, String>>> = Lazy::new(Default::default);
+
+/// Incremented whenever the contents of the last-breath report change.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Adds a value to the context of the last-breath report, such as a hash of the active config.
+pub fn set_context(key: impl Into<String>, value: impl Into<String>) {
+    if let Ok(mut context) = CONTEXT.lock() {
+        context.insert(key.into(), value.into());
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Writer for log messages into the ring buffer of the last-breath report.
+struct RingBufferWriter;
+
+impl Write for RingBufferWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Ok(mut logs) = LOGS.lock() {
+            if logs.len() >= MAX_LOG_LINES {
+                logs.pop_front();
+            }
+            logs.push_back(String::from_utf8_lossy(buf).trim_end().to_owned());
+            GENERATION.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns a layer that keeps the most recent log messages for the last-breath report.
+pub(crate) fn ring_buffer_layer<S>() -> impl Layer<S> + Send + Sync
+where
+    S: Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    tracing_subscriber::fmt::layer()
+        .with_writer(|| RingBufferWriter)
+        .with_target(true)
+        .with_ansi(false)
+        .compact()
+}
+
+/// The report of Relay's state attached to crash reports.
+#[derive(Debug, Serialize)]
+struct LastBreath {
+    release: &'static str,
+    pid: u32,
+    timestamp: String,
+    context: BTreeMap<String, String>,
+    logs: Vec<String>,
+}
+
+impl LastBreath {
+    fn capture() -> Self {
+        Self {
+            release: RELEASE,
+            pid: std::process::id(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            context: CONTEXT.lock().map(|c| c.clone()).unwrap_or_default(),
+            logs: LOGS
+                .lock()
+                .map(|l| l.iter().cloned().collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Writes the report atomically, so that a crash never observes a partial file.
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(temp_path, path)
+    }
+}
+
+/// Captures an envelope from the native crash reporter using the main Sentry SDK.
+fn capture_native_envelope(data: &[u8]) {
+    if let Some(client) = sentry::Hub::main().client() {
+        match sentry::Envelope::from_slice(data) {
+            Ok(envelope) => client.send_envelope(envelope),
+            Err(error) => {
+                let error = &error as &dyn std::error::Error;
+                crate::error!(error, "failed to capture crash")
+            }
+        }
+    } else {
+        crate::error!("failed to capture crash: no sentry client registered");
+    }
+}
+
+/// Installs the native crash handler writing to the given directory.
+///
+/// This also starts a background thread that keeps the last-breath report up to date.
+pub(crate) fn install(sentry: &SentryConfig, directory: &Path) {
+    if let Err(error) = std::fs::create_dir_all(directory) {
+        let error = &error as &dyn std::error::Error;
+        crate::error!(error, "failed to create crash directory");
+        return;
+    }
+
+    let report_path = directory.join(REPORT_FILE_NAME);
+    if let Err(error) = LastBreath::capture().write_to(&report_path) {
+        let error = &error as &dyn std::error::Error;
+        crate::error!(error, "failed to write last-breath report");
+    }
+
+    let thread_path = report_path.clone();
+    let spawned = std::thread::Builder::new()
+        .name("last-breath".to_owned())
+        .spawn(move || {
+            let mut written = GENERATION.load(Ordering::Relaxed);
+            loop {
+                std::thread::sleep(REPORT_INTERVAL);
+
+                let generation = GENERATION.load(Ordering::Relaxed);
+                if generation != written {
+                    LastBreath::capture().write_to(&thread_path).ok();
+                    written = generation;
+                }
+            }
+        });
+
+    if let Err(error) = spawned {
+        let error = &error as &dyn std::error::Error;
+        crate::error!(error, "failed to start last-breath report thread");
+    }
+
+    // Without a DSN, sentry-native does not install the crash handler. The DSN is also used when
+    // uploads are disabled, in which case crash reports remain in the crash directory.
+    let dsn = sentry
+        .dsn
+        .as_ref()
+        .map(|d| d.to_string())
+        .unwrap_or_default();
+
+    let mut handler = relay_crash::CrashHandler::new(&dsn, directory);
+    handler
+        .release(Some(RELEASE))
+        .environment(sentry.environment.as_deref())
+        .attachment(&report_path);
+
+    if sentry.upload_crashes && sentry.enabled_dsn().is_some() {
+        handler.transport(capture_native_envelope);
+    }
+
+    handler.install();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        for i in 0..MAX_LOG_LINES + 10 {
+            RingBufferWriter
+                .write_all(format!("line {i}\n").as_bytes())
+                .unwrap();
+        }
+
+        let logs = LOGS.lock().unwrap();
+        assert_eq!(logs.len(), MAX_LOG_LINES);
+        assert_eq!(logs.front().map(String::as_str), Some("line 10"));
+        assert_eq!(logs.back().map(String::as_str), Some("line 109"));
+    }
+
+    #[test]
+    fn test_write_report() {
+        let dir = std::env::temp_dir().join(format!("relay-crash-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(REPORT_FILE_NAME);
+
+        set_context("config_hash", "abc");
+        LastBreath::capture().write_to(&path).unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(report["release"], RELEASE);
+        assert_eq!(report["context"]["config_hash"], "abc");
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -120,6 +120,12 @@ pub use setup::*;
 #[cfg(feature = "dashboard")]
 pub mod dashboard;
 
+#[cfg(feature = "otlp")]
+pub mod otlp;
+
+#[cfg(feature = "crash-handler")]
+pub mod crash;
+
 #[cfg(feature = "test")]
 mod test;
 #[cfg(feature = "test")]
@@ -131,7 +137,13 @@ pub use sentry::integrations::tower;
 // Expose the minimal log facade.
 #[doc(inline)]
 pub use tracing::{debug, error, info, trace, warn, Level};
+// Expose spans to trace the lifecycle of envelopes.
+//
+// Lifecycle spans are recorded at DEBUG level, since the Sentry integration turns INFO spans into
+// transactions and the log output lists INFO spans on every line.
+#[doc(inline)]
+pub use tracing::{debug_span, Instrument, Span};
 // Expose the minimal error reporting API.
 #[doc(inline)]
-pub use sentry_core::{capture_error, configure_scope, protocol, with_scope, Hub};
+pub use sentry_core::{capture_error, configure_scope, protocol, with_scope, Hub, SentryFutureExt};
 pub use utils::*;
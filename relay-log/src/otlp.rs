@@ -0,0 +1,117 @@
+//! Export of tracing spans to an OpenTelemetry collector via OTLP.
+//!
+//! This module provides a [`Layer`] that sends spans recorded in Relay to the configured
+//! [`OtlpConfig::endpoint`]. Sampling is decided once at the root span, so an envelope is either
+//! traced through its entire lifecycle or not at all.
+//!
+//! The layer is installed with the logging system, before Relay's runtime exists. The exporter
+//! requires a Tokio runtime, so it is created later by [`start`]. Spans that end before the export
+//! has started are discarded.
+
+use std::sync::Mutex;
+
+use once_cell::sync::OnceCell;
+use opentelemetry::trace::{TraceError, TraceResult, TracerProvider as _};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{SpanExporterBuilder, WithExportConfig};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::trace::{self, BatchSpanProcessor, Sampler, Span, SpanProcessor};
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::OtlpConfig;
+
+/// The span processor sending batches to the exporter, created by [`start`].
+static PROCESSOR: OnceCell<Mutex<BatchSpanProcessor<runtime::Tokio>>> = OnceCell::new();
+
+/// Forwards spans to the [`PROCESSOR`] once the export has started.
+#[derive(Debug)]
+struct DeferredProcessor;
+
+impl DeferredProcessor {
+    fn with<R>(&self, f: impl FnOnce(&mut BatchSpanProcessor<runtime::Tokio>) -> R) -> Option<R> {
+        let processor = PROCESSOR.get()?;
+        let mut guard = processor.lock().unwrap_or_else(|e| e.into_inner());
+        Some(f(&mut guard))
+    }
+}
+
+impl SpanProcessor for DeferredProcessor {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.with(|processor| processor.on_start(span, cx));
+    }
+
+    fn on_end(&self, span: SpanData) {
+        self.with(|processor| processor.on_end(span));
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.with(|processor| processor.force_flush())
+            .unwrap_or(Ok(()))
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        self.with(|processor| processor.shutdown())
+            .unwrap_or(Ok(()))
+    }
+}
+
+/// Creates a layer exporting spans via OTLP, if enabled in the config.
+///
+/// Returns `None` if the export is disabled. Spans are only exported after [`start`] has been
+/// called.
+pub fn layer<S>(config: &OtlpConfig) -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    if !config.enabled {
+        return None;
+    }
+
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_rate)));
+    let resource = Resource::new([KeyValue::new("service.name", config.service_name.clone())]);
+
+    let provider = trace::TracerProvider::builder()
+        .with_span_processor(DeferredProcessor)
+        .with_config(
+            trace::config()
+                .with_sampler(sampler)
+                .with_resource(resource),
+        )
+        .build();
+
+    let tracer = provider.tracer("relay");
+    // The global provider keeps the processor alive and is flushed by `shutdown`.
+    opentelemetry::global::set_tracer_provider(provider);
+
+    Some(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Creates the OTLP exporter and starts exporting spans.
+///
+/// This must be called from within a Tokio runtime, which runs the exporter until [`shutdown`].
+/// It has no effect if the export is disabled or has already started.
+pub fn start(config: &OtlpConfig) -> Result<(), TraceError> {
+    if !config.enabled || PROCESSOR.get().is_some() {
+        return Ok(());
+    }
+
+    let exporter = opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(&config.endpoint);
+    let exporter = SpanExporterBuilder::from(exporter).build_span_exporter()?;
+
+    let processor = BatchSpanProcessor::builder(exporter, runtime::Tokio).build();
+    PROCESSOR.set(Mutex::new(processor)).ok();
+
+    Ok(())
+}
+
+/// Flushes all pending spans and shuts down the OTLP exporter.
+///
+/// This must be called before the runtime passed to [`start`] shuts down.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
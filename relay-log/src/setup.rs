@@ -1,23 +1,36 @@
 use std::borrow::Cow;
-use std::env;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::{env, mem};
 
+use once_cell::sync::OnceCell;
 use sentry::types::Dsn;
 use serde::{Deserialize, Serialize};
-use tracing::{level_filters::LevelFilter, Level};
-use tracing_subscriber::{prelude::*, EnvFilter, Layer};
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::{prelude::*, reload, EnvFilter, Layer, Registry};
 
 #[cfg(feature = "dashboard")]
 use crate::dashboard;
 
 /// The full release name including the Relay version and SHA.
-const RELEASE: &str = std::env!("RELAY_RELEASE");
+pub(crate) const RELEASE: &str = std::env!("RELAY_RELEASE");
+
+/// A boxed layer that formats and writes log output.
+type FormatLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Handle to change the level and module filters of the log output after initialization.
+static FILTER_HANDLE: OnceCell<reload::Handle<Targets, Registry>> = OnceCell::new();
+
+/// Handle to change the format of the log output after initialization.
+static FORMAT_HANDLE: OnceCell<reload::Handle<FormatLayer, Registry>> = OnceCell::new();
 
 // Import CRATE_NAMES, which lists all crates in the workspace.
 include!(concat!(env!("OUT_DIR"), "/constants.gen.rs"));
 
 /// Controls the log format.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum LogFormat {
     /// Auto detect the best format.
@@ -88,14 +101,61 @@ mod level_serde {
     }
 }
 
+mod levels_serde {
+    use std::collections::BTreeMap;
+
+    use serde::de::{Error, Unexpected};
+    use serde::ser::SerializeMap;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use tracing::Level;
+
+    pub fn serialize<S>(filters: &BTreeMap<String, Level>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(filters.len()))?;
+        for (module, level) in filters {
+            map.serialize_entry(module, level.as_str())?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<BTreeMap<String, Level>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        BTreeMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(module, level)| match level.parse() {
+                Ok(level) => Ok((module, level)),
+                Err(_) => Err(Error::invalid_value(
+                    Unexpected::Str(&level),
+                    &"a log level",
+                )),
+            })
+            .collect()
+    }
+}
+
 /// Controls the logging system.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct LogConfig {
     /// The log level for Relay.
     #[serde(with = "level_serde")]
+    #[cfg_attr(feature = "jsonschema", schemars(with = "String"))]
     pub level: Level,
 
+    /// Log levels for individual modules, overriding `level`.
+    ///
+    /// Keys are module paths such as `relay_server::actors::processor`, which also apply to all
+    /// submodules. Levels of third-party crates cannot exceed their defaults unless `RUST_LOG` is
+    /// set.
+    #[serde(with = "levels_serde", skip_serializing_if = "BTreeMap::is_empty")]
+    #[cfg_attr(feature = "jsonschema", schemars(with = "BTreeMap<String, String>"))]
+    pub filters: BTreeMap<String, Level>,
+
     /// Controls the log output format.
     ///
     /// Defaults to [`LogFormat::Auto`], which detects the best format based on the TTY.
@@ -110,27 +170,75 @@ pub struct LogConfig {
     ///
     /// Defaults to `0.0` for release builds and `1.0` for local development builds.
     pub traces_sample_rate: f32,
+
+    /// Configures the export of spans to an OpenTelemetry collector.
+    ///
+    /// Requires Relay to be built with the `otlp` feature.
+    pub otlp: OtlpConfig,
 }
 
 impl Default for LogConfig {
     fn default() -> Self {
         Self {
             level: Level::INFO,
+            filters: BTreeMap::new(),
             format: LogFormat::Auto,
             enable_backtraces: false,
             #[cfg(debug_assertions)]
             traces_sample_rate: 1.0,
             #[cfg(not(debug_assertions))]
             traces_sample_rate: 0.0,
+            otlp: OtlpConfig::default(),
+        }
+    }
+}
+
+/// Controls the export of spans to an OpenTelemetry collector.
+///
+/// Relay records spans for the lifecycle of every envelope, from receiving the request through
+/// processing and rate limiting to forwarding or producing it. The export allows to inspect where
+/// latency accumulates inside Relay.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct OtlpConfig {
+    /// Enables the export of spans via OTLP.
+    pub enabled: bool,
+
+    /// The gRPC endpoint of the OpenTelemetry collector.
+    ///
+    /// Defaults to `http://localhost:4317`.
+    pub endpoint: String,
+
+    /// The fraction of traces to export, between `0.0` and `1.0`.
+    ///
+    /// Defaults to `0.01`.
+    pub sample_rate: f64,
+
+    /// The service name reported with all spans.
+    ///
+    /// Defaults to `relay`.
+    pub service_name: String,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4317".to_owned(),
+            sample_rate: 0.01,
+            service_name: "relay".to_owned(),
         }
     }
 }
 
 /// Controls interal reporting to Sentry.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct SentryConfig {
     /// The [`DSN`](sentry::types::Dsn) specifying the Project to report to.
+    #[cfg_attr(feature = "jsonschema", schemars(with = "Option<String>"))]
     pub dsn: Option<Dsn>,
 
     /// Enables reporting to Sentry.
@@ -139,9 +247,19 @@ pub struct SentryConfig {
     /// Sets the environment for this service.
     pub environment: Option<Cow<'static, str>>,
 
-    /// Internal. Enables crash handling and sets the absolute path to where minidumps should be
-    /// cached on disk. The path is created if it doesn't exist. Path must be UTF-8.
-    pub _crash_db: Option<PathBuf>,
+    /// Enables native crash handling and sets the absolute path to the directory where minidumps
+    /// and last-breath reports are written.
+    ///
+    /// The directory is created if it doesn't exist. The path must be UTF-8. Requires Relay to be
+    /// built with the `crash-handler` feature.
+    #[serde(alias = "_crash_db")]
+    pub crash_dir: Option<PathBuf>,
+
+    /// Uploads crash reports of previous runs to the DSN on startup.
+    ///
+    /// Requires reporting to Sentry to be `enabled`. Otherwise, crash reports remain in the
+    /// `crash_dir` for manual retrieval. Defaults to `true`.
+    pub upload_crashes: bool,
 }
 
 impl SentryConfig {
@@ -159,27 +277,12 @@ impl Default for SentryConfig {
                 .ok(),
             enabled: false,
             environment: None,
-            _crash_db: None,
+            crash_dir: None,
+            upload_crashes: true,
         }
     }
 }
 
-/// Captures an envelope from the native crash reporter using the main Sentry SDK.
-#[cfg(feature = "relay-crash")]
-fn capture_native_envelope(data: &[u8]) {
-    if let Some(client) = sentry::Hub::main().client() {
-        match sentry::Envelope::from_slice(data) {
-            Ok(envelope) => client.send_envelope(envelope),
-            Err(error) => {
-                let error = &error as &dyn std::error::Error;
-                crate::error!(error, "failed to capture crash")
-            }
-        }
-    } else {
-        crate::error!("failed to capture crash: no sentry client registered");
-    }
-}
-
 /// Configures the given log level for all of Relay's crates.
 fn get_default_filters() -> EnvFilter {
     // Configure INFO as default, except for crates that are very spammy on INFO level.
@@ -199,6 +302,40 @@ fn get_default_filters() -> EnvFilter {
     env_filter
 }
 
+/// Creates the layer writing log output in the given format to `stderr`.
+fn format_layer(format: LogFormat) -> FormatLayer {
+    let subscriber = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(true);
+
+    match (format, console::user_attended()) {
+        (LogFormat::Auto, true) | (LogFormat::Pretty, _) => {
+            subscriber.compact().without_time().boxed()
+        }
+        (LogFormat::Auto, false) | (LogFormat::Simplified, _) => {
+            subscriber.with_ansi(false).boxed()
+        }
+        (LogFormat::Json, _) => subscriber
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_file(true)
+            .with_line_number(true)
+            .boxed(),
+    }
+}
+
+/// Creates the filter for the log output from the level and module filters in the config.
+fn log_filter(config: &LogConfig) -> Targets {
+    Targets::new().with_default(config.level).with_targets(
+        config
+            .filters
+            .iter()
+            .map(|(module, level)| (module, *level)),
+    )
+}
+
 /// Initialize the logging system and reporting to Sentry.
 ///
 /// # Example
@@ -218,29 +355,14 @@ pub fn init(config: &LogConfig, sentry: &SentryConfig) {
         env::set_var("RUST_BACKTRACE", "full");
     }
 
-    let subscriber = tracing_subscriber::fmt::layer()
-        .with_writer(std::io::stderr)
-        .with_target(true);
+    let (format, format_handle) = reload::Layer::new(format_layer(config.format));
+    FORMAT_HANDLE.set(format_handle).ok();
 
-    let format = match (config.format, console::user_attended()) {
-        (LogFormat::Auto, true) | (LogFormat::Pretty, _) => {
-            subscriber.compact().without_time().boxed()
-        }
-        (LogFormat::Auto, false) | (LogFormat::Simplified, _) => {
-            subscriber.with_ansi(false).boxed()
-        }
-        (LogFormat::Json, _) => subscriber
-            .json()
-            .flatten_event(true)
-            .with_current_span(true)
-            .with_span_list(true)
-            .with_file(true)
-            .with_line_number(true)
-            .boxed(),
-    };
+    let (filter, filter_handle) = reload::Layer::new(log_filter(config));
+    FILTER_HANDLE.set(filter_handle).ok();
 
     let logs_subscriber = tracing_subscriber::registry()
-        .with(format.with_filter(LevelFilter::from(config.level)))
+        .with(format.with_filter(filter))
         .with(sentry::integrations::tracing::layer())
         .with(match env::var(EnvFilter::DEFAULT_ENV) {
             Ok(value) => EnvFilter::new(value),
@@ -251,6 +373,18 @@ pub fn init(config: &LogConfig, sentry: &SentryConfig) {
     #[cfg(feature = "dashboard")]
     let logs_subscriber = logs_subscriber.with(dashboard::dashboard_subscriber());
 
+    #[cfg(feature = "otlp")]
+    let logs_subscriber = logs_subscriber.with(crate::otlp::layer(&config.otlp));
+
+    // Keep recent log messages for crash reports, subject to the same filters as the log output.
+    #[cfg(feature = "crash-handler")]
+    let logs_subscriber = logs_subscriber.with(
+        sentry
+            .crash_dir
+            .as_ref()
+            .map(|_| crate::crash::ring_buffer_layer().with_filter(log_filter(config))),
+    );
+
     logs_subscriber.init();
 
     if let Some(dsn) = sentry.enabled_dsn() {
@@ -270,15 +404,34 @@ pub fn init(config: &LogConfig, sentry: &SentryConfig) {
 
     // Initialize native crash reporting after the Rust SDK, so that `capture_native_envelope` has
     // access to an initialized Hub to capture crashes from the previous run.
-    #[cfg(feature = "relay-crash")]
-    {
-        if let Some(dsn) = sentry.enabled_dsn().map(|d| d.to_string()) {
-            if let Some(db) = sentry._crash_db.as_deref() {
-                relay_crash::CrashHandler::new(dsn.as_str(), db)
-                    .transport(capture_native_envelope)
-                    .release(Some(RELEASE))
-                    .install();
-            }
-        }
+    #[cfg(feature = "crash-handler")]
+    if let Some(directory) = sentry.crash_dir.as_deref() {
+        crate::crash::install(sentry, directory);
+    }
+}
+
+/// Changes the level of the log output at runtime.
+///
+/// Module filters configured in [`LogConfig::filters`] remain in place. This has no effect if the
+/// logging system has not been initialized with [`init`].
+pub fn set_level(level: Level) {
+    if let Some(handle) = FILTER_HANDLE.get() {
+        handle
+            .modify(|filter| *filter = mem::take(filter).with_default(level))
+            .ok();
+    }
+}
+
+/// Applies the level, module filters, and format of the given config at runtime.
+///
+/// All other settings of the logging system require a restart. This has no effect if the logging
+/// system has not been initialized with [`init`].
+pub fn reconfigure(config: &LogConfig) {
+    if let Some(handle) = FILTER_HANDLE.get() {
+        handle.reload(log_filter(config)).ok();
+    }
+
+    if let Some(handle) = FORMAT_HANDLE.get() {
+        handle.reload(format_layer(config.format)).ok();
     }
 }
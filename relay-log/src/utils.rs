@@ -18,6 +18,14 @@ pub fn backtrace_enabled() -> bool {
     )
 }
 
+/// Returns `true` if a Sentry client is bound to the current hub and reports errors.
+///
+/// Use this to skip collecting data that is only attached to error reports, such as breadcrumbs.
+#[cfg(feature = "sentry")]
+pub fn sentry_enabled() -> bool {
+    sentry::Hub::current().is_active_and_usage_safe()
+}
+
 /// Logs an error to the configured logger or `stderr` if not yet configured.
 ///
 /// Prefer to use [`relay_log::error`](crate::error) over this function whenever possible. This
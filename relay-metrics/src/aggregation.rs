@@ -13,11 +13,14 @@ use relay_system::{
     AsyncResponse, Controller, FromMessage, Interface, NoResponse, Recipient, Sender, Service,
     Shutdown,
 };
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::time::Instant;
 
 use crate::bucket::{Bucket, BucketValue, DistributionValue};
+use crate::cardinality::{CardinalityLimit, CardinalityLimiter};
 use crate::protocol::{self, MetricNamespace, MetricResourceIdentifier};
 use crate::statsd::{MetricCounters, MetricGauges, MetricHistograms, MetricSets, MetricTimers};
 
@@ -95,6 +98,8 @@ fn split_at(mut bucket: Bucket, size: usize) -> (Option<Bucket>, Option<Bucket>)
             (Some(bucket), Some(new_bucket))
         }
         BucketValue::Gauge(_) => (None, Some(bucket)),
+        // Sketches are bounded in size and cannot be split.
+        BucketValue::DistributionSketch(_) => (None, Some(bucket)),
     }
 }
 
@@ -152,6 +157,9 @@ enum AggregateMetricsErrorKind {
     /// A metric bucket is too large for the per-project bytes limit.
     #[error("project metrics limit exceeded")]
     ProjectLimitExceeded,
+    /// A metric bucket adds a new series beyond a cardinality limit.
+    #[error("cardinality limit exceeded")]
+    CardinalityLimited,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -190,6 +198,7 @@ impl BucketKey {
 
 /// Configuration value for [`AggregatorConfig::shift_key`].
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum ShiftKey {
     /// Shifts the flush time by an offset based on the [`ProjectKey`].
@@ -206,8 +215,56 @@ pub enum ShiftKey {
     Bucket,
 }
 
+/// Determines how the last value of gauges is computed when merging buckets.
+///
+/// Gauges always track the minimum, maximum, sum, and count of their values. The strategy only
+/// controls the `last` value, which is reported as the value of the gauge.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum GaugeMerge {
+    /// The value merged last wins.
+    ///
+    /// This depends on the order in which buckets arrive.
+    #[default]
+    Last,
+    /// The smallest value is kept.
+    Min,
+    /// The largest value is kept.
+    Max,
+    /// Values are added up, for example for gauges reported by multiple instances.
+    Sum,
+    /// The average of all values.
+    Average,
+}
+
+/// Bucketing and flushing parameters that override the [`AggregatorConfig`] for a namespace.
+///
+/// Fields that are not set fall back to the values of the aggregator config.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct NamespaceConfig {
+    /// Overrides [`AggregatorConfig::bucket_interval`] for this namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket_interval: Option<u64>,
+
+    /// Overrides [`AggregatorConfig::initial_delay`] for this namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_delay: Option<u64>,
+
+    /// Overrides [`AggregatorConfig::shift_key`] for this namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shift_key: Option<ShiftKey>,
+
+    /// Overrides [`AggregatorConfig::gauge_merge`] for this namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gauge_merge: Option<GaugeMerge>,
+}
+
 /// Parameters used by the [`AggregatorService`].
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 pub struct AggregatorConfig {
     /// Determines the wall clock time interval for buckets in seconds.
@@ -296,17 +353,87 @@ pub struct AggregatorConfig {
     /// This prevents flushing all buckets from a bucket interval at the same
     /// time by computing an offset from the hash of the given key.
     pub shift_key: ShiftKey,
+
+    /// Relative accuracy of sketches that summarize distributions.
+    ///
+    /// If set, distributions are summarized into [sketches](crate::DistributionSketch) instead of
+    /// storing all raw values, which bounds the memory and payload size of high-volume
+    /// distributions. Quantiles estimated from the sketch deviate from the exact value by at most
+    /// this fraction, for example `0.01` for 1%. Only enable this if the upstream accepts
+    /// sketches.
+    ///
+    /// Defaults to `None`, i.e. raw values are stored.
+    pub distribution_sketch_accuracy: Option<f64>,
+
+    /// Limits on the number of distinct series per project.
+    ///
+    /// Buckets that would add a new series beyond a limit are rejected before aggregation. This
+    /// stops tag explosions at the edge instead of forwarding them upstream.
+    ///
+    /// Defaults to no limits.
+    pub cardinality_limits: Vec<CardinalityLimit>,
+
+    /// Bucketing and flushing parameters per metric namespace.
+    ///
+    /// This allows, for example, to aggregate session metrics into larger buckets than custom
+    /// metrics, or to flush them with a different delay. Namespaces without an entry use the
+    /// parameters above.
+    ///
+    /// Defaults to no overrides.
+    pub namespaces: BTreeMap<MetricNamespace, NamespaceConfig>,
+
+    /// How the last value of gauges is computed when merging buckets.
+    ///
+    /// Defaults to `last`, i.e. the value merged last wins.
+    pub gauge_merge: GaugeMerge,
+
+    /// Strategies to merge gauges by metric name (MRI).
+    ///
+    /// These take precedence over the strategies configured for namespaces.
+    ///
+    /// Defaults to no overrides.
+    pub gauge_merge_overrides: BTreeMap<String, GaugeMerge>,
 }
 
 impl AggregatorConfig {
-    /// Returns the time width buckets.
-    fn bucket_interval(&self) -> Duration {
-        Duration::from_secs(self.bucket_interval)
+    /// Returns the time width of buckets in the given namespace in seconds.
+    fn bucket_interval(&self, namespace: MetricNamespace) -> u64 {
+        self.namespaces
+            .get(&namespace)
+            .and_then(|config| config.bucket_interval)
+            .unwrap_or(self.bucket_interval)
+            .max(1)
     }
 
     /// Returns the initial flush delay after the end of a bucket's original time window.
-    fn initial_delay(&self) -> Duration {
-        Duration::from_secs(self.initial_delay)
+    fn initial_delay(&self, namespace: MetricNamespace) -> Duration {
+        let initial_delay = self
+            .namespaces
+            .get(&namespace)
+            .and_then(|config| config.initial_delay)
+            .unwrap_or(self.initial_delay);
+
+        Duration::from_secs(initial_delay)
+    }
+
+    /// Returns the key used to shift flushes of buckets in the given namespace.
+    fn shift_key(&self, namespace: MetricNamespace) -> &ShiftKey {
+        self.namespaces
+            .get(&namespace)
+            .and_then(|config| config.shift_key.as_ref())
+            .unwrap_or(&self.shift_key)
+    }
+
+    /// Returns the strategy to merge gauges with the given metric name and namespace.
+    fn gauge_merge(&self, metric_name: &str, namespace: MetricNamespace) -> GaugeMerge {
+        if let Some(strategy) = self.gauge_merge_overrides.get(metric_name) {
+            return *strategy;
+        }
+
+        self.namespaces
+            .get(&namespace)
+            .and_then(|config| config.gauge_merge)
+            .unwrap_or(self.gauge_merge)
     }
 
     /// The delay to debounce backdated flushes.
@@ -314,6 +441,14 @@ impl AggregatorConfig {
         Duration::from_secs(self.debounce_delay)
     }
 
+    /// Returns the relative accuracy of distribution sketches, if they are enabled.
+    ///
+    /// Accuracies outside of the range `(0, 1)` disable sketches.
+    fn distribution_sketch_accuracy(&self) -> Option<f64> {
+        self.distribution_sketch_accuracy
+            .filter(|alpha| *alpha > 0.0 && *alpha < 1.0)
+    }
+
     /// Returns the valid range for metrics timestamps.
     ///
     /// Metrics or buckets outside of this range should be discarded.
@@ -326,17 +461,19 @@ impl AggregatorConfig {
 
     /// Determines the target bucket for an incoming bucket timestamp and bucket width.
     ///
-    /// We select the output bucket which overlaps with the center of the incoming bucket.
-    /// Fails if timestamp is too old or too far into the future.
+    /// We select the output bucket of the namespace which overlaps with the center of the incoming
+    /// bucket. Fails if timestamp is too old or too far into the future.
     fn get_bucket_timestamp(
         &self,
         timestamp: UnixTimestamp,
         bucket_width: u64,
+        namespace: MetricNamespace,
     ) -> Result<UnixTimestamp, AggregateMetricsError> {
         // Find middle of the input bucket to select a target
         let ts = timestamp.as_secs().saturating_add(bucket_width / 2);
         // Align target_timestamp to output bucket width
-        let ts = (ts / self.bucket_interval) * self.bucket_interval;
+        let bucket_interval = self.bucket_interval(namespace);
+        let ts = (ts / bucket_interval) * bucket_interval;
         let output_timestamp = UnixTimestamp::from_secs(ts);
 
         if !self.timestamp_range().contains(&output_timestamp) {
@@ -356,15 +493,16 @@ impl AggregatorConfig {
     /// is, buckets that lie in the past, are flushed after the shorter `debounce_delay`.
     fn get_flush_time(&self, bucket_key: &BucketKey) -> Instant {
         let now = Instant::now();
+        let namespace = bucket_key.namespace();
         let mut flush = None;
 
         if let MonotonicResult::Instant(instant) = bucket_key.timestamp.to_instant() {
             let instant = Instant::from_std(instant);
-            let bucket_end = instant + self.bucket_interval();
-            let initial_flush = bucket_end + self.initial_delay();
+            let bucket_end = instant + Duration::from_secs(self.bucket_interval(namespace));
+            let initial_flush = bucket_end + self.initial_delay(namespace);
             // If the initial flush is still pending, use that.
             if initial_flush > now {
-                flush = Some(initial_flush + self.flush_time_shift(bucket_key));
+                flush = Some(initial_flush + self.flush_time_shift(bucket_key, namespace));
             }
         }
 
@@ -385,8 +523,8 @@ impl AggregatorConfig {
     // Shift deterministically within one bucket interval based on the project or bucket key.
     //
     // This distributes buckets over time to prevent peaks.
-    fn flush_time_shift(&self, bucket: &BucketKey) -> Duration {
-        let hash_value = match self.shift_key {
+    fn flush_time_shift(&self, bucket: &BucketKey, namespace: MetricNamespace) -> Duration {
+        let hash_value = match self.shift_key(namespace) {
             ShiftKey::Project => {
                 let mut hasher = FnvHasher::default();
                 hasher.write(bucket.project_key.as_str().as_bytes());
@@ -394,7 +532,7 @@ impl AggregatorConfig {
             }
             ShiftKey::Bucket => bucket.hash64(),
         };
-        let shift_millis = hash_value % (self.bucket_interval * 1000);
+        let shift_millis = hash_value % (self.bucket_interval(namespace) * 1000);
 
         Duration::from_millis(shift_millis)
     }
@@ -416,6 +554,11 @@ impl Default for AggregatorConfig {
             max_total_bucket_bytes: None,
             max_project_key_bucket_bytes: None,
             shift_key: ShiftKey::default(),
+            distribution_sketch_accuracy: None,
+            cardinality_limits: Vec::new(),
+            namespaces: BTreeMap::new(),
+            gauge_merge: GaugeMerge::default(),
+            gauge_merge_overrides: BTreeMap::new(),
         }
     }
 }
@@ -644,8 +787,7 @@ impl<T: Iterator<Item = Bucket>> FusedIterator for CappedBucketIter<T> {}
 #[derive(Debug)]
 pub struct AcceptsMetrics;
 
-/// Used only for testing the `AggregatorService`.
-#[cfg(test)]
+/// Returns the current number of buckets in the aggregator. Used for health checks.
 #[derive(Debug)]
 pub struct BucketCountInquiry;
 
@@ -701,8 +843,7 @@ pub enum Aggregator {
     /// Merge the buckets.
     MergeBuckets(MergeBuckets),
 
-    /// Message is used only for tests to get the current number of buckets in `AggregatorService`.
-    #[cfg(test)]
+    /// Returns the current number of buckets.
     BucketCountInquiry(BucketCountInquiry, Sender<usize>),
 }
 
@@ -722,7 +863,6 @@ impl FromMessage<MergeBuckets> for Aggregator {
     }
 }
 
-#[cfg(test)]
 impl FromMessage<BucketCountInquiry> for Aggregator {
     type Response = AsyncResponse<usize>;
     fn from_message(message: BucketCountInquiry, sender: Sender<usize>) -> Self {
@@ -768,6 +908,7 @@ pub struct AggregatorService {
     receiver: Option<Recipient<FlushBuckets, NoResponse>>,
     state: AggregatorState,
     cost_tracker: CostTracker,
+    cardinality_limiter: CardinalityLimiter,
 }
 
 impl AggregatorService {
@@ -795,6 +936,7 @@ impl AggregatorService {
             receiver,
             state: AggregatorState::Running,
             cost_tracker: CostTracker::default(),
+            cardinality_limiter: CardinalityLimiter::default(),
         }
     }
 
@@ -928,16 +1070,16 @@ impl AggregatorService {
         project_key: ProjectKey,
         bucket: Bucket,
     ) -> Result<(), AggregateMetricsError> {
-        let timestamp = self
-            .config
-            .get_bucket_timestamp(bucket.timestamp, bucket.width)?;
         let key = BucketKey {
             project_key,
-            timestamp,
+            timestamp: bucket.timestamp,
             metric_name: bucket.name,
             tags: bucket.tags,
         };
-        let key = Self::validate_bucket_key(key, &self.config)?;
+        let mut key = Self::validate_bucket_key(key, &self.config)?;
+        key.timestamp =
+            self.config
+                .get_bucket_timestamp(bucket.timestamp, bucket.width, key.namespace())?;
 
         // XXX: This is not a great implementation of cost enforcement.
         //
@@ -979,15 +1121,42 @@ impl AggregatorService {
                     aggregator = &self.name,
                     namespace = entry.key().namespace().as_str(),
                 );
+                let gauge_merge = match bucket.value {
+                    BucketValue::Gauge(_) => self
+                        .config
+                        .gauge_merge(&entry.key().metric_name, entry.key().namespace()),
+                    _ => GaugeMerge::default(),
+                };
                 let bucket_value = &mut entry.get_mut().value;
                 let cost_before = bucket_value.cost();
                 bucket_value
-                    .merge(bucket.value)
+                    .merge_with(bucket.value, gauge_merge)
                     .map_err(|_| AggregateMetricsErrorKind::InvalidTypes)?;
                 let cost_after = bucket_value.cost();
                 added_cost = cost_after.saturating_sub(cost_before);
             }
             Entry::Vacant(entry) => {
+                let namespace = entry.key().namespace();
+                let limited = self.cardinality_limiter.check(
+                    &self.config.cardinality_limits,
+                    project_key,
+                    namespace,
+                    &entry.key().metric_name,
+                    &entry.key().tags,
+                );
+                if let Err(limit) = limited {
+                    relay_log::debug!(
+                        limit = limit.limit,
+                        "metric bucket dropped due to cardinality limit"
+                    );
+                    relay_statsd::metric!(
+                        counter(MetricCounters::CardinalityLimited) += 1,
+                        aggregator = &self.name,
+                        namespace = namespace.as_str(),
+                    );
+                    return Err(AggregateMetricsErrorKind::CardinalityLimited.into());
+                }
+
                 relay_statsd::metric!(
                     counter(MetricCounters::MergeMiss) += 1,
                     aggregator = &self.name,
@@ -1000,7 +1169,10 @@ impl AggregatorService {
                 );
 
                 let flush_at = self.config.get_flush_time(entry.key());
-                let value = bucket.value;
+                let mut value = bucket.value;
+                if let Some(alpha) = self.config.distribution_sketch_accuracy() {
+                    value.summarize(alpha);
+                }
                 added_cost = entry.key().cost() + value.cost();
                 entry.insert(QueuedBucket::new(flush_at, value));
             }
@@ -1024,7 +1196,10 @@ impl AggregatorService {
     {
         for bucket in buckets.into_iter() {
             if let Err(error) = self.merge(project_key, bucket) {
-                relay_log::error!(error = &error as &dyn Error);
+                // Buckets dropped by cardinality limits are expected and tracked in statsd.
+                if !matches!(error.kind, AggregateMetricsErrorKind::CardinalityLimited) {
+                    relay_log::error!(error = &error as &dyn Error);
+                }
             }
         }
 
@@ -1050,13 +1225,16 @@ impl AggregatorService {
 
         let force = matches!(&self.state, AggregatorState::ShuttingDown);
 
+        self.cardinality_limiter
+            .prune(&self.config.cardinality_limits);
+
         let mut stats = HashMap::new();
 
         relay_statsd::metric!(
             timer(MetricTimers::BucketsScanDuration),
             aggregator = &self.name,
             {
-                let bucket_interval = self.config.bucket_interval;
+                let config = &self.config;
                 let cost_tracker = &mut self.cost_tracker;
                 self.buckets.retain(|key, entry| {
                     if force || entry.elapsed() {
@@ -1073,7 +1251,7 @@ impl AggregatorService {
 
                         let bucket = Bucket {
                             timestamp: key.timestamp,
-                            width: bucket_interval,
+                            width: config.bucket_interval(key.namespace()),
                             name: key.metric_name.clone(),
                             value,
                             tags: key.tags.clone(),
@@ -1224,7 +1402,6 @@ impl AggregatorService {
         match msg {
             Aggregator::AcceptsMetrics(_, sender) => self.handle_accepts_metrics(sender),
             Aggregator::MergeBuckets(msg) => self.handle_merge_buckets(msg),
-            #[cfg(test)]
             Aggregator::BucketCountInquiry(_, sender) => sender.send(self.buckets.len()),
         }
     }
@@ -1292,7 +1469,7 @@ mod tests {
     use similar_asserts::assert_eq;
 
     use super::*;
-    use crate::{dist, GaugeValue};
+    use crate::{dist, DistributionSketch, GaugeValue};
 
     #[derive(Default)]
     struct ReceivedData {
@@ -1455,6 +1632,113 @@ mod tests {
         "#);
     }
 
+    #[test]
+    fn test_aggregator_merge_sketches() {
+        relay_test::setup();
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let config = AggregatorConfig {
+            distribution_sketch_accuracy: Some(0.01),
+            ..test_config()
+        };
+        let mut aggregator = AggregatorService::new(config, None);
+
+        let mut bucket = some_bucket();
+        bucket.name = "d:transactions/foo".to_owned();
+        for value in [1.0, 1.0, 2.0] {
+            bucket.value = BucketValue::distribution(value);
+            aggregator.merge(project_key, bucket.clone()).unwrap();
+        }
+
+        let values: Vec<_> = aggregator.buckets.values().map(|e| &e.value).collect();
+        let expected = DistributionSketch::from_values(0.01, &[1.0, 1.0, 2.0]);
+        assert_eq!(
+            values,
+            [&BucketValue::DistributionSketch(Box::new(expected))]
+        );
+    }
+
+    #[test]
+    fn test_aggregator_gauge_merge() {
+        relay_test::setup();
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let config: AggregatorConfig = serde_json::from_value(serde_json::json!({
+            "namespaces": {"custom": {"gauge_merge": "sum"}},
+            "gauge_merge_overrides": {"g:custom/peak@none": "max"},
+        }))
+        .unwrap();
+        let mut aggregator = AggregatorService::new(
+            AggregatorConfig {
+                namespaces: config.namespaces,
+                gauge_merge_overrides: config.gauge_merge_overrides,
+                ..test_config()
+            },
+            None,
+        );
+
+        for name in [
+            "g:custom/load@none",
+            "g:custom/peak@none",
+            "g:transactions/foo@none",
+        ] {
+            for value in [3.0, 5.0, 1.0] {
+                let mut bucket = some_bucket();
+                bucket.name = name.to_owned();
+                bucket.value = BucketValue::gauge(value);
+                aggregator.merge(project_key, bucket).unwrap();
+            }
+        }
+
+        let mut last: Vec<_> = aggregator
+            .buckets
+            .iter()
+            .map(|(key, entry)| match entry.value {
+                BucketValue::Gauge(gauge) => (key.metric_name.as_str(), gauge.last),
+                _ => unreachable!(),
+            })
+            .collect();
+        last.sort_by(|a, b| a.0.cmp(b.0));
+
+        assert_eq!(
+            last,
+            [
+                ("g:custom/load@none", 9.0),
+                ("g:custom/peak@none", 5.0),
+                ("g:transactions/foo@none", 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aggregator_cardinality_limit() {
+        relay_test::setup();
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let config = AggregatorConfig {
+            cardinality_limits: vec![CardinalityLimit {
+                namespace: Some(MetricNamespace::Transactions),
+                name: None,
+                limit: 1,
+                window: 3600,
+            }],
+            ..test_config()
+        };
+        let mut aggregator = AggregatorService::new(config, None);
+
+        let bucket1 = some_bucket();
+        let mut bucket2 = some_bucket();
+        bucket2.tags.insert("tag".to_owned(), "value".to_owned());
+
+        aggregator.merge(project_key, bucket1.clone()).unwrap();
+        assert!(matches!(
+            aggregator.merge(project_key, bucket2).unwrap_err().kind,
+            AggregateMetricsErrorKind::CardinalityLimited
+        ));
+
+        // The known series is still accepted after the bucket was flushed.
+        aggregator.buckets.clear();
+        aggregator.merge(project_key, bucket1).unwrap();
+        assert_eq!(aggregator.buckets.len(), 1);
+    }
+
     #[test]
     fn test_aggregator_merge_timestamps() {
         relay_test::setup();
@@ -1819,7 +2103,11 @@ mod tests {
 
         assert!(matches!(
             config
-                .get_bucket_timestamp(UnixTimestamp::from_secs(u64::MAX), 2)
+                .get_bucket_timestamp(
+                    UnixTimestamp::from_secs(u64::MAX),
+                    2,
+                    MetricNamespace::Custom
+                )
                 .unwrap_err()
                 .kind,
             AggregateMetricsErrorKind::InvalidTimestamp(_)
@@ -1839,7 +2127,7 @@ mod tests {
         let rounded_now = UnixTimestamp::from_secs(now / 10 * 10);
         assert_eq!(
             config
-                .get_bucket_timestamp(UnixTimestamp::from_secs(now), 0)
+                .get_bucket_timestamp(UnixTimestamp::from_secs(now), 0, MetricNamespace::Custom)
                 .unwrap(),
             rounded_now
         );
@@ -1858,7 +2146,7 @@ mod tests {
         let now = rounded_now + 3;
         assert_eq!(
             config
-                .get_bucket_timestamp(UnixTimestamp::from_secs(now), 20)
+                .get_bucket_timestamp(UnixTimestamp::from_secs(now), 20, MetricNamespace::Custom)
                 .unwrap()
                 .as_secs(),
             rounded_now + 10
@@ -1878,7 +2166,7 @@ mod tests {
         let now = rounded_now + 3;
         assert_eq!(
             config
-                .get_bucket_timestamp(UnixTimestamp::from_secs(now), 23)
+                .get_bucket_timestamp(UnixTimestamp::from_secs(now), 23, MetricNamespace::Custom)
                 .unwrap()
                 .as_secs(),
             rounded_now + 10
@@ -2183,4 +2471,51 @@ mod tests {
         let parsed: AggregatorConfig = serde_json::from_str(json).unwrap();
         assert!(matches!(parsed.shift_key, ShiftKey::Bucket));
     }
+
+    #[test]
+    fn test_namespace_config() {
+        let json = r#"{
+            "bucket_interval": 10,
+            "namespaces": {
+                "sessions": {"bucket_interval": 60, "initial_delay": 0, "shift_key": "bucket"}
+            }
+        }"#;
+        let config: AggregatorConfig = serde_json::from_str(json).unwrap();
+
+        assert_eq!(config.bucket_interval(MetricNamespace::Sessions), 60);
+        assert_eq!(config.bucket_interval(MetricNamespace::Custom), 10);
+        assert_eq!(
+            config.initial_delay(MetricNamespace::Sessions),
+            Duration::ZERO
+        );
+        assert_eq!(
+            config.initial_delay(MetricNamespace::Custom),
+            Duration::from_secs(30)
+        );
+        assert!(matches!(
+            config.shift_key(MetricNamespace::Sessions),
+            ShiftKey::Bucket
+        ));
+        assert!(matches!(
+            config.shift_key(MetricNamespace::Custom),
+            ShiftKey::Project
+        ));
+
+        let rounded_now = UnixTimestamp::now().as_secs() / 60 * 60;
+        let timestamp = UnixTimestamp::from_secs(rounded_now + 15);
+        assert_eq!(
+            config
+                .get_bucket_timestamp(timestamp, 0, MetricNamespace::Sessions)
+                .unwrap()
+                .as_secs(),
+            rounded_now
+        );
+        assert_eq!(
+            config
+                .get_bucket_timestamp(timestamp, 0, MetricNamespace::Custom)
+                .unwrap()
+                .as_secs(),
+            rounded_now + 10
+        );
+    }
 }
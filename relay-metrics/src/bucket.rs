@@ -10,7 +10,7 @@ use crate::protocol::{
     self, hash_set_value, CounterType, DistributionType, GaugeType, MetricResourceIdentifier,
     MetricType, SetType,
 };
-use crate::ParseMetricError;
+use crate::{DistributionSketch, GaugeMerge, ParseMetricError};
 
 const VALUE_SEPARATOR: char = ':';
 
@@ -54,11 +54,28 @@ impl GaugeValue {
 
     /// Merges two gauge snapshots.
     pub fn merge(&mut self, other: Self) {
-        self.last = other.last;
+        self.merge_with(other, GaugeMerge::Last);
+    }
+
+    /// Merges two gauge snapshots and computes the last value with the given strategy.
+    ///
+    /// The minimum, maximum, sum, and count are merged the same way regardless of the strategy.
+    pub fn merge_with(&mut self, other: Self, strategy: GaugeMerge) {
+        let last = match strategy {
+            GaugeMerge::Last | GaugeMerge::Average => other.last,
+            GaugeMerge::Min => self.last.min(other.last),
+            GaugeMerge::Max => self.last.max(other.last),
+            GaugeMerge::Sum => self.last + other.last,
+        };
+
         self.min = self.min.min(other.min);
         self.max = self.max.max(other.max);
         self.sum += other.sum;
         self.count += other.count;
+        self.last = match strategy {
+            GaugeMerge::Average => self.avg(),
+            _ => last,
+        };
     }
 
     /// Returns the average of all values reported in this bucket.
@@ -256,6 +273,23 @@ pub enum BucketValue {
     ///  - `count` adds the count of the newly added gauge (defaulting to `1`)
     #[serde(rename = "g")]
     Gauge(GaugeValue),
+
+    /// A distribution summarized into a sketch ([`MetricType::Distribution`]).
+    ///
+    /// The aggregator summarizes distributions into sketches if
+    /// [`AggregatorConfig::distribution_sketch_accuracy`](crate::AggregatorConfig) is configured.
+    /// Sketches cannot be ingested through the statsd format.
+    ///
+    /// # Serialization
+    ///
+    /// This variant serializes to a structure with named fields, see [`DistributionSketch`].
+    ///
+    /// # Aggregation
+    ///
+    /// Sketches merge with other sketches and with raw [distributions](Self::Distribution). The
+    /// result of merging a distribution and a sketch is always a sketch.
+    #[serde(rename = "ds")]
+    DistributionSketch(Box<DistributionSketch>),
 }
 
 impl BucketValue {
@@ -296,6 +330,7 @@ impl BucketValue {
             Self::Distribution(_) => MetricType::Distribution,
             Self::Set(_) => MetricType::Set,
             Self::Gauge(_) => MetricType::Gauge,
+            Self::DistributionSketch(_) => MetricType::Distribution,
         }
     }
 
@@ -306,6 +341,7 @@ impl BucketValue {
             BucketValue::Distribution(distribution) => distribution.len(),
             BucketValue::Set(set) => set.len(),
             BucketValue::Gauge(_) => 5,
+            BucketValue::DistributionSketch(sketch) => sketch.bins(),
         }
     }
 
@@ -326,6 +362,7 @@ impl BucketValue {
             Self::Set(s) => mem::size_of::<SetType>() * s.len(),
             Self::Gauge(_) => 0,
             Self::Distribution(d) => d.len() * mem::size_of::<DistributionType>(),
+            Self::DistributionSketch(s) => s.cost(),
         };
 
         mem::size_of::<Self>() + allocated_cost
@@ -336,16 +373,48 @@ impl BucketValue {
     /// Returns `Ok(())` if the two bucket values can be merged. This is the case when both bucket
     /// values are of the same variant. Otherwise, this returns `Err(other)`.
     pub fn merge(&mut self, other: Self) -> Result<(), Self> {
+        self.merge_with(other, GaugeMerge::Last)
+    }
+
+    /// Merges the given `bucket_value` into `self`, using the given strategy for gauges.
+    ///
+    /// See [`merge`](Self::merge) for when bucket values can be merged.
+    pub fn merge_with(&mut self, other: Self, gauge_merge: GaugeMerge) -> Result<(), Self> {
         match (self, other) {
             (Self::Counter(slf), Self::Counter(other)) => *slf += other,
             (Self::Distribution(slf), Self::Distribution(other)) => slf.extend_from_slice(&other),
             (Self::Set(slf), Self::Set(other)) => slf.extend(other),
-            (Self::Gauge(slf), Self::Gauge(other)) => slf.merge(other),
+            (Self::Gauge(slf), Self::Gauge(other)) => slf.merge_with(other, gauge_merge),
+            (Self::DistributionSketch(slf), Self::DistributionSketch(other)) => slf.merge(&other),
+            (Self::DistributionSketch(slf), Self::Distribution(other)) => {
+                other.iter().for_each(|&value| slf.add(value))
+            }
+            (slf @ Self::Distribution(_), Self::DistributionSketch(mut other)) => {
+                if let Self::Distribution(values) = slf {
+                    values.iter().for_each(|&value| other.add(value));
+                }
+                *slf = Self::DistributionSketch(other);
+            }
             (_, other) => return Err(other),
         }
 
         Ok(())
     }
+
+    /// Summarizes a distribution into a sketch with the given relative accuracy.
+    ///
+    /// Sketches with a different accuracy, for example submitted by clients, are converted to the
+    /// given accuracy. Other values are not changed.
+    pub fn summarize(&mut self, alpha: f64) {
+        match self {
+            Self::Distribution(values) => {
+                let sketch = DistributionSketch::from_values(alpha, values);
+                *self = Self::DistributionSketch(Box::new(sketch));
+            }
+            Self::DistributionSketch(sketch) => sketch.normalize(alpha),
+            _ => (),
+        }
+    }
 }
 
 /// Parses a list of counter values separated by colons and sums them up.
@@ -795,6 +864,31 @@ mod tests {
         assert_eq!(value, BucketValue::Distribution(dist![1., 2., 3., 2., 4.]));
     }
 
+    #[test]
+    fn test_bucket_value_merge_sketch() {
+        let sketch = |values: &[f64]| {
+            BucketValue::DistributionSketch(Box::new(DistributionSketch::from_values(0.01, values)))
+        };
+
+        let mut value = BucketValue::Distribution(dist![1., 2.]);
+        value.merge(sketch(&[3.])).unwrap();
+        assert_eq!(value, sketch(&[3., 1., 2.]));
+
+        value.merge(BucketValue::Distribution(dist![4.])).unwrap();
+        assert_eq!(value, sketch(&[1., 2., 3., 4.]));
+        assert_eq!(value.ty(), MetricType::Distribution);
+
+        let mut value = BucketValue::Distribution(dist![1., 2.]);
+        value.summarize(0.01);
+        assert_eq!(value, sketch(&[1., 2.]));
+
+        // Sketches with a different accuracy are converted.
+        let mut value =
+            BucketValue::DistributionSketch(Box::new(DistributionSketch::from_values(0.05, &[1.])));
+        value.summarize(0.01);
+        assert!(matches!(value, BucketValue::DistributionSketch(s) if s.alpha == 0.01));
+    }
+
     #[test]
     fn test_bucket_value_merge_set() {
         let mut value = BucketValue::Set(vec![1, 2].into_iter().collect());
@@ -819,6 +913,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_gauge_merge_strategies() {
+        let merge = |strategy| {
+            let mut value = GaugeValue::single(42.);
+            value.merge_with(GaugeValue::single(40.), strategy);
+            value.merge_with(GaugeValue::single(44.), strategy);
+            value
+        };
+
+        assert_eq!(merge(GaugeMerge::Last).last, 44.);
+        assert_eq!(merge(GaugeMerge::Min).last, 40.);
+        assert_eq!(merge(GaugeMerge::Max).last, 44.);
+        assert_eq!(merge(GaugeMerge::Sum).last, 126.);
+        assert_eq!(merge(GaugeMerge::Average).last, 42.);
+
+        // Other aggregates do not depend on the strategy.
+        let value = merge(GaugeMerge::Sum);
+        assert_eq!(
+            (value.min, value.max, value.sum, value.count),
+            (40., 44., 126., 3)
+        );
+    }
+
     #[test]
     fn test_parse_garbage() {
         let s = "x23-408j17z4232@#34d\nc3456y7^😎";
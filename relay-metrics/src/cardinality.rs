@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use fnv::FnvHasher;
+use relay_base_schema::project::ProjectKey;
+use relay_common::time::UnixTimestamp;
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::MetricNamespace;
+
+fn default_window() -> u64 {
+    3600
+}
+
+/// A limit on the number of distinct metric series per project.
+///
+/// A series is a unique combination of metric name and tags. Once the limit is reached within a
+/// window, buckets of new series are rejected, while buckets of series that have been seen in the
+/// window are still accepted. Limits without `namespace` and `name` apply to all metrics of a
+/// project.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct CardinalityLimit {
+    /// The namespace of metrics counted towards this limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<MetricNamespace>,
+
+    /// The full metric name (MRI) of metrics counted towards this limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The maximum number of distinct series per project within a window.
+    pub limit: usize,
+
+    /// The length of the window in seconds.
+    ///
+    /// Defaults to `3600` seconds. Windows are aligned to multiples of this length.
+    #[serde(default = "default_window")]
+    pub window: u64,
+}
+
+impl CardinalityLimit {
+    /// Returns `true` if the metric counts towards this limit.
+    fn matches(&self, namespace: MetricNamespace, name: &str) -> bool {
+        self.namespace.map_or(true, |ns| ns == namespace)
+            && self.name.as_deref().map_or(true, |n| n == name)
+    }
+
+    /// Returns the start of the window containing the given timestamp.
+    fn window_start(&self, timestamp: UnixTimestamp) -> u64 {
+        let window = self.window.max(1);
+        timestamp.as_secs() / window * window
+    }
+}
+
+/// Hashes of the series seen by a limit in its current window.
+#[derive(Debug)]
+struct SeriesSet {
+    window_start: u64,
+    hashes: HashSet<u32>,
+}
+
+/// Enforces [`CardinalityLimit`]s locally.
+///
+/// Series are tracked by 32-bit hashes of their name and tags, so the number of series is counted
+/// approximately: Colliding series are counted once. Each tracked set is bounded by its limit.
+#[derive(Debug, Default)]
+pub(crate) struct CardinalityLimiter {
+    /// Seen series by index of the limit and project key.
+    sets: HashMap<(usize, ProjectKey), SeriesSet>,
+}
+
+impl CardinalityLimiter {
+    /// Checks whether a series is within all matching limits and records it.
+    ///
+    /// Returns the violated limit if the series must be rejected. In that case, the series is not
+    /// recorded for any limit.
+    pub fn check<'a>(
+        &mut self,
+        limits: &'a [CardinalityLimit],
+        project_key: ProjectKey,
+        namespace: MetricNamespace,
+        name: &str,
+        tags: &BTreeMap<String, String>,
+    ) -> Result<(), &'a CardinalityLimit> {
+        if limits.is_empty() {
+            return Ok(());
+        }
+
+        let hash = series_hash(name, tags);
+        let now = UnixTimestamp::now();
+
+        let matching = || {
+            limits
+                .iter()
+                .enumerate()
+                .filter(|(_, limit)| limit.matches(namespace, name))
+        };
+
+        for (index, limit) in matching() {
+            let window_start = limit.window_start(now);
+            let set = self
+                .sets
+                .entry((index, project_key))
+                .or_insert_with(|| SeriesSet {
+                    window_start,
+                    hashes: HashSet::new(),
+                });
+
+            if set.window_start != window_start {
+                set.window_start = window_start;
+                set.hashes.clear();
+            }
+
+            if !set.hashes.contains(&hash) && set.hashes.len() >= limit.limit {
+                return Err(limit);
+            }
+        }
+
+        for (index, _) in matching() {
+            if let Some(set) = self.sets.get_mut(&(index, project_key)) {
+                set.hashes.insert(hash);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the series of windows that have ended.
+    pub fn prune(&mut self, limits: &[CardinalityLimit]) {
+        let now = UnixTimestamp::now();
+        self.sets.retain(|(index, _), set| {
+            limits
+                .get(*index)
+                .map_or(false, |limit| limit.window_start(now) == set.window_start)
+        });
+    }
+}
+
+/// Computes a 32-bit hash identifying the series of a metric.
+fn series_hash(name: &str, tags: &BTreeMap<String, String>) -> u32 {
+    let mut hasher = FnvHasher::default();
+    name.hash(&mut hasher);
+    tags.hash(&mut hasher);
+    hasher.finish() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_series() {
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let limits = [CardinalityLimit {
+            namespace: Some(MetricNamespace::Custom),
+            name: None,
+            limit: 2,
+            window: 3600,
+        }];
+
+        let mut limiter = CardinalityLimiter::default();
+        let mut check = |name: &str, tag: &str| {
+            let tags = BTreeMap::from([("tag".to_owned(), tag.to_owned())]);
+            let namespace = if name.contains("custom") {
+                MetricNamespace::Custom
+            } else {
+                MetricNamespace::Transactions
+            };
+            limiter
+                .check(&limits, project_key, namespace, name, &tags)
+                .is_ok()
+        };
+
+        assert!(check("c:custom/foo@none", "a"));
+        assert!(check("c:custom/foo@none", "b"));
+        assert!(!check("c:custom/foo@none", "c"));
+        assert!(!check("c:custom/bar@none", "a"));
+
+        // Known series and other namespaces are still accepted.
+        assert!(check("c:custom/foo@none", "a"));
+        assert!(check("c:transactions/foo@none", "c"));
+    }
+
+    #[test]
+    fn test_limit_name() {
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let limits: Vec<CardinalityLimit> = serde_json::from_value(serde_json::json!([
+            {"name": "c:custom/foo@none", "limit": 1}
+        ]))
+        .unwrap();
+        assert_eq!(limits[0].window, 3600);
+
+        let mut limiter = CardinalityLimiter::default();
+        let namespace = MetricNamespace::Custom;
+        let tags = |tag: &str| BTreeMap::from([("tag".to_owned(), tag.to_owned())]);
+
+        let name = "c:custom/foo@none";
+        assert!(limiter
+            .check(&limits, project_key, namespace, name, &tags("a"))
+            .is_ok());
+        assert!(limiter
+            .check(&limits, project_key, namespace, name, &tags("b"))
+            .is_err());
+
+        let other = "c:custom/bar@none";
+        assert!(limiter
+            .check(&limits, project_key, namespace, other, &tags("b"))
+            .is_ok());
+
+        limiter.prune(&limits);
+        assert_eq!(limiter.sets.len(), 1);
+    }
+}
@@ -69,11 +69,17 @@
 
 mod aggregation;
 mod bucket;
+mod cardinality;
+mod meta;
 mod protocol;
 mod router;
+mod sketch;
 mod statsd;
 
 pub use aggregation::*;
 pub use bucket::*;
+pub use cardinality::*;
+pub use meta::*;
 pub use protocol::*;
 pub use router::*;
+pub use sketch::*;
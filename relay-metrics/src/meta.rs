@@ -0,0 +1,330 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use relay_base_schema::project::ProjectKey;
+use relay_common::time::UnixTimestamp;
+use serde::{Deserialize, Serialize};
+
+/// The number of seconds in a day.
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Additional metadata for metrics, submitted in `metric_meta` envelope items.
+///
+/// Metadata is collected per day and per metric. SDKs send it independently of the metric values,
+/// typically once for each code location emitting a metric.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct MetricMeta {
+    /// The timestamp the metadata was collected at.
+    ///
+    /// Metadata is stored per day, so [`MetaAggregator`] replaces this with the start of the day
+    /// on which Relay received it.
+    pub timestamp: UnixTimestamp,
+
+    /// Metadata items keyed by the MRI of the metric they belong to.
+    #[serde(default)]
+    pub mapping: BTreeMap<String, Vec<MetaItem>>,
+}
+
+impl MetricMeta {
+    /// Returns `true` if this does not contain metadata for any metric.
+    pub fn is_empty(&self) -> bool {
+        self.mapping.values().all(Vec::is_empty)
+    }
+}
+
+/// A single item of metric metadata.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MetaItem {
+    /// The code location a metric was emitted from.
+    Location(Location),
+    /// An item type that is not supported by this version of Relay.
+    ///
+    /// Unknown items are dropped during aggregation.
+    #[serde(other)]
+    Unknown,
+}
+
+/// A code location, similar to a frame of a stack trace.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Location {
+    /// The relative path to the source file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// The absolute path to the source file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abs_path: Option<String>,
+    /// The containing module name or path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    /// The containing function name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    /// The line number.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lineno: Option<u64>,
+    /// Source code leading up to the line.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pre_context: Vec<String>,
+    /// Source code of the line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_line: Option<String>,
+    /// Source code following the line.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub post_context: Vec<String>,
+}
+
+/// Deduplicates [`MetricMeta`] per project, day, and metric.
+///
+/// SDKs send the same code locations over and over again. The aggregator keeps track of the
+/// locations it has seen during the current and the previous day, and only lets new locations
+/// through. Once the maximum number of locations for a metric is reached, further locations of
+/// that metric are dropped for the rest of the day.
+///
+/// Days are determined by the time Relay received the metadata, so that client timestamps can
+/// neither keep old entries alive nor expire recent ones. Metadata for more metrics than
+/// `max_entries` is dropped until old entries expire.
+#[derive(Debug)]
+pub struct MetaAggregator {
+    /// Locations seen per project, start of day, and MRI.
+    locations: HashMap<(ProjectKey, UnixTimestamp, String), HashSet<Location>>,
+    /// The maximum number of locations stored per metric and day.
+    max_locations: usize,
+    /// The maximum number of metrics stored across all projects and days.
+    max_entries: usize,
+    /// The most recent day seen by this aggregator, used to expire old entries.
+    current_day: UnixTimestamp,
+}
+
+impl MetaAggregator {
+    /// Creates a new aggregator storing up to `max_locations` locations per metric and day, and up
+    /// to `max_entries` metrics in total.
+    pub fn new(max_locations: usize, max_entries: usize) -> Self {
+        Self {
+            locations: HashMap::new(),
+            max_locations,
+            max_entries,
+            current_day: UnixTimestamp::from_secs(0),
+        }
+    }
+
+    /// Adds metadata of a project and returns the items that have not been seen before.
+    ///
+    /// `received_at` is the time Relay received the metadata. Metadata is stored for the day it was
+    /// received, and the returned metadata has its timestamp set to the start of that day. Returns
+    /// `None` if all items have been seen before.
+    pub fn add(
+        &mut self,
+        project_key: ProjectKey,
+        meta: MetricMeta,
+        received_at: UnixTimestamp,
+    ) -> Option<MetricMeta> {
+        let day = start_of_day(received_at);
+        self.expire(day);
+
+        let mut mapping = BTreeMap::new();
+        for (mri, items) in meta.mapping {
+            let key = (project_key, day, mri.clone());
+            if !self.locations.contains_key(&key) && self.locations.len() >= self.max_entries {
+                continue;
+            }
+
+            let seen = self.locations.entry(key).or_default();
+
+            let mut new_items = Vec::new();
+            for item in items {
+                let MetaItem::Location(location) = item else {
+                    continue;
+                };
+
+                if seen.len() >= self.max_locations || seen.contains(&location) {
+                    continue;
+                }
+
+                seen.insert(location.clone());
+                new_items.push(MetaItem::Location(location));
+            }
+
+            if !new_items.is_empty() {
+                mapping.insert(mri, new_items);
+            }
+        }
+
+        let meta = MetricMeta {
+            timestamp: day,
+            mapping,
+        };
+
+        (!meta.is_empty()).then_some(meta)
+    }
+
+    /// Removes all entries older than the day before `day`.
+    fn expire(&mut self, day: UnixTimestamp) {
+        if day <= self.current_day {
+            return;
+        }
+
+        self.current_day = day;
+        let cutoff = UnixTimestamp::from_secs(day.as_secs().saturating_sub(DAY_SECS));
+        self.locations.retain(|(_, day, _), _| *day >= cutoff);
+    }
+}
+
+/// Truncates a timestamp to the start of its day in UTC.
+fn start_of_day(timestamp: UnixTimestamp) -> UnixTimestamp {
+    let secs = timestamp.as_secs();
+    UnixTimestamp::from_secs(secs - secs % DAY_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(timestamp: u64, functions: &[&str]) -> MetricMeta {
+        let items = functions
+            .iter()
+            .map(|function| {
+                MetaItem::Location(Location {
+                    function: Some((*function).to_owned()),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        MetricMeta {
+            timestamp: UnixTimestamp::from_secs(timestamp),
+            mapping: BTreeMap::from([("c:custom/foo@none".to_owned(), items)]),
+        }
+    }
+
+    #[test]
+    fn test_parse_meta() {
+        let json = r#"{
+            "timestamp": 1697011200,
+            "mapping": {
+                "d:custom/sentry.process_profile.track_outcome@second": [
+                    {
+                        "type": "location",
+                        "function": "_track_outcome",
+                        "module": "sentry.tasks.store",
+                        "filename": "sentry/tasks/store.py",
+                        "abs_path": "/usr/src/sentry/src/sentry/tasks/store.py",
+                        "lineno": 532
+                    },
+                    {"type": "future", "value": 42}
+                ]
+            }
+        }"#;
+
+        let meta: MetricMeta = serde_json::from_str(json).unwrap();
+        insta::assert_debug_snapshot!(meta, @r###"
+        MetricMeta {
+            timestamp: UnixTimestamp(1697011200),
+            mapping: {
+                "d:custom/sentry.process_profile.track_outcome@second": [
+                    Location(
+                        Location {
+                            filename: Some(
+                                "sentry/tasks/store.py",
+                            ),
+                            abs_path: Some(
+                                "/usr/src/sentry/src/sentry/tasks/store.py",
+                            ),
+                            module: Some(
+                                "sentry.tasks.store",
+                            ),
+                            function: Some(
+                                "_track_outcome",
+                            ),
+                            lineno: Some(
+                                532,
+                            ),
+                            pre_context: [],
+                            context_line: None,
+                            post_context: [],
+                        },
+                    ),
+                    Unknown,
+                ],
+            },
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_aggregate_meta() {
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let mut aggregator = MetaAggregator::new(3, 100);
+        let add = |aggregator: &mut MetaAggregator, secs, functions| {
+            let received_at = UnixTimestamp::from_secs(secs);
+            aggregator.add(project_key, meta(secs, functions), received_at)
+        };
+
+        // The timestamp is truncated to the start of the day.
+        let added = add(&mut aggregator, 1697020000, &["a", "b"]);
+        assert_eq!(added, Some(meta(1697011200, &["a", "b"])));
+
+        // Known locations are dropped.
+        let added = add(&mut aggregator, 1697030000, &["a", "c", "d"]);
+        assert_eq!(added, Some(meta(1697011200, &["c"])));
+        assert_eq!(add(&mut aggregator, 1697030000, &["b"]), None);
+
+        // The next day starts over.
+        let added = add(&mut aggregator, 1697100000, &["a"]);
+        assert_eq!(added, Some(meta(1697097600, &["a"])));
+    }
+
+    #[test]
+    fn test_aggregate_meta_received_at() {
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let mut aggregator = MetaAggregator::new(3, 100);
+        let received_at = UnixTimestamp::from_secs(1697020000);
+
+        // Client timestamps far in the past or future are stored for the day of receipt.
+        let added = aggregator.add(project_key, meta(0, &["a"]), received_at);
+        assert_eq!(added, Some(meta(1697011200, &["a"])));
+        let added = aggregator.add(project_key, meta(u32::MAX.into(), &["a"]), received_at);
+        assert_eq!(added, None);
+        assert_eq!(aggregator.locations.len(), 1);
+    }
+
+    #[test]
+    fn test_aggregate_meta_max_entries() {
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let mut aggregator = MetaAggregator::new(3, 1);
+        let received_at = UnixTimestamp::from_secs(1697020000);
+
+        let mut other = meta(1697020000, &["a"]);
+        other.mapping = BTreeMap::from([(
+            "c:custom/bar@none".to_owned(),
+            other.mapping.into_values().next().unwrap(),
+        )]);
+
+        assert!(aggregator
+            .add(project_key, meta(0, &["a"]), received_at)
+            .is_some());
+        assert_eq!(aggregator.add(project_key, other, received_at), None);
+
+        // Known metrics still accept new locations.
+        let added = aggregator.add(project_key, meta(0, &["b"]), received_at);
+        assert_eq!(added, Some(meta(1697011200, &["b"])));
+    }
+
+    #[test]
+    fn test_aggregate_meta_expiry() {
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let mut aggregator = MetaAggregator::new(3, 100);
+        let add = |aggregator: &mut MetaAggregator, secs| {
+            let received_at = UnixTimestamp::from_secs(secs);
+            aggregator.add(project_key, meta(secs, &["a"]), received_at);
+        };
+
+        add(&mut aggregator, 1697020000);
+        add(&mut aggregator, 1697100000);
+        assert_eq!(aggregator.locations.len(), 2);
+
+        // Two days later, both previous days are expired.
+        add(&mut aggregator, 1697280000);
+        assert_eq!(aggregator.locations.len(), 1);
+    }
+}
@@ -5,17 +5,20 @@ use std::collections::BTreeMap;
 
 use itertools::Itertools;
 use relay_system::{Addr, NoResponse, Recipient, Service};
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    AcceptsMetrics, Aggregator, AggregatorConfig, AggregatorService, FlushBuckets, MergeBuckets,
-    MetricNamespace, MetricResourceIdentifier,
+    AcceptsMetrics, Aggregator, AggregatorConfig, AggregatorService, BucketCountInquiry,
+    FlushBuckets, MergeBuckets, MetricNamespace, MetricResourceIdentifier,
 };
 
 /// Contains an [`AggregatorConfig`] for a specific scope.
 ///
 /// For now, the only way to scope an aggregator is by [`MetricNamespace`].
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct ScopedAggregatorConfig {
     /// Name of the aggregator, used to tag statsd metrics.
     pub name: String,
@@ -29,6 +32,7 @@ pub struct ScopedAggregatorConfig {
 /// Condition that needs to be met for a metric or bucket to be routed to a
 /// secondary aggregator.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(tag = "op", rename_all = "lowercase")]
 pub enum Condition {
     /// Checks for equality on a specific field.
@@ -37,6 +41,7 @@ pub enum Condition {
 
 /// Defines a field and a field value to compare to when a [`Condition`] is evaluated.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(tag = "field", content = "value", rename_all = "lowercase")]
 pub enum Field {
     /// Field that allows comparison to a metric or bucket's namespace.
@@ -138,8 +143,23 @@ impl StartedRouter {
                 });
             }
             Aggregator::MergeBuckets(msg) => self.handle_merge_buckets(msg),
-            #[cfg(test)]
-            Aggregator::BucketCountInquiry(_, _sender) => (), // not supported
+            Aggregator::BucketCountInquiry(_, sender) => {
+                let requests: Vec<_> = Some(self.default_aggregator.send(BucketCountInquiry))
+                    .into_iter()
+                    .chain(
+                        self.secondary_aggregators
+                            .values_mut()
+                            .map(|agg| agg.send(BucketCountInquiry)),
+                    )
+                    .collect();
+                tokio::spawn(async {
+                    let mut count = 0;
+                    for req in requests {
+                        count += req.await.unwrap_or_default();
+                    }
+                    sender.send(count);
+                });
+            }
         }
     }
 
@@ -0,0 +1,461 @@
+use std::collections::BTreeMap;
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::DistributionType;
+
+/// Values closer to zero than this are counted in the zero bin of a [`DistributionSketch`].
+const MIN_INDEXABLE_VALUE: DistributionType = 1e-9;
+
+/// The finest relative accuracy accepted for sketches.
+///
+/// The number of bins grows inversely with the accuracy, so finer sketches are rejected.
+const MIN_ALPHA: f64 = 1e-4;
+
+/// The coarsest relative accuracy accepted for sketches.
+const MAX_ALPHA: f64 = 0.5;
+
+/// The maximum number of bins of a deserialized sketch.
+///
+/// A sketch with 1% accuracy covering values from `1e-9` to `1e12` needs about 2,400 bins.
+const MAX_BINS: usize = 4096;
+
+/// An error returned when a deserialized [`DistributionSketch`] is invalid.
+#[derive(Clone, Copy, Debug, Error, PartialEq)]
+pub enum InvalidSketch {
+    /// The relative accuracy is outside of the supported range.
+    #[error("sketch accuracy is out of range")]
+    Accuracy,
+    /// The sketch has more bins than supported.
+    #[error("sketch has too many bins")]
+    TooManyBins,
+    /// A bin index does not correspond to a finite value.
+    #[error("sketch bin index out of range")]
+    BinIndex,
+    /// The count does not match the sum of all bin counts.
+    #[error("sketch count does not match its bins")]
+    Count,
+    /// The sum, minimum, or maximum are not finite or inconsistent.
+    #[error("sketch aggregates are invalid")]
+    Aggregates,
+}
+
+/// A summary of a distribution with guaranteed relative accuracy, based on DDSketch.
+///
+/// Values are sorted into logarithmically sized bins, so that any quantile can be estimated with a
+/// relative error of at most `alpha`. The number of bins grows with the logarithm of the value
+/// range rather than the number of values, which makes sketches much smaller than the raw values of
+/// high-volume distributions. Sketches also track the exact count, sum, minimum, and maximum.
+///
+/// # Serialization
+///
+/// Sketches serialize to a structure with the relative accuracy, the exact aggregates, and the
+/// counts of non-empty bins. Bin `i` covers the range `(gamma^(i-1), gamma^i]` with
+/// `gamma = (1 + alpha) / (1 - alpha)`. Negative values are stored by their absolute value in
+/// separate bins, and values close to zero are counted in `zeros`:
+///
+/// ```json
+/// {
+///   "alpha": 0.01,
+///   "count": 4,
+///   "sum": 147.0,
+///   "min": 0.0,
+///   "max": 68.0,
+///   "zeros": 1,
+///   "positive": {"180": 1, "189": 1, "211": 1},
+///   "negative": {}
+/// }
+/// ```
+///
+/// Deserialized sketches are validated, since they can be submitted by clients.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "SketchData")]
+pub struct DistributionSketch {
+    /// The relative accuracy of quantiles estimated from this sketch.
+    pub alpha: f64,
+    /// The number of values added to this sketch.
+    pub count: u64,
+    /// The sum of all values added to this sketch.
+    pub sum: DistributionType,
+    /// The smallest value added to this sketch.
+    pub min: DistributionType,
+    /// The largest value added to this sketch.
+    pub max: DistributionType,
+    /// The number of values close to zero.
+    #[serde(default)]
+    pub zeros: u64,
+    /// Counts of positive values by bin index.
+    #[serde(default)]
+    pub positive: BTreeMap<i32, u64>,
+    /// Counts of negative values by bin index of their absolute value.
+    #[serde(default)]
+    pub negative: BTreeMap<i32, u64>,
+}
+
+impl DistributionSketch {
+    /// Creates an empty sketch with the given relative accuracy.
+    ///
+    /// The accuracy must be in the range `(0, 1)`.
+    pub fn new(alpha: f64) -> Self {
+        debug_assert!(alpha > 0.0 && alpha < 1.0, "invalid sketch accuracy");
+        Self {
+            alpha,
+            count: 0,
+            sum: 0.0,
+            min: DistributionType::INFINITY,
+            max: DistributionType::NEG_INFINITY,
+            zeros: 0,
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a sketch with the given relative accuracy from raw values.
+    pub fn from_values(alpha: f64, values: &[DistributionType]) -> Self {
+        let mut sketch = Self::new(alpha);
+        for &value in values {
+            sketch.add(value);
+        }
+        sketch
+    }
+
+    /// Returns the base of the logarithmic bins.
+    fn gamma(&self) -> f64 {
+        (1.0 + self.alpha) / (1.0 - self.alpha)
+    }
+
+    /// Returns the index of the bin containing the absolute value.
+    fn index(&self, value: DistributionType) -> i32 {
+        (value.ln() / self.gamma().ln()).ceil() as i32
+    }
+
+    /// Returns the value representing all values in the bin with the given index.
+    fn value(&self, index: i32) -> DistributionType {
+        let gamma = self.gamma();
+        2.0 * gamma.powi(index) / (gamma + 1.0)
+    }
+
+    /// Adds a value to the sketch.
+    pub fn add(&mut self, value: DistributionType) {
+        self.add_n(value, 1);
+    }
+
+    /// Adds a value to the sketch `n` times.
+    fn add_n(&mut self, value: DistributionType, n: u64) {
+        if n == 0 || !value.is_finite() {
+            return;
+        }
+
+        self.count += n;
+        self.sum += value * n as DistributionType;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+
+        if value.abs() < MIN_INDEXABLE_VALUE {
+            self.zeros += n;
+        } else if value > 0.0 {
+            *self.positive.entry(self.index(value)).or_default() += n;
+        } else {
+            *self.negative.entry(self.index(-value)).or_default() += n;
+        }
+    }
+
+    /// Converts the sketch to the given relative accuracy.
+    ///
+    /// This is a no-op if the sketch already has this accuracy.
+    pub fn normalize(&mut self, alpha: f64) {
+        if self.alpha != alpha {
+            let mut normalized = Self::new(alpha);
+            normalized.merge(self);
+            *self = normalized;
+        }
+    }
+
+    /// Merges another sketch into this sketch.
+    ///
+    /// Sketches with the same accuracy are merged losslessly. Otherwise, the bins of the other sketch
+    /// are added with their representative values, which retains the accuracy of this sketch
+    /// relative to the other sketch's estimates.
+    pub fn merge(&mut self, other: &Self) {
+        if other.count == 0 {
+            return;
+        }
+
+        if self.alpha == other.alpha {
+            self.count += other.count;
+            self.sum += other.sum;
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+            self.zeros += other.zeros;
+            for (&index, &n) in &other.positive {
+                *self.positive.entry(index).or_default() += n;
+            }
+            for (&index, &n) in &other.negative {
+                *self.negative.entry(index).or_default() += n;
+            }
+            return;
+        }
+
+        let (count, sum, min, max) = (self.count, self.sum, self.min, self.max);
+        self.add_n(0.0, other.zeros);
+        for (&index, &n) in &other.positive {
+            self.add_n(other.value(index), n);
+        }
+        for (&index, &n) in &other.negative {
+            self.add_n(-other.value(index), n);
+        }
+
+        // The aggregates of the other sketch are exact, so use them instead of the estimates.
+        self.count = count + other.count;
+        self.sum = sum + other.sum;
+        self.min = min.min(other.min);
+        self.max = max.max(other.max);
+    }
+
+    /// Returns the number of values in this sketch.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the number of non-empty bins in this sketch.
+    pub fn bins(&self) -> usize {
+        self.positive.len() + self.negative.len() + usize::from(self.zeros > 0)
+    }
+
+    /// Estimates the memory needed to store this sketch.
+    pub fn cost(&self) -> usize {
+        mem::size_of::<Self>() + (self.positive.len() + self.negative.len()) * 12
+    }
+
+    /// Estimates the value at the given quantile within `[0, 1]`.
+    ///
+    /// Returns `None` if the sketch is empty.
+    pub fn quantile(&self, q: f64) -> Option<DistributionType> {
+        if self.count == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+
+        if q == 0.0 {
+            return Some(self.min);
+        } else if q == 1.0 {
+            return Some(self.max);
+        }
+
+        let rank = (q * (self.count - 1) as f64) as u64;
+        let mut seen = 0;
+
+        // Negative values in ascending order have descending absolute values.
+        for (&index, &n) in self.negative.iter().rev() {
+            seen += n;
+            if seen > rank {
+                return Some(-self.value(index));
+            }
+        }
+
+        seen += self.zeros;
+        if seen > rank {
+            return Some(0.0);
+        }
+
+        for (&index, &n) in &self.positive {
+            seen += n;
+            if seen > rank {
+                return Some(self.value(index).clamp(self.min, self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+}
+
+/// The serialized representation of a [`DistributionSketch`], validated before conversion.
+#[derive(Deserialize)]
+struct SketchData {
+    alpha: f64,
+    count: u64,
+    sum: DistributionType,
+    min: DistributionType,
+    max: DistributionType,
+    #[serde(default)]
+    zeros: u64,
+    #[serde(default)]
+    positive: BTreeMap<i32, u64>,
+    #[serde(default)]
+    negative: BTreeMap<i32, u64>,
+}
+
+impl TryFrom<SketchData> for DistributionSketch {
+    type Error = InvalidSketch;
+
+    fn try_from(data: SketchData) -> Result<Self, Self::Error> {
+        let SketchData {
+            alpha,
+            count,
+            sum,
+            min,
+            max,
+            zeros,
+            mut positive,
+            mut negative,
+        } = data;
+
+        if !(MIN_ALPHA..=MAX_ALPHA).contains(&alpha) {
+            return Err(InvalidSketch::Accuracy);
+        }
+
+        // Empty bins carry no information and would only inflate the sketch.
+        positive.retain(|_, n| *n > 0);
+        negative.retain(|_, n| *n > 0);
+        if positive.len() + negative.len() > MAX_BINS {
+            return Err(InvalidSketch::TooManyBins);
+        }
+
+        let mut sketch = Self {
+            positive,
+            negative,
+            ..Self::new(alpha)
+        };
+
+        let is_valid_index = |index: &i32| {
+            let value = sketch.value(*index);
+            value.is_finite() && value >= MIN_INDEXABLE_VALUE / 2.0
+        };
+        let mut indexes = sketch.positive.keys().chain(sketch.negative.keys());
+        if !indexes.all(is_valid_index) {
+            return Err(InvalidSketch::BinIndex);
+        }
+
+        let bin_count = sketch
+            .positive
+            .values()
+            .chain(sketch.negative.values())
+            .try_fold(zeros, |total, n| total.checked_add(*n));
+        if bin_count != Some(count) {
+            return Err(InvalidSketch::Count);
+        }
+
+        if count > 0 {
+            if !sum.is_finite() || !min.is_finite() || !max.is_finite() || min > max {
+                return Err(InvalidSketch::Aggregates);
+            }
+
+            sketch.count = count;
+            sketch.sum = sum;
+            sketch.min = min;
+            sketch.max = max;
+            sketch.zeros = zeros;
+        }
+
+        Ok(sketch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(estimate: Option<f64>, expected: f64, alpha: f64) {
+        let estimate = estimate.unwrap();
+        assert!(
+            (estimate - expected).abs() <= expected.abs() * alpha,
+            "{estimate} not within {alpha} of {expected}"
+        );
+    }
+
+    #[test]
+    fn test_quantiles() {
+        let values: Vec<_> = (1..=1000).map(f64::from).collect();
+        let sketch = DistributionSketch::from_values(0.01, &values);
+
+        assert_eq!(sketch.count(), 1000);
+        assert_eq!(sketch.sum, 500500.0);
+        assert_eq!(sketch.quantile(0.0), Some(1.0));
+        assert_eq!(sketch.quantile(1.0), Some(1000.0));
+        assert_close(sketch.quantile(0.5), 500.0, 0.01);
+        assert_close(sketch.quantile(0.99), 990.0, 0.01);
+
+        // The number of bins is logarithmic in the range of values.
+        assert!(sketch.bins() < 400);
+    }
+
+    #[test]
+    fn test_negative_and_zero() {
+        let sketch = DistributionSketch::from_values(0.01, &[-10.0, -1.0, 0.0, 1.0, 10.0]);
+        assert_close(sketch.quantile(0.25), -1.0, 0.01);
+        assert_eq!(sketch.quantile(0.5), Some(0.0));
+        assert_close(sketch.quantile(0.75), 1.0, 0.01);
+        assert_eq!(sketch.bins(), 5);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = DistributionSketch::from_values(0.01, &[1.0, 2.0]);
+        let b = DistributionSketch::from_values(0.01, &[3.0, 4.0]);
+        a.merge(&b);
+        assert_eq!(
+            a,
+            DistributionSketch::from_values(0.01, &[1.0, 2.0, 3.0, 4.0])
+        );
+
+        let mut coarse = DistributionSketch::from_values(0.05, &[1.0]);
+        coarse.merge(&b);
+        assert_eq!(coarse.count(), 3);
+        assert_eq!(coarse.sum, 8.0);
+        assert_eq!(coarse.max, 4.0);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let sketch = DistributionSketch::from_values(0.01, &[0.0, 1.0, 10.0]);
+        let json = serde_json::to_string(&sketch).unwrap();
+        assert_eq!(
+            json,
+            r#"{"alpha":0.01,"count":3,"sum":11.0,"min":0.0,"max":10.0,"zeros":1,"positive":{"0":1,"116":1},"negative":{}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<DistributionSketch>(&json).unwrap(),
+            sketch
+        );
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        let parse = |json: &str| {
+            serde_json::from_str::<DistributionSketch>(json)
+                .map_err(|e| e.to_string())
+                .unwrap_err()
+        };
+
+        let valid = r#"{"alpha":0.01,"count":2,"sum":11.0,"min":1.0,"max":10.0,"positive":{"0":1,"116":1}}"#;
+        assert!(serde_json::from_str::<DistributionSketch>(valid).is_ok());
+
+        let error = parse(r#"{"alpha":1e-9,"count":0,"sum":0.0,"min":0.0,"max":0.0}"#);
+        assert!(error.contains("accuracy"), "{error}");
+        let error = parse(
+            r#"{"alpha":0.01,"count":5,"sum":11.0,"min":1.0,"max":10.0,"positive":{"0":1,"116":1}}"#,
+        );
+        assert!(error.contains("count"), "{error}");
+        let error = parse(
+            r#"{"alpha":0.01,"count":1,"sum":1.0,"min":1.0,"max":1.0,"positive":{"2147483647":1}}"#,
+        );
+        assert!(error.contains("index"), "{error}");
+        let error =
+            parse(r#"{"alpha":0.01,"count":1,"sum":1.0,"min":2.0,"max":1.0,"positive":{"0":1}}"#);
+        assert!(error.contains("aggregates"), "{error}");
+    }
+
+    #[test]
+    fn test_normalize() {
+        let values = [1.0, 2.0, 10.0];
+        let mut sketch = DistributionSketch::from_values(0.05, &values);
+        sketch.normalize(0.01);
+
+        assert_eq!(sketch.alpha, 0.01);
+        assert_eq!(sketch.count(), 3);
+        assert_eq!(sketch.sum, 13.0);
+        assert_eq!(sketch.min, 1.0);
+        assert_eq!(sketch.max, 10.0);
+    }
+}
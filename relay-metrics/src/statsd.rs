@@ -48,6 +48,13 @@ pub enum MetricCounters {
     /// This metric is tagged with:
     ///  - `aggregator`: The name of the metrics aggregator (usually `"default"`).
     BucketsDropped,
+
+    /// Incremented every time a bucket is rejected because of a cardinality limit.
+    ///
+    /// This metric is tagged with:
+    ///  - `aggregator`: The name of the metrics aggregator (usually `"default"`).
+    ///  - `namespace`: The namespace of the metric.
+    CardinalityLimited,
 }
 
 impl CounterMetric for MetricCounters {
@@ -56,6 +63,7 @@ impl CounterMetric for MetricCounters {
             Self::MergeHit => "metrics.buckets.merge.hit",
             Self::MergeMiss => "metrics.buckets.merge.miss",
             Self::BucketsDropped => "metrics.buckets.dropped",
+            Self::CardinalityLimited => "metrics.buckets.cardinality_limited",
         }
     }
 }
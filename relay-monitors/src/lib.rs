@@ -19,12 +19,19 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+mod schedule;
+
+pub use crate::schedule::ScheduleError;
+
 /// Maximum length of monitor slugs.
 const SLUG_LENGTH: usize = 50;
 
 /// Maximum length of environment names.
 const ENVIRONMENT_LENGTH: usize = 64;
 
+/// Aliases of the UTC timezone, in lowercase, that are normalized to `UTC`.
+const UTC_ALIASES: [&str; 7] = ["utc", "gmt", "z", "zulu", "universal", "etc/utc", "etc/gmt"];
+
 /// Error returned from [`process_check_in`].
 #[derive(Debug, thiserror::Error)]
 pub enum ProcessCheckInError {
@@ -39,6 +46,21 @@ pub enum ProcessCheckInError {
     /// Environment name was invalid.
     #[error("the environment is invalid")]
     InvalidEnvironment,
+
+    /// The schedule of the monitor config is invalid or never matches.
+    #[error("the monitor schedule is invalid")]
+    InvalidSchedule(#[from] ScheduleError),
+}
+
+/// Defaults applied to the monitor config of check-ins in [`process_check_in`].
+///
+/// Values that are `None` are left unset, so that Sentry applies its own defaults.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CheckInDefaults {
+    /// Default margin in minutes if the monitor config does not specify `checkin_margin`.
+    pub checkin_margin: Option<u64>,
+    /// Default runtime in minutes if the monitor config does not specify `max_runtime`.
+    pub max_runtime: Option<u64>,
 }
 
 ///
@@ -153,8 +175,33 @@ pub struct CheckIn {
     pub contexts: Option<CheckInContexts>,
 }
 
+impl MonitorConfig {
+    /// Validates the schedule, normalizes the timezone, and applies defaults for missing values.
+    fn normalize(&mut self, defaults: &CheckInDefaults) -> Result<(), ProcessCheckInError> {
+        match self.schedule {
+            Schedule::Crontab { ref mut value } => *value = schedule::validate_crontab(value)?,
+            Schedule::Interval { value, .. } => schedule::validate_interval(value)?,
+        }
+
+        if let Some(ref mut timezone) = self.timezone {
+            *timezone = normalize_timezone(timezone);
+        }
+
+        self.checkin_margin = self.checkin_margin.or(defaults.checkin_margin);
+        self.max_runtime = self.max_runtime.or(defaults.max_runtime);
+
+        Ok(())
+    }
+}
+
 /// Normalizes a monitor check-in payload.
-pub fn process_check_in(payload: &[u8]) -> Result<Vec<u8>, ProcessCheckInError> {
+///
+/// If the check-in contains a monitor config, its schedule is validated and missing margins are
+/// filled in from `defaults`.
+pub fn process_check_in(
+    payload: &[u8],
+    defaults: &CheckInDefaults,
+) -> Result<Vec<u8>, ProcessCheckInError> {
     let mut check_in = serde_json::from_slice::<CheckIn>(payload)?;
 
     // Missed status cannot be ingested, this is computed on the server.
@@ -176,9 +223,29 @@ pub fn process_check_in(payload: &[u8]) -> Result<Vec<u8>, ProcessCheckInError>
         return Err(ProcessCheckInError::InvalidEnvironment);
     }
 
+    if let Some(ref mut monitor_config) = check_in.monitor_config {
+        monitor_config.normalize(defaults)?;
+    }
+
     Ok(serde_json::to_vec(&check_in)?)
 }
 
+/// Trims a timezone name and normalizes aliases of UTC.
+///
+/// Other names are not validated here, since Sentry validates them against the tz database.
+fn normalize_timezone(timezone: &str) -> String {
+    let timezone = timezone.trim();
+
+    if UTC_ALIASES
+        .iter()
+        .any(|alias| alias.eq_ignore_ascii_case(timezone))
+    {
+        return "UTC".to_owned();
+    }
+
+    timezone.to_owned()
+}
+
 fn trim_slug(slug: &mut String) {
     if let Some((overflow, _)) = slug.char_indices().nth(SLUG_LENGTH) {
         slug.truncate(overflow);
@@ -298,7 +365,7 @@ mod tests {
           "status": "in_progress"
         }"#;
 
-        let result = process_check_in(json.as_bytes());
+        let result = process_check_in(json.as_bytes(), &CheckInDefaults::default());
         assert!(matches!(result, Err(ProcessCheckInError::EmptySlug)));
     }
 
@@ -311,10 +378,80 @@ mod tests {
           "environment": "1234567890123456789012345678901234567890123456789012345678901234567890"
         }"#;
 
-        let result = process_check_in(json.as_bytes());
+        let result = process_check_in(json.as_bytes(), &CheckInDefaults::default());
         assert!(matches!(
             result,
             Err(ProcessCheckInError::InvalidEnvironment)
         ));
     }
+
+    #[test]
+    fn process_normalize_monitor_config() {
+        let json = r#"{
+          "check_in_id": "a460c25ff2554577b920fcfacae4e5eb",
+          "monitor_slug": "my-monitor",
+          "status": "in_progress",
+          "monitor_config": {
+            "schedule": {"type": "crontab", "value": " 0  * * * * "},
+            "max_runtime": 10,
+            "timezone": "Etc/UTC"
+          }
+        }"#;
+
+        let defaults = CheckInDefaults {
+            checkin_margin: Some(1),
+            max_runtime: Some(30),
+        };
+        let result = process_check_in(json.as_bytes(), &defaults).unwrap();
+        let check_in = serde_json::from_slice::<CheckIn>(&result).unwrap();
+        let monitor_config = check_in.monitor_config.unwrap();
+
+        assert_eq!(
+            monitor_config.schedule,
+            Schedule::Crontab {
+                value: "0 * * * *".to_owned()
+            }
+        );
+        assert_eq!(monitor_config.checkin_margin, Some(1));
+        assert_eq!(monitor_config.max_runtime, Some(10));
+        assert_eq!(monitor_config.timezone.as_deref(), Some("UTC"));
+    }
+
+    #[test]
+    fn process_invalid_schedule() {
+        let json = r#"{
+          "check_in_id": "a460c25ff2554577b920fcfacae4e5eb",
+          "monitor_slug": "my-monitor",
+          "status": "in_progress",
+          "monitor_config": {
+            "schedule": {"type": "crontab", "value": "0 0 30 2 *"}
+          }
+        }"#;
+
+        let result = process_check_in(json.as_bytes(), &CheckInDefaults::default());
+        assert!(matches!(
+            result,
+            Err(ProcessCheckInError::InvalidSchedule(
+                ScheduleError::Impossible
+            ))
+        ));
+    }
+
+    #[test]
+    fn process_unknown_timezone() {
+        let json = r#"{
+          "check_in_id": "a460c25ff2554577b920fcfacae4e5eb",
+          "monitor_slug": "my-monitor",
+          "status": "in_progress",
+          "monitor_config": {
+            "schedule": {"type": "interval", "value": 5, "unit": "day"},
+            "timezone": " not a timezone "
+          }
+        }"#;
+
+        let result = process_check_in(json.as_bytes(), &CheckInDefaults::default()).unwrap();
+        let check_in = serde_json::from_slice::<CheckIn>(&result).unwrap();
+        let monitor_config = check_in.monitor_config.unwrap();
+        assert_eq!(monitor_config.timezone.as_deref(), Some("not a timezone"));
+    }
 }
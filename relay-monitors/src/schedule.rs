@@ -0,0 +1,377 @@
+//! Validation of monitor schedules.
+//!
+//! Crontab schedules use the standard five fields: minute, hour, day of month, month, and day of
+//! week. Fields are comma-separated lists of values, ranges (`1-5`), and wildcards, each with an
+//! optional step (`*/15`). Months and days of the week can also be given by their English
+//! three-letter names. In addition, the nicknames `@yearly`, `@annually`, `@monthly`, `@weekly`,
+//! `@daily`, `@midnight`, and `@hourly` are supported.
+//!
+//! The day fields also accept the extensions supported by Sentry: `?` in place of `*`, `L` for the
+//! last day of the month, `W` for the nearest weekday (`15W`, `LW`), `L` for the last given weekday
+//! of the month (`5L`), and `#` for the nth given weekday of the month (`5#3`).
+
+/// Error returned when a monitor schedule cannot be validated.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum ScheduleError {
+    /// The crontab does not have exactly five fields.
+    #[error("crontab must have five fields")]
+    FieldCount,
+
+    /// A field of the crontab could not be parsed or is out of range.
+    #[error("invalid {0} field in crontab")]
+    InvalidField(&'static str),
+
+    /// The crontab is well-formed, but never matches any date.
+    #[error("crontab never matches any date")]
+    Impossible,
+
+    /// The interval of an interval schedule is zero.
+    #[error("interval must be greater than zero")]
+    ZeroInterval,
+}
+
+/// Number of days in each month, allowing for leap years.
+const DAYS_IN_MONTH: [u32; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Bit set in the day of month matches for the last day of the month.
+const LAST_DAY: u64 = 1 << 32;
+
+/// Non-standard syntax accepted by a crontab field.
+#[derive(Clone, Copy, PartialEq)]
+enum Extensions {
+    None,
+    /// `?`, `L`, `LW`, and `W` after a day, such as `15W`.
+    DayOfMonth,
+    /// `?`, `L` after a weekday, such as `5L`, and `#` followed by `1` to `5`, such as `5#3`.
+    DayOfWeek,
+}
+
+/// Definition of a single crontab field.
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    /// Names for the values of this field, starting at `min`.
+    names: &'static [&'static str],
+    extensions: Extensions,
+}
+
+const FIELDS: [Field; 5] = [
+    Field {
+        name: "minute",
+        min: 0,
+        max: 59,
+        names: &[],
+        extensions: Extensions::None,
+    },
+    Field {
+        name: "hour",
+        min: 0,
+        max: 23,
+        names: &[],
+        extensions: Extensions::None,
+    },
+    Field {
+        name: "day of month",
+        min: 1,
+        max: 31,
+        names: &[],
+        extensions: Extensions::DayOfMonth,
+    },
+    Field {
+        name: "month",
+        min: 1,
+        max: 12,
+        names: &MONTH_NAMES,
+        extensions: Extensions::None,
+    },
+    // Both `0` and `7` are Sunday.
+    Field {
+        name: "day of week",
+        min: 0,
+        max: 7,
+        names: &WEEKDAY_NAMES,
+        extensions: Extensions::DayOfWeek,
+    },
+];
+
+/// The values matched by a crontab field.
+struct Matches {
+    /// Bit `n` is set if the field matches value `n`.
+    values: u64,
+    /// `false` if the field starts with a wildcard.
+    restricted: bool,
+}
+
+impl Field {
+    fn parse_value(&self, value: &str) -> Option<u32> {
+        let value = match value.parse::<u32>() {
+            Ok(value) => value,
+            Err(_) => {
+                let position = self
+                    .names
+                    .iter()
+                    .position(|name| name.eq_ignore_ascii_case(value))?;
+                self.min + position as u32
+            }
+        };
+
+        (self.min..=self.max).contains(&value).then_some(value)
+    }
+
+    /// Parses the non-standard syntax of the day fields.
+    ///
+    /// Returns `None` if the item does not use an extension.
+    fn parse_extension(&self, item: &str) -> Option<Option<u64>> {
+        match self.extensions {
+            Extensions::None => None,
+            Extensions::DayOfMonth => {
+                if item.eq_ignore_ascii_case("L") || item.eq_ignore_ascii_case("LW") {
+                    return Some(Some(LAST_DAY));
+                }
+                let day = item.strip_suffix(['W', 'w'])?;
+                Some(self.parse_value(day).map(|day| 1 << day))
+            }
+            Extensions::DayOfWeek => {
+                let day = match item.split_once('#') {
+                    Some((day, nth)) => {
+                        nth.parse::<u32>()
+                            .ok()
+                            .filter(|nth| (1..=5).contains(nth))?;
+                        day
+                    }
+                    None => item.strip_suffix(['L', 'l'])?,
+                };
+                Some(self.parse_value(day).map(|day| 1 << day))
+            }
+        }
+    }
+
+    fn parse_item(&self, item: &str) -> Option<u64> {
+        if let Some(values) = self.parse_extension(item) {
+            return values;
+        }
+
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step = step.parse::<u32>().ok().filter(|step| *step > 0)?;
+                (range, Some(step))
+            }
+            None => (item, None),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (self.min, self.max),
+            Some((start, end)) => (self.parse_value(start)?, self.parse_value(end)?),
+            // A single value with a step runs until the end of the range.
+            None if step.is_some() => (self.parse_value(range)?, self.max),
+            None => {
+                let value = self.parse_value(range)?;
+                (value, value)
+            }
+        };
+
+        if start > end {
+            return None;
+        }
+
+        Some(
+            (start..=end)
+                .step_by(step.unwrap_or(1) as usize)
+                .fold(0, |values, value| values | (1 << value)),
+        )
+    }
+
+    fn parse(&self, field: &str) -> Result<Matches, ScheduleError> {
+        // `?` means "no specific value" and is equivalent to a wildcard.
+        let field = match field {
+            "?" if self.extensions != Extensions::None => "*",
+            _ => field,
+        };
+
+        let mut values = 0;
+        for item in field.split(',') {
+            values |= self
+                .parse_item(item)
+                .ok_or(ScheduleError::InvalidField(self.name))?;
+        }
+
+        Ok(Matches {
+            values,
+            restricted: !field.starts_with('*'),
+        })
+    }
+}
+
+/// Crontab nicknames, which all match at least once a year.
+const NICKNAMES: [&str; 7] = [
+    "@yearly",
+    "@annually",
+    "@monthly",
+    "@weekly",
+    "@daily",
+    "@midnight",
+    "@hourly",
+];
+
+/// Validates a crontab schedule and returns it normalized to single spaces between fields.
+///
+/// Schedules that never match a date, such as `0 0 30 2 *`, are rejected.
+pub fn validate_crontab(crontab: &str) -> Result<String, ScheduleError> {
+    let crontab = crontab.trim();
+    if crontab.starts_with('@') {
+        let nickname = crontab.to_ascii_lowercase();
+        if !NICKNAMES.contains(&nickname.as_str()) {
+            return Err(ScheduleError::InvalidField("nickname"));
+        }
+        return Ok(nickname);
+    }
+
+    let fields = crontab.split_whitespace().collect::<Vec<_>>();
+    if fields.len() != FIELDS.len() {
+        return Err(ScheduleError::FieldCount);
+    }
+
+    let matches = FIELDS
+        .iter()
+        .zip(&fields)
+        .map(|(definition, field)| definition.parse(field))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (days, months, weekdays) = (&matches[2], &matches[3], &matches[4]);
+
+    // If both the day of month and the day of week are restricted, cron runs when either of them
+    // matches. Otherwise, one of the selected days must exist in one of the selected months.
+    if days.restricted && !weekdays.restricted {
+        let possible = (1..=12)
+            .filter(|month| months.values & (1 << month) != 0)
+            .any(|month| {
+                let last_day = DAYS_IN_MONTH[month - 1];
+                days.values & (LAST_DAY | ((1 << (last_day + 1)) - 1)) != 0
+            });
+
+        if !possible {
+            return Err(ScheduleError::Impossible);
+        }
+    }
+
+    Ok(fields.join(" "))
+}
+
+/// Validates an interval schedule.
+pub fn validate_interval(value: u64) -> Result<(), ScheduleError> {
+    if value == 0 {
+        return Err(ScheduleError::ZeroInterval);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use similar_asserts::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_valid_crontabs() {
+        for crontab in [
+            "* * * * *",
+            "*/15 * * * *",
+            "0 9-17 * * mon-fri",
+            "0 0 1,15 * *",
+            "30 4 * jan,jul 0",
+            "5/10 * * * 7",
+            "0 0 29 2 *",
+            "0 0 31 2 1",
+            "0 0 L 2 ?",
+            "0 0 LW * *",
+            "0 0 15W * *",
+            "0 0 ? * 5L",
+            "0 0 ? * fri#3",
+            "0 0 1,L * *",
+            "@Daily",
+        ] {
+            assert!(validate_crontab(crontab).is_ok(), "{crontab}");
+        }
+    }
+
+    #[test]
+    fn test_normalize_crontab() {
+        assert_eq!(validate_crontab("  0  *\t* * *  ").unwrap(), "0 * * * *");
+        assert_eq!(validate_crontab("@Hourly").unwrap(), "@hourly");
+    }
+
+    #[test]
+    fn test_invalid_crontabs() {
+        assert_eq!(validate_crontab("* * * *"), Err(ScheduleError::FieldCount));
+        assert_eq!(
+            validate_crontab("* * * * * *"),
+            Err(ScheduleError::FieldCount)
+        );
+        assert_eq!(
+            validate_crontab("60 * * * *"),
+            Err(ScheduleError::InvalidField("minute"))
+        );
+        assert_eq!(
+            validate_crontab("* 5-2 * * *"),
+            Err(ScheduleError::InvalidField("hour"))
+        );
+        assert_eq!(
+            validate_crontab("* * 0 * *"),
+            Err(ScheduleError::InvalidField("day of month"))
+        );
+        assert_eq!(
+            validate_crontab("* * * foo *"),
+            Err(ScheduleError::InvalidField("month"))
+        );
+        assert_eq!(
+            validate_crontab("* * * * */0"),
+            Err(ScheduleError::InvalidField("day of week"))
+        );
+        assert_eq!(
+            validate_crontab("? * * * *"),
+            Err(ScheduleError::InvalidField("minute"))
+        );
+        assert_eq!(
+            validate_crontab("* * 32W * *"),
+            Err(ScheduleError::InvalidField("day of month"))
+        );
+        assert_eq!(
+            validate_crontab("* * * * 5#6"),
+            Err(ScheduleError::InvalidField("day of week"))
+        );
+        assert_eq!(
+            validate_crontab("* * * * 5W"),
+            Err(ScheduleError::InvalidField("day of week"))
+        );
+        assert_eq!(
+            validate_crontab("@sometimes"),
+            Err(ScheduleError::InvalidField("nickname"))
+        );
+    }
+
+    #[test]
+    fn test_impossible_crontabs() {
+        assert_eq!(
+            validate_crontab("0 0 30 2 *"),
+            Err(ScheduleError::Impossible)
+        );
+        assert_eq!(
+            validate_crontab("0 0 31 apr,jun,sep,nov *"),
+            Err(ScheduleError::Impossible)
+        );
+    }
+
+    #[test]
+    fn test_validate_interval() {
+        assert!(validate_interval(1).is_ok());
+        assert_eq!(validate_interval(0), Err(ScheduleError::ZeroInterval));
+    }
+}
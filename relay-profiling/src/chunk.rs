@@ -0,0 +1,236 @@
+//! Chunks of continuous profiles.
+//!
+//! With continuous profiling, SDKs profile the application independently of transactions and
+//! send the profile in chunks. Every chunk is sent in its own `profile_chunk` envelope item and
+//! is not associated with a transaction.
+//!
+//! Relay only checks the structure of the chunk and forwards the original payload.
+
+use std::time::Duration;
+
+use relay_event_schema::protocol::EventId;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::error::ProfileError;
+
+/// The maximum duration of a single profile chunk.
+///
+/// SDKs rotate chunks every minute. This leaves room for clock skew between samples.
+const MAX_PROFILE_CHUNK_DURATION: Duration = Duration::from_secs(66);
+
+#[derive(Debug, Deserialize)]
+enum Version {
+    #[serde(rename = "2")]
+    V2,
+}
+
+#[derive(Debug, Deserialize)]
+struct Sample {
+    stack_id: usize,
+    /// Unix timestamp in seconds with fractional milliseconds.
+    timestamp: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Profile {
+    samples: Vec<Sample>,
+    stacks: Vec<Vec<usize>>,
+    frames: Vec<IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileChunk {
+    #[serde(rename = "version")]
+    _version: Version,
+    #[serde(rename = "chunk_id")]
+    _chunk_id: EventId,
+    #[serde(rename = "profiler_id")]
+    _profiler_id: EventId,
+    #[serde(rename = "platform")]
+    _platform: String,
+    profile: Profile,
+}
+
+/// Samples reduced to their timestamps, used to compute the duration of a chunk.
+#[derive(Debug, Deserialize)]
+struct MinimalProfileChunk {
+    profile: MinimalProfile,
+}
+
+#[derive(Debug, Deserialize)]
+struct MinimalProfile {
+    samples: Vec<MinimalSample>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MinimalSample {
+    timestamp: f64,
+}
+
+/// Returns the duration between the first and the last sample.
+fn samples_duration(mut timestamps: impl Iterator<Item = f64>) -> Option<Duration> {
+    let first = timestamps.next()?;
+    let last = timestamps.last().unwrap_or(first);
+    Duration::try_from_secs_f64(last - first).ok()
+}
+
+impl ProfileChunk {
+    fn check_samples(&self) -> bool {
+        let mut previous = f64::MIN;
+        for sample in &self.profile.samples {
+            if !sample.timestamp.is_finite() || sample.timestamp < previous {
+                return false;
+            }
+            if self.profile.stacks.get(sample.stack_id).is_none() {
+                return false;
+            }
+            previous = sample.timestamp;
+        }
+        true
+    }
+
+    fn check_stacks(&self) -> bool {
+        self.profile
+            .stacks
+            .iter()
+            .flatten()
+            .all(|frame_id| *frame_id < self.profile.frames.len())
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        samples_duration(self.profile.samples.iter().map(|sample| sample.timestamp))
+    }
+}
+
+/// Checks the structure of a profile chunk and returns its duration.
+///
+/// The chunk must have version `2`, reference valid stacks and frames from its samples, and the
+/// samples must be ordered by timestamp.
+pub fn validate_profile_chunk(payload: &[u8]) -> Result<Duration, ProfileError> {
+    let chunk: ProfileChunk = serde_json::from_slice(payload).map_err(ProfileError::InvalidJson)?;
+
+    if chunk.profile.samples.is_empty() {
+        return Err(ProfileError::NotEnoughSamples);
+    }
+
+    if !chunk.check_samples() {
+        return Err(ProfileError::MalformedSamples);
+    }
+
+    if !chunk.check_stacks() {
+        return Err(ProfileError::MalformedStacks);
+    }
+
+    let duration = chunk.duration().ok_or(ProfileError::MalformedSamples)?;
+    if duration > MAX_PROFILE_CHUNK_DURATION {
+        return Err(ProfileError::DurationIsTooLong);
+    }
+
+    Ok(duration)
+}
+
+/// Returns the duration of a profile chunk without validating it.
+///
+/// This is used to count chunks towards the profile duration quota. Returns `None` if the
+/// samples cannot be read.
+pub fn profile_chunk_duration(payload: &[u8]) -> Option<Duration> {
+    let chunk: MinimalProfileChunk = serde_json::from_slice(payload).ok()?;
+    samples_duration(
+        chunk
+            .profile
+            .samples
+            .into_iter()
+            .map(|sample| sample.timestamp),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(samples: &str, stacks: &str) -> String {
+        format!(
+            r#"{{
+                "version": "2",
+                "chunk_id": "0432a0a4c25f4697bf9f0a2fcbe6a814",
+                "profiler_id": "4d229f1d3807421ba62a5f8bc295d836",
+                "platform": "python",
+                "profile": {{
+                    "samples": {samples},
+                    "stacks": {stacks},
+                    "frames": [{{"function": "main"}}, {{"function": "run"}}]
+                }}
+            }}"#
+        )
+    }
+
+    #[test]
+    fn test_validate_profile_chunk() {
+        let payload = chunk(
+            r#"[
+                {"stack_id": 0, "thread_id": "1", "timestamp": 1710958503.0},
+                {"stack_id": 1, "thread_id": "1", "timestamp": 1710958503.5}
+            ]"#,
+            "[[0], [0, 1]]",
+        );
+
+        let duration = validate_profile_chunk(payload.as_bytes()).unwrap();
+        assert_eq!(duration.as_millis(), 500);
+        assert_eq!(
+            profile_chunk_duration(payload.as_bytes())
+                .unwrap()
+                .as_millis(),
+            500
+        );
+    }
+
+    #[test]
+    fn test_validate_profile_chunk_errors() {
+        let no_samples = chunk("[]", "[[0]]");
+        assert!(matches!(
+            validate_profile_chunk(no_samples.as_bytes()),
+            Err(ProfileError::NotEnoughSamples)
+        ));
+
+        let invalid_stack = chunk(r#"[{"stack_id": 1, "timestamp": 1710958503.0}]"#, "[[0]]");
+        assert!(matches!(
+            validate_profile_chunk(invalid_stack.as_bytes()),
+            Err(ProfileError::MalformedSamples)
+        ));
+
+        let unordered = chunk(
+            r#"[{"stack_id": 0, "timestamp": 1710958504.0}, {"stack_id": 0, "timestamp": 1710958503.0}]"#,
+            "[[0]]",
+        );
+        assert!(matches!(
+            validate_profile_chunk(unordered.as_bytes()),
+            Err(ProfileError::MalformedSamples)
+        ));
+
+        let invalid_frame = chunk(r#"[{"stack_id": 0, "timestamp": 1710958503.0}]"#, "[[2]]");
+        assert!(matches!(
+            validate_profile_chunk(invalid_frame.as_bytes()),
+            Err(ProfileError::MalformedStacks)
+        ));
+
+        let too_long = chunk(
+            r#"[{"stack_id": 0, "timestamp": 1710958503.0}, {"stack_id": 0, "timestamp": 1710958603.0}]"#,
+            "[[0]]",
+        );
+        assert!(matches!(
+            validate_profile_chunk(too_long.as_bytes()),
+            Err(ProfileError::DurationIsTooLong)
+        ));
+    }
+
+    #[test]
+    fn test_validate_profile_chunk_version() {
+        let payload = chunk(r#"[{"stack_id": 0, "timestamp": 1710958503.0}]"#, "[[0]]")
+            .replace(r#""version": "2""#, r#""version": "1""#);
+        assert!(matches!(
+            validate_profile_chunk(payload.as_bytes()),
+            Err(ProfileError::InvalidJson(_))
+        ));
+    }
+}
@@ -98,6 +98,7 @@ use std::collections::BTreeMap;
 use std::time::Duration;
 
 mod android;
+mod chunk;
 mod error;
 mod extract_from_transaction;
 mod measurements;
@@ -111,6 +112,7 @@ use relay_event_schema::protocol::{Event, EventId};
 
 use crate::extract_from_transaction::{extract_transaction_metadata, extract_transaction_tags};
 
+pub use crate::chunk::{profile_chunk_duration, validate_profile_chunk};
 pub use crate::error::ProfileError;
 pub use crate::outcomes::discard_reason;
 
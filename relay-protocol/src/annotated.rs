@@ -268,6 +268,23 @@ where
     pub fn from_json_bytes(b: &[u8]) -> Result<Self, serde_json::Error> {
         Self::deserialize_with_meta(&mut serde_json::Deserializer::from_slice(b))
     }
+
+    /// Deserializes an annotated from JSON bytes using a SIMD-accelerated parser.
+    ///
+    /// The SIMD parser operates on a mutable copy of the input. If it rejects the input, this
+    /// falls back to [`from_json_bytes`](Self::from_json_bytes), so that errors are reported the
+    /// same way regardless of the parser.
+    #[cfg(feature = "simd-json")]
+    pub fn from_json_bytes_simd(b: &[u8]) -> Result<Self, serde_json::Error> {
+        let mut buffer = b.to_vec();
+        if let Ok(mut deserializer) = simd_json::Deserializer::from_slice(&mut buffer) {
+            if let Ok(annotated) = Self::deserialize_with_meta(&mut deserializer) {
+                return Ok(annotated);
+            }
+        }
+
+        Self::from_json_bytes(b)
+    }
 }
 
 impl<T> Annotated<T>
@@ -195,4 +195,55 @@ pub trait IntoValue: Debug + Empty {
 pub trait Getter {
     /// Returns the serialized value of a field pointed to by a `path`.
     fn get_value(&self, path: &str) -> Option<Val<'_>>;
+
+    /// Returns an iterator over the array pointed to by a `path`.
+    ///
+    /// If the path does not exist or is not an array, this returns `None`. Note that `get_value`
+    /// does not return a value for paths that expose an iterator. The default implementation
+    /// always returns `None`.
+    fn get_iter(&self, _path: &str) -> Option<GetterIter<'_>> {
+        None
+    }
+}
+
+/// An iterator over [`Getter`] trait objects, returned by [`Getter::get_iter`].
+pub struct GetterIter<'a> {
+    iter: Box<dyn Iterator<Item = &'a dyn Getter> + 'a>,
+}
+
+impl<'a> GetterIter<'a> {
+    /// Creates a new iterator over getters.
+    pub fn new<I, T>(iterator: I) -> Self
+    where
+        I: Iterator<Item = &'a T> + 'a,
+        T: Getter + 'a,
+    {
+        Self {
+            iter: Box::new(iterator.map(|v| v as &dyn Getter)),
+        }
+    }
+
+    /// Creates a new iterator over annotated getters, skipping empty values.
+    pub fn new_annotated<I, T>(iterator: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Annotated<T>>,
+        I::IntoIter: 'a,
+        T: Getter + 'a,
+    {
+        Self::new(iterator.into_iter().filter_map(Annotated::value))
+    }
+}
+
+impl<'a> Iterator for GetterIter<'a> {
+    type Item = &'a dyn Getter;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+impl Debug for GetterIter<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GetterIter").finish_non_exhaustive()
+    }
 }
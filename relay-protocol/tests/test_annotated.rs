@@ -95,3 +95,25 @@ fn test_annotated_deserialize_with_meta() {
 }"#
     );
 }
+
+#[test]
+#[cfg(feature = "simd-json")]
+fn test_from_json_bytes_simd() {
+    use relay_protocol::Value;
+
+    let json = br#"{"id":"blaflasel","values":[1,-2,3.5,null],"_meta":{"id":{"":{"err":["invalid_data"]}}}}"#;
+
+    let simd = Annotated::<Value>::from_json_bytes_simd(json).unwrap();
+    let serde = Annotated::<Value>::from_json_bytes(json).unwrap();
+    assert_eq!(simd.to_json().unwrap(), serde.to_json().unwrap());
+}
+
+#[test]
+#[cfg(feature = "simd-json")]
+fn test_from_json_bytes_simd_fallback() {
+    use relay_protocol::Value;
+
+    let simd = Annotated::<Value>::from_json_bytes_simd(b"{\"id\":").unwrap_err();
+    let serde = Annotated::<Value>::from_json_bytes(b"{\"id\":").unwrap_err();
+    assert_eq!(simd.to_string(), serde.to_string());
+}
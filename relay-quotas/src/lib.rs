@@ -11,12 +11,22 @@
 /// typically happens for disabled keys, projects, or organizations.
 const REJECT_ALL_SECS: u64 = 60;
 
+mod local;
 mod quota;
 mod rate_limit;
 
+pub use self::local::*;
 pub use self::quota::*;
 pub use self::rate_limit::*;
 
+#[cfg(any(feature = "redis", feature = "memcached"))]
+mod window;
+
+#[cfg(feature = "memcached")]
+mod memcached;
+#[cfg(feature = "memcached")]
+pub use self::memcached::*;
+
 #[cfg(feature = "redis")]
 mod redis;
 #[cfg(feature = "redis")]
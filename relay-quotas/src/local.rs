@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::quota::{ItemScoping, Quota, QuotaScope};
+use crate::rate_limit::{RateLimit, RateLimiter, RateLimits, RetryAfter};
+use crate::REJECT_ALL_SECS;
+
+/// Identifies the counter of a quota for a specific scope.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct CounterKey {
+    /// The quota id.
+    id: String,
+    /// The organization id, which is always part of the key.
+    organization_id: u64,
+    /// The identifier of the quota's scope, unless it is organization-scoped.
+    subscope: Option<u64>,
+    /// The attribute partition of attribute-scoped quotas.
+    partition: Option<u64>,
+}
+
+impl CounterKey {
+    fn new(quota: &Quota, scoping: ItemScoping<'_>) -> Option<Self> {
+        let subscope = match quota.scope {
+            QuotaScope::Organization => None,
+            scope => scoping.scope_id(scope),
+        };
+
+        let partition = quota
+            .attribute
+            .as_deref()
+            .and_then(|name| scoping.attribute_partition(name));
+
+        Some(Self {
+            id: quota.id.clone()?,
+            organization_id: scoping.organization_id,
+            subscope,
+            partition,
+        })
+    }
+}
+
+/// A quota that can be tracked by the [`LocalRateLimiter`].
+#[derive(Debug)]
+struct LocalQuota<'a> {
+    /// The original quota.
+    quota: &'a Quota,
+    /// The key of the quota's counter.
+    key: CounterKey,
+    /// The length of the quota window.
+    window: Duration,
+    /// The time that one unit of quantity occupies in the window.
+    interval: Duration,
+}
+
+impl<'a> LocalQuota<'a> {
+    fn new(quota: &'a Quota, scoping: ItemScoping<'_>) -> Option<Self> {
+        // These fields indicate that we *can* track this quota. Unlimited quotas are counted in
+        // Redis, but there is no point in counting them locally.
+        let window = Duration::from_secs(quota.window.filter(|w| *w > 0)?);
+        let limit = quota.limit.filter(|l| *l > 0)?;
+
+        Some(Self {
+            quota,
+            key: CounterKey::new(quota, scoping)?,
+            window,
+            interval: window.div_f64(limit as f64),
+        })
+    }
+}
+
+/// Tracks quotas in memory using the generic cell rate algorithm (GCRA).
+///
+/// This is an alternative to the `RedisRateLimiter` for Relays that run without Redis. Since the
+/// state is local to this Relay instance, quotas are enforced per instance and thus only
+/// approximately if there are multiple instances.
+///
+/// Every quota counter stores a theoretical arrival time (TAT). Consuming quantity moves the TAT
+/// forward by a fixed interval per unit, computed as `window / limit`. A request is rejected if
+/// the TAT would advance more than one window beyond the current time. As opposed to fixed slots,
+/// this spreads the quota evenly across the window and never allows more than `limit` in any
+/// window.
+///
+/// In addition to the quotas passed to [`is_rate_limited`](Self::is_rate_limited), this rate
+/// limiter can enforce a static list of quotas, see [`with_static_quotas`](Self::with_static_quotas).
+#[derive(Debug)]
+pub struct LocalRateLimiter {
+    max_limit: Option<u64>,
+    counters: Mutex<Counters>,
+}
+
+#[derive(Debug)]
+struct Counters {
+    /// The theoretical arrival time of every counter, relative to `epoch`.
+    tats: HashMap<CounterKey, Duration>,
+    /// The reference point for all arrival times.
+    epoch: Instant,
+    /// The last time that replenished counters were removed.
+    last_prune: Instant,
+}
+
+/// The interval in which replenished counters are removed from the rate limiter.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+impl LocalRateLimiter {
+    /// Creates a new `LocalRateLimiter` instance.
+    pub fn new() -> Self {
+        let now = Instant::now();
+
+        Self {
+            max_limit: None,
+            counters: Mutex::new(Counters {
+                tats: HashMap::new(),
+                epoch: now,
+                last_prune: now,
+            }),
+        }
+    }
+
+    /// Returns a rate limiter that enforces `static_quotas` in addition to the quotas passed by
+    /// callers.
+    ///
+    /// The returned rate limiter borrows the quotas and shares the counters of this instance, so
+    /// static quotas that change over time can be passed without copying them into every call.
+    pub fn with_static_quotas<'a>(&'a self, static_quotas: &'a [Quota]) -> StaticQuotas<'a> {
+        StaticQuotas {
+            limiter: self,
+            static_quotas,
+        }
+    }
+
+    /// Sets the maximum rate limit in seconds.
+    ///
+    /// By default, this rate limiter returns rate limits based on the time until enough quota is
+    /// available again. If a maximum rate limit is set, this limit is bounded.
+    pub fn max_limit(mut self, max_limit: Option<u64>) -> Self {
+        self.max_limit = max_limit;
+        self
+    }
+
+    /// Checks whether any of the quotas in effect for the given item have been exceeded and
+    /// records consumption of the quotas.
+    ///
+    /// This has the same semantics as `RedisRateLimiter::is_rate_limited`: Consumption is only
+    /// recorded if none of the quotas are exceeded, a `quantity` of `0` checks whether the limit
+    /// has been reached, and `over_accept_once` accepts the item if the quota is not yet
+    /// exhausted, even if the quantity exceeds the remaining quota.
+    pub fn is_rate_limited(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> RateLimits {
+        self.check_at(
+            quotas,
+            item_scoping,
+            quantity,
+            over_accept_once,
+            Instant::now(),
+        )
+    }
+
+    fn check_at<'a>(
+        &self,
+        quotas: impl IntoIterator<Item = &'a Quota>,
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+        now: Instant,
+    ) -> RateLimits {
+        let mut tracked_quotas = Vec::new();
+        let mut rate_limits = RateLimits::new();
+
+        for quota in quotas {
+            if !quota.matches(item_scoping) {
+                // Silently skip all quotas that do not apply to this item.
+            } else if quota.limit == Some(0) {
+                // A zero-sized quota is strongest and does not need to be tracked.
+                let retry_after = self.retry_after(Duration::from_secs(REJECT_ALL_SECS));
+                rate_limits.add(RateLimit::from_quota(quota, item_scoping, retry_after));
+            } else if let Some(quota) = LocalQuota::new(quota, item_scoping) {
+                tracked_quotas.push(quota);
+            }
+        }
+
+        if tracked_quotas.is_empty() || rate_limits.is_limited() {
+            return rate_limits;
+        }
+
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = now.saturating_duration_since(counters.epoch);
+
+        if now.saturating_duration_since(counters.last_prune) >= PRUNE_INTERVAL {
+            // Counters with an arrival time in the past are fully replenished.
+            counters.tats.retain(|_, tat| *tat > elapsed);
+            counters.last_prune = now;
+        }
+
+        let units = u32::try_from(quantity).unwrap_or(u32::MAX);
+        let mut updates = Vec::with_capacity(tracked_quotas.len());
+
+        for quota in &tracked_quotas {
+            let tat = counters
+                .tats
+                .get(&quota.key)
+                .copied()
+                .unwrap_or_default()
+                .max(elapsed);
+
+            // With `over_accept_once`, it suffices that there is some quota left. A quantity of `0`
+            // requires capacity for at least one unit, so it reports exhausted quotas.
+            let required = if over_accept_once || quantity == 0 {
+                quota.interval
+            } else {
+                quota.interval.saturating_mul(units)
+            };
+
+            let next = tat.saturating_add(required);
+            if next > elapsed + quota.window {
+                let retry_after = self.retry_after(next - elapsed - quota.window);
+                rate_limits.add(RateLimit::from_quota(
+                    quota.quota,
+                    item_scoping,
+                    retry_after,
+                ));
+            } else {
+                let consumed = quota.interval.saturating_mul(units);
+                updates.push((quota.key.clone(), tat.saturating_add(consumed)));
+            }
+        }
+
+        // Consumption is only recorded if the item is accepted by all quotas.
+        if !rate_limits.is_limited() {
+            counters.tats.extend(updates);
+        }
+
+        rate_limits
+    }
+
+    /// Creates a rate limit bounded by `max_limit`.
+    fn retry_after(&self, duration: Duration) -> RetryAfter {
+        // Round up to full seconds, since rate limits are communicated in seconds.
+        let mut seconds = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+        if let Some(max_limit) = self.max_limit {
+            seconds = std::cmp::min(seconds, max_limit);
+        }
+
+        RetryAfter::from_secs(seconds)
+    }
+}
+
+impl Default for LocalRateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter for LocalRateLimiter {
+    type Error = Infallible;
+
+    fn is_rate_limited(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> Result<RateLimits, Self::Error> {
+        Ok(LocalRateLimiter::is_rate_limited(
+            self,
+            quotas,
+            item_scoping,
+            quantity,
+            over_accept_once,
+        ))
+    }
+}
+
+/// A [`LocalRateLimiter`] that enforces static quotas in addition to the quotas passed by callers.
+///
+/// Created by [`LocalRateLimiter::with_static_quotas`].
+#[derive(Debug)]
+pub struct StaticQuotas<'a> {
+    limiter: &'a LocalRateLimiter,
+    static_quotas: &'a [Quota],
+}
+
+impl RateLimiter for StaticQuotas<'_> {
+    type Error = Infallible;
+
+    fn is_rate_limited(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> Result<RateLimits, Self::Error> {
+        Ok(self.limiter.check_at(
+            quotas.iter().chain(self.static_quotas),
+            item_scoping,
+            quantity,
+            over_accept_once,
+            Instant::now(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_base_schema::project::{ProjectId, ProjectKey};
+
+    use super::*;
+    use crate::quota::{DataCategories, DataCategory, ReasonCode, Scoping};
+
+    fn scoping() -> Scoping {
+        Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(43),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(44),
+        }
+    }
+
+    fn quota(limit: u64, window: u64) -> Quota {
+        Quota {
+            id: Some("foo".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Key,
+            scope_id: None,
+            limit: Some(limit),
+            window: Some(window),
+            reason_code: Some(ReasonCode::new("local")),
+            attribute: None,
+            namespace: None,
+        }
+    }
+
+    #[test]
+    fn test_zero_size_quotas() {
+        let limiter = LocalRateLimiter::new();
+        let scoping = scoping();
+
+        let rate_limits =
+            limiter.is_rate_limited(&[quota(0, 60)], scoping.item(DataCategory::Error), 1, false);
+
+        let limit = rate_limits.longest().unwrap();
+        assert_eq!(limit.reason_code, Some(ReasonCode::new("local")));
+    }
+
+    #[test]
+    fn test_simple_quota() {
+        let limiter = LocalRateLimiter::new();
+        let scoping = scoping();
+        let quotas = &[quota(5, 60)];
+        let now = Instant::now();
+
+        for i in 0..10 {
+            let rate_limits =
+                limiter.check_at(quotas, scoping.item(DataCategory::Error), 1, false, now);
+
+            if i >= 5 {
+                assert!(rate_limits.is_limited(), "not limited at {i}");
+            } else {
+                assert!(rate_limits.is_ok(), "limited at {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_replenishes_over_time() {
+        let limiter = LocalRateLimiter::new();
+        let scoping = scoping();
+        let quotas = &[quota(6, 60)];
+        let now = Instant::now();
+
+        let item = scoping.item(DataCategory::Error);
+        assert!(limiter.check_at(quotas, item, 6, false, now).is_ok());
+
+        let rate_limits = limiter.check_at(quotas, item, 1, false, now);
+        let retry_after = rate_limits.longest().unwrap().retry_after;
+        assert_eq!(retry_after.remaining_seconds(), 10);
+
+        // Each unit takes ten seconds to replenish.
+        let later = now + Duration::from_secs(10);
+        assert!(limiter.check_at(quotas, item, 1, false, later).is_ok());
+        assert!(limiter.check_at(quotas, item, 1, false, later).is_limited());
+    }
+
+    #[test]
+    fn test_quantity_0() {
+        let limiter = LocalRateLimiter::new();
+        let scoping = scoping();
+        let quotas = &[quota(1, 60)];
+        let now = Instant::now();
+
+        let item = scoping.item(DataCategory::Error);
+        assert!(limiter.check_at(quotas, item, 0, false, now).is_ok());
+        assert!(limiter.check_at(quotas, item, 1, false, now).is_ok());
+        assert!(limiter.check_at(quotas, item, 0, false, now).is_limited());
+    }
+
+    #[test]
+    fn test_over_accept_once() {
+        let limiter = LocalRateLimiter::new();
+        let scoping = scoping();
+        let quotas = &[quota(10, 60)];
+        let now = Instant::now();
+
+        let item = scoping.item(DataCategory::Error);
+        assert!(limiter.check_at(quotas, item, 20, false, now).is_limited());
+        assert!(limiter.check_at(quotas, item, 20, true, now).is_ok());
+        assert!(limiter.check_at(quotas, item, 1, true, now).is_limited());
+    }
+
+    #[test]
+    fn test_no_partial_consumption() {
+        let limiter = LocalRateLimiter::new();
+        let scoping = scoping();
+        let now = Instant::now();
+
+        let mut small = quota(1, 60);
+        small.id = Some("small".to_owned());
+        let large = quota(10, 60);
+
+        let item = scoping.item(DataCategory::Error);
+        assert!(limiter
+            .check_at(&[small.clone()], item, 1, false, now)
+            .is_ok());
+
+        // The small quota rejects the item, so the large quota must not be consumed.
+        for _ in 0..5 {
+            let quotas = [small.clone(), large.clone()];
+            assert!(limiter.check_at(&quotas, item, 1, false, now).is_limited());
+        }
+
+        assert!(limiter.check_at(&[large], item, 10, false, now).is_ok());
+    }
+
+    #[test]
+    fn test_scopes_are_independent() {
+        let limiter = LocalRateLimiter::new();
+        let now = Instant::now();
+        let quotas = &[quota(1, 60)];
+
+        let first = scoping();
+        let second = Scoping {
+            key_id: Some(45),
+            ..first
+        };
+
+        let first = first.item(DataCategory::Error);
+        let second = second.item(DataCategory::Error);
+
+        assert!(limiter.check_at(quotas, first, 1, false, now).is_ok());
+        assert!(limiter.check_at(quotas, first, 1, false, now).is_limited());
+        assert!(limiter.check_at(quotas, second, 1, false, now).is_ok());
+    }
+
+    #[test]
+    fn test_static_quotas() {
+        let limiter = LocalRateLimiter::new();
+        let static_quotas = [quota(1, 60)];
+        let scoping = scoping();
+
+        let item = scoping.item(DataCategory::Error);
+        let limiter = limiter.with_static_quotas(&static_quotas);
+        assert!(limiter
+            .is_rate_limited(&[], item, 1, false)
+            .unwrap()
+            .is_ok());
+        assert!(limiter
+            .is_rate_limited(&[], item, 1, false)
+            .unwrap()
+            .is_limited());
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use memcache::{Client, CommandError, MemcacheError};
+use relay_common::time::UnixTimestamp;
+use relay_log::protocol::value;
+
+use crate::quota::{ItemScoping, Quota};
+use crate::rate_limit::{RateLimit, RateLimiter, RateLimitingError, RateLimits, RetryAfter};
+use crate::window::{get_refunded_quota_key, WindowedQuota, GRACE};
+use crate::REJECT_ALL_SECS;
+
+/// Returns `true` if consuming `quantity` exceeds the quota.
+///
+/// This implements the same check as `is_rate_limited.lua`. Unlimited quotas have a negative
+/// limit and are never exceeded.
+fn is_rejected(consumed: i64, limit: i64, quantity: usize, over_accept_once: bool) -> bool {
+    if limit < 0 {
+        false
+    } else if quantity == 0 || over_accept_once {
+        consumed >= limit
+    } else {
+        consumed.saturating_add(quantity as i64) > limit
+    }
+}
+
+/// Counter operations on memcached, implemented by [`Client`].
+///
+/// This allows to replace memcached with an in-memory store in tests.
+trait Counters: Send + Sync {
+    /// Returns the values of all counters that exist.
+    fn get_counters(&self, keys: &[&str]) -> Result<HashMap<String, i64>, MemcacheError>;
+
+    /// Creates a counter, failing with [`CommandError::KeyExists`] if it exists already.
+    fn add(&self, key: &str, value: u64, expiry: u32) -> Result<(), MemcacheError>;
+
+    /// Increments an existing counter.
+    fn increment(&self, key: &str, value: u64) -> Result<(), MemcacheError>;
+}
+
+impl Counters for Client {
+    fn get_counters(&self, keys: &[&str]) -> Result<HashMap<String, i64>, MemcacheError> {
+        self.gets(keys)
+    }
+
+    fn add(&self, key: &str, value: u64, expiry: u32) -> Result<(), MemcacheError> {
+        Client::add(self, key, value, expiry)
+    }
+
+    fn increment(&self, key: &str, value: u64) -> Result<(), MemcacheError> {
+        Client::increment(self, key, value).map(|_| ())
+    }
+}
+
+/// Tracks quotas in memcached.
+///
+/// This is an alternative to the `RedisRateLimiter` for deployments that run memcached. Quotas
+/// are counted in the same windows and under the same keys as in Redis, and counters are
+/// incremented with memcached's atomic `incr`.
+///
+/// Memcached cannot check and increment several counters in one atomic operation. All counters
+/// are read first, and only incremented if none of the quotas is exceeded. Concurrent requests
+/// can therefore exceed a quota by the quantity that is checked at the same time.
+///
+/// Servers must use memcached's binary protocol, which is the default of the client. The text
+/// protocol does not report whether a counter already existed when creating it.
+///
+/// Requires the `memcached` feature.
+#[derive(Clone)]
+pub struct MemcachedRateLimiter {
+    client: Arc<dyn Counters>,
+    max_limit: Option<u64>,
+}
+
+impl MemcachedRateLimiter {
+    /// Connects to the given memcached servers.
+    ///
+    /// Servers are URLs such as `memcache://127.0.0.1:11211`. The `timeout` applies to reads and
+    /// writes on every connection.
+    pub fn new(servers: Vec<String>, timeout: Duration) -> Result<Self, RateLimitingError> {
+        let client = Client::connect(servers).map_err(RateLimitingError::Memcached)?;
+        client
+            .set_read_timeout(Some(timeout))
+            .map_err(RateLimitingError::Memcached)?;
+        client
+            .set_write_timeout(Some(timeout))
+            .map_err(RateLimitingError::Memcached)?;
+
+        Ok(Self::with_counters(client))
+    }
+
+    /// Creates a rate limiter on top of the given counters.
+    fn with_counters(counters: impl Counters + 'static) -> Self {
+        Self {
+            client: Arc::new(counters),
+            max_limit: None,
+        }
+    }
+
+    /// Sets the maximum rate limit in seconds.
+    ///
+    /// By default, this rate limiter will return rate limits based on the quotas' `window` fields.
+    /// If a maximum rate limit is set, this limit is bounded.
+    pub fn max_limit(mut self, max_limit: Option<u64>) -> Self {
+        self.max_limit = max_limit;
+        self
+    }
+
+    /// Checks whether any of the quotas in effect for the given item have been exceeded and
+    /// records consumption of the quotas.
+    ///
+    /// This has the same semantics as `RedisRateLimiter::is_rate_limited`.
+    pub fn is_rate_limited(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> Result<RateLimits, RateLimitingError> {
+        let timestamp = UnixTimestamp::now();
+        let mut tracked_quotas = Vec::new();
+        let mut rate_limits = RateLimits::new();
+
+        for quota in quotas {
+            if !quota.matches(item_scoping) {
+                // Silently skip all quotas that do not apply to this item.
+            } else if quota.limit == Some(0) {
+                // A zero-sized quota is strongest. Do not call into memcached at all.
+                let retry_after = self.retry_after(REJECT_ALL_SECS);
+                rate_limits.add(RateLimit::from_quota(quota, item_scoping, retry_after));
+            } else if let Some(quota) = WindowedQuota::new(quota, item_scoping, timestamp) {
+                tracked_quotas.push(quota);
+            } else {
+                relay_log::with_scope(
+                    |scope| scope.set_extra("quota", value::to_value(quota).unwrap()),
+                    || relay_log::warn!("skipping unsupported quota"),
+                )
+            }
+        }
+
+        if tracked_quotas.is_empty() || rate_limits.is_limited() {
+            return Ok(rate_limits);
+        }
+
+        let keys: Vec<String> = tracked_quotas.iter().map(|quota| quota.key()).collect();
+        let refund_keys: Vec<String> = keys.iter().map(|key| get_refunded_quota_key(key)).collect();
+
+        let all_keys: Vec<&str> = keys
+            .iter()
+            .chain(&refund_keys)
+            .map(String::as_str)
+            .collect();
+        let counters: HashMap<String, i64> = self
+            .client
+            .get_counters(&all_keys)
+            .map_err(RateLimitingError::Memcached)?;
+        let counter = |key: &str| counters.get(key).copied().unwrap_or(0);
+
+        for ((quota, key), refund_key) in tracked_quotas.iter().zip(&keys).zip(&refund_keys) {
+            let consumed = counter(key) - counter(refund_key);
+            if is_rejected(consumed, quota.limit(), quantity, over_accept_once) {
+                let retry_after = self.retry_after((quota.expiry() - timestamp).as_secs());
+                rate_limits.add(RateLimit::from_quota(quota, item_scoping, retry_after));
+            }
+        }
+
+        if rate_limits.is_limited() || quantity == 0 {
+            return Ok(rate_limits);
+        }
+
+        for (quota, key) in tracked_quotas.iter().zip(&keys) {
+            // Expiration times larger than 30 days are absolute timestamps in memcached.
+            let expiry = quota.expiry().as_secs() + GRACE;
+            self.increment(key, quantity as u64, expiry as u32)
+                .map_err(RateLimitingError::Memcached)?;
+        }
+
+        Ok(rate_limits)
+    }
+
+    /// Increments a counter, creating it with the given expiry if it does not exist.
+    fn increment(&self, key: &str, quantity: u64, expiry: u32) -> Result<(), MemcacheError> {
+        // `add` only stores the counter if it does not exist yet, so creation does not race with
+        // increments from other Relays. Only an existing counter is incremented, other errors such
+        // as connection failures are returned.
+        match self.client.add(key, quantity, expiry) {
+            Err(MemcacheError::CommandError(CommandError::KeyExists)) => {
+                self.client.increment(key, quantity)
+            }
+            result => result,
+        }
+    }
+
+    /// Creates a rate limit bounded by `max_limit`.
+    fn retry_after(&self, mut seconds: u64) -> RetryAfter {
+        if let Some(max_limit) = self.max_limit {
+            seconds = std::cmp::min(seconds, max_limit);
+        }
+
+        RetryAfter::from_secs(seconds)
+    }
+}
+
+impl RateLimiter for MemcachedRateLimiter {
+    type Error = RateLimitingError;
+
+    fn is_rate_limited(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> Result<RateLimits, Self::Error> {
+        MemcachedRateLimiter::is_rate_limited(
+            self,
+            quotas,
+            item_scoping,
+            quantity,
+            over_accept_once,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use relay_base_schema::project::{ProjectId, ProjectKey};
+
+    use crate::quota::{DataCategories, DataCategory, QuotaScope, ReasonCode, Scoping};
+
+    use super::*;
+
+    /// In-memory counters with the semantics of memcached's binary protocol.
+    #[derive(Clone, Default)]
+    struct MockCounters(Arc<Mutex<HashMap<String, i64>>>);
+
+    impl MockCounters {
+        fn set(&self, key: &str, value: i64) {
+            self.0.lock().unwrap().insert(key.to_owned(), value);
+        }
+
+        fn snapshot(&self) -> HashMap<String, i64> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl Counters for MockCounters {
+        fn get_counters(&self, keys: &[&str]) -> Result<HashMap<String, i64>, MemcacheError> {
+            let counters = self.0.lock().unwrap();
+            Ok(keys
+                .iter()
+                .filter_map(|key| Some((key.to_string(), *counters.get(*key)?)))
+                .collect())
+        }
+
+        fn add(&self, key: &str, value: u64, _expiry: u32) -> Result<(), MemcacheError> {
+            let mut counters = self.0.lock().unwrap();
+            if counters.contains_key(key) {
+                return Err(CommandError::KeyExists.into());
+            }
+            counters.insert(key.to_owned(), value as i64);
+            Ok(())
+        }
+
+        fn increment(&self, key: &str, value: u64) -> Result<(), MemcacheError> {
+            match self.0.lock().unwrap().get_mut(key) {
+                Some(counter) => {
+                    *counter += value as i64;
+                    Ok(())
+                }
+                None => Err(CommandError::KeyNotFound.into()),
+            }
+        }
+    }
+
+    /// Counters that fail every operation as if the server was unreachable.
+    struct FailingCounters;
+
+    impl Counters for FailingCounters {
+        fn get_counters(&self, _keys: &[&str]) -> Result<HashMap<String, i64>, MemcacheError> {
+            Ok(HashMap::new())
+        }
+
+        fn add(&self, _key: &str, _value: u64, _expiry: u32) -> Result<(), MemcacheError> {
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into())
+        }
+
+        fn increment(&self, _key: &str, _value: u64) -> Result<(), MemcacheError> {
+            panic!("must not increment after a failed add");
+        }
+    }
+
+    fn quota() -> Quota {
+        Quota {
+            id: Some("foo".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(5),
+            window: Some(60),
+            reason_code: Some(ReasonCode::new("get_lost")),
+            attribute: None,
+            namespace: None,
+        }
+    }
+
+    fn scoping() -> Scoping {
+        Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(43),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(44),
+        }
+    }
+
+    #[test]
+    fn test_read_then_increment() {
+        let counters = MockCounters::default();
+        let rate_limiter = MemcachedRateLimiter::with_counters(counters.clone());
+        let quotas = &[quota()];
+        let scoping = scoping();
+        let item_scoping = ItemScoping {
+            category: DataCategory::Error,
+            scoping: &scoping,
+            attributes: None,
+            namespace: None,
+        };
+        let is_limited = |quantity| {
+            rate_limiter
+                .is_rate_limited(quotas, item_scoping, quantity, false)
+                .unwrap()
+                .is_limited()
+        };
+
+        // The first request creates the counter.
+        assert!(!is_limited(3));
+        let snapshot = counters.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        let (key, consumed) = snapshot.into_iter().next().unwrap();
+        assert_eq!(consumed, 3);
+
+        // Without refunds, the next request would exceed the quota and is not counted.
+        assert!(is_limited(3));
+        assert_eq!(counters.snapshot()[&key], 3);
+
+        // Refunds are subtracted from the counter.
+        let refund_key = get_refunded_quota_key(&key);
+        counters.set(&refund_key, 2);
+        assert!(!is_limited(3));
+        assert_eq!(counters.snapshot()[&key], 6);
+        assert_eq!(counters.snapshot()[&refund_key], 2);
+
+        // A quantity of 0 only checks the quota.
+        assert!(!is_limited(0));
+        assert_eq!(counters.snapshot()[&key], 6);
+    }
+
+    #[test]
+    fn test_increment_error() {
+        let rate_limiter = MemcachedRateLimiter::with_counters(FailingCounters);
+        let quotas = &[quota()];
+        let scoping = scoping();
+        let item_scoping = ItemScoping {
+            category: DataCategory::Error,
+            scoping: &scoping,
+            attributes: None,
+            namespace: None,
+        };
+
+        let result = rate_limiter.is_rate_limited(quotas, item_scoping, 1, false);
+        assert!(matches!(result, Err(RateLimitingError::Memcached(_))));
+    }
+
+    #[test]
+    fn test_is_rejected() {
+        // Unlimited quotas.
+        assert!(!is_rejected(100, -1, 1, false));
+
+        // Without over_accept_once, the quantity must fit into the remaining quota.
+        assert!(!is_rejected(3, 5, 2, false));
+        assert!(is_rejected(4, 5, 2, false));
+
+        // With over_accept_once, the quota must not be exhausted yet.
+        assert!(!is_rejected(4, 5, 2, true));
+        assert!(is_rejected(5, 5, 2, true));
+
+        // A quantity of 0 checks whether the quota is exhausted.
+        assert!(!is_rejected(4, 5, 0, false));
+        assert!(is_rejected(5, 5, 0, false));
+    }
+}
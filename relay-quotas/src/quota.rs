@@ -1,13 +1,23 @@
 use std::fmt;
 use std::str::FromStr;
 
+use relay_base_schema::metrics::MetricNamespace;
 use relay_base_schema::project::{ProjectId, ProjectKey};
+use relay_protocol::Getter;
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 
 #[doc(inline)]
 pub use relay_base_schema::data_category::DataCategory;
 
+/// The number of partitions that values of a quota attribute are hashed into.
+///
+/// Attribute-scoped quotas are counted separately for every partition. This bounds the number of
+/// counters per quota and scope regardless of the cardinality of the attribute.
+pub const ATTRIBUTE_PARTITIONS: u64 = 1024;
+
 /// Data scoping information.
 ///
 /// This structure holds information of all scopes required for attributing an item to quotas.
@@ -35,6 +45,8 @@ impl Scoping {
         ItemScoping {
             category,
             scoping: self,
+            attributes: None,
+            namespace: None,
         }
     }
 }
@@ -44,13 +56,35 @@ impl Scoping {
 /// `ItemScoping` is always attached to a `Scope` and references it internally. It is a cheap,
 /// copyable type intended for the use with `RateLimits` and `RateLimiter`. It implements
 /// `Deref<Target = Scoping>` and `AsRef<Scoping>` for ease of use.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy)]
 pub struct ItemScoping<'a> {
     /// The data category of the item.
     pub category: DataCategory,
 
     /// Scoping of the data.
     pub scoping: &'a Scoping,
+
+    /// Attributes of the item, such as the event payload.
+    ///
+    /// Quotas with an [`attribute`](Quota::attribute) only match items that provide a value for
+    /// this attribute. If no attributes are available, such quotas are skipped.
+    pub attributes: Option<&'a dyn Getter>,
+
+    /// The namespace of metric buckets.
+    ///
+    /// Quotas with a [`namespace`](Quota::namespace) only match items in the same namespace.
+    pub namespace: Option<MetricNamespace>,
+}
+
+impl fmt::Debug for ItemScoping<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ItemScoping")
+            .field("category", &self.category)
+            .field("scoping", &self.scoping)
+            .field("attributes", &self.attributes.map(|_| ".."))
+            .field("namespace", &self.namespace)
+            .finish()
+    }
 }
 
 impl AsRef<Scoping> for ItemScoping<'_> {
@@ -67,7 +101,31 @@ impl std::ops::Deref for ItemScoping<'_> {
     }
 }
 
-impl ItemScoping<'_> {
+impl<'a> ItemScoping<'a> {
+    /// Attaches attributes of the item for attribute-scoped quotas.
+    pub fn with_attributes(self, attributes: &'a dyn Getter) -> Self {
+        Self {
+            attributes: Some(attributes),
+            ..self
+        }
+    }
+
+    /// Restricts the item scoping to metric buckets in the given namespace.
+    pub fn with_namespace(self, namespace: MetricNamespace) -> Self {
+        Self {
+            namespace: Some(namespace),
+            ..self
+        }
+    }
+
+    /// Returns the partition of the given attribute's value.
+    ///
+    /// Returns `None` if there are no attributes, or if the attribute is missing or not a string.
+    pub fn attribute_partition(&self, name: &str) -> Option<u64> {
+        let value = self.attributes?.get_value(name)?;
+        Some(hash_value(value.as_str()?) % ATTRIBUTE_PARTITIONS)
+    }
+
     /// Returns the identifier of the given scope.
     pub fn scope_id(&self, scope: QuotaScope) -> Option<u64> {
         match scope {
@@ -78,6 +136,13 @@ impl ItemScoping<'_> {
         }
     }
 
+    /// Checks whether the item's namespace matches the given namespace constraint.
+    ///
+    /// Items match if there is no constraint, or if the item belongs to the same namespace.
+    pub(crate) fn matches_namespace(&self, namespace: Option<MetricNamespace>) -> bool {
+        namespace.is_none() || self.namespace == namespace
+    }
+
     /// Checks whether the category matches any of the quota's categories.
     pub(crate) fn matches_categories(&self, categories: &DataCategories) -> bool {
         // An empty list of categories means that this quota matches all categories. Note that we
@@ -88,12 +153,22 @@ impl ItemScoping<'_> {
     }
 }
 
+/// Hashes the value with 64-bit FNV-1a.
+///
+/// The hash must be stable across Relay instances and versions, since it is part of the Redis key.
+fn hash_value(value: &str) -> u64 {
+    value.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
 /// The unit in which a data category is measured.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum CategoryUnit {
     Count,
     Bytes,
     Batched,
+    Milliseconds,
 }
 
 impl CategoryUnit {
@@ -110,9 +185,13 @@ impl CategoryUnit {
             | DataCategory::TransactionIndexed
             | DataCategory::Span
             | DataCategory::MonitorSeat
+            | DataCategory::MetricBucket
+            | DataCategory::Log
+            | DataCategory::Feedback
             | DataCategory::Monitor => Some(Self::Count),
-            DataCategory::Attachment => Some(Self::Bytes),
+            DataCategory::Attachment | DataCategory::LogByte => Some(Self::Bytes),
             DataCategory::Session => Some(Self::Batched),
+            DataCategory::ProfileDuration => Some(Self::Milliseconds),
 
             DataCategory::Unknown => None,
         }
@@ -129,6 +208,7 @@ pub type DataCategories = SmallVec<[DataCategory; 8]>;
 /// Except for the `Unknown` variant, this type directly translates to the variants of
 /// `RateLimitScope` which are used by rate limits.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum QuotaScope {
     /// The organization that this project belongs to.
@@ -193,6 +273,7 @@ fn default_scope() -> QuotaScope {
 
 /// A machine readable, freeform reason code for rate limits.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Hash)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 pub struct ReasonCode(String);
 
 impl ReasonCode {
@@ -222,6 +303,7 @@ impl fmt::Display for ReasonCode {
 /// configured by the customer. Each piece of data (such as event, attachment) will be counted
 /// against all quotas that it matches with based on the `category`.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Quota {
     /// The unique identifier for counting this quota. Required, except for quotas with a `limit` of
@@ -232,6 +314,7 @@ pub struct Quota {
     /// A set of data categories that this quota applies to. If missing or empty, this quota
     /// applies to all data.
     #[serde(default = "DataCategories::new")]
+    #[cfg_attr(feature = "jsonschema", schemars(with = "Vec<DataCategory>"))]
     pub categories: DataCategories,
 
     /// A scope for this quota. This quota is enforced separately within each instance of this scope
@@ -261,6 +344,23 @@ pub struct Quota {
     /// `limit=None`, since unlimited quotas can never be exceeded.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub reason_code: Option<ReasonCode>,
+
+    /// An event attribute to partition this quota by, such as `event.release` or
+    /// `event.tags.environment`.
+    ///
+    /// If set, the quota is counted separately for every value of the attribute, and it only
+    /// applies to items that have a string value for this attribute. To bound the number of
+    /// counters, values are hashed into [`ATTRIBUTE_PARTITIONS`] partitions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribute: Option<String>,
+
+    /// The metric namespace that this quota applies to.
+    ///
+    /// If set, the quota only applies to metric buckets in this namespace, and all other data is
+    /// not counted towards it. Combined with `scope`, `scopeId`, and `categories`, this allows to
+    /// limit a single namespace for a specific project key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<MetricNamespace>,
 }
 
 impl Quota {
@@ -310,18 +410,38 @@ impl Quota {
         scoping.scope_id(self.scope) == Some(parsed)
     }
 
+    /// Checks whether the item provides a value for the quota's attribute, if there is one.
+    fn matches_attribute(&self, scoping: ItemScoping<'_>) -> bool {
+        match self.attribute {
+            Some(ref attribute) => scoping.attribute_partition(attribute).is_some(),
+            None => true,
+        }
+    }
+
     /// Checks whether the quota's constraints match the current item.
     pub fn matches(&self, scoping: ItemScoping<'_>) -> bool {
-        self.matches_scope(scoping) && scoping.matches_categories(&self.categories)
+        self.matches_scope(scoping)
+            && scoping.matches_categories(&self.categories)
+            && scoping.matches_namespace(self.namespace)
+            && self.matches_attribute(scoping)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use relay_protocol::Val;
     use smallvec::smallvec;
 
     use super::*;
 
+    struct Release(&'static str);
+
+    impl Getter for Release {
+        fn get_value(&self, path: &str) -> Option<Val<'_>> {
+            (path == "event.release").then_some(self.0.into())
+        }
+    }
+
     #[test]
     fn test_parse_quota_reject_all() {
         let json = r#"{
@@ -525,6 +645,8 @@ mod tests {
             limit: Some(0),
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(quota.is_valid());
@@ -540,6 +662,8 @@ mod tests {
             limit: Some(0),
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(!quota.is_valid());
@@ -555,6 +679,8 @@ mod tests {
             limit: Some(0),
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(quota.is_valid());
@@ -570,6 +696,8 @@ mod tests {
             limit: Some(1000),
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         // This category is limited and counted, but has multiple units.
@@ -586,6 +714,8 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         // This category is unlimited and counted, but has multiple units.
@@ -602,6 +732,8 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(quota.matches(ItemScoping {
@@ -611,7 +743,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
@@ -625,6 +759,8 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(!quota.matches(ItemScoping {
@@ -634,7 +770,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
@@ -648,6 +786,8 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(quota.matches(ItemScoping {
@@ -657,7 +797,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -667,7 +809,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
@@ -681,6 +825,8 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(!quota.matches(ItemScoping {
@@ -690,7 +836,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
@@ -704,6 +852,8 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(quota.matches(ItemScoping {
@@ -713,7 +863,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -723,7 +875,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
@@ -737,6 +891,8 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(quota.matches(ItemScoping {
@@ -746,7 +902,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -756,7 +914,9 @@ mod tests {
                 project_id: ProjectId::new(0),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
@@ -770,6 +930,8 @@ mod tests {
             limit: None,
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         assert!(quota.matches(ItemScoping {
@@ -779,7 +941,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(17),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -789,7 +953,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(0),
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
 
         assert!(!quota.matches(ItemScoping {
@@ -799,7 +965,160 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
+
+    #[test]
+    fn test_parse_quota_attribute() {
+        let json = r#"{
+            "id": "r",
+            "categories": ["error"],
+            "limit": 10000,
+            "window": 60,
+            "reasonCode": "release_quota",
+            "attribute": "event.release"
+        }"#;
+
+        let quota = serde_json::from_str::<Quota>(json).expect("parse quota");
+
+        insta::assert_ron_snapshot!(quota, @r#"
+        Quota(
+          id: Some("r"),
+          categories: [
+            error,
+          ],
+          scope: organization,
+          limit: Some(10000),
+          window: Some(60),
+          reasonCode: Some(ReasonCode("release_quota")),
+          attribute: Some("event.release"),
+        )
+        "#);
+    }
+
+    #[test]
+    fn test_parse_quota_namespace() {
+        let json = r#"{
+            "id": "m",
+            "categories": ["metric_bucket"],
+            "scope": "key",
+            "limit": 1000,
+            "window": 60,
+            "reasonCode": "custom_metrics",
+            "namespace": "custom"
+        }"#;
+
+        let quota = serde_json::from_str::<Quota>(json).expect("parse quota");
+
+        insta::assert_ron_snapshot!(quota, @r#"
+        Quota(
+          id: Some("m"),
+          categories: [
+            metric_bucket,
+          ],
+          scope: key,
+          limit: Some(1000),
+          window: Some(60),
+          reasonCode: Some(ReasonCode("custom_metrics")),
+          namespace: Some("custom"),
+        )
+        "#);
+    }
+
+    #[test]
+    fn test_quota_matches_namespace() {
+        let quota = Quota {
+            id: Some("m".to_owned()),
+            categories: smallvec![DataCategory::MetricBucket],
+            scope: QuotaScope::Key,
+            scope_id: Some("17".to_owned()),
+            limit: Some(1000),
+            window: Some(60),
+            reason_code: None,
+            attribute: None,
+            namespace: Some(MetricNamespace::Custom),
+        };
+
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(17),
+        };
+
+        let item = scoping.item(DataCategory::MetricBucket);
+        assert!(quota.matches(item.with_namespace(MetricNamespace::Custom)));
+        assert!(!quota.matches(item.with_namespace(MetricNamespace::Sessions)));
+        assert!(!quota.matches(item));
+
+        // Other data categories of the same key are not affected.
+        assert!(!quota.matches(scoping.item(DataCategory::Error)));
+    }
+
+    #[test]
+    fn test_quota_matches_attribute() {
+        let quota = Quota {
+            id: Some("r".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(10000),
+            window: Some(60),
+            reason_code: None,
+            attribute: Some("event.release".to_owned()),
+            namespace: None,
+        };
+
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(17),
+        };
+
+        let release = Release("1.0.0");
+        assert!(quota.matches(scoping.item(DataCategory::Error).with_attributes(&release)));
+
+        // Items without the attribute are not counted towards the quota.
+        assert!(!quota.matches(scoping.item(DataCategory::Error)));
+        let mut other = quota.clone();
+        other.attribute = Some("event.transaction".to_owned());
+        assert!(!other.matches(scoping.item(DataCategory::Error).with_attributes(&release)));
+    }
+
+    #[test]
+    fn test_attribute_partition() {
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(17),
+        };
+
+        let first = Release("1.0.0");
+        let second = Release("2.0.0");
+        let item = scoping.item(DataCategory::Error);
+
+        let partition = item
+            .with_attributes(&first)
+            .attribute_partition("event.release")
+            .unwrap();
+
+        // The partition is stable and bounded.
+        assert!(partition < ATTRIBUTE_PARTITIONS);
+        assert_eq!(
+            item.with_attributes(&first)
+                .attribute_partition("event.release"),
+            Some(partition)
+        );
+        assert_ne!(
+            item.with_attributes(&second)
+                .attribute_partition("event.release"),
+            Some(partition)
+        );
+        assert_eq!(item.attribute_partition("event.release"), None);
+    }
 }
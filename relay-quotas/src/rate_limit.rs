@@ -2,6 +2,7 @@ use std::fmt;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 
+use relay_base_schema::metrics::MetricNamespace;
 use relay_base_schema::project::{ProjectId, ProjectKey};
 
 use crate::quota::{DataCategories, ItemScoping, Quota, QuotaScope, ReasonCode, Scoping};
@@ -134,6 +135,18 @@ impl RateLimitScope {
     }
 }
 
+/// The partition of an attribute-scoped quota that a rate limit applies to.
+///
+/// See [`Quota::attribute`] for more information.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(test, derive(serde::Serialize))]
+pub struct AttributePartition {
+    /// The name of the attribute, such as `event.release`.
+    pub name: String,
+    /// The partition of the attribute value, see [`ItemScoping::attribute_partition`].
+    pub partition: u64,
+}
+
 /// A bounded rate limit.
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(test, derive(serde::Serialize))]
@@ -150,22 +163,53 @@ pub struct RateLimit {
 
     /// A marker when this rate limit expires.
     pub retry_after: RetryAfter,
+
+    /// The attribute partition this rate limit is restricted to, if it was created from an
+    /// attribute-scoped quota.
+    pub attribute: Option<AttributePartition>,
+
+    /// The metric namespace this rate limit is restricted to, if any.
+    pub namespace: Option<MetricNamespace>,
 }
 
 impl RateLimit {
     /// Creates a new rate limit for the given `Quota`.
-    pub fn from_quota(quota: &Quota, scoping: &Scoping, retry_after: RetryAfter) -> Self {
+    pub fn from_quota(quota: &Quota, scoping: ItemScoping<'_>, retry_after: RetryAfter) -> Self {
+        let attribute = quota.attribute.as_ref().and_then(|name| {
+            Some(AttributePartition {
+                name: name.clone(),
+                partition: scoping.attribute_partition(name)?,
+            })
+        });
+
         Self {
             categories: quota.categories.clone(),
-            scope: RateLimitScope::for_quota(scoping, quota.scope),
+            scope: RateLimitScope::for_quota(&scoping, quota.scope),
             reason_code: quota.reason_code.clone(),
             retry_after,
+            attribute,
+            namespace: quota.namespace,
         }
     }
 
     /// Checks whether the rate limit applies to the given item.
     pub fn matches(&self, scoping: ItemScoping<'_>) -> bool {
-        self.matches_scope(scoping) && scoping.matches_categories(&self.categories)
+        self.matches_scope(scoping)
+            && scoping.matches_categories(&self.categories)
+            && scoping.matches_namespace(self.namespace)
+            && self.matches_attribute(scoping)
+    }
+
+    /// Returns `true` if the item's attribute falls into the partition of this rate limit.
+    ///
+    /// Rate limits without an attribute partition match all items.
+    fn matches_attribute(&self, scoping: ItemScoping<'_>) -> bool {
+        match self.attribute {
+            Some(ref attribute) => {
+                scoping.attribute_partition(&attribute.name) == Some(attribute.partition)
+            }
+            None => true,
+        }
     }
 
     /// Returns `true` if the rate limiting scope matches the given item.
@@ -203,10 +247,11 @@ impl RateLimits {
         // Categories are logically a set, but not implemented as such.
         limit.categories.sort();
 
-        let limit_opt = self
-            .limits
-            .iter_mut()
-            .find(|l| l.categories == limit.categories && l.scope == limit.scope);
+        let limit_opt = self.limits.iter_mut().find(|l| {
+            l.categories == limit.categories
+                && l.scope == limit.scope
+                && l.attribute == limit.attribute
+        });
 
         match limit_opt {
             None => self.limits.push(limit),
@@ -261,7 +306,7 @@ impl RateLimits {
         for quota in quotas {
             if quota.limit == Some(0) && quota.matches(scoping) {
                 let retry_after = RetryAfter::from_secs(REJECT_ALL_SECS);
-                applied_limits.add(RateLimit::from_quota(quota, &scoping, retry_after));
+                applied_limits.add(RateLimit::from_quota(quota, scoping, retry_after));
             }
         }
 
@@ -340,13 +385,59 @@ impl<'a> IntoIterator for &'a RateLimits {
     }
 }
 
+/// An error returned by rate limiters that track quotas in a shared store.
+#[cfg(any(feature = "redis", feature = "memcached"))]
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitingError {
+    /// Failed to communicate with Redis.
+    #[cfg(feature = "redis")]
+    #[error("failed to communicate with redis")]
+    Redis(#[source] relay_redis::RedisError),
+    /// Failed to communicate with memcached.
+    #[cfg(feature = "memcached")]
+    #[error("failed to communicate with memcached")]
+    Memcached(#[source] memcache::MemcacheError),
+}
+
+/// A service that checks quotas and records their consumption.
+///
+/// Rate limiters count the quantity of items against all matching quotas and return rate limits
+/// for quotas that have been exceeded.
+pub trait RateLimiter {
+    /// The error returned if quotas cannot be checked.
+    type Error;
+
+    /// Checks whether any of the quotas in effect for the given item have been exceeded and
+    /// records consumption of the quotas.
+    ///
+    /// If `over_accept_once` is `true`, the item is accepted if the quotas are not exhausted yet,
+    /// even if the quantity exceeds the remaining quota. A `quantity` of `0` checks whether the
+    /// quotas have been exhausted without consuming them.
+    fn is_rate_limited(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> Result<RateLimits, Self::Error>;
+}
+
 #[cfg(test)]
 mod tests {
+    use relay_protocol::{Getter, Val};
     use smallvec::smallvec;
 
     use super::*;
     use crate::quota::DataCategory;
 
+    struct Release(&'static str);
+
+    impl Getter for Release {
+        fn get_value(&self, path: &str) -> Option<Val<'_>> {
+            (path == "event.release").then_some(self.0.into())
+        }
+    }
+
     #[test]
     fn test_parse_retry_after() {
         // positive float always rounds up to the next integer
@@ -399,6 +490,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         };
 
         assert!(rate_limit.matches(ItemScoping {
@@ -408,7 +501,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
 
         assert!(!rate_limit.matches(ItemScoping {
@@ -418,7 +513,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
@@ -429,6 +526,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         };
 
         assert!(rate_limit.matches(ItemScoping {
@@ -438,7 +537,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
 
         assert!(!rate_limit.matches(ItemScoping {
@@ -448,7 +549,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
@@ -459,6 +562,8 @@ mod tests {
             scope: RateLimitScope::Project(ProjectId::new(21)),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         };
 
         assert!(rate_limit.matches(ItemScoping {
@@ -468,7 +573,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
 
         assert!(!rate_limit.matches(ItemScoping {
@@ -478,7 +585,9 @@ mod tests {
                 project_id: ProjectId::new(0),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
@@ -491,6 +600,8 @@ mod tests {
             ),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         };
 
         assert!(rate_limit.matches(ItemScoping {
@@ -500,7 +611,9 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
 
         assert!(!rate_limit.matches(ItemScoping {
@@ -510,10 +623,46 @@ mod tests {
                 project_id: ProjectId::new(21),
                 project_key: ProjectKey::parse("deadbeefdeadbeefdeadbeefdeadbeef").unwrap(),
                 key_id: None,
-            }
+            },
+            attributes: None,
+            namespace: None,
         }));
     }
 
+    #[test]
+    fn test_rate_limit_matches_attribute() {
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: None,
+        };
+
+        let quota = Quota {
+            id: Some("r".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(10),
+            window: Some(60),
+            reason_code: Some(ReasonCode::new("release_quota")),
+            attribute: Some("event.release".to_owned()),
+            namespace: None,
+        };
+
+        let limited = Release("1.0.0");
+        let item_scoping = scoping.item(DataCategory::Error).with_attributes(&limited);
+        let rate_limit = RateLimit::from_quota(&quota, item_scoping, RetryAfter::from_secs(1));
+
+        assert!(rate_limit.attribute.is_some());
+        assert!(rate_limit.matches(item_scoping));
+
+        // Other values of the attribute, and items without attributes, are not affected.
+        let other = Release("2.0.0");
+        assert!(!rate_limit.matches(scoping.item(DataCategory::Error).with_attributes(&other)));
+        assert!(!rate_limit.matches(scoping.item(DataCategory::Error)));
+    }
+
     #[test]
     fn test_rate_limits_add_replacement() {
         let mut rate_limits = RateLimits::new();
@@ -523,6 +672,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: Some(ReasonCode::new("first")),
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         // longer rate limit shadows shorter one
@@ -531,6 +682,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: Some(ReasonCode::new("second")),
             retry_after: RetryAfter::from_secs(10),
+            attribute: None,
+            namespace: None,
         });
 
         insta::assert_ron_snapshot!(rate_limits, @r#"
@@ -544,6 +697,8 @@ mod tests {
               scope: Organization(42),
               reason_code: Some(ReasonCode("second")),
               retry_after: RetryAfter(10),
+              attribute: None,
+              namespace: None,
             ),
           ],
         )
@@ -559,6 +714,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: Some(ReasonCode::new("first")),
             retry_after: RetryAfter::from_secs(10),
+            attribute: None,
+            namespace: None,
         });
 
         // shorter rate limit is shadowed by existing one
@@ -567,6 +724,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: Some(ReasonCode::new("second")),
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         insta::assert_ron_snapshot!(rate_limits, @r#"
@@ -580,6 +739,8 @@ mod tests {
               scope: Organization(42),
               reason_code: Some(ReasonCode("first")),
               retry_after: RetryAfter(10),
+              attribute: None,
+              namespace: None,
             ),
           ],
         )
@@ -595,6 +756,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         // Same scope but different categories
@@ -603,6 +766,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         // Same categories but different scope
@@ -611,6 +776,8 @@ mod tests {
             scope: RateLimitScope::Project(ProjectId::new(21)),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         insta::assert_ron_snapshot!(rate_limits, @r#"
@@ -623,6 +790,8 @@ mod tests {
               scope: Organization(42),
               reason_code: None,
               retry_after: RetryAfter(1),
+              attribute: None,
+              namespace: None,
             ),
             RateLimit(
               categories: [
@@ -631,6 +800,8 @@ mod tests {
               scope: Organization(42),
               reason_code: None,
               retry_after: RetryAfter(1),
+              attribute: None,
+              namespace: None,
             ),
             RateLimit(
               categories: [
@@ -639,6 +810,8 @@ mod tests {
               scope: Project(ProjectId(21)),
               reason_code: None,
               retry_after: RetryAfter(1),
+              attribute: None,
+              namespace: None,
             ),
           ],
         )
@@ -654,6 +827,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: Some(ReasonCode::new("first")),
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         // Distinct scope to prevent deduplication
@@ -662,6 +837,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: Some(ReasonCode::new("second")),
             retry_after: RetryAfter::from_secs(10),
+            attribute: None,
+            namespace: None,
         });
 
         let rate_limit = rate_limits.longest().unwrap();
@@ -673,6 +850,8 @@ mod tests {
           scope: Organization(42),
           reason_code: Some(ReasonCode("second")),
           retry_after: RetryAfter(10),
+          attribute: None,
+          namespace: None,
         )
         "#);
     }
@@ -687,6 +866,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         // Inactive error limit with distinct scope
@@ -695,6 +876,8 @@ mod tests {
             scope: RateLimitScope::Project(ProjectId::new(21)),
             reason_code: None,
             retry_after: RetryAfter::from_secs(0),
+            attribute: None,
+            namespace: None,
         });
 
         // Sanity check before running `clean_expired`
@@ -713,6 +896,8 @@ mod tests {
               scope: Organization(42),
               reason_code: None,
               retry_after: RetryAfter(1),
+              attribute: None,
+              namespace: None,
             ),
           ],
         )
@@ -729,6 +914,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         // Active transaction limit
@@ -737,6 +924,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         let applied_limits = rate_limits.check(ItemScoping {
@@ -747,6 +936,8 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
             },
+            attributes: None,
+            namespace: None,
         });
 
         // Check that the error limit is applied
@@ -760,6 +951,8 @@ mod tests {
               scope: Organization(42),
               reason_code: None,
               retry_after: RetryAfter(1),
+              attribute: None,
+              namespace: None,
             ),
           ],
         )
@@ -776,6 +969,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         // Active transaction limit
@@ -784,6 +979,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         let item_scoping = ItemScoping {
@@ -794,6 +991,8 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: None,
             },
+            attributes: None,
+            namespace: None,
         };
 
         let quotas = &[Quota {
@@ -804,6 +1003,8 @@ mod tests {
             limit: Some(0),
             window: None,
             reason_code: Some(ReasonCode::new("zero")),
+            attribute: None,
+            namespace: None,
         }];
 
         let applied_limits = rate_limits.check_with_quotas(quotas, item_scoping);
@@ -818,6 +1019,8 @@ mod tests {
               scope: Organization(42),
               reason_code: Some(ReasonCode("zero")),
               retry_after: RetryAfter(60),
+              attribute: None,
+              namespace: None,
             ),
           ],
         )
@@ -834,6 +1037,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: Some(ReasonCode::new("first")),
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         rate_limits1.add(RateLimit {
@@ -841,6 +1046,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(1),
+            attribute: None,
+            namespace: None,
         });
 
         rate_limits2.add(RateLimit {
@@ -848,6 +1055,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: Some(ReasonCode::new("second")),
             retry_after: RetryAfter::from_secs(10),
+            attribute: None,
+            namespace: None,
         });
 
         rate_limits1.merge(rate_limits2);
@@ -862,6 +1071,8 @@ mod tests {
               scope: Organization(42),
               reason_code: Some(ReasonCode("second")),
               retry_after: RetryAfter(10),
+              attribute: None,
+              namespace: None,
             ),
             RateLimit(
               categories: [
@@ -870,6 +1081,8 @@ mod tests {
               scope: Organization(42),
               reason_code: None,
               retry_after: RetryAfter(1),
+              attribute: None,
+              namespace: None,
             ),
           ],
         )
@@ -1,127 +1,44 @@
-use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
+use moka::sync::Cache;
 use relay_common::time::UnixTimestamp;
 use relay_log::protocol::value;
-use relay_redis::redis::Script;
+use relay_redis::redis::{Script, ScriptInvocation};
 use relay_redis::{RedisError, RedisPool};
-use thiserror::Error;
 
-use crate::quota::{ItemScoping, Quota, QuotaScope};
-use crate::rate_limit::{RateLimit, RateLimits, RetryAfter};
+use crate::quota::{ItemScoping, Quota};
+use crate::rate_limit::{RateLimit, RateLimiter, RateLimitingError, RateLimits, RetryAfter};
+use crate::window::{get_refunded_quota_key, WindowedQuota, GRACE};
 use crate::REJECT_ALL_SECS;
 
-/// The `grace` period allows accomodating for clock drift in TTL
-/// calculation since the clock on the Redis instance used to store quota
-/// metrics may not be in sync with the computer running this code.
-const GRACE: u64 = 60;
-
-/// An error returned by `RedisRateLimiter`.
-#[derive(Debug, Error)]
-pub enum RateLimitingError {
-    /// Failed to communicate with Redis.
-    #[error("failed to communicate with redis")]
-    Redis(#[source] RedisError),
-}
-
 fn load_lua_script() -> Script {
     Script::new(include_str!("is_rate_limited.lua"))
 }
 
-fn get_refunded_quota_key(counter_key: &str) -> String {
-    format!("r:{counter_key}")
-}
-
-/// A transparent wrapper around an Option that only displays `Some`.
-struct OptionalDisplay<T>(Option<T>);
-
-impl<T> fmt::Display for OptionalDisplay<T>
-where
-    T: fmt::Display,
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.0 {
-            Some(ref value) => write!(f, "{value}"),
-            None => Ok(()),
-        }
-    }
-}
+/// Exhausted quotas by the key of their counter, mapped to the limit that was exhausted.
+type QuotaCache = Cache<String, i64>;
 
-/// Reference to information required for tracking quotas in Redis.
-#[derive(Debug)]
-struct RedisQuota<'a> {
-    /// The original quota.
-    quota: &'a Quota,
-    /// Scopes of the item being tracked.
-    scoping: ItemScoping<'a>,
-    /// The Redis key prefix mapped from the quota id.
-    prefix: &'a str,
-    /// The redis window in seconds mapped from the quota.
-    window: u64,
-    /// The ingestion timestamp determining the rate limiting bucket.
+/// A prepared check of quotas against Redis, see [`RedisRateLimiter::is_rate_limited`].
+struct QuotaCheck<'a> {
     timestamp: UnixTimestamp,
+    item_scoping: ItemScoping<'a>,
+    invocation: ScriptInvocation<'a>,
+    tracked_quotas: Vec<WindowedQuota<'a>>,
+    rate_limits: RateLimits,
+    /// Whether a rejection means that the quota is exhausted.
+    ///
+    /// Otherwise, the quota may only be too small for the requested quantity.
+    rejects_exhausted: bool,
 }
 
-impl<'a> RedisQuota<'a> {
-    fn new(quota: &'a Quota, scoping: ItemScoping<'a>, timestamp: UnixTimestamp) -> Option<Self> {
-        // These fields indicate that we *can* track this quota.
-        let prefix = quota.id.as_deref()?;
-        let window = quota.window?;
-
-        Some(Self {
-            quota,
-            scoping,
-            prefix,
-            window,
-            timestamp,
-        })
-    }
-
-    /// Returns the limit value for Redis (`-1` for unlimited, otherwise the limit value).
-    fn limit(&self) -> i64 {
-        self.limit
-            // If it does not fit into i64, treat as unlimited:
-            .and_then(|limit| limit.try_into().ok())
-            .unwrap_or(-1)
-    }
-
-    fn shift(&self) -> u64 {
-        self.scoping.organization_id % self.window
-    }
-
-    fn slot(&self) -> u64 {
-        (self.timestamp.as_secs() - self.shift()) / self.window
-    }
-
-    fn expiry(&self) -> UnixTimestamp {
-        let next_slot = self.slot() + 1;
-        let next_start = next_slot * self.window + self.shift();
-        UnixTimestamp::from_secs(next_start)
-    }
-
-    fn key(&self) -> String {
-        // The subscope id is only formatted into the key if the quota is not organization-scoped.
-        // The organization id is always included.
-        let subscope = match self.quota.scope {
-            QuotaScope::Organization => None,
-            scope => self.scoping.scope_id(scope),
-        };
-
-        format!(
-            "quota:{id}{{{org}}}{subscope}:{slot}",
-            id = self.prefix,
-            org = self.scoping.organization_id,
-            subscope = OptionalDisplay(subscope),
-            slot = self.slot(),
-        )
-    }
-}
-
-impl std::ops::Deref for RedisQuota<'_> {
-    type Target = Quota;
-
-    fn deref(&self) -> &Self::Target {
-        self.quota
+impl QuotaCheck<'_> {
+    /// Returns `true` if the script does not need to be invoked.
+    ///
+    /// Either there are no quotas to run against Redis, or there is already a rate limit from a
+    /// zero-sized quota.
+    fn is_done(&self) -> bool {
+        self.tracked_quotas.is_empty() || self.rate_limits.is_limited()
     }
 }
 
@@ -140,6 +57,8 @@ pub struct RedisRateLimiter {
     pool: RedisPool,
     script: Arc<Script>,
     max_limit: Option<u64>,
+    cache: Option<QuotaCache>,
+    key_prefix: String,
 }
 
 impl RedisRateLimiter {
@@ -149,9 +68,35 @@ impl RedisRateLimiter {
             pool,
             script: Arc::new(load_lua_script()),
             max_limit: None,
+            cache: None,
+            key_prefix: String::new(),
         }
     }
 
+    /// Sets a prefix for the keys of all quota counters.
+    ///
+    /// This allows multiple environments to share one Redis deployment. The prefix must not
+    /// contain braces, since they denote the hash tag of keys in Redis Cluster.
+    pub fn key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// Caches exhausted quotas in memory for at most `ttl`.
+    ///
+    /// An exhausted quota remains exhausted until its window ends, so further items are rejected
+    /// without a round-trip to Redis. A cached quota is invalidated when its limit changes, and
+    /// expires after `ttl` at the latest to pick up refunds.
+    pub fn cache(mut self, ttl: Duration, max_entries: u64) -> Self {
+        let cache = Cache::builder()
+            .time_to_live(ttl)
+            .max_capacity(max_entries)
+            .build();
+
+        self.cache = Some(cache);
+        self
+    }
+
     /// Sets the maximum rate limit in seconds.
     ///
     /// By default, this rate limiter will return rate limits based on the quotas' `window` fields.
@@ -185,6 +130,60 @@ impl RedisRateLimiter {
         quantity: usize,
         over_accept_once: bool,
     ) -> Result<RateLimits, RateLimitingError> {
+        let checked = self.prepare(quotas, item_scoping, quantity, over_accept_once);
+        if checked.is_done() {
+            return Ok(checked.rate_limits);
+        }
+
+        let mut client = self.pool.client().map_err(RateLimitingError::Redis)?;
+        let rejections: Vec<bool> = checked
+            .invocation
+            .invoke(&mut client.connection().map_err(RateLimitingError::Redis)?)
+            .map_err(RedisError::Redis)
+            .map_err(RateLimitingError::Redis)?;
+
+        Ok(self.finish(checked, rejections))
+    }
+
+    /// Asynchronous version of [`is_rate_limited`](Self::is_rate_limited).
+    ///
+    /// Uses the multiplexed connection of the pool, so concurrent checks are pipelined and do not
+    /// block a thread while waiting for Redis.
+    pub async fn is_rate_limited_async(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> Result<RateLimits, RateLimitingError> {
+        let checked = self.prepare(quotas, item_scoping, quantity, over_accept_once);
+        if checked.is_done() {
+            return Ok(checked.rate_limits);
+        }
+
+        let mut connection = self
+            .pool
+            .async_connection()
+            .await
+            .map_err(RateLimitingError::Redis)?;
+        let rejections: Vec<bool> = checked
+            .invocation
+            .invoke_async(&mut connection)
+            .await
+            .map_err(RedisError::Redis)
+            .map_err(RateLimitingError::Redis)?;
+
+        Ok(self.finish(checked, rejections))
+    }
+
+    /// Prepares the script invocation for all quotas that are tracked in Redis.
+    fn prepare<'a>(
+        &'a self,
+        quotas: &'a [Quota],
+        item_scoping: ItemScoping<'a>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> QuotaCheck<'a> {
         let timestamp = UnixTimestamp::now();
         let mut invocation = self.script.prepare_invoke();
         let mut tracked_quotas = Vec::new();
@@ -198,14 +197,20 @@ impl RedisRateLimiter {
                 // increment any keys, as one quota has reached capacity (this is how regular quotas
                 // behave as well).
                 let retry_after = self.retry_after(REJECT_ALL_SECS);
-                rate_limits.add(RateLimit::from_quota(quota, &item_scoping, retry_after));
-            } else if let Some(quota) = RedisQuota::new(quota, item_scoping, timestamp) {
+                rate_limits.add(RateLimit::from_quota(quota, item_scoping, retry_after));
+            } else if let Some(quota) = WindowedQuota::new(quota, item_scoping, timestamp) {
                 // Remaining quotas are expected to be trackable in Redis.
                 let key = quota.key();
+                if self.is_exhausted(&key, quota.limit()) {
+                    let retry_after = self.retry_after((quota.expiry() - timestamp).as_secs());
+                    rate_limits.add(RateLimit::from_quota(&quota, item_scoping, retry_after));
+                    continue;
+                }
+
                 let refund_key = get_refunded_quota_key(&key);
 
-                invocation.key(key);
-                invocation.key(refund_key);
+                invocation.key(format!("{}{key}", self.key_prefix));
+                invocation.key(format!("{}{refund_key}", self.key_prefix));
 
                 invocation.arg(quota.limit());
                 invocation.arg(quota.expiry().as_secs() + GRACE);
@@ -223,26 +228,56 @@ impl RedisRateLimiter {
             }
         }
 
-        // Either there are no quotas to run against Redis, or we already have a rate limit from a
-        // zero-sized quota. In either cases, skip invoking the script and return early.
-        if tracked_quotas.is_empty() || rate_limits.is_limited() {
-            return Ok(rate_limits);
+        QuotaCheck {
+            timestamp,
+            item_scoping,
+            invocation,
+            tracked_quotas,
+            rate_limits,
+            rejects_exhausted: quantity == 0 || over_accept_once,
         }
+    }
 
-        let mut client = self.pool.client().map_err(RateLimitingError::Redis)?;
-        let rejections: Vec<bool> = invocation
-            .invoke(&mut client.connection().map_err(RateLimitingError::Redis)?)
-            .map_err(RedisError::Redis)
-            .map_err(RateLimitingError::Redis)?;
+    /// Returns `true` if the quota has recently been exhausted with the same limit.
+    fn is_exhausted(&self, key: &str, limit: i64) -> bool {
+        let Some(ref cache) = self.cache else {
+            return false;
+        };
+
+        match cache.get(key) {
+            Some(cached) if cached == limit => true,
+            Some(_) => {
+                // The limit has changed, so the quota may have capacity again.
+                cache.invalidate(key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Adds rate limits for all quotas rejected by the script.
+    fn finish(&self, checked: QuotaCheck<'_>, rejections: Vec<bool>) -> RateLimits {
+        let QuotaCheck {
+            timestamp,
+            item_scoping,
+            tracked_quotas,
+            mut rate_limits,
+            rejects_exhausted,
+            ..
+        } = checked;
 
         for (quota, is_rejected) in tracked_quotas.iter().zip(rejections) {
             if is_rejected {
+                if let Some(cache) = self.cache.as_ref().filter(|_| rejects_exhausted) {
+                    cache.insert(quota.key(), quota.limit());
+                }
+
                 let retry_after = self.retry_after((quota.expiry() - timestamp).as_secs());
-                rate_limits.add(RateLimit::from_quota(quota, &item_scoping, retry_after));
+                rate_limits.add(RateLimit::from_quota(quota, item_scoping, retry_after));
             }
         }
 
-        Ok(rate_limits)
+        rate_limits
     }
 
     /// Creates a rate limit bounded by `max_limit`.
@@ -255,6 +290,20 @@ impl RedisRateLimiter {
     }
 }
 
+impl RateLimiter for RedisRateLimiter {
+    type Error = RateLimitingError;
+
+    fn is_rate_limited(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> Result<RateLimits, Self::Error> {
+        RedisRateLimiter::is_rate_limited(self, quotas, item_scoping, quantity, over_accept_once)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
@@ -276,6 +325,8 @@ mod tests {
             pool: RedisPool::single(&url, RedisConfigOptions::default()).unwrap(),
             script: Arc::new(load_lua_script()),
             max_limit: None,
+            cache: None,
+            key_prefix: String::new(),
         }
     }
 
@@ -290,6 +341,8 @@ mod tests {
                 limit: Some(0),
                 window: None,
                 reason_code: Some(ReasonCode::new("get_lost")),
+                attribute: None,
+                namespace: None,
             },
             Quota {
                 id: Some("42".to_owned()),
@@ -299,6 +352,8 @@ mod tests {
                 limit: None,
                 window: Some(42),
                 reason_code: Some(ReasonCode::new("unlimited")),
+                attribute: None,
+                namespace: None,
             },
         ];
 
@@ -310,6 +365,8 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let rate_limits: Vec<RateLimit> = build_rate_limiter()
@@ -325,6 +382,8 @@ mod tests {
                 scope: RateLimitScope::Organization(42),
                 reason_code: Some(ReasonCode::new("get_lost")),
                 retry_after: rate_limits[0].retry_after,
+                attribute: None,
+                namespace: None,
             }]
         );
     }
@@ -339,6 +398,8 @@ mod tests {
             limit: Some(5),
             window: Some(60),
             reason_code: Some(ReasonCode::new("get_lost")),
+            attribute: None,
+            namespace: None,
         }];
 
         let scoping = ItemScoping {
@@ -349,6 +410,8 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let rate_limiter = build_rate_limiter();
@@ -368,6 +431,8 @@ mod tests {
                         scope: RateLimitScope::Organization(42),
                         reason_code: Some(ReasonCode::new("get_lost")),
                         retry_after: rate_limits[0].retry_after,
+                        attribute: None,
+                        namespace: None,
                     }]
                 );
             } else {
@@ -376,6 +441,143 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_simple_quota_async() {
+        let quotas = &[Quota {
+            id: Some(format!("test_simple_quota_async_{:?}", SystemTime::now())),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(5),
+            window: Some(60),
+            reason_code: Some(ReasonCode::new("get_lost")),
+            attribute: None,
+            namespace: None,
+        }];
+
+        let scoping = ItemScoping {
+            category: DataCategory::Error,
+            scoping: &Scoping {
+                organization_id: 42,
+                project_id: ProjectId::new(43),
+                project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+                key_id: Some(44),
+            },
+            attributes: None,
+            namespace: None,
+        };
+
+        let rate_limiter = build_rate_limiter();
+
+        for i in 0..10 {
+            let rate_limits = rate_limiter
+                .is_rate_limited_async(quotas, scoping, 1, false)
+                .await
+                .expect("rate limiting failed");
+
+            assert_eq!(rate_limits.is_limited(), i >= 5);
+        }
+    }
+
+    #[test]
+    fn test_cached_quota() {
+        let mut quota = Quota {
+            id: Some(format!("test_cached_quota_{:?}", SystemTime::now())),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(5),
+            window: Some(60),
+            reason_code: Some(ReasonCode::new("get_lost")),
+            attribute: None,
+            namespace: None,
+        };
+
+        let scoping = ItemScoping {
+            category: DataCategory::Error,
+            scoping: &Scoping {
+                organization_id: 42,
+                project_id: ProjectId::new(43),
+                project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+                key_id: Some(44),
+            },
+            attributes: None,
+            namespace: None,
+        };
+
+        let rate_limiter = build_rate_limiter().cache(Duration::from_secs(60), 100);
+        let cache = rate_limiter.cache.clone().unwrap();
+
+        for i in 0..6 {
+            let rate_limits = rate_limiter
+                .is_rate_limited(&[quota.clone()], scoping, 1, true)
+                .unwrap();
+            assert_eq!(rate_limits.is_limited(), i >= 5);
+        }
+
+        // The exhausted quota is rejected from the cache.
+        let key = WindowedQuota::new(&quota, scoping, UnixTimestamp::now())
+            .unwrap()
+            .key();
+        assert_eq!(cache.get(&key), Some(5));
+        assert!(rate_limiter
+            .is_rate_limited(&[quota.clone()], scoping, 1, true)
+            .unwrap()
+            .is_limited());
+
+        // Raising the limit invalidates the cached quota.
+        quota.limit = Some(10);
+        assert!(!rate_limiter
+            .is_rate_limited(&[quota], scoping, 1, true)
+            .unwrap()
+            .is_limited());
+        assert_eq!(cache.get(&key), None);
+    }
+
+    #[test]
+    fn test_key_prefix() {
+        let quota = Quota {
+            id: Some(format!("test_key_prefix_{:?}", SystemTime::now())),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Organization,
+            scope_id: None,
+            limit: Some(5),
+            window: Some(60),
+            reason_code: None,
+            attribute: None,
+            namespace: None,
+        };
+
+        let scoping = ItemScoping {
+            category: DataCategory::Error,
+            scoping: &Scoping {
+                organization_id: 42,
+                project_id: ProjectId::new(43),
+                project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+                key_id: Some(44),
+            },
+            attributes: None,
+            namespace: None,
+        };
+
+        let rate_limiter = build_rate_limiter().key_prefix("staging:");
+        let rate_limits = rate_limiter
+            .is_rate_limited(&[quota.clone()], scoping, 1, false)
+            .unwrap();
+        assert!(!rate_limits.is_limited());
+
+        let key = WindowedQuota::new(&quota, scoping, UnixTimestamp::now())
+            .unwrap()
+            .key();
+
+        let mut client = rate_limiter.pool.client().unwrap();
+        let mut conn = client.connection().unwrap();
+        let prefixed: Option<u64> = conn.get(format!("staging:{key}")).unwrap();
+        let unprefixed: Option<u64> = conn.get(&key).unwrap();
+        assert_eq!(prefixed, Some(1));
+        assert_eq!(unprefixed, None);
+    }
+
     #[test]
     fn test_quantity_0() {
         let quotas = &[Quota {
@@ -386,6 +588,8 @@ mod tests {
             limit: Some(1),
             window: Some(60),
             reason_code: Some(ReasonCode::new("get_lost")),
+            attribute: None,
+            namespace: None,
         }];
 
         let scoping = ItemScoping {
@@ -396,6 +600,8 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let rate_limiter = build_rate_limiter();
@@ -435,6 +641,8 @@ mod tests {
             limit: Some(2),
             window: Some(60),
             reason_code: Some(ReasonCode::new("get_lost")),
+            attribute: None,
+            namespace: None,
         }];
 
         let scoping = ItemScoping {
@@ -445,6 +653,8 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let rate_limiter = build_rate_limiter();
@@ -488,6 +698,8 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let rate_limits: Vec<RateLimit> = build_rate_limiter()
@@ -510,6 +722,8 @@ mod tests {
                 limit: None,
                 window: Some(1),
                 reason_code: Some(ReasonCode::new("project_quota0")),
+                attribute: None,
+                namespace: None,
             },
             Quota {
                 id: Some("q1".to_string()),
@@ -519,6 +733,8 @@ mod tests {
                 limit: Some(1),
                 window: Some(1),
                 reason_code: Some(ReasonCode::new("project_quota1")),
+                attribute: None,
+                namespace: None,
             },
         ];
 
@@ -530,6 +746,8 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let rate_limiter = build_rate_limiter();
@@ -551,6 +769,8 @@ mod tests {
                         scope: RateLimitScope::Organization(42),
                         reason_code: Some(ReasonCode::new("project_quota1")),
                         retry_after: rate_limits[0].retry_after,
+                        attribute: None,
+                        namespace: None,
                     }]
                 );
             }
@@ -567,6 +787,8 @@ mod tests {
             limit: Some(500),
             window: Some(60),
             reason_code: Some(ReasonCode::new("get_lost")),
+            attribute: None,
+            namespace: None,
         }];
 
         let scoping = ItemScoping {
@@ -577,6 +799,8 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(44),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let rate_limiter = build_rate_limiter();
@@ -596,6 +820,8 @@ mod tests {
                         scope: RateLimitScope::Organization(42),
                         reason_code: Some(ReasonCode::new("get_lost")),
                         retry_after: rate_limits[0].retry_after,
+                        attribute: None,
+                        namespace: None,
                     }]
                 );
             } else {
@@ -614,6 +840,8 @@ mod tests {
             window: Some(2),
             limit: Some(0),
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         let scoping = ItemScoping {
@@ -624,10 +852,12 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(4711),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let timestamp = UnixTimestamp::from_secs(123_123_123);
-        let redis_quota = RedisQuota::new(&quota, scoping, timestamp).unwrap();
+        let redis_quota = WindowedQuota::new(&quota, scoping, timestamp).unwrap();
         assert_eq!(redis_quota.key(), "quota:foo{69420}42:61561561");
     }
 
@@ -641,6 +871,8 @@ mod tests {
             window: Some(10),
             limit: Some(0),
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         let scoping = ItemScoping {
@@ -651,13 +883,90 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(4711),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let timestamp = UnixTimestamp::from_secs(234_531);
-        let redis_quota = RedisQuota::new(&quota, scoping, timestamp).unwrap();
+        let redis_quota = WindowedQuota::new(&quota, scoping, timestamp).unwrap();
         assert_eq!(redis_quota.key(), "quota:foo{69420}:23453");
     }
 
+    #[test]
+    fn test_get_redis_key_attribute() {
+        struct Release;
+
+        impl relay_protocol::Getter for Release {
+            fn get_value(&self, path: &str) -> Option<relay_protocol::Val<'_>> {
+                (path == "event.release").then_some("1.0.0".into())
+            }
+        }
+
+        let quota = Quota {
+            id: Some("foo".to_owned()),
+            categories: DataCategories::new(),
+            scope: QuotaScope::Project,
+            scope_id: None,
+            window: Some(2),
+            limit: Some(10),
+            reason_code: None,
+            attribute: Some("event.release".to_owned()),
+            namespace: None,
+        };
+
+        let scoping = Scoping {
+            organization_id: 69420,
+            project_id: ProjectId::new(42),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(4711),
+        };
+
+        let item_scoping = scoping.item(DataCategory::Error).with_attributes(&Release);
+        let timestamp = UnixTimestamp::from_secs(123_123_123);
+        let redis_quota = WindowedQuota::new(&quota, item_scoping, timestamp).unwrap();
+        assert_eq!(redis_quota.key(), "quota:foo{69420}42:p716:61561561");
+    }
+
+    #[test]
+    fn test_get_redis_key_cluster_slot() {
+        // All keys of a script invocation must map to the same slot in Redis Cluster.
+        let quota = |scope, id: &str| Quota {
+            id: Some(id.to_owned()),
+            categories: DataCategories::new(),
+            scope,
+            scope_id: None,
+            window: Some(10),
+            limit: Some(10),
+            reason_code: None,
+            attribute: None,
+            namespace: None,
+        };
+
+        let quotas = [
+            quota(QuotaScope::Organization, "org"),
+            quota(QuotaScope::Project, "project"),
+            quota(QuotaScope::Key, "key"),
+        ];
+
+        let scoping = Scoping {
+            organization_id: 69420,
+            project_id: ProjectId::new(42),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(4711),
+        };
+
+        let timestamp = UnixTimestamp::from_secs(123_123_123);
+        let slot = relay_redis::key_slot(b"69420");
+        for quota in &quotas {
+            let redis_quota =
+                WindowedQuota::new(quota, scoping.item(DataCategory::Error), timestamp).unwrap();
+            let key = redis_quota.key();
+            assert_eq!(relay_redis::key_slot(key.as_bytes()), slot);
+            let refund_key = get_refunded_quota_key(&key);
+            assert_eq!(relay_redis::key_slot(refund_key.as_bytes()), slot);
+        }
+    }
+
     #[test]
     fn test_large_redis_limit_large() {
         let quota = Quota {
@@ -668,6 +977,8 @@ mod tests {
             window: Some(10),
             limit: Some(9223372036854775808), // i64::MAX + 1
             reason_code: None,
+            attribute: None,
+            namespace: None,
         };
 
         let scoping = ItemScoping {
@@ -678,10 +989,12 @@ mod tests {
                 project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
                 key_id: Some(4711),
             },
+            attributes: None,
+            namespace: None,
         };
 
         let timestamp = UnixTimestamp::from_secs(234_531);
-        let redis_quota = RedisQuota::new(&quota, scoping, timestamp).unwrap();
+        let redis_quota = WindowedQuota::new(&quota, scoping, timestamp).unwrap();
         assert_eq!(redis_quota.limit(), -1);
     }
 
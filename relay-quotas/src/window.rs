@@ -0,0 +1,124 @@
+use std::fmt;
+
+use relay_common::time::UnixTimestamp;
+
+use crate::quota::{ItemScoping, Quota, QuotaScope};
+
+/// The `grace` period allows accomodating for clock drift in TTL
+/// calculation since the clock on the instance used to store quota
+/// metrics may not be in sync with the computer running this code.
+pub(crate) const GRACE: u64 = 60;
+
+pub(crate) fn get_refunded_quota_key(counter_key: &str) -> String {
+    format!("r:{counter_key}")
+}
+
+/// A transparent wrapper around an Option that only displays `Some`.
+struct OptionalDisplay<T>(Option<T>);
+
+impl<T> fmt::Display for OptionalDisplay<T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ref value) => write!(f, "{value}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reference to information required for tracking quotas in a shared counter store.
+///
+/// Quotas are counted in fixed windows. Every window has its own counter key, which expires when
+/// the window ends.
+#[derive(Debug)]
+pub(crate) struct WindowedQuota<'a> {
+    /// The original quota.
+    quota: &'a Quota,
+    /// Scopes of the item being tracked.
+    scoping: ItemScoping<'a>,
+    /// The key prefix mapped from the quota id.
+    prefix: &'a str,
+    /// The window in seconds mapped from the quota.
+    window: u64,
+    /// The ingestion timestamp determining the rate limiting bucket.
+    timestamp: UnixTimestamp,
+}
+
+impl<'a> WindowedQuota<'a> {
+    pub fn new(
+        quota: &'a Quota,
+        scoping: ItemScoping<'a>,
+        timestamp: UnixTimestamp,
+    ) -> Option<Self> {
+        // These fields indicate that we *can* track this quota.
+        let prefix = quota.id.as_deref()?;
+        let window = quota.window?;
+
+        Some(Self {
+            quota,
+            scoping,
+            prefix,
+            window,
+            timestamp,
+        })
+    }
+
+    /// Returns the limit value for the store (`-1` for unlimited, otherwise the limit value).
+    pub fn limit(&self) -> i64 {
+        self.limit
+            // If it does not fit into i64, treat as unlimited:
+            .and_then(|limit| limit.try_into().ok())
+            .unwrap_or(-1)
+    }
+
+    fn shift(&self) -> u64 {
+        self.scoping.organization_id % self.window
+    }
+
+    fn slot(&self) -> u64 {
+        (self.timestamp.as_secs() - self.shift()) / self.window
+    }
+
+    pub fn expiry(&self) -> UnixTimestamp {
+        let next_slot = self.slot() + 1;
+        let next_start = next_slot * self.window + self.shift();
+        UnixTimestamp::from_secs(next_start)
+    }
+
+    pub fn key(&self) -> String {
+        // The subscope id is only formatted into the key if the quota is not organization-scoped.
+        // The organization id is always included as the hash tag of the key, so that all keys of
+        // a script invocation map to the same slot in Redis Cluster.
+        let subscope = match self.quota.scope {
+            QuotaScope::Organization => None,
+            scope => self.scoping.scope_id(scope),
+        };
+
+        // Attribute-scoped quotas are counted separately for every partition of the attribute.
+        let partition = self
+            .quota
+            .attribute
+            .as_deref()
+            .and_then(|name| self.scoping.attribute_partition(name))
+            .map(|partition| format!(":p{partition}"));
+
+        format!(
+            "quota:{id}{{{org}}}{subscope}{partition}:{slot}",
+            id = self.prefix,
+            org = self.scoping.organization_id,
+            subscope = OptionalDisplay(subscope),
+            partition = OptionalDisplay(partition),
+            slot = self.slot(),
+        )
+    }
+}
+
+impl std::ops::Deref for WindowedQuota<'_> {
+    type Target = Quota;
+
+    fn deref(&self) -> &Self::Target {
+        self.quota
+    }
+}
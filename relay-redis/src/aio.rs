@@ -0,0 +1,205 @@
+//! Asynchronous, multiplexed connections to Redis.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::aio::{ConnectionLike, ConnectionManager, MultiplexedConnection};
+use redis::cluster::ClusterClient;
+use redis::cluster_async::ClusterConnection;
+use redis::{Cmd, ErrorKind, Pipeline, RedisError, RedisFuture, RedisResult, Value};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::sentinel::SentinelManager;
+
+/// Fails a command with a timeout error if it does not complete in time.
+fn with_timeout<'a, T: Send + 'a>(
+    timeout: Duration,
+    future: RedisFuture<'a, T>,
+) -> RedisFuture<'a, T> {
+    Box::pin(async move {
+        tokio::time::timeout(timeout, future)
+            .await
+            .unwrap_or_else(|_| Err((ErrorKind::IoError, "redis command timed out").into()))
+    })
+}
+
+/// Fails the initial connection with a timeout error if it does not complete in time.
+async fn connect_with_timeout<T>(
+    timeout: Duration,
+    future: impl std::future::Future<Output = RedisResult<T>>,
+) -> RedisResult<T> {
+    tokio::time::timeout(timeout, future)
+        .await
+        .unwrap_or_else(|_| Err((ErrorKind::IoError, "redis connection timed out").into()))
+}
+
+/// The connection to the current primary of a Sentinel deployment.
+///
+/// The connection is dropped after errors that indicate a failover, so the next command asks the
+/// sentinels for the new primary.
+pub(crate) struct SentinelPrimary {
+    manager: Arc<SentinelManager>,
+    connection: Mutex<Option<MultiplexedConnection>>,
+}
+
+impl SentinelPrimary {
+    /// Creates a lazily connected primary for the given sentinels.
+    pub fn new(manager: SentinelManager) -> Self {
+        Self {
+            manager: Arc::new(manager),
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Returns the connection to the primary, connecting to it if needed.
+    async fn connection(&self) -> RedisResult<MultiplexedConnection> {
+        let mut connection = self.connection.lock().await;
+        if let Some(ref connection) = *connection {
+            return Ok(connection.clone());
+        }
+
+        let manager = Arc::clone(&self.manager);
+        let client = tokio::task::spawn_blocking(move || manager.primary_client())
+            .await
+            .map_err(|_| RedisError::from((ErrorKind::IoError, "sentinel discovery failed")))??;
+
+        let primary = client.get_multiplexed_tokio_connection().await?;
+        *connection = Some(primary.clone());
+        Ok(primary)
+    }
+
+    /// Drops the connection if the error indicates that the primary has changed.
+    async fn check<T>(&self, result: &RedisResult<T>) {
+        if let Err(ref error) = result {
+            if error.kind() == ErrorKind::ReadOnly || error.is_io_error() {
+                *self.connection.lock().await = None;
+            }
+        }
+    }
+}
+
+/// The lazily connected asynchronous client of a [`RedisPool`](crate::RedisPool).
+pub(crate) enum AsyncClient {
+    Cluster(ClusterClient, OnceCell<ClusterConnection>),
+    Sentinel(Arc<SentinelPrimary>),
+    Single(redis::Client, OnceCell<ConnectionManager>),
+}
+
+impl AsyncClient {
+    /// Returns a connection, connecting to Redis on first use.
+    ///
+    /// Connecting fails after `connect_timeout`, and every command on the returned connection
+    /// fails after `timeout`. Cluster and single-node connections reconnect automatically, so they
+    /// are shared for the lifetime of the client.
+    pub async fn connection(
+        &self,
+        connect_timeout: Duration,
+        timeout: Duration,
+    ) -> RedisResult<AsyncConnection> {
+        let inner = match self {
+            Self::Cluster(client, connection) => AsyncConnectionInner::Cluster(
+                connection
+                    .get_or_try_init(|| {
+                        connect_with_timeout(connect_timeout, client.get_async_connection())
+                    })
+                    .await?
+                    .clone(),
+            ),
+            Self::Sentinel(primary) => AsyncConnectionInner::Sentinel(
+                connect_with_timeout(connect_timeout, primary.connection()).await?,
+                Arc::clone(primary),
+            ),
+            Self::Single(client, connection) => AsyncConnectionInner::Single(
+                connection
+                    .get_or_try_init(|| {
+                        connect_with_timeout(
+                            connect_timeout,
+                            ConnectionManager::new(client.clone()),
+                        )
+                    })
+                    .await?
+                    .clone(),
+            ),
+        };
+
+        Ok(AsyncConnection { inner, timeout })
+    }
+}
+
+impl fmt::Debug for AsyncClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cluster(..) => f.debug_tuple("Cluster").finish(),
+            Self::Sentinel(_) => f.debug_tuple("Sentinel").finish(),
+            Self::Single(..) => f.debug_tuple("Single").finish(),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum AsyncConnectionInner {
+    Cluster(ClusterConnection),
+    Sentinel(MultiplexedConnection, Arc<SentinelPrimary>),
+    Single(ConnectionManager),
+}
+
+/// An asynchronous connection to Redis.
+///
+/// Connections are multiplexed: clones share the same socket, and concurrent commands are
+/// pipelined instead of waiting for a connection from the pool. Every command fails after the
+/// configured `read_timeout`.
+///
+/// Created with [`RedisPool::async_connection`](crate::RedisPool::async_connection).
+#[derive(Clone)]
+pub struct AsyncConnection {
+    inner: AsyncConnectionInner,
+    timeout: Duration,
+}
+
+impl ConnectionLike for AsyncConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        let future = match self.inner {
+            AsyncConnectionInner::Cluster(ref mut con) => con.req_packed_command(cmd),
+            AsyncConnectionInner::Sentinel(ref mut con, ref primary) => Box::pin(async move {
+                let result = con.req_packed_command(cmd).await;
+                primary.check(&result).await;
+                result
+            }),
+            AsyncConnectionInner::Single(ref mut con) => con.req_packed_command(cmd),
+        };
+
+        with_timeout(self.timeout, future)
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        let future = match self.inner {
+            AsyncConnectionInner::Cluster(ref mut con) => {
+                con.req_packed_commands(cmd, offset, count)
+            }
+            AsyncConnectionInner::Sentinel(ref mut con, ref primary) => Box::pin(async move {
+                let result = con.req_packed_commands(cmd, offset, count).await;
+                primary.check(&result).await;
+                result
+            }),
+            AsyncConnectionInner::Single(ref mut con) => {
+                con.req_packed_commands(cmd, offset, count)
+            }
+        };
+
+        with_timeout(self.timeout, future)
+    }
+
+    fn get_db(&self) -> i64 {
+        match self.inner {
+            AsyncConnectionInner::Cluster(ref con) => con.get_db(),
+            AsyncConnectionInner::Sentinel(ref con, _) => con.get_db(),
+            AsyncConnectionInner::Single(ref con) => con.get_db(),
+        }
+    }
+}
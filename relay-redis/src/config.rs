@@ -1,3 +1,7 @@
+use std::path::PathBuf;
+
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 const fn default_max_connections() -> u32 {
@@ -24,8 +28,30 @@ const fn default_write_timeout() -> u64 {
     3
 }
 
+const fn default_cluster_retries() -> u32 {
+    16
+}
+
+/// TLS configuration for `rediss://` connections.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct RedisTlsConfig {
+    /// Path to a PEM file with the certificate authority that signed the server certificates.
+    ///
+    /// Defaults to the trust store of the operating system.
+    pub ca_certificate: Option<PathBuf>,
+    /// Path to a PEM file with a client certificate for mutual TLS.
+    ///
+    /// Requires `client_key`.
+    pub client_certificate: Option<PathBuf>,
+    /// Path to a PEM file with the private key of the client certificate.
+    pub client_key: Option<PathBuf>,
+}
+
 /// Additional configuration options for a redis client.
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(default)]
 pub struct RedisConfigOptions {
     /// Maximum number of connections managed by the pool.
@@ -48,6 +74,22 @@ pub struct RedisConfigOptions {
     /// Sets the write timeout on the connection, in seconds.
     #[serde(default = "default_write_timeout")]
     pub write_timeout: u64,
+    /// Username of the ACL user to authenticate as.
+    ///
+    /// Takes precedence over a username in the server urls.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Password to authenticate with, either of the ACL user or the default user.
+    ///
+    /// Takes precedence over a password in the server urls.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Certificates for `rediss://` urls.
+    ///
+    /// Without this option, `rediss://` connections verify servers with the trust store of the
+    /// operating system.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<RedisTlsConfig>,
 }
 
 impl Default for RedisConfigOptions {
@@ -59,21 +101,116 @@ impl Default for RedisConfigOptions {
             idle_timeout: default_idle_timeout(),
             read_timeout: default_read_timeout(),
             write_timeout: default_write_timeout(),
+            username: None,
+            password: None,
+            tls: None,
         }
     }
 }
 
+/// Additional configuration options for a Redis Cluster.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct RedisClusterOptions {
+    /// Sends read-only commands to replicas instead of primaries.
+    ///
+    /// This spreads the load of reads across the cluster, but reads may return stale values.
+    /// Defaults to `false`.
+    pub read_from_replicas: bool,
+    /// Maximum number of times a command is retried after a `MOVED` or `ASK` redirect or a
+    /// connection error while the cluster topology changes. Defaults to `16`.
+    #[serde(default = "default_cluster_retries")]
+    pub retries: u32,
+}
+
+impl Default for RedisClusterOptions {
+    fn default() -> Self {
+        Self {
+            read_from_replicas: false,
+            retries: default_cluster_retries(),
+        }
+    }
+}
+
+/// Configuration of a Redis Sentinel deployment.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+pub struct RedisSentinelConfig {
+    /// The name of the monitored primary, as configured in the sentinels.
+    pub master_name: String,
+    /// List of `redis://` urls of the sentinels.
+    ///
+    /// Sentinels are queried in order until one of them knows the address of the primary. The
+    /// `username` and `password` options apply to the primary, credentials of the sentinels are
+    /// part of their urls.
+    pub nodes: Vec<String>,
+    /// Username to authenticate with the primary.
+    ///
+    /// Deprecated in favor of the `username` option next to `sentinel`, which takes precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Password to authenticate with the primary.
+    ///
+    /// Deprecated in favor of the `password` option next to `sentinel`, which takes precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Connects to the primary with TLS. Defaults to `false`.
+    #[serde(default)]
+    pub tls: bool,
+    /// The database number to select on the primary. Defaults to `0`.
+    #[serde(default)]
+    pub db: i64,
+}
+
+impl RedisSentinelConfig {
+    /// Returns the username and password to authenticate with the primary.
+    ///
+    /// The `username` and `password` of the client options take precedence over the deprecated
+    /// fields of the sentinel configuration.
+    pub(crate) fn primary_credentials(
+        &self,
+        opts: &RedisConfigOptions,
+    ) -> (Option<String>, Option<String>) {
+        let username = opts.username.as_ref().or(self.username.as_ref());
+        let password = opts.password.as_ref().or(self.password.as_ref());
+        (username.cloned(), password.cloned())
+    }
+}
+
 /// Configuration for connecting a redis client.
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
 #[serde(untagged)]
 pub enum RedisConfig {
     /// Connect to a Redis cluster.
+    ///
+    /// Commands are routed to the node that owns the hash slot of their keys. All keys of a
+    /// script invocation must map to the same slot, see [`key_slot`](crate::key_slot).
     Cluster {
         /// List of `redis://` urls to use in cluster mode.
         ///
-        /// This can also be a single node which is configured in cluster mode.
+        /// This can also be a single node which is configured in cluster mode. The remaining
+        /// nodes are discovered from the cluster topology.
         cluster_nodes: Vec<String>,
 
+        /// Additional configuration options for the cluster.
+        #[serde(flatten)]
+        cluster: RedisClusterOptions,
+
+        /// Additional configuration options for the redis client and a connections pool.
+        #[serde(flatten)]
+        options: RedisConfigOptions,
+    },
+
+    /// Connect to the primary of a Redis Sentinel deployment.
+    ///
+    /// The address of the primary is requested from the sentinels for every new connection, so
+    /// connections move to the new primary after a failover.
+    Sentinel {
+        /// The sentinels and the name of the monitored primary.
+        sentinel: RedisSentinelConfig,
+
         /// Additional configuration options for the redis client and a connections pool.
         #[serde(flatten)]
         options: RedisConfigOptions,
@@ -166,6 +303,7 @@ cluster_nodes:
     - "redis://127.0.0.1:6379"
     - "redis://127.0.0.2:6379"
 read_timeout: 10
+read_from_replicas: true
 "#;
 
         let config: RedisConfig = serde_yaml::from_str(yaml)
@@ -174,8 +312,11 @@ read_timeout: 10
         match config {
             RedisConfig::Cluster {
                 cluster_nodes,
+                cluster,
                 options,
             } => {
+                assert!(cluster.read_from_replicas);
+                assert_eq!(cluster.retries, 16);
                 assert_eq!(options.max_connections, 24);
                 assert_eq!(options.connection_timeout, 5);
                 assert_eq!(options.read_timeout, 10);
@@ -185,4 +326,97 @@ read_timeout: 10
             e => panic!("Expected RedisConfig::SingleWithOpts but got {e:?}"),
         }
     }
+
+    #[test]
+    fn test_redis_tls_acl() {
+        let yaml = r#"
+server: "rediss://redis.example.com:6380"
+username: "relay"
+password: "secret"
+tls:
+    ca_certificate: "/etc/relay/redis-ca.pem"
+"#;
+
+        let config: RedisConfig =
+            serde_yaml::from_str(yaml).expect("Parsed processing redis config: tls");
+
+        match config {
+            RedisConfig::SingleWithOpts { options, .. } => {
+                assert_eq!(options.username.as_deref(), Some("relay"));
+                assert_eq!(options.password.as_deref(), Some("secret"));
+                let tls = options.tls.expect("tls config");
+                assert_eq!(
+                    tls.ca_certificate,
+                    Some(PathBuf::from("/etc/relay/redis-ca.pem"))
+                );
+                assert_eq!(tls.client_certificate, None);
+            }
+            e => panic!("Expected RedisConfig::SingleWithOpts but got {e:?}"),
+        }
+    }
+
+    #[test]
+    fn test_redis_sentinel() {
+        let yaml = r#"
+sentinel:
+    master_name: "mymaster"
+    nodes:
+        - "redis://127.0.0.1:26379"
+        - "redis://127.0.0.2:26379"
+    password: "secret"
+max_connections: 42
+"#;
+
+        let config: RedisConfig =
+            serde_yaml::from_str(yaml).expect("Parsed processing redis config: sentinel");
+
+        match config {
+            RedisConfig::Sentinel { sentinel, options } => {
+                assert_eq!(sentinel.master_name, "mymaster");
+                assert_eq!(sentinel.nodes.len(), 2);
+                assert_eq!(sentinel.password.as_deref(), Some("secret"));
+                assert_eq!(sentinel.username, None);
+                assert_eq!(sentinel.db, 0);
+                assert_eq!(options.max_connections, 42);
+                assert_eq!(options.read_timeout, 3);
+                assert_eq!(
+                    sentinel.primary_credentials(&options),
+                    (None, Some("secret".to_owned()))
+                );
+            }
+            e => panic!("Expected RedisConfig::Sentinel but got {e:?}"),
+        }
+    }
+
+    #[test]
+    fn test_redis_sentinel_options() {
+        let yaml = r#"
+sentinel:
+    master_name: "mymaster"
+    nodes:
+        - "redis://127.0.0.1:26379"
+        - "redis://127.0.0.2:26379"
+    password: "deprecated"
+max_connections: 42
+username: "relay"
+password: "secret"
+"#;
+
+        let config: RedisConfig =
+            serde_yaml::from_str(yaml).expect("Parsed processing redis config: sentinel");
+
+        match config {
+            RedisConfig::Sentinel { sentinel, options } => {
+                assert!(!sentinel.tls);
+                assert_eq!(sentinel.db, 0);
+                assert_eq!(options.password.as_deref(), Some("secret"));
+                assert_eq!(options.max_connections, 42);
+                assert_eq!(
+                    sentinel.primary_credentials(&options),
+                    (Some("relay".to_owned()), Some("secret".to_owned()))
+                );
+            }
+            e => panic!("Expected RedisConfig::Sentinel but got {e:?}"),
+        }
+    }
 }
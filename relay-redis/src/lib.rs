@@ -10,11 +10,19 @@
 #![allow(clippy::derive_partial_eq_without_eq)]
 
 mod config;
+mod slot;
 pub use self::config::*;
+pub use self::slot::*;
 
+#[cfg(feature = "impl")]
+mod aio;
 #[cfg(feature = "impl")]
 mod real;
 #[cfg(feature = "impl")]
+mod sentinel;
+#[cfg(feature = "impl")]
+pub use self::aio::AsyncConnection;
+#[cfg(feature = "impl")]
 pub use self::real::*;
 
 #[cfg(not(feature = "impl"))]
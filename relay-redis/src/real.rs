@@ -1,12 +1,19 @@
-use std::fmt;
+use std::sync::Arc;
 use std::time::Duration;
+use std::{fmt, fs, io};
 
 use r2d2::{Pool, PooledConnection};
 pub use redis;
-use redis::ConnectionLike;
+use redis::cluster::ClusterClientBuilder;
+use redis::{ClientTlsConfig, ConnectionInfo, ConnectionLike, IntoConnectionInfo, TlsCertificates};
 use thiserror::Error;
+use tokio::sync::OnceCell;
 
-use crate::config::{RedisConfig, RedisConfigOptions};
+use crate::aio::{AsyncClient, AsyncConnection, SentinelPrimary};
+use crate::config::{
+    RedisClusterOptions, RedisConfig, RedisConfigOptions, RedisSentinelConfig, RedisTlsConfig,
+};
+use crate::sentinel::SentinelManager;
 
 /// An error returned from `RedisPool`.
 #[derive(Debug, Error)]
@@ -18,6 +25,59 @@ pub enum RedisError {
     /// Failure in Redis communication.
     #[error("failed to communicate with redis")]
     Redis(#[source] redis::RedisError),
+
+    /// Failed to load the certificates for TLS connections.
+    #[error("failed to load redis tls certificates")]
+    Certificate(#[source] io::Error),
+}
+
+/// Loads the certificates of the TLS configuration.
+fn load_certificates(tls: &RedisTlsConfig) -> Result<TlsCertificates, RedisError> {
+    let client_tls = match (&tls.client_certificate, &tls.client_key) {
+        (Some(certificate), Some(key)) => Some(ClientTlsConfig {
+            client_cert: fs::read(certificate).map_err(RedisError::Certificate)?,
+            client_key: fs::read(key).map_err(RedisError::Certificate)?,
+        }),
+        (None, None) => None,
+        _ => {
+            return Err(RedisError::Certificate(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "client_certificate and client_key must be configured together",
+            )))
+        }
+    };
+
+    let root_cert = match tls.ca_certificate {
+        Some(ref path) => Some(fs::read(path).map_err(RedisError::Certificate)?),
+        None => None,
+    };
+
+    Ok(TlsCertificates {
+        client_tls,
+        root_cert,
+    })
+}
+
+/// Parses a server url and applies the credentials of the options.
+fn connection_info(server: &str, opts: &RedisConfigOptions) -> Result<ConnectionInfo, RedisError> {
+    let mut info = server.into_connection_info().map_err(RedisError::Redis)?;
+    if let Some(ref username) = opts.username {
+        info.redis.username = Some(username.clone());
+    }
+    if let Some(ref password) = opts.password {
+        info.redis.password = Some(password.clone());
+    }
+    Ok(info)
+}
+
+/// Creates a client for a server with the credentials and certificates of the options.
+fn open_client(server: &str, opts: &RedisConfigOptions) -> Result<redis::Client, RedisError> {
+    let info = connection_info(server, opts)?;
+    let client = match opts.tls {
+        Some(ref tls) => redis::Client::build_with_tls(info, load_certificates(tls)?),
+        None => redis::Client::open(info),
+    };
+    client.map_err(RedisError::Redis)
 }
 
 enum ConnectionInner<'a> {
@@ -74,6 +134,7 @@ impl ConnectionLike for Connection<'_> {
 
 enum PooledClientInner {
     Cluster(PooledConnection<redis::cluster::ClusterClient>),
+    Sentinel(PooledConnection<SentinelManager>),
     Single(PooledConnection<redis::Client>),
 }
 
@@ -99,6 +160,16 @@ impl PooledClient {
                     .map_err(RedisError::Redis)?;
                 ConnectionInner::Cluster(client)
             }
+            PooledClientInner::Sentinel(ref mut client) => {
+                let client = &mut client.connection;
+                client
+                    .set_read_timeout(Some(Duration::from_secs(self.opts.read_timeout)))
+                    .map_err(RedisError::Redis)?;
+                client
+                    .set_write_timeout(Some(Duration::from_secs(self.opts.write_timeout)))
+                    .map_err(RedisError::Redis)?;
+                ConnectionInner::Single(client)
+            }
             PooledClientInner::Single(ref mut client) => {
                 client
                     .set_read_timeout(Some(Duration::from_secs(self.opts.read_timeout)))
@@ -117,6 +188,7 @@ impl PooledClient {
 #[derive(Clone)]
 enum RedisPoolInner {
     Cluster(Pool<redis::cluster::ClusterClient>),
+    Sentinel(Pool<SentinelManager>),
     Single(Pool<redis::Client>),
 }
 
@@ -124,6 +196,7 @@ impl fmt::Debug for RedisPoolInner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Cluster(_) => f.debug_tuple("Cluster").finish(),
+            Self::Sentinel(_) => f.debug_tuple("Sentinel").finish(),
             Self::Single(_) => f.debug_tuple("Single").finish(),
         }
     }
@@ -141,6 +214,7 @@ impl fmt::Debug for RedisPoolInner {
 pub struct RedisPool {
     opts: RedisConfigOptions,
     inner: RedisPoolInner,
+    aio: Arc<AsyncClient>,
 }
 
 impl RedisPool {
@@ -149,11 +223,16 @@ impl RedisPool {
         match config {
             RedisConfig::Cluster {
                 ref cluster_nodes,
+                cluster,
                 options,
             } => {
                 let servers = cluster_nodes.iter().map(String::as_str).collect();
-                Self::cluster(servers, options.clone())
+                Self::cluster(servers, cluster, options.clone())
             }
+            RedisConfig::Sentinel {
+                ref sentinel,
+                options,
+            } => Self::sentinel(sentinel, options.clone()),
             RedisConfig::Single(ref server) => Self::single(server, RedisConfigOptions::default()),
             RedisConfig::SingleWithOpts {
                 ref server,
@@ -163,33 +242,116 @@ impl RedisPool {
     }
 
     /// Creates a `RedisPool` in cluster configuration.
-    pub fn cluster(servers: Vec<&str>, opts: RedisConfigOptions) -> Result<Self, RedisError> {
+    ///
+    /// The client discovers the cluster topology from the given servers, routes commands to the
+    /// node owning the slot of their keys, and follows `MOVED` and `ASK` redirects.
+    pub fn cluster(
+        servers: Vec<&str>,
+        cluster: &RedisClusterOptions,
+        opts: RedisConfigOptions,
+    ) -> Result<Self, RedisError> {
+        let mut builder = ClusterClientBuilder::new(servers).retries(cluster.retries);
+        if cluster.read_from_replicas {
+            builder = builder.read_from_replicas();
+        }
+        if let Some(ref username) = opts.username {
+            builder = builder.username(username.clone());
+        }
+        if let Some(ref password) = opts.password {
+            builder = builder.password(password.clone());
+        }
+        if let Some(ref tls) = opts.tls {
+            builder = builder.certs(load_certificates(tls)?);
+        }
+
+        let client = builder.build().map_err(RedisError::Redis)?;
+        let aio = AsyncClient::Cluster(client.clone(), OnceCell::new());
+
         let pool = Pool::builder()
             .max_size(opts.max_connections)
             .test_on_check_out(false)
             .max_lifetime(Some(Duration::from_secs(opts.max_lifetime)))
             .idle_timeout(Some(Duration::from_secs(opts.idle_timeout)))
             .connection_timeout(Duration::from_secs(opts.connection_timeout))
-            .build(redis::cluster::ClusterClient::new(servers).map_err(RedisError::Redis)?)
+            .build(client)
             .map_err(RedisError::Pool)?;
 
         let inner = RedisPoolInner::Cluster(pool);
-        Ok(RedisPool { opts, inner })
+        Ok(RedisPool {
+            opts,
+            inner,
+            aio: Arc::new(aio),
+        })
+    }
+
+    /// Creates a `RedisPool` that connects to the primary of a Sentinel deployment.
+    ///
+    /// Idle connections are checked on checkout and replaced if their node is no longer the
+    /// primary, so the pool follows failovers. Checkouts wait at most `connection_timeout` for a
+    /// connection, which also bounds connecting to the sentinels and the primary.
+    pub fn sentinel(
+        sentinel: &RedisSentinelConfig,
+        opts: RedisConfigOptions,
+    ) -> Result<Self, RedisError> {
+        let timeout = Duration::from_secs(opts.connection_timeout);
+        let certificates = opts.tls.as_ref().map(load_certificates).transpose()?;
+        let manager = SentinelManager::new(sentinel, &opts, certificates.clone(), timeout)
+            .map_err(RedisError::Redis)?;
+        let async_manager = SentinelManager::new(sentinel, &opts, certificates, timeout)
+            .map_err(RedisError::Redis)?;
+        let aio = AsyncClient::Sentinel(Arc::new(SentinelPrimary::new(async_manager)));
+
+        let pool = Pool::builder()
+            .max_size(opts.max_connections)
+            .test_on_check_out(true)
+            .max_lifetime(Some(Duration::from_secs(opts.max_lifetime)))
+            .idle_timeout(Some(Duration::from_secs(opts.idle_timeout)))
+            .connection_timeout(timeout)
+            .build(manager)
+            .map_err(RedisError::Pool)?;
+
+        let inner = RedisPoolInner::Sentinel(pool);
+        Ok(RedisPool {
+            opts,
+            inner,
+            aio: Arc::new(aio),
+        })
     }
 
     /// Creates a `RedisPool` in single-node configuration.
     pub fn single(server: &str, opts: RedisConfigOptions) -> Result<Self, RedisError> {
+        let client = open_client(server, &opts)?;
+        let aio = AsyncClient::Single(client.clone(), OnceCell::new());
+
         let pool = Pool::builder()
             .max_size(opts.max_connections)
             .test_on_check_out(false)
             .max_lifetime(Some(Duration::from_secs(opts.max_lifetime)))
             .idle_timeout(Some(Duration::from_secs(opts.idle_timeout)))
             .connection_timeout(Duration::from_secs(opts.connection_timeout))
-            .build(redis::Client::open(server).map_err(RedisError::Redis)?)
+            .build(client)
             .map_err(RedisError::Pool)?;
 
         let inner = RedisPoolInner::Single(pool);
-        Ok(RedisPool { opts, inner })
+        Ok(RedisPool {
+            opts,
+            inner,
+            aio: Arc::new(aio),
+        })
+    }
+
+    /// Returns a multiplexed asynchronous connection.
+    ///
+    /// The first call connects to Redis. All connections of a pool share the same socket, so
+    /// concurrent commands are pipelined instead of waiting for a pooled connection. Unlike
+    /// [`client`](Self::client), this does not block the calling thread.
+    pub async fn async_connection(&self) -> Result<AsyncConnection, RedisError> {
+        let connect_timeout = Duration::from_secs(self.opts.connection_timeout);
+        let timeout = Duration::from_secs(self.opts.read_timeout);
+        self.aio
+            .connection(connect_timeout, timeout)
+            .await
+            .map_err(RedisError::Redis)
     }
 
     /// Returns a pooled connection to a client.
@@ -198,6 +360,9 @@ impl RedisPool {
             RedisPoolInner::Cluster(ref pool) => {
                 PooledClientInner::Cluster(pool.get().map_err(RedisError::Pool)?)
             }
+            RedisPoolInner::Sentinel(ref pool) => {
+                PooledClientInner::Sentinel(pool.get().map_err(RedisError::Pool)?)
+            }
             RedisPoolInner::Single(ref pool) => {
                 PooledClientInner::Single(pool.get().map_err(RedisError::Pool)?)
             }
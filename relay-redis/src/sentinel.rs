@@ -0,0 +1,192 @@
+//! Connections to the primary of a Redis Sentinel deployment.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use redis::{
+    ConnectionAddr, ConnectionInfo, ConnectionLike, ErrorKind, IntoConnectionInfo,
+    RedisConnectionInfo, RedisResult, TlsCertificates, Value,
+};
+
+use crate::config::{RedisConfigOptions, RedisSentinelConfig};
+
+/// The idle time after which a pooled connection is checked to still point at the primary.
+///
+/// Connections in active use skip the check, which would otherwise add a round trip to every
+/// checkout.
+const VALIDATION_IDLE_AGE: Duration = Duration::from_secs(5);
+
+/// A pooled connection to the primary of a Sentinel deployment.
+pub struct SentinelConnection {
+    /// The connection to the primary.
+    pub connection: redis::Connection,
+    /// The time of the last checkout from the pool.
+    last_used: Instant,
+}
+
+/// A connection manager for the pool that connects to the current primary of a Sentinel
+/// deployment.
+///
+/// Every new connection asks the sentinels for the address of the primary. Connections that have
+/// been idle for longer than [`VALIDATION_IDLE_AGE`] are validated on checkout, so connections to a
+/// primary that has been demoted by a failover are replaced with connections to the new primary.
+pub struct SentinelManager {
+    sentinels: Vec<redis::Client>,
+    master_name: String,
+    redis: RedisConnectionInfo,
+    tls: bool,
+    certificates: Option<TlsCertificates>,
+    timeout: Duration,
+}
+
+impl SentinelManager {
+    /// Creates a connection manager for the given sentinels.
+    ///
+    /// The credentials of the options apply to the primary, and the certificates apply to all
+    /// TLS connections. The `timeout` applies to connections to the sentinels.
+    pub fn new(
+        config: &RedisSentinelConfig,
+        opts: &RedisConfigOptions,
+        certificates: Option<TlsCertificates>,
+        timeout: Duration,
+    ) -> RedisResult<Self> {
+        let sentinels = config
+            .nodes
+            .iter()
+            .map(|node| open_client(node.as_str(), certificates.as_ref()))
+            .collect::<RedisResult<_>>()?;
+
+        let (username, password) = config.primary_credentials(opts);
+
+        Ok(Self {
+            sentinels,
+            master_name: config.master_name.clone(),
+            redis: RedisConnectionInfo {
+                db: config.db,
+                username,
+                password,
+                ..Default::default()
+            },
+            tls: config.tls,
+            certificates,
+            timeout,
+        })
+    }
+
+    /// Requests the address of the primary from the first sentinel that knows it.
+    fn primary(&self) -> RedisResult<ConnectionInfo> {
+        let mut last_error = None;
+
+        for sentinel in &self.sentinels {
+            let result =
+                sentinel
+                    .get_connection_with_timeout(self.timeout)
+                    .and_then(|mut connection| {
+                        redis::cmd("SENTINEL")
+                            .arg("get-master-addr-by-name")
+                            .arg(&self.master_name)
+                            .query::<Option<(String, u16)>>(&mut connection)
+                    });
+
+            match result {
+                Ok(Some((host, port))) => {
+                    let addr = if self.tls {
+                        ConnectionAddr::TcpTls {
+                            host,
+                            port,
+                            insecure: false,
+                            tls_params: None,
+                        }
+                    } else {
+                        ConnectionAddr::Tcp(host, port)
+                    };
+
+                    return Ok(ConnectionInfo {
+                        addr,
+                        redis: self.redis.clone(),
+                    });
+                }
+                Ok(None) => {
+                    last_error = Some((ErrorKind::ResponseError, "unknown sentinel master").into())
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| (ErrorKind::InvalidClientConfig, "no sentinel nodes").into()))
+    }
+
+    /// Returns a client for the current primary.
+    ///
+    /// This blocks while the sentinels are queried.
+    pub fn primary_client(&self) -> RedisResult<redis::Client> {
+        open_client(self.primary()?, self.certificates.as_ref())
+    }
+}
+
+impl fmt::Debug for SentinelManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SentinelManager")
+            .field("sentinels", &self.sentinels.len())
+            .field("master_name", &self.master_name)
+            .field("tls", &self.tls)
+            .finish()
+    }
+}
+
+/// Creates a client for a url, with the given certificates for TLS connections.
+fn open_client<T: IntoConnectionInfo>(
+    info: T,
+    certificates: Option<&TlsCertificates>,
+) -> RedisResult<redis::Client> {
+    match certificates {
+        Some(certificates) => redis::Client::build_with_tls(info, certificates.clone()),
+        None => redis::Client::open(info),
+    }
+}
+
+/// Returns `true` if the node of the connection is a primary.
+fn is_primary(connection: &mut redis::Connection) -> RedisResult<bool> {
+    let role: Value = redis::cmd("ROLE").query(connection)?;
+    Ok(match role {
+        Value::Bulk(values) => values.first() == Some(&Value::Data(b"master".to_vec())),
+        _ => false,
+    })
+}
+
+impl r2d2::ManageConnection for SentinelManager {
+    type Connection = SentinelConnection;
+    type Error = redis::RedisError;
+
+    fn connect(&self) -> RedisResult<Self::Connection> {
+        let mut connection = self
+            .primary_client()?
+            .get_connection_with_timeout(self.timeout)?;
+
+        // The sentinels may not have noticed a failover yet.
+        if !is_primary(&mut connection)? {
+            return Err((ErrorKind::ReadOnly, "sentinel master is not a primary").into());
+        }
+
+        Ok(SentinelConnection {
+            connection,
+            last_used: Instant::now(),
+        })
+    }
+
+    fn is_valid(&self, connection: &mut Self::Connection) -> RedisResult<()> {
+        let idle = connection.last_used.elapsed();
+        connection.last_used = Instant::now();
+
+        if idle >= VALIDATION_IDLE_AGE && !is_primary(&mut connection.connection)? {
+            return Err((ErrorKind::ReadOnly, "connected node is no longer a primary").into());
+        }
+
+        Ok(())
+    }
+
+    fn has_broken(&self, connection: &mut Self::Connection) -> bool {
+        !connection.connection.is_open()
+    }
+}
@@ -0,0 +1,68 @@
+//! Hash slots of keys in a Redis Cluster.
+
+/// The number of hash slots in a Redis Cluster.
+pub const CLUSTER_SLOTS: u16 = 16384;
+
+/// Computes the CRC16-XMODEM checksum that Redis Cluster uses to assign keys to slots.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |mut crc: u16, byte| {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Returns the hash slot of a key in a Redis Cluster.
+///
+/// If the key contains a hash tag, which is a non-empty substring between the first `{` and the
+/// following `}`, only the hash tag is hashed. Keys with the same hash tag are stored on the same
+/// node, so they can be used together in scripts and transactions.
+pub fn key_slot(key: &[u8]) -> u16 {
+    let hash_tag = key.iter().position(|b| *b == b'{').and_then(|open| {
+        let tag = &key[open + 1..];
+        let close = tag.iter().position(|b| *b == b'}')?;
+        (close > 0).then(|| &tag[..close])
+    });
+
+    crc16(hash_tag.unwrap_or(key)) % CLUSTER_SLOTS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+    }
+
+    #[test]
+    fn test_key_slot() {
+        assert_eq!(key_slot(b"foo"), 12182);
+        assert_eq!(key_slot(b"bar"), 5061);
+    }
+
+    #[test]
+    fn test_key_slot_hash_tag() {
+        assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+        assert_eq!(
+            key_slot(b"{user1000}.following"),
+            key_slot(b"{user1000}.followers")
+        );
+
+        // Empty hash tags hash the entire key.
+        assert_eq!(
+            key_slot(b"foo{}{bar}"),
+            crc16(b"foo{}{bar}") % CLUSTER_SLOTS
+        );
+        // Only the first hash tag is used.
+        assert_eq!(key_slot(b"foo{{bar}}zap"), key_slot(b"{bar"));
+        assert_eq!(key_slot(b"foo{bar}{zap}"), key_slot(b"bar"));
+    }
+}
@@ -0,0 +1,217 @@
+//! Local adaptive sampling towards a target rate.
+//!
+//! Relays that do not receive server-computed sampling rules, such as proxy or static Relays, can
+//! use an [`AdaptiveSampler`] to keep the volume of forwarded transactions per project close to a
+//! configured budget. The sampler counts incoming items per project in fixed windows and derives
+//! the sample rate for the next window from the observed volume:
+//!
+//! ```text
+//! sample_rate = clamp(target / observed, min_sample_rate, 1.0)
+//! ```
+//!
+//! The new rate is blended with the previous one to avoid oscillation on bursty traffic.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use relay_base_schema::project::ProjectKey;
+
+/// Weight of the newly computed sample rate when blending it with the previous rate.
+const SMOOTHING: f64 = 0.5;
+
+/// Configuration of an [`AdaptiveSampler`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSamplingConfig {
+    /// The targeted number of items per project and window.
+    pub target: f64,
+    /// The length of the window after which sample rates are recomputed.
+    pub window: Duration,
+    /// The lowest sample rate the sampler is allowed to apply.
+    pub min_sample_rate: f64,
+}
+
+/// Sampling state of a single project.
+#[derive(Debug)]
+struct ProjectWindow {
+    /// Start of the current counting window.
+    start: Instant,
+    /// Number of items observed in the current window.
+    count: u64,
+    /// The sample rate applied during the current window.
+    sample_rate: f64,
+}
+
+#[derive(Debug)]
+struct State {
+    projects: HashMap<ProjectKey, ProjectWindow>,
+    last_prune: Instant,
+}
+
+/// Computes per-project sample rates that converge towards a target volume.
+///
+/// This type is safe to share across threads.
+#[derive(Debug)]
+pub struct AdaptiveSampler {
+    config: AdaptiveSamplingConfig,
+    state: Mutex<State>,
+}
+
+impl AdaptiveSampler {
+    /// Creates a new sampler with the given configuration.
+    pub fn new(config: AdaptiveSamplingConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State {
+                projects: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+        }
+    }
+
+    /// Records an incoming item for the project and returns the sample rate to apply to it.
+    ///
+    /// The first window of a project always uses a sample rate of `1.0`, since there is no
+    /// information about its volume yet.
+    pub fn sample_rate(&self, project_key: ProjectKey, now: Instant) -> f64 {
+        let config = &self.config;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if now.saturating_duration_since(state.last_prune) >= config.window {
+            // Forget projects that have not sent anything for two windows.
+            let max_idle = config.window * 2;
+            state
+                .projects
+                .retain(|_, window| now.saturating_duration_since(window.start) < max_idle);
+            state.last_prune = now;
+        }
+
+        let window = state
+            .projects
+            .entry(project_key)
+            .or_insert_with(|| ProjectWindow {
+                start: now,
+                count: 0,
+                sample_rate: 1.0,
+            });
+
+        let elapsed = now.saturating_duration_since(window.start);
+        if elapsed >= config.window {
+            // Normalize the observed count to the window length, in case the project was idle for
+            // a part of the elapsed time.
+            let observed =
+                window.count as f64 * config.window.as_secs_f64() / elapsed.as_secs_f64();
+            let ideal = if observed > 0.0 {
+                config.target / observed
+            } else {
+                1.0
+            };
+
+            let blended = window.sample_rate + (ideal - window.sample_rate) * SMOOTHING;
+            window.sample_rate = blended.clamp(config.min_sample_rate.clamp(0.0, 1.0), 1.0);
+            window.start = now;
+            window.count = 0;
+        }
+
+        window.count += 1;
+        window.sample_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampler(target: f64, min_sample_rate: f64) -> AdaptiveSampler {
+        AdaptiveSampler::new(AdaptiveSamplingConfig {
+            target,
+            window: Duration::from_secs(60),
+            min_sample_rate,
+        })
+    }
+
+    fn project_key(key: &str) -> ProjectKey {
+        ProjectKey::parse(key).unwrap()
+    }
+
+    /// Feeds `count` items evenly into one window starting at `start` and returns the last rate.
+    fn feed(sampler: &AdaptiveSampler, key: ProjectKey, start: Instant, count: u32) -> f64 {
+        let step = Duration::from_secs(60) / count;
+        let mut rate = 0.0;
+        for i in 0..count {
+            rate = sampler.sample_rate(key, start + step * i);
+        }
+        rate
+    }
+
+    #[test]
+    fn test_first_window_keeps_everything() {
+        let sampler = sampler(10.0, 0.0);
+        let key = project_key("a94ae32be2584e0bbd7a4cbb95971fee");
+        assert_eq!(feed(&sampler, key, Instant::now(), 1000), 1.0);
+    }
+
+    #[test]
+    fn test_converges_to_target() {
+        let sampler = sampler(100.0, 0.0);
+        let key = project_key("a94ae32be2584e0bbd7a4cbb95971fee");
+        let start = Instant::now();
+
+        let mut rate = 1.0;
+        for window in 0..10 {
+            rate = feed(
+                &sampler,
+                key,
+                start + Duration::from_secs(60 * window),
+                1000,
+            );
+        }
+
+        assert!((rate - 0.1).abs() < 0.01, "rate was {rate}");
+    }
+
+    #[test]
+    fn test_min_sample_rate() {
+        let sampler = sampler(1.0, 0.2);
+        let key = project_key("a94ae32be2584e0bbd7a4cbb95971fee");
+        let start = Instant::now();
+
+        let mut rate = 1.0;
+        for window in 0..10 {
+            rate = feed(
+                &sampler,
+                key,
+                start + Duration::from_secs(60 * window),
+                1000,
+            );
+        }
+
+        assert_eq!(rate, 0.2);
+    }
+
+    #[test]
+    fn test_below_target_keeps_everything() {
+        let sampler = sampler(100.0, 0.0);
+        let key = project_key("a94ae32be2584e0bbd7a4cbb95971fee");
+        let start = Instant::now();
+
+        feed(&sampler, key, start, 10);
+        let rate = feed(&sampler, key, start + Duration::from_secs(60), 10);
+        assert_eq!(rate, 1.0);
+    }
+
+    #[test]
+    fn test_projects_are_independent() {
+        let sampler = sampler(100.0, 0.0);
+        let busy = project_key("a94ae32be2584e0bbd7a4cbb95971fee");
+        let quiet = project_key("b94ae32be2584e0bbd7a4cbb95971fee");
+        let start = Instant::now();
+
+        feed(&sampler, busy, start, 1000);
+        feed(&sampler, quiet, start, 10);
+
+        let next = start + Duration::from_secs(60);
+        assert!(sampler.sample_rate(busy, next) < 1.0);
+        assert_eq!(sampler.sample_rate(quiet, next), 1.0);
+    }
+}
@@ -2,6 +2,8 @@
 //!
 //! The root type is [`RuleCondition`].
 
+use std::cmp::Ordering;
+
 use relay_common::glob3::GlobPatterns;
 use relay_protocol::{Getter, Val};
 use serde::{Deserialize, Serialize};
@@ -115,6 +117,72 @@ impl_cmp_condition!(LteCondition, <=, "A condition that applies `<=`.");
 impl_cmp_condition!(GtCondition, >, "A condition that applies `>`.");
 impl_cmp_condition!(LtCondition, <, "A condition that applies `<`.");
 
+/// Compares a numeric field value with a number from a condition.
+///
+/// Returns `None` if the value is not numeric or the two cannot be compared.
+fn compare_numbers(value: Val<'_>, number: &Number) -> Option<Ordering> {
+    // Same order of conversions as in `impl_cmp_condition`.
+    if let (Some(a), Some(b)) = (value.as_i64(), number.as_i64()) {
+        Some(a.cmp(&b))
+    } else if let (Some(a), Some(b)) = (value.as_u64(), number.as_u64()) {
+        Some(a.cmp(&b))
+    } else if let (Some(a), Some(b)) = (value.as_f64(), number.as_f64()) {
+        a.partial_cmp(&b)
+    } else {
+        None
+    }
+}
+
+/// A condition that checks whether a numeric value lies within a range.
+///
+/// All bounds are optional and combined with logical AND. A range without any bounds matches all
+/// numeric values. This is useful to target measurements, for instance
+/// `event.measurements.lcp.value` in the interval `[2500, 4000)`.
+///
+/// Strings are explicitly not supported by this.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RangeCondition {
+    /// Path of the field that should match the value.
+    pub name: String,
+    /// Inclusive lower bound.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gte: Option<Number>,
+    /// Exclusive lower bound.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gt: Option<Number>,
+    /// Inclusive upper bound.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lte: Option<Number>,
+    /// Exclusive upper bound.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lt: Option<Number>,
+}
+
+impl RangeCondition {
+    fn matches<T>(&self, instance: &T) -> bool
+    where
+        T: Getter + ?Sized,
+    {
+        let Some(value) = instance.get_value(self.name.as_str()) else {
+            return false;
+        };
+
+        if value.as_f64().is_none() {
+            return false;
+        }
+
+        let check = |bound: &Option<Number>, accept: fn(Ordering) -> bool| match bound {
+            Some(bound) => compare_numbers(value, bound).is_some_and(accept),
+            None => true,
+        };
+
+        check(&self.gte, Ordering::is_ge)
+            && check(&self.gt, Ordering::is_gt)
+            && check(&self.lte, Ordering::is_le)
+            && check(&self.lt, Ordering::is_lt)
+    }
+}
+
 /// A condition that uses glob matching.
 ///
 /// This is similar to [`EqCondition`], but it allows for wildcards in `value`. This is slightly
@@ -140,6 +208,20 @@ impl GlobCondition {
             _ => false,
         }
     }
+
+    /// Negated version of [`matches`](Self::matches).
+    ///
+    /// In contrast to wrapping the glob in a [`NotCondition`], this requires the field to be
+    /// present and to be a string.
+    fn matches_negated<T>(&self, instance: &T) -> bool
+    where
+        T: Getter + ?Sized,
+    {
+        match instance.get_value(self.name.as_str()) {
+            Some(Val::String(s)) => !self.value.is_match(s),
+            _ => false,
+        }
+    }
 }
 
 /// Combines multiple conditions using logical OR.
@@ -207,6 +289,65 @@ impl NotCondition {
     }
 }
 
+/// Applies a condition to the elements of an array.
+///
+/// This condition matches if **any** of the elements in the array at `name` matches the inner
+/// condition. Paths in the inner condition are resolved relative to the array element, for
+/// example `span.op` for elements of `event.spans`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnyCondition {
+    /// Path of the array field.
+    pub name: String,
+    /// The condition to apply to each element.
+    pub inner: Box<RuleCondition>,
+}
+
+impl AnyCondition {
+    fn supported(&self) -> bool {
+        self.inner.supported()
+    }
+
+    fn matches<T>(&self, instance: &T) -> bool
+    where
+        T: Getter + ?Sized,
+    {
+        let Some(mut iter) = instance.get_iter(self.name.as_str()) else {
+            return false;
+        };
+
+        iter.any(|element| self.inner.matches(element))
+    }
+}
+
+/// Applies a condition to the elements of an array.
+///
+/// This condition matches if **all** of the elements in the array at `name` match the inner
+/// condition. An empty array matches, while a missing array does not.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AllCondition {
+    /// Path of the array field.
+    pub name: String,
+    /// The condition to apply to each element.
+    pub inner: Box<RuleCondition>,
+}
+
+impl AllCondition {
+    fn supported(&self) -> bool {
+        self.inner.supported()
+    }
+
+    fn matches<T>(&self, instance: &T) -> bool
+    where
+        T: Getter + ?Sized,
+    {
+        let Some(mut iter) = instance.get_iter(self.name.as_str()) else {
+            return false;
+        };
+
+        iter.all(|element| self.inner.matches(element))
+    }
+}
+
 /// A condition from a sampling rule.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "op")]
@@ -221,14 +362,22 @@ pub enum RuleCondition {
     Gt(GtCondition),
     /// A condition that applies `<`.
     Lt(LtCondition),
+    /// A condition that checks whether a numeric value lies within a range.
+    Range(RangeCondition),
     /// A condition that uses glob matching.
     Glob(GlobCondition),
+    /// A condition that matches if a string field does not match any of the glob patterns.
+    NotGlob(GlobCondition),
     /// Combines multiple conditions using logical OR.
     Or(OrCondition),
     /// Combines multiple conditions using logical AND.
     And(AndCondition),
     /// Applies logical NOT to a condition.
     Not(NotCondition),
+    /// Matches if any element of an array matches the inner condition.
+    Any(AnyCondition),
+    /// Matches if all elements of an array match the inner condition.
+    All(AllCondition),
     /// An unsupported condition for future compatibility.
     #[serde(other)]
     Unsupported,
@@ -252,11 +401,40 @@ impl RuleCondition {
             | RuleCondition::Gt(_)
             | RuleCondition::Lt(_)
             | RuleCondition::Eq(_)
-            | RuleCondition::Glob(_) => true,
+            | RuleCondition::Range(_)
+            | RuleCondition::Glob(_)
+            | RuleCondition::NotGlob(_) => true,
             // dig down for embedded conditions
             RuleCondition::And(rules) => rules.supported(),
             RuleCondition::Or(rules) => rules.supported(),
             RuleCondition::Not(rule) => rule.supported(),
+            RuleCondition::Any(rule) => rule.supported(),
+            RuleCondition::All(rule) => rule.supported(),
+        }
+    }
+
+    /// Compiles the glob patterns of this condition and all inner conditions.
+    ///
+    /// Returns the first pattern that is not a valid glob. See [`GlobPatterns::compile`].
+    pub fn compile_globs(&self) -> Result<(), &str> {
+        match self {
+            RuleCondition::Glob(condition) | RuleCondition::NotGlob(condition) => {
+                condition.value.compile()
+            }
+            RuleCondition::And(AndCondition { inner })
+            | RuleCondition::Or(OrCondition { inner }) => {
+                inner.iter().try_for_each(RuleCondition::compile_globs)
+            }
+            RuleCondition::Not(NotCondition { inner })
+            | RuleCondition::Any(AnyCondition { inner, .. })
+            | RuleCondition::All(AllCondition { inner, .. }) => inner.compile_globs(),
+            RuleCondition::Eq(_)
+            | RuleCondition::Gte(_)
+            | RuleCondition::Lte(_)
+            | RuleCondition::Gt(_)
+            | RuleCondition::Lt(_)
+            | RuleCondition::Range(_)
+            | RuleCondition::Unsupported => Ok(()),
         }
     }
 
@@ -271,10 +449,14 @@ impl RuleCondition {
             RuleCondition::Gte(condition) => condition.matches(value),
             RuleCondition::Gt(condition) => condition.matches(value),
             RuleCondition::Lt(condition) => condition.matches(value),
+            RuleCondition::Range(condition) => condition.matches(value),
             RuleCondition::Glob(condition) => condition.matches(value),
+            RuleCondition::NotGlob(condition) => condition.matches_negated(value),
             RuleCondition::And(conditions) => conditions.matches(value),
             RuleCondition::Or(conditions) => conditions.matches(value),
             RuleCondition::Not(condition) => condition.matches(value),
+            RuleCondition::Any(condition) => condition.matches(value),
+            RuleCondition::All(condition) => condition.matches(value),
             RuleCondition::Unsupported => false,
         }
     }
@@ -282,8 +464,22 @@ impl RuleCondition {
 
 #[cfg(test)]
 mod tests {
+    use relay_event_schema::protocol::Event;
+    use relay_protocol::Annotated;
+
     use super::*;
 
+    fn condition(json: &str) -> RuleCondition {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn event(json: &str) -> Event {
+        Annotated::<Event>::from_json(json)
+            .unwrap()
+            .into_value()
+            .unwrap()
+    }
+
     #[test]
     fn deserialize() {
         let serialized_rules = r#"[
@@ -411,4 +607,148 @@ mod tests {
         let rule: RuleCondition = serde_json::from_str(bad_json).unwrap();
         assert!(matches!(rule, RuleCondition::Unsupported));
     }
+
+    #[test]
+    fn deserialize_new_operators() {
+        let rule = condition(
+            r#"{
+                "op": "any",
+                "name": "event.spans",
+                "inner": {
+                    "op": "and",
+                    "inner": [
+                        {"op": "eq", "name": "span.op", "value": "db.redis"},
+                        {"op": "range", "name": "span.exclusive_time", "gte": 10, "lt": 100.5},
+                        {"op": "notGlob", "name": "span.description", "value": ["GET *"]}
+                    ]
+                }
+            }"#,
+        );
+
+        assert!(rule.supported());
+        insta::assert_ron_snapshot!(rule, @r#"
+        AnyCondition(
+          op: "any",
+          name: "event.spans",
+          inner: AndCondition(
+            op: "and",
+            inner: [
+              EqCondition(
+                op: "eq",
+                name: "span.op",
+                value: "db.redis",
+              ),
+              RangeCondition(
+                op: "range",
+                name: "span.exclusive_time",
+                gte: Some(10),
+                lt: Some(100.5),
+              ),
+              GlobCondition(
+                op: "notGlob",
+                name: "span.description",
+                value: [
+                  "GET *",
+                ],
+              ),
+            ],
+          ),
+        )
+        "#);
+    }
+
+    #[test]
+    fn unsupported_nested_in_any() {
+        let rule = condition(r#"{"op": "all", "name": "event.spans", "inner": {"op": "foo"}}"#);
+        assert!(!rule.supported());
+    }
+
+    #[test]
+    fn compile_nested_globs() {
+        let rule = condition(
+            r#"{"op": "not", "inner": {"op": "any", "name": "event.spans", "inner": {
+                "op": "glob", "name": "span.op", "value": ["db.*", "http[.client"]
+            }}}"#,
+        );
+        assert_eq!(rule.compile_globs(), Err("http[.client"));
+
+        let rule = condition(r#"{"op": "glob", "name": "event.transaction", "value": ["/api/*"]}"#);
+        assert_eq!(rule.compile_globs(), Ok(()));
+    }
+
+    #[test]
+    fn match_any_all_spans() {
+        let event = event(
+            r#"{
+                "type": "transaction",
+                "spans": [
+                    {"op": "db.redis", "exclusive_time": 5.0},
+                    {"op": "http.client", "exclusive_time": 50.0}
+                ]
+            }"#,
+        );
+
+        let any_redis = condition(
+            r#"{"op": "any", "name": "event.spans", "inner": {"op": "eq", "name": "span.op", "value": "db.redis"}}"#,
+        );
+        assert!(any_redis.matches(&event));
+
+        let all_redis = condition(
+            r#"{"op": "all", "name": "event.spans", "inner": {"op": "eq", "name": "span.op", "value": "db.redis"}}"#,
+        );
+        assert!(!all_redis.matches(&event));
+
+        let all_fast = condition(
+            r#"{"op": "all", "name": "event.spans", "inner": {"op": "lt", "name": "span.exclusive_time", "value": 100}}"#,
+        );
+        assert!(all_fast.matches(&event));
+
+        // Missing arrays never match, even for `all`.
+        assert!(!all_fast.matches(&Event::default()));
+        // Paths that are not arrays never match.
+        let any_release = condition(
+            r#"{"op": "any", "name": "event.release", "inner": {"op": "and", "inner": []}}"#,
+        );
+        assert!(!any_release.matches(&event));
+    }
+
+    #[test]
+    fn match_not_glob() {
+        let event = event(r#"{"transaction": "/api/health"}"#);
+
+        let not_health =
+            condition(r#"{"op": "notGlob", "name": "event.transaction", "value": ["*/health*"]}"#);
+        assert!(!not_health.matches(&event));
+
+        let not_users = condition(
+            r#"{"op": "notGlob", "name": "event.transaction", "value": ["/api/users/*"]}"#,
+        );
+        assert!(not_users.matches(&event));
+
+        // Unlike `not`, a missing field does not match.
+        assert!(!not_users.matches(&Event::default()));
+    }
+
+    #[test]
+    fn match_range_measurements() {
+        let event = event(r#"{"measurements": {"lcp": {"value": 4200.0}}}"#);
+
+        let poor_lcp =
+            condition(r#"{"op": "range", "name": "event.measurements.lcp.value", "gt": 4000}"#);
+        assert!(poor_lcp.matches(&event));
+
+        let good_lcp = condition(
+            r#"{"op": "range", "name": "event.measurements.lcp.value", "gte": 0, "lte": 2500}"#,
+        );
+        assert!(!good_lcp.matches(&event));
+
+        let unbounded = condition(r#"{"op": "range", "name": "event.measurements.lcp.value"}"#);
+        assert!(unbounded.matches(&event));
+
+        let missing = condition(r#"{"op": "range", "name": "event.measurements.fcp.value"}"#);
+        assert!(!missing.matches(&event));
+
+        let string_field = condition(r#"{"op": "range", "name": "event.transaction", "gt": 0}"#);
+        assert!(!string_field.matches(&event));
+    }
 }
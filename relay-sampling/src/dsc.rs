@@ -142,15 +142,38 @@ impl Getter for DynamicSamplingContext {
         Some(match path.strip_prefix("trace.")? {
             "release" => self.release.as_deref()?.into(),
             "environment" => self.environment.as_deref()?.into(),
-            "user.id" => or_none(&self.user.user_id)?.into(),
-            "user.segment" => or_none(&self.user.user_segment)?.into(),
+            "user.id" | "user_id" => or_none(&self.user.user_id)?.into(),
+            "user.segment" | "user_segment" => or_none(&self.user.user_segment)?.into(),
             "transaction" => self.transaction.as_deref()?.into(),
             "replay_id" => self.replay_id?.into(),
-            _ => return None,
+            "sample_rate" => self.sample_rate?.into(),
+            "sampled" => self.sampled?.into(),
+            // Arbitrary entries propagated through the trace header or baggage.
+            key => json_to_val(self.other.get(key)?)?,
         })
     }
 }
 
+/// Converts a flat JSON value into a [`Val`].
+///
+/// Since the DSC must be representable as baggage entries, nested values are not supported.
+fn json_to_val(value: &Value) -> Option<Val<'_>> {
+    Some(match value {
+        Value::String(s) => s.as_str().into(),
+        Value::Bool(b) => (*b).into(),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                i.into()
+            } else if let Some(u) = n.as_u64() {
+                u.into()
+            } else {
+                n.as_f64()?.into()
+            }
+        }
+        Value::Null | Value::Array(_) | Value::Object(_) => return None,
+    })
+}
+
 fn or_none(string: &impl AsRef<str>) -> Option<&str> {
     match string.as_ref() {
         "" => None,
@@ -642,6 +665,36 @@ mod tests {
             dsc.get_value("trace.transaction")
         );
         assert_eq!(Some(Val::Uuid(replay_id)), dsc.get_value("trace.replay_id"));
+        assert_eq!(
+            Some(Val::String("user-seg")),
+            dsc.get_value("trace.user_segment")
+        );
+    }
+
+    #[test]
+    fn getter_other() {
+        let json = r#"
+        {
+            "trace_id": "00000000-0000-0000-0000-000000000000",
+            "public_key": "abd0f232775f45feab79864e580d160b",
+            "sample_rate": "0.5",
+            "sampled": "true",
+            "tier": "enterprise",
+            "shard": 7,
+            "beta": false,
+            "nested": {"a": "b"}
+        }
+        "#;
+        let dsc = serde_json::from_str::<DynamicSamplingContext>(json).unwrap();
+
+        assert_eq!(Some(Val::F64(0.5)), dsc.get_value("trace.sample_rate"));
+        assert_eq!(Some(Val::Bool(true)), dsc.get_value("trace.sampled"));
+        assert_eq!(Some(Val::String("enterprise")), dsc.get_value("trace.tier"));
+        assert_eq!(Some(Val::I64(7)), dsc.get_value("trace.shard"));
+        assert_eq!(Some(Val::Bool(false)), dsc.get_value("trace.beta"));
+        assert_eq!(None, dsc.get_value("trace.nested"));
+        assert_eq!(None, dsc.get_value("trace.missing"));
+        assert_eq!(None, dsc.get_value("tier"));
     }
 
     #[test]
@@ -73,6 +73,7 @@
 )]
 #![warn(missing_docs)]
 
+pub mod adaptive;
 pub mod condition;
 pub mod config;
 pub mod dsc;
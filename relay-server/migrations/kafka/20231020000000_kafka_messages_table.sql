@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS kafka_messages (
+  id              INTEGER PRIMARY KEY AUTOINCREMENT,
+  received_at     INTEGER, -- milliseconds since epoch
+  attempts        INTEGER,
+  topic           TEXT, -- the logical topic, e.g. "transactions"
+  topic_name      TEXT, -- the name of the Kafka topic the message was produced to
+  organization_id INTEGER,
+  project_id      INTEGER,
+  category        TEXT,
+  message_key     BLOB,
+  headers         TEXT, -- JSON object
+  variant         TEXT,
+  payload         BLOB
+);
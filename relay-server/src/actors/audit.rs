@@ -0,0 +1,280 @@
+//! Append-only audit log of configuration changes.
+//!
+//! Services report changes to the effective configuration with [`AuditEvent`] messages. If an
+//! audit log file or webhook is configured, each event is written as one JSON line to the file
+//! and sent to the webhook. Events list the paths of changed fields, but never their values.
+
+use std::fs::OpenOptions;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use relay_base_schema::project::ProjectKey;
+use relay_config::Config;
+use relay_system::{FromMessage, Interface, NoResponse, Service};
+use serde::Serialize;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+
+/// The maximum number of changed paths listed in a single audit event.
+const MAX_CHANGES: usize = 100;
+
+/// The maximum number of concurrent requests to the audit webhook.
+///
+/// Further events are not sent to the webhook while this many requests are pending, but are still
+/// written to the audit log file.
+const MAX_WEBHOOK_REQUESTS: usize = 10;
+
+/// The configuration that has changed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditSubject {
+    /// The global config shared by all projects.
+    GlobalConfig,
+    /// The config of a project, excluding its quotas.
+    ProjectConfig,
+    /// Quotas of a project or static quotas of Relay.
+    Quotas,
+    /// The credentials Relay uses to authenticate with the upstream.
+    Credentials,
+    /// Relay's own configuration.
+    RelayConfig,
+}
+
+/// The origin of a configuration change.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditSource {
+    /// Fetched from the upstream.
+    Upstream,
+    /// Fetched from the Redis project config cache.
+    Redis,
+    /// Loaded from a file in the config folder.
+    File,
+    /// Changed through the admin API.
+    AdminApi,
+    /// Fetched from the external secrets provider.
+    SecretsProvider,
+}
+
+/// Records a change to the effective configuration in the audit log.
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditEvent {
+    /// The time at which the change was applied.
+    pub timestamp: DateTime<Utc>,
+    /// The configuration that has changed.
+    pub subject: AuditSubject,
+    /// The origin of the change.
+    pub source: AuditSource,
+    /// The project whose config has changed, if the change applies to a single project.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_key: Option<ProjectKey>,
+    /// Paths of the fields that have changed.
+    pub changes: Vec<String>,
+    /// `true` if the list of changes has been truncated.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl AuditEvent {
+    /// Creates a new event for the given changed paths.
+    pub fn new(subject: AuditSubject, source: AuditSource, mut changes: Vec<String>) -> Self {
+        let truncated = changes.len() > MAX_CHANGES;
+        changes.truncate(MAX_CHANGES);
+
+        Self {
+            timestamp: Utc::now(),
+            subject,
+            source,
+            project_key: None,
+            changes,
+            truncated,
+        }
+    }
+
+    /// Sets the project the change applies to.
+    pub fn project_key(mut self, project_key: ProjectKey) -> Self {
+        self.project_key = Some(project_key);
+        self
+    }
+}
+
+/// Service interface for the [`AuditEvent`] message.
+#[derive(Debug)]
+pub struct AuditLog(AuditEvent);
+
+impl Interface for AuditLog {}
+
+impl FromMessage<AuditEvent> for AuditLog {
+    type Response = NoResponse;
+
+    fn from_message(message: AuditEvent, _: ()) -> Self {
+        Self(message)
+    }
+}
+
+/// Service implementing the [`AuditLog`] interface.
+///
+/// Without a configured file or webhook, events are dropped.
+#[derive(Debug)]
+pub struct AuditLogService {
+    config: Arc<Config>,
+    file: Option<File>,
+    client: reqwest::Client,
+    webhook_permits: Arc<Semaphore>,
+}
+
+impl AuditLogService {
+    /// Creates a new audit log service and opens the audit log file.
+    pub fn new(config: Arc<Config>) -> anyhow::Result<Self> {
+        // Open the file synchronously to report errors on startup.
+        let file = match config.audit_log_path() {
+            Some(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                Some(File::from_std(file))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            config,
+            file,
+            client: reqwest::Client::new(),
+            webhook_permits: Arc::new(Semaphore::new(MAX_WEBHOOK_REQUESTS)),
+        })
+    }
+
+    async fn handle_event(&mut self, event: AuditEvent) {
+        if !self.config.audit_enabled() {
+            return;
+        }
+
+        let mut line = match serde_json::to_vec(&event) {
+            Ok(line) => line,
+            Err(error) => {
+                relay_log::error!(
+                    error = &error as &dyn std::error::Error,
+                    "failed to serialize audit event"
+                );
+                return;
+            }
+        };
+
+        if let Some(url) = self.config.audit_webhook_url() {
+            match self.webhook_permits.clone().try_acquire_owned() {
+                Ok(permit) => {
+                    let request = self
+                        .client
+                        .post(url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .body(line.clone())
+                        .send();
+
+                    tokio::spawn(async move {
+                        let result = request.await.and_then(|r| r.error_for_status());
+                        if let Err(error) = result {
+                            relay_log::error!(
+                                error = &error as &dyn std::error::Error,
+                                "failed to send audit event to webhook"
+                            );
+                        }
+                        drop(permit);
+                    });
+                }
+                Err(_) => {
+                    relay_log::error!("dropped audit event for webhook, too many pending requests");
+                }
+            }
+        }
+
+        if let Some(ref mut file) = self.file {
+            line.push(b'\n');
+            let result = match file.write_all(&line).await {
+                Ok(()) => file.flush().await,
+                Err(error) => Err(error),
+            };
+
+            if let Err(error) = result {
+                relay_log::error!(
+                    error = &error as &dyn std::error::Error,
+                    "failed to write audit log"
+                );
+            }
+        }
+    }
+}
+
+impl Service for AuditLogService {
+    type Interface = AuditLog;
+
+    fn spawn_handler(mut self, mut rx: relay_system::Receiver<Self::Interface>) {
+        tokio::spawn(async move {
+            while let Some(AuditLog(event)) = rx.recv().await {
+                self.handle_event(event).await;
+            }
+        });
+    }
+}
+
+/// Returns the paths of all fields that differ between two serializable values.
+///
+/// See [`relay_config::changed_paths`] for the format of the paths.
+pub fn diff<T: Serialize>(old: &T, new: &T) -> Vec<String> {
+    match (serde_json::to_value(old), serde_json::to_value(new)) {
+        (Ok(old), Ok(new)) => relay_config::changed_paths(&old, &new),
+        _ => Vec::new(),
+    }
+}
+
+/// Splits changed paths of a project config into quota changes and other changes.
+///
+/// Timestamps of the last change are ignored, since they change with every update.
+pub fn split_project_changes(changes: Vec<String>) -> (Vec<String>, Vec<String>) {
+    changes
+        .into_iter()
+        .filter(|path| path != "lastChange")
+        .partition(|path| path == "config.quotas" || path.starts_with("config.quotas."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_project_changes() {
+        let changes = vec![
+            "config.piiConfig".to_owned(),
+            "config.quotas".to_owned(),
+            "lastChange".to_owned(),
+        ];
+
+        let (quotas, other) = split_project_changes(changes);
+        assert_eq!(quotas, vec!["config.quotas"]);
+        assert_eq!(other, vec!["config.piiConfig"]);
+    }
+
+    #[test]
+    fn test_event_truncated() {
+        let changes = (0..MAX_CHANGES + 1).map(|i| i.to_string()).collect();
+        let event = AuditEvent::new(AuditSubject::GlobalConfig, AuditSource::Upstream, changes);
+        assert_eq!(event.changes.len(), MAX_CHANGES);
+        assert!(event.truncated);
+    }
+
+    #[test]
+    fn test_serialize_event() {
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let event = AuditEvent::new(
+            AuditSubject::Quotas,
+            AuditSource::Redis,
+            vec!["config.quotas".to_owned()],
+        )
+        .project_key(project_key);
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["subject"], "quotas");
+        assert_eq!(json["source"], "redis");
+        assert_eq!(json["project_key"], "a94ae32be2584e0bbd7a4cbb95971fee");
+        assert!(json.get("truncated").is_none());
+    }
+}
@@ -0,0 +1,212 @@
+//! Reloads the static configuration while Relay is running.
+//!
+//! The [`ConfigReloadService`] reloads the config file when Relay receives `SIGHUP`, or when the
+//! modification time of the config file changes. Only the reload-safe parts of the configuration
+//! are applied, see [`Config::reload`]. All other changes are logged and require a restart.
+//!
+//! Additionally, `SIGUSR2` cycles the log level towards more verbose output, wrapping around to the
+//! configured level after `TRACE`. This allows to debug a running instance without a restart. The
+//! level is reset with the next config reload.
+
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use relay_config::Config;
+use relay_log::Level;
+use relay_system::{Addr, Service};
+
+use crate::actors::audit::{AuditEvent, AuditLog, AuditSource, AuditSubject};
+use crate::statsd::RelayCounters;
+
+/// The interval in which the config file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Returns the modification time of the given file, if it exists.
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Returns the log level selected by `SIGUSR2` after `current`.
+///
+/// Levels become more verbose until `TRACE`, after which the `configured` level is restored.
+fn next_log_level(current: Level, configured: Level) -> Level {
+    match current {
+        Level::ERROR => Level::WARN,
+        Level::WARN => Level::INFO,
+        Level::INFO => Level::DEBUG,
+        Level::DEBUG => Level::TRACE,
+        _ => configured,
+    }
+}
+
+/// Creates a stream of the given unix signal, or `None` if listening fails.
+#[cfg(unix)]
+fn unix_signal(kind: tokio::signal::unix::SignalKind) -> Option<tokio::signal::unix::Signal> {
+    match tokio::signal::unix::signal(kind) {
+        Ok(signal) => Some(signal),
+        Err(error) => {
+            relay_log::error!(error = &error as &dyn Error, "failed to listen for signal");
+            None
+        }
+    }
+}
+
+/// Service that reloads the configuration on `SIGHUP` or when the config file changes.
+#[derive(Debug)]
+pub struct ConfigReloadService {
+    config: Arc<Config>,
+    audit_log: Addr<AuditLog>,
+}
+
+impl ConfigReloadService {
+    /// Creates a new instance of the config reload service.
+    ///
+    /// The service does not run. To run the service, use [`start`](Self::start).
+    pub fn new(config: Arc<Config>, audit_log: Addr<AuditLog>) -> Self {
+        Self { config, audit_log }
+    }
+
+    /// Reloads the configuration and logs the result.
+    fn reload(&self) {
+        let report = match self.config.reload() {
+            Ok(report) => report,
+            Err(error) => {
+                relay_log::error!(
+                    error = error.as_ref() as &dyn Error,
+                    "failed to reload config"
+                );
+                relay_statsd::metric!(counter(RelayCounters::ConfigReload) += 1, result = "error");
+                return;
+            }
+        };
+
+        relay_log::reconfigure(&self.config.effective_logging());
+        relay_statsd::metric!(counter(RelayCounters::ConfigReload) += 1, result = "ok");
+
+        if report.is_empty() {
+            relay_log::info!("reloaded config, no changes");
+            return;
+        }
+
+        if !report.applied.is_empty() {
+            relay_log::info!("reloaded config, applied: {}", report.applied.join(", "));
+
+            let (quotas, other): (Vec<_>, Vec<_>) = report
+                .applied
+                .iter()
+                .cloned()
+                .partition(|path| path.starts_with("rate_limiting.quotas"));
+
+            for (subject, changes) in [
+                (AuditSubject::RelayConfig, other),
+                (AuditSubject::Quotas, quotas),
+            ] {
+                if !changes.is_empty() {
+                    self.audit_log
+                        .send(AuditEvent::new(subject, AuditSource::File, changes));
+                }
+            }
+        }
+
+        if !report.restart_required.is_empty() {
+            relay_log::warn!(
+                "config changes require a restart: {}",
+                report.restart_required.join(", ")
+            );
+        }
+    }
+}
+
+impl Service for ConfigReloadService {
+    type Interface = ();
+
+    fn spawn_handler(self, _rx: relay_system::Receiver<Self::Interface>) {
+        tokio::spawn(async move {
+            let path = self.config.config_file_path();
+            let mut last_modified = modified_at(&path);
+            let mut log_level = self.config.log_level();
+
+            #[cfg(unix)]
+            let (mut sighup, mut sigusr2) = {
+                use tokio::signal::unix::SignalKind;
+                (
+                    unix_signal(SignalKind::hangup()),
+                    unix_signal(SignalKind::user_defined2()),
+                )
+            };
+
+            let mut ticker = tokio::time::interval(POLL_INTERVAL);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                #[cfg(unix)]
+                let sighup_received = async {
+                    match sighup.as_mut() {
+                        Some(sighup) => sighup.recv().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                #[cfg(not(unix))]
+                let sighup_received = std::future::pending::<Option<()>>();
+
+                #[cfg(unix)]
+                let sigusr2_received = async {
+                    match sigusr2.as_mut() {
+                        Some(sigusr2) => sigusr2.recv().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                #[cfg(not(unix))]
+                let sigusr2_received = std::future::pending::<Option<()>>();
+
+                tokio::select! {
+                    biased;
+
+                    Some(()) = sighup_received => {
+                        relay_log::info!("SIGHUP received, reloading config");
+                        last_modified = modified_at(&path);
+                        self.reload();
+                        log_level = self.config.log_level();
+                    }
+                    Some(()) = sigusr2_received => {
+                        log_level = next_log_level(log_level, self.config.log_level());
+                        relay_log::set_level(log_level);
+                        relay_log::warn!("SIGUSR2 received, log level changed to {log_level}");
+                    }
+                    _ = ticker.tick() => {
+                        let modified = modified_at(&path);
+                        if modified.is_some() && modified != last_modified {
+                            relay_log::info!("config file changed, reloading config");
+                            last_modified = modified;
+                            self.reload();
+                            log_level = self.config.log_level();
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_log_level() {
+        let mut level = Level::INFO;
+        let mut levels = Vec::new();
+        for _ in 0..4 {
+            level = next_log_level(level, Level::INFO);
+            levels.push(level);
+        }
+
+        assert_eq!(
+            levels,
+            [Level::DEBUG, Level::TRACE, Level::INFO, Level::DEBUG]
+        );
+        assert_eq!(next_log_level(Level::ERROR, Level::INFO), Level::WARN);
+    }
+}
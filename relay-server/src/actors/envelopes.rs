@@ -3,25 +3,32 @@ use std::error::Error;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
-use chrono::Utc;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use relay_base_schema::project::ProjectKey;
 use relay_config::{Config, HttpEncoding};
 use relay_event_schema::protocol::ClientReport;
-use relay_metrics::{Aggregator, Bucket, MergeBuckets};
+use relay_log::{Hub, Instrument, SentryFutureExt};
+use relay_metrics::{Aggregator, Bucket, MergeBuckets, MetricMeta};
 use relay_quotas::Scoping;
 use relay_statsd::metric;
 use relay_system::{Addr, FromMessage, NoResponse};
 use tokio::sync::oneshot;
+use tokio::time::MissedTickBehavior;
 
 use crate::actors::outcome::{DiscardReason, Outcome};
 use crate::actors::processor::{EncodeEnvelope, EnvelopeProcessor};
 use crate::actors::project_cache::{ProjectCache, UpdateRateLimits};
+use crate::actors::spooler::forward::{ForwardSpool, SpooledEnvelope};
+use crate::actors::spooler::BufferError;
 #[cfg(feature = "processing")]
 use crate::actors::store::{Store, StoreEnvelope, StoreError};
-use crate::actors::test_store::{Capture, TestStore};
+use crate::actors::test_store::{Capture, TestStore, TrackResult};
 use crate::actors::upstream::{
-    Method, SendRequest, UpstreamRelay, UpstreamRequest, UpstreamRequestError,
+    IsNetworkOutage, Method, SendRequest, UpstreamEncodings, UpstreamRelay, UpstreamRequest,
+    UpstreamRequestError,
 };
 use crate::envelope::{self, ContentType, Envelope, EnvelopeError, Item, ItemType};
 use crate::extractors::{PartialDsn, RequestMeta};
@@ -45,6 +52,8 @@ pub enum SendEnvelopeError {
     BodyEncodingFailed(#[source] std::io::Error),
     #[error("could not send request to upstream")]
     UpstreamRequestFailed(#[from] UpstreamRequestError),
+    #[error("could not spool envelope")]
+    SpoolFailed(#[from] BufferError),
 }
 
 #[cfg(feature = "processing")]
@@ -57,7 +66,7 @@ impl From<relay_system::SendError> for SendEnvelopeError {
 /// An upstream request that submits an envelope via HTTP.
 #[derive(Debug)]
 pub struct SendEnvelope {
-    pub envelope_body: Vec<u8>,
+    pub envelope_body: Bytes,
     pub envelope_meta: RequestMeta,
     pub project_cache: Addr<ProjectCache>,
     pub scoping: Scoping,
@@ -65,6 +74,9 @@ pub struct SendEnvelope {
     pub response_sender: oneshot::Sender<Result<(), SendEnvelopeError>>,
     pub project_key: ProjectKey,
     partition_key: Option<String>,
+    retry: bool,
+    upstream_route: Option<usize>,
+    outcomes: bool,
 }
 
 impl UpstreamRequest for SendEnvelope {
@@ -76,6 +88,22 @@ impl UpstreamRequest for SendEnvelope {
         format!("/api/{}/envelope/", self.scoping.project_id).into()
     }
 
+    fn stateless(&self) -> bool {
+        true
+    }
+
+    fn upstream_route(&self) -> Option<usize> {
+        self.upstream_route
+    }
+
+    fn outcomes(&self) -> bool {
+        self.outcomes
+    }
+
+    fn retry(&self) -> bool {
+        self.retry
+    }
+
     fn route(&self) -> &'static str {
         "envelope"
     }
@@ -93,7 +121,7 @@ impl UpstreamRequest for SendEnvelope {
             .header("X-Forwarded-For", meta.forwarded_for())
             .header("Content-Type", envelope::CONTENT_TYPE)
             .header_opt("X-Sentry-Relay-Shard", self.partition_key.as_ref())
-            .body(envelope_body)
+            .body(envelope_body.clone())
     }
 
     fn respond(
@@ -151,12 +179,22 @@ pub struct SendMetrics {
     pub partition_key: Option<u64>,
 }
 
+/// Sends deduplicated metric metadata to the upstream or Kafka.
+#[derive(Debug)]
+pub struct SendMetricMeta {
+    /// The metadata that has not been sent before.
+    pub meta: MetricMeta,
+    /// Scoping information for the metadata.
+    pub scoping: Scoping,
+}
+
 /// Dispatch service for generating and submitting Envelopes.
 #[derive(Debug)]
 pub enum EnvelopeManager {
     SubmitEnvelope(Box<SubmitEnvelope>),
     SendClientReports(SendClientReports),
     SendMetrics(SendMetrics),
+    SendMetricMeta(SendMetricMeta),
 }
 
 impl relay_system::Interface for EnvelopeManager {}
@@ -185,6 +223,14 @@ impl FromMessage<SendMetrics> for EnvelopeManager {
     }
 }
 
+impl FromMessage<SendMetricMeta> for EnvelopeManager {
+    type Response = NoResponse;
+
+    fn from_message(message: SendMetricMeta, _: ()) -> Self {
+        Self::SendMetricMeta(message)
+    }
+}
+
 /// Service implementing the [`EnvelopeManager`] interface.
 ///
 /// This service will produce envelopes to one the following backends:
@@ -193,6 +239,9 @@ impl FromMessage<SendMetrics> for EnvelopeManager {
 ///  2. The in-memory [`TestStore`] if capture mode is enabled. This is meant for integration
 ///     testing and should not be used in production.
 ///  3. The [`UpstreamRelay`] via HTTP by default.
+///
+/// If store-and-forward mode is enabled via `spool.forward.path`, envelopes that cannot be sent to
+/// the upstream are written to a [`ForwardSpool`] and replayed once the upstream is reachable.
 #[derive(Debug)]
 pub struct EnvelopeManagerService {
     config: Arc<Config>,
@@ -201,6 +250,8 @@ pub struct EnvelopeManagerService {
     project_cache: Addr<ProjectCache>,
     test_store: Addr<TestStore>,
     upstream_relay: Addr<UpstreamRelay>,
+    upstream_encodings: Arc<UpstreamEncodings>,
+    forward_spool: Option<ForwardSpool>,
     #[cfg(feature = "processing")]
     store_forwarder: Option<Addr<Store>>,
 }
@@ -214,6 +265,7 @@ impl EnvelopeManagerService {
         project_cache: Addr<ProjectCache>,
         test_store: Addr<TestStore>,
         upstream_relay: Addr<UpstreamRelay>,
+        upstream_encodings: Arc<UpstreamEncodings>,
     ) -> Self {
         Self {
             config,
@@ -222,6 +274,8 @@ impl EnvelopeManagerService {
             project_cache,
             test_store,
             upstream_relay,
+            upstream_encodings,
+            forward_spool: None,
             #[cfg(feature = "processing")]
             store_forwarder: None,
         }
@@ -234,6 +288,9 @@ impl EnvelopeManagerService {
     }
 
     /// Sends an envelope to the upstream or Kafka.
+    ///
+    /// Tracked envelopes are recorded as accepted once the upstream has received them. When
+    /// producing to Kafka, the store records acceptance after producing the messages.
     async fn submit_envelope(
         &self,
         mut envelope: Box<Envelope>,
@@ -254,8 +311,15 @@ impl EnvelopeManagerService {
             }
         }
 
+        let tracked_event_id = TrackResult::is_tracked(&envelope)
+            .then(|| envelope.event_id())
+            .flatten();
+
         // if we are in capture mode, we stash away the event instead of forwarding it.
         if Capture::should_capture(&self.config) {
+            if let Some(event_id) = tracked_event_id {
+                self.test_store.send(TrackResult::accepted(event_id));
+            }
             self.test_store.send(Capture::accepted(envelope));
             return Ok(());
         }
@@ -269,7 +333,13 @@ impl EnvelopeManagerService {
         // possible so that we avoid internal delays.
         envelope.set_sent_at(Utc::now());
 
-        let envelope_body = envelope.to_vec()?;
+        let envelope_body = Bytes::from(envelope.to_vec()?);
+
+        // Envelopes with only client reports are sent to the outcomes upstream, if configured.
+        let outcomes = !envelope.is_empty()
+            && envelope
+                .items()
+                .all(|item| item.ty() == &ItemType::ClientReport);
 
         let (tx, rx) = oneshot::channel();
         let request = SendEnvelope {
@@ -277,10 +347,16 @@ impl EnvelopeManagerService {
             envelope_meta: envelope.meta().clone(),
             project_cache: self.project_cache.clone(),
             scoping,
-            http_encoding: self.config.http_encoding(),
+            http_encoding: self.upstream_encodings.resolve(self.config.http_encoding()),
             response_sender: tx,
             project_key: scoping.project_key,
             partition_key,
+            // Failed requests are written to the forward spool instead of retrying in memory.
+            retry: self.forward_spool.is_none(),
+            upstream_route: self
+                .config
+                .upstream_route(Some(scoping.project_key), Some(scoping.project_id)),
+            outcomes,
         };
 
         if let HttpEncoding::Identity = request.http_encoding {
@@ -290,25 +366,130 @@ impl EnvelopeManagerService {
         }
 
         match rx.await {
-            Ok(Ok(())) => Ok(()),
+            Ok(Ok(())) => {
+                if let Some(event_id) = tracked_event_id {
+                    self.test_store.send(TrackResult::accepted(event_id));
+                }
+                Ok(())
+            }
             Ok(Err(err)) => Err(err),
             Err(_canceled) => Err(UpstreamRequestError::ChannelClosed.into()),
         }
     }
 
+    /// Returns `true` if the upstream service is in a network outage.
+    async fn is_network_outage(&self) -> bool {
+        self.upstream_relay
+            .send(IsNetworkOutage)
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Sends an envelope to the upstream, or writes it to the forward spool if the upstream is
+    /// unreachable.
+    ///
+    /// While the spool has a backlog, new envelopes are appended to the spool to retain their order.
+    async fn store_and_forward(
+        &self,
+        spool: &ForwardSpool,
+        envelope: Box<Envelope>,
+        scoping: Scoping,
+        received_at: DateTime<Utc>,
+    ) -> Result<(), SendEnvelopeError> {
+        let received_at = received_at.timestamp_millis();
+        let envelope_body = envelope.to_vec()?;
+
+        if spool.has_backlog() || self.is_network_outage().await {
+            spool.push(envelope_body, scoping, received_at).await?;
+            return Ok(());
+        }
+
+        match self.submit_envelope(envelope, scoping, None).await {
+            Err(SendEnvelopeError::UpstreamRequestFailed(error)) if !error.is_received() => {
+                relay_log::debug!(
+                    error = &error as &dyn Error,
+                    "failed to send envelope, writing to forward spool"
+                );
+                spool.push(envelope_body, scoping, received_at).await?;
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Replays envelopes from the forward spool in order while the upstream is reachable.
+    ///
+    /// At most `spool.forward.drain_rate` envelopes are sent per second. Envelopes are removed from
+    /// the spool once the upstream has received them.
+    async fn drain_forward_spool(self: Arc<Self>, spool: ForwardSpool) {
+        let drain_rate = self.config.spool_forward_drain_rate();
+        let mut ticker = tokio::time::interval(Duration::from_secs(1) / drain_rate);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            if !spool.has_backlog() || self.is_network_outage().await {
+                continue;
+            }
+
+            let SpooledEnvelope {
+                id,
+                envelope,
+                scoping,
+            } = match spool.peek().await {
+                Ok(Some(spooled)) => spooled,
+                Ok(None) => continue,
+                Err(error) => {
+                    relay_log::error!(
+                        error = &error as &dyn Error,
+                        "failed to read from forward spool"
+                    );
+                    continue;
+                }
+            };
+
+            match self.submit_envelope(envelope, scoping, None).await {
+                Ok(()) => (),
+                // Keep the envelope at the head of the spool and retry on the next tick.
+                Err(SendEnvelopeError::UpstreamRequestFailed(error)) if !error.is_received() => {
+                    continue
+                }
+                Err(error) => relay_log::error!(
+                    error = &error as &dyn Error,
+                    tags.project_key = %scoping.project_key,
+                    "dropping spooled envelope"
+                ),
+            }
+
+            if let Err(error) = spool.remove(id).await {
+                relay_log::error!(
+                    error = &error as &dyn Error,
+                    "failed to remove envelope from forward spool"
+                );
+            }
+        }
+    }
+
     async fn handle_submit(&self, message: SubmitEnvelope) {
         let SubmitEnvelope { mut envelope } = message;
 
         let scoping = envelope.scoping();
-
-        let inner_envelope = envelope.take_envelope();
-        match self.submit_envelope(inner_envelope, scoping, None).await {
-            Ok(_) => {
-                envelope.accept();
-            }
-            Err(SendEnvelopeError::UpstreamRequestFailed(e)) if e.is_received() => {
-                envelope.accept();
+        let received_at = envelope.received_at();
+
+        // Submit a copy so that the envelope can still be rejected with all of its items.
+        let inner_envelope = Box::new(envelope.envelope().clone());
+        let result = match self.forward_spool {
+            Some(ref spool) => {
+                self.store_and_forward(spool, inner_envelope, scoping, received_at)
+                    .await
             }
+            None => self.submit_envelope(inner_envelope, scoping, None).await,
+        };
+
+        match result {
+            Ok(_) => {}
+            Err(SendEnvelopeError::UpstreamRequestFailed(e)) if e.is_received() => {}
             Err(error) => {
                 // Errors are only logged for what we consider an internal discard reason. These
                 // indicate errors in the infrastructure or implementation bugs.
@@ -323,8 +504,11 @@ impl EnvelopeManagerService {
                     },
                 );
                 envelope.reject(Outcome::Invalid(DiscardReason::Internal));
+                return;
             }
         }
+
+        envelope.accept();
     }
 
     async fn handle_send_metrics(&self, message: SendMetrics) {
@@ -361,6 +545,32 @@ impl EnvelopeManagerService {
         }
     }
 
+    async fn handle_send_metric_meta(&self, message: SendMetricMeta) {
+        let SendMetricMeta { meta, scoping } = message;
+
+        let upstream = self.config.upstream_descriptor();
+        let dsn = PartialDsn {
+            scheme: upstream.scheme(),
+            public_key: scoping.project_key,
+            host: upstream.host().to_owned(),
+            port: upstream.port(),
+            path: "".to_owned(),
+            project_id: Some(scoping.project_id),
+        };
+
+        let mut item = Item::new(ItemType::MetricMeta);
+        item.set_payload(ContentType::Json, serde_json::to_vec(&meta).unwrap());
+        let mut envelope = Envelope::from_request(None, RequestMeta::outbound(dsn));
+        envelope.add_item(item);
+
+        if let Err(error) = self.submit_envelope(envelope, scoping, None).await {
+            relay_log::trace!(
+                error = &error as &dyn Error,
+                "failed to submit the envelope for metric meta",
+            );
+        }
+    }
+
     async fn handle_send_client_reports(&self, message: SendClientReports) {
         let SendClientReports {
             client_reports,
@@ -394,8 +604,21 @@ impl EnvelopeManagerService {
 
     async fn handle_message(&self, message: EnvelopeManager) {
         match message {
-            EnvelopeManager::SubmitEnvelope(message) => {
-                self.handle_submit(*message).await;
+            EnvelopeManager::SubmitEnvelope(mut message) => {
+                let span =
+                    relay_log::debug_span!(parent: message.envelope.span(), "envelope.submit");
+
+                // Attach the path of the envelope to internal errors reported while submitting.
+                message.envelope.record_stage("submit");
+                let hub = Hub::new_from_top(Hub::current());
+                hub.configure_scope(|scope| {
+                    scope.add_breadcrumb(message.envelope.breadcrumbs().to_vec())
+                });
+
+                self.handle_submit(*message)
+                    .instrument(span)
+                    .bind_hub(hub)
+                    .await;
             }
             EnvelopeManager::SendClientReports(message) => {
                 self.handle_send_client_reports(message).await;
@@ -403,6 +626,9 @@ impl EnvelopeManagerService {
             EnvelopeManager::SendMetrics(message) => {
                 self.handle_send_metrics(message).await;
             }
+            EnvelopeManager::SendMetricMeta(message) => {
+                self.handle_send_metric_meta(message).await;
+            }
         }
     }
 }
@@ -410,11 +636,24 @@ impl EnvelopeManagerService {
 impl relay_system::Service for EnvelopeManagerService {
     type Interface = EnvelopeManager;
 
-    fn spawn_handler(self, mut rx: relay_system::Receiver<Self::Interface>) {
+    fn spawn_handler(mut self, mut rx: relay_system::Receiver<Self::Interface>) {
         tokio::spawn(async move {
             relay_log::info!("envelope manager started");
 
+            self.forward_spool = match ForwardSpool::open(&self.config).await {
+                Ok(spool) => spool,
+                Err(error) => {
+                    relay_log::error!(error = &error as &dyn Error, "failed to open forward spool");
+                    // NOTE: The process will exit with error if the spool file could not be opened
+                    // or the migrations could not be run.
+                    std::process::exit(1);
+                }
+            };
+
             let service = Arc::new(self);
+            if let Some(spool) = service.forward_spool.clone() {
+                tokio::spawn(Arc::clone(&service).drain_forward_spool(spool));
+            }
             while let Some(message) = rx.recv().await {
                 let service = Arc::clone(&service);
                 tokio::spawn(async move {
@@ -11,7 +11,9 @@
 
 use std::borrow::Cow;
 use std::sync::Arc;
+use std::time::Duration;
 
+use rand::Rng;
 use relay_config::Config;
 use relay_config::RelayMode;
 use relay_dynamic_config::GlobalConfig;
@@ -21,6 +23,7 @@ use reqwest::Method;
 use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, watch};
 
+use crate::actors::audit::{self, AuditEvent, AuditLog, AuditSource, AuditSubject};
 use crate::actors::upstream::{
     RequestPriority, SendQuery, UpstreamQuery, UpstreamRelay, UpstreamRequestError,
 };
@@ -38,6 +41,13 @@ type UpstreamQueryResult =
 struct GetGlobalConfigResponse {
     #[serde(default)]
     global: Option<GlobalConfig>,
+    /// Number of seconds the upstream asks to wait before the next fetch.
+    #[serde(
+        default,
+        alias = "next_fetch_after",
+        skip_serializing_if = "Option::is_none"
+    )]
+    next_fetch_after: Option<u64>,
 }
 
 /// The request to fetch a global config from upstream.
@@ -145,26 +155,36 @@ pub struct GlobalConfigService {
     internal_rx: mpsc::Receiver<UpstreamQueryResult>,
     /// Upstream service to request global configs from.
     upstream: Addr<UpstreamRelay>,
+    /// Service to record changes of the global config.
+    audit_log: Addr<AuditLog>,
     /// Handle to avoid multiple outgoing requests.
     fetch_handle: SleepHandle,
+    /// The current interval between fetches, before jitter is applied.
+    fetch_interval: Duration,
     /// Disables the upstream fetch loop.
     shutdown: bool,
 }
 
 impl GlobalConfigService {
     /// Creates a new [`GlobalConfigService`].
-    pub fn new(config: Arc<Config>, upstream: Addr<UpstreamRelay>) -> Self {
+    pub fn new(
+        config: Arc<Config>,
+        upstream: Addr<UpstreamRelay>,
+        audit_log: Addr<AuditLog>,
+    ) -> Self {
         let (internal_tx, internal_rx) = mpsc::channel(1);
         let (global_config_watch, _) = watch::channel(Arc::default());
 
         Self {
-            config,
             global_config_watch,
             internal_tx,
             internal_rx,
             upstream,
+            audit_log,
+            fetch_interval: config.global_config_fetch_interval(),
             fetch_handle: SleepHandle::idle(),
             shutdown: false,
+            config,
         }
     }
 
@@ -181,10 +201,14 @@ impl GlobalConfigService {
     }
 
     /// Schedules the next global config request.
+    ///
+    /// The current fetch interval is randomized by the configured jitter to spread requests of
+    /// many Relays over time.
     fn schedule_fetch(&mut self) {
         if !self.shutdown && self.fetch_handle.is_idle() {
-            self.fetch_handle
-                .set(self.config.global_config_fetch_interval());
+            let jitter = self.config.global_config_fetch_jitter();
+            let factor = rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter);
+            self.fetch_handle.set(self.fetch_interval.mul_f64(factor));
         }
     }
 
@@ -218,11 +242,19 @@ impl GlobalConfigService {
     /// 2. If the request was successful, it then checks whether the returned
     /// global config is valid and contains the expected data.
     fn handle_result(&mut self, result: UpstreamQueryResult) {
+        let mut outcome = FetchOutcome::Failed;
+
         match result {
             Ok(Ok(config)) => {
                 let mut success = false;
                 match config.global {
                     Some(global_config) => {
+                        self.audit_change(&global_config);
+                        outcome = if **self.global_config_watch.borrow() == global_config {
+                            FetchOutcome::Unchanged
+                        } else {
+                            FetchOutcome::Changed
+                        };
                         // Notifying subscribers only fails when there are no
                         // subscribers.
                         self.global_config_watch.send(Arc::new(global_config)).ok();
@@ -234,6 +266,10 @@ impl GlobalConfigService {
                     counter(RelayCounters::GlobalConfigFetched) += 1,
                     success = if success { "true" } else { "false" },
                 );
+
+                if let (true, Some(secs)) = (success, config.next_fetch_after) {
+                    outcome = FetchOutcome::Hint(Duration::from_secs(secs));
+                }
             }
             Ok(Err(e)) => relay_log::error!(
                 error = &e as &dyn std::error::Error,
@@ -245,16 +281,66 @@ impl GlobalConfigService {
             ),
         }
 
+        self.fetch_interval = next_fetch_interval(&self.config, self.fetch_interval, outcome);
+
         // Enable upstream requests timer for global configs.
         self.schedule_fetch();
     }
 
+    /// Records changes of the global config fetched from upstream in the audit log.
+    fn audit_change(&self, global_config: &GlobalConfig) {
+        if !self.config.audit_enabled() {
+            return;
+        }
+
+        let current = self.global_config_watch.borrow().clone();
+        let changes = audit::diff(current.as_ref(), global_config);
+        if !changes.is_empty() {
+            self.audit_log.send(AuditEvent::new(
+                AuditSubject::GlobalConfig,
+                AuditSource::Upstream,
+                changes,
+            ));
+        }
+    }
+
     fn handle_shutdown(&mut self) {
         self.shutdown = true;
         self.fetch_handle.reset();
     }
 }
 
+/// The outcome of a global config fetch, used to adapt the fetch interval.
+#[derive(Clone, Copy, Debug)]
+enum FetchOutcome {
+    /// The request failed or the response did not contain a global config.
+    Failed,
+    /// The upstream returned a different global config than the current one.
+    Changed,
+    /// The upstream returned the same global config as the current one.
+    Unchanged,
+    /// The upstream asked to wait for the given duration before fetching again.
+    Hint(Duration),
+}
+
+/// Computes the interval until the next global config fetch.
+///
+/// Relay polls at the base interval after errors and changes, and doubles the interval while the
+/// global config is stable. Hints from the upstream take precedence. The result is always between
+/// the base and the maximum fetch interval.
+fn next_fetch_interval(config: &Config, current: Duration, outcome: FetchOutcome) -> Duration {
+    let base = config.global_config_fetch_interval();
+    let max = config.global_config_max_fetch_interval();
+
+    let interval = match outcome {
+        FetchOutcome::Failed | FetchOutcome::Changed => base,
+        FetchOutcome::Unchanged => current.saturating_mul(2),
+        FetchOutcome::Hint(hint) => hint,
+    };
+
+    interval.clamp(base, max)
+}
+
 impl Service for GlobalConfigService {
     type Interface = GlobalConfigManager;
 
@@ -309,10 +395,12 @@ mod tests {
     use std::time::Duration;
 
     use relay_config::{Config, RelayMode};
-    use relay_system::{Controller, Service, ShutdownMode};
+    use relay_system::{Addr, Controller, Service, ShutdownMode};
     use relay_test::mock_service;
 
-    use crate::actors::global_config::{Get, GlobalConfigService};
+    use crate::actors::global_config::{
+        next_fetch_interval, FetchOutcome, Get, GlobalConfigService,
+    };
 
     /// Tests that the service can still handle requests after sending a
     /// shutdown signal.
@@ -334,7 +422,8 @@ mod tests {
         config.regenerate_credentials(false).unwrap();
         let fetch_interval = config.global_config_fetch_interval();
 
-        let service = GlobalConfigService::new(Arc::new(config), upstream).start();
+        let service =
+            GlobalConfigService::new(Arc::new(config), upstream, Addr::custom().0).start();
 
         assert!(service.send(Get).await.is_ok());
 
@@ -363,13 +452,36 @@ mod tests {
         config.regenerate_credentials(false).unwrap();
 
         let fetch_interval = config.global_config_fetch_interval();
-        let service = GlobalConfigService::new(Arc::new(config), upstream).start();
+        let service =
+            GlobalConfigService::new(Arc::new(config), upstream, Addr::custom().0).start();
         service.send(Get).await.unwrap();
 
         tokio::time::sleep(fetch_interval * 2).await;
         handle.await.unwrap();
     }
 
+    #[test]
+    fn test_next_fetch_interval() {
+        let config = Config::from_json_value(serde_json::json!({
+            "cache": {
+                "global_config_fetch_interval": 10,
+                "global_config_max_fetch_interval": 60,
+            }
+        }))
+        .unwrap();
+
+        let secs = Duration::from_secs;
+        let next = |current, outcome| next_fetch_interval(&config, current, outcome);
+
+        assert_eq!(next(secs(10), FetchOutcome::Unchanged), secs(20));
+        assert_eq!(next(secs(40), FetchOutcome::Unchanged), secs(60));
+        assert_eq!(next(secs(60), FetchOutcome::Changed), secs(10));
+        assert_eq!(next(secs(60), FetchOutcome::Failed), secs(10));
+        assert_eq!(next(secs(10), FetchOutcome::Hint(secs(30))), secs(30));
+        assert_eq!(next(secs(10), FetchOutcome::Hint(secs(1))), secs(10));
+        assert_eq!(next(secs(10), FetchOutcome::Hint(secs(600))), secs(60));
+    }
+
     #[tokio::test]
     async fn proxy_relay_does_not_make_upstream_request() {
         relay_test::setup();
@@ -388,7 +500,8 @@ mod tests {
 
         let fetch_interval = config.global_config_fetch_interval();
 
-        let service = GlobalConfigService::new(Arc::new(config), upstream).start();
+        let service =
+            GlobalConfigService::new(Arc::new(config), upstream, Addr::custom().0).start();
         service.send(Get).await.unwrap();
 
         tokio::time::sleep(fetch_interval * 2).await;
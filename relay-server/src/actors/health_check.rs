@@ -2,13 +2,18 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use relay_config::{Config, RelayMode};
-use relay_metrics::{AcceptsMetrics, Aggregator};
+use relay_metrics::{AcceptsMetrics, Aggregator, BucketCountInquiry};
+use relay_redis::RedisPool;
 use relay_statsd::metric;
 use relay_system::{Addr, AsyncResponse, Controller, FromMessage, Interface, Sender, Service};
+use serde::Serialize;
 
 use crate::actors::project_cache::{ProjectCache, SpoolHealth};
+#[cfg(feature = "processing")]
+use crate::actors::store::{IsKafkaConnected, Store};
 use crate::actors::upstream::{IsAuthenticated, IsNetworkOutage, UpstreamRelay};
 use crate::statsd::RelayGauges;
+use crate::utils::MemoryStat;
 
 /// Checks whether Relay is alive and healthy based on its variant.
 #[derive(Clone, Copy, Debug, serde::Deserialize)]
@@ -22,8 +27,71 @@ pub enum IsHealthy {
     Readiness,
 }
 
-/// Service interface for the [`IsHealthy`] message.
-pub struct HealthCheck(IsHealthy, Sender<bool>);
+/// Requests a detailed report on the health of Relay's components.
+#[derive(Clone, Copy, Debug)]
+pub struct HealthDetail;
+
+/// Machine-readable health of Relay's components, returned for [`HealthDetail`].
+///
+/// Checks that do not apply to this Relay's configuration are omitted.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct HealthReport {
+    /// Whether the Relay is in a state to accept traffic, see [`IsHealthy::Readiness`].
+    pub is_healthy: bool,
+    /// `true` if Relay has started shutting down.
+    pub is_shutting_down: bool,
+    /// The number of buckets across all metrics aggregators.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregator_buckets: Option<usize>,
+    /// `false` if the aggregators have exceeded their limits and reject metrics.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accepts_metrics: Option<bool>,
+    /// `false` if the envelope spool is full.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spool_healthy: Option<bool>,
+    /// System memory usage compared to the configured watermark.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryReport>,
+    /// Connectivity to the upstream, omitted unless Relay is in managed mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<UpstreamReport>,
+    /// Connectivity to Redis, omitted unless processing with Redis is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redis_connected: Option<bool>,
+    /// Connectivity to the Kafka brokers, omitted unless processing is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kafka_connected: Option<bool>,
+}
+
+/// System memory usage reported in the [`HealthReport`].
+#[derive(Clone, Debug, Serialize)]
+pub struct MemoryReport {
+    /// Memory in use in bytes.
+    pub used: u64,
+    /// Total system memory in bytes.
+    pub total: u64,
+    /// The fraction of memory in use.
+    pub used_percent: f32,
+    /// The configured fraction of memory considered high usage, see `health.max_memory_percent`.
+    pub max_percent: f32,
+    /// `true` if memory usage exceeds the configured watermark.
+    pub over_watermark: bool,
+}
+
+/// Connectivity to the upstream reported in the [`HealthReport`].
+#[derive(Clone, Debug, Serialize)]
+pub struct UpstreamReport {
+    /// Whether Relay has authenticated with the upstream.
+    pub authenticated: bool,
+    /// Whether Relay is currently unable to reach the upstream.
+    pub network_outage: bool,
+}
+
+/// Service interface for the [`IsHealthy`] and [`HealthDetail`] messages.
+pub enum HealthCheck {
+    IsHealthy(IsHealthy, Sender<bool>),
+    HealthDetail(HealthDetail, Sender<HealthReport>),
+}
 
 impl Interface for HealthCheck {}
 
@@ -31,7 +99,15 @@ impl FromMessage<IsHealthy> for HealthCheck {
     type Response = AsyncResponse<bool>;
 
     fn from_message(message: IsHealthy, sender: Sender<bool>) -> Self {
-        Self(message, sender)
+        Self::IsHealthy(message, sender)
+    }
+}
+
+impl FromMessage<HealthDetail> for HealthCheck {
+    type Response = AsyncResponse<HealthReport>;
+
+    fn from_message(message: HealthDetail, sender: Sender<HealthReport>) -> Self {
+        Self::HealthDetail(message, sender)
     }
 }
 
@@ -43,6 +119,10 @@ pub struct HealthCheckService {
     aggregator: Addr<Aggregator>,
     upstream_relay: Addr<UpstreamRelay>,
     project_cache: Addr<ProjectCache>,
+    #[cfg_attr(not(feature = "processing"), allow(dead_code))]
+    redis_pool: Option<RedisPool>,
+    #[cfg(feature = "processing")]
+    store_forwarder: Option<Addr<Store>>,
 }
 
 impl HealthCheckService {
@@ -54,6 +134,7 @@ impl HealthCheckService {
         aggregator: Addr<Aggregator>,
         upstream_relay: Addr<UpstreamRelay>,
         project_cache: Addr<ProjectCache>,
+        redis_pool: Option<RedisPool>,
     ) -> Self {
         HealthCheckService {
             is_shutting_down: AtomicBool::new(false),
@@ -61,9 +142,18 @@ impl HealthCheckService {
             aggregator,
             upstream_relay,
             project_cache,
+            redis_pool,
+            #[cfg(feature = "processing")]
+            store_forwarder: None,
         }
     }
 
+    /// Configures the store service to check connectivity to Kafka.
+    #[cfg(feature = "processing")]
+    pub fn set_store_forwarder(&mut self, store_forwarder: Addr<Store>) {
+        self.store_forwarder = Some(store_forwarder);
+    }
+
     async fn handle_is_healthy(&self, message: IsHealthy) -> bool {
         let upstream = self.upstream_relay.clone();
 
@@ -106,10 +196,107 @@ impl HealthCheckService {
         }
     }
 
+    async fn handle_health_detail(&self) -> HealthReport {
+        let is_managed = self.config.relay_mode() == RelayMode::Managed;
+        let upstream = if is_managed {
+            Some(UpstreamReport {
+                authenticated: self
+                    .upstream_relay
+                    .send(IsAuthenticated)
+                    .await
+                    .unwrap_or(false),
+                network_outage: self
+                    .upstream_relay
+                    .send(IsNetworkOutage)
+                    .await
+                    .unwrap_or(true),
+            })
+        } else {
+            None
+        };
+
+        let max_percent = self.config.health_max_memory_percent();
+        let memory = MemoryStat::current().map(|stat| MemoryReport {
+            used: stat.used,
+            total: stat.total,
+            used_percent: stat.used_percent(),
+            max_percent,
+            over_watermark: stat.used_percent() > max_percent,
+        });
+
+        HealthReport {
+            is_healthy: self.handle_is_healthy(IsHealthy::Readiness).await,
+            is_shutting_down: self.is_shutting_down.load(Ordering::Relaxed),
+            aggregator_buckets: self.aggregator.send(BucketCountInquiry).await.ok(),
+            accepts_metrics: self.aggregator.send(AcceptsMetrics).await.ok(),
+            spool_healthy: self.project_cache.send(SpoolHealth).await.ok(),
+            memory,
+            upstream,
+            redis_connected: self.check_redis().await,
+            kafka_connected: self.check_kafka().await,
+        }
+    }
+
+    #[cfg(feature = "redis-sync")]
+    async fn check_redis(&self) -> Option<bool> {
+        let pool = self.redis_pool.clone()?;
+
+        // The Redis client is synchronous, so the check must not block the runtime.
+        let result = tokio::task::spawn_blocking(move || {
+            let mut client = pool.client()?;
+            relay_redis::redis::cmd("PING")
+                .query::<()>(&mut client.connection()?)
+                .map_err(relay_redis::RedisError::Redis)
+        })
+        .await;
+
+        Some(matches!(result, Ok(Ok(()))))
+    }
+
+    #[cfg(all(feature = "processing", not(feature = "redis-sync")))]
+    async fn check_redis(&self) -> Option<bool> {
+        let pool = self.redis_pool.as_ref()?;
+
+        let result = async {
+            let mut connection = pool.async_connection().await?;
+            relay_redis::redis::cmd("PING")
+                .query_async::<_, ()>(&mut connection)
+                .await
+                .map_err(relay_redis::RedisError::Redis)
+        }
+        .await;
+
+        Some(result.is_ok())
+    }
+
+    #[cfg(not(feature = "processing"))]
+    async fn check_redis(&self) -> Option<bool> {
+        None
+    }
+
+    #[cfg(feature = "processing")]
+    async fn check_kafka(&self) -> Option<bool> {
+        let store_forwarder = self.store_forwarder.as_ref()?;
+        Some(
+            store_forwarder
+                .send(IsKafkaConnected)
+                .await
+                .unwrap_or(false),
+        )
+    }
+
+    #[cfg(not(feature = "processing"))]
+    async fn check_kafka(&self) -> Option<bool> {
+        None
+    }
+
     async fn handle_message(&self, message: HealthCheck) {
-        let HealthCheck(message, sender) = message;
-        let response = self.handle_is_healthy(message).await;
-        sender.send(response);
+        match message {
+            HealthCheck::IsHealthy(message, sender) => {
+                sender.send(self.handle_is_healthy(message).await)
+            }
+            HealthCheck::HealthDetail(_, sender) => sender.send(self.handle_health_detail().await),
+        }
     }
 }
 
@@ -28,17 +28,23 @@
 //! Controller::run(|| Server::start())
 //!     .expect("failed to start relay");
 //! ```
+pub mod audit;
+pub mod config_reload;
 pub mod envelopes;
 pub mod global_config;
 pub mod health_check;
 pub mod outcome;
 pub mod outcome_aggregator;
+pub mod pii_overrides;
 pub mod processor;
 pub mod project;
 pub mod project_cache;
 pub mod project_local;
+pub mod project_shards;
+pub mod project_snapshot;
 pub mod project_upstream;
 pub mod relays;
+pub mod secrets;
 pub mod server;
 pub mod spooler;
 pub mod test_store;
@@ -47,4 +53,6 @@ pub mod upstream;
 #[cfg(feature = "processing")]
 pub mod project_redis;
 #[cfg(feature = "processing")]
+pub mod sinks;
+#[cfg(feature = "processing")]
 pub mod store;
@@ -16,31 +16,37 @@ use std::{fmt, mem};
 #[cfg(feature = "processing")]
 use anyhow::Context;
 use chrono::{DateTime, SecondsFormat, Utc};
-use relay_base_schema::project::ProjectId;
+use itertools::Itertools;
+use relay_base_schema::project::{ProjectId, ProjectKey};
 use relay_common::time::UnixTimestamp;
-use relay_config::{Config, EmitOutcomes};
+use relay_config::{Config, EmitOutcomes, OutcomeWebhook};
 use relay_event_schema::protocol::{ClientReport, DiscardedEvent, EventId};
 use relay_filter::FilterStatKey;
 #[cfg(feature = "processing")]
-use relay_kafka::{ClientError, KafkaClient, KafkaTopic};
+use relay_kafka::{ClientError, DeliveryMode, KafkaClient, KafkaTopic};
 use relay_quotas::{DataCategory, ReasonCode, Scoping};
 use relay_sampling::evaluation::MatchedRuleIds;
 use relay_statsd::metric;
 use relay_system::{Addr, FromMessage, Interface, NoResponse, Service};
 use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
 
 use crate::actors::envelopes::{EnvelopeManager, SendClientReports};
 use crate::actors::upstream::{Method, SendQuery, UpstreamQuery, UpstreamRelay};
+use crate::http;
 #[cfg(feature = "processing")]
 use crate::service::ServiceError;
 use crate::statsd::RelayCounters;
-use crate::utils::SleepHandle;
+use crate::utils::{RetryBackoff, SleepHandle};
 
 /// Defines the structure of the HTTP outcomes requests
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct SendOutcomes {
     #[serde(default)]
     pub outcomes: Vec<TrackRawOutcome>,
+    /// Index of the route in `relay.routes` that the outcomes are sent to.
+    #[serde(skip)]
+    pub upstream_route: Option<usize>,
 }
 
 impl UpstreamQuery for SendOutcomes {
@@ -58,6 +64,18 @@ impl UpstreamQuery for SendOutcomes {
         true
     }
 
+    fn stateless() -> bool {
+        true
+    }
+
+    fn outcomes() -> bool {
+        true
+    }
+
+    fn upstream_route(&self) -> Option<usize> {
+        self.upstream_route
+    }
+
     fn route(&self) -> &'static str {
         "outcomes"
     }
@@ -353,9 +371,36 @@ pub enum DiscardReason {
     InvalidReplayEventNoPayload,
     InvalidReplayEventPii,
     InvalidReplayRecordingEvent,
+    InvalidReplayVideoEvent,
 
     /// (Relay) Profiling related discard reasons
     Profiling(&'static str),
+
+    /// (Relay) A monitor check-in could not be parsed or normalized.
+    InvalidCheckIn,
+
+    /// (Relay) The monitor config of a check-in has an invalid schedule.
+    InvalidMonitorConfig,
+
+    /// (Relay) A standalone span could not be parsed or scrubbed.
+    InvalidSpan,
+
+    /// (Relay) A log could not be parsed, failed schema validation, or could not be scrubbed.
+    InvalidLog,
+
+    /// (Relay) A log was not stored because the project does not have logs ingestion enabled.
+    ///
+    /// Metrics may still have been extracted from the log.
+    LogsDisabled,
+
+    /// (Relay) A feedback item could not be parsed.
+    InvalidFeedback,
+
+    /// (Relay) A trace attachment does not specify the trace it belongs to.
+    InvalidTraceAttachment,
+
+    /// (Relay) The item type has been disabled at runtime through the admin API.
+    ItemTypeDisabled,
 }
 
 impl DiscardReason {
@@ -397,7 +442,16 @@ impl DiscardReason {
             DiscardReason::InvalidReplayEventNoPayload => "invalid_replay_no_payload",
             DiscardReason::InvalidReplayEventPii => "invalid_replay_pii_scrubber_failed",
             DiscardReason::InvalidReplayRecordingEvent => "invalid_replay_recording",
+            DiscardReason::InvalidReplayVideoEvent => "invalid_replay_video",
             DiscardReason::Profiling(reason) => reason,
+            DiscardReason::InvalidCheckIn => "invalid_check_in",
+            DiscardReason::InvalidMonitorConfig => "invalid_monitor_config",
+            DiscardReason::InvalidSpan => "invalid_span",
+            DiscardReason::InvalidLog => "invalid_log",
+            DiscardReason::LogsDisabled => "logs_disabled",
+            DiscardReason::InvalidFeedback => "invalid_feedback",
+            DiscardReason::InvalidTraceAttachment => "invalid_trace_attachment",
+            DiscardReason::ItemTypeDisabled => "item_type_disabled",
         }
     }
 }
@@ -444,6 +498,11 @@ pub struct TrackRawOutcome {
     /// The number of events or total attachment size in bytes.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub quantity: Option<u32>,
+    /// The public key of the project, if known.
+    ///
+    /// This is not serialized and only used to send the outcome to the upstream of the project.
+    #[serde(skip)]
+    project_key: Option<ProjectKey>,
 }
 
 impl TrackRawOutcome {
@@ -475,6 +534,37 @@ impl TrackRawOutcome {
             source,
             category: msg.category.value(),
             quantity: Some(msg.quantity),
+            project_key: Some(msg.scoping.project_key),
+        }
+    }
+
+    /// Creates an outcome for data that is only attributed to an organization and project.
+    ///
+    /// This is used for messages dropped by the store, which do not retain the full [`Scoping`] of
+    /// their envelope.
+    #[cfg(feature = "processing")]
+    pub fn from_parts(
+        timestamp: DateTime<Utc>,
+        organization_id: u64,
+        project_id: ProjectId,
+        outcome: &Outcome,
+        category: DataCategory,
+        quantity: u32,
+        config: &Config,
+    ) -> Self {
+        TrackRawOutcome {
+            timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+            org_id: Some(organization_id).filter(|id| *id != 0),
+            project_id,
+            key_id: None,
+            outcome: outcome.to_outcome_id(),
+            reason: outcome.to_reason().map(|reason| reason.to_string()),
+            event_id: None,
+            remote_addr: None,
+            source: config.outcome_source().map(str::to_owned),
+            category: category.value(),
+            quantity: Some(quantity),
+            project_key: None,
         }
     }
 
@@ -547,35 +637,238 @@ impl HttpOutcomeProducer {
             );
         }
 
+        // Outcomes are sent to the upstream that the data of their project is routed to.
+        let routes = mem::take(&mut self.unsent_outcomes)
+            .into_iter()
+            .into_group_map_by(|outcome| {
+                self.config
+                    .upstream_route(outcome.project_key, Some(outcome.project_id))
+            });
+
+        for (upstream_route, outcomes) in routes {
+            let request = SendOutcomes {
+                outcomes,
+                upstream_route,
+            };
+
+            let upstream_relay = self.upstream_relay.clone();
+
+            tokio::spawn(async move {
+                match upstream_relay.send(SendQuery(request)).await {
+                    Ok(_) => relay_log::trace!("outcome batch sent"),
+                    Err(error) => {
+                        relay_log::error!(
+                            error = &error as &dyn Error,
+                            "outcome batch sending failed"
+                        )
+                    }
+                }
+            });
+        }
+    }
+
+    fn handle_message(&mut self, message: TrackRawOutcome) {
+        relay_log::trace!("batching outcome");
+        self.unsent_outcomes.push(message);
+
+        if self.unsent_outcomes.len() >= self.config.outcome_batch_size() {
+            self.send_batch();
+        } else if self.flush_handle.is_idle() {
+            self.flush_handle.set(self.config.outcome_batch_interval());
+        }
+    }
+}
+
+impl Service for HttpOutcomeProducer {
+    type Interface = TrackRawOutcome;
+
+    fn spawn_handler(mut self, mut rx: relay_system::Receiver<Self::Interface>) {
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    // Prioritize flush over receiving messages to prevent starving.
+                    biased;
+
+                    () = &mut self.flush_handle => self.send_batch(),
+                    Some(message) = rx.recv() => self.handle_message(message),
+                    else => break,
+                }
+            }
+        });
+    }
+}
+
+/// An error returned when posting a batch of outcomes to the webhook.
+#[derive(Debug, thiserror::Error)]
+enum WebhookError {
+    #[error("could not send request to webhook")]
+    SendFailed(#[from] reqwest::Error),
+    #[error("webhook responded with status {0}")]
+    ResponseError(reqwest::StatusCode),
+}
+
+impl WebhookError {
+    /// Returns `true` if sending the batch again may succeed.
+    ///
+    /// Client errors other than `429 Too Many Requests` indicate that the webhook does not accept
+    /// the request, so there is no point in retrying them.
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::SendFailed(_) => true,
+            Self::ResponseError(status) => {
+                !status.is_client_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+}
+
+/// Outcome producer that posts batches of [`TrackRawOutcome`] to an HTTP webhook.
+///
+/// This runs in addition to the configured outcome backend. The request body has the same format
+/// as [`SendOutcomes`]. Failed requests are retried with exponential backoff up to the configured
+/// number of retries, after which the batch is dropped. Both the number of concurrent requests and
+/// the number of pending batches are bounded, and new batches are dropped if too many are pending.
+#[derive(Debug)]
+struct WebhookOutcomeProducer {
+    client: reqwest::Client,
+    url: Arc<str>,
+    batch_size: usize,
+    batch_interval: Duration,
+    max_retries: usize,
+    max_backoff: Duration,
+    /// Limits the number of requests in flight.
+    request_permits: Arc<Semaphore>,
+    /// Limits the number of batches that are being sent or waiting for a retry.
+    pending_permits: Arc<Semaphore>,
+    unsent_outcomes: Vec<TrackRawOutcome>,
+    flush_handle: SleepHandle,
+}
+
+impl WebhookOutcomeProducer {
+    fn new(config: &Config, webhook: &OutcomeWebhook) -> anyhow::Result<Self> {
+        let client = http::client_builder(config)?.build()?;
+
+        Ok(Self {
+            client,
+            url: webhook.url.as_str().into(),
+            batch_size: webhook.batch_size.max(1),
+            batch_interval: Duration::from_millis(webhook.batch_interval),
+            max_retries: webhook.max_retries,
+            max_backoff: Duration::from_secs(webhook.max_backoff),
+            request_permits: Arc::new(Semaphore::new(webhook.max_concurrent_requests.max(1))),
+            pending_permits: Arc::new(Semaphore::new(webhook.max_pending_batches.max(1))),
+            unsent_outcomes: Vec::new(),
+            flush_handle: SleepHandle::idle(),
+        })
+    }
+
+    fn send_batch(&mut self) {
+        self.flush_handle.reset();
+
+        if self.unsent_outcomes.is_empty() {
+            return;
+        }
+
         let request = SendOutcomes {
             outcomes: mem::take(&mut self.unsent_outcomes),
+            upstream_route: None,
         };
 
-        let upstream_relay = self.upstream_relay.clone();
+        let Ok(pending_permit) = Arc::clone(&self.pending_permits).try_acquire_owned() else {
+            relay_log::error!(
+                outcomes = request.outcomes.len(),
+                "dropping outcome batch for webhook, too many pending batches"
+            );
+            return;
+        };
+
+        let body = match serde_json::to_vec(&request) {
+            Ok(body) => bytes::Bytes::from(body),
+            Err(error) => {
+                relay_log::error!(
+                    error = &error as &dyn Error,
+                    "failed to serialize outcome batch for webhook"
+                );
+                return;
+            }
+        };
+
+        let client = self.client.clone();
+        let url = Arc::clone(&self.url);
+        let max_retries = self.max_retries;
+        let request_permits = Arc::clone(&self.request_permits);
+        let mut backoff = RetryBackoff::new(self.max_backoff);
 
         tokio::spawn(async move {
-            match upstream_relay.send(SendQuery(request)).await {
-                Ok(_) => relay_log::trace!("outcome batch sent"),
-                Err(error) => {
-                    relay_log::error!(error = &error as &dyn Error, "outcome batch sending failed")
+            // Released once the batch has been sent or dropped.
+            let _pending_permit = pending_permit;
+
+            loop {
+                tokio::time::sleep(backoff.next_backoff()).await;
+
+                let result = match request_permits.acquire().await {
+                    Ok(_permit) => Self::post(&client, &url, body.clone()).await,
+                    // The semaphore is never closed.
+                    Err(_) => return,
+                };
+
+                let error = match result {
+                    Ok(()) => {
+                        relay_log::trace!("outcome batch sent to webhook");
+                        return;
+                    }
+                    Err(error) => error,
+                };
+
+                if !error.is_retryable() || backoff.attempt() > max_retries {
+                    relay_log::error!(
+                        error = &error as &dyn Error,
+                        attempts = backoff.attempt(),
+                        "dropping outcome batch for webhook"
+                    );
+                    return;
                 }
+
+                relay_log::warn!(
+                    error = &error as &dyn Error,
+                    "failed to send outcome batch to webhook, retrying"
+                );
             }
         });
     }
 
+    async fn post(
+        client: &reqwest::Client,
+        url: &str,
+        body: bytes::Bytes,
+    ) -> Result<(), WebhookError> {
+        let response = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(WebhookError::ResponseError(status))
+        }
+    }
+
     fn handle_message(&mut self, message: TrackRawOutcome) {
-        relay_log::trace!("batching outcome");
         self.unsent_outcomes.push(message);
 
-        if self.unsent_outcomes.len() >= self.config.outcome_batch_size() {
+        if self.unsent_outcomes.len() >= self.batch_size {
             self.send_batch();
         } else if self.flush_handle.is_idle() {
-            self.flush_handle.set(self.config.outcome_batch_interval());
+            self.flush_handle.set(self.batch_interval);
         }
     }
 }
 
-impl Service for HttpOutcomeProducer {
+impl Service for WebhookOutcomeProducer {
     type Interface = TrackRawOutcome;
 
     fn spawn_handler(mut self, mut rx: relay_system::Receiver<Self::Interface>) {
@@ -590,24 +883,46 @@ impl Service for HttpOutcomeProducer {
                     else => break,
                 }
             }
+
+            // Send remaining outcomes when the service shuts down.
+            self.send_batch();
         });
     }
 }
 
+/// Fields of a [`ClientReport`] that outcomes are reported in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ClientReportField {
+    Discarded,
+    RateLimited,
+    Filtered,
+    FilteredSampling,
+}
+
+/// Key by which the quantities of outcomes are summed up within a client report.
+type DiscardedEventKey = (ClientReportField, String, DataCategory);
+
 /// Outcome producer backend via HTTP as [`ClientReport`].
 #[derive(Debug)]
 struct ClientReportOutcomeProducer {
     flush_interval: Duration,
-    unsent_reports: BTreeMap<Scoping, Vec<ClientReport>>,
+    group_by_timestamp: bool,
+    remap_reasons: bool,
+    fold_downstream: bool,
+    unsent_reports: BTreeMap<(Scoping, Option<UnixTimestamp>), BTreeMap<DiscardedEventKey, u32>>,
     flush_handle: SleepHandle,
     envelope_manager: Addr<EnvelopeManager>,
 }
 
 impl ClientReportOutcomeProducer {
     fn new(config: &Config, envelope_manager: Addr<EnvelopeManager>) -> Self {
+        let client_reports = config.client_reports();
+
         Self {
-            // Use same batch interval as outcome aggregator
-            flush_interval: Duration::from_secs(config.outcome_aggregator().flush_interval),
+            flush_interval: config.client_reports_flush_interval(),
+            group_by_timestamp: client_reports.group_by_timestamp,
+            remap_reasons: client_reports.remap_reasons,
+            fold_downstream: config.fold_client_reports(),
             unsent_reports: BTreeMap::new(),
             flush_handle: SleepHandle::idle(),
             envelope_manager,
@@ -618,8 +933,37 @@ impl ClientReportOutcomeProducer {
         relay_log::trace!("flushing client reports");
         self.flush_handle.reset();
 
-        let unsent_reports = mem::take(&mut self.unsent_reports);
-        for (scoping, client_reports) in unsent_reports.into_iter() {
+        let mut client_reports = BTreeMap::<Scoping, Vec<ClientReport>>::new();
+        for ((scoping, timestamp), events) in mem::take(&mut self.unsent_reports) {
+            let mut client_report = ClientReport {
+                timestamp: Some(timestamp.unwrap_or_else(UnixTimestamp::now)),
+                ..Default::default()
+            };
+
+            for ((field, reason, category), quantity) in events {
+                let discarded_events = match field {
+                    ClientReportField::Discarded => &mut client_report.discarded_events,
+                    ClientReportField::RateLimited => &mut client_report.rate_limited_events,
+                    ClientReportField::Filtered => &mut client_report.filtered_events,
+                    ClientReportField::FilteredSampling => {
+                        &mut client_report.filtered_sampling_events
+                    }
+                };
+
+                discarded_events.push(DiscardedEvent {
+                    reason,
+                    category,
+                    quantity,
+                });
+            }
+
+            client_reports
+                .entry(scoping)
+                .or_default()
+                .push(client_report);
+        }
+
+        for (scoping, client_reports) in client_reports {
             self.envelope_manager.send(SendClientReports {
                 client_reports,
                 scoping,
@@ -628,36 +972,32 @@ impl ClientReportOutcomeProducer {
     }
 
     fn handle_message(&mut self, msg: TrackOutcome) {
-        let mut client_report = ClientReport {
-            timestamp: Some(UnixTimestamp::from_secs(
-                msg.timestamp.timestamp().try_into().unwrap_or(0),
-            )),
-            ..Default::default()
-        };
-
         // The outcome type determines what field to place the outcome in:
-        let discarded_events = match msg.outcome {
-            Outcome::Filtered(_) => &mut client_report.filtered_events,
-            Outcome::FilteredSampling(_) => &mut client_report.filtered_sampling_events,
-            Outcome::RateLimited(_) => &mut client_report.rate_limited_events,
+        let field = match msg.outcome {
+            Outcome::Filtered(_) => ClientReportField::Filtered,
+            Outcome::FilteredSampling(_) => ClientReportField::FilteredSampling,
+            Outcome::RateLimited(_) => ClientReportField::RateLimited,
+            // Client discards can only originate from downstream client reports.
+            Outcome::ClientDiscard(_) if self.fold_downstream => ClientReportField::Discarded,
+            Outcome::Invalid(_) if self.remap_reasons => ClientReportField::Discarded,
             _ => {
                 // Cannot convert this outcome to a client report.
                 return;
             }
         };
 
-        // Now that we know where to put it, let's create a DiscardedEvent
-        let discarded_event = DiscardedEvent {
-            reason: msg.outcome.to_reason().unwrap_or_default().to_string(),
-            category: msg.category,
-            quantity: msg.quantity,
-        };
-        discarded_events.push(discarded_event);
+        let timestamp = self
+            .group_by_timestamp
+            .then(|| UnixTimestamp::from_secs(msg.timestamp.timestamp().try_into().unwrap_or(0)));
 
-        self.unsent_reports
-            .entry(msg.scoping)
+        let reason = msg.outcome.to_reason().unwrap_or_default().into_owned();
+        let quantity = self
+            .unsent_reports
+            .entry((msg.scoping, timestamp))
             .or_default()
-            .push(client_report);
+            .entry((field, reason, msg.category))
+            .or_insert(0);
+        *quantity = quantity.saturating_add(msg.quantity);
 
         if self.flush_interval == Duration::ZERO {
             // Flush immediately. Useful for integration tests.
@@ -709,7 +1049,14 @@ impl KafkaOutcomesProducer {
     /// If the given Kafka configuration parameters are invalid, or an error happens during
     /// connecting during the broker, an error is returned.
     pub fn create(config: &Config) -> anyhow::Result<Self> {
-        let mut client_builder = KafkaClient::builder();
+        // Outcomes are not produced in transactions, since they are not bound to envelopes.
+        let delivery = match config.kafka_delivery_mode() {
+            DeliveryMode::AtLeastOnce => DeliveryMode::AtLeastOnce,
+            DeliveryMode::Idempotent | DeliveryMode::Transactional => DeliveryMode::Idempotent,
+        };
+        let mut client_builder = KafkaClient::builder()
+            .oauth(config.kafka_oauth().cloned())
+            .delivery(delivery, None);
 
         for topic in &[KafkaTopic::Outcomes, KafkaTopic::OutcomesBilling] {
             let kafka_config = &config.kafka_config(*topic).context(ServiceError::Kafka)?;
@@ -736,6 +1083,8 @@ impl KafkaOutcomesProducer {
 ///  2. Upstream Relay via batch HTTP request in point-of-presence configuration
 ///  3. Upstream Relay via client reports in external configuration
 ///  4. (default) Disabled
+///
+/// Additionally, all outcomes can be sent to an HTTP webhook configured in `outcomes.webhook`.
 #[derive(Debug)]
 pub enum OutcomeProducer {
     TrackOutcome(TrackOutcome),
@@ -896,6 +1245,7 @@ impl ProducerInner {
 pub struct OutcomeProducerService {
     config: Arc<Config>,
     inner: ProducerInner,
+    webhook: Option<WebhookOutcomeProducer>,
 }
 
 impl OutcomeProducerService {
@@ -932,7 +1282,19 @@ impl OutcomeProducerService {
             }
         };
 
-        Ok(Self { config, inner })
+        let webhook = match config.outcome_webhook() {
+            Some(webhook) => {
+                relay_log::info!("Configured to send outcomes to webhook");
+                Some(WebhookOutcomeProducer::new(&config, webhook)?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            config,
+            inner,
+            webhook,
+        })
     }
 }
 
@@ -940,16 +1302,220 @@ impl Service for OutcomeProducerService {
     type Interface = OutcomeProducer;
 
     fn spawn_handler(self, mut rx: relay_system::Receiver<Self::Interface>) {
-        let Self { config, inner } = self;
+        let Self {
+            config,
+            inner,
+            webhook,
+        } = self;
 
         tokio::spawn(async move {
             let broker = inner.start();
+            let webhook = webhook.map(Service::start);
 
             relay_log::info!("OutcomeProducer started.");
             while let Some(message) = rx.recv().await {
+                if let Some(ref webhook) = webhook {
+                    let raw_message = match message {
+                        OutcomeProducer::TrackOutcome(ref msg) => {
+                            TrackRawOutcome::from_outcome(msg.clone(), &config)
+                        }
+                        OutcomeProducer::TrackRawOutcome(ref msg) => msg.clone(),
+                    };
+
+                    send_outcome_metric(&raw_message, "webhook");
+                    webhook.send(raw_message);
+                }
+
                 broker.handle_message(message, &config);
             }
             relay_log::info!("OutcomeProducer stopped.");
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::actors::upstream::UpstreamRequest;
+
+    fn outcome() -> TrackRawOutcome {
+        serde_json::from_value(serde_json::json!({
+            "timestamp": "2023-10-01T12:00:00.000000Z",
+            "org_id": 1,
+            "project_id": 42,
+            "outcome": 3,
+            "reason": "project_id",
+            "category": 1,
+            "quantity": 1,
+        }))
+        .unwrap()
+    }
+
+    /// Starts a webhook that responds with `status` to the first `failures` requests.
+    ///
+    /// Returns the URL of the webhook and the number of outcomes in every received batch.
+    fn webhook(failures: usize, status: StatusCode) -> (String, Arc<Mutex<Vec<usize>>>) {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let received = batches.clone();
+
+        let router = axum::Router::new().fallback(move |body: bytes::Bytes| {
+            let request: SendOutcomes = serde_json::from_slice(&body).unwrap();
+            let mut received = received.lock().unwrap();
+            received.push(request.outcomes.len());
+            let attempt = received.len();
+            async move {
+                if attempt <= failures {
+                    status
+                } else {
+                    StatusCode::OK
+                }
+            }
+        });
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/outcomes", listener.local_addr().unwrap());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router.into_make_service());
+        tokio::spawn(server);
+
+        (url, batches)
+    }
+
+    fn producer(url: String, batch_size: usize, batch_interval: u64) -> Addr<TrackRawOutcome> {
+        let webhook = OutcomeWebhook {
+            url,
+            batch_size,
+            batch_interval,
+            max_retries: 1,
+            ..Default::default()
+        };
+
+        start_producer(webhook)
+    }
+
+    fn start_producer(webhook: OutcomeWebhook) -> Addr<TrackRawOutcome> {
+        WebhookOutcomeProducer::new(&Config::default(), &webhook)
+            .unwrap()
+            .start()
+    }
+
+    /// Waits until the webhook has received `count` batches and returns their sizes.
+    async fn wait_for_batches(batches: &Mutex<Vec<usize>>, count: usize) -> Vec<usize> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let received = batches.lock().unwrap().clone();
+                if received.len() >= count {
+                    return received;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_webhook_flush_batch_size() {
+        let (url, batches) = webhook(0, StatusCode::OK);
+        // The interval is long enough that only the batch size can trigger a flush.
+        let addr = producer(url, 2, 60_000);
+
+        addr.send(outcome());
+        addr.send(outcome());
+        addr.send(outcome());
+
+        assert_eq!(wait_for_batches(&batches, 1).await, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_flush_interval() {
+        let (url, batches) = webhook(0, StatusCode::OK);
+        let addr = producer(url, 100, 50);
+
+        addr.send(outcome());
+        assert_eq!(wait_for_batches(&batches, 1).await, vec![1]);
+
+        addr.send(outcome());
+        addr.send(outcome());
+        assert_eq!(wait_for_batches(&batches, 2).await, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retry() {
+        let (url, batches) = webhook(1, StatusCode::SERVICE_UNAVAILABLE);
+        let addr = producer(url, 1, 60_000);
+
+        addr.send(outcome());
+
+        // The same batch is sent again after the first request failed.
+        assert_eq!(wait_for_batches(&batches, 2).await, vec![1, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_no_retry_client_error() {
+        let (url, batches) = webhook(1, StatusCode::BAD_REQUEST);
+        let addr = producer(url, 1, 60_000);
+
+        addr.send(outcome());
+        assert_eq!(wait_for_batches(&batches, 1).await, vec![1]);
+
+        // Wait longer than the first backoff interval to make sure no retry follows.
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(batches.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_max_pending_batches() {
+        let (url, batches) = webhook(usize::MAX, StatusCode::SERVICE_UNAVAILABLE);
+        let addr = start_producer(OutcomeWebhook {
+            url,
+            batch_size: 1,
+            max_retries: 1,
+            max_pending_batches: 1,
+            ..Default::default()
+        });
+
+        // The first batch waits for its retry, so the second batch is dropped.
+        addr.send(outcome());
+        addr.send(outcome());
+        assert_eq!(wait_for_batches(&batches, 2).await, vec![1, 1]);
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(batches.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_http_outcomes_routed() {
+        let config = Config::from_json_value(serde_json::json!({
+            "relay": {
+                "routes": [{"upstream": "https://eu.example.com/", "project_ids": [43]}]
+            }
+        }))
+        .unwrap();
+
+        let (upstream_relay, mut rx) = Addr::custom();
+        let mut producer = HttpOutcomeProducer::new(Arc::new(config), upstream_relay);
+
+        let mut routed = outcome();
+        routed.project_id = ProjectId::new(43);
+        producer.handle_message(outcome());
+        producer.handle_message(routed);
+        producer.send_batch();
+
+        let mut routes = Vec::new();
+        for _ in 0..2 {
+            let UpstreamRelay::SendRequest(request) = rx.recv().await.unwrap() else {
+                panic!("expected an upstream request");
+            };
+            routes.push(request.upstream_route());
+        }
+
+        routes.sort();
+        assert_eq!(routes, vec![None, Some(0)]);
+    }
+}
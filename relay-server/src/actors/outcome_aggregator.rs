@@ -63,6 +63,8 @@ impl OutcomeAggregator {
         let mode = match config.emit_outcomes() {
             EmitOutcomes::AsOutcomes => AggregationMode::Lossless,
             EmitOutcomes::AsClientReports => AggregationMode::Lossy,
+            // Outcomes are still needed for the webhook, even if they are not emitted upstream.
+            EmitOutcomes::None if config.outcome_webhook().is_some() => AggregationMode::Lossless,
             EmitOutcomes::None => AggregationMode::DropEverything,
         };
 
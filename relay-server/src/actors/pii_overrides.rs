@@ -0,0 +1,435 @@
+//! Local additions to the PII configs of projects.
+//!
+//! If `pii_overrides.path` is configured, [`LocalPiiOverridesService`] periodically loads PII
+//! configs named `<project_id>.json` from that directory. Their rules and applications are merged
+//! into the PII config of every fetched project state, regardless of the source of the state.
+//!
+//! Merging is additive: local rules are added with the [`LOCAL_RULE_PREFIX`], so they cannot
+//! replace rules of the project's PII config, and local applications are appended to the rules
+//! already applied to a selector. Vars of local configs are ignored.
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::Arc;
+
+use relay_base_schema::project::ProjectId;
+use relay_config::Config;
+use relay_pii::{PiiConfig, RuleType};
+use relay_system::{Addr, AsyncResponse, FromMessage, Interface, Receiver, Sender, Service};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::actors::project::ProjectState;
+use crate::actors::project_cache::{ProjectCache, RefreshProjects};
+
+/// Prefix of the IDs of rules added from local PII configs.
+///
+/// Rules with this prefix in the project's own PII config are removed when merging.
+pub const LOCAL_RULE_PREFIX: &str = "local:";
+
+/// Local PII configs by project ID.
+type PiiConfigs = BTreeMap<ProjectId, Arc<PiiConfig>>;
+
+/// Merges local PII configs into a project state.
+///
+/// Responds with the given state if no local PII config applies.
+#[derive(Debug)]
+pub struct ApplyPiiOverrides {
+    pub state: Arc<ProjectState>,
+}
+
+/// Service interface of the local PII config additions.
+#[derive(Debug)]
+pub struct LocalPiiOverrides(ApplyPiiOverrides, Sender<Arc<ProjectState>>);
+
+impl Interface for LocalPiiOverrides {}
+
+impl FromMessage<ApplyPiiOverrides> for LocalPiiOverrides {
+    type Response = AsyncResponse<Arc<ProjectState>>;
+
+    fn from_message(message: ApplyPiiOverrides, sender: Sender<Arc<ProjectState>>) -> Self {
+        Self(message, sender)
+    }
+}
+
+/// A service which periodically loads local PII configs from disk.
+///
+/// Projects whose local PII config was added, changed, or removed are refreshed in the
+/// [`ProjectCache`] immediately.
+#[derive(Debug)]
+pub struct LocalPiiOverridesService {
+    config: Arc<Config>,
+    project_cache: Addr<ProjectCache>,
+    configs: PiiConfigs,
+}
+
+impl LocalPiiOverridesService {
+    pub fn new(config: Arc<Config>, project_cache: Addr<ProjectCache>) -> Self {
+        Self {
+            config,
+            project_cache,
+            configs: PiiConfigs::new(),
+        }
+    }
+
+    fn handle_message(&mut self, message: LocalPiiOverrides) {
+        let LocalPiiOverrides(message, sender) = message;
+        sender.send(self.apply(message.state));
+    }
+
+    fn apply(&self, state: Arc<ProjectState>) -> Arc<ProjectState> {
+        if state.invalid() || state.disabled() {
+            return state;
+        }
+
+        let local = state.project_id.and_then(|id| self.configs.get(&id));
+        let has_local_rules = state
+            .config
+            .pii_config
+            .as_ref()
+            .map_or(false, has_local_rules);
+
+        // Revalidated states already contain the local rules from the previous fetch.
+        if local.is_none() && !has_local_rules {
+            return state;
+        }
+
+        let mut state = ProjectState::clone(&state);
+        state.config.pii_config = merge(state.config.pii_config.as_ref(), local.map(Arc::as_ref));
+        Arc::new(state)
+    }
+
+    /// Replaces the local PII configs and refreshes all projects that changed.
+    fn update_configs(&mut self, configs: PiiConfigs) {
+        let changed = configs
+            .iter()
+            .filter(|(id, config)| self.configs.get(*id) != Some(*config))
+            .map(|(id, _)| *id);
+        let removed = self.configs.keys().filter(|id| !configs.contains_key(*id));
+        let project_ids = changed.chain(removed.copied()).collect::<BTreeSet<_>>();
+
+        self.configs = configs;
+
+        if !project_ids.is_empty() {
+            relay_log::info!("local PII configs changed for projects {project_ids:?}");
+            self.project_cache.send(RefreshProjects { project_ids });
+        }
+    }
+}
+
+/// Returns `true` if the PII config contains rules added from a local PII config.
+fn has_local_rules(config: &PiiConfig) -> bool {
+    config
+        .rules
+        .keys()
+        .any(|id| id.starts_with(LOCAL_RULE_PREFIX))
+}
+
+/// Prefixes a reference to a rule defined in the local PII config.
+///
+/// References to built-in rules and to rules of the project's PII config remain unchanged.
+fn local_reference(id: &str, local: &PiiConfig) -> String {
+    match local.rules.contains_key(id) {
+        true => format!("{LOCAL_RULE_PREFIX}{id}"),
+        false => id.to_owned(),
+    }
+}
+
+/// Merges the rules and applications of a local PII config into the project's PII config.
+///
+/// Local rules from a previous merge are removed first, so that merging a state repeatedly yields
+/// the same config. Returns `None` if the merged config is empty.
+fn merge(base: Option<&PiiConfig>, local: Option<&PiiConfig>) -> Option<PiiConfig> {
+    // Start from a new config, since the compiled config of `base` must not be reused.
+    let mut merged = PiiConfig::default();
+
+    if let Some(base) = base {
+        merged.vars = base.vars.clone();
+        merged.rules = base
+            .rules
+            .iter()
+            .filter(|(id, _)| !id.starts_with(LOCAL_RULE_PREFIX))
+            .map(|(id, rule)| (id.clone(), rule.clone()))
+            .collect();
+
+        for (selector, ids) in &base.applications {
+            let ids = ids
+                .iter()
+                .filter(|id| !id.starts_with(LOCAL_RULE_PREFIX))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !ids.is_empty() {
+                merged.applications.insert(selector.clone(), ids);
+            }
+        }
+    }
+
+    if let Some(local) = local {
+        for (id, rule) in &local.rules {
+            let mut rule = rule.clone();
+            match rule.ty {
+                RuleType::Multiple(ref mut multiple) => {
+                    for reference in &mut multiple.rules {
+                        *reference = local_reference(reference, local);
+                    }
+                }
+                RuleType::Alias(ref mut alias) => alias.rule = local_reference(&alias.rule, local),
+                _ => (),
+            }
+
+            merged
+                .rules
+                .insert(format!("{LOCAL_RULE_PREFIX}{id}"), rule);
+        }
+
+        for (selector, ids) in &local.applications {
+            let applied = merged.applications.entry(selector.clone()).or_default();
+            applied.extend(ids.iter().map(|id| local_reference(id, local)));
+        }
+    }
+
+    if merged.rules.is_empty() && merged.applications.is_empty() {
+        return None;
+    }
+
+    Some(merged)
+}
+
+/// Checks that all pattern rules of a local PII config compile.
+fn validate(config: &PiiConfig) -> Result<(), String> {
+    for (id, rule) in &config.rules {
+        if let RuleType::Pattern(ref rule) = rule.ty {
+            if let Err(error) = rule.pattern.compiled() {
+                return Err(format!("invalid PII rule `{id}`: {error}"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn load_file(path: &Path) -> Result<PiiConfig, String> {
+    let file = std::fs::read(path).map_err(|error| error.to_string())?;
+    let config = serde_json::from_slice(&file).map_err(|error| error.to_string())?;
+    validate(&config)?;
+    Ok(config)
+}
+
+/// Loads all local PII configs from the given directory.
+///
+/// If a file cannot be loaded, an error is logged and the `previous` config of that project
+/// remains in effect.
+fn load_configs(path: &Path, previous: &PiiConfigs) -> std::io::Result<PiiConfigs> {
+    let directory = match std::fs::read_dir(path) {
+        Ok(directory) => directory,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(PiiConfigs::new()),
+        Err(error) => return Err(error),
+    };
+
+    let mut configs = PiiConfigs::new();
+    for entry in directory {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new("json")) {
+            continue;
+        }
+
+        let Some(project_id) = path
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .and_then(|stem| stem.parse().ok())
+        else {
+            relay_log::warn!(?path, "skipping PII config, filename is not a project id");
+            continue;
+        };
+
+        match load_file(&path) {
+            Ok(config) => match previous.get(&project_id) {
+                // Keep the previous config if unchanged to retain its compiled state.
+                Some(previous) if **previous == config => {
+                    configs.insert(project_id, previous.clone());
+                }
+                _ => {
+                    configs.insert(project_id, Arc::new(config));
+                }
+            },
+            Err(error) => {
+                relay_log::error!(?path, "failed to load local PII config: {error}");
+                if let Some(previous) = previous.get(&project_id) {
+                    configs.insert(project_id, previous.clone());
+                }
+            }
+        }
+    }
+
+    Ok(configs)
+}
+
+/// Loads the local PII configs on a blocking thread and logs errors.
+async fn poll_configs(path: &Path, previous: &PiiConfigs) -> Option<PiiConfigs> {
+    let path = path.to_owned();
+    let previous = previous.clone();
+    let result = tokio::task::spawn_blocking(move || load_configs(&path, &previous)).await;
+
+    match result {
+        Ok(Ok(configs)) => Some(configs),
+        Ok(Err(error)) => {
+            relay_log::error!(
+                error = &error as &dyn std::error::Error,
+                "failed to load local PII configs",
+            );
+            None
+        }
+        Err(error) => {
+            relay_log::error!(
+                error = &error as &dyn std::error::Error,
+                "failed to load local PII configs",
+            );
+            None
+        }
+    }
+}
+
+/// Loads the local PII configs and starts a background task that reloads them periodically.
+///
+/// Returns the initially loaded configs. Reloaded configs are sent to `tx`.
+async fn spawn_poll_configs(config: &Config, tx: mpsc::Sender<PiiConfigs>) -> PiiConfigs {
+    let Some(path) = config.pii_overrides_path() else {
+        return PiiConfigs::new();
+    };
+
+    let period = config.local_cache_interval();
+    let initial = poll_configs(&path, &PiiConfigs::new())
+        .await
+        .unwrap_or_default();
+
+    let mut configs = initial.clone();
+    tokio::spawn(async move {
+        let start_at = Instant::now() + period;
+        let mut ticker = tokio::time::interval_at(start_at, period);
+
+        loop {
+            ticker.tick().await;
+            if let Some(reloaded) = poll_configs(&path, &configs).await {
+                configs = reloaded.clone();
+                if tx.send(reloaded).await.is_err() {
+                    relay_log::error!("failed to store local PII configs");
+                }
+            }
+        }
+    });
+
+    initial
+}
+
+impl Service for LocalPiiOverridesService {
+    type Interface = LocalPiiOverrides;
+
+    fn spawn_handler(mut self, mut rx: Receiver<Self::Interface>) {
+        let (configs_tx, mut configs_rx) = mpsc::channel(1);
+
+        tokio::spawn(async move {
+            relay_log::info!("local PII configs started");
+
+            // Load the configs before handling any message, so that the first fetched project
+            // states already contain them.
+            self.configs = spawn_poll_configs(&self.config, configs_tx).await;
+
+            loop {
+                tokio::select! {
+                    biased;
+                    Some(message) = rx.recv() => self.handle_message(message),
+                    Some(configs) = configs_rx.recv() => self.update_configs(configs),
+
+                    else => break,
+                }
+            }
+            relay_log::info!("local PII configs stopped");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pii_config(json: serde_json::Value) -> PiiConfig {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_merge_additive() {
+        let base = pii_config(serde_json::json!({
+            "rules": {
+                "secret": {"type": "pattern", "pattern": "secret", "redaction": {"method": "remove"}}
+            },
+            "applications": {"$string": ["secret"]}
+        }));
+
+        // The local rule has the same ID as a rule of the project and references it.
+        let local = pii_config(serde_json::json!({
+            "rules": {
+                "secret": {"type": "anything", "redaction": {"method": "remove"}},
+                "both": {"type": "multiple", "rules": ["secret", "@ip"]}
+            },
+            "applications": {"$string": ["both"], "extra.token": ["secret"]}
+        }));
+
+        let merged = merge(Some(&base), Some(&local)).unwrap();
+
+        assert_eq!(
+            merged.rules.keys().collect::<Vec<_>>(),
+            ["local:both", "local:secret", "secret"]
+        );
+        match merged.rules["local:both"].ty {
+            RuleType::Multiple(ref multiple) => {
+                assert_eq!(multiple.rules, ["local:secret", "@ip"]);
+            }
+            _ => panic!("expected multiple rule"),
+        }
+
+        let applications = serde_json::to_value(&merged.applications).unwrap();
+        assert_eq!(
+            applications,
+            serde_json::json!({
+                "$string": ["secret", "local:both"],
+                "extra.token": ["local:secret"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_idempotent() {
+        let local = pii_config(serde_json::json!({
+            "rules": {"token": {"type": "anything", "redaction": {"method": "remove"}}},
+            "applications": {"extra.token": ["token"]}
+        }));
+
+        let merged = merge(None, Some(&local)).unwrap();
+        assert_eq!(merge(Some(&merged), Some(&local)), Some(merged.clone()));
+
+        // Removing the local config also removes its rules from a previously merged config.
+        assert_eq!(merge(Some(&merged), None), None);
+    }
+
+    #[test]
+    fn test_load_configs() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path();
+
+        let valid = r#"{"applications": {"$string": ["@email"]}}"#;
+        std::fs::write(path.join("42.json"), valid).unwrap();
+        std::fs::write(path.join("43.json"), r#"{"rules": {"#).unwrap();
+        std::fs::write(path.join("foo.json"), valid).unwrap();
+
+        let config = serde_json::from_str(valid).unwrap();
+        let previous = BTreeMap::from([(ProjectId::new(43), Arc::new(config))]);
+        let configs = load_configs(path, &previous).unwrap();
+
+        // The invalid config of project 43 is replaced by its previous config.
+        assert_eq!(
+            configs.keys().collect::<Vec<_>>(),
+            [&ProjectId::new(42), &ProjectId::new(43)]
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use std::collections::BTreeMap;
-use std::convert::TryFrom;
+use std::convert::{Infallible, TryFrom};
 use std::error::Error;
 use std::io::Write;
 use std::net;
@@ -19,44 +19,49 @@ use relay_base_schema::project::{ProjectId, ProjectKey};
 use relay_common::time::UnixTimestamp;
 use relay_config::{Config, HttpEncoding};
 use relay_dynamic_config::{
-    ErrorBoundary, Feature, GlobalConfig, ProjectConfig, SessionMetricsConfig,
+    ErrorBoundary, Feature, GlobalConfig, ProjectConfig, SessionMetricsConfig, TagMapping,
 };
 use relay_event_normalization::replay::{self, ReplayError};
 use relay_event_normalization::{
-    ClockDriftProcessor, DynamicMeasurementsConfig, LightNormalizationConfig, MeasurementsConfig,
-    TransactionNameConfig,
+    normalize_ip, ClockDriftProcessor, DynamicMeasurementsConfig, LightNormalizationConfig,
+    MeasurementsConfig, TransactionNameConfig,
 };
 use relay_event_normalization::{GeoIpLookup, RawUserAgentInfo};
 use relay_event_schema::processor::{self, ProcessingAction, ProcessingState};
 use relay_event_schema::protocol::{
-    Breadcrumb, ClientReport, Contexts, Csp, Event, EventType, ExpectCt, ExpectStaple, Hpkp,
-    IpAddr, LenientString, Metrics, OtelContext, RelayInfo, Replay, SecurityReportType,
+    Breadcrumb, ClientReport, Contexts, Csp, Event, EventType, ExpectCt, ExpectStaple, Feedback,
+    Hpkp, IpAddr, LenientString, Metrics, OtelContext, RelayInfo, Replay, SecurityReportType,
     SessionAggregates, SessionAttributes, SessionStatus, SessionUpdate, Timestamp, TraceContext,
     UserReport, Values,
 };
 use relay_filter::FilterStatKey;
-use relay_metrics::{Bucket, MergeBuckets, MetricNamespace};
+use relay_metrics::{Bucket, MergeBuckets, MetricMeta, MetricNamespace};
 use relay_pii::{PiiAttachmentsProcessor, PiiConfigError, PiiProcessor};
 use relay_profiling::ProfileError;
 use relay_protocol::{Annotated, Array, Empty, FromValue, Object, Value};
-use relay_quotas::{DataCategory, ReasonCode};
+use relay_quotas::{DataCategory, LocalRateLimiter, Quota, RateLimiter, ReasonCode};
 use relay_redis::RedisPool;
 use relay_replays::recording::RecordingScrubber;
+use relay_sampling::adaptive::{AdaptiveSampler, AdaptiveSamplingConfig};
 use relay_sampling::evaluation::MatchedRuleIds;
 use relay_sampling::DynamicSamplingContext;
 use relay_statsd::metric;
 use relay_system::{Addr, FromMessage, NoResponse, Service};
 use serde_json::Value as SerdeValue;
-use tokio::sync::Semaphore;
 
 #[cfg(feature = "processing")]
 use {
     crate::actors::envelopes::SendMetrics,
-    crate::actors::project_cache::UpdateRateLimits,
-    crate::utils::{EnvelopeLimiter, MetricsLimiter},
-    relay_event_normalization::{span, StoreConfig, StoreProcessor},
-    relay_event_schema::protocol::{ProfileContext, Span},
-    relay_quotas::{RateLimitingError, RedisRateLimiter},
+    crate::utils::MetricsLimiter,
+    relay_event_normalization::span::standalone::{
+        normalize_standalone_span, StandaloneSpanConfig,
+    },
+    relay_event_normalization::{log, span, StoreConfig, StoreProcessor},
+    relay_event_schema::protocol::{Log, ProfileContext, Span},
+    relay_monitors::ProcessCheckInError,
+    relay_quotas::{
+        ItemScoping, MemcachedRateLimiter, RateLimitingError, RateLimits, RedisRateLimiter,
+    },
     symbolic_unreal::{Unreal4Error, Unreal4ErrorKind},
 };
 
@@ -64,21 +69,25 @@ use crate::actors::envelopes::{EnvelopeManager, SendEnvelope, SendEnvelopeError,
 use crate::actors::global_config::{GlobalConfigManager, Subscribe};
 use crate::actors::outcome::{DiscardReason, Outcome, TrackOutcome};
 use crate::actors::project::ProjectState;
-use crate::actors::project_cache::ProjectCache;
+use crate::actors::project_cache::{AddMetricMeta, ProjectCache, UpdateRateLimits};
 use crate::actors::upstream::{SendRequest, UpstreamRelay};
 use crate::envelope::{AttachmentType, ContentType, Envelope, Item, ItemType};
 use crate::extractors::RequestMeta;
 use crate::metrics_extraction::transactions::types::ExtractMetricsError;
 use crate::metrics_extraction::transactions::{ExtractedMetrics, TransactionExtractor};
 use crate::service::ServiceError;
-use crate::statsd::{PlatformTag, RelayCounters, RelayHistograms, RelayTimers};
+use crate::statsd::{PlatformTag, RelayCounters, RelayGauges, RelayHistograms, RelayTimers};
 use crate::utils::{
-    self, ChunkedFormDataAggregator, FormDataIter, ItemAction, ManagedEnvelope, SamplingResult,
+    self, ChunkedFormDataAggregator, EnvelopeInspector, EnvelopeLimiter, FormDataIter, Inspection,
+    ItemAction, ManagedEnvelope, SamplingResult, WeightedLanes,
 };
 
 /// The minimum clock drift for correction to apply.
 const MINIMUM_CLOCK_DRIFT: Duration = Duration::from_secs(55 * 60);
 
+/// The interval in which the queue sizes of processor lanes are reported.
+const LANE_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
 /// An error returned when handling [`ProcessEnvelope`].
 #[derive(Debug, thiserror::Error)]
 pub enum ProcessingError {
@@ -185,6 +194,12 @@ impl ProcessingError {
     }
 }
 
+impl From<Infallible> for ProcessingError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
 #[cfg(feature = "processing")]
 impl From<Unreal4Error> for ProcessingError {
     fn from(err: Unreal4Error) -> Self {
@@ -307,6 +322,11 @@ struct ProcessEnvelopeState {
 
     /// Whether there is a profiling item in the envelope.
     has_profile: bool,
+
+    /// Processing decisions recorded if this envelope was sampled for inspection.
+    ///
+    /// See [`Config::inspect`].
+    inspection: Option<Inspection>,
 }
 
 impl ProcessEnvelopeState {
@@ -356,7 +376,6 @@ impl ProcessEnvelopeState {
     }
 
     /// Removes the event payload from this processing state.
-    #[cfg(feature = "processing")]
     fn remove_event(&mut self) {
         self.event = Annotated::empty();
     }
@@ -378,6 +397,103 @@ enum ClientReportField {
     ClientDiscard,
 }
 
+/// Checks quotas against Redis, blocking the current thread.
+#[cfg(feature = "redis-sync")]
+fn check_quotas(
+    rate_limiter: &RedisRateLimiter,
+    quotas: &[Quota],
+    item_scoping: ItemScoping<'_>,
+    quantity: usize,
+    over_accept_once: bool,
+) -> Result<RateLimits, RateLimitingError> {
+    rate_limiter.is_rate_limited(quotas, item_scoping, quantity, over_accept_once)
+}
+
+/// Checks quotas against Redis over a multiplexed connection.
+///
+/// Envelopes are processed on blocking threads, so this waits for the result on the current
+/// runtime without occupying one of its workers.
+#[cfg(all(feature = "processing", not(feature = "redis-sync")))]
+fn check_quotas(
+    rate_limiter: &RedisRateLimiter,
+    quotas: &[Quota],
+    item_scoping: ItemScoping<'_>,
+    quantity: usize,
+    over_accept_once: bool,
+) -> Result<RateLimits, RateLimitingError> {
+    tokio::runtime::Handle::current().block_on(rate_limiter.is_rate_limited_async(
+        quotas,
+        item_scoping,
+        quantity,
+        over_accept_once,
+    ))
+}
+
+/// The rate limiter that tracks quotas in a store shared by all processing Relays.
+#[cfg(feature = "processing")]
+enum SharedRateLimiter {
+    /// Tracks quotas in Redis, see [`check_quotas`].
+    Redis(RedisRateLimiter),
+    /// Tracks quotas in memcached.
+    Memcached(MemcachedRateLimiter),
+}
+
+#[cfg(feature = "processing")]
+impl SharedRateLimiter {
+    /// Creates the rate limiter for the configured store.
+    ///
+    /// Memcached takes precedence over Redis if it is configured. Returns an error if memcached
+    /// cannot be reached.
+    fn new(config: &Config, redis: Option<RedisPool>) -> anyhow::Result<Option<Self>> {
+        if let Some(memcached) = config.memcached() {
+            let timeout = Duration::from_secs(memcached.timeout);
+            let rate_limiter = MemcachedRateLimiter::new(memcached.servers.clone(), timeout)
+                .context(ServiceError::Memcached)?;
+            return Ok(Some(Self::Memcached(
+                rate_limiter.max_limit(config.max_rate_limit()),
+            )));
+        }
+
+        let Some(redis) = redis else {
+            return Ok(None);
+        };
+
+        let rate_limiter = RedisRateLimiter::new(redis)
+            .max_limit(config.max_rate_limit())
+            .key_prefix(config.redis_key_prefix());
+        Ok(Some(Self::Redis(match config.redis_cache_ttl() {
+            Some(ttl) => rate_limiter.cache(ttl, config.redis_cache_max_entries()),
+            None => rate_limiter,
+        })))
+    }
+}
+
+#[cfg(feature = "processing")]
+impl RateLimiter for SharedRateLimiter {
+    type Error = RateLimitingError;
+
+    fn is_rate_limited(
+        &self,
+        quotas: &[Quota],
+        item_scoping: ItemScoping<'_>,
+        quantity: usize,
+        over_accept_once: bool,
+    ) -> Result<RateLimits, Self::Error> {
+        match self {
+            Self::Redis(rate_limiter) => check_quotas(
+                rate_limiter,
+                quotas,
+                item_scoping,
+                quantity,
+                over_accept_once,
+            ),
+            Self::Memcached(rate_limiter) => {
+                rate_limiter.is_rate_limited(quotas, item_scoping, quantity, over_accept_once)
+            }
+        }
+    }
+}
+
 /// Parse an outcome from an outcome ID and a reason string.
 ///
 /// Currently only used to reconstruct outcomes encoded in client reports.
@@ -434,6 +550,8 @@ pub struct ProcessEnvelope {
 ///    ignored independently.
 ///  - For [`MetricBuckets`](ItemType::MetricBuckets), the entire list of buckets is parsed and
 ///    dropped together on parsing failure.
+///  - For [`MetricMeta`](ItemType::MetricMeta), the metadata is parsed and sent to the project
+///    cache for deduplication.
 ///  - Other items will be ignored with an error message.
 ///
 /// Additionally, processing applies clock drift correction using the system clock of this Relay, if
@@ -487,8 +605,68 @@ pub enum EnvelopeProcessor {
     RateLimitFlushBuckets(RateLimitFlushBuckets),
 }
 
+impl EnvelopeProcessor {
+    /// Returns the lane of the processor that handles this message.
+    fn lane(&self) -> ProcessorLane {
+        match self {
+            Self::ProcessEnvelope(message) => {
+                let envelope = message.envelope.envelope();
+                if envelope
+                    .items()
+                    .any(|item| matches!(item.ty(), ItemType::Attachment | ItemType::UnrealReport))
+                {
+                    ProcessorLane::Attachments
+                } else if envelope
+                    .items()
+                    .any(|item| matches!(item.ty(), ItemType::ReplayRecording))
+                {
+                    ProcessorLane::Pii
+                } else {
+                    ProcessorLane::Normalization
+                }
+            }
+            Self::ProcessMetrics(_) => ProcessorLane::Metrics,
+            Self::EncodeEnvelope(_) => ProcessorLane::Encoding,
+            #[cfg(feature = "processing")]
+            Self::RateLimitFlushBuckets(_) => ProcessorLane::Metrics,
+        }
+    }
+}
+
 impl relay_system::Interface for EnvelopeProcessor {}
 
+/// Lanes of the envelope processor, see [`ProcessorLanes`](relay_config::ProcessorLanes).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ProcessorLane {
+    Normalization,
+    Pii,
+    Attachments,
+    Metrics,
+    Encoding,
+}
+
+impl ProcessorLane {
+    /// All lanes in the order of their discriminants.
+    const ALL: [Self; 5] = [
+        Self::Normalization,
+        Self::Pii,
+        Self::Attachments,
+        Self::Metrics,
+        Self::Encoding,
+    ];
+
+    /// Returns the name of the lane used in metric tags.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Normalization => "normalization",
+            Self::Pii => "pii",
+            Self::Attachments => "attachments",
+            Self::Metrics => "metrics",
+            Self::Encoding => "encoding",
+        }
+    }
+}
+
 impl FromMessage<ProcessEnvelope> for EnvelopeProcessor {
     type Response = relay_system::NoResponse;
 
@@ -539,12 +717,19 @@ struct InnerProcessor {
     outcome_aggregator: Addr<TrackOutcome>,
     upstream_relay: Addr<UpstreamRelay>,
     #[cfg(feature = "processing")]
-    rate_limiter: Option<RedisRateLimiter>,
+    rate_limiter: Option<SharedRateLimiter>,
+    local_rate_limiter: Option<LocalRateLimiter>,
     geoip_lookup: Option<GeoIpLookup>,
+    adaptive_sampler: Option<AdaptiveSampler>,
+    inspector: Option<EnvelopeInspector>,
+    /// Tag mappings from the conditional tagging rules in `transaction_metrics` overrides.
+    transaction_metrics_tags: Vec<TagMapping>,
 }
 
 impl EnvelopeProcessorService {
     /// Creates a multi-threaded envelope processor.
+    ///
+    /// Returns an error if the store for rate limits cannot be reached.
     pub fn new(
         config: Arc<Config>,
         _redis: Option<RedisPool>,
@@ -553,7 +738,7 @@ impl EnvelopeProcessorService {
         project_cache: Addr<ProjectCache>,
         global_config: Addr<GlobalConfigManager>,
         upstream_relay: Addr<UpstreamRelay>,
-    ) -> Self {
+    ) -> anyhow::Result<Self> {
         let geoip_lookup = config.geoip_path().and_then(|p| {
             match GeoIpLookup::open(p).context(ServiceError::GeoIp) {
                 Ok(geoip) => Some(geoip),
@@ -564,10 +749,28 @@ impl EnvelopeProcessorService {
             }
         });
 
+        let adaptive_sampler = config.target_sampling().map(|target| {
+            let window = Duration::from_secs(target.adjustment_interval.max(1));
+            AdaptiveSampler::new(AdaptiveSamplingConfig {
+                target: target.events_per_minute.unwrap_or_default() as f64 * window.as_secs_f64()
+                    / 60.0,
+                window,
+                min_sample_rate: target.min_sample_rate,
+            })
+        });
+
+        let inspector = config.inspect().and_then(EnvelopeInspector::new);
+        let transaction_metrics_tags = config.transaction_metrics_overrides().tag_mappings();
+
+        // Static quotas are borrowed on every call, since they can change when the config reloads.
+        let local_rate_limiter = config
+            .local_rate_limiting()
+            .map(|_| LocalRateLimiter::new().max_limit(config.max_rate_limit()));
+
         let inner = InnerProcessor {
             #[cfg(feature = "processing")]
-            rate_limiter: _redis
-                .map(|pool| RedisRateLimiter::new(pool).max_limit(config.max_rate_limit())),
+            rate_limiter: SharedRateLimiter::new(&config, _redis)?,
+            local_rate_limiter,
             config,
             envelope_manager,
             project_cache,
@@ -575,12 +778,23 @@ impl EnvelopeProcessorService {
             outcome_aggregator,
             upstream_relay,
             geoip_lookup,
+            adaptive_sampler,
+            inspector,
+            transaction_metrics_tags,
         };
 
-        Self {
+        Ok(Self {
             global_config: Arc::default(),
             inner: Arc::new(inner),
-        }
+        })
+    }
+
+    /// Returns the normalized IP address of the client that sent the envelope.
+    ///
+    /// This is the address used to resolve `{{auto}}`. See [`normalize_ip`].
+    fn client_addr(&self, meta: &RequestMeta) -> Option<net::IpAddr> {
+        let client_addr = meta.client_addr()?;
+        Some(normalize_ip(client_addr, self.inner.config.ipv6_prefix()))
     }
 
     /// Returns Ok(true) if attributes were modified.
@@ -852,7 +1066,7 @@ impl EnvelopeProcessorService {
         let metrics_config = state.project_state.config().session_metrics;
         let envelope = state.managed_envelope.envelope_mut();
         let client = envelope.meta().client().map(|x| x.to_owned());
-        let client_addr = envelope.meta().client_addr();
+        let client_addr = self.client_addr(envelope.meta());
 
         let clock_drift_processor =
             ClockDriftProcessor::new(envelope.sent_at(), received).at_least(MINIMUM_CLOCK_DRIFT);
@@ -887,13 +1101,41 @@ impl EnvelopeProcessorService {
         });
     }
 
-    /// Validates and normalizes all user report items in the envelope.
+    /// Validates and normalizes all user report and feedback items in the envelope.
     ///
     /// User feedback items are removed from the envelope if they contain invalid JSON or if the
     /// JSON violates the schema (basic type validation). Otherwise, their normalized representation
     /// is written back into the item.
+    ///
+    /// If configured, processing Relays convert user reports into feedback items. The converted
+    /// items count towards the feedback data category.
     fn process_user_reports(&self, state: &mut ProcessEnvelopeState) {
+        let processing_enabled = self.inner.config.processing_enabled();
+        let convert = processing_enabled && self.inner.config.convert_user_reports();
+        let received = state.managed_envelope.received_at();
+        let timestamp = received.timestamp_millis() as f64 / 1000.0;
+
         state.managed_envelope.retain_items(|item| {
+            if item.ty() == &ItemType::Feedback {
+                // The feedback format is owned by Sentry, so Relay only ensures that the payload
+                // is a JSON object and leaves the schema to Sentry.
+                if !processing_enabled {
+                    return ItemAction::Keep;
+                }
+
+                return match serde_json::from_slice::<SerdeValue>(&item.payload()) {
+                    Ok(SerdeValue::Object(_)) => ItemAction::Keep,
+                    Ok(_) => {
+                        relay_log::debug!("invalid feedback: not a JSON object");
+                        ItemAction::Drop(Outcome::Invalid(DiscardReason::InvalidFeedback))
+                    }
+                    Err(error) => {
+                        relay_log::debug!(error = &error as &dyn Error, "invalid feedback");
+                        ItemAction::Drop(Outcome::Invalid(DiscardReason::InvalidFeedback))
+                    }
+                };
+            }
+
             if item.ty() != &ItemType::UserReport {
                 return ItemAction::Keep;
             };
@@ -906,6 +1148,26 @@ impl EnvelopeProcessorService {
                 }
             };
 
+            if convert {
+                // Replace the item in place, so that failures are counted as feedback.
+                *item = Item::new(ItemType::Feedback);
+
+                let feedback = Feedback::from_user_report(report, timestamp);
+                return match serde_json::to_vec(&feedback) {
+                    Ok(payload) => {
+                        item.set_payload(ContentType::Json, payload);
+                        ItemAction::Keep
+                    }
+                    Err(err) => {
+                        relay_log::error!(
+                            error = &err as &dyn Error,
+                            "failed to serialize feedback"
+                        );
+                        ItemAction::Drop(Outcome::Invalid(DiscardReason::Internal))
+                    }
+                };
+            }
+
             let json_string = match serde_json::to_string(&report) {
                 Ok(json) => json,
                 Err(err) => {
@@ -922,6 +1184,16 @@ impl EnvelopeProcessorService {
         });
     }
 
+    /// Removes trace attachments that do not specify the trace they belong to.
+    fn process_trace_attachments(&self, state: &mut ProcessEnvelopeState) {
+        state.managed_envelope.retain_items(|item| match item.ty() {
+            ItemType::TraceAttachment if item.trace_id().is_none() => {
+                ItemAction::Drop(Outcome::Invalid(DiscardReason::InvalidTraceAttachment))
+            }
+            _ => ItemAction::Keep,
+        });
+    }
+
     /// Validates and extracts client reports.
     ///
     /// At the moment client reports are primarily used to transfer outcomes from
@@ -929,10 +1201,13 @@ impl EnvelopeProcessorService {
     /// system.
     fn process_client_reports(&self, state: &mut ProcessEnvelopeState) {
         // if client outcomes are disabled we leave the the client reports unprocessed
-        // and pass them on.
-        if !self.inner.config.emit_outcomes().any() || !self.inner.config.emit_client_outcomes() {
+        // and pass them on, unless they are folded into the client reports of this Relay.
+        let config = &self.inner.config;
+        if !config.fold_client_reports()
+            && (!config.emit_outcomes().any() || !config.emit_client_outcomes())
+        {
             // if a processing relay has client outcomes disabled we drop them.
-            if self.inner.config.processing_enabled() {
+            if config.processing_enabled() {
                 state.managed_envelope.retain_items(|item| match item.ty() {
                     ItemType::ClientReport => ItemAction::DropSilently,
                     _ => ItemAction::Keep,
@@ -1104,28 +1379,133 @@ impl EnvelopeProcessorService {
     /// Normalize monitor check-ins and remove invalid ones.
     #[cfg(feature = "processing")]
     fn process_check_ins(&self, state: &mut ProcessEnvelopeState) {
+        let defaults = relay_monitors::CheckInDefaults {
+            checkin_margin: self.inner.config.monitor_checkin_margin(),
+            max_runtime: self.inner.config.monitor_max_runtime(),
+        };
+
         state.managed_envelope.retain_items(|item| {
             if item.ty() != &ItemType::CheckIn {
                 return ItemAction::Keep;
             }
 
-            match relay_monitors::process_check_in(&item.payload()) {
+            match relay_monitors::process_check_in(&item.payload(), &defaults) {
                 Ok(processed) => {
                     item.set_payload(ContentType::Json, processed);
                     ItemAction::Keep
                 }
                 Err(error) => {
-                    // TODO: Track an outcome.
                     relay_log::debug!(
                         error = &error as &dyn Error,
                         "dropped invalid monitor check-in"
                     );
-                    ItemAction::DropSilently
+                    let reason = match error {
+                        ProcessCheckInError::InvalidSchedule(_) => {
+                            DiscardReason::InvalidMonitorConfig
+                        }
+                        _ => DiscardReason::InvalidCheckIn,
+                    };
+                    ItemAction::Drop(Outcome::Invalid(reason))
                 }
             }
         })
     }
 
+    /// Validates, normalizes, and scrubs log items and extracts metrics from them.
+    ///
+    /// Metrics are extracted according to the `log` specs in the project's metric extraction
+    /// config, so that logs can drive alerts. Logs are only stored if the project has the logs
+    /// ingestion feature, and are removed from the envelope with an outcome otherwise.
+    ///
+    /// This runs before quotas are enforced, so that metrics are also extracted from logs that
+    /// exceed the log byte quota. Metrics are extracted from the scrubbed log.
+    #[cfg(feature = "processing")]
+    fn process_logs(&self, state: &mut ProcessEnvelopeState) -> Result<(), ProcessingError> {
+        let project_state = &state.project_state;
+        let ingestion_enabled = project_state.has_feature(Feature::OurLogsIngestion);
+
+        let metrics_config = match project_state.config.metric_extraction {
+            ErrorBoundary::Ok(ref config) if config.is_enabled() => {
+                Some(crate::metrics_extraction::log::restrict_tags(config))
+            }
+            _ => None,
+        };
+
+        let pii_config = project_state.config.pii_config.as_ref();
+        let datascrubbing_config = project_state
+            .config
+            .datascrubbing_settings
+            .pii_config()
+            .map_err(|e| ProcessingError::PiiConfigError(e.clone()))?;
+
+        let extracted_metrics = &mut state.extracted_metrics.project_metrics;
+
+        state.managed_envelope.retain_items(|item| {
+            if item.ty() != &ItemType::Log {
+                return ItemAction::Keep;
+            }
+
+            let disabled = ItemAction::Drop(Outcome::Invalid(DiscardReason::LogsDisabled));
+            if !ingestion_enabled && metrics_config.is_none() {
+                return disabled;
+            }
+
+            let invalid = || ItemAction::Drop(Outcome::Invalid(DiscardReason::InvalidLog));
+
+            let mut log = match Annotated::<Log>::from_json_bytes(&item.payload()) {
+                Ok(log) => log,
+                Err(error) => {
+                    relay_log::debug!(error = &error as &dyn Error, "dropped invalid log");
+                    return invalid();
+                }
+            };
+
+            if let Err(error) = log::validate(&mut log) {
+                relay_log::debug!(error = &error as &dyn Error, "dropped invalid log");
+                return invalid();
+            }
+
+            if let Some(inner_log) = log.value_mut() {
+                log::normalize(inner_log);
+            }
+
+            for config in [pii_config, datascrubbing_config.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                let mut processor = PiiProcessor::new(config.compiled());
+                if let Err(error) =
+                    processor::process_value(&mut log, &mut processor, ProcessingState::root())
+                {
+                    relay_log::debug!(error = &error as &dyn Error, "failed to scrub log");
+                    return invalid();
+                }
+            }
+
+            if let (Some(config), Some(log)) = (&metrics_config, log.value()) {
+                let metrics = crate::metrics_extraction::log::extract_metrics(log, config);
+                extracted_metrics.extend(metrics);
+            }
+
+            if !ingestion_enabled {
+                return disabled;
+            }
+
+            match log.to_json() {
+                Ok(payload) => {
+                    item.set_payload(ContentType::Json, payload);
+                    ItemAction::Keep
+                }
+                Err(error) => {
+                    relay_log::error!(error = &error as &dyn Error, "failed to serialize log");
+                    ItemAction::Drop(Outcome::Invalid(DiscardReason::Internal))
+                }
+            }
+        });
+
+        Ok(())
+    }
+
     /// Process profiles and set the profile ID in the profile context on the transaction if successful
     #[cfg(feature = "processing")]
     fn process_profiles(&self, state: &mut ProcessEnvelopeState) {
@@ -1177,14 +1557,43 @@ impl EnvelopeProcessorService {
         }
     }
 
+    /// Validates profile chunks and removes invalid ones.
+    ///
+    /// Chunks of continuous profiles are not associated with a transaction, so they are validated
+    /// independently and forwarded with their original payload.
+    #[cfg(feature = "processing")]
+    fn process_profile_chunks(&self, state: &mut ProcessEnvelopeState) {
+        state.managed_envelope.retain_items(|item| {
+            if item.ty() != &ItemType::ProfileChunk {
+                return ItemAction::Keep;
+            }
+
+            match relay_profiling::validate_profile_chunk(&item.payload()) {
+                Ok(_) => ItemAction::Keep,
+                Err(err) => {
+                    relay_log::debug!(error = &err as &dyn Error, "invalid profile chunk");
+                    ItemAction::Drop(Outcome::Invalid(DiscardReason::Profiling(
+                        relay_profiling::discard_reason(err),
+                    )))
+                }
+            }
+        });
+    }
+
     /// Remove replays if the feature flag is not enabled.
+    ///
+    /// Replay videos additionally require the video feature, and are dropped if the envelope does
+    /// not contain a valid replay event whose `replay_id` matches the envelope's event ID. The
+    /// video is produced under that ID, so this pairs it with the replay event of its segment.
     fn process_replays(&self, state: &mut ProcessEnvelopeState) -> Result<(), ProcessingError> {
         let project_state = &state.project_state;
         let replays_enabled = project_state.has_feature(Feature::SessionReplay);
+        let videos_enabled =
+            replays_enabled && project_state.has_feature(Feature::SessionReplayVideo);
         let scrubbing_enabled = project_state.has_feature(Feature::SessionReplayRecordingScrubbing);
 
         let meta = state.envelope().meta().clone();
-        let client_addr = meta.client_addr();
+        let client_addr = self.client_addr(&meta);
         let event_id = state.envelope().event_id();
 
         let limit = self.inner.config.max_replay_uncompressed_size();
@@ -1202,13 +1611,22 @@ impl EnvelopeProcessorService {
             client_hints: meta.client_hints().as_deref(),
         };
 
+        // Replay IDs of the valid replay events in the envelope.
+        let mut replay_ids = Vec::new();
+
         state.managed_envelope.retain_items(|item| match item.ty() {
             ItemType::ReplayEvent => {
                 if !replays_enabled {
                     return ItemAction::DropSilently;
                 }
 
-                match self.process_replay_event(&item.payload(), config, client_addr, user_agent) {
+                let replay =
+                    self.process_replay_event(&item.payload(), config, client_addr, user_agent);
+                if let Ok(ref replay) = replay {
+                    replay_ids.extend(replay.value().and_then(|r| r.replay_id.value().copied()));
+                }
+
+                match replay {
                     Ok(replay) => match replay.to_json() {
                         Ok(json) => {
                             item.set_payload(ContentType::Json, json);
@@ -1265,6 +1683,22 @@ impl EnvelopeProcessorService {
                     }
                 }
             }
+            ItemType::ReplayVideo if !videos_enabled => ItemAction::DropSilently,
+            _ => ItemAction::Keep,
+        });
+
+        // Videos are only ingested along with the replay event of their segment. This runs after
+        // replay events have been validated, so that videos of invalid replays are dropped too.
+        let has_replay_event = event_id.map_or(false, |id| replay_ids.contains(&id));
+
+        state.managed_envelope.retain_items(|item| match item.ty() {
+            ItemType::ReplayVideo if item.is_empty() => {
+                ItemAction::Drop(Outcome::Invalid(DiscardReason::InvalidReplayVideoEvent))
+            }
+            ItemType::ReplayVideo if !has_replay_event => {
+                relay_log::debug!("dropping replay video without matching replay event");
+                ItemAction::Drop(Outcome::Invalid(DiscardReason::InvalidReplayVideoEvent))
+            }
             _ => ItemAction::Keep,
         });
 
@@ -1352,6 +1786,9 @@ impl EnvelopeProcessorService {
         //  2. The DSN was moved and the envelope sent to the old project ID.
         envelope.meta_mut().set_project_id(project_id);
 
+        let inspector = self.inner.inspector.as_ref();
+        let inspection = inspector.and_then(EnvelopeInspector::sample);
+
         Ok(ProcessEnvelopeState {
             event: Annotated::empty(),
             event_metrics_extracted: false,
@@ -1364,6 +1801,7 @@ impl EnvelopeProcessorService {
             project_id,
             managed_envelope,
             has_profile: false,
+            inspection,
         })
     }
 
@@ -1395,8 +1833,12 @@ impl EnvelopeProcessorService {
         item: Item,
         event_type: Option<EventType>,
     ) -> Result<ExtractedEvent, ProcessingError> {
-        let mut event = Annotated::<Event>::from_json_bytes(&item.payload())
-            .map_err(ProcessingError::InvalidJson)?;
+        #[cfg(feature = "simd-json")]
+        let event = Annotated::<Event>::from_json_bytes_simd(&item.payload());
+        #[cfg(not(feature = "simd-json"))]
+        let event = Annotated::<Event>::from_json_bytes(&item.payload());
+
+        let mut event = event.map_err(ProcessingError::InvalidJson)?;
 
         if let Some(event_value) = event.value_mut() {
             event_value.ty.set_value(event_type);
@@ -1622,19 +2064,25 @@ impl EnvelopeProcessorService {
 
             // These may be forwarded to upstream / store:
             ItemType::Attachment => false,
+            ItemType::TraceAttachment => false,
             ItemType::UserReport => false,
+            ItemType::Feedback => false,
 
             // Aggregate data is never considered as part of deduplication
             ItemType::Session => false,
             ItemType::Sessions => false,
             ItemType::Statsd => false,
             ItemType::MetricBuckets => false,
+            ItemType::MetricMeta => false,
             ItemType::ClientReport => false,
             ItemType::Profile => false,
+            ItemType::ProfileChunk => false,
             ItemType::ReplayEvent => false,
             ItemType::ReplayRecording => false,
+            ItemType::ReplayVideo => false,
             ItemType::CheckIn => false,
             ItemType::Span => false,
+            ItemType::Log => false,
 
             // Without knowing more, `Unknown` items are allowed to be repeated
             ItemType::Unknown(_) => false,
@@ -1922,7 +2370,7 @@ impl EnvelopeProcessorService {
 
         let store_config = StoreConfig {
             project_id: Some(state.project_id.value()),
-            client_ip: envelope.meta().client_addr().map(IpAddr::from),
+            client_ip: self.client_addr(envelope.meta()).map(IpAddr::from),
             client: envelope.meta().client().map(str::to_owned),
             key_id,
             protocol_version: Some(envelope.meta().version().to_string()),
@@ -2004,37 +2452,72 @@ impl EnvelopeProcessorService {
             None => return Ok(()),
         };
 
-        let client_ip = state.managed_envelope.envelope().meta().client_addr();
+        let client_ip = self.client_addr(state.managed_envelope.envelope().meta());
         let filter_settings = &state.project_state.config.filter_settings;
 
         metric!(timer(RelayTimers::EventProcessingFiltering), {
             relay_filter::should_filter(event, client_ip, filter_settings).map_err(|err| {
+                if let Some(ref mut inspection) = state.inspection {
+                    inspection.filter(err.name());
+                }
                 state.managed_envelope.reject(Outcome::Filtered(err));
                 ProcessingError::EventFiltered(err)
             })
         })
     }
 
-    #[cfg(feature = "processing")]
+    /// Enforces quotas with the rate limiter configured for this Relay.
+    ///
+    /// Processing Relays count quotas in Redis. Other Relays can enforce quotas approximately in
+    /// memory if the local rate limiter is enabled, see [`Config::local_rate_limiting`].
     fn enforce_quotas(&self, state: &mut ProcessEnvelopeState) -> Result<(), ProcessingError> {
-        let rate_limiter = match self.inner.rate_limiter.as_ref() {
-            Some(rate_limiter) => rate_limiter,
-            None => return Ok(()),
+        let _span = relay_log::debug_span!("envelope.rate_limit").entered();
+
+        #[cfg(feature = "processing")]
+        if let Some(rate_limiter) = self.inner.rate_limiter.as_ref() {
+            if state.project_state.config.quotas.is_empty() {
+                return Ok(());
+            }
+
+            return self.enforce_quotas_with(rate_limiter, state);
+        }
+
+        let Some(rate_limiter) = self.inner.local_rate_limiter.as_ref() else {
+            return Ok(());
         };
 
+        let static_quotas = self.inner.config.local_rate_limiting();
+        let static_quotas = static_quotas.as_deref().map_or(&[][..], Vec::as_slice);
+        self.enforce_quotas_with(&rate_limiter.with_static_quotas(static_quotas), state)
+    }
+
+    fn enforce_quotas_with<R>(
+        &self,
+        rate_limiter: &R,
+        state: &mut ProcessEnvelopeState,
+    ) -> Result<(), ProcessingError>
+    where
+        R: RateLimiter,
+        ProcessingError: From<R::Error>,
+    {
         let project_state = &state.project_state;
         let quotas = project_state.config.quotas.as_slice();
-        if quotas.is_empty() {
-            return Ok(());
-        }
 
         let event_category = state.event_category();
+        let event = state.event.value();
 
         // When invoking the rate limiter, capture if the event item has been rate limited to also
         // remove it from the processing state eventually.
         let mut envelope_limiter =
             EnvelopeLimiter::new(Some(&project_state.config), |item_scope, quantity| {
-                rate_limiter.is_rate_limited(quotas, item_scope, quantity, false)
+                // Expose the event to quotas that are partitioned by an event attribute.
+                let item_scope = match event {
+                    Some(event) => item_scope.with_attributes(event),
+                    None => item_scope,
+                };
+                rate_limiter
+                    .is_rate_limited(quotas, item_scope, quantity, false)
+                    .map_err(ProcessingError::from)
             });
 
         // Tell the envelope limiter about the event, since it has been removed from the Envelope at
@@ -2048,6 +2531,10 @@ impl EnvelopeProcessorService {
             envelope_limiter.enforce(state.managed_envelope.envelope_mut(), &scoping)?
         });
 
+        if let Some(ref mut inspection) = state.inspection {
+            inspection.rate_limits(&limits);
+        }
+
         if limits.is_limited() {
             self.inner
                 .project_cache
@@ -2106,6 +2593,8 @@ impl EnvelopeProcessorService {
                     let extractor = TransactionExtractor {
                         config: tx_config,
                         generic_tags: config.map(|c| c.tags.as_slice()).unwrap_or_default(),
+                        overrides: self.inner.config.transaction_metrics_overrides(),
+                        override_tags: &self.inner.transaction_metrics_tags,
                         transaction_from_dsc,
                         sampling_result: &state.sampling_result,
                         has_profile: state.has_profile,
@@ -2253,14 +2742,117 @@ impl EnvelopeProcessorService {
             && !(op == "db.sql.query" && !(description.contains(r#""$"#) || system == "mongodb"))
     }
 
+    /// Normalizes, scrubs, and samples standalone spans, and extracts metrics from them.
+    ///
+    /// Standalone spans are sent by SDKs outside of a transaction. They are dropped unless the
+    /// project has the standalone span ingestion feature. Sampling is based on the trace only,
+    /// since there is no transaction to match rules against. Like for transactions, metrics are
+    /// extracted from all spans, including the ones dropped by sampling.
     #[cfg(feature = "processing")]
-    fn extract_spans(&self, state: &mut ProcessEnvelopeState) {
-        // For now, drop any spans submitted by the SDK.
-        state.managed_envelope.retain_items(|item| match item.ty() {
-            ItemType::Span => ItemAction::DropSilently,
-            _ => ItemAction::Keep,
+    fn process_spans(&self, state: &mut ProcessEnvelopeState) -> Result<(), ProcessingError> {
+        let project_state = &state.project_state;
+
+        if !project_state.has_feature(Feature::StandaloneSpanIngestion) {
+            state.managed_envelope.retain_items(|item| match item.ty() {
+                ItemType::Span => ItemAction::DropSilently,
+                _ => ItemAction::Keep,
+            });
+            return Ok(());
+        }
+
+        let sampling_result = utils::get_sampling_result(
+            self.inner.config.processing_enabled(),
+            Some(project_state),
+            state.sampling_project_state.as_deref(),
+            state.managed_envelope.envelope().dsc(),
+            None,
+        );
+
+        let pii_config = project_state.config.pii_config.as_ref();
+        let datascrubbing_config = project_state
+            .config
+            .datascrubbing_settings
+            .pii_config()
+            .map_err(|e| ProcessingError::PiiConfigError(e.clone()))?;
+
+        let metrics_config = match project_state.config.metric_extraction {
+            ErrorBoundary::Ok(ref config) if config.is_enabled() => Some(config),
+            _ => None,
+        }
+        .filter(|_| project_state.has_feature(Feature::SpanMetricsExtraction));
+
+        let normalization_config = StandaloneSpanConfig {
+            max_tag_value_length: self
+                .inner
+                .config
+                .aggregator_config_for(MetricNamespace::Spans)
+                .max_tag_value_length,
+            span_description_rules: project_state
+                .config
+                .span_description_rules
+                .as_deref()
+                .unwrap_or_default(),
+        };
+
+        let extracted_metrics = &mut state.extracted_metrics.project_metrics;
+
+        state.managed_envelope.retain_items(|item| {
+            if item.ty() != &ItemType::Span {
+                return ItemAction::Keep;
+            }
+
+            let mut span = match Annotated::<Span>::from_json_bytes(&item.payload()) {
+                Ok(span) => span,
+                Err(error) => {
+                    relay_log::debug!(error = &error as &dyn Error, "dropped invalid span");
+                    return ItemAction::Drop(Outcome::Invalid(DiscardReason::InvalidSpan));
+                }
+            };
+
+            let Some(inner_span) = span.value_mut() else {
+                return ItemAction::Drop(Outcome::Invalid(DiscardReason::InvalidSpan));
+            };
+            normalize_standalone_span(inner_span, &normalization_config);
+
+            for config in [pii_config, datascrubbing_config.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                let mut processor = PiiProcessor::new(config.compiled());
+                if let Err(error) =
+                    processor::process_value(&mut span, &mut processor, ProcessingState::root())
+                {
+                    relay_log::debug!(error = &error as &dyn Error, "failed to scrub span");
+                    return ItemAction::Drop(Outcome::Invalid(DiscardReason::InvalidSpan));
+                }
+            }
+
+            if let (Some(config), Some(span)) = (metrics_config, span.value()) {
+                let metrics = crate::metrics_extraction::event::extract_span_metrics(span, config);
+                extracted_metrics.extend(metrics);
+            }
+
+            if let SamplingResult::Drop(ref rule_ids) = sampling_result {
+                return ItemAction::Drop(Outcome::FilteredSampling(rule_ids.clone()));
+            }
+
+            match span.to_json() {
+                Ok(payload) => {
+                    item.set_payload(ContentType::Json, payload);
+                    ItemAction::Keep
+                }
+                Err(error) => {
+                    relay_log::error!(error = &error as &dyn Error, "failed to serialize span");
+                    ItemAction::Drop(Outcome::Invalid(DiscardReason::Internal))
+                }
+            }
         });
 
+        Ok(())
+    }
+
+    #[cfg(feature = "processing")]
+    fn extract_spans(&self, state: &mut ProcessEnvelopeState) {
         // Only extract spans from transactions (not errors).
         if state.event_type() != Some(EventType::Transaction) {
             return;
@@ -2284,6 +2876,7 @@ impl EnvelopeProcessorService {
             };
             let mut item = Item::new(ItemType::Span);
             item.set_payload(ContentType::Json, span);
+            item.set_from_transaction(true);
             state.managed_envelope.envelope_mut().add_item(item);
         };
 
@@ -2351,6 +2944,8 @@ impl EnvelopeProcessorService {
                         self.compute_sampling_decision(state);
                     }
                 }
+
+                self.compute_adaptive_sampling_decision(state);
             }
 
             _ => {}
@@ -2368,13 +2963,40 @@ impl EnvelopeProcessorService {
         );
     }
 
-    /// Runs dynamic sampling on an incoming error and tags it in case of successful sampling
-    /// decision.
+    /// Applies local adaptive sampling to a transaction that was kept by sampling rules.
     ///
-    /// This execution of dynamic sampling is technically a "simulation" since we will use the result
-    /// only for tagging errors and not for actually sampling incoming events.
-    fn tag_error_with_sampling_decision(&self, state: &mut ProcessEnvelopeState) {
-        if state.event.is_empty() {
+    /// The random seed is the trace ID if available, so that all transactions of a trace receive
+    /// the same decision as long as their projects are sampled at the same rate.
+    fn compute_adaptive_sampling_decision(&self, state: &mut ProcessEnvelopeState) {
+        let Some(ref sampler) = self.inner.adaptive_sampler else {
+            return;
+        };
+
+        if state.sampling_result != SamplingResult::Keep {
+            return;
+        }
+
+        let trace_id = state.envelope().dsc().map(|dsc| dsc.trace_id);
+        let event_id = state
+            .event
+            .value()
+            .and_then(|e| e.id.value())
+            .map(|id| id.0);
+        let Some(seed) = trace_id.or(event_id) else {
+            return;
+        };
+
+        let project_key = state.envelope().meta().public_key();
+        state.sampling_result = utils::get_adaptive_sampling_result(sampler, project_key, seed);
+    }
+
+    /// Runs dynamic sampling on an incoming error and tags it in case of successful sampling
+    /// decision.
+    ///
+    /// This execution of dynamic sampling is technically a "simulation" since we will use the result
+    /// only for tagging errors and not for actually sampling incoming events.
+    fn tag_error_with_sampling_decision(&self, state: &mut ProcessEnvelopeState) {
+        if state.event.is_empty() {
             return;
         }
 
@@ -2410,6 +3032,9 @@ impl EnvelopeProcessorService {
             SamplingResult::Drop(rule_ids)
                 if state.event_type() == Some(EventType::Transaction) =>
             {
+                if let Some(ref mut inspection) = state.inspection {
+                    inspection.sampling_rules(&rule_ids);
+                }
                 state
                     .managed_envelope
                     .reject(Outcome::FilteredSampling(rule_ids.clone()));
@@ -2425,7 +3050,7 @@ impl EnvelopeProcessorService {
         state: &mut ProcessEnvelopeState,
     ) -> Result<(), ProcessingError> {
         let request_meta = state.managed_envelope.envelope().meta();
-        let client_ipaddr = request_meta.client_addr().map(IpAddr::from);
+        let client_ipaddr = self.client_addr(request_meta).map(IpAddr::from);
 
         let light_normalize_spans = state
             .project_state
@@ -2434,6 +3059,7 @@ impl EnvelopeProcessorService {
         utils::log_transaction_name_metrics(&mut state.event, |event| {
             let config = LightNormalizationConfig {
                 client_ip: client_ipaddr.as_ref(),
+                ipv6_prefix: self.inner.config.ipv6_prefix(),
                 user_agent: RawUserAgentInfo {
                     user_agent: request_meta.user_agent(),
                     client_hints: request_meta.client_hints().as_deref(),
@@ -2502,6 +3128,7 @@ impl EnvelopeProcessorService {
         self.process_sessions(state);
         self.process_client_reports(state);
         self.process_user_reports(state);
+        self.process_trace_attachments(state);
         self.process_replays(state)?;
         self.filter_profiles(state);
 
@@ -2535,10 +3162,17 @@ impl EnvelopeProcessorService {
         }
 
         if_processing!({
-            self.enforce_quotas(state)?;
+            self.process_logs(state)?;
+        });
+
+        self.enforce_quotas(state)?;
+
+        if_processing!({
             // We need the event parsed in order to set the profile context on it
             self.process_profiles(state);
+            self.process_profile_chunks(state);
             self.process_check_ins(state);
+            self.process_spans(state)?;
         });
 
         if state.has_event() {
@@ -2554,6 +3188,13 @@ impl EnvelopeProcessorService {
         Ok(())
     }
 
+    /// Writes the processing decisions and the envelope with the configured inspector.
+    fn inspect(&self, inspection: Inspection, state: &ProcessEnvelopeState, scrubbed: bool) {
+        if let Some(ref inspector) = self.inner.inspector {
+            inspector.write(inspection, state.project_id, state.envelope(), scrubbed);
+        }
+    }
+
     fn process(
         &self,
         message: ProcessEnvelope,
@@ -2587,6 +3228,10 @@ impl EnvelopeProcessorService {
                             self.inner.project_cache.clone(),
                         );
 
+                        if let Some(inspection) = state.inspection.take() {
+                            self.inspect(inspection, &state, true);
+                        }
+
                         let envelope_response = if state.managed_envelope.envelope().is_empty() {
                             if !has_metrics {
                                 // Individual rate limits have already been issued
@@ -2604,6 +3249,13 @@ impl EnvelopeProcessorService {
                         })
                     }
                     Err(err) => {
+                        if let Some(mut inspection) = state.inspection.take() {
+                            if let Some(outcome) = err.to_outcome() {
+                                inspection.outcome(outcome);
+                            }
+                            self.inspect(inspection, &state, false);
+                        }
+
                         if let Some(outcome) = err.to_outcome() {
                             state.managed_envelope.reject(outcome);
                         }
@@ -2622,35 +3274,48 @@ impl EnvelopeProcessorService {
         )
     }
 
-    fn handle_process_envelope(&self, message: ProcessEnvelope) {
-        let project_key = message.envelope.envelope().meta().public_key();
-        let wait_time = message.envelope.start_time().elapsed();
-        metric!(timer(RelayTimers::EnvelopeWaitTime) = wait_time);
+    fn handle_process_envelope(&self, mut message: ProcessEnvelope) {
+        let _span =
+            relay_log::debug_span!(parent: message.envelope.span(), "envelope.process").entered();
 
-        let result = metric!(timer(RelayTimers::EnvelopeProcessingTime), {
-            self.process(message)
-        });
+        // Attach the path of the envelope to internal errors reported during processing.
+        message.envelope.record_stage("process");
+        let breadcrumbs = message.envelope.breadcrumbs().to_vec();
 
-        match result {
-            Ok(response) => {
-                if let Some(managed_envelope) = response.envelope {
-                    self.inner.envelope_manager.send(SubmitEnvelope {
-                        envelope: managed_envelope,
-                    })
-                };
-            }
-            Err(error) => {
-                // Errors are only logged for what we consider infrastructure or implementation
-                // bugs. In other cases, we "expect" errors and log them as debug level.
-                if error.is_unexpected() {
-                    relay_log::error!(
-                        tags.project_key = %project_key,
-                        error = &error as &dyn Error,
-                        "error processing envelope"
-                    );
+        relay_log::with_scope(
+            |scope| scope.add_breadcrumb(breadcrumbs),
+            || {
+                let project_key = message.envelope.envelope().meta().public_key();
+                let wait_time = message.envelope.start_time().elapsed();
+                metric!(timer(RelayTimers::EnvelopeWaitTime) = wait_time);
+
+                let result = metric!(timer(RelayTimers::EnvelopeProcessingTime), {
+                    self.process(message)
+                });
+
+                match result {
+                    Ok(response) => {
+                        if let Some(managed_envelope) = response.envelope {
+                            self.inner.envelope_manager.send(SubmitEnvelope {
+                                envelope: managed_envelope,
+                            })
+                        };
+                    }
+                    Err(error) => {
+                        // Errors are only logged for what we consider infrastructure or
+                        // implementation bugs. In other cases, we "expect" errors and log them as
+                        // debug level.
+                        if error.is_unexpected() {
+                            relay_log::error!(
+                                tags.project_key = %project_key,
+                                error = &error as &dyn Error,
+                                "error processing envelope"
+                            );
+                        }
+                    }
                 }
-            }
-        }
+            },
+        );
     }
 
     fn handle_process_metrics(&self, message: ProcessMetrics) {
@@ -2708,6 +3373,23 @@ impl EnvelopeProcessorService {
                         metric!(counter(RelayCounters::MetricBucketsParsingFailed) += 1);
                     }
                 }
+            } else if item.ty() == &ItemType::MetricMeta {
+                match serde_json::from_slice::<MetricMeta>(&payload) {
+                    Ok(mut meta) => {
+                        clock_drift_processor.process_timestamp(&mut meta.timestamp);
+
+                        relay_log::trace!("adding metric meta to project cache");
+                        self.inner.project_cache.send(AddMetricMeta {
+                            project_key: public_key,
+                            meta,
+                            received_at: received_timestamp,
+                        });
+                    }
+                    Err(error) => relay_log::debug!(
+                        error = &error as &dyn Error,
+                        "failed to parse metric meta",
+                    ),
+                }
             } else {
                 relay_log::error!(
                     "invalid item of type {} passed to ProcessMetrics",
@@ -2720,8 +3402,6 @@ impl EnvelopeProcessorService {
     /// Check and apply rate limits to metrics buckets.
     #[cfg(feature = "processing")]
     fn handle_rate_limit_flush_buckets(&self, message: RateLimitFlushBuckets) {
-        use relay_quotas::ItemScoping;
-
         let RateLimitFlushBuckets {
             mut bucket_limiter,
             partition_key,
@@ -2730,20 +3410,48 @@ impl EnvelopeProcessorService {
         let scoping = *bucket_limiter.scoping();
 
         if let Some(rate_limiter) = self.inner.rate_limiter.as_ref() {
-            let item_scoping = ItemScoping {
-                category: DataCategory::Transaction,
-                scoping: &scoping,
-            };
-
             // We set over_accept_once such that the limit is actually reached, which allows subsequent
             // calls with quantity=0 to be rate limited.
             let over_accept_once = true;
-            let rate_limits = rate_limiter.is_rate_limited(
-                bucket_limiter.quotas(),
-                item_scoping,
-                bucket_limiter.transaction_count(),
-                over_accept_once,
-            );
+            let mut rate_limits = Ok(RateLimits::new());
+
+            if bucket_limiter.has_transactions() {
+                let item_scoping = ItemScoping {
+                    category: DataCategory::Transaction,
+                    scoping: &scoping,
+                    attributes: None,
+                    namespace: None,
+                };
+
+                rate_limits = rate_limiter.is_rate_limited(
+                    bucket_limiter.quotas(),
+                    item_scoping,
+                    bucket_limiter.transaction_count(),
+                    over_accept_once,
+                );
+            }
+
+            for (namespace, quantity) in bucket_limiter.limited_namespaces() {
+                if rate_limits.is_err() {
+                    break;
+                }
+
+                let item_scoping = scoping
+                    .item(DataCategory::MetricBucket)
+                    .with_namespace(namespace);
+
+                let namespace_limits = rate_limiter.is_rate_limited(
+                    bucket_limiter.quotas(),
+                    item_scoping,
+                    quantity,
+                    over_accept_once,
+                );
+
+                rate_limits = rate_limits.and_then(|mut limits| {
+                    limits.merge(namespace_limits?);
+                    Ok(limits)
+                });
+            }
 
             let was_enforced = bucket_limiter.enforce_limits(
                 rate_limits.as_ref().map_err(|_| ()),
@@ -2772,34 +3480,49 @@ impl EnvelopeProcessorService {
     }
 
     fn encode_envelope_body(
-        body: Vec<u8>,
+        body: Bytes,
         http_encoding: HttpEncoding,
-    ) -> Result<Vec<u8>, std::io::Error> {
-        let envelope_body = match http_encoding {
-            HttpEncoding::Identity => body,
+        level: Option<u32>,
+    ) -> Result<Bytes, std::io::Error> {
+        let envelope_body: Vec<u8> = match http_encoding {
+            HttpEncoding::Identity => return Ok(body),
             HttpEncoding::Deflate => {
-                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                let compression =
+                    level.map_or(Compression::default(), |l| Compression::new(l.min(9)));
+                let mut encoder = ZlibEncoder::new(Vec::new(), compression);
                 encoder.write_all(body.as_ref())?;
                 encoder.finish()?
             }
             HttpEncoding::Gzip => {
-                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                let compression =
+                    level.map_or(Compression::default(), |l| Compression::new(l.min(9)));
+                let mut encoder = GzEncoder::new(Vec::new(), compression);
                 encoder.write_all(body.as_ref())?;
                 encoder.finish()?
             }
             HttpEncoding::Br => {
                 // Use default buffer size (via 0), medium quality (5), and the default lgwin (22).
-                let mut encoder = BrotliEncoder::new(Vec::new(), 0, 5, 22);
+                let quality = level.map_or(5, |l| l.min(11));
+                let mut encoder = BrotliEncoder::new(Vec::new(), 0, quality, 22);
                 encoder.write_all(body.as_ref())?;
                 encoder.into_inner()
             }
+            HttpEncoding::Zstd => {
+                let level =
+                    level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| l.clamp(1, 22) as i32);
+                zstd::encode_all(body.as_ref(), level)?
+            }
         };
-        Ok(envelope_body)
+        Ok(envelope_body.into())
     }
 
     fn handle_encode_envelope(&self, message: EncodeEnvelope) {
         let mut request = message.request;
-        match Self::encode_envelope_body(request.envelope_body, request.http_encoding) {
+        match Self::encode_envelope_body(
+            request.envelope_body,
+            request.http_encoding,
+            self.inner.config.http_encoding_level(),
+        ) {
             Err(e) => {
                 request
                     .response_sender
@@ -2833,9 +3556,19 @@ impl Service for EnvelopeProcessorService {
         let thread_count = self.inner.config.cpu_concurrency();
         relay_log::info!("starting {thread_count} envelope processing workers");
 
-        tokio::spawn(async move {
-            let semaphore = Arc::new(Semaphore::new(thread_count));
+        // Lanes are added in the order of `ProcessorLane`, so its discriminant is the index.
+        let config = self.inner.config.processor_lanes();
+        let mut lanes = WeightedLanes::new(thread_count);
+        let lane_ids = [
+            config.normalization,
+            config.pii,
+            config.attachments,
+            config.metrics,
+            config.encoding,
+        ]
+        .map(|lane| lanes.add_lane(lane.weight, lane.max_concurrency));
 
+        tokio::spawn(async move {
             let Ok(mut subscription) = self.inner.global_config.send(Subscribe).await else {
                 // TODO(iker): we accept this sub-optimal error handling. TBD
                 // the approach to deal with failures on the subscription
@@ -2848,26 +3581,43 @@ impl Service for EnvelopeProcessorService {
             // should immediatly use the content of the watch.
             self.global_config = subscription.borrow().clone();
 
+            let mut report_ticker = tokio::time::interval(LANE_REPORT_INTERVAL);
+            let mut rx_closed = false;
+
             loop {
-                let next_msg = async {
-                    let permit_result = semaphore.clone().acquire_owned().await;
-                    // `permit_result` might get dropped when this future is cancelled while awaiting
-                    // `rx.recv()`. This is OK though: No envelope is received so the permit is not
-                    // required.
-                    (rx.recv().await, permit_result)
-                };
+                while let Some((message, permit)) = lanes.pop() {
+                    let service = self.clone();
+                    tokio::task::spawn_blocking(move || {
+                        service.handle_message(message);
+                        drop(permit);
+                    });
+                }
 
                 tokio::select! {
                    biased;
 
                     Ok(()) = subscription.changed() => self.global_config = subscription.borrow().clone(),
-                    (Some(message), Ok(permit)) = next_msg => {
-                        let service = self.clone();
-                        tokio::task::spawn_blocking(move || {
-                            service.handle_message(message);
-                            drop(permit);
-                        });
+                    message = rx.recv(), if !rx_closed => match message {
+                        Some(message) => {
+                            let lane = message.lane();
+                            lanes.push(lane_ids[lane as usize], message);
+                        }
+                        None => rx_closed = true,
                     },
+                    // A worker became available for pending messages.
+                    () = lanes.ready(), if !lanes.is_empty() => (),
+                    _ = report_ticker.tick(), if !rx_closed || !lanes.is_empty() => {
+                        for (lane, id) in ProcessorLane::ALL.into_iter().zip(lane_ids) {
+                            metric!(
+                                gauge(RelayGauges::ProcessorLaneQueueSize) = lanes.len(id) as u64,
+                                lane = lane.name()
+                            );
+                            metric!(
+                                gauge(RelayGauges::ProcessorLaneActive) = lanes.active(id) as u64,
+                                lane = lane.name()
+                            );
+                        }
+                    }
 
                     else => break
                 }
@@ -2878,12 +3628,14 @@ impl Service for EnvelopeProcessorService {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeSet;
     use std::env;
     use std::str::FromStr;
 
     use chrono::{DateTime, TimeZone, Utc};
     use relay_base_schema::metrics::{DurationUnit, MetricUnit};
     use relay_common::glob2::LazyGlob;
+    use relay_dynamic_config::FeatureSet;
     use relay_event_normalization::{MeasurementsConfig, RedactionRule, TransactionNameRule};
     use relay_event_schema::protocol::{EventId, TransactionSource};
     use relay_pii::DataScrubbingConfig;
@@ -2893,6 +3645,7 @@ mod tests {
     };
     use relay_test::mock_service;
     use similar_asserts::assert_eq;
+    use tokio::sync::Semaphore;
     use uuid::Uuid;
 
     use crate::actors::test_store::TestStore;
@@ -3110,6 +3863,7 @@ mod tests {
                 ),
                 has_profile: false,
                 event_metrics_extracted: false,
+                inspection: None,
             }
         };
 
@@ -3176,6 +3930,7 @@ mod tests {
                     test_store.clone(),
                 ),
                 has_profile: false,
+                inspection: None,
             };
 
             // TODO: This does not test if the sampling decision is actually applied. This should be
@@ -3320,8 +4075,12 @@ mod tests {
             upstream_relay,
             #[cfg(feature = "processing")]
             rate_limiter: None,
+            local_rate_limiter: None,
             geoip_lookup: None,
             global_config,
+            adaptive_sampler: None,
+            inspector: None,
+            transaction_metrics_tags: Vec::new(),
         };
 
         EnvelopeProcessorService {
@@ -3369,6 +4128,243 @@ mod tests {
         assert_eq!(new_envelope.items().next().unwrap().ty(), &ItemType::Event);
     }
 
+    /// Processes an envelope with a single item of the given type and payload.
+    fn process_single_item(config: Config, ty: ItemType, payload: &'static str) -> Vec<Item> {
+        let processor = create_test_processor(config);
+        let (outcome_aggregator, test_store) = services();
+
+        let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+            .parse()
+            .unwrap();
+        let mut envelope = Envelope::from_request(None, RequestMeta::new(dsn));
+        envelope.add_item({
+            let mut item = Item::new(ty);
+            item.set_payload(ContentType::Json, payload);
+            item
+        });
+
+        let message = ProcessEnvelope {
+            envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+            project_state: Arc::new(ProjectState::allowed()),
+            sampling_project_state: None,
+        };
+
+        let envelope_response = processor.process(message).unwrap();
+        match envelope_response.envelope {
+            Some(ctx) => {
+                let items = ctx.envelope().items().cloned().collect();
+                ctx.accept();
+                items
+            }
+            None => Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_feedback_forwarded_without_processing() {
+        let items = process_single_item(Config::default(), ItemType::Feedback, "[]");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].ty(), &ItemType::Feedback);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "processing")]
+    async fn test_feedback_invalid() {
+        let config = || {
+            Config::from_json_value(serde_json::json!({
+                "processing": {
+                    "enabled": true,
+                    "kafka_config": [],
+                }
+            }))
+            .unwrap()
+        };
+
+        // Unknown fields are left to Sentry.
+        let items = process_single_item(config(), ItemType::Feedback, r#"{"foo": "bar"}"#);
+        assert_eq!(items.len(), 1);
+
+        let items = process_single_item(config(), ItemType::Feedback, "[]");
+        assert!(items.is_empty());
+
+        let items = process_single_item(config(), ItemType::Feedback, "{");
+        assert!(items.is_empty());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "processing")]
+    async fn test_user_report_converted_to_feedback() {
+        let config = Config::from_json_value(serde_json::json!({
+            "processing": {
+                "enabled": true,
+                "kafka_config": [],
+                "convert_user_reports": true,
+            }
+        }))
+        .unwrap();
+
+        let items = process_single_item(
+            config,
+            ItemType::UserReport,
+            r#"{
+                "event_id": "52df9022835246eeb317dbd739ccd059",
+                "name": "Jane",
+                "email": "jane@example.org",
+                "comments": "It broke."
+            }"#,
+        );
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].ty(), &ItemType::Feedback);
+
+        let feedback: SerdeValue = serde_json::from_slice(&items[0].payload()).unwrap();
+        assert_eq!(feedback["event_id"], "578830d62e5c5b1a90ca7d628fffeb66");
+        assert_eq!(
+            feedback["contexts"]["feedback"],
+            serde_json::json!({
+                "message": "It broke.",
+                "contact_email": "jane@example.org",
+                "name": "Jane",
+                "associated_event_id": "52df9022835246eeb317dbd739ccd059",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trace_attachment_requires_trace_id() {
+        let processor = create_test_processor(Default::default());
+        let (outcome_aggregator, test_store) = services();
+
+        let bytes = Bytes::from(
+            "\
+             {\"dsn\":\"https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42\"}\n\
+             {\"type\":\"trace_attachment\",\"length\":3,\"trace_id\":\"52df9022835246eeb317dbd739ccd059\"}\n\
+             foo\n\
+             {\"type\":\"trace_attachment\",\"length\":3}\n\
+             bar\n\
+             ",
+        );
+        let envelope = Envelope::parse_bytes(bytes).unwrap();
+
+        let message = ProcessEnvelope {
+            envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+            project_state: Arc::new(ProjectState::allowed()),
+            sampling_project_state: None,
+        };
+
+        let envelope_response = processor.process(message).unwrap();
+        let ctx = envelope_response.envelope.unwrap();
+        let new_envelope = ctx.envelope();
+
+        assert_eq!(new_envelope.len(), 1);
+        let item = new_envelope.items().next().unwrap();
+        assert_eq!(item.ty(), &ItemType::TraceAttachment);
+        assert!(item.trace_id().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_replay_video_requires_replay_event() {
+        let processor = create_test_processor(Default::default());
+
+        let mut project_state = ProjectState::allowed();
+        project_state.config.features = FeatureSet(BTreeSet::from([
+            Feature::SessionReplay,
+            Feature::SessionReplayVideo,
+        ]));
+        let project_state = Arc::new(project_state);
+
+        let process = |replay_id: Option<&str>| {
+            let (outcome_aggregator, test_store) = services();
+            let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+                .parse()
+                .unwrap();
+            let event_id = "52df9022835246eeb317dbd739ccd059".parse().unwrap();
+            let mut envelope = Envelope::from_request(Some(event_id), RequestMeta::new(dsn));
+
+            if let Some(replay_id) = replay_id {
+                envelope.add_item({
+                    let mut item = Item::new(ItemType::ReplayEvent);
+                    item.set_payload(
+                        ContentType::Json,
+                        format!(
+                            r#"{{"type":"replay_event","replay_id":"{replay_id}","segment_id":0}}"#
+                        ),
+                    );
+                    item
+                });
+            }
+
+            envelope.add_item({
+                let mut item = Item::new(ItemType::ReplayVideo);
+                item.set_payload(ContentType::OctetStream, &b"video"[..]);
+                item
+            });
+
+            let message = ProcessEnvelope {
+                envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+                project_state: project_state.clone(),
+                sampling_project_state: None,
+            };
+
+            let envelope_response = processor.process(message).unwrap();
+            envelope_response.envelope.map(|ctx| {
+                ctx.envelope()
+                    .items()
+                    .map(|item| item.ty().clone())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            process(Some("52df9022835246eeb317dbd739ccd059")),
+            Some(vec![ItemType::ReplayEvent, ItemType::ReplayVideo])
+        );
+        assert_eq!(process(None), None);
+    }
+
+    #[tokio::test]
+    async fn test_replay_video_replay_id_mismatch() {
+        let processor = create_test_processor(Default::default());
+
+        let mut project_state = ProjectState::allowed();
+        project_state.config.features = FeatureSet(BTreeSet::from([
+            Feature::SessionReplay,
+            Feature::SessionReplayVideo,
+        ]));
+
+        let (outcome_aggregator, test_store) = services();
+        let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+            .parse()
+            .unwrap();
+        let event_id = "52df9022835246eeb317dbd739ccd059".parse().unwrap();
+        let mut envelope = Envelope::from_request(Some(event_id), RequestMeta::new(dsn));
+
+        envelope.add_item({
+            let mut item = Item::new(ItemType::ReplayEvent);
+            item.set_payload(
+                ContentType::Json,
+                r#"{"type":"replay_event","replay_id":"fc6d8c0c43fc4630ad850ee518f1b9d0","segment_id":0}"#,
+            );
+            item
+        });
+        envelope.add_item({
+            let mut item = Item::new(ItemType::ReplayVideo);
+            item.set_payload(ContentType::OctetStream, &b"video"[..]);
+            item
+        });
+
+        let message = ProcessEnvelope {
+            envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+            project_state: Arc::new(project_state),
+            sampling_project_state: None,
+        };
+
+        let envelope_response = processor.process(message).unwrap();
+        let ctx = envelope_response.envelope.unwrap();
+        let items = ctx.envelope().items().map(|item| item.ty().clone());
+        assert_eq!(items.collect::<Vec<_>>(), vec![ItemType::ReplayEvent]);
+    }
+
     fn process_envelope_with_root_project_state(
         envelope: Box<Envelope>,
         sampling_project_state: Option<Arc<ProjectState>>,
@@ -3770,6 +4766,226 @@ mod tests {
         assert!(envelope_response.envelope.is_none());
     }
 
+    /// Runs a processing step on an envelope with a single item in processing mode.
+    ///
+    /// Returns the remaining envelope and the extracted metrics.
+    #[cfg(feature = "processing")]
+    fn process_item<F>(
+        item: Item,
+        project_state: ProjectState,
+        sampling_project_state: Option<ProjectState>,
+        step: F,
+    ) -> (Envelope, Vec<Bucket>)
+    where
+        F: FnOnce(&EnvelopeProcessorService, &mut ProcessEnvelopeState),
+    {
+        let config = Config::from_json_value(serde_json::json!({
+            "processing": {
+                "enabled": true,
+                "kafka_config": [],
+            }
+        }))
+        .unwrap();
+        let processor = create_test_processor(config);
+        let (outcome_aggregator, test_store) = services();
+
+        let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+            .parse()
+            .unwrap();
+        let mut envelope = Envelope::from_request(None, RequestMeta::new(dsn));
+        envelope.set_dsc(DynamicSamplingContext {
+            trace_id: Uuid::new_v4(),
+            public_key: ProjectKey::parse("e12d836b15bb49d7bbf99e64295d995b").unwrap(),
+            release: None,
+            user: Default::default(),
+            replay_id: None,
+            environment: None,
+            transaction: None,
+            sample_rate: None,
+            sampled: None,
+            other: BTreeMap::new(),
+        });
+        envelope.add_item(item);
+
+        let message = ProcessEnvelope {
+            envelope: ManagedEnvelope::standalone(envelope, outcome_aggregator, test_store),
+            project_state: Arc::new(project_state),
+            sampling_project_state: sampling_project_state.map(Arc::new),
+        };
+
+        let mut state = processor.prepare_state(message).unwrap();
+        step(&processor, &mut state);
+
+        let envelope = state.managed_envelope.envelope().clone();
+        let metrics = std::mem::take(&mut state.extracted_metrics.project_metrics);
+        state.managed_envelope.accept();
+        (envelope, metrics)
+    }
+
+    /// Runs [`EnvelopeProcessorService::process_spans`] on an envelope with a single span.
+    #[cfg(feature = "processing")]
+    fn process_span(
+        project_state: ProjectState,
+        sampling_project_state: Option<ProjectState>,
+    ) -> (Envelope, Vec<Bucket>) {
+        let mut item = Item::new(ItemType::Span);
+        item.set_payload(
+            ContentType::Json,
+            r#"{
+                "op": "db",
+                "span_id": "bd429c44b67a3eb4",
+                "trace_id": "ff62a8b040f340bda5d830223def1d81",
+                "start_timestamp": 1597976300.0,
+                "timestamp": 1597976302.0,
+                "data": {"user": "john@example.com"}
+            }"#,
+        );
+
+        process_item(item, project_state, sampling_project_state, |p, state| {
+            p.process_spans(state).unwrap()
+        })
+    }
+
+    /// Returns a project state that ingests standalone spans and extracts a span counter.
+    #[cfg(feature = "processing")]
+    fn span_project_state() -> ProjectState {
+        let mut project_state = ProjectState::allowed();
+        project_state.config.features = FeatureSet(BTreeSet::from([
+            Feature::StandaloneSpanIngestion,
+            Feature::SpanMetricsExtraction,
+        ]));
+        project_state.config.metric_extraction = ErrorBoundary::Ok(
+            serde_json::from_value(serde_json::json!({
+                "version": 1,
+                "metrics": [{"category": "span", "mri": "c:spans/count@none"}],
+            }))
+            .unwrap(),
+        );
+        project_state
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "processing")]
+    async fn test_process_spans_requires_feature() {
+        let (envelope, metrics) = process_span(ProjectState::allowed(), None);
+        assert!(envelope.is_empty());
+        assert!(metrics.is_empty());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "processing")]
+    async fn test_process_spans_scrubs_pii() {
+        let mut project_state = span_project_state();
+        project_state.config.pii_config = Some(
+            serde_json::from_str(r#"{"applications": {"$string": ["@email:replace"]}}"#).unwrap(),
+        );
+
+        let (envelope, metrics) = process_span(project_state, None);
+        assert_eq!(metrics.len(), 1);
+
+        let item = envelope.items().next().unwrap();
+        let span = Annotated::<Span>::from_json_bytes(&item.payload()).unwrap();
+        let data = span.value().unwrap().data.value().unwrap();
+        assert_eq!(
+            data["user"],
+            Annotated::new(Value::String("[email]".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "processing")]
+    async fn test_process_spans_sampled_extracts_metrics() {
+        let sampling_project_state = project_state_with_single_rule(0.0);
+        let (envelope, metrics) = process_span(span_project_state(), Some(sampling_project_state));
+
+        // The span is dropped by sampling, but its metrics are still extracted.
+        assert!(envelope.is_empty());
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].name, "c:spans/count@none");
+    }
+
+    /// Runs [`EnvelopeProcessorService::process_logs`] on an envelope with a log containing PII.
+    #[cfg(feature = "processing")]
+    fn process_log(ingestion_enabled: bool) -> (Envelope, Vec<Bucket>) {
+        let mut project_state = ProjectState::allowed();
+        if ingestion_enabled {
+            project_state.config.features = FeatureSet(BTreeSet::from([Feature::OurLogsIngestion]));
+        }
+        project_state.config.pii_config = Some(
+            serde_json::from_str(r#"{"applications": {"$string": ["@email:replace"]}}"#).unwrap(),
+        );
+        project_state.config.metric_extraction = ErrorBoundary::Ok(
+            serde_json::from_value(serde_json::json!({
+                "version": 1,
+                "metrics": [{
+                    "category": "log",
+                    "mri": "c:custom/logs@none",
+                    "condition": {"op": "eq", "name": "log.attributes.user", "value": "[email]"},
+                    "tags": [
+                        {"key": "severity", "field": "log.severity"},
+                        {"key": "user", "field": "log.attributes.user"},
+                    ],
+                }],
+            }))
+            .unwrap(),
+        );
+
+        let mut item = Item::new(ItemType::Log);
+        item.set_payload(
+            ContentType::Json,
+            r#"{
+                "timestamp": 1597976302.0,
+                "severity": "error",
+                "body": "request failed",
+                "attributes": {"user": "john@example.com"}
+            }"#,
+        );
+
+        process_item(item, project_state, None, |p, state| {
+            p.process_logs(state).unwrap()
+        })
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "processing")]
+    async fn test_process_logs_ingestion_enabled() {
+        let (envelope, metrics) = process_log(true);
+
+        let item = envelope.items().next().unwrap();
+        let log = Annotated::<Log>::from_json_bytes(&item.payload()).unwrap();
+        let attributes = log.value().unwrap().attributes.value().unwrap();
+        assert_eq!(
+            attributes["user"],
+            Annotated::new(Value::String("[email]".to_owned()))
+        );
+
+        // Metrics are extracted from the scrubbed log, and tags only from allowed fields.
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].tags["severity"], "error");
+        assert!(!metrics[0].tags.contains_key("user"));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "processing")]
+    async fn test_process_logs_ingestion_disabled() {
+        let (envelope, metrics) = process_log(false);
+
+        // The log is only used for metrics, which are still extracted from the scrubbed log.
+        assert!(envelope.is_empty());
+        assert_eq!(metrics.len(), 1);
+        assert!(!metrics[0].tags.contains_key("user"));
+    }
+
+    #[test]
+    fn test_extracted_span_outcome_category() {
+        let mut item = Item::new(ItemType::Span);
+        assert_eq!(item.outcome_category(false), Some(DataCategory::Span));
+
+        // Spans extracted from a transaction are accounted for by the transaction.
+        item.set_from_transaction(true);
+        assert_eq!(item.outcome_category(false), None);
+    }
+
     #[test]
     #[cfg(feature = "processing")]
     fn test_unprintable_fields() {
@@ -3783,7 +4999,8 @@ mod tests {
 
         let event = Annotated::new(Event {
             release: Annotated::new(
-                String::from("���7��#1G����7��#1G����7��#1G����7��#1G����7��#").into(),
+                String::from("���7��#1G����7��#1G����7��#1G����7��#1G����7��#")
+                    .into(),
             ),
             ..Default::default()
         });
@@ -4037,4 +5254,21 @@ mod tests {
             "Update `MEASUREMENT_MRI_OVERHEAD` if the naming scheme changed."
         );
     }
+
+    #[test]
+    fn test_encode_envelope_body_zstd() {
+        let body = Bytes::from(b"{}\n{\"type\":\"event\"}\n{}\n".repeat(10));
+
+        for level in [None, Some(1), Some(100)] {
+            let encoded = EnvelopeProcessorService::encode_envelope_body(
+                body.clone(),
+                HttpEncoding::Zstd,
+                level,
+            )
+            .unwrap();
+
+            assert!(encoded.len() < body.len());
+            assert_eq!(zstd::decode_all(encoded.as_ref()).unwrap(), body);
+        }
+    }
 }
@@ -6,7 +6,9 @@ use relay_base_schema::project::{ProjectId, ProjectKey};
 use relay_config::Config;
 use relay_dynamic_config::{Feature, LimitedProjectConfig, ProjectConfig};
 use relay_filter::matches_any_origin;
-use relay_metrics::{Aggregator, Bucket, MergeBuckets, MetricNamespace, MetricResourceIdentifier};
+use relay_metrics::{
+    Aggregator, Bucket, MergeBuckets, MetricMeta, MetricNamespace, MetricResourceIdentifier,
+};
 use relay_quotas::{Quota, RateLimits, Scoping};
 use relay_statsd::metric;
 use relay_system::{Addr, BroadcastChannel};
@@ -15,7 +17,7 @@ use smallvec::SmallVec;
 use tokio::time::Instant;
 use url::Url;
 
-use crate::actors::envelopes::SendMetrics;
+use crate::actors::envelopes::{EnvelopeManager, SendMetricMeta, SendMetrics};
 use crate::actors::outcome::{DiscardReason, Outcome, TrackOutcome};
 #[cfg(feature = "processing")]
 use crate::actors::processor::RateLimitFlushBuckets;
@@ -64,6 +66,12 @@ pub struct ProjectState {
     /// are faked locally.
     #[serde(default)]
     pub last_change: Option<DateTime<Utc>>,
+    /// The revision of the project config.
+    ///
+    /// Relay sends the revision of cached configs to the upstream, which responds that the config
+    /// is unchanged instead of sending it again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
     /// Indicates that the project is disabled.
     #[serde(default)]
     pub disabled: bool,
@@ -90,6 +98,13 @@ pub struct ProjectState {
     /// True if this project state failed fetching or was incompatible with this Relay.
     #[serde(skip, default)]
     pub invalid: bool,
+
+    /// True if this project state was restored from a snapshot of a previous run.
+    ///
+    /// Restored states remain usable for an entire cache expiry period, even if no grace period
+    /// is configured.
+    #[serde(skip, default)]
+    pub restored: bool,
 }
 
 /// Controls how we serialize a ProjectState for an external Relay
@@ -98,6 +113,8 @@ pub struct ProjectState {
 pub struct LimitedProjectState {
     pub project_id: Option<ProjectId>,
     pub last_change: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
     pub disabled: bool,
     pub public_keys: SmallVec<[PublicKeyConfig; 1]>,
     pub slug: Option<String>,
@@ -112,6 +129,7 @@ impl ProjectState {
         ProjectState {
             project_id: None,
             last_change: None,
+            rev: None,
             disabled: true,
             public_keys: SmallVec::new(),
             slug: None,
@@ -119,6 +137,7 @@ impl ProjectState {
             organization_id: None,
             last_fetch: Instant::now(),
             invalid: false,
+            restored: false,
         }
     }
 
@@ -156,6 +175,13 @@ impl ProjectState {
         self.invalid
     }
 
+    /// Returns `true` if this state has not passed its cache timeout.
+    ///
+    /// Stale states in the grace period are not considered fresh.
+    pub fn is_fresh(&self, config: &Config) -> bool {
+        self.check_expiry(config) == Expiry::Updated
+    }
+
     /// Returns whether this state is outdated and needs to be refetched.
     fn check_expiry(&self, config: &Config) -> Expiry {
         let expiry = match self.project_id {
@@ -163,8 +189,14 @@ impl ProjectState {
             Some(_) => config.project_cache_expiry(),
         };
 
+        let grace_period = if self.restored {
+            config.project_grace_period().max(expiry)
+        } else {
+            config.project_grace_period()
+        };
+
         let elapsed = self.last_fetch.elapsed();
-        if elapsed >= expiry + config.project_grace_period() {
+        if elapsed >= expiry + grace_period {
             Expiry::Expired
         } else if elapsed >= expiry {
             Expiry::Stale
@@ -329,6 +361,17 @@ impl ProjectState {
         Ok(())
     }
 
+    /// Returns a copy of this state that is marked as freshly fetched.
+    ///
+    /// This is used when the upstream confirms that the revision of this state is unchanged. The
+    /// copy retains compiled PII and sampling configs, so they are not compiled again.
+    pub fn revalidated(&self) -> Self {
+        let mut state = self.clone();
+        state.last_fetch = Instant::now();
+        state.restored = false;
+        state
+    }
+
     /// Validates data in this project state and removes values that are partially invalid.
     pub fn sanitize(mut self) -> Self {
         self.config.sanitize();
@@ -393,6 +436,8 @@ pub struct Project {
     state_channel: Option<StateChannel>,
     rate_limits: RateLimits,
     last_no_cache: Instant,
+    /// Metric metadata waiting for the project state to be loaded.
+    pending_metric_meta: Vec<MetricMeta>,
 }
 
 impl Project {
@@ -408,6 +453,7 @@ impl Project {
             state_channel: None,
             rate_limits: RateLimits::new(),
             last_no_cache: Instant::now(),
+            pending_metric_meta: Vec::new(),
         }
     }
 
@@ -438,6 +484,11 @@ impl Project {
         }
     }
 
+    /// Returns the most recent project state, regardless of its expiry.
+    pub fn last_state(&self) -> Option<Arc<ProjectState>> {
+        self.state.clone()
+    }
+
     /// Returns the project state if it is not expired.
     ///
     /// Convenience wrapper around [`expiry_state`](Self::expiry_state).
@@ -449,6 +500,13 @@ impl Project {
         }
     }
 
+    /// Sets a project state restored from a snapshot of a previous run.
+    ///
+    /// Restored states are stale, so that they are used while a fresh state is fetched.
+    pub fn restore_state(&mut self, state: Arc<ProjectState>) {
+        self.state = Some(state);
+    }
+
     /// Returns the next attempt `Instant` if backoff is initiated, or None otherwise.
     pub fn next_fetch_attempt(&self) -> Option<Instant> {
         self.next_fetch_attempt
@@ -818,6 +876,51 @@ impl Project {
         })
     }
 
+    /// Sends deduplicated metric metadata to the upstream or Kafka.
+    ///
+    /// If the project state is not available yet, an update is scheduled and the metadata is kept
+    /// until [`flush_metric_meta`](Self::flush_metric_meta) is called with a loaded state.
+    pub fn add_metric_meta(
+        &mut self,
+        meta: MetricMeta,
+        project_cache: Addr<ProjectCache>,
+        envelope_manager: Addr<EnvelopeManager>,
+    ) {
+        self.pending_metric_meta.push(meta);
+
+        // Schedule an update to the project state if it is missing or outdated.
+        if self.get_cached_state(project_cache, false).is_some() {
+            self.flush_metric_meta(envelope_manager);
+        }
+    }
+
+    /// Sends pending metric metadata if the project state is loaded.
+    ///
+    /// Metadata of disabled projects is dropped.
+    pub fn flush_metric_meta(&mut self, envelope_manager: Addr<EnvelopeManager>) {
+        if self.pending_metric_meta.is_empty() {
+            return;
+        }
+
+        let Some(state) = self.valid_state().filter(|state| !state.invalid()) else {
+            return;
+        };
+
+        let pending = std::mem::take(&mut self.pending_metric_meta);
+        if state.check_disabled(&self.config).is_err() {
+            relay_log::trace!("project disabled: dropping {} metric meta", pending.len());
+            return;
+        }
+
+        let Some(scoping) = self.scoping() else {
+            return;
+        };
+
+        for meta in pending {
+            envelope_manager.send(SendMetricMeta { meta, scoping });
+        }
+    }
+
     pub fn flush_buckets(
         &mut self,
         services: Services,
@@ -2,24 +2,33 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
 use std::sync::Arc;
 
-use relay_base_schema::project::ProjectKey;
+use relay_base_schema::project::{ProjectId, ProjectKey};
+use relay_common::time::UnixTimestamp;
 use relay_config::{Config, RelayMode};
-use relay_metrics::{self, Aggregator, FlushBuckets, MergeBuckets};
+use relay_metrics::{self, Aggregator, FlushBuckets, MergeBuckets, MetaAggregator, MetricMeta};
 use relay_quotas::RateLimits;
 use relay_redis::RedisPool;
 use relay_statsd::metric;
-use relay_system::{Addr, FromMessage, Interface, Sender, Service};
+use relay_system::{Addr, Controller, FromMessage, Interface, Sender, Service};
 use tokio::sync::mpsc;
 use tokio::time::Instant;
 
+use crate::actors::audit::{self, AuditEvent, AuditLog, AuditSource, AuditSubject};
 use crate::actors::envelopes::EnvelopeManager;
 use crate::actors::outcome::{DiscardReason, TrackOutcome};
+use crate::actors::pii_overrides::{
+    ApplyPiiOverrides, LocalPiiOverrides, LocalPiiOverridesService,
+};
 use crate::actors::processor::{EnvelopeProcessor, ProcessEnvelope};
 use crate::actors::project::{Project, ProjectSender, ProjectState};
 use crate::actors::project_local::{LocalProjectSource, LocalProjectSourceService};
 #[cfg(feature = "processing")]
 use crate::actors::project_redis::RedisProjectSource;
-use crate::actors::project_upstream::{UpstreamProjectSource, UpstreamProjectSourceService};
+use crate::actors::project_shards::ProjectStates;
+use crate::actors::project_snapshot;
+use crate::actors::project_upstream::{
+    UpstreamProjectSource, UpstreamProjectSourceService, UpstreamProjectState,
+};
 use crate::actors::spooler::{
     self, Buffer, BufferService, DequeueMany, Enqueue, QueueKey, RemoveMany,
 };
@@ -29,6 +38,12 @@ use crate::actors::upstream::UpstreamRelay;
 use crate::statsd::{RelayCounters, RelayGauges, RelayHistograms, RelayTimers};
 use crate::utils::{self, BufferGuard, GarbageDisposal, ManagedEnvelope};
 
+/// The maximum number of code locations forwarded per metric, project, and day.
+const MAX_METRIC_META_LOCATIONS: usize = 5;
+
+/// The maximum number of metrics for which code locations are deduplicated at the same time.
+const MAX_METRIC_META_ENTRIES: usize = 100_000;
+
 /// Requests a refresh of a project state from one of the available sources.
 ///
 /// The project state is resolved in the following precedence:
@@ -57,6 +72,15 @@ impl RequestUpdate {
     }
 }
 
+/// Requests a refresh of all cached projects with the given project IDs.
+///
+/// This is sent when the local PII config additions of projects change. See
+/// [`LocalPiiOverrides`].
+#[derive(Debug)]
+pub struct RefreshProjects {
+    pub project_ids: BTreeSet<ProjectId>,
+}
+
 /// Returns the project state.
 ///
 /// The project state is fetched if it is missing or outdated. If `no_cache` is specified, then the
@@ -165,6 +189,25 @@ impl UpdateRateLimits {
     }
 }
 
+/// Returns the rate limits cached for a project key.
+///
+/// Responds with `None` if the project is not in the cache. Expired rate limits are omitted.
+#[derive(Debug)]
+pub struct GetCachedRateLimits {
+    pub project_key: ProjectKey,
+}
+
+/// Adds metric metadata, such as code locations, of a project.
+///
+/// Metadata is deduplicated per day before it is forwarded to the upstream or Kafka.
+#[derive(Debug)]
+pub struct AddMetricMeta {
+    pub project_key: ProjectKey,
+    pub meta: MetricMeta,
+    /// The time at which the metadata was received by Relay.
+    pub received_at: UnixTimestamp,
+}
+
 /// Updates the buffer index for [`ProjectKey`] with the [`QueueKey`] keys.
 ///
 /// This message is sent from the project buffer in case of the error while fetching the data from
@@ -202,6 +245,7 @@ pub struct SpoolHealth;
 /// See the enumerated variants for a full list of available messages for this service.
 pub enum ProjectCache {
     RequestUpdate(RequestUpdate),
+    RefreshProjects(RefreshProjects),
     Get(GetProjectState, ProjectSender),
     GetCached(GetCachedProjectState, Sender<Option<Arc<ProjectState>>>),
     CheckEnvelope(
@@ -210,8 +254,10 @@ pub enum ProjectCache {
     ),
     ValidateEnvelope(ValidateEnvelope),
     UpdateRateLimits(UpdateRateLimits),
+    GetRateLimits(GetCachedRateLimits, Sender<Option<RateLimits>>),
     MergeBuckets(MergeBuckets),
     FlushBuckets(FlushBuckets),
+    AddMetricMeta(AddMetricMeta),
     UpdateBufferIndex(UpdateBufferIndex),
     SpoolHealth(Sender<bool>),
 }
@@ -234,6 +280,14 @@ impl FromMessage<RequestUpdate> for ProjectCache {
     }
 }
 
+impl FromMessage<RefreshProjects> for ProjectCache {
+    type Response = relay_system::NoResponse;
+
+    fn from_message(message: RefreshProjects, _: ()) -> Self {
+        Self::RefreshProjects(message)
+    }
+}
+
 impl FromMessage<GetProjectState> for ProjectCache {
     type Response = relay_system::BroadcastResponse<Arc<ProjectState>>;
 
@@ -280,6 +334,14 @@ impl FromMessage<UpdateRateLimits> for ProjectCache {
     }
 }
 
+impl FromMessage<GetCachedRateLimits> for ProjectCache {
+    type Response = relay_system::AsyncResponse<Option<RateLimits>>;
+
+    fn from_message(message: GetCachedRateLimits, sender: Sender<Option<RateLimits>>) -> Self {
+        Self::GetRateLimits(message, sender)
+    }
+}
+
 impl FromMessage<MergeBuckets> for ProjectCache {
     type Response = relay_system::NoResponse;
 
@@ -296,6 +358,14 @@ impl FromMessage<FlushBuckets> for ProjectCache {
     }
 }
 
+impl FromMessage<AddMetricMeta> for ProjectCache {
+    type Response = relay_system::NoResponse;
+
+    fn from_message(message: AddMetricMeta, _: ()) -> Self {
+        Self::AddMetricMeta(message)
+    }
+}
+
 impl FromMessage<SpoolHealth> for ProjectCache {
     type Response = relay_system::AsyncResponse<bool>;
 
@@ -312,6 +382,7 @@ struct ProjectSource {
     config: Arc<Config>,
     local_source: Addr<LocalProjectSource>,
     upstream_source: Addr<UpstreamProjectSource>,
+    pii_overrides: Option<Addr<LocalPiiOverrides>>,
     #[cfg(feature = "processing")]
     redis_source: Option<RedisProjectSource>,
 }
@@ -321,9 +392,13 @@ impl ProjectSource {
     pub fn start(
         config: Arc<Config>,
         upstream_relay: Addr<UpstreamRelay>,
+        project_cache: Addr<ProjectCache>,
         _redis: Option<RedisPool>,
     ) -> Self {
-        let local_source = LocalProjectSourceService::new(config.clone()).start();
+        let pii_overrides = config
+            .pii_overrides_path()
+            .map(|_| LocalPiiOverridesService::new(config.clone(), project_cache.clone()).start());
+        let local_source = LocalProjectSourceService::new(config.clone(), project_cache).start();
         let upstream_source =
             UpstreamProjectSourceService::new(config.clone(), upstream_relay).start();
 
@@ -334,12 +409,52 @@ impl ProjectSource {
             config,
             local_source,
             upstream_source,
+            pii_overrides,
             #[cfg(feature = "processing")]
             redis_source,
         }
     }
 
-    async fn fetch(self, project_key: ProjectKey, no_cache: bool) -> Result<Arc<ProjectState>, ()> {
+    /// Fetches the state of a project and applies local PII config additions.
+    ///
+    /// See [`fetch_state`](Self::fetch_state) for the sources of project states.
+    async fn fetch(
+        self,
+        project_key: ProjectKey,
+        no_cache: bool,
+        has_envelopes: bool,
+        current: Option<Arc<ProjectState>>,
+    ) -> Result<(Arc<ProjectState>, Option<AuditSource>), ()> {
+        let pii_overrides = self.pii_overrides.clone();
+        let (state, source) = self
+            .fetch_state(project_key, no_cache, has_envelopes, current)
+            .await?;
+
+        let Some(pii_overrides) = pii_overrides else {
+            return Ok((state, source));
+        };
+
+        let state = pii_overrides
+            .send(ApplyPiiOverrides { state })
+            .await
+            .map_err(|_| ())?;
+
+        Ok((state, source))
+    }
+
+    /// Fetches the state of a project along with the source it was fetched from.
+    ///
+    /// The source is `None` for default states that do not depend on a project config.
+    ///
+    /// If the `current` state carries a revision, the upstream may answer that the config is
+    /// unchanged. In this case, the current state is revalidated instead of being replaced.
+    async fn fetch_state(
+        self,
+        project_key: ProjectKey,
+        no_cache: bool,
+        has_envelopes: bool,
+        current: Option<Arc<ProjectState>>,
+    ) -> Result<(Arc<ProjectState>, Option<AuditSource>), ()> {
         let state_opt = self
             .local_source
             .send(FetchOptionalProjectState { project_key })
@@ -347,22 +462,29 @@ impl ProjectSource {
             .map_err(|_| ())?;
 
         if let Some(state) = state_opt {
-            return Ok(state);
+            return Ok((state, Some(AuditSource::File)));
         }
 
         match self.config.relay_mode() {
-            RelayMode::Proxy => return Ok(Arc::new(ProjectState::allowed())),
-            RelayMode::Static => return Ok(Arc::new(ProjectState::missing())),
-            RelayMode::Capture => return Ok(Arc::new(ProjectState::allowed())),
+            RelayMode::Proxy => return Ok((Arc::new(ProjectState::allowed()), None)),
+            RelayMode::Static => return Ok((Arc::new(ProjectState::missing()), None)),
+            RelayMode::Capture => return Ok((Arc::new(ProjectState::allowed()), None)),
             RelayMode::Managed => (), // Proceed with loading the config from redis or upstream
         }
 
         #[cfg(feature = "processing")]
         if let Some(redis_source) = self.redis_source {
+            if no_cache {
+                redis_source.invalidate(project_key);
+            }
+
+            #[cfg(feature = "redis-sync")]
             let state_fetch_result =
                 tokio::task::spawn_blocking(move || redis_source.get_config(project_key))
                     .await
                     .map_err(|_| ())?;
+            #[cfg(not(feature = "redis-sync"))]
+            let state_fetch_result = redis_source.get_config(project_key).await;
 
             let state_opt = match state_fetch_result {
                 Ok(state) => state.map(ProjectState::sanitize).map(Arc::new),
@@ -376,17 +498,32 @@ impl ProjectSource {
             };
 
             if let Some(state) = state_opt {
-                return Ok(state);
+                return Ok((state, Some(AuditSource::Redis)));
             }
         };
 
-        self.upstream_source
+        let current = current.filter(|state| !no_cache && !state.invalid());
+        let revision = current.as_ref().and_then(|state| state.rev.clone());
+
+        let state = match self
+            .upstream_source
             .send(FetchProjectState {
                 project_key,
                 no_cache,
+                has_envelopes,
+                revision,
             })
             .await
-            .map_err(|_| ())
+            .map_err(|_| ())?
+        {
+            UpstreamProjectState::New(state) => state,
+            UpstreamProjectState::NotModified => match current {
+                Some(current) => Arc::new(current.revalidated()),
+                None => Arc::new(ProjectState::err()),
+            },
+        };
+
+        Ok((state, Some(AuditSource::Upstream)))
     }
 }
 
@@ -400,6 +537,31 @@ struct UpdateProjectState {
 
     /// If true, all caches should be skipped and a fresh state should be computed.
     no_cache: bool,
+
+    /// The source the state was fetched from, if it is based on a project config.
+    source: Option<AuditSource>,
+}
+
+/// Records changes between two states of a project in the audit log.
+///
+/// Quotas are recorded separately from the remaining project config.
+fn audit_project_change(
+    audit_log: &Addr<AuditLog>,
+    project_key: ProjectKey,
+    source: AuditSource,
+    previous: &ProjectState,
+    state: &ProjectState,
+) {
+    let (quotas, other) = audit::split_project_changes(audit::diff(previous, state));
+
+    for (subject, changes) in [
+        (AuditSubject::ProjectConfig, other),
+        (AuditSubject::Quotas, quotas),
+    ] {
+        if !changes.is_empty() {
+            audit_log.send(AuditEvent::new(subject, source, changes).project_key(project_key));
+        }
+    }
 }
 
 /// Holds the addresses of all services required for [`ProjectCache`].
@@ -412,6 +574,7 @@ pub struct Services {
     pub project_cache: Addr<ProjectCache>,
     pub test_store: Addr<TestStore>,
     pub upstream_relay: Addr<UpstreamRelay>,
+    pub audit_log: Addr<AuditLog>,
 }
 
 impl Services {
@@ -424,6 +587,7 @@ impl Services {
         project_cache: Addr<ProjectCache>,
         test_store: Addr<TestStore>,
         upstream_relay: Addr<UpstreamRelay>,
+        audit_log: Addr<AuditLog>,
     ) -> Self {
         Self {
             aggregator,
@@ -433,6 +597,7 @@ impl Services {
             project_cache,
             test_store,
             upstream_relay,
+            audit_log,
         }
     }
 }
@@ -447,6 +612,8 @@ struct ProjectCacheBroker {
     services: Services,
     // Need hashbrown because drain_filter is not stable in std yet.
     projects: hashbrown::HashMap<ProjectKey, Project>,
+    /// Snapshots of the project states for lock-free reads outside of this service.
+    states: Arc<ProjectStates>,
     garbage_disposal: GarbageDisposal<Project>,
     source: ProjectSource,
     state_tx: mpsc::UnboundedSender<UpdateProjectState>,
@@ -455,6 +622,8 @@ struct ProjectCacheBroker {
     /// Index of the buffered project keys.
     index: BTreeMap<ProjectKey, BTreeSet<QueueKey>>,
     buffer: Addr<Buffer>,
+    /// Deduplicates metric metadata per project and day.
+    metric_meta: MetaAggregator,
 }
 
 impl ProjectCacheBroker {
@@ -524,15 +693,18 @@ impl ProjectCacheBroker {
             .drain_filter(|_, entry| entry.last_updated_at() + delta <= eviction_start);
 
         // Defer dropping the projects to a dedicated thread:
-        let mut count = 0;
+        let mut evicted = Vec::new();
         for (project_key, project) in expired {
             if let Some(keys) = self.index.remove(&project_key) {
                 self.buffer.send(RemoveMany::new(project_key, keys))
             }
 
             self.garbage_disposal.dispose(project);
-            count += 1;
+            evicted.push(project_key);
         }
+
+        let count = evicted.len() as i64;
+        self.states.remove_many(evicted);
         metric!(counter(RelayCounters::EvictingStaleProjectCaches) += count);
 
         // Log garbage queue size:
@@ -542,6 +714,54 @@ impl ProjectCacheBroker {
         metric!(timer(RelayTimers::ProjectStateEvictionDuration) = eviction_start.elapsed());
     }
 
+    /// Restores project states from the snapshot of a previous run, if configured.
+    fn restore_snapshot(&mut self) {
+        let Some(path) = self.config.project_cache_snapshot_path() else {
+            return;
+        };
+
+        let result = project_snapshot::load(
+            path,
+            self.config.project_cache_snapshot_max_age(),
+            self.config.project_cache_expiry(),
+        );
+
+        match result {
+            Ok(states) => {
+                let count = states.len();
+                for (project_key, state) in states {
+                    let state = Arc::new(state);
+                    self.get_or_create_project(project_key)
+                        .restore_state(state.clone());
+                    self.states.insert(project_key, state);
+                }
+                relay_log::info!(count, "restored project states from snapshot");
+            }
+            Err(error) => relay_log::error!(
+                error = &error as &dyn Error,
+                "failed to load project cache snapshot"
+            ),
+        }
+    }
+
+    /// Writes all valid project states to the snapshot file, if configured.
+    fn save_snapshot(&self) {
+        let Some(path) = self.config.project_cache_snapshot_path() else {
+            return;
+        };
+
+        let states = (self.projects.iter())
+            .filter_map(|(project_key, project)| Some((*project_key, project.last_state()?)));
+
+        match project_snapshot::save(path, states) {
+            Ok(count) => relay_log::info!(count, "saved project states to snapshot"),
+            Err(error) => relay_log::error!(
+                error = &error as &dyn Error,
+                "failed to save project cache snapshot"
+            ),
+        }
+    }
+
     fn get_or_create_project(&mut self, project_key: ProjectKey) -> &mut Project {
         metric!(histogram(RelayHistograms::ProjectStateCacheSize) = self.projects.len() as u64);
 
@@ -567,14 +787,29 @@ impl ProjectCacheBroker {
             project_key,
             state,
             no_cache,
+            source,
         } = message;
 
         let project_cache = self.services.project_cache.clone();
-        self.get_or_create_project(project_key).update_state(
-            project_cache,
-            state.clone(),
-            no_cache,
-        );
+        let envelope_manager = self.services.envelope_manager.clone();
+        let audit_log = self.services.audit_log.clone();
+        let audit_enabled = self.config.audit_enabled();
+
+        let project = self.get_or_create_project(project_key);
+        let previous = project.last_state();
+        project.update_state(project_cache, state.clone(), no_cache);
+        if let Some(current) = project.last_state() {
+            self.states.insert(project_key, current);
+        }
+        project.flush_metric_meta(envelope_manager);
+
+        if let (true, Some(source), Some(previous)) = (audit_enabled, source, previous) {
+            let unchanged = Arc::ptr_eq(&previous, &state)
+                || (previous.rev.is_some() && previous.rev == state.rev);
+            if !previous.invalid() && !state.invalid() && !unchanged {
+                audit_project_change(&audit_log, project_key, source, &previous, &state);
+            }
+        }
 
         if !state.invalid() {
             self.dequeue(project_key);
@@ -591,6 +826,8 @@ impl ProjectCacheBroker {
         let project = self.get_or_create_project(project_key);
         project.refresh_updated_timestamp();
         let next_attempt = project.next_fetch_attempt();
+        let current = project.last_state();
+        let has_envelopes = self.index.contains_key(&project_key);
 
         let source = self.source.clone();
         let sender = self.state_tx.clone();
@@ -600,21 +837,41 @@ impl ProjectCacheBroker {
             if let Some(next_attempt) = next_attempt {
                 tokio::time::sleep_until(next_attempt).await;
             }
-            let state = source
-                .fetch(project_key, no_cache)
+            let (state, source) = source
+                .fetch(project_key, no_cache, has_envelopes, current)
                 .await
-                .unwrap_or_else(|()| Arc::new(ProjectState::err()));
+                .unwrap_or_else(|()| (Arc::new(ProjectState::err()), None));
 
             let message = UpdateProjectState {
                 project_key,
                 state,
                 no_cache,
+                source,
             };
 
             sender.send(message).ok();
         });
     }
 
+    fn handle_refresh_projects(&mut self, message: RefreshProjects) {
+        let project_keys = self
+            .projects
+            .iter()
+            .filter(|(_, project)| {
+                project
+                    .last_state()
+                    .and_then(|state| state.project_id)
+                    .map_or(false, |id| message.project_ids.contains(&id))
+            })
+            .map(|(project_key, _)| *project_key)
+            .collect::<Vec<_>>();
+
+        for project_key in project_keys {
+            relay_log::debug!(%project_key, "local PII config changed");
+            self.handle_request_update(RequestUpdate::new(project_key, false));
+        }
+    }
+
     fn handle_get(&mut self, message: GetProjectState, sender: ProjectSender) {
         let project_cache = self.services.project_cache.clone();
         self.get_or_create_project(message.project_key).get_state(
@@ -751,6 +1008,13 @@ impl ProjectCacheBroker {
             .merge_rate_limits(message.rate_limits);
     }
 
+    fn handle_get_rate_limits(&self, message: GetCachedRateLimits) -> Option<RateLimits> {
+        let project = self.projects.get(&message.project_key)?;
+        let mut rate_limits = project.rate_limits().clone();
+        rate_limits.clean_expired();
+        Some(rate_limits)
+    }
+
     fn handle_merge_buckets(&mut self, message: MergeBuckets) {
         let aggregator = self.services.aggregator.clone();
         let outcome_aggregator = self.services.outcome_aggregator.clone();
@@ -765,6 +1029,26 @@ impl ProjectCacheBroker {
             .flush_buckets(context, message.partition_key, message.buckets);
     }
 
+    fn handle_add_metric_meta(&mut self, message: AddMetricMeta) {
+        let AddMetricMeta {
+            project_key,
+            meta,
+            received_at,
+        } = message;
+
+        let Some(meta) = self.metric_meta.add(project_key, meta, received_at) else {
+            return;
+        };
+
+        let project_cache = self.services.project_cache.clone();
+        let envelope_manager = self.services.envelope_manager.clone();
+        self.get_or_create_project(project_key).add_metric_meta(
+            meta,
+            project_cache,
+            envelope_manager,
+        );
+    }
+
     fn handle_buffer_index(&mut self, message: UpdateBufferIndex) {
         self.index.insert(message.project_key, message.keys);
     }
@@ -776,6 +1060,7 @@ impl ProjectCacheBroker {
     fn handle_message(&mut self, message: ProjectCache) {
         match message {
             ProjectCache::RequestUpdate(message) => self.handle_request_update(message),
+            ProjectCache::RefreshProjects(message) => self.handle_refresh_projects(message),
             ProjectCache::Get(message, sender) => self.handle_get(message, sender),
             ProjectCache::GetCached(message, sender) => {
                 sender.send(self.handle_get_cached(message))
@@ -785,8 +1070,12 @@ impl ProjectCacheBroker {
             }
             ProjectCache::ValidateEnvelope(message) => self.handle_validate_envelope(message),
             ProjectCache::UpdateRateLimits(message) => self.handle_rate_limits(message),
+            ProjectCache::GetRateLimits(message, sender) => {
+                sender.send(self.handle_get_rate_limits(message))
+            }
             ProjectCache::MergeBuckets(message) => self.handle_merge_buckets(message),
             ProjectCache::FlushBuckets(message) => self.handle_flush_buckets(message),
+            ProjectCache::AddMetricMeta(message) => self.handle_add_metric_meta(message),
             ProjectCache::UpdateBufferIndex(message) => self.handle_buffer_index(message),
             ProjectCache::SpoolHealth(sender) => self.handle_spool_health(sender),
         }
@@ -797,6 +1086,7 @@ impl ProjectCacheBroker {
 #[derive(Debug)]
 pub struct ProjectCacheService {
     buffer_guard: Arc<BufferGuard>,
+    states: Arc<ProjectStates>,
     config: Arc<Config>,
     services: Services,
     redis: Option<RedisPool>,
@@ -804,14 +1094,18 @@ pub struct ProjectCacheService {
 
 impl ProjectCacheService {
     /// Creates a new `ProjectCacheService`.
+    ///
+    /// The service keeps `states` up to date with the project states in its cache.
     pub fn new(
         config: Arc<Config>,
         buffer_guard: Arc<BufferGuard>,
+        states: Arc<ProjectStates>,
         services: Services,
         redis: Option<RedisPool>,
     ) -> Self {
         Self {
             buffer_guard,
+            states,
             config,
             services,
             redis,
@@ -825,6 +1119,7 @@ impl Service for ProjectCacheService {
     fn spawn_handler(self, mut rx: relay_system::Receiver<Self::Interface>) {
         let Self {
             buffer_guard,
+            states,
             config,
             services,
             redis,
@@ -868,16 +1163,29 @@ impl Service for ProjectCacheService {
             let mut broker = ProjectCacheBroker {
                 config: config.clone(),
                 projects: hashbrown::HashMap::new(),
+                states,
                 garbage_disposal: GarbageDisposal::new(),
-                source: ProjectSource::start(config, services.upstream_relay.clone(), redis),
+                source: ProjectSource::start(
+                    config,
+                    services.upstream_relay.clone(),
+                    services.project_cache.clone(),
+                    redis,
+                ),
                 services,
                 state_tx,
                 buffer_tx,
                 buffer_guard,
                 index: BTreeMap::new(),
                 buffer,
+                metric_meta: MetaAggregator::new(
+                    MAX_METRIC_META_LOCATIONS,
+                    MAX_METRIC_META_ENTRIES,
+                ),
             };
 
+            broker.restore_snapshot();
+            let mut shutdown = Controller::shutdown_handle();
+
             loop {
                 tokio::select! {
                     biased;
@@ -887,6 +1195,7 @@ impl Service for ProjectCacheService {
                     // permits in `BufferGuard` available. Currently this is 50%.
                     Some(managed_envelope) = buffer_rx.recv() => broker.handle_processing(managed_envelope),
                     _ = ticker.tick() => broker.evict_stale_project_caches(),
+                    _ = shutdown.notified() => broker.save_snapshot(),
                     Some(message) = rx.recv() => broker.handle_message(message),
                     else => break,
                 }
@@ -904,6 +1213,12 @@ pub struct FetchProjectState {
 
     /// If true, all caches should be skipped and a fresh state should be computed.
     pub no_cache: bool,
+
+    /// If true, envelopes of this project are waiting for the state and it is fetched first.
+    pub has_envelopes: bool,
+
+    /// The revision of the cached project state, used to skip unchanged configs.
+    pub revision: Option<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -936,6 +1251,7 @@ mod tests {
         let (project_cache, _) = mock_service("project_cache", (), |&mut (), _| {});
         let (test_store, _) = mock_service("test_store", (), |&mut (), _| {});
         let (upstream_relay, _) = mock_service("upstream_relay", (), |&mut (), _| {});
+        let (audit_log, _) = mock_service("audit_log", (), |&mut (), _| {});
 
         Services {
             aggregator,
@@ -945,6 +1261,7 @@ mod tests {
             outcome_aggregator,
             test_store,
             upstream_relay,
+            audit_log,
         }
     }
 
@@ -989,14 +1306,24 @@ mod tests {
             ProjectCacheBroker {
                 config: config.clone(),
                 projects: hashbrown::HashMap::new(),
+                states: Arc::new(ProjectStates::new()),
                 garbage_disposal: GarbageDisposal::new(),
-                source: ProjectSource::start(config, services.upstream_relay.clone(), None),
+                source: ProjectSource::start(
+                    config,
+                    services.upstream_relay.clone(),
+                    services.project_cache.clone(),
+                    None,
+                ),
                 services,
                 state_tx,
                 buffer_tx,
                 buffer_guard,
                 index: BTreeMap::new(),
                 buffer: buffer.clone(),
+                metric_meta: MetaAggregator::new(
+                    MAX_METRIC_META_LOCATIONS,
+                    MAX_METRIC_META_ENTRIES,
+                ),
             },
             buffer,
         )
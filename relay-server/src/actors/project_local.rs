@@ -1,16 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use relay_base_schema::project::{ProjectId, ProjectKey};
 use relay_config::Config;
-use relay_system::{AsyncResponse, FromMessage, Interface, Receiver, Sender, Service};
+use relay_pii::RuleType;
+use relay_system::{Addr, AsyncResponse, FromMessage, Interface, Receiver, Sender, Service};
 use tokio::sync::mpsc;
 use tokio::time::Instant;
 
 use crate::actors::project::ProjectState;
-use crate::actors::project_cache::FetchOptionalProjectState;
+use crate::actors::project_cache::{FetchOptionalProjectState, ProjectCache, RequestUpdate};
 
 /// Service interface of the local project source.
 #[derive(Debug)]
@@ -29,25 +31,67 @@ impl FromMessage<FetchOptionalProjectState> for LocalProjectSource {
 }
 
 /// A service which periodically loads project states from disk.
+///
+/// Added, changed, and removed project files are applied without a restart. Projects whose state
+/// changed are refreshed in the [`ProjectCache`] immediately.
 #[derive(Debug)]
 pub struct LocalProjectSourceService {
     config: Arc<Config>,
-    local_states: HashMap<ProjectKey, Arc<ProjectState>>,
+    project_cache: Addr<ProjectCache>,
+    local_states: Option<HashMap<ProjectKey, Arc<ProjectState>>>,
 }
 
 impl LocalProjectSourceService {
-    pub fn new(config: Arc<Config>) -> Self {
+    pub fn new(config: Arc<Config>, project_cache: Addr<ProjectCache>) -> Self {
         Self {
             config,
-            local_states: HashMap::new(),
+            project_cache,
+            local_states: None,
         }
     }
 
     fn handle_message(&mut self, message: LocalProjectSource) {
         let LocalProjectSource(message, sender) = message;
-        let states = self.local_states.get(&message.project_key()).cloned();
+        let states = self
+            .local_states
+            .as_ref()
+            .and_then(|states| states.get(&message.project_key()))
+            .cloned();
         sender.send(states);
     }
+
+    /// Replaces the local states and refreshes all projects that changed.
+    fn update_states(&mut self, states: HashMap<ProjectKey, Arc<ProjectState>>) {
+        // Projects are fetched lazily, so there is nothing to refresh on the initial load.
+        if let Some(ref previous) = self.local_states {
+            let changed = states
+                .iter()
+                .filter(|(key, state)| {
+                    !previous
+                        .get(*key)
+                        .map_or(false, |previous| Arc::ptr_eq(previous, state))
+                })
+                .map(|(key, _)| *key);
+            let removed = previous.keys().filter(|key| !states.contains_key(*key));
+
+            for project_key in changed.chain(removed.copied()).collect::<Vec<_>>() {
+                relay_log::debug!(%project_key, "local project config changed");
+                self.project_cache
+                    .send(RequestUpdate::new(project_key, false));
+            }
+        }
+
+        self.local_states = Some(states);
+    }
+}
+
+/// Project states loaded from a single project file.
+#[derive(Debug)]
+struct LocalFile {
+    /// The modification time of the file when it was loaded.
+    modified: Option<SystemTime>,
+    /// The project states by public key.
+    states: Vec<(ProjectKey, Arc<ProjectState>)>,
 }
 
 fn get_project_id(path: &Path) -> Option<ProjectId> {
@@ -56,22 +100,88 @@ fn get_project_id(path: &Path) -> Option<ProjectId> {
         .and_then(|stem| stem.parse().ok())
 }
 
-fn parse_file(path: std::path::PathBuf) -> tokio::io::Result<(std::path::PathBuf, ProjectState)> {
+fn parse_file(path: PathBuf) -> tokio::io::Result<(PathBuf, ProjectState)> {
     let file = std::fs::File::open(&path)?;
     let reader = std::io::BufReader::new(file);
     Ok((path, serde_json::from_reader(reader)?))
 }
 
+/// Checks the PII configs and quotas of a project state loaded from disk.
+///
+/// Invalid project states are rejected when loading, rather than failing later during processing.
+fn validate_state(state: &ProjectState) -> Result<(), String> {
+    let config = &state.config;
+
+    if let Some(ref pii_config) = config.pii_config {
+        for (id, rule) in &pii_config.rules {
+            if let RuleType::Pattern(ref rule) = rule.ty {
+                if let Err(error) = rule.pattern.compiled() {
+                    return Err(format!("invalid PII rule `{id}`: {error}"));
+                }
+            }
+        }
+    }
+
+    if let Err(error) = config.datascrubbing_settings.pii_config() {
+        return Err(format!("invalid data scrubbing settings: {error}"));
+    }
+
+    for quota in &config.quotas {
+        if !quota.is_valid() {
+            let id = quota.id.as_deref().unwrap_or("<unnamed>");
+            return Err(format!("invalid quota `{id}`"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses and validates a project file.
+async fn load_file(path: PathBuf) -> Result<Vec<(ProjectKey, Arc<ProjectState>)>, String> {
+    // serde_json is not async, so spawn a blocking task here:
+    let (path, state) = tokio::task::spawn_blocking(move || parse_file(path))
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())?;
+
+    let mut sanitized = ProjectState::sanitize(state);
+    if sanitized.project_id.is_none() {
+        match get_project_id(&path) {
+            Some(project_id) => sanitized.project_id = Some(project_id),
+            None => return Err("filename is not a valid project id".to_owned()),
+        }
+    }
+
+    validate_state(&sanitized)?;
+
+    // Keep a separate project state per key.
+    let keys = std::mem::take(&mut sanitized.public_keys);
+    let mut states = Vec::with_capacity(keys.len());
+    for key in keys {
+        sanitized.public_keys = smallvec::smallvec![key.clone()];
+        states.push((key.public_key, Arc::new(sanitized.clone())));
+    }
+
+    Ok(states)
+}
+
+/// Loads all project states from the given directory.
+///
+/// Files that have not been modified since the last load are not parsed again and retain their
+/// states. If a file cannot be loaded, an error is logged and the previously loaded states of that
+/// file remain in effect. Files that no longer exist are removed from `files`.
 async fn load_local_states(
     projects_path: &Path,
+    files: &mut HashMap<PathBuf, LocalFile>,
 ) -> tokio::io::Result<HashMap<ProjectKey, Arc<ProjectState>>> {
-    let mut states = HashMap::new();
-
     let mut directory = match tokio::fs::read_dir(projects_path).await {
         Ok(directory) => directory,
         Err(error) => {
             return match error.kind() {
-                tokio::io::ErrorKind::NotFound => Ok(states),
+                tokio::io::ErrorKind::NotFound => {
+                    files.clear();
+                    Ok(HashMap::new())
+                }
                 _ => Err(error),
             };
         }
@@ -80,6 +190,7 @@ async fn load_local_states(
     // only printed when directory even exists.
     relay_log::debug!(directory = ?projects_path, "loading local states from file system");
 
+    let mut seen = HashSet::new();
     while let Some(entry) = directory.next_entry().await? {
         let path = entry.path();
 
@@ -94,32 +205,44 @@ async fn load_local_states(
             continue;
         }
 
-        // serde_json is not async, so spawn a blocking task here:
-        let (path, state) = tokio::task::spawn_blocking(move || parse_file(path)).await??;
+        seen.insert(path.clone());
 
-        let mut sanitized = ProjectState::sanitize(state);
-        if sanitized.project_id.is_none() {
-            if let Some(project_id) = get_project_id(&path) {
-                sanitized.project_id = Some(project_id);
-            } else {
-                relay_log::warn!(?path, "skipping file, filename is not a valid project id");
+        // Follow symlinks to detect changes of the target file.
+        let modified = tokio::fs::metadata(&path)
+            .await
+            .and_then(|m| m.modified())
+            .ok();
+
+        if let Some(file) = files.get(&path) {
+            if modified.is_some() && file.modified == modified {
                 continue;
             }
         }
 
-        // Keep a separate project state per key.
-        let keys = std::mem::take(&mut sanitized.public_keys);
-        for key in keys {
-            sanitized.public_keys = smallvec::smallvec![key.clone()];
-            states.insert(key.public_key, Arc::new(sanitized.clone()));
+        match load_file(path.clone()).await {
+            Ok(states) => {
+                files.insert(path, LocalFile { modified, states });
+            }
+            Err(error) => {
+                relay_log::error!(?path, "failed to load project config: {error}");
+            }
         }
     }
 
-    Ok(states)
+    files.retain(|path, _| seen.contains(path));
+
+    Ok(files
+        .values()
+        .flat_map(|file| file.states.iter().cloned())
+        .collect())
 }
 
-async fn poll_local_states(path: &Path, tx: &mpsc::Sender<HashMap<ProjectKey, Arc<ProjectState>>>) {
-    let states = load_local_states(path).await;
+async fn poll_local_states(
+    path: &Path,
+    files: &mut HashMap<PathBuf, LocalFile>,
+    tx: &mpsc::Sender<HashMap<ProjectKey, Arc<ProjectState>>>,
+) {
+    let states = load_local_states(path, files).await;
     match states {
         Ok(states) => {
             let res = tx.send(states).await;
@@ -140,10 +263,11 @@ async fn spawn_poll_local_states(
 ) {
     let project_path = config.project_configs_path();
     let period = config.local_cache_interval();
+    let mut files = HashMap::new();
 
     // Poll local states once before handling any message, such that the projects are
     // populated.
-    poll_local_states(&project_path, &tx).await;
+    poll_local_states(&project_path, &mut files, &tx).await;
 
     // Start a background loop that polls periodically:
     tokio::spawn(async move {
@@ -153,7 +277,7 @@ async fn spawn_poll_local_states(
 
         loop {
             ticker.tick().await;
-            poll_local_states(&project_path, &tx).await;
+            poll_local_states(&project_path, &mut files, &tx).await;
         }
     });
 }
@@ -176,7 +300,7 @@ impl Service for LocalProjectSourceService {
                 tokio::select! {
                     biased;
                     Some(message) = rx.recv() => self.handle_message(message),
-                    Some(states) = state_rx.recv() => self.local_states = states,
+                    Some(states) = state_rx.recv() => self.update_states(states),
 
                     else => break,
                 }
@@ -226,7 +350,9 @@ mod tests {
         .await
         .unwrap();
 
-        let extracted_project_state = load_local_states(temp2.path()).await.unwrap();
+        let extracted_project_state = load_local_states(temp2.path(), &mut HashMap::new())
+            .await
+            .unwrap();
 
         assert_eq!(
             extracted_project_state
@@ -274,10 +400,72 @@ mod tests {
             .await
             .unwrap();
 
-        let extracted_project_state = load_local_states(temp.path()).await.unwrap();
+        let extracted_project_state = load_local_states(temp.path(), &mut HashMap::new())
+            .await
+            .unwrap();
 
         assert_eq!(extracted_project_state.len(), 2);
         assert!(extracted_project_state.get(&project_key1).is_some());
         assert!(extracted_project_state.get(&project_key2).is_some());
     }
+
+    #[tokio::test]
+    async fn test_reload_changed_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut files = HashMap::new();
+
+        let project_key1 = ProjectKey::parse("55f6b2d962564e99832a39890ee4573e").unwrap();
+        let project_key2 = ProjectKey::parse("55bbb2d96256bb9983bb39890bb457bb").unwrap();
+
+        let write_state = |file: &str, key: ProjectKey| {
+            let mut state = ProjectState::allowed();
+            state.public_keys.push(PublicKeyConfig {
+                public_key: key,
+                numeric_id: None,
+            });
+            std::fs::write(
+                temp.path().join(file),
+                serde_json::to_string(&state).unwrap(),
+            )
+            .unwrap();
+        };
+
+        write_state("111111.json", project_key1);
+        write_state("222222.json", project_key2);
+
+        let states = load_local_states(temp.path(), &mut files).await.unwrap();
+        assert_eq!(states.len(), 2);
+
+        // Unchanged files are not parsed again.
+        let reloaded = load_local_states(temp.path(), &mut files).await.unwrap();
+        assert!(Arc::ptr_eq(
+            &states[&project_key1],
+            &reloaded[&project_key1]
+        ));
+
+        // Invalid files retain their previous states.
+        std::fs::write(temp.path().join("111111.json"), "{").unwrap();
+        let reloaded = load_local_states(temp.path(), &mut files).await.unwrap();
+        assert!(reloaded.contains_key(&project_key1));
+
+        // Removed files drop their states.
+        std::fs::remove_file(temp.path().join("222222.json")).unwrap();
+        let reloaded = load_local_states(temp.path(), &mut files).await.unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert!(!reloaded.contains_key(&project_key2));
+    }
+
+    #[test]
+    fn test_validate_state() {
+        let mut state = ProjectState::allowed();
+        assert_eq!(validate_state(&state), Ok(()));
+
+        state.config.pii_config = Some(
+            serde_json::from_value(serde_json::json!({
+                "rules": {"broken": {"type": "pattern", "pattern": "(", "redaction": {"method": "remove"}}}
+            }))
+            .unwrap(),
+        );
+        assert!(validate_state(&state).is_err());
+    }
 }
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use moka::sync::Cache;
 use relay_base_schema::project::ProjectKey;
 use relay_config::Config;
 use relay_redis::{RedisError, RedisPool};
@@ -12,6 +13,8 @@ use crate::statsd::{RelayCounters, RelayHistograms, RelayTimers};
 pub struct RedisProjectSource {
     config: Arc<Config>,
     redis: RedisPool,
+    /// Recently read project states, including projects missing from Redis.
+    cache: Option<Cache<ProjectKey, Option<ProjectState>>>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -49,19 +52,93 @@ fn parse_redis_response(raw_response: &[u8]) -> Result<ProjectState, RedisProjec
 
 impl RedisProjectSource {
     pub fn new(config: Arc<Config>, redis: RedisPool) -> Self {
-        RedisProjectSource { config, redis }
+        let cache = config.redis_cache_ttl().map(|ttl| {
+            Cache::builder()
+                .time_to_live(ttl)
+                .max_capacity(config.redis_cache_max_entries())
+                .build()
+        });
+
+        RedisProjectSource {
+            config,
+            redis,
+            cache,
+        }
     }
 
-    pub fn get_config(&self, key: ProjectKey) -> Result<Option<ProjectState>, RedisProjectError> {
+    /// Returns the cached project state, if it has been read recently.
+    fn cached(&self, key: ProjectKey) -> Option<Option<ProjectState>> {
+        let state = self.cache.as_ref()?.get(&key);
+        if state.is_some() {
+            metric!(counter(RelayCounters::ProjectStateRedisCacheHit) += 1);
+        }
+        state
+    }
+
+    /// Removes the project from the cache, so that the next lookup reads from Redis.
+    pub fn invalidate(&self, key: ProjectKey) {
+        if let Some(ref cache) = self.cache {
+            cache.invalidate(&key);
+        }
+    }
+
+    fn cache(&self, key: ProjectKey, state: &Option<ProjectState>) {
+        if let Some(ref cache) = self.cache {
+            cache.insert(key, state.clone());
+        }
+    }
+
+    fn command(&self, key: ProjectKey) -> relay_redis::redis::Cmd {
         let mut command = relay_redis::redis::cmd("GET");
 
+        let key_prefix = self.config.redis_key_prefix();
         let prefix = self.config.projectconfig_cache_prefix();
-        command.arg(format!("{prefix}:{key}"));
+        command.arg(format!("{key_prefix}{prefix}:{key}"));
+        command
+    }
 
-        let raw_response_opt: Option<Vec<u8>> = command
+    /// Fetches the project state from Redis, blocking the current thread.
+    #[cfg(feature = "redis-sync")]
+    pub fn get_config(&self, key: ProjectKey) -> Result<Option<ProjectState>, RedisProjectError> {
+        if let Some(state) = self.cached(key) {
+            return Ok(state);
+        }
+
+        let raw_response_opt: Option<Vec<u8>> = self
+            .command(key)
             .query(&mut self.redis.client()?.connection()?)
             .map_err(RedisError::Redis)?;
 
+        let state = Self::handle_response(raw_response_opt)?;
+        self.cache(key, &state);
+        Ok(state)
+    }
+
+    /// Fetches the project state from Redis over a multiplexed connection.
+    #[cfg(not(feature = "redis-sync"))]
+    pub async fn get_config(
+        &self,
+        key: ProjectKey,
+    ) -> Result<Option<ProjectState>, RedisProjectError> {
+        if let Some(state) = self.cached(key) {
+            return Ok(state);
+        }
+
+        let mut connection = self.redis.async_connection().await?;
+        let raw_response_opt: Option<Vec<u8>> = self
+            .command(key)
+            .query_async(&mut connection)
+            .await
+            .map_err(RedisError::Redis)?;
+
+        let state = Self::handle_response(raw_response_opt)?;
+        self.cache(key, &state);
+        Ok(state)
+    }
+
+    fn handle_response(
+        raw_response_opt: Option<Vec<u8>>,
+    ) -> Result<Option<ProjectState>, RedisProjectError> {
         let response = match raw_response_opt {
             Some(response) => {
                 metric!(counter(RelayCounters::ProjectStateRedis) += 1, hit = "true");
@@ -0,0 +1,155 @@
+//! Sharded snapshots of cached project states.
+//!
+//! The [`ProjectCache`](super::project_cache::ProjectCache) service owns all projects and is the
+//! only writer of [`ProjectStates`]. Readers on hot paths, such as the project configs endpoint,
+//! look up states directly without sending a message to the service and without taking a lock.
+//!
+//! Project keys are distributed over a fixed number of shards by their hash. Every shard holds an
+//! immutable map that is replaced as a whole on updates, so that an update only copies the states
+//! of a single shard.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use relay_base_schema::project::ProjectKey;
+use relay_config::Config;
+
+use crate::actors::project::ProjectState;
+
+/// The number of shards used by [`ProjectStates`].
+const SHARDS: usize = 64;
+
+/// The immutable contents of a single shard.
+type Shard = HashMap<ProjectKey, Arc<ProjectState>>;
+
+/// Lock-free, sharded snapshots of the project states in the project cache.
+#[derive(Debug)]
+pub struct ProjectStates {
+    shards: Box<[ArcSwap<Shard>]>,
+}
+
+impl ProjectStates {
+    /// Creates an empty set of project states.
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| ArcSwap::default()).collect(),
+        }
+    }
+
+    /// Returns the index of the shard responsible for the given project key.
+    fn shard_index(&self, project_key: ProjectKey) -> usize {
+        let mut hasher = DefaultHasher::new();
+        project_key.hash(&mut hasher);
+        hasher.finish() as usize % self.shards.len()
+    }
+
+    /// Returns the shard responsible for the given project key.
+    fn shard(&self, project_key: ProjectKey) -> &ArcSwap<Shard> {
+        &self.shards[self.shard_index(project_key)]
+    }
+
+    /// Returns the cached state of a project, if any.
+    ///
+    /// The state may be outdated. Use [`get_fresh`](Self::get_fresh) to skip outdated states.
+    pub fn get(&self, project_key: ProjectKey) -> Option<Arc<ProjectState>> {
+        self.shard(project_key).load().get(&project_key).cloned()
+    }
+
+    /// Returns the cached state of a project if it is valid and has not passed its cache timeout.
+    ///
+    /// Outdated states must be requested from the project cache, which schedules an update.
+    pub fn get_fresh(&self, project_key: ProjectKey, config: &Config) -> Option<Arc<ProjectState>> {
+        self.get(project_key)
+            .filter(|state| !state.invalid() && state.is_fresh(config))
+    }
+
+    /// Inserts or replaces the state of a project.
+    pub fn insert(&self, project_key: ProjectKey, state: Arc<ProjectState>) {
+        self.shard(project_key).rcu(|shard| {
+            let mut shard = Shard::clone(shard);
+            shard.insert(project_key, state.clone());
+            shard
+        });
+    }
+
+    /// Removes the states of all given projects.
+    ///
+    /// Every affected shard is copied only once.
+    pub fn remove_many(&self, project_keys: impl IntoIterator<Item = ProjectKey>) {
+        let mut by_shard = vec![Vec::new(); self.shards.len()];
+        for project_key in project_keys {
+            by_shard[self.shard_index(project_key)].push(project_key);
+        }
+
+        for (shard, keys) in self.shards.iter().zip(by_shard) {
+            if keys.is_empty() {
+                continue;
+            }
+
+            shard.rcu(|shard| {
+                let mut shard = Shard::clone(shard);
+                for project_key in &keys {
+                    shard.remove(project_key);
+                }
+                shard
+            });
+        }
+    }
+
+    /// Returns the number of cached project states.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.load().len()).sum()
+    }
+
+    /// Returns `true` if no project states are cached.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.load().is_empty())
+    }
+}
+
+impl Default for ProjectStates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(index: u8) -> ProjectKey {
+        ProjectKey::parse(&format!("{index:032x}")).unwrap()
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let states = ProjectStates::new();
+        for index in 0..200 {
+            states.insert(key(index), Arc::new(ProjectState::allowed()));
+        }
+
+        assert_eq!(states.len(), 200);
+        assert!(states.get(key(42)).is_some());
+
+        states.remove_many((0..100).map(key));
+        assert_eq!(states.len(), 100);
+        assert!(states.get(key(42)).is_none());
+        assert!(states.get(key(142)).is_some());
+    }
+
+    #[test]
+    fn test_get_fresh() {
+        let config = Config::default();
+        let states = ProjectStates::new();
+
+        states.insert(key(1), Arc::new(ProjectState::allowed()));
+        states.insert(key(2), Arc::new(ProjectState::err()));
+
+        assert!(states.get_fresh(key(1), &config).is_some());
+        assert!(states.get_fresh(key(2), &config).is_none());
+        assert!(states.get_fresh(key(3), &config).is_none());
+    }
+}
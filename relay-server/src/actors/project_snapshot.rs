@@ -0,0 +1,168 @@
+//! Snapshots of the project cache that persist project states across restarts.
+//!
+//! If `cache.snapshot_path` is configured, the [`ProjectCache`](super::project_cache::ProjectCache)
+//! writes all valid project states to the snapshot file on shutdown and restores them on startup.
+//! Restored states are stale: they are used to process envelopes right away, while fresh states
+//! are fetched in the background.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use relay_base_schema::project::ProjectKey;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::actors::project::ProjectState;
+
+/// The version of the snapshot format. Snapshots with a different version are ignored.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// A project state along with the time it was fetched.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotEntry {
+    project_key: ProjectKey,
+    fetched_at: DateTime<Utc>,
+    state: ProjectState,
+}
+
+/// The contents of a snapshot file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    projects: Vec<SnapshotEntry>,
+}
+
+/// Writes a snapshot of the given project states to `path`.
+///
+/// The snapshot is written atomically, so that a crash never leaves a partial file behind. Invalid
+/// states and states without a project are skipped.
+pub fn save<I>(path: &Path, states: I) -> io::Result<usize>
+where
+    I: IntoIterator<Item = (ProjectKey, Arc<ProjectState>)>,
+{
+    let now = Utc::now();
+    let projects: Vec<_> = states
+        .into_iter()
+        .filter(|(_, state)| !state.invalid() && state.project_id.is_some())
+        .map(|(project_key, state)| SnapshotEntry {
+            project_key,
+            fetched_at: now
+                - chrono::Duration::from_std(state.last_fetch.elapsed()).unwrap_or_default(),
+            state: ProjectState::clone(&state),
+        })
+        .collect();
+
+    let count = projects.len();
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        projects,
+    };
+
+    let temp_path = path.with_extension("tmp");
+    std::fs::write(&temp_path, serde_json::to_vec(&snapshot)?)?;
+    std::fs::rename(temp_path, path)?;
+
+    Ok(count)
+}
+
+/// Loads project states from the snapshot at `path`.
+///
+/// States fetched longer than `max_age` ago are discarded. All other states are marked as
+/// restored, which makes them usable but stale until a fresh state has been fetched. Returns an
+/// empty list if the snapshot does not exist.
+pub fn load(
+    path: &Path,
+    max_age: Duration,
+    expiry: Duration,
+) -> io::Result<Vec<(ProjectKey, ProjectState)>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    let snapshot: Snapshot = serde_json::from_slice(&data)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        relay_log::warn!(
+            version = snapshot.version,
+            "ignoring project cache snapshot with unsupported version"
+        );
+        return Ok(Vec::new());
+    }
+
+    let now = Utc::now();
+    let last_fetch = Instant::now()
+        .checked_sub(expiry)
+        .unwrap_or_else(Instant::now);
+
+    let states = snapshot
+        .projects
+        .into_iter()
+        .filter(|entry| {
+            (now - entry.fetched_at)
+                .to_std()
+                .map_or(true, |age| age <= max_age)
+        })
+        .map(|entry| {
+            let mut state = entry.state.sanitize();
+            state.last_fetch = last_fetch;
+            state.restored = true;
+            (entry.project_key, state)
+        })
+        .collect();
+
+    Ok(states)
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_base_schema::project::ProjectId;
+
+    use super::*;
+
+    fn state() -> ProjectState {
+        let mut state = ProjectState::allowed();
+        state.project_id = Some(ProjectId::new(42));
+        state
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("relay-snapshot-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("projects.json");
+
+        let key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let missing = ProjectKey::parse("b94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let states = [
+            (key, Arc::new(state())),
+            (missing, Arc::new(ProjectState::missing())),
+        ];
+
+        let count = save(&path, states).unwrap();
+        assert_eq!(count, 1);
+
+        let expiry = Duration::from_secs(300);
+        let states = load(&path, Duration::from_secs(3600), expiry).unwrap();
+        assert_eq!(states.len(), 1);
+
+        let (restored_key, restored) = &states[0];
+        assert_eq!(*restored_key, key);
+        assert_eq!(restored.project_id, Some(ProjectId::new(42)));
+        assert!(restored.restored);
+        assert!(restored.last_fetch.elapsed() >= expiry);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let path = std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let states = load(&path, Duration::from_secs(3600), Duration::from_secs(300)).unwrap();
+        assert!(states.is_empty());
+    }
+}
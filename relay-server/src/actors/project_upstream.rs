@@ -33,8 +33,13 @@ use crate::utils::{RetryBackoff, SleepHandle};
 #[serde(rename_all = "camelCase")]
 pub struct GetProjectStates {
     public_keys: Vec<ProjectKey>,
+    /// Revisions of cached project configs, in the same order as `public_keys`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    revisions: Vec<Option<String>>,
     full_config: bool,
     no_cache: bool,
+    #[serde(skip)]
+    upstream_route: Option<usize>,
 }
 
 /// The response of the projects states requests.
@@ -50,6 +55,18 @@ pub struct GetProjectStatesResponse {
     /// The [`ProjectKey`]'s that couldn't be immediately retrieved from the upstream.
     #[serde(default)]
     pending: Vec<ProjectKey>,
+    /// The [`ProjectKey`]'s whose config has not changed since the requested revision.
+    #[serde(default)]
+    unchanged: Vec<ProjectKey>,
+}
+
+/// The result of fetching a project state from the upstream.
+#[derive(Clone, Debug)]
+pub enum UpstreamProjectState {
+    /// The upstream returned a new project state.
+    New(Arc<ProjectState>),
+    /// The project state has not changed since the revision in [`FetchProjectState`].
+    NotModified,
 }
 
 impl UpstreamQuery for GetProjectStates {
@@ -71,6 +88,10 @@ impl UpstreamQuery for GetProjectStates {
         false
     }
 
+    fn upstream_route(&self) -> Option<usize> {
+        self.upstream_route
+    }
+
     fn route(&self) -> &'static str {
         "project_configs"
     }
@@ -79,22 +100,32 @@ impl UpstreamQuery for GetProjectStates {
 /// The wrapper struct for the incoming external requests which also keeps addition information.
 #[derive(Debug)]
 struct ProjectStateChannel {
-    channel: BroadcastChannel<Arc<ProjectState>>,
+    channel: BroadcastChannel<UpstreamProjectState>,
+    /// Channels of requests for the same project that were merged into this one.
+    merged: Vec<BroadcastChannel<UpstreamProjectState>>,
+    /// The revision of the cached project state, if all requests know the same revision.
+    revision: Option<String>,
     deadline: Instant,
     no_cache: bool,
+    has_envelopes: bool,
     attempts: u64,
 }
 
 impl ProjectStateChannel {
     pub fn new(
-        sender: BroadcastSender<Arc<ProjectState>>,
+        sender: BroadcastSender<UpstreamProjectState>,
         timeout: Duration,
         no_cache: bool,
+        has_envelopes: bool,
+        revision: Option<String>,
     ) -> Self {
         let now = Instant::now();
         Self {
             no_cache,
+            has_envelopes,
             channel: sender.into_channel(),
+            merged: Vec::new(),
+            revision,
             deadline: now + timeout,
             attempts: 0,
         }
@@ -104,12 +135,40 @@ impl ProjectStateChannel {
         self.no_cache = true;
     }
 
-    pub fn attach(&mut self, sender: BroadcastSender<Arc<ProjectState>>) {
+    pub fn has_envelopes(&mut self) {
+        self.has_envelopes = true;
+    }
+
+    pub fn attach(&mut self, sender: BroadcastSender<UpstreamProjectState>) {
         self.channel.attach(sender)
     }
 
-    pub fn send(self, state: ProjectState) {
-        self.channel.send(Arc::new(state))
+    /// Drops the known revision if it differs from the revision of another request.
+    pub fn revision(&mut self, revision: Option<&str>) {
+        if self.revision.as_deref() != revision {
+            self.revision = None;
+        }
+    }
+
+    /// Merges a channel for the same project into this channel.
+    ///
+    /// The merged channel receives the same state as this channel. Flags are additive, and the
+    /// later deadline and the higher number of attempts are kept.
+    pub fn merge(&mut self, other: Self) {
+        self.revision(other.revision.as_deref());
+        self.no_cache |= other.no_cache;
+        self.has_envelopes |= other.has_envelopes;
+        self.deadline = self.deadline.max(other.deadline);
+        self.attempts = self.attempts.max(other.attempts);
+        self.merged.push(other.channel);
+        self.merged.extend(other.merged);
+    }
+
+    pub fn send(self, state: UpstreamProjectState) {
+        for channel in self.merged {
+            channel.send(state.clone());
+        }
+        self.channel.send(state)
     }
 
     pub fn expired(&self) -> bool {
@@ -120,22 +179,36 @@ impl ProjectStateChannel {
 /// The map of project keys with their project state channels.
 type ProjectStateChannels = HashMap<ProjectKey, ProjectStateChannel>;
 
+/// Inserts a channel into the map, merging it with an existing channel for the same project.
+fn insert_channel(
+    channels: &mut ProjectStateChannels,
+    key: ProjectKey,
+    channel: ProjectStateChannel,
+) {
+    match channels.entry(key) {
+        Entry::Vacant(entry) => {
+            entry.insert(channel);
+        }
+        Entry::Occupied(mut entry) => entry.get_mut().merge(channel),
+    }
+}
+
 /// This is the [`UpstreamProjectSourceService`] interface.
 ///
 /// The service is responsible for fetching the [`ProjectState`] from the upstream.
 /// Internally it maintains the buffer queue of the incoming requests, which got scheduled to fetch the
 /// state and takes care of the backoff in case there is a problem with the requests.
 #[derive(Debug)]
-pub struct UpstreamProjectSource(FetchProjectState, BroadcastSender<Arc<ProjectState>>);
+pub struct UpstreamProjectSource(FetchProjectState, BroadcastSender<UpstreamProjectState>);
 
 impl Interface for UpstreamProjectSource {}
 
 impl FromMessage<FetchProjectState> for UpstreamProjectSource {
-    type Response = BroadcastResponse<Arc<ProjectState>>;
+    type Response = BroadcastResponse<UpstreamProjectState>;
 
     fn from_message(
         message: FetchProjectState,
-        sender: BroadcastSender<Arc<ProjectState>>,
+        sender: BroadcastSender<UpstreamProjectState>,
     ) -> Self {
         Self(message, sender)
     }
@@ -185,8 +258,16 @@ impl UpstreamProjectSourceService {
     ///
     /// If previous queries succeeded, this will be the general batch interval. Additionally, an
     /// exponentially increasing backoff is used for retrying the upstream request.
+    ///
+    /// The batch interval only serves to collect more projects into a batch. It is skipped if
+    /// enough projects are pending to fill an entire batch, such as during a cold start.
     fn next_backoff(&mut self) -> Duration {
-        self.config.query_batch_interval() + self.backoff.next_backoff()
+        let backoff = self.backoff.next_backoff();
+        if self.state_channels.len() >= self.config.query_batch_size() {
+            backoff
+        } else {
+            self.config.query_batch_interval() + backoff
+        }
     }
 
     /// Prepares the batches of the cache and nocache channels which could be used to request the
@@ -200,10 +281,16 @@ impl UpstreamProjectSourceService {
         // much of the iterator is consumed.
         //
         // Instead, we have to collect the keys we want into a separate vector and pop them
-        // one-by-one.
-        let projects: Vec<_> = (self.state_channels.keys().copied())
-            .take(batch_size * num_batches)
+        // one-by-one. Projects with envelopes waiting for their state are fetched first.
+        let mut projects: Vec<_> = (self.state_channels.iter())
+            .map(|(key, channel)| (!channel.has_envelopes, *key))
             .collect();
+        let count = projects.len().min(batch_size * num_batches);
+        if count < projects.len() {
+            projects.select_nth_unstable(count);
+            projects.truncate(count);
+        }
+        let projects: Vec<_> = projects.into_iter().map(|(_, key)| key).collect();
 
         let fresh_channels = (projects.iter())
             .filter_map(|id| Some((*id, self.state_channels.remove(id)?)))
@@ -255,16 +342,24 @@ impl UpstreamProjectSourceService {
     ) -> Vec<Option<UpstreamResponse>> {
         let request_start = Instant::now();
         let batch_size = config.query_batch_size();
-        let cache_batches = channels.cache_channels.into_iter().chunks(batch_size);
-        let nocache_batches = channels.nocache_channels.into_iter().chunks(batch_size);
+
+        // Projects with a route in `relay.routes` are fetched from the upstream of that route, so
+        // batches must not mix projects of different routes.
+        let mut batches = vec![];
+        for channels in [channels.cache_channels, channels.nocache_channels] {
+            let routes = channels
+                .into_iter()
+                .into_group_map_by(|(key, _)| config.upstream_route(Some(*key), None));
+
+            for (upstream_route, channels) in routes {
+                for batch in &channels.into_iter().chunks(batch_size) {
+                    batches.push((upstream_route, batch.collect::<ProjectStateChannels>()));
+                }
+            }
+        }
 
         let mut requests = vec![];
-        // The `nocache_batches.into_iter()` still must be called here, since compiler produces the
-        // error: `that nocache_batches is not an iterator`.
-        // Since `IntoChunks` is not an iterator itself but only implements `IntoIterator` trait.
-        #[allow(clippy::useless_conversion)]
-        for channels_batch in cache_batches.into_iter().chain(nocache_batches.into_iter()) {
-            let mut channels_batch: ProjectStateChannels = channels_batch.collect();
+        for (upstream_route, mut channels_batch) in batches {
             for channel in channels_batch.values_mut() {
                 channel.attempts += 1;
             }
@@ -274,10 +369,21 @@ impl UpstreamProjectSourceService {
                     channels_batch.len() as u64
             );
 
+            let (public_keys, mut revisions): (Vec<_>, Vec<_>) = channels_batch
+                .iter()
+                .map(|(key, channel)| (*key, channel.revision.clone()))
+                .unzip();
+
+            if revisions.iter().all(Option::is_none) {
+                revisions.clear();
+            }
+
             let query = GetProjectStates {
-                public_keys: channels_batch.keys().copied().collect(),
+                public_keys,
+                revisions,
                 full_config: config.processing_enabled(),
                 no_cache: channels_batch.values().any(|c| c.no_cache),
+                upstream_route,
             };
 
             // count number of http requests for project states
@@ -346,9 +452,23 @@ impl UpstreamProjectSourceService {
                     );
                     for (key, channel) in channels_batch {
                         if response.pending.contains(&key) {
-                            self.state_channels.insert(key, channel);
+                            insert_channel(&mut self.state_channels, key, channel);
+                            continue;
+                        }
+
+                        if channel.revision.is_some() && response.unchanged.contains(&key) {
+                            metric!(
+                                histogram(RelayHistograms::ProjectStateAttempts) = channel.attempts,
+                                result = "unchanged",
+                            );
+                            metric!(
+                                counter(RelayCounters::ProjectUpstreamCompleted) += 1,
+                                result = "unchanged",
+                            );
+                            channel.send(UpstreamProjectState::NotModified);
                             continue;
                         }
+
                         let state = response
                             .configs
                             .remove(&key)
@@ -367,7 +487,19 @@ impl UpstreamProjectSourceService {
                             counter(RelayCounters::ProjectUpstreamCompleted) += 1,
                             result = result,
                         );
-                        channel.send(state.sanitize());
+
+                        let state = UpstreamProjectState::New(Arc::new(state.sanitize()));
+
+                        // Requests for the same project that arrived while the request was in
+                        // flight receive the same state, unless they require an uncached state.
+                        if let Entry::Occupied(entry) = self.state_channels.entry(key) {
+                            if channel.no_cache || !entry.get().no_cache {
+                                metric!(counter(RelayCounters::ProjectUpstreamDeduplicated) += 1);
+                                entry.remove().send(state.clone());
+                            }
+                        }
+
+                        channel.send(state);
                     }
                 }
                 Err(err) => {
@@ -380,7 +512,9 @@ impl UpstreamProjectSourceService {
                             self.state_channels.len() as u64
                     );
                     // Put the channels back into the queue, we will retry again shortly.
-                    self.state_channels.extend(channels_batch)
+                    for (key, channel) in channels_batch {
+                        insert_channel(&mut self.state_channels, key, channel);
+                    }
                 }
             }
         }
@@ -433,6 +567,8 @@ impl UpstreamProjectSourceService {
             FetchProjectState {
                 project_key,
                 no_cache,
+                has_envelopes,
+                revision,
             },
             sender,
         ) = message;
@@ -443,7 +579,13 @@ impl UpstreamProjectSourceService {
         // otherwise create a new one.
         match self.state_channels.entry(project_key) {
             Entry::Vacant(entry) => {
-                entry.insert(ProjectStateChannel::new(sender, query_timeout, no_cache));
+                entry.insert(ProjectStateChannel::new(
+                    sender,
+                    query_timeout,
+                    no_cache,
+                    has_envelopes,
+                    revision,
+                ));
             }
             Entry::Occupied(mut entry) => {
                 let channel = entry.get_mut();
@@ -453,6 +595,10 @@ impl UpstreamProjectSourceService {
                 if no_cache {
                     channel.no_cache();
                 }
+                if has_envelopes {
+                    channel.has_envelopes();
+                }
+                channel.revision(revision.as_deref());
             }
         }
 
@@ -485,3 +631,43 @@ impl Service for UpstreamProjectSourceService {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use relay_system::MessageResponse;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_merge_channels() {
+        let timeout = Duration::from_secs(10);
+        let (sender1, rx1) = BroadcastResponse::<UpstreamProjectState>::channel();
+        let (sender2, rx2) = BroadcastResponse::<UpstreamProjectState>::channel();
+
+        let revision = Some("abc".to_owned());
+        let mut channel = ProjectStateChannel::new(sender1, timeout, false, false, revision);
+        channel.merge(ProjectStateChannel::new(sender2, timeout, true, true, None));
+        assert!(channel.no_cache);
+        assert!(channel.has_envelopes);
+        assert_eq!(channel.revision, None);
+
+        channel.send(UpstreamProjectState::NotModified);
+        assert!(matches!(rx1.await, Ok(UpstreamProjectState::NotModified)));
+        assert!(matches!(rx2.await, Ok(UpstreamProjectState::NotModified)));
+    }
+
+    #[test]
+    fn test_serialize_revisions() {
+        let key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let query = GetProjectStates {
+            public_keys: vec![key],
+            revisions: vec![Some("abc".to_owned())],
+            full_config: false,
+            no_cache: false,
+            upstream_route: None,
+        };
+
+        let json = serde_json::to_value(&query).unwrap();
+        assert_eq!(json["revisions"], serde_json::json!(["abc"]));
+    }
+}
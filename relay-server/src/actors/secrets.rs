@@ -0,0 +1,252 @@
+//! Fetches credentials from an external secrets store.
+//!
+//! If `secrets.provider` is configured, Relay fetches a secret from HashiCorp Vault or AWS Secrets
+//! Manager at startup, before any services are created. The secret can contain the Relay
+//! credentials as well as credentials for Redis and Kafka, see [`ProvidedSecrets`].
+//!
+//! The [`SecretsService`] fetches the secret again in the configured interval. Rotated Relay
+//! credentials are used for the next authentication with the upstream. Other credentials are only
+//! read at startup.
+
+use std::error::Error;
+use std::sync::Arc;
+
+use anyhow::Context;
+use relay_aws::{send_aws_request, AwsAction, AwsCredentialsProvider};
+use relay_config::{Config, ProvidedSecrets, RelayMode, SecretsProvider};
+use relay_system::{Addr, Service};
+use serde::Deserialize;
+
+use crate::actors::audit::{self, AuditEvent, AuditLog, AuditSource, AuditSubject};
+use crate::http::client_builder;
+
+/// The `GetSecretValue` action of the AWS Secrets Manager API.
+const AWS_GET_SECRET_VALUE: AwsAction<'static> = AwsAction {
+    service: "secretsmanager",
+    target: "secretsmanager.GetSecretValue",
+    content_type: "application/x-amz-json-1.1",
+};
+
+/// Response of the Vault KV version 2 secrets engine.
+#[derive(Debug, Deserialize)]
+struct VaultResponse {
+    data: VaultSecret,
+}
+
+/// A versioned secret in the Vault KV version 2 secrets engine.
+#[derive(Debug, Deserialize)]
+struct VaultSecret {
+    data: ProvidedSecrets,
+}
+
+/// Response of the AWS Secrets Manager `GetSecretValue` action.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AwsSecretValue {
+    secret_string: Option<String>,
+}
+
+/// Fetches a secret from the Vault KV version 2 secrets engine.
+async fn fetch_vault(
+    client: &reqwest::Client,
+    address: &str,
+    token: &str,
+    mount: &str,
+    path: &str,
+    namespace: Option<&str>,
+) -> anyhow::Result<ProvidedSecrets> {
+    let url = format!(
+        "{}/v1/{}/data/{}",
+        address.trim_end_matches('/'),
+        mount.trim_matches('/'),
+        path.trim_start_matches('/')
+    );
+
+    let mut request = client.get(url).header("X-Vault-Token", token);
+    if let Some(namespace) = namespace {
+        request = request.header("X-Vault-Namespace", namespace);
+    }
+
+    let response: VaultResponse = request.send().await?.error_for_status()?.json().await?;
+    Ok(response.data.data)
+}
+
+/// Fetches a secret from AWS Secrets Manager.
+async fn fetch_aws_secrets_manager(
+    client: &reqwest::Client,
+    region: &str,
+    secret_id: &str,
+    endpoint: Option<&str>,
+) -> anyhow::Result<ProvidedSecrets> {
+    let credentials = AwsCredentialsProvider::from_env(client.clone())?
+        .credentials()
+        .await?;
+    let url = AWS_GET_SECRET_VALUE.endpoint(region, endpoint)?;
+
+    let body = serde_json::to_vec(&serde_json::json!({ "SecretId": secret_id }))?;
+    let response = send_aws_request(
+        client,
+        &credentials,
+        AWS_GET_SECRET_VALUE,
+        region,
+        &url,
+        body,
+    )
+    .await?;
+    let response: AwsSecretValue = serde_json::from_slice(&response)?;
+
+    let secret = response
+        .secret_string
+        .context("secret does not contain a string value")?;
+
+    Ok(serde_json::from_str(&secret)?)
+}
+
+/// Fetches secrets from the given provider.
+async fn fetch_secrets(
+    client: &reqwest::Client,
+    provider: &SecretsProvider,
+) -> anyhow::Result<ProvidedSecrets> {
+    match provider {
+        SecretsProvider::Vault {
+            address,
+            token,
+            mount,
+            path,
+            namespace,
+        } => fetch_vault(client, address, token, mount, path, namespace.as_deref()).await,
+        SecretsProvider::AwsSecretsManager {
+            region,
+            secret_id,
+            endpoint,
+        } => fetch_aws_secrets_manager(client, region, secret_id, endpoint.as_deref()).await,
+    }
+}
+
+/// Fetches secrets from the configured provider and applies them to the configuration.
+///
+/// Returns `Ok(None)` if no secrets provider is configured. This must be called before the
+/// configuration is shared with services.
+pub async fn load_secrets(config: &mut Config) -> anyhow::Result<Option<ProvidedSecrets>> {
+    let Some(provider) = config.secrets_provider() else {
+        return Ok(None);
+    };
+
+    let client = client_builder(config)?.build()?;
+    let secrets = fetch_secrets(&client, provider)
+        .await
+        .context("failed to fetch secrets from secrets provider")?;
+
+    config.apply_provided_secrets(&secrets);
+    relay_log::info!("loaded secrets from secrets provider");
+
+    if config.relay_mode() == RelayMode::Managed && !config.has_credentials() {
+        anyhow::bail!("relay has no credentials, which are required in managed mode");
+    }
+
+    Ok(Some(secrets))
+}
+
+/// Service that periodically fetches secrets to pick up rotations.
+#[derive(Debug)]
+pub struct SecretsService {
+    config: Arc<Config>,
+    client: reqwest::Client,
+    secrets: ProvidedSecrets,
+    audit_log: Addr<AuditLog>,
+}
+
+impl SecretsService {
+    /// Creates a new secrets service with the secrets fetched at startup.
+    pub fn new(
+        config: Arc<Config>,
+        secrets: ProvidedSecrets,
+        audit_log: Addr<AuditLog>,
+    ) -> anyhow::Result<Self> {
+        let client = client_builder(&config)?.build()?;
+        Ok(Self {
+            config,
+            client,
+            secrets,
+            audit_log,
+        })
+    }
+
+    /// Applies secrets fetched at runtime.
+    fn apply(&mut self, secrets: ProvidedSecrets) {
+        if let Some(credentials) = secrets.credentials() {
+            let previous = self.config.credentials();
+            let changes = audit::diff(&previous, &Some(credentials.clone()));
+
+            if self.config.rotate_credentials(credentials) {
+                relay_log::info!("rotated relay credentials from secrets provider");
+                self.audit_log.send(AuditEvent::new(
+                    AuditSubject::Credentials,
+                    AuditSource::SecretsProvider,
+                    changes,
+                ));
+            }
+        }
+
+        if secrets.redis != self.secrets.redis || secrets.kafka_config != self.secrets.kafka_config
+        {
+            relay_log::warn!("redis or kafka credentials changed, restart relay to apply them");
+        }
+
+        self.secrets = secrets;
+    }
+}
+
+impl Service for SecretsService {
+    type Interface = ();
+
+    fn spawn_handler(mut self, _rx: relay_system::Receiver<Self::Interface>) {
+        let (Some(provider), Some(interval)) = (
+            self.config.secrets_provider().cloned(),
+            self.config.secrets_refresh_interval(),
+        ) else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                match fetch_secrets(&self.client, &provider).await {
+                    Ok(secrets) => self.apply(secrets),
+                    Err(error) => relay_log::error!(
+                        error = error.as_ref() as &dyn Error,
+                        "failed to fetch secrets from secrets provider"
+                    ),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_response() {
+        let json = r#"{
+            "data": {
+                "data": {
+                    "redis": "redis://:hunter2@redis:6379",
+                    "kafka_config": {"sasl.password": "swordfish"}
+                },
+                "metadata": {"version": 3}
+            }
+        }"#;
+
+        let response: VaultResponse = serde_json::from_str(json).unwrap();
+        let secrets = response.data.data;
+        assert_eq!(
+            secrets.redis.as_deref(),
+            Some("redis://:hunter2@redis:6379")
+        );
+        assert_eq!(secrets.kafka_config["sasl.password"], "swordfish");
+        assert!(secrets.credentials().is_none());
+    }
+}
@@ -14,6 +14,7 @@ use tower_http::set_header::SetResponseHeaderLayer;
 use crate::constants;
 use crate::middlewares::{
     self, CatchPanicLayer, HandleErrorLayer, NormalizePathLayer, RequestDecompressionLayer,
+    ResponseHeaders,
 };
 use crate::service::ServiceState;
 use crate::statsd::RelayCounters;
@@ -71,6 +72,10 @@ impl Service for HttpServer {
         let middleware = ServiceBuilder::new()
             .layer(axum::middleware::from_fn(middlewares::metrics))
             .layer(CatchPanicLayer::custom(middlewares::handle_panic))
+            .layer(axum::middleware::from_fn_with_state(
+                ResponseHeaders::new(config.response_headers()),
+                middlewares::response_headers,
+            ))
             .layer(SetResponseHeaderLayer::overriding(
                 header::SERVER,
                 HeaderValue::from_static(constants::SERVER),
@@ -79,9 +84,17 @@ impl Service for HttpServer {
                 HeaderName::from_static("cross-origin-resource-policy"),
                 HeaderValue::from_static("cross-origin"),
             ))
+            .layer(SetResponseHeaderLayer::if_not_present(
+                header::ACCEPT_ENCODING,
+                HeaderValue::from_static(constants::ACCEPTED_ENCODINGS),
+            ))
             .layer(NewSentryLayer::new_from_top())
             .layer(SentryHttpLayer::with_transaction())
             .layer(middlewares::trace_http_layer())
+            .layer(axum::middleware::from_fn_with_state(
+                service.capture().cloned(),
+                middlewares::capture,
+            ))
             .layer(HandleErrorLayer::new(middlewares::decompression_error))
             .map_request(middlewares::remove_empty_encoding)
             .layer(RequestDecompressionLayer::new());
@@ -0,0 +1,831 @@
+//! Sending of messages to AWS Kinesis data streams and SQS queues.
+//!
+//! Messages are sent to a background task, which collects them into batches per stream or queue.
+//! Batches are sent once they reach the limits of the `PutRecords` or `SendMessageBatch` action,
+//! or after `max_batch_delay` at the latest.
+//!
+//! Up to [`MAX_CONCURRENT_REQUESTS`] batches are sent concurrently. Records and SQS messages that
+//! fail are sent again with exponential backoff, up to `max_retries` times, after which they are
+//! dropped with an outcome. Messages rejected by SQS as invalid are dropped right away. The sink
+//! accepts at most `max_queue_size` messages that have not been sent yet and rejects new messages
+//! once this limit is reached.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use data_encoding::{BASE64, HEXLOWER};
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use relay_aws::{send_aws_request, AwsAction, AwsCredentialsProvider};
+use relay_config::{AwsPartitionKey, AwsSinkConfig};
+use relay_kafka::Message;
+use relay_statsd::metric;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::actors::sinks::retry::{self, MessageOrigin, RetryQueue, SinkOutcomes};
+use crate::actors::sinks::{SinkError, SinkRoute};
+use crate::statsd::RelayCounters;
+use crate::utils::RetryBackoff;
+
+/// The maximum number of requests to AWS that are sent concurrently.
+const MAX_CONCURRENT_REQUESTS: usize = 16;
+
+/// The `PutRecords` action of the Kinesis API.
+const KINESIS_PUT_RECORDS: AwsAction<'static> = AwsAction {
+    service: "kinesis",
+    target: "Kinesis_20131202.PutRecords",
+    content_type: "application/x-amz-json-1.1",
+};
+
+/// The `SendMessageBatch` action of the SQS API.
+const SQS_SEND_MESSAGE_BATCH: AwsAction<'static> = AwsAction {
+    service: "sqs",
+    target: "AmazonSQS.SendMessageBatch",
+    content_type: "application/x-amz-json-1.0",
+};
+
+/// Limits of a destination for a single message and for a batch of messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Limits {
+    /// The maximum size of a single message.
+    message_size: usize,
+    /// The maximum number of messages in a batch.
+    batch_messages: usize,
+    /// The maximum total size of messages in a batch.
+    batch_size: usize,
+}
+
+/// Limits of the Kinesis `PutRecords` action.
+const KINESIS_LIMITS: Limits = Limits {
+    message_size: 1024 * 1024,
+    batch_messages: 500,
+    batch_size: 5 * 1024 * 1024,
+};
+
+/// Limits of the SQS `SendMessageBatch` action.
+const SQS_LIMITS: Limits = Limits {
+    message_size: 256 * 1024,
+    batch_messages: 10,
+    batch_size: 256 * 1024,
+};
+
+/// A Kinesis data stream or SQS queue.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Destination {
+    /// The name of a Kinesis data stream.
+    Stream(String),
+    /// The URL of an SQS queue.
+    Queue(String),
+}
+
+impl Destination {
+    fn limits(&self) -> Limits {
+        match self {
+            Self::Stream(_) => KINESIS_LIMITS,
+            Self::Queue(_) => SQS_LIMITS,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Stream(name) => name,
+            Self::Queue(url) => url,
+        }
+    }
+
+    /// Returns the name of the sink used in metrics.
+    fn sink(&self) -> &'static str {
+        match self {
+            Self::Stream(_) => "kinesis",
+            Self::Queue(_) => "sqs",
+        }
+    }
+}
+
+/// A message waiting to be sent.
+#[derive(Debug)]
+struct AwsMessage {
+    data: Vec<u8>,
+    partition_key: String,
+    attributes: BTreeMap<String, String>,
+    /// The deduplication ID for FIFO queues, which remains the same across retries.
+    deduplication_id: String,
+    origin: MessageOrigin,
+}
+
+impl AwsMessage {
+    /// Returns the size of the message counted towards the limits of the destination.
+    ///
+    /// SQS message bodies are base64-encoded and include message attributes.
+    fn size(&self, destination: &Destination) -> usize {
+        match destination {
+            Destination::Stream(_) => self.data.len() + self.partition_key.len(),
+            Destination::Queue(_) => {
+                let attributes: usize = self
+                    .attributes
+                    .iter()
+                    // Every attribute also counts its data type `String`.
+                    .map(|(key, value)| key.len() + value.len() + 6)
+                    .sum();
+                BASE64.encode_len(self.data.len()) + attributes
+            }
+        }
+    }
+}
+
+/// A record in a Kinesis `PutRecords` request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct KinesisRecord<'a> {
+    data: String,
+    partition_key: &'a str,
+}
+
+/// Body of a Kinesis `PutRecords` request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PutRecordsRequest<'a> {
+    stream_name: &'a str,
+    records: Vec<KinesisRecord<'a>>,
+}
+
+/// The result of a single record in a Kinesis `PutRecords` response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PutRecordsResultEntry {
+    #[serde(default)]
+    error_code: Option<String>,
+}
+
+/// Response of a Kinesis `PutRecords` request.
+///
+/// The results are in the same order as the records of the request.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PutRecordsResponse {
+    #[serde(default)]
+    records: Vec<PutRecordsResultEntry>,
+}
+
+/// A message attribute in an SQS request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SqsAttribute<'a> {
+    data_type: &'static str,
+    string_value: &'a str,
+}
+
+/// An entry in an SQS `SendMessageBatch` request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SqsEntry<'a> {
+    id: String,
+    message_body: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    message_attributes: BTreeMap<&'a str, SqsAttribute<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_group_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_deduplication_id: Option<&'a str>,
+}
+
+/// Body of an SQS `SendMessageBatch` request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendMessageBatchRequest<'a> {
+    queue_url: &'a str,
+    entries: Vec<SqsEntry<'a>>,
+}
+
+/// A failed entry in an SQS `SendMessageBatch` response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SqsErrorEntry {
+    id: String,
+    code: String,
+    /// Whether the message was rejected as invalid, in which case sending it again fails, too.
+    #[serde(default)]
+    sender_fault: bool,
+}
+
+/// Response of an SQS `SendMessageBatch` request.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendMessageBatchResponse {
+    #[serde(default)]
+    failed: Vec<SqsErrorEntry>,
+}
+
+/// A record or SQS message that failed within an otherwise successful request.
+#[derive(Debug, PartialEq, Eq)]
+struct FailedRecord {
+    /// The index of the message in its batch.
+    index: usize,
+    /// The error code returned by AWS.
+    code: String,
+    /// Whether sending the message again can succeed.
+    retryable: bool,
+}
+
+/// Returns the failed records of a Kinesis `PutRecords` response.
+fn kinesis_failures(response: PutRecordsResponse) -> Vec<FailedRecord> {
+    response
+        .records
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            Some(FailedRecord {
+                index,
+                code: entry.error_code?,
+                // Both throttling and internal failures can succeed on the next attempt.
+                retryable: true,
+            })
+        })
+        .collect()
+}
+
+/// Returns the failed messages of an SQS `SendMessageBatch` response.
+fn sqs_failures(response: SendMessageBatchResponse) -> anyhow::Result<Vec<FailedRecord>> {
+    response
+        .failed
+        .into_iter()
+        .map(|entry| {
+            Ok(FailedRecord {
+                index: entry.id.parse()?,
+                code: entry.code,
+                retryable: !entry.sender_fault,
+            })
+        })
+        .collect()
+}
+
+/// Messages collected for a single destination.
+#[derive(Debug, Default)]
+struct Batch {
+    messages: Vec<AwsMessage>,
+    size: usize,
+}
+
+impl Batch {
+    /// Returns `true` if the message does not fit into this batch.
+    fn is_full(&self, limits: Limits, size: usize) -> bool {
+        self.messages.len() >= limits.batch_messages || self.size + size > limits.batch_size
+    }
+}
+
+/// The result of sending a batch, along with the batch for retries.
+type SendResult = (
+    Destination,
+    Batch,
+    RetryBackoff,
+    anyhow::Result<Vec<FailedRecord>>,
+);
+
+/// A request to AWS that has not completed yet.
+type PendingSend = Pin<Box<dyn Future<Output = SendResult> + Send>>;
+
+/// Sends messages to AWS Kinesis data streams and SQS queues.
+///
+/// Messages are sent asynchronously by a background task. Call [`start`](Self::start) within a
+/// Tokio runtime before sending messages.
+#[derive(Debug)]
+pub struct AwsSink {
+    config: Arc<AwsSinkConfig>,
+    tx: mpsc::Sender<(Destination, AwsMessage)>,
+    publisher: Option<Publisher>,
+    healthy: Arc<AtomicBool>,
+}
+
+impl AwsSink {
+    /// Creates a new sink without starting the background task.
+    ///
+    /// Outcomes for messages that are dropped after failed retries are emitted to `outcomes`.
+    pub fn create(
+        config: &AwsSinkConfig,
+        client: reqwest::Client,
+        outcomes: SinkOutcomes,
+    ) -> anyhow::Result<Self> {
+        let config = Arc::new(config.clone());
+        let healthy = Arc::new(AtomicBool::new(true));
+        let (tx, rx) = mpsc::channel(config.max_queue_size.max(1));
+
+        let client = AwsClient {
+            credentials: AwsCredentialsProvider::from_env(client.clone())?,
+            kinesis_url: KINESIS_PUT_RECORDS
+                .endpoint(&config.region, config.kinesis_endpoint.as_deref())?,
+            sqs_url: SQS_SEND_MESSAGE_BATCH
+                .endpoint(&config.region, config.sqs_endpoint.as_deref())?,
+            config: config.clone(),
+            client,
+        };
+
+        let publisher = Publisher {
+            retries: RetryQueue::new(config.max_retries),
+            config: config.clone(),
+            client: Arc::new(client),
+            rx,
+            batches: BTreeMap::new(),
+            in_flight: FuturesUnordered::new(),
+            healthy: healthy.clone(),
+            outcomes,
+            shutdown: false,
+        };
+
+        Ok(Self {
+            config,
+            tx,
+            publisher: Some(publisher),
+            healthy,
+        })
+    }
+
+    /// Spawns the background task that sends messages.
+    pub fn start(&mut self) {
+        if let Some(publisher) = self.publisher.take() {
+            tokio::spawn(publisher.run());
+        }
+    }
+
+    /// Queues a message for sending to the stream or queue of its data category.
+    ///
+    /// Fails with [`SinkError::QueueFull`] if `max_queue_size` messages are waiting to be sent.
+    pub fn send(&self, route: SinkRoute, message: &impl Message) -> Result<(), SinkError> {
+        let destination = if let Some(stream) = self.config.streams.get(&route.category) {
+            Destination::Stream(stream.clone())
+        } else if let Some(queue) = self.config.queues.get(&route.category) {
+            Destination::Queue(queue.clone())
+        } else {
+            return Err(SinkError::NoTopic(route.category));
+        };
+
+        let partition_key = match self.config.partition_key {
+            AwsPartitionKey::MessageKey => HEXLOWER.encode(&message.key()),
+            AwsPartitionKey::Organization => route.organization_id.to_string(),
+            // Buckets merged across projects are partitioned by their organization instead.
+            AwsPartitionKey::Project => match route.project_id {
+                Some(project_id) => project_id.to_string(),
+                None => route.organization_id.to_string(),
+            },
+        };
+
+        let data = message.serialize()?;
+        let message = AwsMessage {
+            origin: MessageOrigin::new(route, data.len()),
+            data,
+            partition_key,
+            attributes: message.headers().cloned().unwrap_or_default(),
+            deduplication_id: Uuid::new_v4().simple().to_string(),
+        };
+
+        let size = message.size(&destination);
+        let limit = destination.limits().message_size;
+        if size > limit {
+            return Err(SinkError::TooLarge { size, limit });
+        }
+
+        self.tx
+            .try_send((destination, message))
+            .map_err(|error| match error {
+                mpsc::error::TrySendError::Full(_) => SinkError::QueueFull,
+                mpsc::error::TrySendError::Closed(_) => SinkError::Closed,
+            })
+    }
+
+    /// Returns `true` if no messages are waiting to be sent again after a failure.
+    pub fn is_connected(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+}
+
+/// Sends batches to Kinesis and SQS.
+///
+/// This is shared by all concurrent requests of the [`Publisher`].
+#[derive(Debug)]
+struct AwsClient {
+    config: Arc<AwsSinkConfig>,
+    credentials: AwsCredentialsProvider,
+    kinesis_url: url::Url,
+    sqs_url: url::Url,
+    client: reqwest::Client,
+}
+
+impl AwsClient {
+    /// Sends a batch to its destination and returns the records that failed.
+    async fn send(
+        &self,
+        destination: &Destination,
+        batch: &Batch,
+    ) -> anyhow::Result<Vec<FailedRecord>> {
+        match destination {
+            Destination::Stream(stream) => self.put_records(stream, batch).await,
+            Destination::Queue(queue) => self.send_message_batch(queue, batch).await,
+        }
+    }
+
+    /// Sends a batch to a Kinesis data stream and returns the records that failed.
+    async fn put_records(&self, stream: &str, batch: &Batch) -> anyhow::Result<Vec<FailedRecord>> {
+        let records = batch
+            .messages
+            .iter()
+            .map(|message| KinesisRecord {
+                data: BASE64.encode(&message.data),
+                partition_key: &message.partition_key,
+            })
+            .collect();
+
+        let body = serde_json::to_vec(&PutRecordsRequest {
+            stream_name: stream,
+            records,
+        })?;
+
+        let credentials = self.credentials.credentials().await?;
+        let response = send_aws_request(
+            &self.client,
+            &credentials,
+            KINESIS_PUT_RECORDS,
+            &self.config.region,
+            &self.kinesis_url,
+            body,
+        )
+        .await?;
+
+        let response: PutRecordsResponse = serde_json::from_slice(&response)?;
+        Ok(kinesis_failures(response))
+    }
+
+    /// Sends a batch to an SQS queue and returns the messages that failed.
+    async fn send_message_batch(
+        &self,
+        queue: &str,
+        batch: &Batch,
+    ) -> anyhow::Result<Vec<FailedRecord>> {
+        let body = serde_json::to_vec(&SendMessageBatchRequest {
+            queue_url: queue,
+            entries: sqs_entries(queue, batch),
+        })?;
+
+        let credentials = self.credentials.credentials().await?;
+        let response = send_aws_request(
+            &self.client,
+            &credentials,
+            SQS_SEND_MESSAGE_BATCH,
+            &self.config.region,
+            &self.sqs_url,
+            body,
+        )
+        .await?;
+
+        let response: SendMessageBatchResponse = serde_json::from_slice(&response)?;
+        sqs_failures(response)
+    }
+}
+
+/// The background task of [`AwsSink`].
+struct Publisher {
+    config: Arc<AwsSinkConfig>,
+    client: Arc<AwsClient>,
+    rx: mpsc::Receiver<(Destination, AwsMessage)>,
+    batches: BTreeMap<Destination, Batch>,
+    retries: RetryQueue<Destination, Batch>,
+    in_flight: FuturesUnordered<PendingSend>,
+    healthy: Arc<AtomicBool>,
+    outcomes: SinkOutcomes,
+    /// Drops failed messages instead of retrying them while shutting down.
+    shutdown: bool,
+}
+
+impl fmt::Debug for Publisher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Publisher")
+            .field("batches", &self.batches)
+            .field("retries", &self.retries)
+            .field("in_flight", &self.in_flight.len())
+            .finish()
+    }
+}
+
+impl Publisher {
+    async fn run(mut self) {
+        let mut ticker = tokio::time::interval(Duration::from_millis(self.config.max_batch_delay));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            let next_retry = self.retries.next_due();
+            // Failed messages count towards the queue size, so that the channel fills up and new
+            // messages are rejected while AWS is unavailable.
+            let accepts_messages = self.in_flight.len() < MAX_CONCURRENT_REQUESTS
+                && self.retries.messages() < self.config.max_queue_size;
+
+            tokio::select! {
+                biased;
+
+                Some((destination, batch, backoff, result)) = self.in_flight.next() => {
+                    self.sent(destination, batch, backoff, result)
+                },
+                _ = tokio::time::sleep_until(next_retry.unwrap_or_else(Instant::now)), if next_retry.is_some() => {
+                    self.retry_due()
+                },
+                message = self.rx.recv(), if accepts_messages => match message {
+                    Some((destination, message)) => self.push(destination, message),
+                    None => break,
+                },
+                _ = ticker.tick() => self.flush_all(),
+            }
+        }
+
+        // Send all remaining messages once more and drop them if that fails.
+        self.flush_all();
+        for (destination, batch, backoff) in self.retries.take_all() {
+            self.send(destination, batch, backoff);
+        }
+
+        self.shutdown = true;
+        while let Some((destination, batch, backoff, result)) = self.in_flight.next().await {
+            self.sent(destination, batch, backoff, result);
+        }
+    }
+
+    /// Adds a message to the batch of its destination.
+    ///
+    /// If the message does not fit into the batch, the batch is sent first.
+    fn push(&mut self, destination: Destination, message: AwsMessage) {
+        let limits = destination.limits();
+        let size = message.size(&destination);
+
+        let batch = self.batches.entry(destination.clone()).or_default();
+        if batch.is_full(limits, size) {
+            let full = std::mem::take(batch);
+            self.send(destination.clone(), full, retry::backoff());
+        }
+
+        let batch = self.batches.entry(destination).or_default();
+        batch.size += size;
+        batch.messages.push(message);
+    }
+
+    /// Sends all pending batches.
+    fn flush_all(&mut self) {
+        for (destination, batch) in std::mem::take(&mut self.batches) {
+            if !batch.messages.is_empty() {
+                self.send(destination, batch, retry::backoff());
+            }
+        }
+    }
+
+    /// Sends all failed messages that are due for their next attempt.
+    fn retry_due(&mut self) {
+        for (destination, batch, backoff) in self.retries.take_due() {
+            self.send(destination, batch, backoff);
+        }
+    }
+
+    /// Starts sending a batch without waiting for the response.
+    fn send(&mut self, destination: Destination, batch: Batch, backoff: RetryBackoff) {
+        let client = self.client.clone();
+        self.in_flight.push(Box::pin(async move {
+            let result = client.send(&destination, &batch).await;
+            (destination, batch, backoff, result)
+        }));
+    }
+
+    /// Handles the response of a batch.
+    ///
+    /// Failed messages are scheduled for a retry. Once they have exhausted their retries, or if
+    /// they cannot succeed, they are dropped with an outcome.
+    fn sent(
+        &mut self,
+        destination: Destination,
+        batch: Batch,
+        backoff: RetryBackoff,
+        result: anyhow::Result<Vec<FailedRecord>>,
+    ) {
+        let sink = destination.sink();
+        let total = batch.messages.len();
+
+        // If the entire request fails, all messages are sent again.
+        let failed: BTreeMap<usize, bool> = match result {
+            Ok(ref failed) => failed.iter().map(|f| (f.index, f.retryable)).collect(),
+            Err(_) => (0..total).map(|index| (index, true)).collect(),
+        };
+
+        metric!(
+            counter(RelayCounters::SinkMessages) += (total - failed.len()) as i64,
+            sink = sink,
+            result = "published"
+        );
+
+        let mut retry = Batch::default();
+        let mut dropped = Vec::new();
+        for (index, message) in batch.messages.into_iter().enumerate() {
+            match failed.get(&index) {
+                None => (),
+                Some(true) if !self.shutdown => {
+                    retry.size += message.size(&destination);
+                    retry.messages.push(message);
+                }
+                Some(_) => dropped.push(message.origin),
+            }
+        }
+
+        let retries = backoff.attempt();
+        if !retry.messages.is_empty() {
+            let count = retry.messages.len();
+            match self
+                .retries
+                .schedule(destination.clone(), retry, count, backoff)
+            {
+                Ok(()) => metric!(
+                    counter(RelayCounters::SinkMessages) += count as i64,
+                    sink = sink,
+                    result = "retried"
+                ),
+                Err(retry) => dropped.extend(retry.messages.into_iter().map(|m| m.origin)),
+            }
+        }
+
+        if !dropped.is_empty() {
+            match result {
+                Err(error) => relay_log::error!(
+                    error = error.as_ref() as &dyn Error,
+                    tags.destination = destination.name(),
+                    retries,
+                    "failed to send messages to {sink}"
+                ),
+                Ok(failed) => relay_log::error!(
+                    tags.destination = destination.name(),
+                    error_code = failed.first().map(|f| f.code.as_str()),
+                    retries,
+                    "failed to send {} messages to {sink}",
+                    dropped.len(),
+                ),
+            }
+
+            self.outcomes.dropped(sink, &dropped);
+        }
+
+        self.healthy
+            .store(self.retries.is_empty(), Ordering::Relaxed);
+    }
+}
+
+/// Creates the entries of an SQS `SendMessageBatch` request.
+fn sqs_entries<'a>(queue: &str, batch: &'a Batch) -> Vec<SqsEntry<'a>> {
+    let fifo = queue.ends_with(".fifo");
+
+    batch
+        .messages
+        .iter()
+        .enumerate()
+        .map(|(index, message)| SqsEntry {
+            id: index.to_string(),
+            message_body: BASE64.encode(&message.data),
+            message_attributes: message
+                .attributes
+                .iter()
+                .map(|(key, value)| {
+                    let attribute = SqsAttribute {
+                        data_type: "String",
+                        string_value: value,
+                    };
+                    (key.as_str(), attribute)
+                })
+                .collect(),
+            message_group_id: fifo.then_some(message.partition_key.as_str()),
+            message_deduplication_id: fifo.then_some(message.deduplication_id.as_str()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_base_schema::project::ProjectId;
+    use relay_kafka::KafkaTopic;
+    use relay_quotas::DataCategory;
+
+    use super::*;
+
+    fn message(size: usize) -> AwsMessage {
+        let route = SinkRoute {
+            topic: KafkaTopic::Events,
+            organization_id: 1,
+            project_id: Some(ProjectId::new(1)),
+            category: DataCategory::Error,
+            kafka_route: None,
+        };
+
+        AwsMessage {
+            data: vec![0; size],
+            partition_key: "key".to_owned(),
+            attributes: BTreeMap::from([("org_id".to_owned(), "1".to_owned())]),
+            deduplication_id: "dedup".to_owned(),
+            origin: MessageOrigin::new(route, size),
+        }
+    }
+
+    #[test]
+    fn test_message_size() {
+        let message = message(3000);
+
+        let stream = Destination::Stream("stream".to_owned());
+        assert_eq!(message.size(&stream), 3003);
+
+        let queue = Destination::Queue("https://sqs/queue".to_owned());
+        assert_eq!(message.size(&queue), 4000 + 13);
+    }
+
+    #[test]
+    fn test_batch_limits() {
+        let mut batch = Batch::default();
+        for _ in 0..10 {
+            assert!(!batch.is_full(SQS_LIMITS, 100));
+            batch.messages.push(message(100));
+            batch.size += 100;
+        }
+        assert!(batch.is_full(SQS_LIMITS, 100));
+
+        let batch = Batch {
+            messages: vec![message(0)],
+            size: 5 * 1024 * 1024 - 10,
+        };
+        assert!(!batch.is_full(KINESIS_LIMITS, 10));
+        assert!(batch.is_full(KINESIS_LIMITS, 11));
+    }
+
+    #[test]
+    fn test_sqs_entries() {
+        let batch = Batch {
+            messages: vec![message(3)],
+            size: 0,
+        };
+
+        let entries = sqs_entries("https://sqs/queue", &batch);
+        let json = serde_json::to_string(&entries).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"Id":"0","MessageBody":"AAAA","MessageAttributes":{"org_id":{"DataType":"String","StringValue":"1"}}}]"#
+        );
+
+        let entries = sqs_entries("https://sqs/queue.fifo", &batch);
+        assert_eq!(entries[0].message_group_id, Some("key"));
+        assert_eq!(entries[0].message_deduplication_id, Some("dedup"));
+    }
+
+    #[test]
+    fn test_kinesis_failures() {
+        let response = r#"{
+            "FailedRecordCount": 1,
+            "Records": [
+                {"SequenceNumber": "1", "ShardId": "shardId-000000000000"},
+                {"ErrorCode": "ProvisionedThroughputExceededException", "ErrorMessage": "Rate exceeded"},
+                {"SequenceNumber": "2", "ShardId": "shardId-000000000000"}
+            ]
+        }"#;
+
+        let response = serde_json::from_str(response).unwrap();
+        assert_eq!(
+            kinesis_failures(response),
+            vec![FailedRecord {
+                index: 1,
+                code: "ProvisionedThroughputExceededException".to_owned(),
+                retryable: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_sqs_failures() {
+        let response = r#"{
+            "Successful": [{"Id": "0", "MessageId": "a"}],
+            "Failed": [
+                {"Id": "1", "Code": "InternalError", "SenderFault": false},
+                {"Id": "2", "Code": "InvalidMessageContents", "SenderFault": true}
+            ]
+        }"#;
+
+        let response = serde_json::from_str(response).unwrap();
+        assert_eq!(
+            sqs_failures(response).unwrap(),
+            vec![
+                FailedRecord {
+                    index: 1,
+                    code: "InternalError".to_owned(),
+                    retryable: true,
+                },
+                FailedRecord {
+                    index: 2,
+                    code: "InvalidMessageContents".to_owned(),
+                    retryable: false,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,876 @@
+//! Producing of messages to Kafka.
+//!
+//! If `spool.kafka.path` is configured, messages that cannot be produced because the producer
+//! queue is full or the brokers are unreachable are written to a [`KafkaSpool`] on disk. This
+//! includes messages that the producer accepted but failed to deliver, such as after
+//! `message.timeout.ms`, which are reported asynchronously by its delivery callback. The store
+//! periodically produces spooled messages again, one batch at a time, and drops them with an
+//! outcome once they have failed `spool.kafka.max_retries` times.
+//!
+//! If `processing.dead_letters` is enabled, messages that cannot be serialized or exceed the
+//! maximum message size of the brokers are wrapped in a [`DeadLetterMessage`] and produced to the
+//! dead-letter topic. Messages that the brokers reject as too large after the producer accepted
+//! them are reported by the delivery callback and produced to the dead-letter topic on the next
+//! tick of the store, see [`KafkaSink::replay`].
+//!
+//! With transactional delivery, the store wraps the messages of each envelope in a transaction,
+//! see [`KafkaSink::begin_transaction`]. Failed messages are not spooled, since the transaction
+//! of their envelope is aborted instead.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use data_encoding::BASE64;
+use relay_base_schema::project::ProjectId;
+use relay_config::Config;
+use relay_kafka::{
+    to_avro_value, AvroValue, ClientError, DeliveryFailure, DeliveryFailureHandler, EncodedMessage,
+    KafkaClient, KafkaTopic, Message,
+};
+use relay_quotas::DataCategory;
+use relay_statsd::metric;
+use relay_system::Addr;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::actors::outcome::{DiscardReason, Outcome, OutcomeProducer, TrackRawOutcome};
+use crate::actors::sinks::{SinkError, SinkRoute};
+use crate::actors::spooler::kafka::{KafkaSpool, SpooledMessage};
+use crate::actors::spooler::BufferError;
+use crate::statsd::RelayCounters;
+
+/// The maximum time to wait for Kafka brokers to respond to a health check.
+const KAFKA_HEALTH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The maximum number of dead letters from the delivery callback waiting to be produced.
+const MAX_PENDING_DEAD_LETTERS: usize = 1000;
+
+/// Metadata of a message produced to Kafka, used to spool it if it fails.
+#[derive(Clone, Debug)]
+struct Delivery {
+    route: SinkRoute,
+    /// The name of the Kafka topic the message is produced to.
+    topic_name: String,
+    /// The time at which the message was first produced.
+    received_at: DateTime<Utc>,
+    /// The number of failed attempts to produce the message from the spool.
+    attempts: u32,
+    /// Whether the message is written to the spool if the producer fails to deliver it.
+    spills: bool,
+}
+
+impl Delivery {
+    /// Creates a message that waits to be written to the spool.
+    fn into_spooled(self, message: EncodedMessage) -> SpooledMessage {
+        SpooledMessage {
+            id: 0,
+            attempts: self.attempts,
+            topic_name: self.topic_name,
+            received_at: self.received_at,
+            route: self.route,
+            message,
+        }
+    }
+}
+
+/// Produces messages to Kafka and spools them on disk if the producer fails.
+#[derive(Debug)]
+pub struct KafkaSink {
+    config: Arc<Config>,
+    client: KafkaClient,
+    outcomes: Addr<OutcomeProducer>,
+    spool: Option<KafkaSpool>,
+    spill_tx: mpsc::Sender<SpooledMessage>,
+    spill_rx: Option<mpsc::Receiver<SpooledMessage>>,
+    dead_letter_rx: mpsc::Receiver<DeadLetterMessage>,
+}
+
+impl KafkaSink {
+    /// Creates a sink with producers for all topics of the store.
+    ///
+    /// The spool is opened in [`start`](Self::start).
+    pub fn create(config: Arc<Config>, outcomes: Addr<OutcomeProducer>) -> anyhow::Result<Self> {
+        let (spill_tx, spill_rx) = mpsc::channel(config.spool_kafka_max_queue_size());
+        let (dead_letter_tx, dead_letter_rx) = mpsc::channel(MAX_PENDING_DEAD_LETTERS);
+
+        let failures: DeliveryFailureHandler = {
+            let config = config.clone();
+            let outcomes = outcomes.clone();
+            let spill_tx = spill_tx.clone();
+            Arc::new(move |failure| {
+                handle_delivery_failure(&config, &outcomes, &spill_tx, &dead_letter_tx, failure)
+            })
+        };
+
+        Ok(Self {
+            client: super::create_kafka_client(&config, failures)?,
+            config,
+            outcomes,
+            spool: None,
+            spill_tx,
+            spill_rx: Some(spill_rx),
+            dead_letter_rx,
+        })
+    }
+
+    /// Returns the underlying Kafka client.
+    pub fn client_mut(&mut self) -> &mut KafkaClient {
+        &mut self.client
+    }
+
+    /// Opens the spool, if configured, and spawns the background task that writes to it.
+    ///
+    /// Exits the process if the spool file cannot be opened.
+    pub async fn start(&mut self) {
+        let spool = match KafkaSpool::open(&self.config).await {
+            Ok(Some(spool)) => spool,
+            Ok(None) => return,
+            Err(error) => {
+                relay_log::error!(error = &error as &dyn Error, "failed to open kafka spool");
+                // NOTE: The process will exit with error if the spool file could not be opened
+                // or the migrations could not be run.
+                std::process::exit(1);
+            }
+        };
+
+        let Some(rx) = self.spill_rx.take() else {
+            return;
+        };
+
+        tokio::spawn(write_spool(
+            spool.clone(),
+            rx,
+            self.config.clone(),
+            self.outcomes.clone(),
+        ));
+
+        self.spool = Some(spool);
+    }
+
+    /// Returns `true` if failed messages are written to the spool.
+    ///
+    /// With transactional delivery, failed messages abort the transaction of their envelope
+    /// instead.
+    fn spills(&self) -> bool {
+        self.spool.is_some() && !self.client.is_transactional()
+    }
+
+    /// Produces a message to the Kafka topic of its route.
+    ///
+    /// If producing fails with a retryable error and the spool is enabled, the message is written
+    /// to the spool instead and this returns `Ok`, unless more than `spool.kafka.max_queue_size`
+    /// messages are waiting to be written. The same applies if the producer fails to deliver the
+    /// message later. Messages that cannot be serialized or are too large are produced to the
+    /// dead-letter topic, if enabled, and the error is returned.
+    pub fn send(&self, route: SinkRoute, message: &impl Message) -> Result<(), SinkError> {
+        let encoded = match self.client.encode_message(
+            route.topic,
+            route.kafka_route,
+            route.organization_id,
+            message,
+        ) {
+            Ok(encoded) => encoded,
+            Err(error) => {
+                if error.is_serialization_error() {
+                    let payload = message.serialize().unwrap_or_default();
+                    self.send_dead_letter(DeadLetterMessage::new(
+                        &route,
+                        message,
+                        &payload,
+                        &error,
+                        self.config.kafka_dead_letter_max_payload_size(),
+                    ));
+                }
+                return Err(error.into());
+            }
+        };
+
+        // Track deliveries to spool messages or produce dead letters if the producer fails later.
+        let spills = self.spills();
+        let delivery = match spills || self.config.kafka_dead_letters_enabled() {
+            true => Some(Delivery {
+                route,
+                topic_name: self.client.topic_name(&encoded)?.to_owned(),
+                received_at: Utc::now(),
+                attempts: 0,
+                spills,
+            }),
+            false => None,
+        };
+
+        let result = match delivery {
+            Some(ref delivery) => self
+                .client
+                .send_encoded_tracked(&encoded, Arc::new(delivery.clone())),
+            None => self.client.send_encoded(&encoded),
+        };
+
+        let Err(error) = result else {
+            return Ok(());
+        };
+
+        if error.is_too_large() {
+            self.send_dead_letter(DeadLetterMessage::new(
+                &route,
+                message,
+                &encoded.payload,
+                &error,
+                self.config.kafka_dead_letter_max_payload_size(),
+            ));
+        }
+
+        match delivery {
+            Some(delivery) if delivery.spills && error.is_retryable() => self
+                .spill_tx
+                .try_send(delivery.into_spooled(encoded))
+                .map_err(|error| match error {
+                    mpsc::error::TrySendError::Full(_) => SinkError::QueueFull,
+                    mpsc::error::TrySendError::Closed(_) => SinkError::Closed,
+                }),
+            _ => Err(error.into()),
+        }
+    }
+
+    /// Begins a transaction if the sink produces transactionally.
+    ///
+    /// Every transaction must be ended with [`end_transaction`](Self::end_transaction).
+    pub fn begin_transaction(&self) -> Result<(), SinkError> {
+        self.client.begin_transaction()?;
+        Ok(())
+    }
+
+    /// Commits the current transaction if `commit` is `true`, and aborts it otherwise.
+    ///
+    /// This is a no-op if the sink does not produce transactionally. The brokers are awaited on a
+    /// blocking thread.
+    pub async fn end_transaction(&self, commit: bool) -> Result<(), SinkError> {
+        end_transaction(&self.client, commit).await?;
+        Ok(())
+    }
+
+    /// Produces a message to the dead-letter topic, if enabled.
+    fn send_dead_letter(&self, dead_letter: DeadLetterMessage) {
+        if !self.config.kafka_dead_letters_enabled() {
+            return;
+        }
+
+        metric!(
+            counter(RelayCounters::KafkaDeadLetters) += 1,
+            variant = &dead_letter.variant
+        );
+
+        if let Err(error) = self.client.send_message(
+            KafkaTopic::DeadLetters,
+            dead_letter.organization_id,
+            &dead_letter,
+        ) {
+            relay_log::error!(
+                error = &error as &dyn Error,
+                tags.variant = dead_letter.variant,
+                "failed to produce kafka dead letter"
+            );
+        }
+    }
+
+    /// Produces dead letters of messages that the producer failed to deliver after accepting
+    /// them, see [`handle_delivery_failure`].
+    async fn send_pending_dead_letters(&mut self) {
+        let mut dead_letters = Vec::new();
+        while let Ok(dead_letter) = self.dead_letter_rx.try_recv() {
+            dead_letters.push(dead_letter);
+        }
+
+        if dead_letters.is_empty() {
+            return;
+        }
+
+        // Without transactions, this is a no-op.
+        if let Err(error) = self.client.begin_transaction() {
+            relay_log::error!(
+                error = &error as &dyn Error,
+                "failed to begin kafka transaction for dead letters"
+            );
+            return;
+        }
+
+        for dead_letter in dead_letters {
+            self.send_dead_letter(dead_letter);
+        }
+
+        if let Err(error) = end_transaction(&self.client, true).await {
+            relay_log::error!(
+                error = &error as &dyn Error,
+                "failed to commit kafka transaction for dead letters"
+            );
+        }
+    }
+
+    /// Produces up to `spool.kafka.batch_size` spooled messages again.
+    ///
+    /// Dead letters reported by the delivery callback are produced first.
+    ///
+    /// Returns `true` if all of them were produced and the spool has more messages, in which case
+    /// the next batch can be replayed right away.
+    pub async fn replay(&mut self) -> bool {
+        self.send_pending_dead_letters().await;
+
+        let Some(spool) = self.spool.clone() else {
+            return false;
+        };
+
+        if !spool.has_backlog() {
+            return false;
+        }
+
+        let batch = match spool.peek(self.config.spool_kafka_batch_size()).await {
+            Ok(batch) => batch,
+            Err(error) => {
+                relay_log::error!(
+                    error = &error as &dyn Error,
+                    "failed to read from kafka spool"
+                );
+                return false;
+            }
+        };
+
+        for spooled in batch {
+            match self.replay_message(&spool, spooled).await {
+                Ok(true) => (),
+                Ok(false) => return false,
+                Err(error) => {
+                    relay_log::error!(error = &error as &dyn Error, "failed to update kafka spool");
+                    return false;
+                }
+            }
+        }
+
+        spool.has_backlog()
+    }
+
+    /// Produces a single spooled message again.
+    ///
+    /// Returns `false` if Kafka is still unavailable and replaying should stop.
+    async fn replay_message(
+        &mut self,
+        spool: &KafkaSpool,
+        spooled: SpooledMessage,
+    ) -> Result<bool, BufferError> {
+        let route = self.client.find_route(
+            spooled.route.topic,
+            spooled.route.organization_id,
+            &spooled.topic_name,
+        );
+
+        let Some(route) = route else {
+            relay_log::error!(
+                tags.variant = spooled.message.variant,
+                "kafka topic {} of spooled message is no longer configured",
+                spooled.topic_name
+            );
+            spool.remove(spooled.id).await?;
+            drop_message(&self.config, &self.outcomes, &spooled, "invalid");
+            return Ok(true);
+        };
+
+        let mut spooled = spooled;
+        spooled.route.kafka_route = route;
+        spooled.message.route = route;
+
+        let error = match self.send_spooled(&spooled).await {
+            Ok(()) => {
+                spool.remove(spooled.id).await?;
+                return Ok(true);
+            }
+            Err(error) => error,
+        };
+
+        if !error.is_retryable() {
+            relay_log::error!(
+                error = &error as &dyn Error,
+                tags.variant = spooled.message.variant,
+                "failed to produce spooled kafka message"
+            );
+            spool.remove(spooled.id).await?;
+            drop_message(&self.config, &self.outcomes, &spooled, "invalid");
+            return Ok(true);
+        }
+
+        if spooled.attempts + 1 >= self.config.spool_kafka_max_retries() {
+            spool.remove(spooled.id).await?;
+            drop_message(&self.config, &self.outcomes, &spooled, "retries_exhausted");
+        } else {
+            spool.record_attempt(spooled.id).await?;
+        }
+
+        Ok(false)
+    }
+
+    /// Produces a spooled message, in its own transaction if the sink produces transactionally.
+    ///
+    /// Without transactions, the message is spooled again with an additional attempt if the
+    /// producer fails to deliver it later.
+    async fn send_spooled(&self, spooled: &SpooledMessage) -> Result<(), ClientError> {
+        if !self.client.is_transactional() {
+            let delivery = Delivery {
+                route: spooled.route,
+                topic_name: spooled.topic_name.clone(),
+                received_at: spooled.received_at,
+                attempts: spooled.attempts + 1,
+                spills: true,
+            };
+            return self
+                .client
+                .send_encoded_tracked(&spooled.message, Arc::new(delivery));
+        }
+
+        self.client.begin_transaction()?;
+        match self.client.send_encoded(&spooled.message) {
+            Ok(()) => end_transaction(&self.client, true).await,
+            Err(error) => {
+                end_transaction(&self.client, false).await.ok();
+                Err(error)
+            }
+        }
+    }
+
+    /// Returns `true` if all producers can reach their Kafka brokers.
+    pub fn is_connected(&self) -> bool {
+        self.client.is_connected(KAFKA_HEALTH_TIMEOUT)
+    }
+}
+
+/// Commits the current transaction of the client if `commit` is `true`, and aborts it otherwise.
+///
+/// Ending a transaction blocks until the brokers respond, so this runs on a blocking thread.
+async fn end_transaction(client: &KafkaClient, commit: bool) -> Result<(), ClientError> {
+    if !client.is_transactional() {
+        return Ok(());
+    }
+
+    let transaction = client.transaction();
+    let result = tokio::task::spawn_blocking(move || match commit {
+        true => transaction.commit(),
+        false => transaction.abort(),
+    })
+    .await;
+
+    result.unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic()))
+}
+
+/// Writes messages that failed to send to the spool.
+///
+/// Messages that are waiting are written in batches of up to `spool.kafka.batch_size`.
+async fn write_spool(
+    spool: KafkaSpool,
+    mut rx: mpsc::Receiver<SpooledMessage>,
+    config: Arc<Config>,
+    outcomes: Addr<OutcomeProducer>,
+) {
+    let batch_size = config.spool_kafka_batch_size() as usize;
+    let mut batch = Vec::with_capacity(batch_size);
+
+    while let Some(spooled) = rx.recv().await {
+        batch.push(spooled);
+        while batch.len() < batch_size {
+            match rx.try_recv() {
+                Ok(spooled) => batch.push(spooled),
+                Err(_) => break,
+            }
+        }
+
+        let reason = match spool.push(&batch).await {
+            Ok(()) => {
+                batch.clear();
+                continue;
+            }
+            Err(BufferError::SpoolIsFull) => "spool_full",
+            Err(error) => {
+                relay_log::error!(
+                    error = &error as &dyn Error,
+                    "failed to write to kafka spool"
+                );
+                "invalid"
+            }
+        };
+
+        for spooled in batch.drain(..) {
+            drop_message(&config, &outcomes, &spooled, reason);
+        }
+    }
+}
+
+/// Handles a message that the producer failed to deliver after accepting it.
+///
+/// This is called from the polling thread of the producer. Messages that are too large are sent to
+/// the [`KafkaSink`] to be produced to the dead-letter topic, if enabled. If the message spills,
+/// it is written to the spool. Messages that failed with an error that is not retryable, that have
+/// exhausted `spool.kafka.max_retries`, or that exceed `spool.kafka.max_queue_size` are dropped
+/// with an outcome.
+fn handle_delivery_failure(
+    config: &Config,
+    outcomes: &Addr<OutcomeProducer>,
+    spill_tx: &mpsc::Sender<SpooledMessage>,
+    dead_letter_tx: &mpsc::Sender<DeadLetterMessage>,
+    failure: DeliveryFailure,
+) {
+    let Ok(delivery) = failure.tag.downcast::<Delivery>() else {
+        return;
+    };
+    let delivery = Delivery::clone(&delivery);
+
+    if failure.error.is_too_large() && config.kafka_dead_letters_enabled() {
+        let message = &failure.message;
+        let dead_letter = DeadLetterMessage::from_encoded(
+            &delivery.route,
+            message,
+            &failure.error,
+            config.kafka_dead_letter_max_payload_size(),
+        );
+
+        if dead_letter_tx.try_send(dead_letter).is_err() {
+            relay_log::error!(
+                tags.variant = message.variant,
+                "dropped kafka dead letter, too many pending dead letters"
+            );
+        }
+    }
+
+    if !delivery.spills {
+        return;
+    }
+
+    let spooled = delivery.into_spooled(failure.message);
+
+    let (spooled, reason) = if !failure.error.is_retryable() {
+        (spooled, "invalid")
+    } else if spooled.attempts >= config.spool_kafka_max_retries() {
+        (spooled, "retries_exhausted")
+    } else {
+        match spill_tx.try_send(spooled) {
+            Ok(()) => return,
+            Err(mpsc::error::TrySendError::Full(spooled)) => (spooled, "queue_full"),
+            // The spool writer runs as long as the sink, so this only fails during shutdown.
+            Err(mpsc::error::TrySendError::Closed(spooled)) => (spooled, "shutdown"),
+        }
+    };
+
+    drop_message(config, outcomes, &spooled, reason);
+}
+
+/// A message that could not be produced to its topic.
+///
+/// Contains the metadata of the original message, the error, and its serialized payload truncated
+/// to `processing.dead_letters.max_payload_size`.
+#[derive(Debug, Serialize)]
+struct DeadLetterMessage {
+    #[serde(skip)]
+    key: [u8; 16],
+    #[serde(skip)]
+    headers: Option<BTreeMap<String, String>>,
+    /// The topic the message was produced to.
+    topic: KafkaTopic,
+    /// The type of the original message.
+    variant: String,
+    organization_id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project_id: Option<ProjectId>,
+    /// The data category of the original message.
+    category: &'static str,
+    /// The error and all of its sources.
+    error: String,
+    /// UNIX timestamp in seconds when the message failed.
+    timestamp: i64,
+    /// The size of the original payload in bytes.
+    payload_size: usize,
+    /// The base64-encoded original payload, possibly truncated.
+    payload: String,
+    /// Whether the payload was truncated.
+    truncated: bool,
+}
+
+impl DeadLetterMessage {
+    fn new(
+        route: &SinkRoute,
+        message: &impl Message,
+        payload: &[u8],
+        error: &ClientError,
+        max_payload_size: usize,
+    ) -> Self {
+        Self::with_metadata(
+            route,
+            message.key(),
+            message.headers().cloned(),
+            message.variant().to_owned(),
+            payload,
+            error,
+            max_payload_size,
+        )
+    }
+
+    /// Creates a dead letter from an encoded message reported by the delivery callback.
+    ///
+    /// Messages without a partitioning key are assigned a random key.
+    fn from_encoded(
+        route: &SinkRoute,
+        message: &EncodedMessage,
+        error: &ClientError,
+        max_payload_size: usize,
+    ) -> Self {
+        Self::with_metadata(
+            route,
+            message.key.unwrap_or_else(|| Uuid::new_v4().into_bytes()),
+            message.headers.clone(),
+            message.variant.clone(),
+            &message.payload,
+            error,
+            max_payload_size,
+        )
+    }
+
+    fn with_metadata(
+        route: &SinkRoute,
+        key: [u8; 16],
+        headers: Option<BTreeMap<String, String>>,
+        variant: String,
+        payload: &[u8],
+        error: &ClientError,
+        max_payload_size: usize,
+    ) -> Self {
+        let truncated = payload.len() > max_payload_size;
+        let kept = &payload[..payload.len().min(max_payload_size)];
+
+        Self {
+            key,
+            headers,
+            topic: route.topic,
+            variant,
+            organization_id: route.organization_id,
+            project_id: route.project_id,
+            category: route.category.name(),
+            error: error_chain(error),
+            timestamp: Utc::now().timestamp(),
+            payload_size: payload.len(),
+            payload: BASE64.encode(kept),
+            truncated,
+        }
+    }
+}
+
+impl Message for DeadLetterMessage {
+    fn key(&self) -> [u8; 16] {
+        self.key
+    }
+
+    fn variant(&self) -> &'static str {
+        "dead_letter"
+    }
+
+    fn headers(&self) -> Option<&BTreeMap<String, String>> {
+        self.headers.as_ref()
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, ClientError> {
+        serde_json::to_vec(self).map_err(ClientError::InvalidJson)
+    }
+
+    fn avro_value(&self) -> Result<AvroValue, ClientError> {
+        to_avro_value(self)
+    }
+}
+
+/// Formats an error with all of its sources, separated by colons.
+fn error_chain(error: &dyn Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        chain.push_str(": ");
+        chain.push_str(&error.to_string());
+        source = error.source();
+    }
+    chain
+}
+
+/// Emits an outcome for a message that is dropped from the spool.
+fn drop_message(
+    config: &Config,
+    outcomes: &Addr<OutcomeProducer>,
+    spooled: &SpooledMessage,
+    reason: &str,
+) {
+    metric!(
+        counter(RelayCounters::KafkaSpoolDropped) += 1,
+        reason = reason
+    );
+
+    // Outcomes require a project, which buckets merged across projects do not have.
+    let Some(project_id) = spooled.route.project_id else {
+        return;
+    };
+
+    outcomes.send(TrackRawOutcome::from_parts(
+        spooled.received_at,
+        spooled.route.organization_id,
+        project_id,
+        &Outcome::Invalid(DiscardReason::Internal),
+        spooled.route.category,
+        outcome_quantity(spooled),
+        config,
+    ));
+}
+
+/// Returns the quantity of the outcome for a dropped message.
+///
+/// Attachments are counted in bytes, all other data categories count one item per message.
+fn outcome_quantity(spooled: &SpooledMessage) -> u32 {
+    match spooled.route.category {
+        DataCategory::Attachment => spooled.message.payload.len() as u32,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rdkafka::error::KafkaError;
+    use rdkafka::types::RDKafkaErrorCode;
+
+    use super::*;
+    use crate::testutils::message;
+
+    #[test]
+    fn test_dead_letter_message() {
+        let route = SinkRoute {
+            topic: KafkaTopic::Events,
+            organization_id: 42,
+            project_id: Some(ProjectId::new(21)),
+            category: DataCategory::Error,
+            kafka_route: None,
+        };
+
+        let error = ClientError::InvalidJson(serde_json::from_str::<u32>("x").unwrap_err());
+        let dead_letter =
+            DeadLetterMessage::new(&route, &message(b"hello world"), b"hello world", &error, 5);
+
+        assert_eq!(dead_letter.key(), [1; 16]);
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&Message::serialize(&dead_letter).unwrap()).unwrap();
+
+        assert_eq!(json["topic"], "events");
+        assert_eq!(json["variant"], "test");
+        assert_eq!(json["organization_id"], 42);
+        assert_eq!(json["project_id"], 21);
+        assert_eq!(json["category"], "error");
+        assert_eq!(
+            json["error"],
+            "failed to serialize json message: expected value at line 1 column 1"
+        );
+        assert_eq!(json["payload_size"], 11);
+        assert_eq!(json["payload"], BASE64.encode(b"hello"));
+        assert_eq!(json["truncated"], true);
+        assert!(json.get("key").is_none());
+    }
+
+    fn delivery(spills: bool) -> Delivery {
+        Delivery {
+            route: SinkRoute {
+                topic: KafkaTopic::Events,
+                organization_id: 42,
+                project_id: Some(ProjectId::new(21)),
+                category: DataCategory::Error,
+                kafka_route: None,
+            },
+            topic_name: "ingest-events".to_owned(),
+            received_at: Utc::now(),
+            attempts: 0,
+            spills,
+        }
+    }
+
+    fn failure(error: ClientError, delivery: Delivery) -> DeliveryFailure {
+        DeliveryFailure {
+            message: EncodedMessage {
+                topic: KafkaTopic::Events,
+                route: None,
+                organization_id: 42,
+                key: None,
+                headers: None,
+                variant: "test".to_owned(),
+                payload: b"hello world".to_vec(),
+            },
+            error,
+            tag: Arc::new(delivery),
+        }
+    }
+
+    fn too_large() -> ClientError {
+        ClientError::SendFailed(KafkaError::MessageProduction(
+            RDKafkaErrorCode::MessageSizeTooLarge,
+        ))
+    }
+
+    #[test]
+    fn test_delivery_failure_not_retryable() {
+        let config = Config::default();
+        let (outcomes, mut outcomes_rx) = Addr::custom();
+        let (spill_tx, mut spill_rx) = mpsc::channel(1);
+        let (dead_letter_tx, mut dead_letter_rx) = mpsc::channel(1);
+
+        let failure = failure(ClientError::InvalidShard, delivery(true));
+        handle_delivery_failure(&config, &outcomes, &spill_tx, &dead_letter_tx, failure);
+
+        // The message is dropped with an outcome instead of being spooled.
+        assert!(spill_rx.try_recv().is_err());
+        assert!(dead_letter_rx.try_recv().is_err());
+        assert!(outcomes_rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_delivery_failure_too_large() {
+        let config = Config::from_json_value(serde_json::json!({
+            "processing": {
+                "enabled": true,
+                "kafka_config": [],
+                "dead_letters": {
+                    "enabled": true,
+                    "max_payload_size": 5,
+                },
+            }
+        }))
+        .unwrap();
+        let (outcomes, mut outcomes_rx) = Addr::custom();
+        let (spill_tx, mut spill_rx) = mpsc::channel(1);
+        let (dead_letter_tx, mut dead_letter_rx) = mpsc::channel(1);
+
+        let failure = failure(too_large(), delivery(false));
+        handle_delivery_failure(&config, &outcomes, &spill_tx, &dead_letter_tx, failure);
+
+        let dead_letter = dead_letter_rx.try_recv().unwrap();
+        assert_eq!(dead_letter.topic, KafkaTopic::Events);
+        assert_eq!(dead_letter.variant, "test");
+        assert_eq!(dead_letter.organization_id, 42);
+        assert_eq!(dead_letter.payload_size, 11);
+        assert_eq!(dead_letter.payload, BASE64.encode(b"hello"));
+        assert!(dead_letter.truncated);
+
+        // Without the spool, the failure is only logged as before.
+        assert!(spill_rx.try_recv().is_err());
+        assert!(outcomes_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_delivery_failure_too_large_disabled() {
+        let config = Config::default();
+        let (outcomes, _outcomes_rx) = Addr::custom();
+        let (spill_tx, _spill_rx) = mpsc::channel(1);
+        let (dead_letter_tx, mut dead_letter_rx) = mpsc::channel(1);
+
+        let failure = failure(too_large(), delivery(false));
+        handle_delivery_failure(&config, &outcomes, &spill_tx, &dead_letter_tx, failure);
+
+        assert!(dead_letter_rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! Destinations of messages produced by the [`StoreService`](super::store::StoreService).
+//!
+//! By default, Relay produces processed envelopes and metrics to Kafka. Alternatively, messages
+//! can be published to other message brokers, configured with `processing.sink`. All sinks receive
+//! the same [`Message`]s, along with a [`SinkRoute`] to route them.
+
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use relay_base_schema::project::ProjectId;
+use relay_config::{Config, StoreSinkConfig};
+use relay_kafka::{
+    ClientError, DeliveryFailureHandler, KafkaClient, KafkaTopic, Message, RegisteredSchema,
+};
+use relay_quotas::DataCategory;
+use relay_system::Addr;
+use serde::Deserialize;
+
+use crate::actors::outcome::OutcomeProducer;
+
+mod aws;
+mod kafka;
+mod nats;
+mod pubsub;
+mod retry;
+
+pub use self::aws::AwsSink;
+pub use self::kafka::KafkaSink;
+pub use self::nats::NatsSink;
+pub use self::pubsub::PubSubSink;
+
+use self::retry::SinkOutcomes;
+
+/// Errors when sending messages to a [`StoreSink`].
+#[derive(Debug, thiserror::Error)]
+pub enum SinkError {
+    /// Serializing or producing the message failed.
+    #[error("failed to produce the message")]
+    Client(#[from] ClientError),
+    /// There is no topic, stream, or queue for the data category of the message.
+    #[error("no destination configured for data category {0}")]
+    NoTopic(DataCategory),
+    /// The message exceeds the size limit of its destination.
+    #[error("message of {size} bytes exceeds the size limit of {limit} bytes")]
+    TooLarge { size: usize, limit: usize },
+    /// The background task of the sink has stopped.
+    #[error("the sink is closed")]
+    Closed,
+    /// Too many messages are waiting to be sent, see `max_queue_size`.
+    #[error("the sink queue is full")]
+    QueueFull,
+}
+
+/// Routing information of a message sent to a [`StoreSink`].
+#[derive(Clone, Copy, Debug)]
+pub struct SinkRoute {
+    /// The Kafka topic of the message.
+    pub topic: KafkaTopic,
+    /// The organization ID of the message.
+    pub organization_id: u64,
+    /// The project ID of the message.
+    ///
+    /// `None` for metric buckets that have been merged across the projects of an organization.
+    pub project_id: Option<ProjectId>,
+    /// The data category of the message.
+    pub category: DataCategory,
+    /// The index of the matching topic route, see `processing.topic_routes`.
+    ///
+    /// Only applies to Kafka. If `None`, the message is sent to the configured topic.
+    pub kafka_route: Option<usize>,
+}
+
+/// The latest version of a subject in the schema registry.
+#[derive(Debug, Deserialize)]
+struct SubjectVersion {
+    /// The globally unique identifier of the schema.
+    id: u32,
+    /// The schema definition.
+    schema: String,
+}
+
+/// A destination of messages produced by the store.
+#[derive(Debug)]
+pub enum StoreSink {
+    /// Produces messages to Kafka.
+    Kafka(KafkaSink),
+    /// Publishes messages to Google Cloud Pub/Sub.
+    PubSub(PubSubSink),
+    /// Sends messages to AWS Kinesis data streams and SQS queues.
+    Aws(AwsSink),
+    /// Publishes messages to NATS JetStream.
+    Nats(NatsSink),
+}
+
+impl StoreSink {
+    /// Creates the sink configured in `processing.sink`.
+    ///
+    /// Outcomes for messages dropped from the Kafka spool or after failed retries are sent to
+    /// `outcomes`.
+    pub fn create(config: &Arc<Config>, outcomes: Addr<OutcomeProducer>) -> anyhow::Result<Self> {
+        Ok(match config.store_sink() {
+            StoreSinkConfig::Kafka => Self::Kafka(KafkaSink::create(config.clone(), outcomes)?),
+            StoreSinkConfig::PubSub(pubsub) => {
+                let client = crate::http::client_builder(config)?.build()?;
+                let outcomes = SinkOutcomes::new(config.clone(), outcomes);
+                Self::PubSub(PubSubSink::create(pubsub, client, outcomes))
+            }
+            StoreSinkConfig::Aws(aws) => {
+                let client = crate::http::client_builder(config)?.build()?;
+                let outcomes = SinkOutcomes::new(config.clone(), outcomes);
+                Self::Aws(AwsSink::create(aws, client, outcomes)?)
+            }
+            StoreSinkConfig::Nats(nats) => {
+                let outcomes = SinkOutcomes::new(config.clone(), outcomes);
+                Self::Nats(NatsSink::create(nats, outcomes))
+            }
+        })
+    }
+
+    /// Loads the latest schemas of all Kafka topics from the schema registry.
+    ///
+    /// This is a no-op for other sinks and if no schema registry is configured. On error, the
+    /// previously loaded schemas remain in use.
+    pub async fn load_schemas(&mut self, config: &Config) -> anyhow::Result<()> {
+        match self {
+            Self::Kafka(sink) => match config.kafka_schema_registry() {
+                Some(registry) => register_schemas(sink.client_mut(), registry).await,
+                None => Ok(()),
+            },
+            Self::PubSub(_) | Self::Aws(_) | Self::Nats(_) => Ok(()),
+        }
+    }
+
+    /// Prepares the sink for sending messages.
+    ///
+    /// This must be called within the Tokio runtime of the store.
+    pub async fn start(&mut self) {
+        match self {
+            Self::Kafka(sink) => sink.start().await,
+            Self::PubSub(sink) => sink.start(),
+            Self::Aws(sink) => sink.start(),
+            Self::Nats(sink) => {
+                if let Err(error) = sink.start().await {
+                    relay_log::error!(
+                        error = error.as_ref() as &dyn Error,
+                        "failed to connect to nats",
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    /// Sends a message to the destination of its route.
+    pub fn send(&self, route: SinkRoute, message: &impl Message) -> Result<(), SinkError> {
+        match self {
+            Self::Kafka(sink) => sink.send(route, message)?,
+            Self::PubSub(sink) => sink.send(route, message)?,
+            Self::Aws(sink) => sink.send(route, message)?,
+            Self::Nats(sink) => sink.send(route, message)?,
+        }
+
+        Ok(())
+    }
+
+    /// Begins a transaction for the messages of one envelope.
+    ///
+    /// This is a no-op unless the sink is Kafka with transactional delivery. Every transaction must
+    /// be ended with [`end_transaction`](Self::end_transaction).
+    pub fn begin_transaction(&self) -> Result<(), SinkError> {
+        match self {
+            Self::Kafka(sink) => sink.begin_transaction(),
+            Self::PubSub(_) | Self::Aws(_) | Self::Nats(_) => Ok(()),
+        }
+    }
+
+    /// Commits the current transaction if `commit` is `true`, and aborts it otherwise.
+    pub async fn end_transaction(&self, commit: bool) -> Result<(), SinkError> {
+        match self {
+            Self::Kafka(sink) => sink.end_transaction(commit).await,
+            Self::PubSub(_) | Self::Aws(_) | Self::Nats(_) => Ok(()),
+        }
+    }
+
+    /// Produces the next batch of messages from the Kafka spool again.
+    ///
+    /// Returns `true` if more messages can be replayed right away. This is a no-op for other sinks
+    /// and if the spool is not configured.
+    pub async fn replay(&mut self) -> bool {
+        match self {
+            Self::Kafka(sink) => sink.replay().await,
+            Self::PubSub(_) | Self::Aws(_) | Self::Nats(_) => false,
+        }
+    }
+
+    /// Returns `true` if the sink can reach its message broker.
+    pub fn is_connected(&self) -> bool {
+        match self {
+            Self::Kafka(sink) => sink.is_connected(),
+            Self::PubSub(sink) => sink.is_connected(),
+            Self::Aws(sink) => sink.is_connected(),
+            Self::Nats(sink) => sink.is_connected(),
+        }
+    }
+}
+
+/// Creates a Kafka client with producers for all topics of the store.
+///
+/// Messages that the producers fail to deliver are passed to `failures`.
+fn create_kafka_client(
+    config: &Config,
+    failures: DeliveryFailureHandler,
+) -> anyhow::Result<KafkaClient> {
+    let mut client_builder = KafkaClient::builder()
+        .delivery_failures(failures)
+        .oauth(config.kafka_oauth().cloned())
+        .delivery(
+            config.kafka_delivery_mode(),
+            config.kafka_transactional_id().map(str::to_owned),
+        );
+
+    for topic in KafkaTopic::iter()
+        .filter(|t| **t != KafkaTopic::Outcomes || **t != KafkaTopic::OutcomesBilling)
+    {
+        let kafka_config = &config.kafka_config(*topic)?;
+        client_builder = client_builder
+            .add_kafka_topic_config(*topic, kafka_config)?
+            .partition_strategy(*topic, config.kafka_partition_strategy(*topic));
+    }
+
+    // Routes are added in order, so their indexes per topic match `processing.topic_routes`.
+    for route in config.topic_routes() {
+        let kafka_config = &config.kafka_route_config(route)?;
+        client_builder = client_builder.add_kafka_route(route.topic, kafka_config)?;
+    }
+
+    Ok(client_builder.build())
+}
+
+/// Fetches the latest schemas of all topics configured in the schema registry and registers them
+/// with the Kafka client.
+async fn register_schemas(
+    client: &mut KafkaClient,
+    registry: &relay_kafka::SchemaRegistryConfig,
+) -> anyhow::Result<()> {
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(registry.timeout))
+        .connect_timeout(Duration::from_secs(registry.connect_timeout))
+        .build()?;
+    let base_url = registry.url.trim_end_matches('/');
+
+    for (topic_name, topic) in &registry.topics {
+        let subject = topic.subject(topic_name)?;
+        let subject = utf8_percent_encode(&subject, NON_ALPHANUMERIC);
+        let url = format!("{base_url}/subjects/{subject}/versions/latest");
+
+        let mut request = http.get(url);
+        if let Some(ref username) = registry.username {
+            request = request.basic_auth(username, registry.password.as_ref());
+        }
+
+        let response = request.send().await?.error_for_status()?.bytes().await?;
+        let version: SubjectVersion = serde_json::from_slice(&response)?;
+
+        let schema = RegisteredSchema {
+            id: version.id,
+            format: topic.format,
+            schema: version.schema,
+        };
+        client.register_schema(topic_name, &schema)?;
+        relay_log::info!(
+            tags.topic = topic_name,
+            schema_id = version.id,
+            "registered kafka schema"
+        );
+    }
+
+    Ok(())
+}
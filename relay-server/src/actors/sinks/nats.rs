@@ -0,0 +1,460 @@
+//! Publishing of messages to NATS JetStream.
+//!
+//! Messages are sent to a background task, which publishes them and waits for the JetStream
+//! acknowledgements concurrently. Messages that are not acknowledged are published again with
+//! exponential backoff, up to `max_retries` times, after which they are dropped with an outcome.
+//! The sink accepts at most `max_queue_size` messages that have not been published yet and
+//! rejects new messages once this limit is reached.
+//!
+//! Reconnects to the server use the same backoff as retries.
+
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use async_nats::jetstream;
+use bytes::Bytes;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use relay_config::NatsSinkConfig;
+use relay_kafka::Message;
+use relay_statsd::metric;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::actors::sinks::retry::{self, MessageOrigin, RetryQueue, SinkOutcomes};
+use crate::actors::sinks::{SinkError, SinkRoute};
+use crate::statsd::RelayCounters;
+use crate::utils::RetryBackoff;
+
+/// A message waiting to be published.
+#[derive(Debug)]
+struct NatsMessage {
+    subject: String,
+    headers: async_nats::HeaderMap,
+    payload: Bytes,
+    origin: MessageOrigin,
+}
+
+/// The result of waiting for the acknowledgement of a published message.
+type PendingAck =
+    Pin<Box<dyn Future<Output = (NatsMessage, RetryBackoff, anyhow::Result<()>)> + Send>>;
+
+/// Renders a subject template for the given route.
+fn render_subject(template: &str, route: &SinkRoute) -> String {
+    template
+        .replace("{category}", route.category.name())
+        .replace("{org_id}", &route.organization_id.to_string())
+        .replace(
+            "{project_id}",
+            &route
+                .project_id
+                .map_or_else(|| "none".to_owned(), |id| id.to_string()),
+        )
+}
+
+/// Publishes messages to NATS JetStream.
+///
+/// The connection is established in [`start`](Self::start), which must be called within a Tokio
+/// runtime before sending messages.
+#[derive(Debug)]
+pub struct NatsSink {
+    config: Arc<NatsSinkConfig>,
+    tx: mpsc::Sender<NatsMessage>,
+    rx: Option<mpsc::Receiver<NatsMessage>>,
+    outcomes: SinkOutcomes,
+    client: Option<async_nats::Client>,
+}
+
+impl NatsSink {
+    /// Creates a new sink without connecting to the server.
+    ///
+    /// Outcomes for messages that are dropped after failed retries are emitted to `outcomes`.
+    pub fn create(config: &NatsSinkConfig, outcomes: SinkOutcomes) -> Self {
+        let (tx, rx) = mpsc::channel(config.max_queue_size.max(1));
+
+        Self {
+            config: Arc::new(config.clone()),
+            tx,
+            rx: Some(rx),
+            outcomes,
+            client: None,
+        }
+    }
+
+    /// Connects to the NATS server and spawns the background task that publishes messages.
+    ///
+    /// If the server is not reachable, the client keeps connecting in the background.
+    pub async fn start(&mut self) -> anyhow::Result<()> {
+        let Some(rx) = self.rx.take() else {
+            return Ok(());
+        };
+
+        let backoff = Mutex::new(retry::backoff());
+        let mut options = async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .reconnect_delay_callback(move |attempts| {
+                let mut backoff = backoff.lock().unwrap_or_else(PoisonError::into_inner);
+                // The client counts attempts since the last connection, starting at `1`.
+                if attempts <= 1 {
+                    backoff.reset();
+                }
+                backoff.next_backoff()
+            });
+
+        if let Some(ref path) = self.config.credentials_file {
+            options = options.credentials_file(path).await?;
+        }
+
+        let client = options.connect(self.config.url.as_str()).await?;
+        let publisher = Publisher {
+            retries: RetryQueue::new(self.config.max_retries),
+            config: self.config.clone(),
+            context: jetstream::new(client.clone()),
+            rx,
+            pending: FuturesUnordered::new(),
+            outcomes: self.outcomes.clone(),
+            shutdown: false,
+        };
+
+        self.client = Some(client);
+        tokio::spawn(publisher.run());
+        Ok(())
+    }
+
+    /// Queues a message for publishing to the subject of its data category.
+    ///
+    /// Fails with [`SinkError::QueueFull`] if `max_queue_size` messages are waiting to be
+    /// published.
+    pub fn send(&self, route: SinkRoute, message: &impl Message) -> Result<(), SinkError> {
+        let template = self
+            .config
+            .subject(route.category)
+            .ok_or(SinkError::NoTopic(route.category))?;
+
+        let mut headers = async_nats::HeaderMap::new();
+        for (key, value) in message.headers().into_iter().flatten() {
+            headers.insert(key.as_str(), value.as_str());
+        }
+
+        let payload = message.serialize()?;
+        let message = NatsMessage {
+            subject: render_subject(template, &route),
+            headers,
+            origin: MessageOrigin::new(route, payload.len()),
+            payload: payload.into(),
+        };
+
+        self.tx.try_send(message).map_err(|error| match error {
+            mpsc::error::TrySendError::Full(_) => SinkError::QueueFull,
+            mpsc::error::TrySendError::Closed(_) => SinkError::Closed,
+        })
+    }
+
+    /// Returns `true` if the client is connected to the NATS server.
+    pub fn is_connected(&self) -> bool {
+        self.client.as_ref().map_or(false, |client| {
+            client.connection_state() == async_nats::connection::State::Connected
+        })
+    }
+}
+
+/// The background task of [`NatsSink`].
+struct Publisher {
+    config: Arc<NatsSinkConfig>,
+    context: jetstream::Context,
+    rx: mpsc::Receiver<NatsMessage>,
+    pending: FuturesUnordered<PendingAck>,
+    retries: RetryQueue<(), NatsMessage>,
+    outcomes: SinkOutcomes,
+    /// Drops failed messages instead of retrying them while shutting down.
+    shutdown: bool,
+}
+
+impl Publisher {
+    async fn run(mut self) {
+        loop {
+            let next_retry = self.retries.next_due();
+            // Failed messages count towards the queue size, so that the channel fills up and new
+            // messages are rejected while the server is unavailable.
+            let accepts_messages = self.pending.len() < self.config.max_in_flight.max(1)
+                && self.retries.messages() < self.config.max_queue_size;
+
+            tokio::select! {
+                biased;
+
+                Some((message, backoff, result)) = self.pending.next() => {
+                    self.acknowledged(message, backoff, result)
+                },
+                _ = tokio::time::sleep_until(next_retry.unwrap_or_else(Instant::now)), if next_retry.is_some() => {
+                    self.retry_due().await
+                },
+                message = self.rx.recv(), if accepts_messages => match message {
+                    Some(message) => self.publish(message, retry::backoff()).await,
+                    None => break,
+                },
+            }
+        }
+
+        // Publish failed messages once more and wait for the acknowledgements of all published
+        // messages before shutting down. Messages that fail now are dropped.
+        self.shutdown = true;
+        for ((), message, backoff) in self.retries.take_all() {
+            self.publish(message, backoff).await;
+        }
+        while let Some((message, backoff, result)) = self.pending.next().await {
+            self.acknowledged(message, backoff, result);
+        }
+    }
+
+    /// Publishes all failed messages that are due for their next attempt.
+    async fn retry_due(&mut self) {
+        for ((), message, backoff) in self.retries.take_due() {
+            self.publish(message, backoff).await;
+        }
+    }
+
+    /// Publishes a message and queues the wait for its acknowledgement.
+    async fn publish(&mut self, message: NatsMessage, backoff: RetryBackoff) {
+        let result = self
+            .context
+            .publish_with_headers(
+                message.subject.clone(),
+                message.headers.clone(),
+                message.payload.clone(),
+            )
+            .await;
+
+        let ack: PendingAck = match result {
+            Ok(ack) => Box::pin(async move {
+                let result = ack.await.map(drop).map_err(anyhow::Error::from);
+                (message, backoff, result)
+            }),
+            Err(error) => Box::pin(async move { (message, backoff, Err(error.into())) }),
+        };
+
+        self.pending.push(ack);
+    }
+
+    /// Handles the acknowledgement of a message, scheduling it for a retry on failure.
+    ///
+    /// Once the message has exhausted its retries, it is dropped with an outcome.
+    fn acknowledged(
+        &mut self,
+        message: NatsMessage,
+        backoff: RetryBackoff,
+        result: anyhow::Result<()>,
+    ) {
+        let error = match result {
+            Ok(()) => {
+                metric!(
+                    counter(RelayCounters::SinkMessages) += 1,
+                    sink = "nats",
+                    result = "published"
+                );
+                return;
+            }
+            Err(error) => error,
+        };
+
+        if self.shutdown {
+            relay_log::error!(
+                error = error.as_ref() as &dyn Error,
+                tags.subject = message.subject,
+                "failed to publish message to nats during shutdown"
+            );
+            self.outcomes.dropped("nats", [&message.origin]);
+            return;
+        }
+
+        let retries = backoff.attempt();
+        match self.retries.schedule((), message, 1, backoff) {
+            Ok(()) => metric!(
+                counter(RelayCounters::SinkMessages) += 1,
+                sink = "nats",
+                result = "retried"
+            ),
+            Err(message) => {
+                relay_log::error!(
+                    error = error.as_ref() as &dyn Error,
+                    tags.subject = message.subject,
+                    retries,
+                    "failed to publish message to nats"
+                );
+                self.outcomes.dropped("nats", [&message.origin]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_base_schema::project::ProjectId;
+    use relay_config::Config;
+    use relay_kafka::KafkaTopic;
+    use relay_quotas::DataCategory;
+    use relay_system::Addr;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::actors::outcome::OutcomeProducer;
+    use crate::testutils::message;
+
+    const SERVER_INFO: &[u8] =
+        b"INFO {\"server_id\":\"test\",\"headers\":true,\"max_payload\":1048576}\r\n";
+    const ACK: &str = r#"{"stream":"test","seq":1}"#;
+    const NACK: &str = r#"{"error":{"code":503,"err_code":10077,"description":"unavailable"}}"#;
+
+    fn route() -> SinkRoute {
+        SinkRoute {
+            topic: KafkaTopic::Transactions,
+            organization_id: 42,
+            project_id: Some(ProjectId::new(21)),
+            category: DataCategory::Transaction,
+            kafka_route: None,
+        }
+    }
+
+    fn sink(url: &str, max_retries: u32) -> (NatsSink, mpsc::UnboundedReceiver<OutcomeProducer>) {
+        let config: NatsSinkConfig = serde_json::from_value(serde_json::json!({
+            "url": url,
+            "default_subject": "ingest.{category}",
+            "max_queue_size": 1,
+            "max_retries": max_retries,
+        }))
+        .unwrap();
+
+        let (outcomes, outcomes_rx) = Addr::custom();
+        let outcomes = SinkOutcomes::new(Arc::new(Config::default()), outcomes);
+        (NatsSink::create(&config, outcomes), outcomes_rx)
+    }
+
+    /// Starts a NATS server that acknowledges JetStream publishes if `acknowledge` returns `true`.
+    ///
+    /// `acknowledge` receives the number of the publish. Returns the URL of the server and the
+    /// subjects of all received publishes.
+    async fn server<F>(acknowledge: F) -> (String, mpsc::UnboundedReceiver<String>)
+    where
+        F: Fn(usize) -> bool + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("nats://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+
+            writer.write_all(SERVER_INFO).await.unwrap();
+
+            let mut sid = String::new();
+            let mut line = String::new();
+            let mut publishes = 0;
+
+            while reader.read_line(&mut line).await.unwrap_or(0) > 0 {
+                let owned = std::mem::take(&mut line);
+                let args: Vec<&str> = owned.split_whitespace().collect();
+
+                let (subject, reply, size) = match args[..] {
+                    ["PING"] => {
+                        writer.write_all(b"PONG\r\n").await.unwrap();
+                        continue;
+                    }
+                    ["SUB", _, id] => {
+                        sid = id.to_owned();
+                        continue;
+                    }
+                    ["PUB", subject, reply, size] | ["HPUB", subject, reply, _, size] => {
+                        (subject, reply, size)
+                    }
+                    _ => continue,
+                };
+
+                // Skip the payload and its trailing line break.
+                let mut payload = vec![0; size.parse::<usize>().unwrap() + 2];
+                reader.read_exact(&mut payload).await.unwrap();
+
+                let response = if acknowledge(publishes) { ACK } else { NACK };
+                publishes += 1;
+
+                let len = response.len();
+                let msg = format!("MSG {reply} {sid} {len}\r\n{response}\r\n");
+                writer.write_all(msg.as_bytes()).await.unwrap();
+                tx.send(subject.to_owned()).ok();
+            }
+        });
+
+        (url, rx)
+    }
+
+    #[test]
+    fn test_render_subject() {
+        assert_eq!(
+            render_subject("ingest.{category}.{org_id}.{project_id}", &route()),
+            "ingest.transaction.42.21"
+        );
+        assert_eq!(render_subject("ingest.all", &route()), "ingest.all");
+    }
+
+    #[test]
+    fn test_queue_full() {
+        let (sink, _outcomes) = sink("nats://127.0.0.1:0", 0);
+
+        // The publisher is not started, so messages remain in the queue.
+        sink.send(route(), &message(b"hello")).unwrap();
+        assert!(matches!(
+            sink.send(route(), &message(b"hello")),
+            Err(SinkError::QueueFull)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let (url, mut subjects) = server(|_| true).await;
+        let (mut sink, mut outcomes) = sink(&url, 0);
+
+        sink.start().await.unwrap();
+        sink.send(route(), &message(b"hello")).unwrap();
+        assert_eq!(subjects.recv().await.unwrap(), "ingest.transaction");
+
+        drop(sink);
+        assert!(outcomes.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_publish_retry() {
+        let (url, mut subjects) = server(|publish| publish > 0).await;
+        let (mut sink, mut outcomes) = sink(&url, 1);
+
+        sink.start().await.unwrap();
+        sink.send(route(), &message(b"hello")).unwrap();
+
+        // The first attempt is rejected and the retry is acknowledged.
+        assert_eq!(subjects.recv().await.unwrap(), "ingest.transaction");
+        assert_eq!(subjects.recv().await.unwrap(), "ingest.transaction");
+
+        drop(sink);
+        assert!(outcomes.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_publish_failed() {
+        let (url, _subjects) = server(|_| false).await;
+        let (mut sink, mut outcomes) = sink(&url, 1);
+
+        sink.start().await.unwrap();
+        sink.send(route(), &message(b"hello")).unwrap();
+
+        let Some(OutcomeProducer::TrackRawOutcome(outcome)) = outcomes.recv().await else {
+            panic!("expected an outcome");
+        };
+        assert_eq!(outcome.category, DataCategory::Transaction.value());
+        assert_eq!(outcome.quantity, Some(1));
+
+        drop(sink);
+        assert!(outcomes.recv().await.is_none());
+    }
+}
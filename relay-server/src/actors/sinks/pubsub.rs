@@ -0,0 +1,690 @@
+//! Publishing of messages to Google Cloud Pub/Sub.
+//!
+//! Messages are sent to a background task, which collects them into batches per topic and
+//! publishes them through the Pub/Sub REST API. A batch is published once it reaches the
+//! configured number of messages or size, or after `max_batch_delay` at the latest.
+//!
+//! Batches that fail to publish are published again with exponential backoff, up to
+//! `max_retries` times, after which their messages are dropped with an outcome. If Pub/Sub rejects
+//! a batch with a client error, its messages are published individually without retries, so that
+//! only the rejected messages are dropped. The sink accepts at
+//! most `max_queue_size` messages that have not been published yet and rejects new messages once
+//! this limit is reached.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use data_encoding::{BASE64, HEXLOWER};
+use relay_config::PubSubConfig;
+use relay_kafka::Message;
+use relay_statsd::metric;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::time::Instant as TokioInstant;
+
+use crate::actors::sinks::retry::{self, MessageOrigin, RetryQueue, SinkOutcomes};
+use crate::actors::sinks::{SinkError, SinkRoute};
+use crate::statsd::RelayCounters;
+use crate::utils::RetryBackoff;
+
+/// The default base URL of the Pub/Sub API.
+const DEFAULT_ENDPOINT: &str = "https://pubsub.googleapis.com";
+
+/// The GCE metadata server endpoint for access tokens of the default service account.
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// Access tokens are refreshed this long before they expire.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// The maximum size of a publish request accepted by Pub/Sub.
+const MAX_REQUEST_SIZE: usize = 10_000_000;
+
+/// Estimated size of the JSON structure around every message in a publish request.
+const MESSAGE_OVERHEAD: usize = 64;
+
+/// An error returned when publishing a batch to Pub/Sub.
+#[derive(Debug, thiserror::Error)]
+enum PublishError {
+    #[error("could not serialize the publish request")]
+    Serialize(#[source] serde_json::Error),
+    #[error("could not send request to pubsub")]
+    SendFailed(#[from] reqwest::Error),
+    #[error("received an invalid access token")]
+    InvalidToken(#[source] serde_json::Error),
+    #[error("pubsub responded with status {0}")]
+    ResponseError(reqwest::StatusCode),
+}
+
+impl PublishError {
+    /// Returns `true` if publishing the batch again may succeed.
+    ///
+    /// Client errors other than `429 Too Many Requests` indicate that Pub/Sub does not accept the
+    /// messages, so there is no point in retrying them.
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Serialize(_) => false,
+            Self::SendFailed(_) | Self::InvalidToken(_) => true,
+            Self::ResponseError(status) => {
+                !status.is_client_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+}
+
+/// A message in a Pub/Sub publish request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PubSubMessage {
+    /// The base64-encoded payload.
+    data: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    attributes: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ordering_key: Option<String>,
+}
+
+impl PubSubMessage {
+    /// Returns the estimated size of this message in a publish request.
+    fn size(&self) -> usize {
+        let attributes: usize = self
+            .attributes
+            .iter()
+            // Every attribute is enclosed in quotes and separated by a colon and a comma.
+            .map(|(key, value)| key.len() + value.len() + 6)
+            .sum();
+        let ordering_key = self.ordering_key.as_ref().map_or(0, String::len);
+
+        self.data.len() + attributes + ordering_key + MESSAGE_OVERHEAD
+    }
+}
+
+/// Body of a Pub/Sub publish request.
+#[derive(Debug, Serialize)]
+struct PublishRequest<'a> {
+    messages: &'a [PubSubMessage],
+}
+
+/// Response of the GCE metadata server for access tokens.
+#[derive(Debug, Deserialize)]
+struct AccessTokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// A cached access token.
+#[derive(Debug)]
+struct AccessToken {
+    token: String,
+    expires: Instant,
+}
+
+/// A message queued for publishing to a topic.
+type QueuedMessage = (String, PubSubMessage, MessageOrigin);
+
+/// Messages collected for a single topic.
+#[derive(Debug, Default)]
+struct Batch {
+    messages: Vec<PubSubMessage>,
+    origins: Vec<MessageOrigin>,
+    size: usize,
+}
+
+impl Batch {
+    /// Splits the batch into batches with a single message each.
+    fn split(self) -> impl Iterator<Item = Batch> {
+        self.messages
+            .into_iter()
+            .zip(self.origins)
+            .map(|(message, origin)| Batch {
+                size: message.size(),
+                messages: vec![message],
+                origins: vec![origin],
+            })
+    }
+}
+
+/// Publishes messages to Google Cloud Pub/Sub.
+///
+/// Messages are published asynchronously by a background task. Call [`start`](Self::start) within
+/// a Tokio runtime before sending messages.
+#[derive(Debug)]
+pub struct PubSubSink {
+    config: Arc<PubSubConfig>,
+    tx: mpsc::Sender<QueuedMessage>,
+    publisher: Option<Publisher>,
+    healthy: Arc<AtomicBool>,
+}
+
+impl PubSubSink {
+    /// Creates a new sink without starting the background task.
+    ///
+    /// Outcomes for messages that are dropped after failed retries are emitted to `outcomes`.
+    pub fn create(config: &PubSubConfig, client: reqwest::Client, outcomes: SinkOutcomes) -> Self {
+        let config = Arc::new(config.clone());
+        let healthy = Arc::new(AtomicBool::new(true));
+        let (tx, rx) = mpsc::channel(config.max_queue_size.max(1));
+
+        let publisher = Publisher {
+            retries: RetryQueue::new(config.max_retries),
+            config: config.clone(),
+            client,
+            rx,
+            batches: BTreeMap::new(),
+            token: None,
+            healthy: healthy.clone(),
+            outcomes,
+            shutdown: false,
+        };
+
+        Self {
+            config,
+            tx,
+            publisher: Some(publisher),
+            healthy,
+        }
+    }
+
+    /// Spawns the background task that publishes messages.
+    pub fn start(&mut self) {
+        if let Some(publisher) = self.publisher.take() {
+            tokio::spawn(publisher.run());
+        }
+    }
+
+    /// Queues a message for publishing to the topic of its data category.
+    ///
+    /// Fails with [`SinkError::QueueFull`] if `max_queue_size` messages are waiting to be
+    /// published, and with [`SinkError::TooLarge`] if the message exceeds the request size limit
+    /// of Pub/Sub.
+    pub fn send(&self, route: SinkRoute, message: &impl Message) -> Result<(), SinkError> {
+        let category = route.category;
+        let topic = self
+            .config
+            .topic(category)
+            .ok_or(SinkError::NoTopic(category))?;
+
+        let ordering_key = if self.config.ordering_keys {
+            Some(HEXLOWER.encode(&message.key()))
+        } else {
+            None
+        };
+
+        let data = message.serialize()?;
+        let origin = MessageOrigin::new(route, data.len());
+        let message = PubSubMessage {
+            data: BASE64.encode(&data),
+            attributes: message.headers().cloned().unwrap_or_default(),
+            ordering_key,
+        };
+
+        let size = message.size();
+        if size > MAX_REQUEST_SIZE {
+            return Err(SinkError::TooLarge {
+                size,
+                limit: MAX_REQUEST_SIZE,
+            });
+        }
+
+        self.tx
+            .try_send((topic.to_owned(), message, origin))
+            .map_err(|error| match error {
+                mpsc::error::TrySendError::Full(_) => SinkError::QueueFull,
+                mpsc::error::TrySendError::Closed(_) => SinkError::Closed,
+            })
+    }
+
+    /// Returns `true` if no batches are waiting to be published again after a failure.
+    pub fn is_connected(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+}
+
+/// The background task of [`PubSubSink`].
+#[derive(Debug)]
+struct Publisher {
+    config: Arc<PubSubConfig>,
+    client: reqwest::Client,
+    rx: mpsc::Receiver<QueuedMessage>,
+    batches: BTreeMap<String, Batch>,
+    retries: RetryQueue<String, Batch>,
+    token: Option<AccessToken>,
+    healthy: Arc<AtomicBool>,
+    outcomes: SinkOutcomes,
+    /// Drops failed batches instead of retrying them while shutting down.
+    shutdown: bool,
+}
+
+impl Publisher {
+    async fn run(mut self) {
+        let mut ticker = tokio::time::interval(Duration::from_millis(self.config.max_batch_delay));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            let next_retry = self.retries.next_due();
+            // Failed batches count towards the queue size, so that the channel fills up and new
+            // messages are rejected while Pub/Sub is unavailable.
+            let accepts_messages = self.retries.messages() < self.config.max_queue_size;
+
+            tokio::select! {
+                biased;
+
+                _ = tokio::time::sleep_until(next_retry.unwrap_or_else(TokioInstant::now)), if next_retry.is_some() => {
+                    self.retry_due().await
+                },
+                message = self.rx.recv(), if accepts_messages => match message {
+                    Some((topic, message, origin)) => self.push(topic, message, origin).await,
+                    None => break,
+                },
+                _ = ticker.tick() => self.flush_all().await,
+            }
+        }
+
+        // Publish all remaining messages once more and drop them if that fails.
+        self.shutdown = true;
+        self.flush_all().await;
+        for (topic, batch, backoff) in self.retries.take_all() {
+            self.publish(topic, batch, backoff).await;
+        }
+    }
+
+    /// Adds a message to the batch of its topic and publishes the batch once it is full.
+    ///
+    /// If the message does not fit into the request size limit of Pub/Sub, the pending batch is
+    /// published first.
+    async fn push(&mut self, topic: String, message: PubSubMessage, origin: MessageOrigin) {
+        let size = message.size();
+        let batch = self.batches.entry(topic.clone()).or_default();
+        if !batch.messages.is_empty() && batch.size + size > MAX_REQUEST_SIZE {
+            let full = std::mem::take(batch);
+            self.publish(topic.clone(), full, retry::backoff()).await;
+        }
+
+        let batch = self.batches.entry(topic.clone()).or_default();
+        batch.size += size;
+        batch.messages.push(message);
+        batch.origins.push(origin);
+
+        let max_batch_size = self.config.max_batch_size.as_bytes().min(MAX_REQUEST_SIZE);
+        if batch.messages.len() >= self.config.max_batch_messages || batch.size >= max_batch_size {
+            let batch = std::mem::take(batch);
+            self.publish(topic, batch, retry::backoff()).await;
+        }
+    }
+
+    /// Publishes all pending batches.
+    async fn flush_all(&mut self) {
+        for (topic, batch) in std::mem::take(&mut self.batches) {
+            if !batch.messages.is_empty() {
+                self.publish(topic, batch, retry::backoff()).await;
+            }
+        }
+    }
+
+    /// Publishes all failed batches that are due for their next attempt.
+    async fn retry_due(&mut self) {
+        for (topic, batch, backoff) in self.retries.take_due() {
+            self.publish(topic, batch, backoff).await;
+        }
+    }
+
+    /// Publishes a batch and schedules it for a retry if that fails.
+    ///
+    /// Once the batch has exhausted its retries, its messages are dropped with an outcome. If
+    /// Pub/Sub rejects the batch, its messages are published individually and only the rejected
+    /// messages are dropped.
+    async fn publish(&mut self, topic: String, batch: Batch, backoff: RetryBackoff) {
+        let mut pending = vec![(batch, backoff)];
+
+        while let Some((batch, backoff)) = pending.pop() {
+            let count = batch.messages.len();
+
+            match self.try_publish(&topic, &batch.messages).await {
+                Ok(()) => {
+                    metric!(
+                        counter(RelayCounters::SinkMessages) += count as i64,
+                        sink = "pubsub",
+                        result = "published"
+                    );
+                }
+                Err(error) if !error.is_retryable() && count > 1 => {
+                    pending.extend(batch.split().map(|batch| (batch, retry::backoff())));
+                }
+                Err(error) if !error.is_retryable() => {
+                    relay_log::error!(
+                        error = &error as &dyn Error,
+                        tags.topic = topic,
+                        "pubsub rejected a message"
+                    );
+                    self.outcomes.dropped("pubsub", &batch.origins);
+                }
+                Err(error) if self.shutdown => {
+                    relay_log::error!(
+                        error = &error as &dyn Error,
+                        tags.topic = topic,
+                        "failed to publish messages to pubsub during shutdown"
+                    );
+                    self.outcomes.dropped("pubsub", &batch.origins);
+                }
+                Err(error) => {
+                    let retries = backoff.attempt();
+                    match self.retries.schedule(topic.clone(), batch, count, backoff) {
+                        Ok(()) => metric!(
+                            counter(RelayCounters::SinkMessages) += count as i64,
+                            sink = "pubsub",
+                            result = "retried"
+                        ),
+                        Err(batch) => {
+                            relay_log::error!(
+                                error = &error as &dyn Error,
+                                tags.topic = topic,
+                                retries,
+                                "failed to publish messages to pubsub"
+                            );
+                            self.outcomes.dropped("pubsub", &batch.origins);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.healthy
+            .store(self.retries.is_empty(), Ordering::Relaxed);
+    }
+
+    async fn try_publish(
+        &mut self,
+        topic: &str,
+        messages: &[PubSubMessage],
+    ) -> Result<(), PublishError> {
+        let endpoint = self.config.endpoint.as_deref().unwrap_or(DEFAULT_ENDPOINT);
+        let url = format!(
+            "{}/v1/projects/{}/topics/{topic}:publish",
+            endpoint.trim_end_matches('/'),
+            self.config.project,
+        );
+
+        let body =
+            serde_json::to_vec(&PublishRequest { messages }).map_err(PublishError::Serialize)?;
+        let mut request = self
+            .client
+            .post(url)
+            .header("content-type", "application/json")
+            .body(body);
+
+        // Custom endpoints, such as the Pub/Sub emulator, do not require authentication.
+        if self.config.endpoint.is_none() {
+            request = request.bearer_auth(self.access_token().await?);
+        }
+
+        let status = request.send().await?.status();
+        if !status.is_success() {
+            return Err(PublishError::ResponseError(status));
+        }
+
+        Ok(())
+    }
+
+    /// Returns a valid access token, fetching a new one from the metadata server if needed.
+    async fn access_token(&mut self) -> Result<String, PublishError> {
+        if let Some(ref token) = self.token {
+            if token.expires > Instant::now() {
+                return Ok(token.token.clone());
+            }
+        }
+
+        let response = self
+            .client
+            .get(METADATA_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let response: AccessTokenResponse =
+            serde_json::from_slice(&response).map_err(PublishError::InvalidToken)?;
+
+        let lifetime = Duration::from_secs(response.expires_in).saturating_sub(TOKEN_EXPIRY_MARGIN);
+        self.token = Some(AccessToken {
+            token: response.access_token.clone(),
+            expires: Instant::now() + lifetime,
+        });
+
+        Ok(response.access_token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use axum::http::StatusCode;
+    use relay_base_schema::project::ProjectId;
+    use relay_config::{ByteSize, Config};
+    use relay_kafka::KafkaTopic;
+    use relay_quotas::DataCategory;
+    use relay_system::Addr;
+
+    use super::*;
+    use crate::actors::outcome::OutcomeProducer;
+    use crate::testutils::message;
+
+    fn route() -> SinkRoute {
+        SinkRoute {
+            topic: KafkaTopic::Attachments,
+            organization_id: 42,
+            project_id: Some(ProjectId::new(21)),
+            category: DataCategory::Attachment,
+            kafka_route: None,
+        }
+    }
+
+    fn config(endpoint: &str, max_queue_size: usize) -> PubSubConfig {
+        serde_json::from_value(serde_json::json!({
+            "project": "project",
+            "default_topic": "topic",
+            "endpoint": endpoint,
+            "max_queue_size": max_queue_size,
+            "max_retries": 1,
+        }))
+        .unwrap()
+    }
+
+    /// Starts a Pub/Sub emulator that responds with the status returned by `respond`.
+    ///
+    /// `respond` receives the number of the request and its body. Returns the endpoint of the
+    /// emulator and the number of received requests.
+    fn emulator_with<F>(respond: F) -> (String, Arc<AtomicUsize>)
+    where
+        F: Fn(usize, &[u8]) -> StatusCode + Clone + Send + Sync + 'static,
+    {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+
+        let router = axum::Router::new()
+            .fallback(move |body: axum::body::Bytes| {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst);
+                let status = respond(attempt, &body);
+                async move { status }
+            })
+            .layer(axum::extract::DefaultBodyLimit::disable());
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router.into_make_service());
+        tokio::spawn(server);
+
+        (endpoint, requests)
+    }
+
+    /// Starts a Pub/Sub emulator that fails the first `failures` publish requests.
+    fn emulator(failures: usize) -> (String, Arc<AtomicUsize>) {
+        emulator_with(move |attempt, _| {
+            if attempt < failures {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                StatusCode::OK
+            }
+        })
+    }
+
+    fn sink(config: &PubSubConfig) -> (PubSubSink, mpsc::UnboundedReceiver<OutcomeProducer>) {
+        let (outcomes, outcomes_rx) = Addr::custom();
+        let outcomes = SinkOutcomes::new(Arc::new(Config::default()), outcomes);
+        let sink = PubSubSink::create(config, reqwest::Client::new(), outcomes);
+        (sink, outcomes_rx)
+    }
+
+    #[test]
+    fn test_queue_full() {
+        let (sink, _outcomes) = sink(&config("http://127.0.0.1:0", 1));
+
+        // The publisher is not started, so messages remain in the queue.
+        sink.send(route(), &message(b"hello world")).unwrap();
+        assert!(matches!(
+            sink.send(route(), &message(b"hello world")),
+            Err(SinkError::QueueFull)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_publish_retry() {
+        let (endpoint, requests) = emulator(1);
+        let (mut sink, mut outcomes) = sink(&config(&endpoint, 10));
+        sink.start();
+
+        sink.send(route(), &message(b"hello world")).unwrap();
+        while requests.load(Ordering::SeqCst) < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Dropping the sink stops the publisher once all messages are published.
+        drop(sink);
+        assert!(outcomes.recv().await.is_none());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_publish_dropped() {
+        let (endpoint, requests) = emulator(usize::MAX);
+        let (mut sink, mut outcomes) = sink(&config(&endpoint, 10));
+        sink.start();
+
+        sink.send(route(), &message(b"hello world")).unwrap();
+        let Some(OutcomeProducer::TrackRawOutcome(outcome)) = outcomes.recv().await else {
+            panic!("expected a raw outcome");
+        };
+
+        assert_eq!(outcome.category, DataCategory::Attachment.value());
+        assert_eq!(outcome.quantity, Some(11));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_publish_rejected() {
+        let (endpoint, requests) = emulator_with(|_, _| StatusCode::BAD_REQUEST);
+        let (mut sink, mut outcomes) = sink(&config(&endpoint, 10));
+        sink.start();
+
+        sink.send(route(), &message(b"hello world")).unwrap();
+        let Some(OutcomeProducer::TrackRawOutcome(outcome)) = outcomes.recv().await else {
+            panic!("expected a raw outcome");
+        };
+
+        // Client errors are not retried.
+        assert_eq!(outcome.quantity, Some(11));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_publish_rejected_split() {
+        let invalid = BASE64.encode(b"invalid");
+        let (endpoint, requests) = emulator_with(move |_, body| {
+            if String::from_utf8_lossy(body).contains(&invalid) {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::OK
+            }
+        });
+
+        let mut config = config(&endpoint, 10);
+        config.max_batch_messages = 2;
+        config.max_batch_delay = 60_000;
+        let (mut sink, mut outcomes) = sink(&config);
+        sink.start();
+
+        sink.send(route(), &message(b"valid")).unwrap();
+        sink.send(route(), &message(b"invalid")).unwrap();
+        let Some(OutcomeProducer::TrackRawOutcome(outcome)) = outcomes.recv().await else {
+            panic!("expected a raw outcome");
+        };
+
+        // Only the invalid message is dropped after publishing both messages individually.
+        assert_eq!(outcome.quantity, Some(7));
+        drop(sink);
+        assert!(outcomes.recv().await.is_none());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_publish_request_size_limit() {
+        let (endpoint, requests) = emulator_with(|_, body| {
+            if body.len() > MAX_REQUEST_SIZE {
+                StatusCode::PAYLOAD_TOO_LARGE
+            } else {
+                StatusCode::OK
+            }
+        });
+
+        let mut config = config(&endpoint, 10);
+        config.max_batch_size = ByteSize::mebibytes(100);
+        config.max_batch_delay = 60_000;
+        let (mut sink, mut outcomes) = sink(&config);
+        sink.start();
+
+        // Each message takes up more than half of the request size limit after encoding.
+        let data = vec![0; 4_000_000];
+        sink.send(route(), &message(&data)).unwrap();
+        sink.send(route(), &message(&data)).unwrap();
+        assert!(matches!(
+            sink.send(route(), &message(&vec![0; MAX_REQUEST_SIZE])),
+            Err(SinkError::TooLarge { .. })
+        ));
+
+        drop(sink);
+        assert!(outcomes.recv().await.is_none());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_serialize_publish_request() {
+        let messages = [
+            PubSubMessage {
+                data: BASE64.encode(b"hello"),
+                attributes: BTreeMap::from([("org_id".to_owned(), "1".to_owned())]),
+                ordering_key: Some("key".to_owned()),
+            },
+            PubSubMessage {
+                data: BASE64.encode(b"world"),
+                attributes: BTreeMap::new(),
+                ordering_key: None,
+            },
+        ];
+
+        let json = serde_json::to_string(&PublishRequest {
+            messages: &messages,
+        })
+        .unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"messages":[{"data":"aGVsbG8=","attributes":{"org_id":"1"},"orderingKey":"key"},{"data":"d29ybGQ="}]}"#
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Retrying of failed batches in sinks that send messages over HTTP.
+//!
+//! Batches that fail to send are scheduled again with exponential backoff. Once a batch has failed
+//! `max_retries` times, its messages are dropped with an outcome.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use relay_config::Config;
+use relay_quotas::DataCategory;
+use relay_statsd::metric;
+use relay_system::Addr;
+use tokio::time::Instant;
+
+use crate::actors::outcome::{DiscardReason, Outcome, OutcomeProducer, TrackRawOutcome};
+use crate::actors::sinks::SinkRoute;
+use crate::statsd::RelayCounters;
+use crate::utils::RetryBackoff;
+
+/// The maximum delay between two attempts to send a batch.
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The origin of a message accepted by a sink, used to emit an outcome if it is dropped.
+#[derive(Clone, Copy, Debug)]
+pub struct MessageOrigin {
+    route: SinkRoute,
+    received_at: DateTime<Utc>,
+    quantity: u32,
+}
+
+impl MessageOrigin {
+    /// Creates the origin of a message with the given route and payload size.
+    ///
+    /// Attachments are counted in bytes, all other data categories count one item per message.
+    pub fn new(route: SinkRoute, size: usize) -> Self {
+        let quantity = match route.category {
+            DataCategory::Attachment => size as u32,
+            _ => 1,
+        };
+
+        Self {
+            route,
+            received_at: Utc::now(),
+            quantity,
+        }
+    }
+}
+
+/// Emits outcomes for messages that a sink drops after it has accepted them.
+#[derive(Clone, Debug)]
+pub struct SinkOutcomes {
+    config: Arc<Config>,
+    outcomes: Addr<OutcomeProducer>,
+}
+
+impl SinkOutcomes {
+    /// Creates a new instance that sends outcomes to the given producer.
+    pub fn new(config: Arc<Config>, outcomes: Addr<OutcomeProducer>) -> Self {
+        Self { config, outcomes }
+    }
+
+    /// Emits an outcome for every dropped message and counts them as failed.
+    pub fn dropped<'a>(
+        &self,
+        sink: &'static str,
+        origins: impl IntoIterator<Item = &'a MessageOrigin>,
+    ) {
+        let mut count = 0;
+
+        for origin in origins {
+            count += 1;
+
+            // Outcomes require a project, which buckets merged across projects do not have.
+            let Some(project_id) = origin.route.project_id else {
+                continue;
+            };
+
+            self.outcomes.send(TrackRawOutcome::from_parts(
+                origin.received_at,
+                origin.route.organization_id,
+                project_id,
+                &Outcome::Invalid(DiscardReason::Internal),
+                origin.route.category,
+                origin.quantity,
+                &self.config,
+            ));
+        }
+
+        metric!(
+            counter(RelayCounters::SinkMessages) += count,
+            sink = sink,
+            result = "failed"
+        );
+    }
+}
+
+/// Returns the backoff for a batch that is sent for the first time.
+pub fn backoff() -> RetryBackoff {
+    RetryBackoff::new(MAX_RETRY_INTERVAL)
+}
+
+/// A batch that failed to send and waits for its next attempt.
+#[derive(Debug)]
+struct Retry<D, B> {
+    destination: D,
+    batch: B,
+    messages: usize,
+    backoff: RetryBackoff,
+    due: Instant,
+}
+
+/// Failed batches waiting to be sent again.
+#[derive(Debug)]
+pub struct RetryQueue<D, B> {
+    entries: Vec<Retry<D, B>>,
+    max_retries: u32,
+    messages: usize,
+}
+
+impl<D, B> RetryQueue<D, B> {
+    /// Creates an empty queue that retries every batch up to `max_retries` times.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            entries: Vec::new(),
+            max_retries,
+            messages: 0,
+        }
+    }
+
+    /// Schedules a failed batch with the given number of messages to be sent again.
+    ///
+    /// Returns the batch back if it has exhausted its retries.
+    pub fn schedule(
+        &mut self,
+        destination: D,
+        batch: B,
+        messages: usize,
+        mut backoff: RetryBackoff,
+    ) -> Result<(), B> {
+        if backoff.attempt() >= self.max_retries as usize {
+            return Err(batch);
+        }
+
+        let due = Instant::now() + backoff.next_backoff();
+        self.messages += messages;
+        self.entries.push(Retry {
+            destination,
+            batch,
+            messages,
+            backoff,
+            due,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the time at which the next batch is due, if any.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.entries.iter().map(|retry| retry.due).min()
+    }
+
+    /// Removes and returns all batches that are due.
+    pub fn take_due(&mut self) -> Vec<(D, B, RetryBackoff)> {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|retry| retry.due <= now);
+
+        self.entries = pending;
+        self.take(due)
+    }
+
+    /// Removes and returns all batches, regardless of when they are due.
+    pub fn take_all(&mut self) -> Vec<(D, B, RetryBackoff)> {
+        let all = std::mem::take(&mut self.entries);
+        self.take(all)
+    }
+
+    /// Returns `true` if no batches are waiting to be sent again.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total number of messages in all batches waiting to be sent again.
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    fn take(&mut self, entries: Vec<Retry<D, B>>) -> Vec<(D, B, RetryBackoff)> {
+        entries
+            .into_iter()
+            .map(|retry| {
+                self.messages -= retry.messages;
+                (retry.destination, retry.batch, retry.backoff)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retry_queue() {
+        tokio::time::pause();
+
+        let mut queue = RetryQueue::new(2);
+        assert_eq!(queue.next_due(), None);
+
+        // The first retry is due immediately.
+        let start = Instant::now();
+        queue.schedule("a", "batch", 3, backoff()).unwrap();
+        assert_eq!(queue.messages(), 3);
+        assert_eq!(queue.next_due(), Some(start));
+
+        let (destination, batch, backoff) = queue.take_due().pop().unwrap();
+        assert_eq!((destination, batch), ("a", "batch"));
+        assert_eq!(queue.messages(), 0);
+
+        // The second retry waits for the initial interval.
+        queue.schedule("a", "batch", 3, backoff).unwrap();
+        assert_eq!(queue.next_due(), Some(start + Duration::from_secs(1)));
+        assert!(queue.take_due().is_empty());
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let (_, batch, backoff) = queue.take_due().pop().unwrap();
+
+        // The batch has exhausted its retries.
+        assert_eq!(queue.schedule("a", batch, 3, backoff), Err("batch"));
+        assert!(queue.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retry_queue_take_all() {
+        tokio::time::pause();
+
+        let mut queue = RetryQueue::new(5);
+        let mut backoff = backoff();
+        backoff.next_backoff();
+
+        queue.schedule("a", 1, 1, backoff()).unwrap();
+        queue.schedule("b", 2, 2, backoff).unwrap();
+        assert_eq!(queue.take_due().len(), 1);
+        assert_eq!(queue.messages(), 2);
+
+        let all = queue.take_all();
+        assert_eq!(all.len(), 1);
+        assert_eq!((all[0].0, all[0].1), ("b", 2));
+        assert_eq!(queue.messages(), 0);
+    }
+}
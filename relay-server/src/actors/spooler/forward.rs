@@ -0,0 +1,339 @@
+//! Durable store-and-forward queue for envelopes that could not be sent to the upstream.
+//!
+//! The [`ForwardSpool`] is enabled with `spool.forward.path`. While the upstream is unreachable,
+//! the [`EnvelopeManager`](crate::actors::envelopes::EnvelopeManager) writes outgoing envelopes to
+//! this spool instead of holding them in memory. Once connectivity returns, the backlog is replayed
+//! in the order it was written at the configured drain rate.
+
+use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use relay_base_schema::project::{ProjectId, ProjectKey};
+use relay_config::Config;
+use relay_quotas::Scoping;
+use sqlx::sqlite::{
+    SqliteAutoVacuum, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow,
+    SqliteSynchronous,
+};
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::actors::spooler::BufferError;
+use crate::envelope::Envelope;
+use crate::statsd::{RelayCounters, RelayHistograms};
+
+/// An envelope read back from the [`ForwardSpool`].
+#[derive(Debug)]
+pub struct SpooledEnvelope {
+    /// The row id, which must be passed to [`ForwardSpool::remove`] once the envelope is sent.
+    pub id: i64,
+    /// The spooled envelope.
+    pub envelope: Box<Envelope>,
+    /// Scoping of the envelope at the time it was spooled.
+    pub scoping: Scoping,
+}
+
+/// Persistent FIFO queue of outgoing envelopes backed by SQLite.
+///
+/// This instance holds a connection pool internally and can be cloned cheaply.
+#[derive(Clone, Debug)]
+pub struct ForwardSpool {
+    db: Pool<Sqlite>,
+    max_disk_size: Option<usize>,
+    backlog: Arc<AtomicBool>,
+}
+
+impl ForwardSpool {
+    /// Opens the spool file configured in `spool.forward.path`.
+    ///
+    /// Returns `Ok(None)` if store-and-forward mode is not configured.
+    pub async fn open(config: &Config) -> Result<Option<Self>, BufferError> {
+        let Some(path) = config.spool_forward_path() else {
+            return Ok(None);
+        };
+
+        relay_log::info!("forward spool file {}", path.to_string_lossy());
+        Self::setup(&path).await?;
+
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .journal_mode(SqliteJournalMode::Wal)
+            // Envelopes in this spool have already been accepted, so they must survive a crash.
+            .synchronous(SqliteSynchronous::Full)
+            .auto_vacuum(SqliteAutoVacuum::Full);
+
+        let db = SqlitePoolOptions::new()
+            .max_connections(config.spool_envelopes_max_connections())
+            .min_connections(1)
+            .connect_with(options)
+            .await
+            .map_err(BufferError::SetupFailed)?;
+
+        let spool = Self {
+            db,
+            max_disk_size: config.spool_forward_max_disk_size(),
+            backlog: Arc::new(AtomicBool::new(false)),
+        };
+
+        let backlog = !spool.is_empty().await?;
+        spool.backlog.store(backlog, Ordering::Relaxed);
+        if backlog {
+            relay_log::info!("found spooled envelopes, replaying backlog");
+        }
+
+        Ok(Some(spool))
+    }
+
+    /// Creates the spool file and runs migrations.
+    async fn setup(path: &Path) -> Result<(), BufferError> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .journal_mode(SqliteJournalMode::Wal)
+            .create_if_missing(true);
+
+        let db = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .map_err(BufferError::SetupFailed)?;
+
+        sqlx::migrate!("../migrations").run(&db).await?;
+        Ok(())
+    }
+
+    /// Returns `true` if there are envelopes waiting to be replayed.
+    ///
+    /// New envelopes must be appended to the spool while there is a backlog to retain their order.
+    pub fn has_backlog(&self) -> bool {
+        self.backlog.load(Ordering::Relaxed)
+    }
+
+    /// Appends a serialized envelope to the end of the spool.
+    ///
+    /// Returns [`BufferError::SpoolIsFull`] if the configured maximum disk size is exceeded.
+    pub async fn push(
+        &self,
+        envelope: Vec<u8>,
+        scoping: Scoping,
+        received_at: i64,
+    ) -> Result<(), BufferError> {
+        if let Some(max_disk_size) = self.max_disk_size {
+            if self.estimate_size().await? >= max_disk_size {
+                return Err(BufferError::SpoolIsFull);
+            }
+        }
+
+        sqlx::query(
+            "INSERT INTO forward_envelopes
+                (received_at, organization_id, project_id, project_key, key_id, envelope)
+             VALUES (?, ?, ?, ?, ?, ?);",
+        )
+        .bind(received_at)
+        .bind(scoping.organization_id as i64)
+        .bind(scoping.project_id.value() as i64)
+        .bind(scoping.project_key.to_string())
+        .bind(scoping.key_id.map(|id| id as i64))
+        .bind(envelope)
+        .execute(&self.db)
+        .await
+        .map_err(BufferError::InsertFailed)?;
+
+        self.backlog.store(true, Ordering::Relaxed);
+        relay_statsd::metric!(counter(RelayCounters::ForwardSpoolWritten) += 1);
+        Ok(())
+    }
+
+    /// Returns the oldest envelope in the spool without removing it.
+    ///
+    /// Rows that cannot be parsed are logged and removed. This also updates the backlog flag.
+    pub async fn peek(&self) -> Result<Option<SpooledEnvelope>, BufferError> {
+        loop {
+            let row = sqlx::query(
+                "SELECT id, organization_id, project_id, project_key, key_id, envelope
+                 FROM forward_envelopes ORDER BY id LIMIT 1;",
+            )
+            .fetch_optional(&self.db)
+            .await
+            .map_err(BufferError::FetchFailed)?;
+
+            let Some(row) = row else {
+                self.backlog.store(false, Ordering::Relaxed);
+                return Ok(None);
+            };
+
+            self.backlog.store(true, Ordering::Relaxed);
+            let id: i64 = row.try_get("id").map_err(BufferError::FetchFailed)?;
+
+            match Self::extract_envelope(id, &row) {
+                Ok(Some(spooled)) => return Ok(Some(spooled)),
+                Ok(None) => relay_log::error!("invalid scoping of spooled envelope"),
+                Err(err) => relay_log::error!(
+                    error = &err as &dyn Error,
+                    "failed to read spooled envelope"
+                ),
+            }
+
+            self.remove(id).await?;
+        }
+    }
+
+    /// Parses an envelope and its scoping from a database row.
+    ///
+    /// Returns `Ok(None)` if the scoping stored with the envelope is invalid.
+    fn extract_envelope(id: i64, row: &SqliteRow) -> Result<Option<SpooledEnvelope>, BufferError> {
+        let envelope: Vec<u8> = row.try_get("envelope").map_err(BufferError::FetchFailed)?;
+        let envelope = Envelope::parse_bytes(envelope.into())?;
+
+        let organization_id: i64 = row
+            .try_get("organization_id")
+            .map_err(BufferError::FetchFailed)?;
+        let project_id: i64 = row
+            .try_get("project_id")
+            .map_err(BufferError::FetchFailed)?;
+        let project_key: String = row
+            .try_get("project_key")
+            .map_err(BufferError::FetchFailed)?;
+        let key_id: Option<i64> = row.try_get("key_id").map_err(BufferError::FetchFailed)?;
+
+        let Ok(project_key) = ProjectKey::parse(&project_key) else {
+            return Ok(None);
+        };
+
+        let scoping = Scoping {
+            organization_id: organization_id as u64,
+            project_id: ProjectId::new(project_id as u64),
+            project_key,
+            key_id: key_id.map(|id| id as u64),
+        };
+
+        Ok(Some(SpooledEnvelope {
+            id,
+            envelope,
+            scoping,
+        }))
+    }
+
+    /// Removes an envelope from the spool after it has been sent.
+    pub async fn remove(&self, id: i64) -> Result<(), BufferError> {
+        sqlx::query("DELETE FROM forward_envelopes WHERE id = ?;")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(BufferError::DeleteFailed)?;
+
+        relay_statsd::metric!(counter(RelayCounters::ForwardSpoolRead) += 1);
+        Ok(())
+    }
+
+    /// Returns `true` if the spool is empty.
+    async fn is_empty(&self) -> Result<bool, BufferError> {
+        let is_empty = sqlx::query("SELECT id FROM forward_envelopes LIMIT 1;")
+            .fetch_optional(&self.db)
+            .await
+            .map_err(BufferError::FetchFailed)?
+            .is_none();
+
+        Ok(is_empty)
+    }
+
+    /// Returns the allocated size of the spool file in bytes.
+    async fn estimate_size(&self) -> Result<usize, BufferError> {
+        let size: i64 = sqlx::query(
+            "SELECT page_count * page_size as size FROM pragma_page_count(), pragma_page_size();",
+        )
+        .fetch_one(&self.db)
+        .await
+        .and_then(|r| r.try_get(0))
+        .map_err(BufferError::FileSizeReadFailed)?;
+
+        relay_statsd::metric!(histogram(RelayHistograms::ForwardSpoolDiskSize) = size as u64);
+        Ok(size as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use relay_event_schema::protocol::EventId;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn scoping() -> Scoping {
+        Scoping {
+            organization_id: 1,
+            project_id: ProjectId::new(42),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(17),
+        }
+    }
+
+    fn envelope(event_id: &str) -> Vec<u8> {
+        format!(
+            "{{\"event_id\":\"{event_id}\",\"dsn\":\"https://a94ae32be2584e0bbd7a4cbb95971fee:@sentry.io/42\"}}\n"
+        )
+        .into_bytes()
+    }
+
+    async fn spool() -> ForwardSpool {
+        let path = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let config = Config::from_json_value(serde_json::json!({
+            "spool": {
+                "forward": {
+                    "path": path,
+                }
+            }
+        }))
+        .unwrap();
+
+        ForwardSpool::open(&config).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_forward_spool_order() {
+        let spool = spool().await;
+        assert!(!spool.has_backlog());
+        assert!(spool.peek().await.unwrap().is_none());
+
+        let first = "9ec79c33ec9942ab8353589fcb2e04dc";
+        let second = "8ec79c33ec9942ab8353589fcb2e04dc";
+        spool.push(envelope(first), scoping(), 0).await.unwrap();
+        spool.push(envelope(second), scoping(), 0).await.unwrap();
+        assert!(spool.has_backlog());
+
+        // Peeking does not remove the envelope.
+        let spooled = spool.peek().await.unwrap().unwrap();
+        let spooled_again = spool.peek().await.unwrap().unwrap();
+        assert_eq!(spooled.id, spooled_again.id);
+        assert_eq!(spooled.scoping, scoping());
+        assert_eq!(
+            spooled.envelope.event_id(),
+            Some(EventId::from_str(first).unwrap())
+        );
+
+        spool.remove(spooled.id).await.unwrap();
+        let spooled = spool.peek().await.unwrap().unwrap();
+        assert_eq!(
+            spooled.envelope.event_id(),
+            Some(EventId::from_str(second).unwrap())
+        );
+
+        spool.remove(spooled.id).await.unwrap();
+        assert!(spool.peek().await.unwrap().is_none());
+        assert!(!spool.has_backlog());
+    }
+
+    #[tokio::test]
+    async fn test_forward_spool_skips_invalid() {
+        let spool = spool().await;
+        spool.push(b"invalid".to_vec(), scoping(), 0).await.unwrap();
+        spool
+            .push(envelope("9ec79c33ec9942ab8353589fcb2e04dc"), scoping(), 0)
+            .await
+            .unwrap();
+
+        let spooled = spool.peek().await.unwrap().unwrap();
+        assert!(spooled.envelope.event_id().is_some());
+    }
+}
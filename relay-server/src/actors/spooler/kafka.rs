@@ -0,0 +1,449 @@
+//! Durable queue for messages that could not be produced to Kafka.
+//!
+//! The [`KafkaSpool`] is enabled with `spool.kafka.path`. When the producer queue is full or the
+//! brokers are unreachable, the [`KafkaSink`](crate::actors::sinks::KafkaSink) writes encoded
+//! messages to this spool instead of dropping them, and produces them again once Kafka recovers.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use chrono::{DateTime, TimeZone, Utc};
+use relay_base_schema::project::ProjectId;
+use relay_config::Config;
+use relay_kafka::{EncodedMessage, KafkaTopic};
+use relay_quotas::DataCategory;
+use sqlx::sqlite::{
+    SqliteAutoVacuum, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow,
+    SqliteSynchronous,
+};
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::actors::sinks::SinkRoute;
+use crate::actors::spooler::BufferError;
+use crate::statsd::{RelayCounters, RelayHistograms};
+
+/// A message read back from the [`KafkaSpool`].
+#[derive(Debug)]
+pub struct SpooledMessage {
+    /// The row id, which must be passed to [`KafkaSpool::remove`] once the message is produced.
+    pub id: i64,
+    /// The number of failed attempts to produce the message from the spool.
+    pub attempts: u32,
+    /// The name of the Kafka topic the message was produced to.
+    ///
+    /// Route indexes change with the configuration, so the route of a message read from the spool
+    /// is resolved from this name, see [`relay_kafka::KafkaClient::find_route`].
+    pub topic_name: String,
+    /// The time at which the message was first spooled.
+    pub received_at: DateTime<Utc>,
+    /// Routing information of the message.
+    pub route: SinkRoute,
+    /// The encoded message.
+    pub message: EncodedMessage,
+}
+
+/// Persistent FIFO queue of encoded Kafka messages backed by SQLite.
+///
+/// This instance holds a connection pool internally and can be cloned cheaply.
+#[derive(Clone, Debug)]
+pub struct KafkaSpool {
+    db: Pool<Sqlite>,
+    max_disk_size: usize,
+    backlog: Arc<AtomicBool>,
+}
+
+impl KafkaSpool {
+    /// Opens the spool file configured in `spool.kafka.path`.
+    ///
+    /// Returns `Ok(None)` if the Kafka spool is not configured.
+    pub async fn open(config: &Config) -> Result<Option<Self>, BufferError> {
+        let Some(path) = config.spool_kafka_path() else {
+            return Ok(None);
+        };
+
+        relay_log::info!("kafka spool file {}", path.to_string_lossy());
+        Self::setup(&path).await?;
+
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .journal_mode(SqliteJournalMode::Wal)
+            // Messages in this spool have already been accepted, so they must survive a crash.
+            .synchronous(SqliteSynchronous::Full)
+            .auto_vacuum(SqliteAutoVacuum::Full);
+
+        let db = SqlitePoolOptions::new()
+            .max_connections(config.spool_envelopes_max_connections())
+            .min_connections(1)
+            .connect_with(options)
+            .await
+            .map_err(BufferError::SetupFailed)?;
+
+        let spool = Self {
+            db,
+            max_disk_size: config.spool_kafka_max_disk_size(),
+            backlog: Arc::new(AtomicBool::new(false)),
+        };
+
+        let backlog = !spool.is_empty().await?;
+        spool.backlog.store(backlog, Ordering::Relaxed);
+        if backlog {
+            relay_log::info!("found spooled kafka messages, replaying backlog");
+        }
+
+        Ok(Some(spool))
+    }
+
+    /// Creates the spool file and runs migrations.
+    async fn setup(path: &Path) -> Result<(), BufferError> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .journal_mode(SqliteJournalMode::Wal)
+            .create_if_missing(true);
+
+        let db = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .map_err(BufferError::SetupFailed)?;
+
+        sqlx::migrate!("./migrations/kafka").run(&db).await?;
+        Ok(())
+    }
+
+    /// Returns `true` if there are messages waiting to be produced.
+    pub fn has_backlog(&self) -> bool {
+        self.backlog.load(Ordering::Relaxed)
+    }
+
+    /// Appends a batch of messages to the end of the spool.
+    ///
+    /// All messages are written in a single transaction, so that the file is synced once per batch.
+    /// The `id` of the messages is ignored. Returns [`BufferError::SpoolIsFull`] if the configured
+    /// maximum disk size is exceeded.
+    pub async fn push(&self, messages: &[SpooledMessage]) -> Result<(), BufferError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        if self.estimate_size().await? >= self.max_disk_size {
+            return Err(BufferError::SpoolIsFull);
+        }
+
+        let mut transaction = self.db.begin().await.map_err(BufferError::InsertFailed)?;
+
+        for spooled in messages {
+            let message = &spooled.message;
+            let topic = serde_json::to_value(message.topic)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_owned));
+            let headers = match message.headers {
+                Some(ref headers) => serde_json::to_string(headers).ok(),
+                None => None,
+            };
+
+            sqlx::query(
+                "INSERT INTO kafka_messages
+                    (received_at, attempts, topic, topic_name, organization_id, project_id,
+                     category, message_key, headers, variant, payload)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            )
+            .bind(spooled.received_at.timestamp_millis())
+            .bind(spooled.attempts as i64)
+            .bind(topic)
+            .bind(&spooled.topic_name)
+            .bind(message.organization_id as i64)
+            .bind(spooled.route.project_id.map(|id| id.value() as i64))
+            .bind(spooled.route.category.name())
+            .bind(message.key.map(|key| key.to_vec()))
+            .bind(headers)
+            .bind(&message.variant)
+            .bind(&message.payload)
+            .execute(&mut *transaction)
+            .await
+            .map_err(BufferError::InsertFailed)?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(BufferError::InsertFailed)?;
+
+        self.backlog.store(true, Ordering::Relaxed);
+        relay_statsd::metric!(counter(RelayCounters::KafkaSpoolWritten) += messages.len() as i64);
+        Ok(())
+    }
+
+    /// Returns up to `limit` of the oldest messages in the spool without removing them.
+    ///
+    /// Rows that cannot be parsed are logged and removed. This also updates the backlog flag.
+    pub async fn peek(&self, limit: u32) -> Result<Vec<SpooledMessage>, BufferError> {
+        let rows = sqlx::query(
+            "SELECT id, received_at, attempts, topic, topic_name, organization_id, project_id,
+                    category, message_key, headers, variant, payload
+             FROM kafka_messages ORDER BY id LIMIT ?;",
+        )
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .map_err(BufferError::FetchFailed)?;
+
+        self.backlog.store(!rows.is_empty(), Ordering::Relaxed);
+
+        let mut messages = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: i64 = row.try_get("id").map_err(BufferError::FetchFailed)?;
+
+            match Self::extract_message(id, &row) {
+                Ok(Some(spooled)) => messages.push(spooled),
+                Ok(None) => {
+                    relay_log::error!("invalid destination of spooled kafka message");
+                    self.remove(id).await?;
+                }
+                Err(err) => {
+                    relay_log::error!(
+                        error = &err as &dyn Error,
+                        "failed to read spooled kafka message"
+                    );
+                    self.remove(id).await?;
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Parses a message and its route from a database row.
+    ///
+    /// The route index of the message is not stored, see [`SpooledMessage::topic_name`]. Returns
+    /// `Ok(None)` if the topic or key stored with the message is invalid.
+    fn extract_message(id: i64, row: &SqliteRow) -> Result<Option<SpooledMessage>, BufferError> {
+        let received_at: i64 = row
+            .try_get("received_at")
+            .map_err(BufferError::FetchFailed)?;
+        let attempts: i64 = row.try_get("attempts").map_err(BufferError::FetchFailed)?;
+        let topic: String = row.try_get("topic").map_err(BufferError::FetchFailed)?;
+        let topic_name: String = row
+            .try_get("topic_name")
+            .map_err(BufferError::FetchFailed)?;
+        let organization_id: i64 = row
+            .try_get("organization_id")
+            .map_err(BufferError::FetchFailed)?;
+        let project_id: Option<i64> = row
+            .try_get("project_id")
+            .map_err(BufferError::FetchFailed)?;
+        let category: String = row.try_get("category").map_err(BufferError::FetchFailed)?;
+        let key: Option<Vec<u8>> = row
+            .try_get("message_key")
+            .map_err(BufferError::FetchFailed)?;
+        let headers: Option<String> = row.try_get("headers").map_err(BufferError::FetchFailed)?;
+        let variant: String = row.try_get("variant").map_err(BufferError::FetchFailed)?;
+        let payload: Vec<u8> = row.try_get("payload").map_err(BufferError::FetchFailed)?;
+
+        let Ok(topic) = serde_json::from_value::<KafkaTopic>(topic.into()) else {
+            return Ok(None);
+        };
+        let key = match key.map(<[u8; 16]>::try_from) {
+            Some(Ok(key)) => Some(key),
+            Some(Err(_)) => return Ok(None),
+            None => None,
+        };
+        let headers = match headers {
+            Some(headers) => match serde_json::from_str::<BTreeMap<String, String>>(&headers) {
+                Ok(headers) => Some(headers),
+                Err(_) => return Ok(None),
+            },
+            None => None,
+        };
+
+        let route = SinkRoute {
+            topic,
+            organization_id: organization_id as u64,
+            project_id: project_id.map(|id| ProjectId::new(id as u64)),
+            category: DataCategory::from_name(&category),
+            kafka_route: None,
+        };
+
+        let message = EncodedMessage {
+            topic,
+            route: route.kafka_route,
+            organization_id: route.organization_id,
+            key,
+            headers,
+            variant,
+            payload,
+        };
+
+        Ok(Some(SpooledMessage {
+            id,
+            attempts: attempts as u32,
+            topic_name,
+            received_at: Utc
+                .timestamp_millis_opt(received_at)
+                .single()
+                .unwrap_or_else(Utc::now),
+            route,
+            message,
+        }))
+    }
+
+    /// Records a failed attempt to produce a spooled message.
+    pub async fn record_attempt(&self, id: i64) -> Result<(), BufferError> {
+        sqlx::query("UPDATE kafka_messages SET attempts = attempts + 1 WHERE id = ?;")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(BufferError::InsertFailed)?;
+
+        Ok(())
+    }
+
+    /// Removes a message from the spool after it has been produced or dropped.
+    pub async fn remove(&self, id: i64) -> Result<(), BufferError> {
+        sqlx::query("DELETE FROM kafka_messages WHERE id = ?;")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(BufferError::DeleteFailed)?;
+
+        relay_statsd::metric!(counter(RelayCounters::KafkaSpoolRead) += 1);
+        Ok(())
+    }
+
+    /// Returns `true` if the spool is empty.
+    async fn is_empty(&self) -> Result<bool, BufferError> {
+        let is_empty = sqlx::query("SELECT id FROM kafka_messages LIMIT 1;")
+            .fetch_optional(&self.db)
+            .await
+            .map_err(BufferError::FetchFailed)?
+            .is_none();
+
+        Ok(is_empty)
+    }
+
+    /// Returns the allocated size of the spool file in bytes.
+    async fn estimate_size(&self) -> Result<usize, BufferError> {
+        let size: i64 = sqlx::query(
+            "SELECT page_count * page_size as size FROM pragma_page_count(), pragma_page_size();",
+        )
+        .fetch_one(&self.db)
+        .await
+        .and_then(|r| r.try_get(0))
+        .map_err(BufferError::FileSizeReadFailed)?;
+
+        relay_statsd::metric!(histogram(RelayHistograms::KafkaSpoolDiskSize) = size as u64);
+        Ok(size as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn message(payload: &str) -> EncodedMessage {
+        EncodedMessage {
+            topic: KafkaTopic::Transactions,
+            route: None,
+            organization_id: 1,
+            key: Some([7; 16]),
+            headers: Some(BTreeMap::from([("org_id".to_owned(), "1".to_owned())])),
+            variant: "transaction".to_owned(),
+            payload: payload.as_bytes().to_vec(),
+        }
+    }
+
+    fn spooled(payload: &str) -> SpooledMessage {
+        SpooledMessage {
+            id: 0,
+            attempts: 0,
+            topic_name: "ingest-transactions-2".to_owned(),
+            received_at: Utc::now(),
+            route: SinkRoute {
+                topic: KafkaTopic::Transactions,
+                organization_id: 1,
+                project_id: Some(ProjectId::new(42)),
+                category: DataCategory::Transaction,
+                kafka_route: Some(2),
+            },
+            message: EncodedMessage {
+                route: Some(2),
+                ..message(payload)
+            },
+        }
+    }
+
+    async fn spool() -> KafkaSpool {
+        let path = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let config = Config::from_json_value(serde_json::json!({
+            "processing": {
+                "enabled": true,
+                "kafka_config": [],
+            },
+            "spool": {
+                "kafka": {
+                    "path": path,
+                }
+            }
+        }))
+        .unwrap();
+
+        KafkaSpool::open(&config).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_kafka_spool_roundtrip() {
+        let spool = spool().await;
+        assert!(!spool.has_backlog());
+        assert!(spool.peek(10).await.unwrap().is_empty());
+
+        spool.push(&[]).await.unwrap();
+        assert!(!spool.has_backlog());
+
+        spool
+            .push(&[spooled("first"), spooled("second")])
+            .await
+            .unwrap();
+        assert!(spool.has_backlog());
+
+        let spooled = spool.peek(1).await.unwrap();
+        assert_eq!(spooled.len(), 1);
+        // The route index is not stored and must be resolved from the topic name.
+        assert_eq!(spooled[0].message, message("first"));
+        assert_eq!(spooled[0].topic_name, "ingest-transactions-2");
+        assert_eq!(spooled[0].route.kafka_route, None);
+        assert_eq!(spooled[0].route.category, DataCategory::Transaction);
+        assert_eq!(spooled[0].attempts, 0);
+
+        spool.record_attempt(spooled[0].id).await.unwrap();
+        spool.remove(spooled[0].id).await.unwrap();
+
+        let spooled = spool.peek(10).await.unwrap();
+        assert_eq!(spooled.len(), 1);
+        assert_eq!(spooled[0].message, message("second"));
+
+        spool.record_attempt(spooled[0].id).await.unwrap();
+        let spooled = spool.peek(10).await.unwrap();
+        assert_eq!(spooled[0].attempts, 1);
+
+        spool.remove(spooled[0].id).await.unwrap();
+        assert!(spool.peek(10).await.unwrap().is_empty());
+        assert!(!spool.has_backlog());
+    }
+
+    #[tokio::test]
+    async fn test_kafka_spool_disabled_without_processing() {
+        let config = Config::from_json_value(serde_json::json!({
+            "spool": {
+                "kafka": {
+                    "path": std::env::temp_dir().join(Uuid::new_v4().to_string()),
+                }
+            }
+        }))
+        .unwrap();
+
+        assert!(KafkaSpool::open(&config).await.unwrap().is_none());
+    }
+}
@@ -33,8 +33,11 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
+use chrono::Utc;
 use futures::stream::{self, StreamExt};
 use relay_base_schema::project::ProjectKey;
 use relay_config::Config;
@@ -52,9 +55,12 @@ use crate::actors::project_cache::{ProjectCache, UpdateBufferIndex};
 use crate::actors::test_store::TestStore;
 use crate::envelope::{Envelope, EnvelopeError};
 use crate::extractors::StartTime;
-use crate::statsd::{RelayCounters, RelayGauges, RelayHistograms};
+use crate::statsd::{RelayCounters, RelayGauges, RelayHistograms, RelayTimers};
 use crate::utils::{BufferGuard, ManagedEnvelope};
 
+pub mod forward;
+#[cfg(feature = "processing")]
+pub mod kafka;
 mod sql;
 
 /// The set of errors which can happend while working the the buffer.
@@ -317,6 +323,8 @@ struct OnDisk {
     /// We do not track the count when we encounter envelopes in the database on startup,
     /// because counting those envelopes would risk locking the db for multiple seconds.
     count: Option<u64>,
+    /// Whether a compaction of the database is currently running.
+    compacting: Arc<AtomicBool>,
 }
 
 impl OnDisk {
@@ -348,9 +356,11 @@ impl OnDisk {
                 },
             );
 
+        let start = Instant::now();
         let inserted = sql::do_insert(stream::iter(envelopes), &self.db)
             .await
             .map_err(BufferError::InsertFailed)?;
+        relay_statsd::metric!(timer(RelayTimers::BufferWriteDuration) = start.elapsed());
 
         self.track_count(inserted as i64);
 
@@ -435,6 +445,7 @@ impl OnDisk {
             //
             // Right now we use 100 for batch size.
             let batch_size = 100;
+            let start = Instant::now();
             let mut envelopes = sql::delete_and_fetch(key, batch_size)
                 .fetch(&self.db)
                 .peekable();
@@ -473,6 +484,7 @@ impl OnDisk {
                 }
             }
 
+            relay_statsd::metric!(timer(RelayTimers::BufferReadDuration) = start.elapsed());
             self.track_count(-count);
         }
     }
@@ -514,6 +526,68 @@ impl OnDisk {
         Ok(size)
     }
 
+    /// Reports health metrics of the spool and compacts the spool file if it has too many free
+    /// pages.
+    ///
+    /// Compaction runs `VACUUM`, which also applies the auto-vacuum mode to spool files created
+    /// before it was enabled. Since it rewrites the entire file, it runs on a separate task and
+    /// this function does not wait for it to complete.
+    async fn maintain(&self, compaction_threshold: f64) -> Result<(), BufferError> {
+        let row = sql::page_stats()
+            .fetch_one(&self.db)
+            .await
+            .map_err(BufferError::FileSizeReadFailed)?;
+
+        let page_count: i64 = row.try_get(0).map_err(BufferError::FileSizeReadFailed)?;
+        let freelist_count: i64 = row.try_get(1).map_err(BufferError::FileSizeReadFailed)?;
+        let page_size: i64 = row.try_get(2).map_err(BufferError::FileSizeReadFailed)?;
+
+        let fragmentation = fragmentation(page_count, freelist_count);
+        relay_statsd::metric!(
+            histogram(RelayHistograms::BufferDiskSize) = (page_count * page_size) as u64
+        );
+        relay_statsd::metric!(
+            gauge(RelayGauges::BufferDiskFragmentation) = (fragmentation * 100.0) as u64
+        );
+
+        let oldest = sql::select_oldest()
+            .fetch_optional(&self.db)
+            .await
+            .map_err(BufferError::FetchFailed)?;
+
+        let oldest_age = match oldest {
+            Some(row) => {
+                let received_at: i64 = row.try_get(0).map_err(BufferError::FetchFailed)?;
+                (Utc::now().timestamp_millis() - received_at).max(0) as u64 / 1000
+            }
+            None => 0,
+        };
+        relay_statsd::metric!(gauge(RelayGauges::BufferOldestEnvelopeAge) = oldest_age);
+
+        if fragmentation < compaction_threshold || self.compacting.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        relay_log::info!(page_count, freelist_count, "compacting envelope spool");
+        let db = self.db.clone();
+        let compacting = self.compacting.clone();
+        tokio::spawn(async move {
+            let start = Instant::now();
+            match sql::vacuum().execute(&db).await {
+                Ok(_) => relay_statsd::metric!(
+                    timer(RelayTimers::BufferCompactionDuration) = start.elapsed()
+                ),
+                Err(err) => relay_log::error!(
+                    error = &err as &dyn Error,
+                    "failed to compact the envelope spool",
+                ),
+            }
+            compacting.store(false, Ordering::Relaxed);
+        });
+
+        Ok(())
+    }
+
     /// Returns `true` if the maximum size is reached, `false` otherwise.
     async fn is_full(&self) -> Result<bool, BufferError> {
         let current_size = self.estimate_spool_size().await?;
@@ -538,6 +612,7 @@ impl OnDisk {
         managed_envelope: ManagedEnvelope,
     ) -> Result<(), BufferError> {
         let received_at = managed_envelope.received_at().timestamp_millis();
+        let start = Instant::now();
         sql::insert(
             key,
             managed_envelope.into_envelope().to_vec().unwrap(),
@@ -546,6 +621,7 @@ impl OnDisk {
         .execute(&self.db)
         .await
         .map_err(BufferError::InsertFailed)?;
+        relay_statsd::metric!(timer(RelayTimers::BufferWriteDuration) = start.elapsed());
 
         self.track_count(1);
         relay_statsd::metric!(counter(RelayCounters::BufferWrites) += 1);
@@ -568,6 +644,15 @@ impl OnDisk {
     }
 }
 
+/// Returns the fraction of free pages in the spool file in the range `[0, 1]`.
+fn fragmentation(page_count: i64, freelist_count: i64) -> f64 {
+    if page_count <= 0 {
+        return 0.0;
+    }
+
+    (freelist_count as f64 / page_count as f64).clamp(0.0, 1.0)
+}
+
 /// The state which defines the [`BufferService`] behaviour.
 #[derive(Debug)]
 enum BufferState {
@@ -734,6 +819,7 @@ impl BufferService {
             buffer_guard,
             max_disk_size: config.spool_envelopes_max_disk_size(),
             count: None,
+            compacting: Arc::new(AtomicBool::new(false)),
         };
 
         if on_disk.is_empty().await? {
@@ -855,6 +941,17 @@ impl BufferService {
         Ok(())
     }
 
+    /// Reports health metrics of the on-disk spool and compacts it if needed.
+    async fn handle_maintenance(&mut self) -> Result<(), BufferError> {
+        let disk = match self.state {
+            BufferState::MemoryFileStandby { ref disk, .. } | BufferState::Disk(ref disk) => disk,
+            BufferState::Memory(_) => return Ok(()),
+        };
+
+        disk.maintain(self.config.spool_envelopes_compaction_threshold())
+            .await
+    }
+
     /// Handles all the incoming messages from the [`Buffer`] interface.
     async fn handle_message(&mut self, message: Buffer) -> Result<(), BufferError> {
         match message {
@@ -901,7 +998,25 @@ impl Service for BufferService {
         tokio::spawn(async move {
             let mut shutdown = Controller::shutdown_handle();
 
+            // Maintenance only applies to the on-disk spool, so skip the timer without one.
+            let mut maintenance = match self.state {
+                BufferState::Memory(_) => None,
+                BufferState::MemoryFileStandby { .. } | BufferState::Disk(_) => {
+                    let mut interval =
+                        tokio::time::interval(self.config.spool_envelopes_compaction_interval());
+                    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                    Some(interval)
+                }
+            };
+
             loop {
+                let maintenance_tick = async {
+                    match maintenance.as_mut() {
+                        Some(interval) => interval.tick().await,
+                        None => std::future::pending().await,
+                    }
+                };
+
                 tokio::select! {
                     biased;
 
@@ -913,6 +1028,14 @@ impl Service for BufferService {
                             );
                         }
                     }
+                    _ = maintenance_tick => {
+                        if let Err(err) = self.handle_maintenance().await {
+                            relay_log::error!(
+                                error = &err as &dyn Error,
+                                "failed to maintain the envelope spool",
+                            );
+                        }
+                    }
                     _ = shutdown.notified() => {
                        if let Err(err) = self.handle_shutdown().await {
                             relay_log::error!(
@@ -977,6 +1100,64 @@ mod tests {
         ManagedEnvelope::untracked(envelope, outcome_aggregator, test_store)
     }
 
+    #[test]
+    fn test_fragmentation() {
+        assert_eq!(fragmentation(0, 0), 0.0);
+        assert_eq!(fragmentation(100, 25), 0.25);
+        assert_eq!(fragmentation(100, 200), 1.0);
+    }
+
+    #[tokio::test]
+    async fn maintain_compacts_spool() {
+        let buffer_guard: Arc<_> = BufferGuard::new(10).into();
+        let config: Arc<_> = Config::from_json_value(serde_json::json!({
+            "spool": {
+                "envelopes": {
+                    "path": std::env::temp_dir().join(Uuid::new_v4().to_string()),
+                    "max_memory_size": 0,
+                }
+            }
+        }))
+        .unwrap()
+        .into();
+
+        let mut service = BufferService::create(buffer_guard, services(), config)
+            .await
+            .unwrap();
+
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+        let key = QueueKey::new(project_key, project_key);
+        for _ in 0..10 {
+            service
+                .handle_enqueue(Enqueue::new(key, empty_managed_envelope()))
+                .await
+                .unwrap();
+        }
+
+        service
+            .handle_remove(RemoveMany::new(project_key, [key].into()))
+            .await
+            .unwrap();
+
+        let disk = match service.state {
+            BufferState::MemoryFileStandby { ref disk, .. } | BufferState::Disk(ref disk) => disk,
+            BufferState::Memory(_) => panic!("expected the disk spool"),
+        };
+
+        // A threshold of zero always compacts the spool.
+        disk.maintain(0.0).await.unwrap();
+        assert!(disk.compacting.load(Ordering::Relaxed));
+
+        // Compaction runs in the background, wait for it to complete.
+        while disk.compacting.load(Ordering::Relaxed) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let row = sql::page_stats().fetch_one(&disk.db).await.unwrap();
+        let freelist_count: i64 = row.try_get(1).unwrap();
+        assert_eq!(freelist_count, 0);
+    }
+
     #[tokio::test]
     async fn ensure_start_time_restore() {
         let buffer_guard: Arc<_> = BufferGuard::new(10).into();
@@ -1195,10 +1376,10 @@ mod tests {
             })
         });
 
-        // Collect only the buffer metrics.
+        // Collect only the buffer metrics, excluding timings which vary between runs.
         let captures: Vec<_> = captures
             .into_iter()
-            .filter(|name| name.contains("buffer."))
+            .filter(|name| name.contains("buffer.") && !name.ends_with("|ms"))
             .collect();
 
         assert_debug_snapshot!(captures, @r#"
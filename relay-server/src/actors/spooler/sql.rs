@@ -54,6 +54,26 @@ pub fn current_size<'a>() -> Query<'a, Sqlite, SqliteArguments<'a>> {
     )
 }
 
+/// Creates a query which fetches the page count, the number of free pages, and the page size.
+pub fn page_stats<'a>() -> Query<'a, Sqlite, SqliteArguments<'a>> {
+    sqlx::query(
+        "SELECT page_count, freelist_count, page_size
+         FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size();",
+    )
+}
+
+/// Creates a query which fetches `received_at` of the envelope that was spooled first.
+///
+/// This uses the primary key to avoid scanning the entire table.
+pub fn select_oldest<'a>() -> Query<'a, Sqlite, SqliteArguments<'a>> {
+    sqlx::query("SELECT received_at FROM envelopes ORDER BY id LIMIT 1;")
+}
+
+/// Creates a query which rebuilds the database file, returning free pages to the filesystem.
+pub fn vacuum<'a>() -> Query<'a, Sqlite, SqliteArguments<'a>> {
+    sqlx::query("VACUUM;")
+}
+
 /// Creates the query to select only 1 record's `received_at` from the database.
 ///
 /// It is usefull and very fast for checking if the table is empty.
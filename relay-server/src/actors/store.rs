@@ -1,27 +1,34 @@
 //! This module contains the service that forwards events and attachments to the Sentry store.
 //! The service uses kafka topics to forward data to Sentry
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use once_cell::sync::OnceCell;
 use relay_base_schema::project::ProjectId;
 use relay_common::time::UnixTimestamp;
-use relay_config::Config;
+use relay_config::{Config, KafkaHeader};
 use relay_event_schema::protocol::{
     self, EventId, SessionAggregates, SessionStatus, SessionUpdate,
 };
-use relay_kafka::{ClientError, KafkaClient, KafkaTopic, Message};
-use relay_metrics::{Bucket, BucketValue, MetricNamespace, MetricResourceIdentifier};
-use relay_quotas::Scoping;
+use relay_kafka::{to_avro_value, AvroValue, ClientError, KafkaTopic, Message, PartitionStrategy};
+use relay_metrics::{
+    Bucket, BucketValue, Location, MetaItem, MetricMeta, MetricNamespace, MetricResourceIdentifier,
+    MetricType,
+};
+use relay_protocol::{Getter, Val};
+use relay_quotas::{DataCategory, Scoping};
 use relay_statsd::metric;
-use relay_system::{AsyncResponse, FromMessage, Interface, Sender, Service};
+use relay_system::{Addr, AsyncResponse, Controller, FromMessage, Interface, Sender, Service};
 use serde::ser::Error;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::actors::outcome::OutcomeProducer;
+use crate::actors::sinks::{SinkError, SinkRoute, StoreSink};
+use crate::actors::test_store::{TestStore, TrackResult};
 use crate::envelope::{AttachmentType, Envelope, Item, ItemType};
 use crate::statsd::RelayCounters;
 
@@ -33,10 +40,12 @@ const UNNAMED_ATTACHMENT: &str = "Unnamed Attachment";
 
 #[derive(Debug, thiserror::Error)]
 pub enum StoreError {
-    #[error("failed to send the message to kafka")]
-    SendFailed(#[from] ClientError),
+    #[error("failed to send the message")]
+    SendFailed(#[from] SinkError),
     #[error("failed to store event because event id was missing")]
     NoEventId,
+    #[error("failed to store trace attachment because trace id was missing")]
+    NoTraceId,
 }
 
 fn make_distinct_id(s: &str) -> Uuid {
@@ -48,27 +57,6 @@ fn make_distinct_id(s: &str) -> Uuid {
         .unwrap_or_else(|_| Uuid::new_v5(namespace, s.as_bytes()))
 }
 
-struct Producer {
-    client: KafkaClient,
-}
-
-impl Producer {
-    pub fn create(config: &Arc<Config>) -> anyhow::Result<Self> {
-        let mut client_builder = KafkaClient::builder();
-
-        for topic in KafkaTopic::iter()
-            .filter(|t| **t != KafkaTopic::Outcomes || **t != KafkaTopic::OutcomesBilling)
-        {
-            let kafka_config = &config.kafka_config(*topic)?;
-            client_builder = client_builder.add_kafka_topic_config(*topic, kafka_config)?;
-        }
-
-        Ok(Self {
-            client: client_builder.build(),
-        })
-    }
-}
-
 /// Publishes an [`Envelope`] to the Sentry core application through Kafka topics.
 #[derive(Clone, Debug)]
 pub struct StoreEnvelope {
@@ -77,9 +65,16 @@ pub struct StoreEnvelope {
     pub scoping: Scoping,
 }
 
+/// Checks whether the message broker of the store sink is reachable. Used for health checks.
+#[derive(Debug)]
+pub struct IsKafkaConnected;
+
 /// Service interface for the [`StoreEnvelope`] message.
 #[derive(Debug)]
-pub struct Store(StoreEnvelope, Sender<Result<(), StoreError>>);
+pub enum Store {
+    Envelope(StoreEnvelope, Sender<Result<(), StoreError>>),
+    IsKafkaConnected(IsKafkaConnected, Sender<bool>),
+}
 
 impl Interface for Store {}
 
@@ -87,28 +82,233 @@ impl FromMessage<StoreEnvelope> for Store {
     type Response = AsyncResponse<Result<(), StoreError>>;
 
     fn from_message(message: StoreEnvelope, sender: Sender<Result<(), StoreError>>) -> Self {
-        Self(message, sender)
+        Self::Envelope(message, sender)
+    }
+}
+
+impl FromMessage<IsKafkaConnected> for Store {
+    type Response = AsyncResponse<bool>;
+
+    fn from_message(message: IsKafkaConnected, sender: Sender<bool>) -> Self {
+        Self::IsKafkaConnected(message, sender)
+    }
+}
+
+/// Identifies buckets that are merged across the projects of an organization.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct OrgBucketKey {
+    org_id: u64,
+    name: String,
+    timestamp: UnixTimestamp,
+    tags: BTreeMap<String, String>,
+    retention_days: u16,
+}
+
+/// Counter and gauge buckets merged across projects that are waiting to be produced.
+#[derive(Debug, Default)]
+struct OrgBuckets {
+    buckets: HashMap<OrgBucketKey, BucketValue>,
+    /// Set on shutdown, after which buckets are no longer merged.
+    closed: bool,
+}
+
+impl OrgBuckets {
+    /// Merges the bucket if it is a counter or gauge in one of the given namespaces.
+    ///
+    /// Returns the bucket if it cannot be merged across projects.
+    fn merge(
+        &mut self,
+        namespaces: &BTreeSet<MetricNamespace>,
+        org_id: u64,
+        retention_days: u16,
+        bucket: Bucket,
+    ) -> Option<Bucket> {
+        if self.closed || !matches!(bucket.value.ty(), MetricType::Counter | MetricType::Gauge) {
+            return Some(bucket);
+        }
+
+        match MetricResourceIdentifier::parse(&bucket.name) {
+            Ok(mri) if namespaces.contains(&mri.namespace) => (),
+            _ => return Some(bucket),
+        }
+
+        let key = OrgBucketKey {
+            org_id,
+            name: bucket.name,
+            timestamp: bucket.timestamp,
+            tags: bucket.tags,
+            retention_days,
+        };
+
+        self.insert(key, bucket.value);
+        None
+    }
+
+    /// Merges a bucket back after it failed to be produced, so it is retried with the next flush.
+    fn requeue(&mut self, key: OrgBucketKey, value: BucketValue) {
+        self.insert(key, value);
+    }
+
+    fn insert(&mut self, key: OrgBucketKey, value: BucketValue) {
+        match self.buckets.get_mut(&key) {
+            Some(existing) => {
+                // Buckets with the same name but different types cannot be merged. Like the
+                // aggregator, keep the bucket that arrived first.
+                if existing.merge(value).is_err() {
+                    relay_log::debug!("dropping merged bucket with mismatching type");
+                }
+            }
+            None => {
+                self.buckets.insert(key, value);
+            }
+        }
+    }
+
+    /// Stops merging buckets, so that all further buckets are produced per project.
+    fn close(&mut self) {
+        self.closed = true;
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Removes and returns all merged buckets.
+    fn take(&mut self) -> HashMap<OrgBucketKey, BucketValue> {
+        std::mem::take(&mut self.buckets)
     }
 }
 
 /// Service implementing the [`Store`] interface.
 pub struct StoreService {
     config: Arc<Config>,
-    producer: Producer,
+    sink: StoreSink,
+    org_buckets: OrgBuckets,
+    test_store: Addr<TestStore>,
 }
 
 impl StoreService {
-    pub fn create(config: Arc<Config>) -> anyhow::Result<Self> {
-        let producer = Producer::create(&config)?;
-        Ok(Self { config, producer })
+    pub fn create(
+        config: Arc<Config>,
+        outcomes: Addr<OutcomeProducer>,
+        test_store: Addr<TestStore>,
+    ) -> anyhow::Result<Self> {
+        let sink = StoreSink::create(&config, outcomes)?;
+        Ok(Self {
+            config,
+            sink,
+            org_buckets: OrgBuckets::default(),
+            test_store,
+        })
+    }
+
+    /// Loads the schemas of Kafka topics from the schema registry, if configured.
+    ///
+    /// This must complete before the service starts, since producing messages without their
+    /// registered schemas would break consumers of these topics.
+    pub async fn load_schemas(&mut self) -> anyhow::Result<()> {
+        self.sink.load_schemas(&self.config).await
+    }
+
+    /// Fetches the latest schemas from the schema registry, keeping the current ones on error.
+    async fn refresh_schemas(&mut self) {
+        if let Err(error) = self.sink.load_schemas(&self.config).await {
+            relay_log::error!(
+                error = error.as_ref() as &dyn std::error::Error,
+                "failed to refresh kafka schemas from the schema registry",
+            );
+        }
+    }
+
+    async fn handle_message(&mut self, message: Store) {
+        match message {
+            Store::Envelope(message, sender) => {
+                sender.send(self.handle_store_envelope(message).await)
+            }
+            Store::IsKafkaConnected(_, sender) => sender.send(self.sink.is_connected()),
+        }
+    }
+
+    /// Produces all buckets that have been merged across projects.
+    ///
+    /// With transactional delivery, all buckets of a flush are produced in one transaction. Buckets
+    /// that fail to be produced or committed are merged back and retried with the next flush.
+    async fn flush_org_buckets(&mut self) {
+        if let Err(error) = self.sink.begin_transaction() {
+            relay_log::error!(
+                error = &error as &dyn std::error::Error,
+                "failed to begin transaction for merged metric buckets"
+            );
+            return;
+        }
+
+        let mut sent = Vec::new();
+        for (key, value) in self.org_buckets.take() {
+            let message = MetricKafkaMessage {
+                org_id: key.org_id,
+                // Merged buckets are not attributed to a single project.
+                project_id: None,
+                name: key.name.clone(),
+                value: value.clone(),
+                timestamp: key.timestamp,
+                tags: key.tags.clone(),
+                retention_days: key.retention_days,
+            };
+
+            match self.send_metric_message(key.org_id, message) {
+                Ok(()) => sent.push((key, value)),
+                Err(error) => {
+                    relay_log::error!(
+                        error = &error as &dyn std::error::Error,
+                        "failed to produce merged metric bucket"
+                    );
+                    self.org_buckets.requeue(key, value);
+                }
+            }
+        }
+
+        if let Err(error) = self.sink.end_transaction(true).await {
+            relay_log::error!(
+                error = &error as &dyn std::error::Error,
+                "failed to commit transaction for merged metric buckets"
+            );
+            for (key, value) in sent {
+                self.org_buckets.requeue(key, value);
+            }
+        }
     }
 
-    fn handle_message(&self, message: Store) {
-        let Store(message, sender) = message;
-        sender.send(self.handle_store_envelope(message));
+    /// Produces all messages of an envelope.
+    ///
+    /// With transactional delivery, the messages are produced in one transaction that is aborted if
+    /// any of them fails.
+    async fn handle_store_envelope(&mut self, message: StoreEnvelope) -> Result<(), StoreError> {
+        let tracked_event_id = TrackResult::is_tracked(&message.envelope)
+            .then(|| message.envelope.event_id())
+            .flatten();
+
+        self.sink.begin_transaction()?;
+
+        if let Err(error) = self.store_envelope(message) {
+            if let Err(abort_error) = self.sink.end_transaction(false).await {
+                relay_log::error!(
+                    error = &abort_error as &dyn std::error::Error,
+                    "failed to abort kafka transaction"
+                );
+            }
+            return Err(error);
+        }
+
+        self.sink.end_transaction(true).await?;
+
+        if let Some(event_id) = tracked_event_id {
+            self.test_store.send(TrackResult::accepted(event_id));
+        }
+
+        Ok(())
     }
 
-    fn handle_store_envelope(&self, message: StoreEnvelope) -> Result<(), StoreError> {
+    fn store_envelope(&mut self, message: StoreEnvelope) -> Result<(), StoreError> {
         let StoreEnvelope {
             envelope,
             start_time,
@@ -182,9 +382,19 @@ impl StoreService {
                     start_time,
                     item,
                 )?,
+                ItemType::ProfileChunk => self.produce_profile_chunk(
+                    scoping.organization_id,
+                    scoping.project_id,
+                    start_time,
+                    retention,
+                    item,
+                )?,
                 ItemType::ReplayRecording => {
                     self.produce_replay_recording(event_id, scoping, item, start_time, retention)?
                 }
+                ItemType::ReplayVideo => {
+                    self.produce_replay_video(event_id, scoping, item, start_time, retention)?
+                }
                 ItemType::ReplayEvent => self.produce_replay_event(
                     event_id.ok_or(StoreError::NoEventId)?,
                     scoping.organization_id,
@@ -208,6 +418,34 @@ impl StoreService {
                     start_time,
                     item,
                 )?,
+                ItemType::Log => self.produce_log(
+                    scoping.organization_id,
+                    scoping.project_id,
+                    start_time,
+                    retention,
+                    item,
+                )?,
+                ItemType::TraceAttachment => self.produce_trace_attachment(
+                    item.trace_id().ok_or(StoreError::NoTraceId)?,
+                    scoping.organization_id,
+                    scoping.project_id,
+                    start_time,
+                    retention,
+                    item,
+                )?,
+                ItemType::Feedback => self.produce_feedback(
+                    scoping.organization_id,
+                    scoping.project_id,
+                    start_time,
+                    retention,
+                    item,
+                )?,
+                ItemType::MetricMeta => self.produce_metric_meta(
+                    scoping.organization_id,
+                    scoping.project_id,
+                    retention,
+                    item,
+                )?,
                 _ => {}
             }
         }
@@ -217,7 +455,9 @@ impl StoreService {
             return Ok(());
         }
 
-        let remote_addr = envelope.meta().client_addr().map(|addr| addr.to_string());
+        let remote_addr = envelope.meta().client_addr().map(|addr| {
+            relay_event_normalization::normalize_ip(addr, self.config.ipv6_prefix()).to_string()
+        });
 
         let kafka_messages = Self::extract_kafka_messages_for_event(
             event_item,
@@ -305,13 +545,69 @@ impl StoreService {
     ) -> Result<(), StoreError> {
         relay_log::trace!("Sending kafka message of type {}", message.variant());
 
-        self.producer
-            .client
-            .send_message(topic, organization_id, &message)?;
+        let mut route = SinkRoute {
+            topic,
+            organization_id,
+            project_id: message.project_id(),
+            category: message.data_category(topic),
+            kafka_route: None,
+        };
+        route.kafka_route = self.kafka_route(&route, &message);
+
+        let kafka_headers = self.config.kafka_headers();
+        if kafka_headers.is_empty() {
+            self.sink.send(route, &message)?;
+        } else {
+            let mut headers = message.headers().cloned().unwrap_or_default();
+            for &header in kafka_headers {
+                if let Some(value) = self.header_value(header, topic, organization_id, &message) {
+                    headers.insert(header.name().to_owned(), value);
+                }
+            }
+
+            let message = RoutedKafkaMessage {
+                message: &message,
+                headers,
+            };
+            self.sink.send(route, &message)?;
+        }
 
         Ok(())
     }
 
+    /// Returns the index of the first topic route of the message's topic that matches it.
+    fn kafka_route(&self, route: &SinkRoute, message: &KafkaMessage) -> Option<usize> {
+        let mut routes = self
+            .config
+            .topic_routes()
+            .iter()
+            .filter(|topic_route| topic_route.topic == route.topic)
+            .peekable();
+
+        // Skip creating the context for topics without routes.
+        routes.peek()?;
+
+        let context = RoutingContext::new(route, message);
+        routes.position(|topic_route| topic_route.condition.matches(&context))
+    }
+
+    /// Returns the value of a routing header for the given message.
+    fn header_value(
+        &self,
+        header: KafkaHeader,
+        topic: KafkaTopic,
+        organization_id: u64,
+        message: &KafkaMessage,
+    ) -> Option<String> {
+        Some(match header {
+            KafkaHeader::OrgId => organization_id.to_string(),
+            KafkaHeader::ProjectId => message.project_id()?.to_string(),
+            KafkaHeader::DataCategory => message.data_category(topic).name().to_owned(),
+            KafkaHeader::RelayInstance => self.config.relay_id()?.to_string(),
+            KafkaHeader::IngestTimestamp => UnixTimestamp::now().as_secs().to_string(),
+        })
+    }
+
     fn produce_attachment_chunks(
         &self,
         event_id: EventId,
@@ -362,6 +658,72 @@ impl StoreService {
         })
     }
 
+    /// Produces a trace attachment in chunks, followed by a message that references the chunks.
+    ///
+    /// All messages are keyed by the trace ID, so that the chunks and the attachment end up in the
+    /// same partition.
+    fn produce_trace_attachment(
+        &self,
+        trace_id: Uuid,
+        organization_id: u64,
+        project_id: ProjectId,
+        start_time: Instant,
+        retention_days: u16,
+        item: &Item,
+    ) -> Result<(), StoreError> {
+        let trace_id = EventId(trace_id);
+        let id = Uuid::new_v4().to_string();
+
+        let mut chunk_index = 0;
+        let mut offset = 0;
+        let payload = item.payload();
+        let size = item.len();
+
+        while offset < size {
+            let max_chunk_size = self.config.attachment_chunk_size();
+            let chunk_size = std::cmp::min(max_chunk_size, size - offset);
+            let chunk_message =
+                KafkaMessage::TraceAttachmentChunk(TraceAttachmentChunkKafkaMessage {
+                    payload: payload.slice(offset..offset + chunk_size),
+                    trace_id,
+                    project_id,
+                    id: id.clone(),
+                    chunk_index,
+                });
+            self.produce(KafkaTopic::TraceAttachments, organization_id, chunk_message)?;
+            offset += chunk_size;
+            chunk_index += 1;
+        }
+
+        let message = KafkaMessage::TraceAttachment(TraceAttachmentKafkaMessage {
+            trace_id,
+            project_id,
+            start_time: UnixTimestamp::from_instant(start_time).as_secs(),
+            retention_days,
+            attachment: ChunkedAttachment {
+                id,
+                name: match item.filename() {
+                    Some(name) => name.to_owned(),
+                    None => UNNAMED_ATTACHMENT.to_owned(),
+                },
+                content_type: item
+                    .content_type()
+                    .map(|content_type| content_type.as_str().to_owned()),
+                attachment_type: item.attachment_type().cloned().unwrap_or_default(),
+                chunks: chunk_index,
+                size: Some(size),
+                rate_limited: None,
+            },
+        });
+        self.produce(KafkaTopic::TraceAttachments, organization_id, message)?;
+
+        metric!(
+            counter(RelayCounters::ProcessingMessageProduced) += 1,
+            event_type = "trace_attachment"
+        );
+        Ok(())
+    }
+
     fn produce_user_report(
         &self,
         event_id: EventId,
@@ -564,20 +926,28 @@ impl StoreService {
     }
 
     fn produce_metrics(
-        &self,
+        &mut self,
         org_id: u64,
         project_id: ProjectId,
         item: &Item,
         retention: u16,
     ) -> Result<(), StoreError> {
         let payload = item.payload();
+        let namespaces = self.config.org_metrics_namespaces();
 
         for bucket in serde_json::from_slice::<Vec<Bucket>>(&payload).unwrap_or_default() {
+            let Some(bucket) = self
+                .org_buckets
+                .merge(namespaces, org_id, retention, bucket)
+            else {
+                continue;
+            };
+
             self.send_metric_message(
                 org_id,
                 MetricKafkaMessage {
                     org_id,
-                    project_id,
+                    project_id: Some(project_id),
                     name: bucket.name,
                     value: bucket.value,
                     timestamp: bucket.timestamp,
@@ -634,6 +1004,33 @@ impl StoreService {
         Ok(())
     }
 
+    fn produce_profile_chunk(
+        &self,
+        organization_id: u64,
+        project_id: ProjectId,
+        start_time: Instant,
+        retention_days: u16,
+        item: &Item,
+    ) -> Result<(), StoreError> {
+        let message = ProfileChunkKafkaMessage {
+            organization_id,
+            project_id,
+            received: UnixTimestamp::from_instant(start_time).as_secs(),
+            retention_days,
+            payload: item.payload(),
+        };
+        self.produce(
+            KafkaTopic::ProfileChunks,
+            organization_id,
+            KafkaMessage::ProfileChunk(message),
+        )?;
+        metric!(
+            counter(RelayCounters::ProcessingMessageProduced) += 1,
+            event_type = "profile_chunk"
+        );
+        Ok(())
+    }
+
     fn produce_replay_event(
         &self,
         replay_id: EventId,
@@ -705,6 +1102,46 @@ impl StoreService {
         Ok(())
     }
 
+    fn produce_replay_video(
+        &self,
+        event_id: Option<EventId>,
+        scoping: Scoping,
+        item: &Item,
+        start_time: Instant,
+        retention: u16,
+    ) -> Result<(), StoreError> {
+        // 2000 bytes are reserved for the message metadata.
+        let max_payload_size = self.config.max_replay_message_size() - 2000;
+
+        if item.payload().len() >= max_payload_size {
+            relay_log::warn!("replay_video over maximum size.");
+            return Ok(());
+        }
+
+        let message = KafkaMessage::ReplayVideo(ReplayVideoKafkaMessage {
+            replay_id: event_id.ok_or(StoreError::NoEventId)?,
+            project_id: scoping.project_id,
+            key_id: scoping.key_id,
+            org_id: scoping.organization_id,
+            received: UnixTimestamp::from_instant(start_time).as_secs(),
+            retention_days: retention,
+            replay_video: item.payload(),
+        });
+
+        self.produce(
+            KafkaTopic::ReplayRecordings,
+            scoping.organization_id,
+            message,
+        )?;
+
+        metric!(
+            counter(RelayCounters::ProcessingMessageProduced) += 1,
+            event_type = "replay_video"
+        );
+
+        Ok(())
+    }
+
     fn produce_check_in(
         &self,
         organization_id: u64,
@@ -767,19 +1204,161 @@ impl StoreService {
 
         Ok(())
     }
+
+    fn produce_log(
+        &self,
+        organization_id: u64,
+        project_id: ProjectId,
+        start_time: Instant,
+        retention_days: u16,
+        item: &Item,
+    ) -> Result<(), StoreError> {
+        let message = LogKafkaMessage {
+            organization_id,
+            project_id,
+            received: UnixTimestamp::from_instant(start_time).as_secs(),
+            retention_days,
+            payload: item.payload(),
+        };
+        self.produce(
+            KafkaTopic::Logs,
+            organization_id,
+            KafkaMessage::Log(message),
+        )?;
+        metric!(
+            counter(RelayCounters::ProcessingMessageProduced) += 1,
+            event_type = "log"
+        );
+        Ok(())
+    }
+
+    fn produce_feedback(
+        &self,
+        organization_id: u64,
+        project_id: ProjectId,
+        start_time: Instant,
+        retention_days: u16,
+        item: &Item,
+    ) -> Result<(), StoreError> {
+        let message = FeedbackKafkaMessage {
+            project_id,
+            start_time: UnixTimestamp::from_instant(start_time).as_secs(),
+            retention_days,
+            payload: item.payload(),
+        };
+        self.produce(
+            KafkaTopic::Feedback,
+            organization_id,
+            KafkaMessage::Feedback(message),
+        )?;
+        metric!(
+            counter(RelayCounters::ProcessingMessageProduced) += 1,
+            event_type = "feedback"
+        );
+        Ok(())
+    }
+
+    fn produce_metric_meta(
+        &self,
+        organization_id: u64,
+        project_id: ProjectId,
+        retention_days: u16,
+        item: &Item,
+    ) -> Result<(), StoreError> {
+        let meta = match serde_json::from_slice::<MetricMeta>(&item.payload()) {
+            Ok(meta) => meta,
+            Err(error) => {
+                relay_log::error!(
+                    error = &error as &dyn std::error::Error,
+                    "failed to parse metric meta"
+                );
+                return Ok(());
+            }
+        };
+
+        for (mri, items) in meta.mapping {
+            let locations: Vec<_> = items
+                .into_iter()
+                .filter_map(|item| match item {
+                    MetaItem::Location(location) => Some(location),
+                    MetaItem::Unknown => None,
+                })
+                .collect();
+
+            if locations.is_empty() {
+                continue;
+            }
+
+            let message = KafkaMessage::MetricMeta(MetricMetaKafkaMessage {
+                org_id: organization_id,
+                project_id,
+                mri,
+                timestamp: meta.timestamp,
+                retention_days,
+                locations,
+            });
+
+            self.produce(KafkaTopic::MetricsMeta, organization_id, message)?;
+
+            metric!(
+                counter(RelayCounters::ProcessingMessageProduced) += 1,
+                event_type = "metric_meta"
+            );
+        }
+
+        Ok(())
+    }
 }
 
 impl Service for StoreService {
     type Interface = Store;
 
-    fn spawn_handler(self, mut rx: relay_system::Receiver<Self::Interface>) {
+    fn spawn_handler(mut self, mut rx: relay_system::Receiver<Self::Interface>) {
         tokio::spawn(async move {
+            self.sink.start().await;
+
+            let mut ticker = tokio::time::interval(self.config.org_metrics_flush_interval());
+            let mut replay_ticker = tokio::time::interval(self.config.spool_kafka_retry_interval());
+            // Schemas were loaded before the service started, so the first refresh is delayed.
+            let mut schema_ticker = self.config.kafka_schema_registry().map(|registry| {
+                let period = Duration::from_secs(registry.refresh_interval.max(1));
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+            });
+            let mut shutdown = Controller::shutdown_handle();
             relay_log::info!("store forwarder started");
 
-            while let Some(message) = rx.recv().await {
-                self.handle_message(message);
+            loop {
+                let schema_tick = async {
+                    match schema_ticker.as_mut() {
+                        Some(interval) => interval.tick().await,
+                        None => std::future::pending().await,
+                    }
+                };
+
+                tokio::select! {
+                    biased;
+
+                    // Flush merged buckets on shutdown and produce all later buckets per project,
+                    // so that nothing is left in memory when the service stops.
+                    _ = shutdown.notified(), if !self.org_buckets.is_closed() => {
+                        self.org_buckets.close();
+                        self.flush_org_buckets().await;
+                    }
+                    _ = ticker.tick() => self.flush_org_buckets().await,
+                    Some(message) = rx.recv() => self.handle_message(message).await,
+                    // Incoming messages take precedence. The spool is drained one batch per tick,
+                    // and the next batch follows immediately while producing succeeds.
+                    _ = replay_ticker.tick() => {
+                        if self.sink.replay().await {
+                            replay_ticker.reset_immediately();
+                        }
+                    }
+                    _ = schema_tick => self.refresh_schemas().await,
+                    else => break,
+                }
             }
 
+            self.flush_org_buckets().await;
             relay_log::info!("store forwarder stopped");
         });
     }
@@ -883,6 +1462,38 @@ struct AttachmentChunkKafkaMessage {
     chunk_index: usize,
 }
 
+/// Container payload for chunks of trace attachments.
+#[derive(Debug, Serialize)]
+struct TraceAttachmentChunkKafkaMessage {
+    /// Chunk payload of the attachment.
+    payload: Bytes,
+    /// The trace the attachment belongs to, formatted like an event ID.
+    trace_id: EventId,
+    /// The project id for the current trace attachment.
+    project_id: ProjectId,
+    /// The attachment ID within the trace.
+    ///
+    /// The triple `(project_id, trace_id, id)` identifies a trace attachment uniquely.
+    id: String,
+    /// Sequence number of chunk. Starts at 0 and ends at `ChunkedAttachment.chunks - 1`.
+    chunk_index: usize,
+}
+
+/// An attachment that belongs to a trace rather than a single event.
+#[derive(Debug, Serialize)]
+struct TraceAttachmentKafkaMessage {
+    /// The trace the attachment belongs to, formatted like an event ID.
+    trace_id: EventId,
+    /// The project id for the current trace attachment.
+    project_id: ProjectId,
+    /// Time at which the attachment was received by Relay.
+    start_time: u64,
+    /// Number of days to retain.
+    retention_days: u16,
+    /// The attachment.
+    attachment: ChunkedAttachment,
+}
+
 /// A "standalone" attachment.
 ///
 /// Still belongs to an event but can be sent independently (like UserReport) and is not
@@ -956,6 +1567,21 @@ struct ReplayRecordingNotChunkedKafkaMessage {
     payload: Bytes,
 }
 
+/// Video of a mobile replay segment.
+///
+/// Produced to the replay recordings topic, where it is paired with the replay event by
+/// `replay_id`. The processor only keeps videos whose envelope contains that replay event.
+#[derive(Debug, Serialize)]
+struct ReplayVideoKafkaMessage {
+    replay_id: EventId,
+    key_id: Option<u64>,
+    org_id: u64,
+    project_id: ProjectId,
+    received: u64,
+    retention_days: u16,
+    replay_video: Bytes,
+}
+
 /// User report for an event wrapped up in a message ready for consumption in Kafka.
 ///
 /// Is always independent of an event and can be sent as part of any envelope.
@@ -971,6 +1597,21 @@ struct UserReportKafkaMessage {
     event_id: EventId,
 }
 
+/// User feedback wrapped up in a message ready for consumption in Kafka.
+///
+/// Is independent of an event and can be sent as part of any envelope.
+#[derive(Debug, Serialize)]
+struct FeedbackKafkaMessage {
+    /// The project id for the current feedback.
+    project_id: ProjectId,
+    /// Time at which the feedback was received by Relay.
+    start_time: u64,
+    /// Number of days to retain.
+    retention_days: u16,
+    /// Raw feedback payload.
+    payload: Bytes,
+}
+
 #[derive(Clone, Debug, Serialize)]
 struct SessionKafkaMessage {
     org_id: u64,
@@ -993,7 +1634,9 @@ struct SessionKafkaMessage {
 #[derive(Clone, Debug, Serialize)]
 struct MetricKafkaMessage {
     org_id: u64,
-    project_id: ProjectId,
+    /// The project of the bucket, or `None` if it has been merged across projects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project_id: Option<ProjectId>,
     name: String,
     #[serde(flatten)]
     value: BucketValue,
@@ -1011,6 +1654,26 @@ struct ProfileKafkaMessage {
     payload: Bytes,
 }
 
+/// Chunk of a continuous profile, forwarded with its original payload.
+#[derive(Debug, Serialize)]
+struct ProfileChunkKafkaMessage {
+    organization_id: u64,
+    project_id: ProjectId,
+    received: u64,
+    retention_days: u16,
+    payload: Bytes,
+}
+
+/// Log record that has been validated and scrubbed by Relay.
+#[derive(Debug, Serialize)]
+struct LogKafkaMessage {
+    organization_id: u64,
+    project_id: ProjectId,
+    received: u64,
+    retention_days: u16,
+    payload: Bytes,
+}
+
 #[derive(Debug, Serialize)]
 struct CheckInKafkaMessage {
     /// Raw event payload.
@@ -1040,6 +1703,22 @@ struct SpanKafkaMessage {
     event_id: Option<EventId>,
 }
 
+#[derive(Debug, Serialize)]
+struct MetricMetaKafkaMessage {
+    /// The organization id of the project.
+    org_id: u64,
+    /// The project id the metadata belongs to.
+    project_id: ProjectId,
+    /// The MRI of the metric the locations belong to.
+    mri: String,
+    /// The start of the day the locations were seen.
+    timestamp: UnixTimestamp,
+    /// Number of days to retain.
+    retention_days: u16,
+    /// Code locations the metric was emitted from.
+    locations: Vec<Location>,
+}
+
 /// An enum over all possible ingest messages.
 #[derive(Debug, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -1048,6 +1727,8 @@ enum KafkaMessage {
     Event(EventKafkaMessage),
     Attachment(AttachmentKafkaMessage),
     AttachmentChunk(AttachmentChunkKafkaMessage),
+    TraceAttachment(TraceAttachmentKafkaMessage),
+    TraceAttachmentChunk(TraceAttachmentChunkKafkaMessage),
     UserReport(UserReportKafkaMessage),
     Session(SessionKafkaMessage),
     Metric {
@@ -1057,10 +1738,123 @@ enum KafkaMessage {
         message: MetricKafkaMessage,
     },
     Profile(ProfileKafkaMessage),
+    ProfileChunk(ProfileChunkKafkaMessage),
     ReplayEvent(ReplayEventKafkaMessage),
     ReplayRecordingNotChunked(ReplayRecordingNotChunkedKafkaMessage),
+    ReplayVideo(ReplayVideoKafkaMessage),
     CheckIn(CheckInKafkaMessage),
     Span(SpanKafkaMessage),
+    Log(LogKafkaMessage),
+    Feedback(FeedbackKafkaMessage),
+    MetricMeta(MetricMetaKafkaMessage),
+}
+
+impl KafkaMessage {
+    /// Returns the project ID of the message.
+    ///
+    /// Returns `None` for metric buckets that have been merged across projects.
+    fn project_id(&self) -> Option<ProjectId> {
+        Some(match self {
+            KafkaMessage::Event(message) => message.project_id,
+            KafkaMessage::Attachment(message) => message.project_id,
+            KafkaMessage::AttachmentChunk(message) => message.project_id,
+            KafkaMessage::TraceAttachment(message) => message.project_id,
+            KafkaMessage::TraceAttachmentChunk(message) => message.project_id,
+            KafkaMessage::UserReport(message) => message.project_id,
+            KafkaMessage::Session(message) => message.project_id,
+            KafkaMessage::Metric { message, .. } => return message.project_id,
+            KafkaMessage::Profile(message) => message.project_id,
+            KafkaMessage::ProfileChunk(message) => message.project_id,
+            KafkaMessage::ReplayEvent(message) => message.project_id,
+            KafkaMessage::ReplayRecordingNotChunked(message) => message.project_id,
+            KafkaMessage::ReplayVideo(message) => message.project_id,
+            KafkaMessage::CheckIn(message) => message.project_id,
+            KafkaMessage::Span(message) => message.project_id,
+            KafkaMessage::Log(message) => message.project_id,
+            KafkaMessage::Feedback(message) => message.project_id,
+            KafkaMessage::MetricMeta(message) => message.project_id,
+        })
+    }
+
+    /// Returns the platform of events, if set in the payload.
+    fn platform(&self) -> Option<String> {
+        #[derive(Deserialize)]
+        struct EventPlatform {
+            platform: Option<String>,
+        }
+
+        let KafkaMessage::Event(message) = self else {
+            return None;
+        };
+
+        serde_json::from_slice::<EventPlatform>(&message.payload)
+            .ok()?
+            .platform
+    }
+
+    /// Returns the trace ID of events and spans, if set in the payload.
+    fn trace_id(&self) -> Option<Uuid> {
+        #[derive(Deserialize)]
+        struct TraceContext {
+            trace_id: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct Contexts {
+            trace: Option<TraceContext>,
+        }
+
+        #[derive(Deserialize)]
+        struct EventContexts {
+            contexts: Option<Contexts>,
+        }
+
+        let trace_id = match self {
+            KafkaMessage::Event(message) => {
+                serde_json::from_slice::<EventContexts>(&message.payload)
+                    .ok()?
+                    .contexts?
+                    .trace?
+                    .trace_id?
+            }
+            KafkaMessage::Span(message) => message.span.get("trace_id")?.as_str()?.to_owned(),
+            KafkaMessage::TraceAttachment(message) => return Some(message.trace_id.0),
+            KafkaMessage::TraceAttachmentChunk(message) => return Some(message.trace_id.0),
+            _ => return None,
+        };
+
+        Uuid::parse_str(&trace_id).ok()
+    }
+
+    /// Returns the data category of the message.
+    ///
+    /// Events are produced to the transactions topic for transactions and to the events topic for
+    /// all other event types.
+    fn data_category(&self, topic: KafkaTopic) -> DataCategory {
+        match self {
+            KafkaMessage::Event(_) if topic == KafkaTopic::Transactions => {
+                DataCategory::Transaction
+            }
+            KafkaMessage::Event(_) => DataCategory::Error,
+            KafkaMessage::Attachment(_) => DataCategory::Attachment,
+            KafkaMessage::AttachmentChunk(_) => DataCategory::Attachment,
+            KafkaMessage::TraceAttachment(_) => DataCategory::Attachment,
+            KafkaMessage::TraceAttachmentChunk(_) => DataCategory::Attachment,
+            KafkaMessage::UserReport(_) => DataCategory::Default,
+            KafkaMessage::Session(_) => DataCategory::Session,
+            KafkaMessage::Metric { .. } => DataCategory::MetricBucket,
+            KafkaMessage::Profile(_) => DataCategory::Profile,
+            KafkaMessage::ProfileChunk(_) => DataCategory::ProfileDuration,
+            KafkaMessage::ReplayEvent(_) => DataCategory::Replay,
+            KafkaMessage::ReplayRecordingNotChunked(_) => DataCategory::Replay,
+            KafkaMessage::ReplayVideo(_) => DataCategory::Replay,
+            KafkaMessage::CheckIn(_) => DataCategory::Monitor,
+            KafkaMessage::Span(_) => DataCategory::Span,
+            KafkaMessage::Log(_) => DataCategory::LogByte,
+            KafkaMessage::Feedback(_) => DataCategory::Feedback,
+            KafkaMessage::MetricMeta(_) => DataCategory::MetricBucket,
+        }
+    }
 }
 
 impl Message for KafkaMessage {
@@ -1069,14 +1863,21 @@ impl Message for KafkaMessage {
             KafkaMessage::Event(_) => "event",
             KafkaMessage::Attachment(_) => "attachment",
             KafkaMessage::AttachmentChunk(_) => "attachment_chunk",
+            KafkaMessage::TraceAttachment(_) => "trace_attachment",
+            KafkaMessage::TraceAttachmentChunk(_) => "trace_attachment_chunk",
             KafkaMessage::UserReport(_) => "user_report",
             KafkaMessage::Session(_) => "session",
             KafkaMessage::Metric { .. } => "metric",
             KafkaMessage::Profile(_) => "profile",
+            KafkaMessage::ProfileChunk(_) => "profile_chunk",
             KafkaMessage::ReplayEvent(_) => "replay_event",
             KafkaMessage::ReplayRecordingNotChunked(_) => "replay_recording_not_chunked",
+            KafkaMessage::ReplayVideo(_) => "replay_video",
             KafkaMessage::CheckIn(_) => "check_in",
             KafkaMessage::Span(_) => "span",
+            KafkaMessage::Log(_) => "log",
+            KafkaMessage::Feedback(_) => "feedback",
+            KafkaMessage::MetricMeta(_) => "metric_meta",
         }
     }
 
@@ -1086,14 +1887,21 @@ impl Message for KafkaMessage {
             Self::Event(message) => message.event_id.0,
             Self::Attachment(message) => message.event_id.0,
             Self::AttachmentChunk(message) => message.event_id.0,
+            Self::TraceAttachment(message) => message.trace_id.0,
+            Self::TraceAttachmentChunk(message) => message.trace_id.0,
             Self::UserReport(message) => message.event_id.0,
             Self::Session(_message) => Uuid::nil(), // Explicit random partitioning for sessions
             Self::Metric { .. } => Uuid::nil(),     // TODO(ja): Determine a partitioning key
             Self::Profile(_message) => Uuid::nil(),
+            Self::ProfileChunk(_message) => Uuid::nil(),
             Self::ReplayEvent(message) => message.replay_id.0,
             Self::ReplayRecordingNotChunked(_message) => Uuid::nil(), // Ensure random partitioning.
+            Self::ReplayVideo(message) => message.replay_id.0,
             Self::CheckIn(_message) => Uuid::nil(),
             Self::Span(_) => Uuid::nil(), // random partitioning
+            Self::Log(_) => Uuid::nil(),
+            Self::Feedback(_) => Uuid::nil(),
+            Self::MetricMeta(_) => Uuid::nil(),
         };
 
         if uuid.is_nil() {
@@ -1103,6 +1911,21 @@ impl Message for KafkaMessage {
         *uuid.as_bytes()
     }
 
+    fn partition_key(&self, strategy: PartitionStrategy) -> Option<[u8; 16]> {
+        match strategy {
+            PartitionStrategy::Message => Some(self.key()),
+            PartitionStrategy::Project => match self.project_id() {
+                Some(project_id) => Some(*Uuid::from_u64_pair(project_id.value(), 0).as_bytes()),
+                None => Some(self.key()),
+            },
+            PartitionStrategy::TraceId => match self.trace_id() {
+                Some(trace_id) => Some(*trace_id.as_bytes()),
+                None => Some(self.key()),
+            },
+            PartitionStrategy::RoundRobin | PartitionStrategy::Sticky => None,
+        }
+    }
+
     fn headers(&self) -> Option<&BTreeMap<String, String>> {
         if let KafkaMessage::Metric { headers, .. } = &self {
             if !headers.is_empty() {
@@ -1112,6 +1935,17 @@ impl Message for KafkaMessage {
         None
     }
 
+    /// Converts the message into an Avro value, analogous to [`serialize`](Self::serialize).
+    fn avro_value(&self) -> Result<AvroValue, ClientError> {
+        match self {
+            KafkaMessage::Session(message) => to_avro_value(message),
+            KafkaMessage::Metric { message, .. } => to_avro_value(message),
+            KafkaMessage::ReplayEvent(message) => to_avro_value(message),
+            KafkaMessage::MetricMeta(message) => to_avro_value(message),
+            _ => to_avro_value(self),
+        }
+    }
+
     /// Serializes the message into its binary format.
     fn serialize(&self) -> Result<Vec<u8>, ClientError> {
         match self {
@@ -1124,11 +1958,92 @@ impl Message for KafkaMessage {
             KafkaMessage::ReplayEvent(message) => {
                 serde_json::to_vec(message).map_err(ClientError::InvalidJson)
             }
+            KafkaMessage::MetricMeta(message) => {
+                serde_json::to_vec(message).map_err(ClientError::InvalidJson)
+            }
             _ => rmp_serde::to_vec_named(&self).map_err(ClientError::InvalidMsgPack),
         }
     }
 }
 
+/// Attributes of a [`KafkaMessage`] that topic routes match on.
+///
+/// The platform and size are computed on first access, since they require parsing or serializing
+/// the message.
+struct RoutingContext<'a> {
+    route: &'a SinkRoute,
+    message: &'a KafkaMessage,
+    platform: OnceCell<Option<String>>,
+    size: OnceCell<Option<u64>>,
+}
+
+impl<'a> RoutingContext<'a> {
+    fn new(route: &'a SinkRoute, message: &'a KafkaMessage) -> Self {
+        Self {
+            route,
+            message,
+            platform: OnceCell::new(),
+            size: OnceCell::new(),
+        }
+    }
+}
+
+impl Getter for RoutingContext<'_> {
+    fn get_value(&self, path: &str) -> Option<Val<'_>> {
+        Some(match path {
+            "org_id" => Val::U64(self.route.organization_id),
+            "project_id" => Val::U64(self.route.project_id?.value()),
+            "category" => Val::String(self.route.category.name()),
+            "platform" => {
+                let platform = self.platform.get_or_init(|| self.message.platform());
+                Val::String(platform.as_deref()?)
+            }
+            "size" => {
+                let size = self.size.get_or_init(|| {
+                    let serialized = self.message.serialize().ok()?;
+                    Some(serialized.len() as u64)
+                });
+                Val::U64((*size)?)
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// A [`KafkaMessage`] with additional routing headers.
+///
+/// The headers replace the message's own headers, so they must include them.
+struct RoutedKafkaMessage<'a> {
+    message: &'a KafkaMessage,
+    headers: BTreeMap<String, String>,
+}
+
+impl Message for RoutedKafkaMessage<'_> {
+    fn key(&self) -> [u8; 16] {
+        self.message.key()
+    }
+
+    fn partition_key(&self, strategy: PartitionStrategy) -> Option<[u8; 16]> {
+        self.message.partition_key(strategy)
+    }
+
+    fn variant(&self) -> &'static str {
+        self.message.variant()
+    }
+
+    fn headers(&self) -> Option<&BTreeMap<String, String>> {
+        Some(&self.headers)
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, ClientError> {
+        self.message.serialize()
+    }
+
+    fn avro_value(&self) -> Result<AvroValue, ClientError> {
+        self.message.avro_value()
+    }
+}
+
 /// Determines if the given item is considered slow.
 ///
 /// Slow items must be routed to the `Attachments` topic.
@@ -1139,6 +2054,7 @@ fn is_slow_item(item: &Item) -> bool {
 #[cfg(test)]
 mod tests {
     use relay_base_schema::project::ProjectKey;
+    use relay_sampling::condition::RuleCondition;
 
     use super::*;
 
@@ -1233,6 +2149,125 @@ mod tests {
         assert!(standalone_attachments.len() == number_of_attachments);
     }
 
+    #[test]
+    fn test_routing_metadata() {
+        let (start_time, event_id, scoping, attachment_vec) = arguments_extract_kafka_msgs();
+
+        let item = Item::new(ItemType::Transaction);
+        let kafka_messages: Vec<_> = StoreService::extract_kafka_messages_for_event(
+            Some(&item),
+            event_id,
+            scoping,
+            start_time,
+            None,
+            attachment_vec,
+        )
+        .collect();
+
+        for message in &kafka_messages {
+            assert_eq!(message.project_id(), Some(scoping.project_id));
+        }
+        assert_eq!(
+            kafka_messages[0].data_category(KafkaTopic::Attachments),
+            DataCategory::Attachment
+        );
+
+        let event = kafka_messages
+            .iter()
+            .find(|message| matches!(message, KafkaMessage::Event(_)))
+            .unwrap();
+        assert_eq!(
+            event.data_category(KafkaTopic::Transactions),
+            DataCategory::Transaction
+        );
+        assert_eq!(event.data_category(KafkaTopic::Events), DataCategory::Error);
+    }
+
+    #[test]
+    fn test_routing_context() {
+        let message = KafkaMessage::Event(EventKafkaMessage {
+            payload: Bytes::from_static(br#"{"platform":"python"}"#),
+            start_time: 0,
+            event_id: EventId::new(),
+            project_id: ProjectId::new(21),
+            remote_addr: None,
+            attachments: Vec::new(),
+        });
+
+        let route = SinkRoute {
+            topic: KafkaTopic::Events,
+            organization_id: 1500,
+            project_id: Some(ProjectId::new(21)),
+            category: DataCategory::Error,
+            kafka_route: None,
+        };
+
+        let condition: RuleCondition = serde_json::from_value(serde_json::json!({
+            "op": "and",
+            "inner": [
+                {"op": "range", "name": "org_id", "gte": 1000, "lt": 2000},
+                {"op": "glob", "name": "platform", "value": ["python"]},
+                {"op": "eq", "name": "category", "value": "error"},
+                {"op": "lt", "name": "size", "value": 1000},
+            ]
+        }))
+        .unwrap();
+
+        let context = RoutingContext::new(&route, &message);
+        assert!(condition.matches(&context));
+
+        let route = SinkRoute {
+            organization_id: 2000,
+            ..route
+        };
+        let context = RoutingContext::new(&route, &message);
+        assert!(!condition.matches(&context));
+    }
+
+    #[test]
+    fn test_partition_key() {
+        let event = |payload: &'static [u8], project_id| {
+            KafkaMessage::Event(EventKafkaMessage {
+                payload: Bytes::from_static(payload),
+                start_time: 0,
+                event_id: EventId::new(),
+                project_id: ProjectId::new(project_id),
+                remote_addr: None,
+                attachments: Vec::new(),
+            })
+        };
+
+        let traced = br#"{"contexts":{"trace":{"trace_id":"4c79f60c11214eb38604f4ae0781bfb2"}}}"#;
+        let trace_id = Uuid::parse_str("4c79f60c11214eb38604f4ae0781bfb2").unwrap();
+
+        let message = event(traced, 21);
+        assert_eq!(
+            message.partition_key(PartitionStrategy::Message),
+            Some(message.key())
+        );
+        assert_eq!(
+            message.partition_key(PartitionStrategy::TraceId),
+            Some(*trace_id.as_bytes())
+        );
+        assert_eq!(
+            message.partition_key(PartitionStrategy::Project),
+            event(b"{}", 21).partition_key(PartitionStrategy::Project)
+        );
+        assert_ne!(
+            message.partition_key(PartitionStrategy::Project),
+            event(b"{}", 22).partition_key(PartitionStrategy::Project)
+        );
+        assert_eq!(message.partition_key(PartitionStrategy::RoundRobin), None);
+        assert_eq!(message.partition_key(PartitionStrategy::Sticky), None);
+
+        // Messages without a trace fall back to their message key.
+        let message = event(b"{}", 21);
+        assert_eq!(
+            message.partition_key(PartitionStrategy::TraceId),
+            Some(message.key())
+        );
+    }
+
     /// If there is an event_item, and it is not a transaction. The attachments should be kept in
     /// the event and not be returned as stand-alone attachments.
     #[test]
@@ -1271,4 +2306,73 @@ mod tests {
             panic!("No event found")
         }
     }
+
+    #[test]
+    fn test_merge_org_buckets() {
+        let namespaces = BTreeSet::from([MetricNamespace::Custom]);
+        let bucket = |name: &str, value| Bucket {
+            timestamp: UnixTimestamp::from_secs(1000),
+            width: 10,
+            name: name.to_owned(),
+            value,
+            tags: BTreeMap::new(),
+        };
+
+        let mut org_buckets = OrgBuckets::default();
+        for _ in 0..3 {
+            let counter = bucket("c:custom/foo@none", BucketValue::counter(1.0));
+            assert!(org_buckets.merge(&namespaces, 1, 90, counter).is_none());
+        }
+
+        // Other organizations, namespaces, and types are not merged.
+        let counter = bucket("c:custom/foo@none", BucketValue::counter(1.0));
+        assert!(org_buckets.merge(&namespaces, 2, 90, counter).is_none());
+        let counter = bucket("c:transactions/foo@none", BucketValue::counter(1.0));
+        assert!(org_buckets.merge(&namespaces, 1, 90, counter).is_some());
+        let set = bucket("s:custom/foo@none", BucketValue::set(1));
+        assert!(org_buckets.merge(&namespaces, 1, 90, set).is_some());
+
+        let mut merged: Vec<_> = org_buckets
+            .take()
+            .into_iter()
+            .map(|(key, value)| (key.org_id, value))
+            .collect();
+        merged.sort_by_key(|(org_id, _)| *org_id);
+        assert_eq!(
+            merged,
+            vec![
+                (1, BucketValue::counter(3.0)),
+                (2, BucketValue::counter(1.0))
+            ]
+        );
+        assert!(org_buckets.take().is_empty());
+    }
+
+    #[test]
+    fn test_requeue_org_buckets() {
+        let namespaces = BTreeSet::from([MetricNamespace::Custom]);
+        let counter = || Bucket {
+            timestamp: UnixTimestamp::from_secs(1000),
+            width: 10,
+            name: "c:custom/foo@none".to_owned(),
+            value: BucketValue::counter(1.0),
+            tags: BTreeMap::new(),
+        };
+
+        let mut org_buckets = OrgBuckets::default();
+        assert!(org_buckets.merge(&namespaces, 1, 90, counter()).is_none());
+
+        // A failed bucket is merged with buckets that arrived in the meanwhile.
+        let (key, value) = org_buckets.take().into_iter().next().unwrap();
+        assert!(org_buckets.merge(&namespaces, 1, 90, counter()).is_none());
+        org_buckets.requeue(key, value);
+
+        let merged: Vec<_> = org_buckets.take().into_values().collect();
+        assert_eq!(merged, vec![BucketValue::counter(2.0)]);
+
+        // After closing, buckets are no longer merged.
+        org_buckets.close();
+        assert!(org_buckets.merge(&namespaces, 1, 90, counter()).is_some());
+        assert!(org_buckets.take().is_empty());
+    }
 }
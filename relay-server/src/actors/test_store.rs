@@ -1,13 +1,24 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::Arc;
 
 use relay_config::{Config, RelayMode};
 use relay_event_schema::protocol::EventId;
+use relay_protocol::Value;
 use relay_system::{AsyncResponse, FromMessage, NoResponse, Sender};
+use serde::{Deserialize, Serialize};
 
 use crate::actors::outcome::Outcome;
 use crate::envelope::Envelope;
 
+/// Envelope header that requests tracking of the envelope's result.
+///
+/// Set to `true` by `relay send-event`. The result can be retrieved with [`GetTrackedResult`].
+/// Results are only tracked if the admin API is enabled, since they are only served through it.
+pub const TRACK_RESULT_HEADER: &str = "track_result";
+
+/// The maximum number of tracked results kept in memory.
+const MAX_TRACKED_RESULTS: usize = 1000;
+
 /// Either a captured envelope or an error that occured during processing.
 pub type CapturedEnvelope = Result<Box<Envelope>, String>;
 
@@ -47,6 +58,60 @@ impl Capture {
     }
 }
 
+/// The result of processing a tracked envelope.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TrackedResult {
+    /// The envelope was sent to the upstream or stored.
+    Accepted,
+    /// The envelope was dropped.
+    Rejected {
+        /// Description of the outcome.
+        reason: String,
+    },
+}
+
+/// Records the result of an envelope with the [`TRACK_RESULT_HEADER`].
+#[derive(Debug)]
+pub struct TrackResult {
+    event_id: EventId,
+    result: TrackedResult,
+}
+
+impl TrackResult {
+    /// Returns `true` if the envelope requests tracking of its result.
+    ///
+    /// Only envelopes with an event ID can be tracked.
+    pub fn is_tracked(envelope: &Envelope) -> bool {
+        envelope.event_id().is_some()
+            && envelope.get_header(TRACK_RESULT_HEADER) == Some(&Value::Bool(true))
+    }
+
+    /// Tracks that the envelope was sent to the upstream or stored.
+    pub fn accepted(event_id: EventId) -> Self {
+        Self {
+            event_id,
+            result: TrackedResult::Accepted,
+        }
+    }
+
+    /// Tracks the outcome that led to envelope rejection.
+    pub fn rejected(event_id: EventId, outcome: &Outcome) -> Self {
+        Self {
+            event_id,
+            result: TrackedResult::Rejected {
+                reason: outcome.to_string(),
+            },
+        }
+    }
+}
+
+/// Resolves the [`TrackedResult`] by the given `event_id`.
+#[derive(Debug)]
+pub struct GetTrackedResult {
+    pub event_id: EventId,
+}
+
 /// Resolves a [`CapturedEnvelope`] by the given `event_id`.
 #[derive(Debug)]
 pub struct GetCapturedEnvelope {
@@ -58,6 +123,8 @@ pub struct GetCapturedEnvelope {
 pub enum TestStore {
     Capture(Box<Capture>),
     Get(GetCapturedEnvelope, Sender<Option<CapturedEnvelope>>),
+    Track(TrackResult),
+    GetTracked(GetTrackedResult, Sender<Option<TrackedResult>>),
 }
 
 impl relay_system::Interface for TestStore {}
@@ -81,10 +148,29 @@ impl FromMessage<GetCapturedEnvelope> for TestStore {
     }
 }
 
+impl FromMessage<TrackResult> for TestStore {
+    type Response = NoResponse;
+
+    fn from_message(message: TrackResult, _: ()) -> Self {
+        Self::Track(message)
+    }
+}
+
+impl FromMessage<GetTrackedResult> for TestStore {
+    type Response = AsyncResponse<Option<TrackedResult>>;
+
+    fn from_message(message: GetTrackedResult, sender: Sender<Option<TrackedResult>>) -> Self {
+        Self::GetTracked(message, sender)
+    }
+}
+
 /// Service implementing the [`TestStore`] interface.
 pub struct TestStoreService {
     config: Arc<Config>,
     captures: BTreeMap<EventId, CapturedEnvelope>,
+    tracked: BTreeMap<EventId, TrackedResult>,
+    /// Tracked event IDs in insertion order, used to evict the oldest results.
+    tracked_order: VecDeque<EventId>,
 }
 
 impl TestStoreService {
@@ -92,6 +178,8 @@ impl TestStoreService {
         Self {
             config,
             captures: BTreeMap::new(),
+            tracked: BTreeMap::new(),
+            tracked_order: VecDeque::new(),
         }
     }
 
@@ -118,10 +206,39 @@ impl TestStoreService {
         self.captures.get(&message.event_id).cloned()
     }
 
+    fn track(&mut self, message: TrackResult) {
+        if !self.config.admin_enabled() {
+            return;
+        }
+
+        relay_log::debug!(event_id = %message.event_id, "tracking envelope result");
+
+        // The first result wins. Envelopes can be rejected after acceptance, for example if only
+        // a part of the envelope is dropped while the rest is sent.
+        if self.tracked.contains_key(&message.event_id) {
+            return;
+        }
+
+        if self.tracked_order.len() >= MAX_TRACKED_RESULTS {
+            if let Some(oldest) = self.tracked_order.pop_front() {
+                self.tracked.remove(&oldest);
+            }
+        }
+
+        self.tracked_order.push_back(message.event_id);
+        self.tracked.insert(message.event_id, message.result);
+    }
+
+    fn get_tracked(&self, message: GetTrackedResult) -> Option<TrackedResult> {
+        self.tracked.get(&message.event_id).cloned()
+    }
+
     fn handle_message(&mut self, message: TestStore) {
         match message {
             TestStore::Capture(message) => self.capture(*message),
             TestStore::Get(message, sender) => sender.send(self.get(message)),
+            TestStore::Track(message) => self.track(message),
+            TestStore::GetTracked(message, sender) => sender.send(self.get_tracked(message)),
         }
     }
 }
@@ -137,3 +254,27 @@ impl relay_system::Service for TestStoreService {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track_and_get(config: Config) -> Option<TrackedResult> {
+        let mut service = TestStoreService::new(Arc::new(config));
+        let event_id = EventId::new();
+
+        service.track(TrackResult::accepted(event_id));
+        service.get_tracked(GetTrackedResult { event_id })
+    }
+
+    #[test]
+    fn test_track_requires_admin() {
+        assert_eq!(track_and_get(Config::default()), None);
+
+        let config = Config::from_json_value(serde_json::json!({
+            "admin": {"token": "secret"}
+        }))
+        .unwrap();
+        assert_eq!(track_and_get(config), Some(TrackedResult::Accepted));
+    }
+}
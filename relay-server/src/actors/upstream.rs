@@ -5,21 +5,31 @@
 //! service-level docs for more information.
 
 use std::borrow::Cow;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::fmt;
 use std::future::Future;
+use std::net::SocketAddr;
+use std::ops::Range;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use bytes::Bytes;
+use hyper::client::connect::HttpInfo;
 use itertools::Itertools;
-use relay_auth::{RegisterChallenge, RegisterRequest, RegisterResponse, Registration};
-use relay_config::{Config, Credentials, RelayMode};
+use rand::Rng;
+use relay_auth::{
+    RegisterChallenge, RegisterRequest, RegisterResponse, Registration, SignatureHeader,
+};
+use relay_config::{Config, Credentials, HttpEncoding, LoadBalancing, UpstreamDescriptor};
 use relay_quotas::{
     DataCategories, QuotaScope, RateLimit, RateLimitScope, RateLimits, ReasonCode, RetryAfter,
     Scoping,
 };
+use relay_statsd::metric;
 use relay_system::{
-    AsyncResponse, FromMessage, Interface, MessageResponse, NoResponse, Sender, Service,
+    Addr, AsyncResponse, FromMessage, Interface, MessageResponse, NoResponse, Sender, Service,
 };
 use reqwest::header;
 pub use reqwest::Method;
@@ -28,8 +38,8 @@ use serde::Serialize;
 use tokio::sync::mpsc;
 use tokio::time::Instant;
 
-use crate::http::{HttpError, Request, RequestBuilder, Response, StatusCode};
-use crate::statsd::{RelayHistograms, RelayTimers};
+use crate::http::{self, HttpError, Request, RequestBuilder, Response, StatusCode};
+use crate::statsd::{RelayCounters, RelayGauges, RelayHistograms, RelayTimers};
 use crate::utils::{self, ApiErrorResponse, RelayErrorAction, RetryBackoff};
 
 /// Rate limits returned by the upstream.
@@ -88,6 +98,8 @@ impl UpstreamRateLimits {
                 scope: RateLimitScope::for_quota(scoping, QuotaScope::Key),
                 reason_code: Some(ReasonCode::new("generic")),
                 retry_after: self.retry_after,
+                attribute: None,
+                namespace: None,
             });
         }
         rate_limits
@@ -119,6 +131,10 @@ pub enum UpstreamRequestError {
 
     #[error("upstream permanently denied authentication")]
     AuthDenied,
+
+    /// The request was not sent because the upstream returned too many server errors.
+    #[error("upstream circuit breaker is open")]
+    CircuitOpen,
 }
 
 impl UpstreamRequestError {
@@ -145,6 +161,24 @@ impl UpstreamRequestError {
         }
     }
 
+    /// Returns `true` if the request should be retried according to the configured status codes.
+    ///
+    /// This includes all connection errors and responses with one of the given status codes.
+    fn is_retryable(&self, status_codes: &[u16]) -> bool {
+        match self {
+            Self::SendFailed(_) => true,
+            Self::ResponseError(code, _) => status_codes.contains(&code.as_u16()),
+            Self::Http(http) => http.is_network_error(),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the upstream responded with a 5xx status code.
+    fn is_server_error(&self) -> bool {
+        self.status_code()
+            .map_or(false, |code| code.is_server_error())
+    }
+
     /// Returns `true` if the upstream has permanently rejected this Relay.
     ///
     /// This Relay should cease communication with the upstream and may shut down.
@@ -170,9 +204,11 @@ impl UpstreamRequestError {
             // Everything except network errors indicates the upstream has handled this request.
             Self::ResponseError(_, _) | Self::Http(_) => !self.is_network_error(),
             // Remaining kinds indicate a failure to send the request.
-            Self::NoCredentials | Self::SendFailed(_) | Self::ChannelClosed | Self::AuthDenied => {
-                false
-            }
+            Self::NoCredentials
+            | Self::SendFailed(_)
+            | Self::ChannelClosed
+            | Self::AuthDenied
+            | Self::CircuitOpen => false,
         }
     }
 
@@ -192,6 +228,7 @@ impl UpstreamRequestError {
             UpstreamRequestError::ResponseError(_, _) => "response_error",
             UpstreamRequestError::ChannelClosed => "channel_closed",
             UpstreamRequestError::AuthDenied => "auth_denied",
+            UpstreamRequestError::CircuitOpen => "circuit_open",
         }
     }
 }
@@ -305,6 +342,32 @@ pub trait UpstreamRequest: Send + Sync + fmt::Debug {
         true
     }
 
+    /// Whether this request can be sent to any of the configured upstreams.
+    ///
+    /// Stateless requests are distributed across upstreams if `http.load_balancing` is enabled.
+    /// All other requests are sent to the active upstream, which also holds the registration of
+    /// this Relay.
+    ///
+    /// Defaults to `false`.
+    fn stateless(&self) -> bool {
+        false
+    }
+
+    /// Index of the route in `relay.routes` that this request is sent to.
+    ///
+    /// Use [`Config::upstream_route`] to determine the route of a project. Defaults to `None`,
+    /// which sends the request to the default upstream.
+    fn upstream_route(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether this request carries outcomes or client reports.
+    ///
+    /// These requests are sent to `outcomes.upstream` if it is configured. Defaults to `false`.
+    fn outcomes(&self) -> bool {
+        false
+    }
+
     /// Returns the name of the logical route.
     ///
     /// This is used for internal metrics and logging. Other than the path, this cannot contain
@@ -373,6 +436,27 @@ pub trait UpstreamQuery: Serialize + Send + Sync + fmt::Debug {
         RequestPriority::Low
     }
 
+    /// Whether this query can be sent to any of the configured upstreams.
+    ///
+    /// See [`UpstreamRequest::stateless`]. Defaults to `false`.
+    fn stateless() -> bool {
+        false
+    }
+
+    /// Index of the route in `relay.routes` that this query is sent to.
+    ///
+    /// See [`UpstreamRequest::upstream_route`]. Defaults to `None`.
+    fn upstream_route(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether this query carries outcomes.
+    ///
+    /// See [`UpstreamRequest::outcomes`]. Defaults to `false`.
+    fn outcomes() -> bool {
+        false
+    }
+
     /// Returns the name of the logical route.
     ///
     /// This is used for internal metrics and logging. Other than the path, this cannot contain
@@ -390,7 +474,7 @@ type QuerySender<T> = Sender<Result<<T as UpstreamQuery>::Response, UpstreamRequ
 #[derive(Debug)]
 struct UpstreamQueryRequest<T: UpstreamQuery> {
     query: T,
-    compiled: Option<(Vec<u8>, String)>,
+    compiled: Option<(Bytes, String)>,
     max_response_size: usize,
     sender: QuerySender<T>,
 }
@@ -430,6 +514,18 @@ where
         true
     }
 
+    fn stateless(&self) -> bool {
+        T::stateless()
+    }
+
+    fn upstream_route(&self) -> Option<usize> {
+        self.query.upstream_route()
+    }
+
+    fn outcomes(&self) -> bool {
+        T::outcomes()
+    }
+
     fn method(&self) -> Method {
         self.query.method()
     }
@@ -445,9 +541,13 @@ where
     fn build(&mut self, config: &Config, builder: RequestBuilder) -> Result<Request, HttpError> {
         // Memoize the serialized body and signature for retries.
         let credentials = config.credentials().ok_or(HttpError::NoCredentials)?;
-        let (body, signature) = self
-            .compiled
-            .get_or_insert_with(|| credentials.secret_key.pack(&self.query));
+        let (body, signature) = self.compiled.get_or_insert_with(|| {
+            let header = SignatureHeader::with_algorithm(config.signature_algorithm());
+            let (body, signature) = credentials
+                .secret_key
+                .pack_with_header(&self.query, &header);
+            (Bytes::from(body), signature)
+        });
 
         // This config attribute is needed during `respond`, which does not have access to the
         // config. For this reason, we need to store it on the request struct.
@@ -460,7 +560,7 @@ where
         builder
             .header("X-Sentry-Relay-Signature", signature.as_bytes())
             .header(header::CONTENT_TYPE, b"application/json")
-            .body(&body)
+            .body(body.clone())
     }
 
     fn respond(
@@ -545,6 +645,14 @@ where
     }
 }
 
+impl FromMessage<Box<dyn UpstreamRequest>> for UpstreamRelay {
+    type Response = NoResponse;
+
+    fn from_message(message: Box<dyn UpstreamRequest>, _: ()) -> Self {
+        Self::SendRequest(message)
+    }
+}
+
 impl<T> FromMessage<SendQuery<T>> for UpstreamRelay
 where
     T: UpstreamQuery + 'static,
@@ -692,6 +800,408 @@ impl UpstreamQuery for RegisterResponse {
     }
 }
 
+/// Weight of a new latency measurement in the moving average of an upstream's response time.
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Lower bound for response times used in latency-weighted load balancing, in seconds.
+const MIN_LATENCY: f64 = 0.001;
+
+/// Response time and health of an upstream used for load balancing.
+#[derive(Clone, Copy, Debug, Default)]
+struct UpstreamStats {
+    /// Exponentially weighted moving average of response times in seconds.
+    latency: Option<f64>,
+    /// Time until which the upstream receives no stateless requests after a network error.
+    unhealthy_until: Option<Instant>,
+}
+
+impl UpstreamStats {
+    fn is_healthy(&self, now: Instant) -> bool {
+        !matches!(self.unhealthy_until, Some(until) if until > now)
+    }
+}
+
+/// Keeps track of the upstream that requests are sent to.
+///
+/// Upstreams are kept in the order of priority configured in `relay.upstream`. Requests go to the
+/// active upstream, which changes when Relay fails over to a fallback upstream during a network
+/// outage, and when it fails back to an upstream with a higher priority.
+///
+/// If load balancing is enabled, stateless requests are distributed across all reachable
+/// upstreams instead.
+#[derive(Debug)]
+struct UpstreamSelector {
+    upstreams: Vec<UpstreamDescriptor<'static>>,
+    active: AtomicUsize,
+    balancing: LoadBalancing,
+    unhealthy_timeout: Duration,
+    next: AtomicUsize,
+    stats: Mutex<Vec<UpstreamStats>>,
+}
+
+impl UpstreamSelector {
+    /// Creates a new selector with the primary upstream active.
+    pub fn new(config: &Config) -> Self {
+        let upstreams = config.upstreams().as_slice().to_vec();
+        let stats = vec![UpstreamStats::default(); upstreams.len()];
+
+        Self {
+            upstreams,
+            active: AtomicUsize::new(0),
+            balancing: config.http_load_balancing(),
+            unhealthy_timeout: config.http_failback_interval(),
+            next: AtomicUsize::new(0),
+            stats: Mutex::new(stats),
+        }
+    }
+
+    /// Returns the index of the upstream to send a request to.
+    ///
+    /// Requests that are not stateless always go to the active upstream.
+    pub fn select(&self, stateless: bool) -> usize {
+        let active = self.active_index();
+        if !stateless || self.count() == 1 {
+            return active;
+        }
+
+        let now = Instant::now();
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+
+        let selected = match self.balancing {
+            LoadBalancing::Disabled => None,
+            LoadBalancing::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..self.count())
+                    .map(|offset| (start + offset) % self.count())
+                    .find(|&index| stats[index].is_healthy(now))
+            }
+            LoadBalancing::Latency => {
+                // Upstreams without measurements are weighted like the fastest known upstream, so
+                // that they receive traffic and get measured.
+                let fastest = stats
+                    .iter()
+                    .filter_map(|stats| stats.latency)
+                    .fold(f64::INFINITY, f64::min);
+
+                let weights = stats.iter().map(|stats| {
+                    if !stats.is_healthy(now) {
+                        return 0.0;
+                    }
+
+                    match stats.latency {
+                        Some(latency) => 1.0 / latency.max(MIN_LATENCY),
+                        None if fastest.is_finite() => 1.0 / fastest.max(MIN_LATENCY),
+                        None => 1.0,
+                    }
+                });
+
+                pick_weighted(weights, rand::thread_rng().gen())
+            }
+        };
+
+        selected.unwrap_or(active)
+    }
+
+    /// Records the result of a stateless request for load balancing.
+    ///
+    /// Upstreams with network errors receive no stateless requests for the failback interval.
+    pub fn record(&self, index: usize, network_error: bool, latency: Duration) {
+        if self.balancing == LoadBalancing::Disabled {
+            return;
+        }
+
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = &mut stats[index];
+
+        if network_error {
+            stats.unhealthy_until = Some(Instant::now() + self.unhealthy_timeout);
+        } else {
+            let latency = latency.as_secs_f64();
+            stats.unhealthy_until = None;
+            stats.latency = Some(match stats.latency {
+                Some(previous) => previous + (latency - previous) * LATENCY_SMOOTHING,
+                None => latency,
+            });
+        }
+    }
+
+    /// Returns the number of configured upstreams.
+    pub fn count(&self) -> usize {
+        self.upstreams.len()
+    }
+
+    /// Returns the upstream at the given index in order of priority.
+    pub fn get(&self, index: usize) -> &UpstreamDescriptor<'static> {
+        &self.upstreams[index]
+    }
+
+    /// Returns the index of the active upstream.
+    pub fn active_index(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Makes the upstream at the given index the active upstream.
+    pub fn switch_to(&self, index: usize) {
+        let previous = self.active.swap(index, Ordering::Relaxed);
+        if previous == index {
+            return;
+        }
+
+        let direction = if index < previous {
+            "failback"
+        } else {
+            "failover"
+        };
+
+        relay_log::info!(
+            upstream = %self.get(index),
+            "switching from upstream {} to upstream {} ({direction})",
+            previous + 1,
+            index + 1,
+        );
+        metric!(
+            counter(RelayCounters::UpstreamSwitched) += 1,
+            direction = direction
+        );
+    }
+}
+
+/// Picks an index with a probability proportional to its weight.
+///
+/// `random` must be in the range `[0, 1)`. Returns `None` if all weights are zero.
+fn pick_weighted(weights: impl Iterator<Item = f64> + Clone, random: f64) -> Option<usize> {
+    let total: f64 = weights.clone().sum();
+    if total <= 0.0 {
+        return None;
+    }
+
+    let mut remaining = random * total;
+    for (index, weight) in weights.enumerate() {
+        if weight > 0.0 && remaining < weight {
+            return Some(index);
+        }
+        remaining -= weight;
+    }
+
+    None
+}
+
+/// State of the [`CircuitBreaker`].
+#[derive(Debug, Default)]
+struct CircuitState {
+    /// Number of consecutive 5xx responses.
+    failures: u32,
+    /// The instant until which requests are short-circuited, if the circuit is open.
+    open_until: Option<Instant>,
+}
+
+/// Short-circuits requests during sustained periods of server errors from the upstream.
+///
+/// After the configured number of consecutive 5xx responses, the circuit opens and
+/// [`allow`](Self::allow) returns `false` until the timeout has elapsed. Afterwards, requests are
+/// sent again. Another 5xx response opens the circuit immediately, and any other response from the
+/// upstream closes it.
+#[derive(Debug)]
+struct CircuitBreaker {
+    threshold: u32,
+    timeout: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    /// Creates a new circuit breaker in closed state.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            threshold: config.http_circuit_breaker_threshold(),
+            timeout: config.http_circuit_breaker_timeout(),
+            state: Mutex::new(CircuitState::default()),
+        }
+    }
+
+    /// Returns `true` if requests may be sent to the upstream.
+    pub fn allow(&self) -> bool {
+        if self.threshold == 0 {
+            return true;
+        }
+
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        !matches!(state.open_until, Some(until) if until > Instant::now())
+    }
+
+    /// Records a response from the upstream.
+    ///
+    /// `server_error` indicates whether the upstream responded with a 5xx status code.
+    pub fn record(&self, server_error: bool) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if !server_error {
+            if state.open_until.take().is_some() {
+                relay_log::info!("upstream recovered, closing circuit breaker");
+            }
+            state.failures = 0;
+            return;
+        }
+
+        state.failures = state.failures.saturating_add(1);
+        if state.failures < self.threshold {
+            return;
+        }
+
+        if state.open_until.is_none() {
+            relay_log::warn!(
+                "upstream returned {} consecutive server errors, opening circuit breaker",
+                state.failures
+            );
+            metric!(counter(RelayCounters::UpstreamCircuitBreakerOpened) += 1);
+        }
+
+        state.open_until = Some(Instant::now() + self.timeout);
+    }
+}
+
+/// Maximum number of connections remembered by the [`ConnectionTracker`].
+const MAX_TRACKED_CONNECTIONS: usize = 10_000;
+
+/// Detects whether upstream requests reuse pooled connections.
+///
+/// Connections are identified by their local and peer socket addresses, since the same local port
+/// can be used for connections to different upstreams. A response received on a previously seen
+/// pair of addresses was sent over a reused connection.
+#[derive(Debug, Default)]
+struct ConnectionTracker {
+    seen: Mutex<HashSet<(SocketAddr, SocketAddr)>>,
+}
+
+impl ConnectionTracker {
+    /// Records the connection of a response and returns `true` if it was reused.
+    pub fn track(&self, local_addr: SocketAddr, peer_addr: SocketAddr) -> bool {
+        let connection = (local_addr, peer_addr);
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        if seen.contains(&connection) {
+            return true;
+        }
+
+        // Connections are closed over time, so reset instead of growing indefinitely.
+        if seen.len() >= MAX_TRACKED_CONNECTIONS {
+            seen.clear();
+        }
+
+        seen.insert(connection);
+        false
+    }
+}
+
+/// Token bucket that limits the rate of request bodies sent to the upstream.
+///
+/// Callers reserve bytes from the bucket and wait for the returned delay before sending. The
+/// bucket may go into debt, so that requests larger than the burst size can still be sent, and
+/// concurrent requests are delayed in the order of their reservation. Requests that fail without
+/// a response refund their reservation, so that failed attempts do not delay retries.
+#[derive(Debug)]
+struct EgressLimiter {
+    rate: f64,
+    burst: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl EgressLimiter {
+    /// Creates a limiter from the configuration, or `None` if the egress rate is not limited.
+    fn new(config: &Config) -> Option<Self> {
+        let rate = config.http_max_egress_rate()? as f64;
+        let burst = config.http_egress_burst() as f64;
+
+        Some(Self {
+            rate,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        })
+    }
+
+    /// Reserves the given number of bytes and returns how long to wait before sending them.
+    fn reserve(&self, bytes: usize, now: Instant) -> Duration {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (ref mut tokens, ref mut last) = *guard;
+
+        let elapsed = now.saturating_duration_since(*last).as_secs_f64();
+        *tokens = (*tokens + elapsed * self.rate).min(self.burst) - bytes as f64;
+        *last = now;
+
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / self.rate)
+        }
+    }
+
+    /// Returns previously reserved bytes that were not sent to the bucket.
+    fn refund(&self, bytes: usize) {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        guard.0 = (guard.0 + bytes as f64).min(self.burst);
+    }
+}
+
+/// Request content encodings supported by the configured upstreams.
+///
+/// Upstreams advertise the encodings they accept for request bodies in the `accept-encoding`
+/// response header, as specified in [RFC 7694](https://datatracker.ietf.org/doc/html/rfc7694).
+/// Since upstream store requests are encoded before an upstream is selected, zstd is only used if
+/// all upstreams, including the upstreams of all routes, have advertised support for it.
+#[derive(Debug)]
+pub struct UpstreamEncodings {
+    zstd: Vec<AtomicBool>,
+    routes: Vec<Arc<UpstreamEncodings>>,
+}
+
+impl UpstreamEncodings {
+    /// Creates a new instance for the given number of upstreams, none of which supports zstd yet.
+    fn new(count: usize, routes: Vec<Arc<UpstreamEncodings>>) -> Self {
+        Self {
+            zstd: (0..count).map(|_| AtomicBool::new(false)).collect(),
+            routes,
+        }
+    }
+
+    /// Returns `true` if all upstreams support zstd.
+    fn supports_zstd(&self) -> bool {
+        self.zstd.iter().all(|flag| flag.load(Ordering::Relaxed))
+            && self.routes.iter().all(|route| route.supports_zstd())
+    }
+
+    /// Updates the supported encodings of an upstream from the headers of its response.
+    ///
+    /// Responses without an `accept-encoding` header do not change the known encodings.
+    fn record(&self, index: usize, headers: &header::HeaderMap) {
+        let mut values = headers.get_all(header::ACCEPT_ENCODING).iter().peekable();
+        if values.peek().is_none() {
+            return;
+        }
+
+        let zstd = values
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|encoding| matches!(HttpEncoding::parse(encoding), HttpEncoding::Zstd));
+
+        if let Some(flag) = self.zstd.get(index) {
+            flag.store(zstd, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the encoding to apply to upstream store requests.
+    ///
+    /// Falls back from [`HttpEncoding::Zstd`] to [`HttpEncoding::Gzip`] unless all upstreams
+    /// support zstd.
+    pub fn resolve(&self, encoding: HttpEncoding) -> HttpEncoding {
+        match encoding {
+            HttpEncoding::Zstd if !self.supports_zstd() => HttpEncoding::Gzip,
+            encoding => encoding,
+        }
+    }
+}
+
 /// A shared, asynchronous client to build and execute requests.
 ///
 /// The main way to send a request through this client is [`send`](Self::send).
@@ -702,14 +1212,22 @@ impl UpstreamQuery for RegisterResponse {
 struct SharedClient {
     config: Arc<Config>,
     reqwest: reqwest::Client,
+    upstreams: Arc<UpstreamSelector>,
+    breaker: Arc<CircuitBreaker>,
+    connections: Arc<ConnectionTracker>,
+    encodings: Arc<UpstreamEncodings>,
+    egress: Option<Arc<EgressLimiter>>,
 }
 
 impl SharedClient {
     /// Creates a new `SharedClient` instance.
-    pub fn build(config: Arc<Config>) -> Self {
-        let reqwest = reqwest::ClientBuilder::new()
-            .connect_timeout(config.http_connection_timeout())
-            .timeout(config.http_timeout())
+    ///
+    /// The encodings of routes are considered when resolving the encoding of upstream requests.
+    pub fn build(
+        config: Arc<Config>,
+        route_encodings: Vec<Arc<UpstreamEncodings>>,
+    ) -> anyhow::Result<Self> {
+        let reqwest = http::client_builder(&config)?
             // In the forward endpoint, this means that content negotiation is done twice, and the
             // response body is first decompressed by the client, then re-compressed by the server.
             .gzip(true)
@@ -717,10 +1235,36 @@ impl SharedClient {
             // This helps to limit the amount of requests made to upstream DNS server (important
             // for K8s infrastructure).
             .trust_dns(true)
-            .build()
-            .unwrap();
+            .build()?;
+
+        let upstreams = Arc::new(UpstreamSelector::new(&config));
+        let breaker = Arc::new(CircuitBreaker::new(&config));
+        let encodings = Arc::new(UpstreamEncodings::new(upstreams.count(), route_encodings));
+        let egress = EgressLimiter::new(&config).map(Arc::new);
+
+        Ok(Self {
+            config,
+            reqwest,
+            upstreams,
+            breaker,
+            connections: Arc::new(ConnectionTracker::default()),
+            encodings,
+            egress,
+        })
+    }
 
-        Self { config, reqwest }
+    /// Creates a retry backoff based on the configured retry policy.
+    fn backoff(&self) -> RetryBackoff {
+        RetryBackoff::with_params(
+            self.config.http_retry_backoff_base(),
+            self.config.http_max_retry_interval(),
+            self.config.http_retry_jitter(),
+        )
+    }
+
+    /// Returns `true` if a request that failed with the given error should be retried.
+    fn is_retryable(&self, error: &UpstreamRequestError) -> bool {
+        error.is_retryable(&self.config.http_retry_status_codes())
     }
 
     /// Builds the request in a non-blocking fashion.
@@ -730,18 +1274,16 @@ impl SharedClient {
     /// asynchronous runtime.
     fn build_request(
         &self,
+        upstream: &UpstreamDescriptor<'_>,
         request: &mut dyn UpstreamRequest,
     ) -> Result<reqwest::Request, UpstreamRequestError> {
         tokio::task::block_in_place(|| {
-            let url = self
-                .config
-                .upstream_descriptor()
-                .get_url(request.path().as_ref());
+            let url = upstream.get_url(request.path().as_ref());
 
             let host_header = self
                 .config
                 .http_host_header()
-                .unwrap_or_else(|| self.config.upstream_descriptor().host());
+                .unwrap_or_else(|| upstream.host());
 
             let mut builder = RequestBuilder::reqwest(self.reqwest.request(request.method(), url))
                 .header("Host", host_header.as_bytes());
@@ -815,15 +1357,93 @@ impl SharedClient {
     }
 
     /// Builds and sends a request to the upstream, returning either a response or the error.
+    ///
+    /// Stateless requests may be sent to any of the configured upstreams, all other requests are
+    /// sent to the active upstream.
     pub async fn send(
         &self,
         request: &mut dyn UpstreamRequest,
     ) -> Result<Response, UpstreamRequestError> {
-        let client_request = self.build_request(request)?;
-        let response = self.reqwest.execute(client_request).await?;
+        let stateless = request.stateless();
+        let index = self.upstreams.select(stateless);
+
+        let start = Instant::now();
+        let result = self.send_to(index, request).await;
+
+        match result {
+            Ok(_) => self.breaker.record(false),
+            Err(ref error) if error.status_code().is_some() => {
+                self.breaker.record(error.is_server_error())
+            }
+            Err(_) => (),
+        }
+
+        if stateless {
+            let network_error = matches!(result, Err(ref e) if e.is_network_error());
+            self.upstreams.record(index, network_error, start.elapsed());
+        }
+
+        result
+    }
+
+    /// Builds and sends a request to the upstream at the given index in order of priority.
+    async fn send_to(
+        &self,
+        index: usize,
+        request: &mut dyn UpstreamRequest,
+    ) -> Result<Response, UpstreamRequestError> {
+        let client_request = self.build_request(self.upstreams.get(index), request)?;
+
+        let body_size = client_request
+            .body()
+            .and_then(reqwest::Body::as_bytes)
+            .map_or(0, <[u8]>::len);
+
+        if let Some(ref egress) = self.egress {
+            let delay = egress.reserve(body_size, Instant::now());
+            if !delay.is_zero() {
+                metric!(timer(RelayTimers::UpstreamEgressDelay) = delay);
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        let response = match self.reqwest.execute(client_request).await {
+            Ok(response) => response,
+            Err(error) => {
+                if let Some(ref egress) = self.egress {
+                    egress.refund(body_size);
+                }
+                return Err(error.into());
+            }
+        };
+        self.encodings.record(index, response.headers());
+
+        if let Some(info) = response.extensions().get::<HttpInfo>() {
+            let reused = self
+                .connections
+                .track(info.local_addr(), info.remote_addr());
+            metric!(
+                counter(RelayCounters::UpstreamConnections) += 1,
+                reused = if reused { "true" } else { "false" },
+            );
+        }
+
         self.transform_response(request, Response(response)).await
     }
 
+    /// Returns the index of the first reachable upstream within the given range of indexes.
+    async fn first_reachable(&self, indexes: Range<usize>) -> Option<usize> {
+        for index in indexes {
+            match self.send_to(index, &mut GetHealthCheck).await {
+                // All errors that are not connection errors are considered a successful attempt
+                Err(e) if e.is_network_error() => continue,
+                _ => return Some(index),
+            }
+        }
+
+        None
+    }
+
     /// Convenience method to send a query to the upstream and await the result.
     pub async fn send_query<T>(&self, query: T) -> Result<T::Response, UpstreamRequestError>
     where
@@ -863,6 +1483,8 @@ struct Entry {
     /// This starts with `0` and is incremented every time a request is placed back into the queue
     /// following a network error.
     pub retries: usize,
+    /// The time at which the entry was last placed into the queue.
+    pub enqueued_at: Instant,
 }
 
 impl Entry {
@@ -871,6 +1493,7 @@ impl Entry {
         Self {
             request,
             retries: 0,
+            enqueued_at: Instant::now(),
         }
     }
 }
@@ -974,11 +1597,13 @@ impl AuthState {
     /// Returns the initial `AuthState` based on configuration.
     ///
     /// - Relays in managed mode require authentication. The state is set to `AuthState::Unknown`.
-    /// - Other Relays do not require authentication. The state is set to `AuthState::Registered`.
+    /// - Other Relays, and Relays authenticating with a client certificate, do not require
+    ///   registration. The state is set to `AuthState::Registered`.
     pub fn init(config: &Config) -> Self {
-        match config.relay_mode() {
-            RelayMode::Managed => AuthState::Unknown,
-            _ => AuthState::Registered,
+        if config.requires_registration() {
+            AuthState::Unknown
+        } else {
+            AuthState::Registered
         }
     }
 
@@ -998,6 +1623,8 @@ enum RequestOutcome {
     /// This does not automatically mean that the request was successfully accepted. It could also
     /// have been rate limited or rejected as invalid.
     Received,
+    /// The request was not sent because the circuit breaker is open.
+    Skipped,
 }
 
 /// Internal message of the upstream's [`UpstreamBroker`].
@@ -1108,25 +1735,25 @@ impl AuthMonitor {
     /// Authentication starts immediately and then enters a loop of recurring reauthentication until
     /// one of the following conditions is met:
     ///
-    ///  - Authentication is not required based on the Relay's mode configuration.
+    ///  - Authentication is not required based on the Relay's mode and authentication method.
     ///  - The upstream responded with a permanent rejection (auth denied).
     ///  - All subscibers have shut down and the action channel is closed.
     pub async fn run(mut self) {
-        if self.config.relay_mode() != RelayMode::Managed {
+        if !self.config.requires_registration() {
             return;
         }
 
-        let config = self.config.clone();
-        let Some(credentials) = config.credentials() else {
-            // This is checked during setup by `check_config` and should never happen.
-            relay_log::error!("authentication called without credentials");
-            return;
-        };
-
-        let mut backoff = RetryBackoff::new(self.config.http_max_retry_interval());
+        let mut backoff = self.client.backoff();
 
         loop {
-            match self.authenticate(credentials).await {
+            // Credentials are read on every attempt, since they can be rotated at runtime.
+            let Some(credentials) = self.config.credentials() else {
+                // This is checked during setup by `check_config` and should never happen.
+                relay_log::error!("authentication called without credentials");
+                return;
+            };
+
+            match self.authenticate(&credentials).await {
                 Ok(_) => {
                     backoff.reset();
 
@@ -1239,18 +1866,20 @@ impl ConnectionMonitor {
     }
 
     /// Performs connection attempts with exponential backoff until successful.
+    ///
+    /// Every attempt checks all upstreams in order of priority and switches to the first reachable
+    /// one.
     async fn connect(client: SharedClient, tx: ActionTx) {
-        let mut backoff = RetryBackoff::new(client.config.http_max_retry_interval());
+        let mut backoff = client.backoff();
 
         loop {
             let next_backoff = backoff.next_backoff();
             relay_log::warn!("network outage, scheduling another check in {next_backoff:?}");
 
             tokio::time::sleep(next_backoff).await;
-            match client.send(&mut GetHealthCheck).await {
-                // All errors that are not connection errors are considered a successful attempt
-                Err(e) if e.is_network_error() => continue,
-                _ => break,
+            if let Some(index) = client.first_reachable(0..client.upstreams.count()).await {
+                client.upstreams.switch_to(index);
+                break;
             }
         }
 
@@ -1295,6 +1924,40 @@ impl ConnectionMonitor {
     }
 }
 
+/// Switches back to upstreams with a higher priority after a failover.
+///
+/// In randomized intervals, the monitor checks all upstreams with a higher priority than the active
+/// one and switches to the first reachable upstream. This runs only if multiple upstreams are
+/// configured.
+#[derive(Debug)]
+struct FailbackMonitor {
+    client: SharedClient,
+    interval: Duration,
+}
+
+impl FailbackMonitor {
+    /// Returns the configured interval with a random jitter of up to 50%.
+    fn next_interval(&self) -> Duration {
+        self.interval
+            .mul_f64(rand::thread_rng().gen_range(0.5..1.5))
+    }
+
+    async fn run(self) {
+        loop {
+            tokio::time::sleep(self.next_interval()).await;
+
+            let active = self.client.upstreams.active_index();
+            if active == 0 {
+                continue;
+            }
+
+            if let Some(index) = self.client.first_reachable(0..active).await {
+                self.client.upstreams.switch_to(index);
+            }
+        }
+    }
+}
+
 /// Main broker of the [`UpstreamRelayService`].
 ///
 /// This handles incoming public messages, internal actions, and maintains the upstream queue.
@@ -1306,6 +1969,8 @@ struct UpstreamBroker {
     conn: ConnectionMonitor,
     permits: usize,
     action_tx: ActionTx,
+    routes: Vec<Addr<UpstreamRelay>>,
+    outcomes: Option<Addr<UpstreamRelay>>,
 }
 
 impl UpstreamBroker {
@@ -1351,7 +2016,17 @@ impl UpstreamBroker {
                 sender.send(self.auth_state.is_authenticated())
             }
             UpstreamRelay::IsNetworkOutage(_, sender) => sender.send(self.conn.is_outage()),
-            UpstreamRelay::SendRequest(request) => self.enqueue(request).await,
+            UpstreamRelay::SendRequest(request) => {
+                let outcomes = self.outcomes.as_ref().filter(|_| request.outcomes());
+                let route = request
+                    .upstream_route()
+                    .and_then(|index| self.routes.get(index));
+
+                match outcomes.or(route) {
+                    Some(upstream) => upstream.send(request),
+                    None => self.enqueue(request).await,
+                }
+            }
         }
     }
 
@@ -1363,18 +2038,39 @@ impl UpstreamBroker {
         let client = self.client.clone();
         let action_tx = self.action_tx.clone();
 
+        metric!(
+            timer(RelayTimers::UpstreamRequestsQueueTime) = entry.enqueued_at.elapsed(),
+            route = entry.request.route(),
+        );
+        self.emit_in_flight();
+
         tokio::spawn(async move {
+            if !client.breaker.allow() {
+                let result = Err(UpstreamRequestError::CircuitOpen);
+                emit_response_metrics(Instant::now(), &entry, &result);
+                entry.request.respond(result).await;
+                action_tx
+                    .send(Action::Complete(RequestOutcome::Skipped))
+                    .ok();
+                return;
+            }
+
             let send_start = Instant::now();
             let result = client.send(entry.request.as_mut()).await;
             emit_response_metrics(send_start, &entry, &result);
 
             let status = match result {
-                Err(ref err) if err.is_network_error() => RequestOutcome::Dropped,
+                Err(ref err) if client.is_retryable(err) => RequestOutcome::Dropped,
                 _ => RequestOutcome::Received,
             };
 
+            let can_retry = client
+                .config
+                .http_max_retries()
+                .map_or(true, |max| entry.retries < max);
+
             match status {
-                RequestOutcome::Dropped if entry.request.retry() => {
+                RequestOutcome::Dropped if entry.request.retry() && can_retry => {
                     entry.retries += 1;
                     action_tx.send(Action::Retry(entry)).ok();
                 }
@@ -1388,20 +2084,36 @@ impl UpstreamBroker {
         });
     }
 
+    /// Emits the number of requests currently sent to the upstream.
+    fn emit_in_flight(&self) {
+        let in_flight = self
+            .client
+            .config
+            .max_concurrent_requests()
+            .saturating_sub(self.permits);
+
+        metric!(gauge(RelayGauges::UpstreamRequestsInFlight) = in_flight as u64);
+    }
+
     /// Marks completion of a running request and reclaims its slot.
     fn complete(&mut self, status: RequestOutcome) {
         self.permits += 1;
+        self.emit_in_flight();
 
         match status {
             RequestOutcome::Dropped => self.conn.notify_error(&self.action_tx),
             RequestOutcome::Received => self.conn.reset_error(),
+            RequestOutcome::Skipped => (),
         }
     }
 
     /// Handler of the internal action channel.
     fn handle_action(&mut self, action: Action) {
         match action {
-            Action::Retry(request) => self.queue.enqueue_immediate(request),
+            Action::Retry(mut request) => {
+                request.enqueued_at = Instant::now();
+                self.queue.enqueue_immediate(request)
+            }
             Action::Complete(status) => self.complete(status),
             Action::Connected => self.conn.reset_error(),
             Action::UpdateAuth(state) => self.auth_state = state,
@@ -1410,16 +2122,55 @@ impl UpstreamBroker {
 }
 
 /// Implementation of the [`UpstreamRelay`] interface.
+///
+/// For every route in `relay.routes` and for `outcomes.upstream`, this service starts a nested
+/// service with its own queue, connection monitoring and authentication state. Requests with an
+/// [`upstream_route`](UpstreamRequest::upstream_route) or [`outcomes`](UpstreamRequest::outcomes)
+/// are forwarded to the respective nested service.
 #[derive(Debug)]
 pub struct UpstreamRelayService {
     config: Arc<Config>,
+    client: SharedClient,
+    routes: Vec<UpstreamRelayService>,
+    outcomes: Option<Box<UpstreamRelayService>>,
 }
 
 impl UpstreamRelayService {
     /// Creates a new `UpstreamRelay` instance.
-    pub fn new(config: Arc<Config>) -> Self {
+    ///
+    /// Returns an error if the HTTP client cannot be created from the configuration, for instance
+    /// due to an invalid CA file.
+    pub fn new(config: Arc<Config>) -> anyhow::Result<Self> {
         // Broker and other actual components are implemented in the Service's `spawn_handler`.
-        Self { config }
+        let routes = (0..config.upstream_routes().len())
+            .map(|index| Self::new(config.for_upstream_route(index)?))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let outcomes = match config.for_outcomes_upstream()? {
+            Some(outcomes_config) => Some(Box::new(Self::new(outcomes_config)?)),
+            None => None,
+        };
+
+        let route_encodings = routes
+            .iter()
+            .chain(outcomes.as_deref())
+            .map(|route| route.encodings())
+            .collect();
+        let client = SharedClient::build(config.clone(), route_encodings)?;
+
+        Ok(Self {
+            config,
+            client,
+            routes,
+            outcomes,
+        })
+    }
+
+    /// Returns the request encodings supported by the upstreams.
+    ///
+    /// This is updated as responses from the upstreams are received.
+    pub fn encodings(&self) -> Arc<UpstreamEncodings> {
+        self.client.encodings.clone()
     }
 }
 
@@ -1427,9 +2178,15 @@ impl Service for UpstreamRelayService {
     type Interface = UpstreamRelay;
 
     fn spawn_handler(self, mut rx: relay_system::Receiver<Self::Interface>) {
-        let Self { config } = self;
+        let Self {
+            config,
+            client,
+            routes,
+            outcomes,
+        } = self;
 
-        let client = SharedClient::build(config.clone());
+        let routes = routes.into_iter().map(|route| route.start()).collect();
+        let outcomes = outcomes.map(|outcomes| outcomes.start());
 
         // Channel for serialized communication from the auth monitor, connection monitor, and
         // concurrent requests back to the broker.
@@ -1445,6 +2202,16 @@ impl Service for UpstreamRelayService {
         };
         tokio::spawn(auth.run());
 
+        // Spawn a background task that fails back to higher-priority upstreams after a failover.
+        let failback_interval = config.http_failback_interval();
+        if client.upstreams.count() > 1 && !failback_interval.is_zero() {
+            let failback = FailbackMonitor {
+                client: client.clone(),
+                interval: failback_interval,
+            };
+            tokio::spawn(failback.run());
+        }
+
         // Main broker that serializes public and internal messages, as well as maintains connection
         // and authentication state.
         let mut broker = UpstreamBroker {
@@ -1454,6 +2221,8 @@ impl Service for UpstreamRelayService {
             conn: ConnectionMonitor::new(client),
             permits: config.max_concurrent_requests(),
             action_tx,
+            routes,
+            outcomes,
         };
 
         tokio::spawn(async move {
@@ -1471,3 +2240,349 @@ impl Service for UpstreamRelayService {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector(balancing: &str) -> UpstreamSelector {
+        let config = Config::from_json_value(serde_json::json!({
+            "relay": {
+                "upstream": [
+                    "https://primary.example.com/",
+                    "https://secondary.example.com/",
+                    "https://tertiary.example.com/",
+                ]
+            },
+            "http": {
+                "load_balancing": balancing,
+            }
+        }))
+        .unwrap();
+
+        UpstreamSelector::new(&config)
+    }
+
+    #[test]
+    fn test_pick_weighted() {
+        let weights = [1.0, 0.0, 3.0];
+        assert_eq!(pick_weighted(weights.iter().copied(), 0.0), Some(0));
+        assert_eq!(pick_weighted(weights.iter().copied(), 0.24), Some(0));
+        assert_eq!(pick_weighted(weights.iter().copied(), 0.25), Some(2));
+        assert_eq!(pick_weighted(weights.iter().copied(), 0.99), Some(2));
+        assert_eq!(pick_weighted([0.0, 0.0].into_iter(), 0.5), None);
+    }
+
+    #[test]
+    fn test_select_disabled() {
+        let selector = selector("disabled");
+        assert_eq!(selector.select(true), 0);
+
+        selector.switch_to(1);
+        assert_eq!(selector.select(true), 1);
+        assert_eq!(selector.select(false), 1);
+    }
+
+    #[test]
+    fn test_select_round_robin() {
+        let selector = selector("round_robin");
+
+        let selected: Vec<_> = (0..4).map(|_| selector.select(true)).collect();
+        assert_eq!(selected, [0, 1, 2, 0]);
+
+        // Requests that are not stateless stay on the active upstream.
+        assert_eq!(selector.select(false), 0);
+    }
+
+    #[test]
+    fn test_select_skips_unhealthy() {
+        let selector = selector("round_robin");
+        selector.record(1, true, Duration::ZERO);
+
+        let selected: Vec<_> = (0..3).map(|_| selector.select(true)).collect();
+        assert_eq!(selected, [0, 2, 2]);
+
+        selector.record(1, false, Duration::from_millis(10));
+        assert!(selector.stats.lock().unwrap()[1].is_healthy(Instant::now()));
+    }
+
+    #[test]
+    fn test_select_latency() {
+        let selector = selector("latency");
+        selector.record(0, false, Duration::from_millis(10));
+        selector.record(1, true, Duration::ZERO);
+        selector.record(2, false, Duration::from_secs(10));
+
+        let primary = (0..100).filter(|_| selector.select(true) == 0).count();
+        assert!(primary > 95, "primary selected {primary} times");
+    }
+
+    fn circuit_breaker(timeout: u64) -> CircuitBreaker {
+        let config = Config::from_json_value(serde_json::json!({
+            "http": {
+                "circuit_breaker_threshold": 2,
+                "circuit_breaker_timeout": timeout,
+            }
+        }))
+        .unwrap();
+
+        CircuitBreaker::new(&config)
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = circuit_breaker(30);
+        breaker.record(true);
+        assert!(breaker.allow());
+
+        breaker.record(true);
+        assert!(!breaker.allow());
+
+        breaker.record(false);
+        assert!(breaker.allow());
+
+        // Failures must be consecutive to open the circuit.
+        breaker.record(true);
+        breaker.record(false);
+        breaker.record(true);
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn test_circuit_breaker_timeout() {
+        let breaker = circuit_breaker(0);
+        breaker.record(true);
+        breaker.record(true);
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn test_circuit_breaker_disabled() {
+        let breaker = CircuitBreaker::new(&Config::default());
+        for _ in 0..100 {
+            breaker.record(true);
+        }
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn test_connection_tracker() {
+        let tracker = ConnectionTracker::default();
+        let first = "127.0.0.1:50000".parse().unwrap();
+        let second = "127.0.0.1:50001".parse().unwrap();
+        let upstream = "10.0.0.1:443".parse().unwrap();
+        let other_upstream = "10.0.0.2:443".parse().unwrap();
+
+        assert!(!tracker.track(first, upstream));
+        assert!(tracker.track(first, upstream));
+        assert!(!tracker.track(second, upstream));
+        assert!(tracker.track(first, upstream));
+
+        // The same local port connected to a different upstream is a new connection.
+        assert!(!tracker.track(first, other_upstream));
+    }
+
+    #[test]
+    fn test_egress_limiter() {
+        let config = Config::from_json_value(serde_json::json!({
+            "http": {
+                "max_egress_rate": 1000,
+                "egress_burst": 2000,
+            }
+        }))
+        .unwrap();
+
+        let limiter = EgressLimiter::new(&config).unwrap();
+        let now = Instant::now();
+
+        // The burst can be sent immediately, after that requests are delayed.
+        assert_eq!(limiter.reserve(2000, now), Duration::ZERO);
+        assert_eq!(limiter.reserve(500, now), Duration::from_millis(500));
+        assert_eq!(limiter.reserve(500, now), Duration::from_secs(1));
+
+        // Tokens refill at the configured rate.
+        let later = now + Duration::from_secs(3);
+        assert_eq!(limiter.reserve(1000, later), Duration::ZERO);
+
+        // Refunded bytes are available again, but never exceed the burst.
+        assert_eq!(limiter.reserve(1000, later), Duration::ZERO);
+        limiter.refund(1000);
+        assert_eq!(limiter.reserve(1000, later), Duration::ZERO);
+        limiter.refund(5000);
+        assert_eq!(limiter.reserve(2000, later), Duration::ZERO);
+        assert_eq!(limiter.reserve(500, later), Duration::from_millis(500));
+
+        assert!(EgressLimiter::new(&Config::default()).is_none());
+    }
+
+    #[test]
+    fn test_upstream_encodings() {
+        let encodings = UpstreamEncodings::new(2, Vec::new());
+        let headers = |value: &'static str| {
+            let mut headers = header::HeaderMap::new();
+            headers.insert(header::ACCEPT_ENCODING, value.parse().unwrap());
+            headers
+        };
+
+        assert!(matches!(
+            encodings.resolve(HttpEncoding::Zstd),
+            HttpEncoding::Gzip
+        ));
+
+        encodings.record(0, &headers("gzip, zstd"));
+        assert!(matches!(
+            encodings.resolve(HttpEncoding::Zstd),
+            HttpEncoding::Gzip
+        ));
+
+        encodings.record(1, &headers("br,zstd"));
+        assert!(matches!(
+            encodings.resolve(HttpEncoding::Zstd),
+            HttpEncoding::Zstd
+        ));
+
+        // Responses without the header do not reset support.
+        encodings.record(1, &header::HeaderMap::new());
+        assert!(matches!(
+            encodings.resolve(HttpEncoding::Zstd),
+            HttpEncoding::Zstd
+        ));
+
+        encodings.record(1, &headers("gzip"));
+        assert!(matches!(
+            encodings.resolve(HttpEncoding::Zstd),
+            HttpEncoding::Gzip
+        ));
+        assert!(matches!(
+            encodings.resolve(HttpEncoding::Br),
+            HttpEncoding::Br
+        ));
+
+        // All routes must support zstd as well.
+        let route = Arc::new(UpstreamEncodings::new(1, Vec::new()));
+        let encodings = UpstreamEncodings::new(0, vec![route.clone()]);
+        assert!(matches!(
+            encodings.resolve(HttpEncoding::Zstd),
+            HttpEncoding::Gzip
+        ));
+
+        route.record(0, &headers("zstd"));
+        assert!(matches!(
+            encodings.resolve(HttpEncoding::Zstd),
+            HttpEncoding::Zstd
+        ));
+    }
+
+    #[test]
+    fn test_retryable_status_codes() {
+        let error = |code| UpstreamRequestError::ResponseError(code, ApiErrorResponse::default());
+
+        assert!(error(StatusCode::BAD_GATEWAY).is_retryable(&[502, 503, 504]));
+        assert!(!error(StatusCode::INTERNAL_SERVER_ERROR).is_retryable(&[502, 503, 504]));
+        assert!(error(StatusCode::INTERNAL_SERVER_ERROR).is_retryable(&[500]));
+        assert!(!UpstreamRequestError::CircuitOpen.is_retryable(&[500]));
+    }
+
+    /// Query that returns the name of the upstream it was sent to.
+    #[derive(Debug, Serialize)]
+    struct GetUpstreamName;
+
+    impl UpstreamQuery for GetUpstreamName {
+        type Response = String;
+
+        fn method(&self) -> Method {
+            Method::GET
+        }
+
+        fn path(&self) -> Cow<'static, str> {
+            Cow::Borrowed("/api/0/name/")
+        }
+
+        fn retry() -> bool {
+            true
+        }
+
+        fn route(&self) -> &'static str {
+            "name"
+        }
+    }
+
+    /// Starts an upstream that responds with its name on the given listener.
+    fn upstream(listener: std::net::TcpListener, name: &'static str) {
+        let router = axum::Router::new().fallback(move || async move { axum::Json(name) });
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router.into_make_service());
+        tokio::spawn(server);
+    }
+
+    /// Reserves a local address that refuses connections until a listener is bound to it.
+    fn unused_addr() -> SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    fn service(primary: SocketAddr, secondary: SocketAddr) -> Addr<UpstreamRelay> {
+        let config = Config::from_json_value(serde_json::json!({
+            "relay": {
+                "mode": "proxy",
+                "upstream": [format!("http://{primary}/"), format!("http://{secondary}/")],
+            },
+            "http": {
+                "outage_grace_period": 0,
+                "failback_interval": 1,
+            }
+        }))
+        .unwrap();
+
+        UpstreamRelayService::new(Arc::new(config)).unwrap().start()
+    }
+
+    /// Sends queries until one is answered by the upstream with the given name.
+    async fn wait_for_upstream(addr: &Addr<UpstreamRelay>, name: &str) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let response = addr.send(SendQuery(GetUpstreamName)).await.unwrap();
+                if response.ok().as_deref() == Some(name) {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_failover_and_failback() {
+        let primary = unused_addr();
+        let secondary = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = service(primary, secondary.local_addr().unwrap());
+        upstream(secondary, "secondary");
+
+        // The primary upstream is unreachable, so the query is retried on the secondary upstream.
+        let response = addr.send(SendQuery(GetUpstreamName)).await.unwrap();
+        assert_eq!(response.unwrap(), "secondary");
+
+        // Once the primary upstream is reachable again, the failback monitor switches back to it.
+        upstream(std::net::TcpListener::bind(primary).unwrap(), "primary");
+        wait_for_upstream(&addr, "primary").await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_no_failover_while_primary_reachable() {
+        let primary = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let secondary = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = service(
+            primary.local_addr().unwrap(),
+            secondary.local_addr().unwrap(),
+        );
+        upstream(primary, "primary");
+        upstream(secondary, "secondary");
+
+        for _ in 0..3 {
+            let response = addr.send(SendQuery(GetUpstreamName)).await.unwrap();
+            assert_eq!(response.unwrap(), "primary");
+        }
+    }
+}
@@ -0,0 +1,369 @@
+//! Recording of incoming requests and their replay.
+//!
+//! If `capture.path` is configured, the [capture middleware](crate::middlewares::capture) records
+//! requests to ingestion endpoints for a bounded window after startup. Every request is stored as
+//! a pair of files in the capture directory, named after its sequence number: `<seq>.json` with
+//! the method, URI, and headers, and `<seq>.body` with the raw, possibly compressed request body.
+//!
+//! The window starts when Relay starts and is not restarted by config reloads. To capture traffic
+//! at a later point in time, restart Relay with capturing enabled.
+//!
+//! Credentials in headers are not recorded. Requests authenticated with a header are replayed
+//! without authentication, unless the DSN key is also part of the URI or the envelope header.
+//! Captured files are only readable by the owner, since request bodies may still contain
+//! sensitive data.
+//!
+//! The `relay replay` command sends recorded requests to a Relay in the order they were recorded
+//! with [`replay`].
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use relay_config::Config;
+use serde::{Deserialize, Serialize};
+
+/// Headers that are not recorded, since they are specific to the original connection or contain
+/// credentials.
+const SKIPPED_HEADERS: &[&str] = &[
+    "authorization",
+    "connection",
+    "content-length",
+    "cookie",
+    "host",
+    "transfer-encoding",
+    "x-forwarded-for",
+    "x-sentry-auth",
+    "x-vercel-forwarded-for",
+];
+
+/// Metadata of a recorded request, stored in `<seq>.json`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CapturedRequest {
+    /// The time at which the request was received.
+    pub timestamp: DateTime<Utc>,
+    /// Milliseconds since the start of the capture window.
+    pub offset_ms: u64,
+    /// The HTTP method of the request.
+    pub method: String,
+    /// The path and query of the request.
+    pub uri: String,
+    /// The client IP address chain, including the peer address.
+    ///
+    /// Sent as `X-Forwarded-For` header on replay.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub forwarded_for: String,
+    /// Request headers in their original order.
+    pub headers: Vec<(String, String)>,
+}
+
+impl CapturedRequest {
+    /// Creates a new record from request parts.
+    ///
+    /// Headers that are not valid UTF-8, connection-specific headers, and headers with credentials
+    /// are skipped.
+    pub fn new(
+        offset: Duration,
+        method: &str,
+        uri: &str,
+        forwarded_for: String,
+        headers: &axum::http::HeaderMap,
+    ) -> Self {
+        let headers = headers
+            .iter()
+            .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect();
+
+        Self {
+            timestamp: Utc::now(),
+            offset_ms: offset.as_millis() as u64,
+            method: method.to_owned(),
+            uri: uri.to_owned(),
+            forwarded_for,
+            headers,
+        }
+    }
+}
+
+/// Records incoming requests into the capture directory.
+#[derive(Debug)]
+pub struct TrafficCapture {
+    path: PathBuf,
+    started: Instant,
+    duration: Duration,
+    max_requests: usize,
+    max_body_size: usize,
+    count: AtomicUsize,
+}
+
+impl TrafficCapture {
+    /// Creates the capture directory if capturing is enabled in the config.
+    pub fn new(config: &Config) -> anyhow::Result<Option<Self>> {
+        let Some(path) = config.capture_path() else {
+            return Ok(None);
+        };
+
+        fs::create_dir_all(path)
+            .with_context(|| format!("failed to create capture directory {}", path.display()))?;
+
+        Ok(Some(Self {
+            path: path.to_owned(),
+            started: Instant::now(),
+            duration: config.capture_duration(),
+            max_requests: config.capture_max_requests(),
+            max_body_size: config.max_envelope_size(),
+            count: AtomicUsize::new(0),
+        }))
+    }
+
+    /// Returns the maximum size of recorded request bodies.
+    ///
+    /// Larger requests are not recorded.
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    /// Returns `true` if the capture window has not elapsed and requests may still be recorded.
+    pub fn is_active(&self) -> bool {
+        self.started.elapsed() <= self.duration
+            && self.count.load(Ordering::Relaxed) < self.max_requests
+    }
+
+    /// Returns the sequence number and offset for the next request.
+    ///
+    /// Returns `None` once the capture window has elapsed or the maximum number of requests has
+    /// been recorded.
+    pub fn reserve(&self) -> Option<(usize, Duration)> {
+        let offset = self.started.elapsed();
+        if offset > self.duration {
+            return None;
+        }
+
+        let seq = self.count.fetch_add(1, Ordering::Relaxed);
+        (seq < self.max_requests).then_some((seq, offset))
+    }
+
+    /// Writes a request with the given sequence number to the capture directory.
+    pub fn write(&self, seq: usize, request: &CapturedRequest, body: &[u8]) -> anyhow::Result<()> {
+        let json = serde_json::to_vec_pretty(request)?;
+        write_private(&self.path.join(format!("{seq:08}.body")), body)?;
+        // Write the metadata last, since replay only picks up requests with metadata.
+        write_private(&self.path.join(format!("{seq:08}.json")), &json)?;
+        Ok(())
+    }
+}
+
+/// Writes a file that is only accessible by the owner.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents)
+}
+
+/// Reads all recorded requests from a capture directory, ordered by sequence number.
+pub fn read_captured(path: &Path) -> anyhow::Result<Vec<(CapturedRequest, Bytes)>> {
+    let mut files = fs::read_dir(path)
+        .with_context(|| format!("failed to read capture directory {}", path.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    files.retain(|file| file.extension().map_or(false, |ext| ext == "json"));
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|file| {
+            let request = serde_json::from_slice(&fs::read(&file)?)
+                .with_context(|| format!("invalid captured request {}", file.display()))?;
+            let body = fs::read(file.with_extension("body"))?;
+            Ok((request, Bytes::from(body)))
+        })
+        .collect()
+}
+
+/// The result of [`replay`].
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// The number of requests sent.
+    pub sent: usize,
+    /// The number of responses per status code.
+    pub statuses: BTreeMap<u16, usize>,
+    /// The number of requests that failed without a response.
+    pub failed: usize,
+}
+
+/// Sends recorded requests to the Relay at `target` in the order they were recorded.
+///
+/// Requests are sent one at a time, each after the response to the previous one. If `realtime` is
+/// set, requests are additionally delayed to match the timing of the original traffic.
+pub async fn replay(path: &Path, target: &str, realtime: bool) -> anyhow::Result<ReplayReport> {
+    let requests = read_captured(path)?;
+    let client = reqwest::Client::new();
+    let target = target.trim_end_matches('/');
+
+    let mut report = ReplayReport::default();
+    let started = tokio::time::Instant::now();
+
+    for (request, body) in requests {
+        if realtime {
+            tokio::time::sleep_until(started + Duration::from_millis(request.offset_ms)).await;
+        }
+
+        let method = request.method.parse::<reqwest::Method>()?;
+        let mut builder = client.request(method, format!("{target}{}", request.uri));
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if !request.forwarded_for.is_empty() {
+            builder = builder.header("x-forwarded-for", &request.forwarded_for);
+        }
+
+        report.sent += 1;
+        match builder.body(body).send().await {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                *report.statuses.entry(status).or_default() += 1;
+            }
+            Err(error) => {
+                relay_log::debug!(
+                    error = &error as &dyn std::error::Error,
+                    "failed to replay request {}",
+                    request.uri
+                );
+                report.failed += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use super::*;
+
+    fn capture_config(path: &Path, max_requests: usize) -> Config {
+        Config::from_json_value(serde_json::json!({
+            "capture": {
+                "path": path,
+                "max_requests": max_requests,
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_reserve_max_requests() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = capture_config(directory.path(), 2);
+        let capture = TrafficCapture::new(&config).unwrap().unwrap();
+
+        assert_eq!(capture.reserve().map(|(seq, _)| seq), Some(0));
+        assert_eq!(capture.reserve().map(|(seq, _)| seq), Some(1));
+        assert_eq!(capture.reserve(), None);
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = capture_config(directory.path(), 10);
+        let capture = TrafficCapture::new(&config).unwrap().unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("content-encoding", HeaderValue::from_static("gzip"));
+        headers.insert("content-length", HeaderValue::from_static("3"));
+
+        for body in [&b"abc"[..], &b"def"[..]] {
+            let (seq, offset) = capture.reserve().unwrap();
+            let request = CapturedRequest::new(
+                offset,
+                "POST",
+                "/api/42/envelope/",
+                "127.0.0.1".to_owned(),
+                &headers,
+            );
+            capture.write(seq, &request, body).unwrap();
+        }
+
+        let requests = read_captured(directory.path()).unwrap();
+        assert_eq!(requests.len(), 2);
+
+        let (request, body) = &requests[1];
+        assert_eq!(request.uri, "/api/42/envelope/");
+        assert_eq!(
+            request.headers,
+            vec![("content-encoding".to_owned(), "gzip".to_owned())]
+        );
+        assert_eq!(body.as_ref(), b"def");
+    }
+
+    #[test]
+    fn test_skip_credentials() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer secret"));
+        headers.insert("cookie", HeaderValue::from_static("session=secret"));
+        headers.insert(
+            "x-sentry-auth",
+            HeaderValue::from_static("Sentry sentry_key=a"),
+        );
+        headers.insert("user-agent", HeaderValue::from_static("sentry.python"));
+
+        let request = CapturedRequest::new(
+            Duration::ZERO,
+            "POST",
+            "/api/42/envelope/",
+            String::new(),
+            &headers,
+        );
+
+        assert_eq!(
+            request.headers,
+            vec![("user-agent".to_owned(), "sentry.python".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_is_active() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = capture_config(directory.path(), 1);
+        let capture = TrafficCapture::new(&config).unwrap().unwrap();
+
+        assert!(capture.is_active());
+        capture.reserve().unwrap();
+        assert!(!capture.is_active());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = tempfile::tempdir().unwrap();
+        let config = capture_config(directory.path(), 1);
+        let capture = TrafficCapture::new(&config).unwrap().unwrap();
+
+        let (seq, offset) = capture.reserve().unwrap();
+        let request = CapturedRequest::new(offset, "POST", "/", String::new(), &HeaderMap::new());
+        capture.write(seq, &request, b"abc").unwrap();
+
+        for file in ["00000000.json", "00000000.body"] {
+            let metadata = fs::metadata(directory.path().join(file)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+    }
+}
@@ -26,5 +26,11 @@ pub const UNREAL_USER_HEADER: &str = "unreal_user_id";
 /// configurations.
 pub const DEFAULT_EVENT_RETENTION: u16 = 90;
 
+/// Content encodings accepted for request bodies.
+///
+/// This is advertised in the `accept-encoding` response header, so that downstream Relays can
+/// choose a supported encoding for their requests.
+pub const ACCEPTED_ENCODINGS: &str = "gzip, deflate, br, zstd";
+
 /// Maximum size of JSON request bodies.
 pub const MAX_JSON_SIZE: usize = 262_144;
@@ -0,0 +1,474 @@
+//! Admin API to change a limited set of settings at runtime and inspect the health of Relay and
+//! the rate limits it enforces.
+//!
+//! Requests must carry the token configured in `admin.token` or a JSON Web Token signed with one of
+//! the keys in `admin.jwt` as bearer token. Changes are applied immediately and persisted to the
+//! config folder, see [`RuntimeOverrides`].
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use axum::extract::Path;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use relay_auth::JwtClaims;
+use relay_base_schema::project::ProjectKey;
+use relay_config::{Config, RuntimeOverrides};
+use relay_quotas::{RateLimit, RateLimitScope, RateLimits};
+use serde::Serialize;
+
+use crate::actors::audit::{self, AuditEvent, AuditSource, AuditSubject};
+use crate::actors::health_check::{HealthDetail, HealthReport};
+use crate::actors::project_cache::GetCachedRateLimits;
+use crate::envelope::ItemType;
+use crate::service::ServiceState;
+
+/// Compares two byte strings in constant time, so that the token cannot be guessed by timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Scope of JSON Web Tokens that may read settings.
+const SCOPE_READ: &str = "admin:read";
+
+/// Scope of JSON Web Tokens that may change settings.
+const SCOPE_WRITE: &str = "admin:write";
+
+/// The kind of access an admin request requires.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum Access {
+    /// Reading settings.
+    Read,
+    /// Changing settings.
+    Write,
+}
+
+impl Access {
+    /// Returns `true` if the token was granted this kind of access.
+    fn granted(self, claims: &JwtClaims) -> bool {
+        match self {
+            Self::Read => claims.has_scope(SCOPE_READ) || claims.has_scope(SCOPE_WRITE),
+            Self::Write => claims.has_scope(SCOPE_WRITE),
+        }
+    }
+}
+
+/// Checks the bearer token of an admin request.
+///
+/// The static token grants full access. JSON Web Tokens must be granted the scope required for
+/// `access`. Returns the response to send if the request must be rejected.
+pub(super) fn authorize(
+    config: &Config,
+    headers: &HeaderMap,
+    access: Access,
+) -> Result<(), Response> {
+    if !config.admin_enabled() {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let Some(provided) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return Err(StatusCode::UNAUTHORIZED.into_response());
+    };
+
+    if let Some(token) = config.admin_token() {
+        if constant_time_eq(provided.as_bytes(), token.as_bytes()) {
+            return Ok(());
+        }
+    }
+
+    let keys = config.admin_jwt_keys();
+    if keys.is_empty() {
+        return Err(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let claims = match relay_auth::decode_jwt(provided, &keys, config.admin_jwt_validation()) {
+        Ok(claims) => claims,
+        Err(error) => {
+            relay_log::debug!("rejected admin token: {error}");
+            return Err(StatusCode::UNAUTHORIZED.into_response());
+        }
+    };
+
+    if !access.granted(&claims) {
+        relay_log::warn!(
+            subject = claims.sub.as_deref().unwrap_or_default(),
+            "admin token lacks scope for {access:?} access"
+        );
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+
+    Ok(())
+}
+
+/// Checks that the overrides contain valid values.
+fn check_overrides(overrides: &RuntimeOverrides) -> Result<(), String> {
+    if let Some(ref filters) = overrides.log_filters {
+        if filters.keys().any(|module| module.is_empty()) {
+            return Err("log_filters must not contain empty module names".to_owned());
+        }
+    }
+
+    if let Some(sample_rate) = overrides.metrics_sample_rate {
+        if !(0.0..=1.0).contains(&sample_rate) {
+            return Err("metrics_sample_rate must be between 0.0 and 1.0".to_owned());
+        }
+    }
+
+    for ty in &overrides.disabled_item_types {
+        if let Ok(ItemType::Unknown(_)) | Err(_) = ItemType::from_str(ty) {
+            return Err(format!("unknown item type `{ty}`"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies overrides that are not read from the config on every use.
+fn apply_overrides(config: &Config) {
+    relay_log::reconfigure(&config.effective_logging());
+    relay_statsd::set_sample_rate(Some(config.metrics_sample_rate()));
+}
+
+/// Returns the current runtime overrides.
+pub async fn get_overrides(state: ServiceState, headers: HeaderMap) -> Response {
+    if let Err(response) = authorize(state.config(), &headers, Access::Read) {
+        return response;
+    }
+
+    axum::Json(state.config().runtime_overrides()).into_response()
+}
+
+/// Usage of the envelope buffer, see [`BufferGuard`](crate::utils::BufferGuard).
+#[derive(Debug, Serialize)]
+struct BufferReport {
+    used: usize,
+    capacity: usize,
+    over_high_watermark: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_used: Option<usize>,
+}
+
+/// Response of the detailed health check.
+#[derive(Debug, Serialize)]
+struct HealthDetailResponse {
+    #[serde(flatten)]
+    report: HealthReport,
+    buffer: BufferReport,
+    queue_sizes: BTreeMap<&'static str, u64>,
+}
+
+/// Returns a detailed report on the health of Relay's services and connections.
+pub async fn get_health_detail(state: ServiceState, headers: HeaderMap) -> Response {
+    if let Err(response) = authorize(state.config(), &headers, Access::Read) {
+        return response;
+    }
+
+    let Ok(report) = state.health_check().send(HealthDetail).await else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+
+    let buffer_guard = state.buffer_guard();
+    let buffer = BufferReport {
+        used: buffer_guard.used(),
+        capacity: buffer_guard.capacity(),
+        over_high_watermark: buffer_guard.is_over_high_watermark(),
+        memory_used: buffer_guard.memory_used(),
+    };
+
+    let status = if report.is_healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    let response = HealthDetailResponse {
+        report,
+        buffer,
+        queue_sizes: state.queue_sizes(),
+    };
+
+    (status, axum::Json(response)).into_response()
+}
+
+/// An active rate limit, as reported by [`get_rate_limits`].
+#[derive(Debug, Serialize)]
+struct RateLimitReport {
+    /// The name of the scope, such as `organization` or `key`.
+    scope: &'static str,
+    /// The ID of the organization, project, or key the rate limit applies to.
+    scope_id: String,
+    /// The data categories that are limited. Empty if all data is limited.
+    categories: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    namespace: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attribute: Option<String>,
+    /// Seconds until the rate limit expires.
+    retry_after: u64,
+    /// The time at which the rate limit expires.
+    reset_at: DateTime<Utc>,
+}
+
+impl RateLimitReport {
+    fn new(limit: &RateLimit, now: DateTime<Utc>) -> Self {
+        let scope_id = match limit.scope {
+            RateLimitScope::Organization(id) => id.to_string(),
+            RateLimitScope::Project(id) => id.to_string(),
+            RateLimitScope::Key(key) => key.to_string(),
+        };
+
+        let remaining = limit.retry_after.remaining().unwrap_or_default();
+
+        Self {
+            scope: limit.scope.name(),
+            scope_id,
+            categories: limit.categories.iter().map(|c| c.name()).collect(),
+            reason_code: limit.reason_code.as_ref().map(|r| r.as_str().to_owned()),
+            namespace: limit.namespace.map(|n| n.as_str()),
+            attribute: limit.attribute.as_ref().map(|a| a.name.clone()),
+            retry_after: limit.retry_after.remaining_seconds(),
+            reset_at: now + chrono::Duration::from_std(remaining).unwrap_or_default(),
+        }
+    }
+}
+
+/// Response of the rate limit status endpoint.
+#[derive(Debug, Serialize)]
+struct RateLimitsResponse {
+    project_key: ProjectKey,
+    rate_limits: Vec<RateLimitReport>,
+}
+
+impl RateLimitsResponse {
+    fn new(project_key: ProjectKey, rate_limits: &RateLimits) -> Self {
+        let now = Utc::now();
+        Self {
+            project_key,
+            rate_limits: rate_limits
+                .iter()
+                .map(|limit| RateLimitReport::new(limit, now))
+                .collect(),
+        }
+    }
+}
+
+/// Returns the active rate limits cached for a project key.
+///
+/// These are the rate limits Relay enforces without asking the upstream, which includes all limits
+/// that caused `429` responses. Responds with `404` if the project is not in the cache.
+pub async fn get_rate_limits(
+    state: ServiceState,
+    headers: HeaderMap,
+    Path(project_key): Path<ProjectKey>,
+) -> Response {
+    if let Err(response) = authorize(state.config(), &headers, Access::Read) {
+        return response;
+    }
+
+    let message = GetCachedRateLimits { project_key };
+    match state.project_cache().send(message).await {
+        Ok(Some(rate_limits)) => {
+            axum::Json(RateLimitsResponse::new(project_key, &rate_limits)).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "project not cached").into_response(),
+        Err(_) => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+    }
+}
+
+/// Replaces the runtime overrides and applies them without a restart.
+pub async fn put_overrides(state: ServiceState, headers: HeaderMap, body: Bytes) -> Response {
+    let config = state.config();
+    if let Err(response) = authorize(config, &headers, Access::Write) {
+        return response;
+    }
+
+    let overrides: RuntimeOverrides = match serde_json::from_slice(&body) {
+        Ok(overrides) => overrides,
+        Err(error) => return (StatusCode::BAD_REQUEST, error.to_string()).into_response(),
+    };
+
+    if let Err(message) = check_overrides(&overrides) {
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
+
+    let changes = audit::diff(config.runtime_overrides().as_ref(), &overrides);
+
+    if let Err(error) = config.set_runtime_overrides(overrides) {
+        relay_log::error!(
+            error = error.as_ref() as &dyn std::error::Error,
+            "failed to persist runtime overrides"
+        );
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    apply_overrides(config);
+
+    if !changes.is_empty() {
+        state.audit_log().send(AuditEvent::new(
+            AuditSubject::RelayConfig,
+            AuditSource::AdminApi,
+            changes,
+        ));
+    }
+
+    let overrides = config.runtime_overrides();
+    relay_log::info!("runtime overrides changed: {overrides:?}");
+    axum::Json(overrides).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use relay_quotas::{DataCategory, ReasonCode, RetryAfter};
+    use smallvec::smallvec;
+
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        let config = Config::from_json_value(serde_json::json!({
+            "admin": {"token": "secret"}
+        }))
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        let status = |headers: &HeaderMap| {
+            authorize(&config, headers, Access::Write).map_err(|r| r.status())
+        };
+        assert_eq!(status(&headers), Err(StatusCode::UNAUTHORIZED));
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer wrong"),
+        );
+        assert_eq!(status(&headers), Err(StatusCode::UNAUTHORIZED));
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secret"),
+        );
+        assert_eq!(status(&headers), Ok(()));
+
+        let disabled = Config::default();
+        assert_eq!(
+            authorize(&disabled, &headers, Access::Read).map_err(|r| r.status()),
+            Err(StatusCode::NOT_FOUND)
+        );
+    }
+
+    #[test]
+    fn test_authorize_jwt() {
+        use data_encoding::BASE64URL_NOPAD;
+        use hmac::{Hmac, Mac};
+
+        let config = Config::from_json_value(serde_json::json!({
+            "admin": {"jwt": {"secrets": ["secret"]}}
+        }))
+        .unwrap();
+
+        let token = |scope: &str| {
+            let exp = relay_common::time::UnixTimestamp::now().as_secs() + 60;
+            let header = BASE64URL_NOPAD.encode(br#"{"alg":"HS256"}"#);
+            let claims = serde_json::json!({"aud": "relay-admin", "exp": exp, "scope": scope});
+            let payload = BASE64URL_NOPAD.encode(claims.to_string().as_bytes());
+            let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+            mac.update(format!("{header}.{payload}").as_bytes());
+            let signature = BASE64URL_NOPAD.encode(&mac.finalize().into_bytes());
+            let value = format!("Bearer {header}.{payload}.{signature}");
+
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+
+        let status = |headers: &HeaderMap, access| {
+            authorize(&config, headers, access).map_err(|r| r.status())
+        };
+
+        let read = token("admin:read");
+        assert_eq!(status(&read, Access::Read), Ok(()));
+        assert_eq!(status(&read, Access::Write), Err(StatusCode::FORBIDDEN));
+
+        let write = token("admin:read admin:write");
+        assert_eq!(status(&write, Access::Write), Ok(()));
+
+        let mut invalid = HeaderMap::new();
+        invalid.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer a.b.c"),
+        );
+        assert_eq!(
+            status(&invalid, Access::Read),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[test]
+    fn test_rate_limits_response() {
+        let project_key = ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap();
+
+        let mut rate_limits = RateLimits::new();
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::Error],
+            scope: RateLimitScope::Organization(42),
+            reason_code: Some(ReasonCode::new("my_limit")),
+            retry_after: RetryAfter::from_secs(60),
+            attribute: None,
+            namespace: None,
+        });
+
+        let response = RateLimitsResponse::new(project_key, &rate_limits);
+        let json = serde_json::to_value(&response).unwrap();
+
+        assert_eq!(json["project_key"], "a94ae32be2584e0bbd7a4cbb95971fee");
+        let limit = &json["rate_limits"][0];
+        assert_eq!(limit["scope"], "organization");
+        assert_eq!(limit["scope_id"], "42");
+        assert_eq!(limit["categories"], serde_json::json!(["error"]));
+        assert_eq!(limit["reason_code"], "my_limit");
+        assert_eq!(limit["retry_after"], 60);
+        assert!(limit.get("namespace").is_none());
+    }
+
+    #[test]
+    fn test_check_overrides() {
+        let valid: RuntimeOverrides = serde_json::from_value(serde_json::json!({
+            "log_level": "debug",
+            "log_filters": {"relay_server::actors::processor": "trace"},
+            "log_format": "json",
+            "metrics_sample_rate": 0.5,
+            "disabled_item_types": ["attachment", "profile"],
+        }))
+        .unwrap();
+        assert_eq!(check_overrides(&valid), Ok(()));
+
+        let invalid_rate = RuntimeOverrides {
+            metrics_sample_rate: Some(1.5),
+            ..Default::default()
+        };
+        assert!(check_overrides(&invalid_rate).is_err());
+
+        let empty_module = RuntimeOverrides {
+            log_filters: Some([(String::new(), relay_log::Level::DEBUG)].into()),
+            ..Default::default()
+        };
+        assert!(check_overrides(&empty_module).is_err());
+
+        let unknown_type = RuntimeOverrides {
+            disabled_item_types: ["attachments".to_owned()].into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            check_overrides(&unknown_type),
+            Err("unknown item type `attachments`".to_owned())
+        );
+    }
+}
@@ -41,7 +41,7 @@ async fn handle(
     multipart: Multipart,
 ) -> Result<impl IntoResponse, BadStoreRequest> {
     let envelope = extract_envelope(state.config(), meta, path, multipart).await?;
-    common::handle_envelope(&state, envelope).await?;
+    common::handle_envelope(&state, envelope, "attachments").await?;
     Ok(StatusCode::CREATED)
 }
 
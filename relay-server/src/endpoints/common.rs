@@ -262,7 +262,12 @@ fn queue_envelope(
 ) -> Result<(), BadStoreRequest> {
     // Remove metrics from the envelope and queue them directly on the project's `Aggregator`.
     let mut metric_items = Vec::new();
-    let is_metric = |i: &Item| matches!(i.ty(), ItemType::Statsd | ItemType::MetricBuckets);
+    let is_metric = |i: &Item| {
+        matches!(
+            i.ty(),
+            ItemType::Statsd | ItemType::MetricBuckets | ItemType::MetricMeta
+        )
+    };
     let envelope = managed_envelope.envelope_mut();
     while let Some(item) = envelope.take_item_by(is_metric) {
         metric_items.push(item);
@@ -286,12 +291,15 @@ fn queue_envelope(
         relay_log::trace!("queueing separate envelope for non-event items");
 
         // The envelope has been split, so we need to fork the context.
-        let event_context = buffer_guard.enter(
+        let mut event_context = buffer_guard.enter(
             event_envelope,
             state.outcome_aggregator().clone(),
             state.test_store().clone(),
         )?;
 
+        event_context.copy_breadcrumbs(&managed_envelope);
+        event_context.share_span(&managed_envelope);
+
         // Update the old context after successful forking.
         managed_envelope.update();
         state
@@ -321,10 +329,22 @@ fn queue_envelope(
 ///
 /// This returns `Some(EventId)` if the envelope contains an event, either explicitly as payload or
 /// implicitly through an item that will create an event during ingestion.
+///
+/// The `endpoint` names the endpoint that received the request. It is recorded in breadcrumbs of
+/// internal error reports.
 pub async fn handle_envelope(
     state: &ServiceState,
-    envelope: Box<Envelope>,
+    mut envelope: Box<Envelope>,
+    endpoint: &str,
 ) -> Result<Option<EventId>, BadStoreRequest> {
+    // Profile chunks count towards quotas by their duration, which must be known before the
+    // envelope summary is computed.
+    for item in envelope.items_mut() {
+        if item.ty() == &ItemType::ProfileChunk {
+            item.set_profile_chunk_duration();
+        }
+    }
+
     let buffer_guard = state.buffer_guard();
     let mut managed_envelope = buffer_guard
         .enter(
@@ -333,11 +353,13 @@ pub async fn handle_envelope(
             state.test_store().clone(),
         )
         .map_err(BadStoreRequest::QueueFailed)?;
+    managed_envelope.record_received(endpoint);
 
-    // If configured, remove unknown items at the very beginning. If the envelope is
-    // empty, we fail the request with a special control flow error to skip checks and
-    // queueing, that still results in a `200 OK` response.
+    // If configured, remove unknown items and item types disabled through the admin API at the
+    // very beginning. If the envelope is empty, we fail the request with a special control flow
+    // error to skip checks and queueing, that still results in a `200 OK` response.
     utils::remove_unknown_items(state.config(), &mut managed_envelope);
+    utils::remove_disabled_items(state.config(), &mut managed_envelope);
 
     let event_id = managed_envelope.envelope().event_id();
     if managed_envelope.envelope().is_empty() {
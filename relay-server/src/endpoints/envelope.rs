@@ -121,7 +121,7 @@ async fn handle(
     params: EnvelopeParams,
 ) -> Result<impl IntoResponse, BadStoreRequest> {
     let envelope = params.extract_envelope()?;
-    let id = common::handle_envelope(&state, envelope).await?;
+    let id = common::handle_envelope(&state, envelope, "envelope").await?;
     Ok(Json(StoreResponse { id }))
 }
 
@@ -1,11 +1,13 @@
-//! Returns captured events.
+//! Returns captured events and the results of tracked envelopes.
 
 use axum::extract::Path;
-use axum::http::{header, StatusCode};
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::IntoResponse;
+use axum::Json;
 use relay_event_schema::protocol::EventId;
 
-use crate::actors::test_store::GetCapturedEnvelope;
+use crate::actors::test_store::{GetCapturedEnvelope, GetTrackedResult};
+use crate::endpoints::admin::{authorize, Access};
 use crate::endpoints::common::ServiceUnavailable;
 use crate::envelope;
 use crate::service::ServiceState;
@@ -28,3 +30,27 @@ pub async fn handle(
         None => StatusCode::NOT_FOUND.into_response(),
     })
 }
+
+/// Returns the result of an envelope submitted with the `track_result` header.
+///
+/// Requires read access to the admin API, see [`authorize`]. Responds with `404` while the
+/// envelope is still being processed.
+pub async fn handle_result(
+    state: ServiceState,
+    headers: HeaderMap,
+    Path(event_id): Path<EventId>,
+) -> Result<impl IntoResponse, ServiceUnavailable> {
+    if let Err(response) = authorize(state.config(), &headers, Access::Read) {
+        return Ok(response);
+    }
+
+    let result = state
+        .test_store()
+        .send(GetTrackedResult { event_id })
+        .await?;
+
+    Ok(match result {
+        Some(result) => Json(result).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    })
+}
@@ -81,6 +81,7 @@ impl IntoResponse for ForwardError {
                     StatusCode::BAD_GATEWAY.into_response()
                 }
             }
+            UpstreamRequestError::CircuitOpen => StatusCode::SERVICE_UNAVAILABLE.into_response(),
             error => {
                 // should all be unreachable
                 relay_log::error!(error = error as &dyn Error, "unreachable code");
@@ -152,7 +153,7 @@ impl UpstreamRequest for ForwardRequest {
 
         builder
             .header("X-Forwarded-For", self.forwarded_for.as_ref())
-            .body(&self.data)
+            .body(self.data.clone())
     }
 
     fn respond(
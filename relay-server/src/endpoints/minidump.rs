@@ -151,7 +151,7 @@ where
     let id = envelope.event_id();
 
     // Never respond with a 429 since clients often retry these
-    match common::handle_envelope(&state, envelope).await {
+    match common::handle_envelope(&state, envelope, "minidump").await {
         Ok(_) | Err(BadStoreRequest::RateLimited(_)) => (),
         Err(error) => return Err(error.into()),
     };
@@ -3,6 +3,7 @@
 //! This module contains implementations for all supported relay endpoints, as well as a generic
 //! `forward` endpoint that sends unknown requests to the upstream.
 
+mod admin;
 mod attachments;
 mod common;
 #[cfg(feature = "dashboard")]
@@ -16,6 +17,8 @@ mod logs;
 mod minidump;
 mod monitor;
 mod outcomes;
+#[cfg(feature = "profiling")]
+mod profiling;
 mod project_configs;
 mod public_keys;
 mod security_report;
@@ -45,12 +48,20 @@ where
         .route("/dashboard/*file", get(dashboard::handle));
     // Relay-internal routes pointing to /api/relay/
     let internal_routes = Router::new()
+        .route("/api/relay/healthcheck/detail/", get(admin::get_health_detail))
         .route("/api/relay/healthcheck/:kind/", get(health_check::handle))
-        .route("/api/relay/events/:event_id/", get(events::handle));
+        .route("/api/relay/events/:event_id/", get(events::handle))
+        .route("/api/relay/events/:event_id/result/", get(events::handle_result))
+        .route("/api/relay/admin/overrides/", get(admin::get_overrides).put(admin::put_overrides))
+        .route("/api/relay/admin/ratelimits/:project_key/", get(admin::get_rate_limits));
     #[cfg(feature = "dashboard")]
     let internal_routes = internal_routes
         .route("/api/relay/logs/", get(logs::handle))
         .route("/api/relay/stats/", get(stats::handle));
+    #[cfg(feature = "profiling")]
+    let internal_routes = internal_routes
+        .route("/api/relay/admin/profile/cpu/", get(profiling::get_cpu_profile))
+        .route("/api/relay/admin/profile/heap/", get(profiling::get_heap_profile));
     let internal_routes = internal_routes
         // Fallback route, but with a name, and just on `/api/relay/*`.
         .route("/api/relay/*not_found", any(statics::not_found));
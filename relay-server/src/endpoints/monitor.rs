@@ -65,7 +65,7 @@ where
     envelope.add_item(item);
 
     // Never respond with a 429
-    match common::handle_envelope(&state, envelope).await {
+    match common::handle_envelope(&state, envelope, "monitor").await {
         Ok(_) | Err(BadStoreRequest::RateLimited(_)) => (),
         Err(error) => return Err(error.into()),
     };
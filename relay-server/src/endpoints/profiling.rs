@@ -0,0 +1,190 @@
+//! Admin endpoints to record CPU and heap profiles of a running Relay.
+//!
+//! Profiling must be enabled with `admin.profiling` and requires the `profiling` feature. Only one
+//! profile can be recorded at a time. CPU profiles are returned in the pprof protobuf format, heap
+//! profiles in the format of jemalloc's `jeprof`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use axum::extract::Query;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+use crate::endpoints::admin::{self, Access};
+use crate::service::ServiceState;
+
+/// The default duration of a profile in seconds.
+const DEFAULT_DURATION_SECS: u64 = 30;
+
+/// The maximum duration of a profile in seconds.
+const MAX_DURATION_SECS: u64 = 300;
+
+/// The sampling frequency of CPU profiles in Hz.
+const CPU_SAMPLE_FREQUENCY: i32 = 99;
+
+/// Set while a profile is being recorded.
+static IS_PROFILING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    /// The duration of the profile in seconds.
+    #[serde(default)]
+    seconds: Option<u64>,
+}
+
+impl ProfileQuery {
+    fn duration(&self) -> Duration {
+        let seconds = self.seconds.unwrap_or(DEFAULT_DURATION_SECS);
+        Duration::from_secs(seconds.clamp(1, MAX_DURATION_SECS))
+    }
+}
+
+/// Resets [`IS_PROFILING`] when the profile has been recorded.
+struct ProfilingGuard;
+
+impl ProfilingGuard {
+    fn acquire() -> Option<Self> {
+        IS_PROFILING
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()
+            .map(|_| Self)
+    }
+}
+
+impl Drop for ProfilingGuard {
+    fn drop(&mut self) {
+        IS_PROFILING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Checks access and runs the blocking `record` function on a dedicated thread.
+async fn run_profile<F>(state: ServiceState, headers: HeaderMap, record: F) -> Response
+where
+    F: FnOnce() -> Result<Vec<u8>, String> + Send + 'static,
+{
+    let config = state.config();
+    if let Err(response) = admin::authorize(config, &headers, Access::Write) {
+        return response;
+    }
+
+    if !config.admin_profiling_enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let Some(guard) = ProfilingGuard::acquire() else {
+        return (StatusCode::CONFLICT, "a profile is already being recorded").into_response();
+    };
+
+    let result = tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        record()
+    })
+    .await;
+
+    match result {
+        Ok(Ok(profile)) => profile.into_response(),
+        Ok(Err(message)) => {
+            relay_log::error!("failed to record profile: {message}");
+            (StatusCode::INTERNAL_SERVER_ERROR, message).into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Records a CPU profile for the requested duration.
+pub async fn get_cpu_profile(
+    state: ServiceState,
+    headers: HeaderMap,
+    Query(query): Query<ProfileQuery>,
+) -> Response {
+    let duration = query.duration();
+    run_profile(state, headers, move || record_cpu_profile(duration)).await
+}
+
+fn record_cpu_profile(duration: Duration) -> Result<Vec<u8>, String> {
+    use pprof::protos::Message;
+
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(CPU_SAMPLE_FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    std::thread::sleep(duration);
+
+    let profile = guard
+        .report()
+        .build()
+        .and_then(|report| report.pprof())
+        .map_err(|e| e.to_string())?;
+
+    let mut body = Vec::new();
+    profile.encode(&mut body).map_err(|e| e.to_string())?;
+    Ok(body)
+}
+
+/// Samples heap allocations for the requested duration and returns a heap profile.
+pub async fn get_heap_profile(
+    state: ServiceState,
+    headers: HeaderMap,
+    Query(query): Query<ProfileQuery>,
+) -> Response {
+    let duration = query.duration();
+    run_profile(state, headers, move || record_heap_profile(duration)).await
+}
+
+#[cfg(target_os = "linux")]
+fn record_heap_profile(duration: Duration) -> Result<Vec<u8>, String> {
+    use std::ffi::CString;
+
+    use tikv_jemalloc_ctl::raw;
+
+    const PROF_ACTIVE: &[u8] = b"prof.active\0";
+    const PROF_DUMP: &[u8] = b"prof.dump\0";
+
+    // Fails if jemalloc has been built without profiling or `prof` is not enabled on startup.
+    unsafe { raw::write(PROF_ACTIVE, true) }
+        .map_err(|e| format!("heap profiling is not available: {e}"))?;
+
+    std::thread::sleep(duration);
+
+    let path = std::env::temp_dir().join(format!("relay-heap-{}.prof", std::process::id()));
+    let c_path = CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+
+    let dumped = unsafe { raw::write(PROF_DUMP, c_path.as_ptr()) };
+    unsafe { raw::write(PROF_ACTIVE, false) }.ok();
+    dumped.map_err(|e| format!("failed to dump heap profile: {e}"))?;
+
+    let profile = std::fs::read(&path).map_err(|e| e.to_string());
+    std::fs::remove_file(&path).ok();
+    profile
+}
+
+#[cfg(not(target_os = "linux"))]
+fn record_heap_profile(_duration: Duration) -> Result<Vec<u8>, String> {
+    Err("heap profiles are only supported on Linux".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_duration() {
+        let query = |seconds| ProfileQuery { seconds }.duration().as_secs();
+        assert_eq!(query(None), DEFAULT_DURATION_SECS);
+        assert_eq!(query(Some(0)), 1);
+        assert_eq!(query(Some(10)), 10);
+        assert_eq!(query(Some(3600)), MAX_DURATION_SECS);
+    }
+
+    #[test]
+    fn test_profiling_guard() {
+        let guard = ProfilingGuard::acquire().unwrap();
+        assert!(ProfilingGuard::acquire().is_none());
+        drop(guard);
+        assert!(ProfilingGuard::acquire().is_some());
+    }
+}
@@ -73,12 +73,17 @@ impl ProjectStateWrapper {
 /// Version 3 also adds a list of projects whose response is pending.  A [`ProjectKey`] should never
 /// be in both collections. This list is always empty before V3. If `global` is
 /// enabled, version 3 also responds with [`GlobalConfig`].
+///
+/// Projects whose config still matches the revision sent by the downstream Relay are listed in
+/// `unchanged` instead of `configs`.
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct GetProjectStatesResponseWrapper {
     configs: HashMap<ProjectKey, Option<ProjectStateWrapper>>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pending: Vec<ProjectKey>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unchanged: Vec<ProjectKey>,
     #[serde(skip_serializing_if = "Option::is_none")]
     global: Option<Arc<GlobalConfig>>,
 }
@@ -92,6 +97,8 @@ struct GetProjectStatesResponseWrapper {
 struct GetProjectStatesRequest {
     public_keys: Vec<ErrorBoundary<ProjectKey>>,
     #[serde(default)]
+    revisions: Vec<Option<String>>,
+    #[serde(default)]
     full_config: bool,
     #[serde(default)]
     no_cache: bool,
@@ -106,17 +113,32 @@ async fn inner(
 ) -> Result<impl IntoResponse, ServiceUnavailable> {
     let SignedJson { inner, relay } = body;
     let project_cache = &state.project_cache().clone();
+    let project_states = state.project_states();
+    let config = state.config();
 
     let no_cache = inner.no_cache;
     let keys_len = inner.public_keys.len();
 
+    // Revisions are aligned with public keys, so they have to be paired before skipping keys.
+    let revisions = inner.revisions.into_iter().chain(std::iter::repeat(None));
+
     // Skip unparsable public keys. The downstream Relay will consider them `ProjectState::missing`.
-    let valid_keys = inner.public_keys.into_iter().filter_map(|e| e.ok());
-    let futures = valid_keys.map(|project_key| async move {
+    let valid_keys = inner
+        .public_keys
+        .into_iter()
+        .zip(revisions)
+        .filter_map(|(key, revision)| Some((key.ok()?, revision)));
+    let futures = valid_keys.map(|(project_key, revision)| async move {
         let state_result = if version.version >= ENDPOINT_V3 && !no_cache {
-            project_cache
-                .send(GetCachedProjectState::new(project_key))
-                .await
+            // Serve fresh states from the snapshots without waiting for the project cache.
+            match project_states.get_fresh(project_key, config) {
+                Some(project_state) => Ok(Some(project_state)),
+                None => {
+                    project_cache
+                        .send(GetCachedProjectState::new(project_key))
+                        .await
+                }
+            }
         } else {
             project_cache
                 .send(GetProjectState::new(project_key).no_cache(no_cache))
@@ -124,31 +146,34 @@ async fn inner(
                 .map(Some)
         };
 
-        (project_key, state_result)
+        (project_key, revision, state_result)
     });
 
     let mut configs = HashMap::with_capacity(keys_len);
     let mut pending = Vec::with_capacity(keys_len);
+    let mut unchanged = Vec::new();
     let global_config = match inner.global {
         true => Some(state.global_config().send(global_config::Get).await?),
         false => None,
     };
 
-    for (project_key, state_result) in future::join_all(futures).await {
+    for (project_key, revision, state_result) in future::join_all(futures).await {
         let Some(project_state) = state_result? else {
             pending.push(project_key);
             continue;
         };
 
         // If public key is known (even if rate-limited, which is Some(false)), it has
-        // access to the project config
+        // access to the project config. During a key rotation, previous keys are trusted as well.
         let has_access = relay.internal
-            || project_state
-                .config
-                .trusted_relays
-                .contains(&relay.public_key);
-
-        if has_access {
+            || relay
+                .public_keys()
+                .iter()
+                .any(|key| project_state.config.trusted_relays.contains(key));
+
+        if has_access && revision.is_some() && revision == project_state.rev {
+            unchanged.push(project_key);
+        } else if has_access {
             let full = relay.internal && inner.full_config;
             let wrapper = ProjectStateWrapper::new((*project_state).clone(), full);
             configs.insert(project_key, Some(wrapper));
@@ -164,6 +189,7 @@ async fn inner(
     Ok(Json(GetProjectStatesResponseWrapper {
         configs,
         pending,
+        unchanged,
         global: global_config,
     }))
 }
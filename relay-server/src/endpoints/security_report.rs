@@ -83,7 +83,7 @@ async fn handle(
     }
 
     let envelope = params.extract_envelope()?;
-    common::handle_envelope(&state, envelope).await?;
+    common::handle_envelope(&state, envelope, "security").await?;
     Ok(().into_response())
 }
 
@@ -114,7 +114,7 @@ async fn handle_post(
         _ => parse_event(body, meta, state.config())?,
     };
 
-    let id = common::handle_envelope(&state, envelope).await?;
+    let id = common::handle_envelope(&state, envelope, "store").await?;
     Ok(axum::Json(PostResponse { id }).into_response())
 }
 
@@ -139,7 +139,7 @@ async fn handle_get(
     Query(query): Query<GetQuery>,
 ) -> Result<impl IntoResponse, BadStoreRequest> {
     let envelope = parse_event(query.sentry_data.into(), meta, state.config())?;
-    common::handle_envelope(&state, envelope).await?;
+    common::handle_envelope(&state, envelope, "store").await?;
     Ok(([(header::CONTENT_TYPE, "image/gif")], PIXEL))
 }
 
@@ -46,12 +46,16 @@ use relay_sampling::DynamicSamplingContext;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
+use uuid::Uuid;
 
 use crate::constants::DEFAULT_EVENT_RETENTION;
 use crate::extractors::{PartialMeta, RequestMeta};
 
 pub const CONTENT_TYPE: &str = "application/x-sentry-envelope";
 
+/// Estimated size of serialized envelope and item headers, used to preallocate buffers.
+const HEADER_SIZE_ESTIMATE: usize = 128;
+
 #[derive(Debug, thiserror::Error)]
 pub enum EnvelopeError {
     #[error("unexpected end of file")]
@@ -83,6 +87,10 @@ pub enum ItemType {
     Security,
     /// Raw payload of an arbitrary attachment.
     Attachment,
+    /// Raw payload of an attachment that belongs to a trace rather than a single event.
+    ///
+    /// The trace is identified by the `trace_id` item header.
+    TraceAttachment,
     /// Multipart form data collected into a stream of JSON tuples.
     FormData,
     /// Security report as sent by the browser in JSON.
@@ -91,6 +99,8 @@ pub enum ItemType {
     UnrealReport,
     /// User feedback encoded as JSON.
     UserReport,
+    /// User feedback in the format of the feedback product, encoded as JSON.
+    Feedback,
     /// Session update data.
     Session,
     /// Aggregated session data.
@@ -99,18 +109,26 @@ pub enum ItemType {
     Statsd,
     /// Buckets of preaggregated metrics encoded as JSON.
     MetricBuckets,
+    /// Additional metadata for metrics, such as code locations, encoded as JSON.
+    MetricMeta,
     /// Client internal report (eg: outcomes).
     ClientReport,
     /// Profile event payload encoded as JSON.
     Profile,
+    /// Chunk of a continuous profile encoded as JSON.
+    ProfileChunk,
     /// Replay metadata and breadcrumb payload.
     ReplayEvent,
     /// Replay Recording data.
     ReplayRecording,
+    /// Video recording of a mobile replay segment.
+    ReplayVideo,
     /// Monitor check-in encoded as JSON.
     CheckIn,
     /// A standalone span.
     Span,
+    /// A log record encoded as JSON.
+    Log,
     /// A new item type that is yet unknown by this version of Relay.
     ///
     /// By default, items of this type are forwarded without modification. Processing Relays and
@@ -140,20 +158,26 @@ impl fmt::Display for ItemType {
             Self::Transaction => write!(f, "transaction"),
             Self::Security => write!(f, "security"),
             Self::Attachment => write!(f, "attachment"),
+            Self::TraceAttachment => write!(f, "trace_attachment"),
             Self::FormData => write!(f, "form_data"),
             Self::RawSecurity => write!(f, "raw_security"),
             Self::UnrealReport => write!(f, "unreal_report"),
             Self::UserReport => write!(f, "user_report"),
+            Self::Feedback => write!(f, "feedback"),
             Self::Session => write!(f, "session"),
             Self::Sessions => write!(f, "sessions"),
             Self::Statsd => write!(f, "statsd"),
             Self::MetricBuckets => write!(f, "metric_buckets"),
+            Self::MetricMeta => write!(f, "metric_meta"),
             Self::ClientReport => write!(f, "client_report"),
             Self::Profile => write!(f, "profile"),
+            Self::ProfileChunk => write!(f, "profile_chunk"),
             Self::ReplayEvent => write!(f, "replay_event"),
             Self::ReplayRecording => write!(f, "replay_recording"),
+            Self::ReplayVideo => write!(f, "replay_video"),
             Self::CheckIn => write!(f, "check_in"),
             Self::Span => write!(f, "span"),
+            Self::Log => write!(f, "log"),
             Self::Unknown(s) => s.fmt(f),
         }
     }
@@ -168,20 +192,26 @@ impl std::str::FromStr for ItemType {
             "transaction" => Self::Transaction,
             "security" => Self::Security,
             "attachment" => Self::Attachment,
+            "trace_attachment" => Self::TraceAttachment,
             "form_data" => Self::FormData,
             "raw_security" => Self::RawSecurity,
             "unreal_report" => Self::UnrealReport,
             "user_report" => Self::UserReport,
+            "feedback" => Self::Feedback,
             "session" => Self::Session,
             "sessions" => Self::Sessions,
             "statsd" => Self::Statsd,
             "metric_buckets" => Self::MetricBuckets,
+            "metric_meta" => Self::MetricMeta,
             "client_report" => Self::ClientReport,
             "profile" => Self::Profile,
+            "profile_chunk" => Self::ProfileChunk,
             "replay_event" => Self::ReplayEvent,
             "replay_recording" => Self::ReplayRecording,
+            "replay_video" => Self::ReplayVideo,
             "check_in" => Self::CheckIn,
             "span" => Self::Span,
+            "log" => Self::Log,
             other => Self::Unknown(other.to_owned()),
         })
     }
@@ -451,6 +481,10 @@ pub struct ItemHeaders {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     filename: Option<String>,
 
+    /// If this is a trace attachment, this contains the trace the attachment belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trace_id: Option<Uuid>,
+
     /// Indicates that this item is being rate limited.
     ///
     /// By default, rate limited items are immediately removed from Envelopes. For processing,
@@ -462,6 +496,22 @@ pub struct ItemHeaders {
     #[serde(default, skip)]
     rate_limited: bool,
 
+    /// Indicates that this span was extracted from a transaction.
+    ///
+    /// The transaction accounts for its spans, so extracted spans do not generate outcomes or
+    /// count towards the span quota.
+    ///
+    /// NOTE: This is internal-only and not exposed into the Envelope.
+    #[serde(default, skip)]
+    from_transaction: bool,
+
+    /// The duration of the samples of a profile chunk in milliseconds.
+    ///
+    /// Computed once when the envelope is received, replacing any value sent by the client, and
+    /// used as the quantity of the item.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile_duration_ms: Option<u64>,
+
     /// A list of cumulative sample rates applied to this event.
     ///
     /// Multiple entries in `sample_rates` mean that the event was sampled multiple times. The
@@ -499,7 +549,10 @@ impl Item {
                 attachment_type: None,
                 content_type: None,
                 filename: None,
+                trace_id: None,
                 rate_limited: false,
+                from_transaction: false,
+                profile_duration_ms: None,
                 sample_rates: None,
                 other: BTreeMap::new(),
                 metrics_extracted: false,
@@ -520,10 +573,14 @@ impl Item {
 
     /// Returns the number used for counting towards rate limits and producing outcomes.
     ///
-    /// For attachments, we count the number of bytes. Other items are counted as 1.
+    /// For attachments and logs, we count the number of bytes. Profile chunks are counted by the
+    /// duration of their samples in milliseconds, as computed by
+    /// [`set_profile_chunk_duration`](Self::set_profile_chunk_duration). Other items are counted
+    /// as 1.
     pub fn quantity(&self) -> usize {
         match self.ty() {
-            ItemType::Attachment => self.len().max(1),
+            ItemType::Attachment | ItemType::TraceAttachment | ItemType::Log => self.len().max(1),
+            ItemType::ProfileChunk => self.headers.profile_duration_ms.unwrap_or(0).max(1) as usize,
             _ => 1,
         }
     }
@@ -541,21 +598,27 @@ impl Item {
             }),
             ItemType::Security | ItemType::RawSecurity => Some(DataCategory::Security),
             ItemType::UnrealReport => Some(DataCategory::Error),
-            ItemType::Attachment => Some(DataCategory::Attachment),
+            ItemType::Attachment | ItemType::TraceAttachment => Some(DataCategory::Attachment),
             ItemType::Session | ItemType::Sessions => None,
-            ItemType::Statsd | ItemType::MetricBuckets => None,
+            ItemType::Statsd | ItemType::MetricBuckets | ItemType::MetricMeta => None,
             ItemType::FormData => None,
             ItemType::UserReport => None,
+            ItemType::Feedback => Some(DataCategory::Feedback),
             ItemType::Profile => Some(if indexed {
                 DataCategory::ProfileIndexed
             } else {
                 DataCategory::Profile
             }),
-            ItemType::ReplayEvent | ItemType::ReplayRecording => Some(DataCategory::Replay),
+            ItemType::ProfileChunk => Some(DataCategory::ProfileDuration),
+            ItemType::ReplayEvent | ItemType::ReplayRecording | ItemType::ReplayVideo => {
+                Some(DataCategory::Replay)
+            }
             ItemType::ClientReport => None,
             ItemType::CheckIn => Some(DataCategory::Monitor),
             ItemType::Unknown(_) => None,
-            ItemType::Span => None, // No outcomes, for now
+            ItemType::Span if self.from_transaction() => None,
+            ItemType::Span => Some(DataCategory::Span),
+            ItemType::Log => Some(DataCategory::LogByte),
         }
     }
 
@@ -618,6 +681,11 @@ impl Item {
         self.headers.filename = Some(filename.into());
     }
 
+    /// Returns the trace ID of this item, if it is a trace attachment.
+    pub fn trace_id(&self) -> Option<Uuid> {
+        self.headers.trace_id
+    }
+
     /// Returns whether this item should be rate limited.
     pub fn rate_limited(&self) -> bool {
         self.headers.rate_limited
@@ -628,6 +696,24 @@ impl Item {
         self.headers.rate_limited = rate_limited;
     }
 
+    /// Returns whether this span was extracted from a transaction.
+    pub fn from_transaction(&self) -> bool {
+        self.headers.from_transaction
+    }
+
+    /// Sets whether this span was extracted from a transaction.
+    pub fn set_from_transaction(&mut self, from_transaction: bool) {
+        self.headers.from_transaction = from_transaction;
+    }
+
+    /// Computes the duration of the samples of a profile chunk and stores it in the headers.
+    ///
+    /// The duration is cleared if the samples cannot be read.
+    pub fn set_profile_chunk_duration(&mut self) {
+        self.headers.profile_duration_ms = relay_profiling::profile_chunk_duration(&self.payload)
+            .map(|duration| duration.as_millis() as u64);
+    }
+
     /// Removes sample rates from the headers, if any.
     pub fn take_sample_rates(&mut self) -> Option<Value> {
         self.headers.sample_rates.take()
@@ -704,18 +790,26 @@ impl Item {
             // report. For this reason, FormData alone does not constitute an event item.
             ItemType::FormData => false,
 
+            // Trace attachments belong to a trace and never create events.
+            ItemType::TraceAttachment => false,
+
             // The remaining item types cannot carry event payloads.
             ItemType::UserReport
+            | ItemType::Feedback
             | ItemType::Session
             | ItemType::Sessions
             | ItemType::Statsd
             | ItemType::MetricBuckets
+            | ItemType::MetricMeta
             | ItemType::ClientReport
             | ItemType::ReplayEvent
             | ItemType::ReplayRecording
+            | ItemType::ReplayVideo
             | ItemType::Profile
+            | ItemType::ProfileChunk
             | ItemType::CheckIn
-            | ItemType::Span => false,
+            | ItemType::Span
+            | ItemType::Log => false,
 
             // The unknown item type can observe any behavior, most likely there are going to be no
             // item types added that create events.
@@ -732,20 +826,26 @@ impl Item {
             ItemType::Transaction => true,
             ItemType::Security => true,
             ItemType::Attachment => true,
+            ItemType::TraceAttachment => false,
             ItemType::FormData => true,
             ItemType::RawSecurity => true,
             ItemType::UnrealReport => true,
             ItemType::UserReport => true,
+            ItemType::Feedback => false,
             ItemType::ReplayEvent => true,
             ItemType::Session => false,
             ItemType::Sessions => false,
             ItemType::Statsd => false,
             ItemType::MetricBuckets => false,
+            ItemType::MetricMeta => false,
             ItemType::ClientReport => false,
             ItemType::ReplayRecording => false,
+            ItemType::ReplayVideo => false,
             ItemType::Profile => true,
+            ItemType::ProfileChunk => false,
             ItemType::CheckIn => false,
             ItemType::Span => false,
+            ItemType::Log => false,
 
             // Since this Relay cannot interpret the semantics of this item, it does not know
             // whether it requires an event or not. Depending on the strategy, this can cause two
@@ -884,6 +984,8 @@ impl Envelope {
         bytes: Bytes,
         request_meta: RequestMeta,
     ) -> Result<Box<Self>, EnvelopeError> {
+        let _span = relay_log::debug_span!("envelope.parse", bytes = bytes.len()).entered();
+
         let (partial_headers, offset) = Self::parse_headers::<PartialMeta>(&bytes)?;
         let mut headers = partial_headers.complete(request_meta)?;
 
@@ -1105,8 +1207,17 @@ impl Envelope {
     }
 
     /// Serializes this envelope into a buffer.
+    ///
+    /// The buffer is preallocated for the size of all payloads, so that large attachments are not
+    /// copied while the buffer grows.
     pub fn to_vec(&self) -> Result<Vec<u8>, EnvelopeError> {
-        let mut vec = Vec::new(); // TODO: Preallocate?
+        let capacity = self
+            .items
+            .iter()
+            .map(|item| item.len() + HEADER_SIZE_ESTIMATE)
+            .sum::<usize>();
+
+        let mut vec = Vec::with_capacity(capacity + HEADER_SIZE_ESTIMATE);
         self.serialize(&mut vec)?;
         Ok(vec)
     }
@@ -1696,4 +1807,23 @@ mod tests {
             assert_eq!(item.ty(), &ItemType::Attachment);
         }
     }
+
+    #[test]
+    fn test_profile_chunk_quantity() {
+        let mut item = Item::new(ItemType::ProfileChunk);
+        item.set_payload(
+            ContentType::Json,
+            r#"{"profile": {"samples": [{"timestamp": 1710958503.0}, {"timestamp": 1710958503.5}]}}"#,
+        );
+        assert_eq!(item.quantity(), 1);
+
+        item.set_profile_chunk_duration();
+        assert_eq!(item.quantity(), 500);
+
+        // The duration survives serialization, for example through the spool.
+        let mut envelope = Envelope::from_request(None, request_meta());
+        envelope.add_item(item);
+        let envelope = Envelope::parse_bytes(envelope.to_vec().unwrap().into()).unwrap();
+        assert_eq!(envelope.items().next().unwrap().quantity(), 500);
+    }
 }
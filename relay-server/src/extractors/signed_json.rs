@@ -1,5 +1,7 @@
+use std::net::SocketAddr;
+
 use axum::extract::rejection::BytesRejection;
-use axum::extract::FromRequest;
+use axum::extract::{ConnectInfo, FromRequest};
 use axum::http::{Request, StatusCode};
 use axum::response::{IntoResponse, Response};
 use bytes::Bytes;
@@ -9,7 +11,7 @@ use serde::de::DeserializeOwned;
 
 use crate::actors::relays::GetRelay;
 use crate::service::ServiceState;
-use crate::utils::ApiErrorResponse;
+use crate::utils::{self, ApiErrorResponse};
 
 #[derive(Debug)]
 pub struct SignedJson<T> {
@@ -27,6 +29,8 @@ pub enum SignatureError {
     MalformedHeader(&'static str),
     #[error("unknown relay id")]
     UnknownRelay,
+    #[error("client certificate does not match relay id")]
+    CertificateMismatch,
     #[error(transparent)]
     MalformedBody(#[from] BytesRejection),
     #[error("invalid JSON data")]
@@ -92,6 +96,40 @@ where
         // Track the relay header value even if is not a string.
         relay_log::configure_scope(|s| s.set_tag("relay_id", relay_id.to_string()));
 
+        // The certificate header can be set by any client, so it is only honored if the request
+        // was forwarded by a trusted proxy.
+        let trusted_proxy = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or(false, |ConnectInfo(peer)| {
+                state.config().is_client_certificate_proxy(peer.ip())
+            });
+
+        // Relays authenticated by a client certificate do not sign their requests.
+        let certificate_relay = state
+            .config()
+            .client_certificate_header()
+            .filter(|_| trusted_proxy)
+            .and_then(|name| request.headers().get(name))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| utils::client_certificate_relay(state.config(), value));
+
+        if let Some(certificate_relay) = certificate_relay {
+            if certificate_relay != relay_id {
+                return Err(SignatureError::CertificateMismatch);
+            }
+
+            let relay = state
+                .relay_cache()
+                .send(GetRelay { relay_id })
+                .await?
+                .ok_or(SignatureError::UnknownRelay)?;
+
+            let body = Bytes::from_request(request, state).await?;
+            let inner = serde_json::from_slice(&body)?;
+            return Ok(SignedJson { inner, relay });
+        }
+
         let signature = get_header(&request, "x-sentry-relay-signature")?.to_owned();
 
         let relay = state
@@ -101,7 +139,7 @@ where
             .ok_or(SignatureError::UnknownRelay)?;
 
         let body = Bytes::from_request(request, state).await?;
-        let inner = relay.public_key.unpack(&body, &signature, None)?;
+        let inner = relay.public_keys().unpack(&body, &signature, None)?;
         Ok(SignedJson { inner, relay })
     }
 }
@@ -10,11 +10,41 @@
 //! logic.
 use std::io;
 
-use relay_config::HttpEncoding;
+use bytes::Bytes;
+use relay_config::{Config, HttpEncoding};
 use reqwest::header::{HeaderMap, HeaderValue};
 pub use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
 
+use crate::utils;
+
+/// Creates a client builder with the timeouts, connection pool, proxy and TLS settings from the
+/// configuration.
+///
+/// All outbound HTTP clients should be created through this function, so that they honor the
+/// `http.proxy_url` and TLS settings.
+pub fn client_builder(config: &Config) -> anyhow::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::ClientBuilder::new()
+        .connect_timeout(config.http_connection_timeout())
+        .timeout(config.http_timeout())
+        .pool_idle_timeout(config.http_pool_idle_timeout())
+        .tcp_keepalive(config.http_tcp_keepalive());
+
+    if let Some(max_idle) = config.http_pool_max_idle_per_host() {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+
+    if let Some(url) = config.http_proxy_url() {
+        let mut proxy = reqwest::Proxy::all(url)?;
+        if let Some((username, password)) = config.http_proxy_auth() {
+            proxy = proxy.basic_auth(username, password);
+        }
+        builder = builder.proxy(proxy);
+    }
+
+    utils::configure_tls(builder, config)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum HttpError {
     #[error("payload too large")]
@@ -80,9 +110,11 @@ impl RequestBuilder {
         self.header_opt("content-encoding", encoding.name())
     }
 
-    pub fn body<B: AsRef<[u8]>>(mut self, body: B) -> Result<Request, HttpError> {
-        // TODO: This clones data. Change the signature to require `Bytes` to prevent cloning.
-        self.builder = self.builder.body(body.as_ref().to_vec());
+    /// Sets the request body and builds the request.
+    ///
+    /// The body is reference counted, so that retries of a request share the same buffer.
+    pub fn body(mut self, body: Bytes) -> Result<Request, HttpError> {
+        self.builder = self.builder.body(body);
         self.finish()
     }
 }
@@ -254,13 +254,16 @@
 #![allow(clippy::derive_partial_eq_without_eq)]
 
 mod actors;
+mod capture;
 mod constants;
 mod endpoints;
 mod envelope;
 mod extractors;
 mod http;
+mod loadgen;
 mod metrics_extraction;
 mod middlewares;
+mod send_event;
 mod service;
 mod statsd;
 mod utils;
@@ -268,26 +271,37 @@ mod utils;
 #[cfg(test)]
 mod testutils;
 
+use std::path::Path;
 use std::sync::Arc;
 
 use relay_config::Config;
+use relay_event_schema::protocol::EventId;
 use relay_system::{Controller, Service};
 
+use crate::actors::secrets::{load_secrets, SecretsService};
 use crate::actors::server::HttpServer;
 use crate::service::{Runtimes, ServiceState};
 
+pub use crate::actors::test_store::TrackedResult;
+pub use crate::capture::ReplayReport;
+pub use crate::loadgen::{LoadKind, LoadMix, LoadgenOptions, LoadgenReport};
+pub use crate::send_event::{SendEventOptions, SendEventReport};
+
 /// Runs a relay web server and spawns all internal worker threads.
 ///
 /// This effectively boots the entire server application. It blocks the current thread until a
 /// shutdown signal is received or a fatal error happens. Behavior of the server is determined by
 /// the `config` passed into this funciton.
-pub fn run(config: Config) -> anyhow::Result<()> {
-    let config = Arc::new(config);
+pub fn run(mut config: Config) -> anyhow::Result<()> {
     relay_log::info!("relay server starting");
 
     // Creates the main runtime.
     let main_runtime = crate::service::create_runtime("main-rt", config.cpu_concurrency());
 
+    // Secrets must be loaded before the configuration is shared with services.
+    let secrets = main_runtime.block_on(load_secrets(&mut config))?;
+    let config = Arc::new(config);
+
     // Create secondary service runtimes.
     //
     // Runtimes must not be dropped within other runtimes, so keep them alive here.
@@ -297,16 +311,52 @@ pub fn run(config: Config) -> anyhow::Result<()> {
     // web server and run all relevant services. See the `actors` module documentation for more
     // information on all services.
     main_runtime.block_on(async {
+        // The OTLP exporter runs on the main runtime, which must exist before it is created.
+        #[cfg(feature = "otlp")]
+        relay_log::otlp::start(&config.effective_logging().otlp)?;
+
         Controller::start(config.shutdown_timeout());
-        let service = ServiceState::start(config.clone(), &runtimes)?;
+        let service = ServiceState::start(config.clone(), &runtimes).await?;
+        if let Some(secrets) = secrets {
+            SecretsService::new(config.clone(), secrets, service.audit_log().clone())?.start();
+        }
         HttpServer::new(config, service.clone())?.start();
         Controller::shutdown_handle().finished().await;
         anyhow::Ok(())
     })?;
 
+    // Flush pending spans while the runtime running the exporter is still alive.
+    #[cfg(feature = "otlp")]
+    relay_log::otlp::shutdown();
+
     drop(runtimes);
     drop(main_runtime);
 
     relay_log::info!("relay shutdown complete");
     Ok(())
 }
+
+/// Replays requests recorded via `capture.path` against the Relay at `target`.
+///
+/// Requests are sent in the order they were recorded. If `realtime` is set, the timing of the
+/// original traffic is preserved, otherwise requests are sent one after another.
+pub fn replay(path: &Path, target: &str, realtime: bool) -> anyhow::Result<ReplayReport> {
+    let runtime = crate::service::create_runtime("replay-rt", 1);
+    runtime.block_on(crate::capture::replay(path, target, realtime))
+}
+
+/// Sends generated envelopes to a Relay and reports their acceptance latency.
+///
+/// See [`LoadgenOptions`] for the rate, duration, and kinds of generated envelopes.
+pub fn loadgen(options: LoadgenOptions) -> anyhow::Result<LoadgenReport> {
+    let runtime = crate::service::create_runtime("loadgen-rt", 2);
+    runtime.block_on(crate::loadgen::loadgen(options))
+}
+
+/// Submits a test event to a Relay and waits until it is sent to the upstream or dropped.
+///
+/// Returns the ID of the submitted event along with its result.
+pub fn send_event(options: SendEventOptions) -> anyhow::Result<(EventId, SendEventReport)> {
+    let runtime = crate::service::create_runtime("send-event-rt", 1);
+    runtime.block_on(crate::send_event::send_event(options))
+}
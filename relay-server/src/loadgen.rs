@@ -0,0 +1,457 @@
+//! Synthetic load generation for capacity planning.
+//!
+//! The `relay loadgen` command sends generated envelopes to a Relay at a fixed rate with
+//! [`loadgen`]. Envelopes contain errors, transactions with spans, sessions, or metrics in a
+//! configurable [mix](LoadMix). Their payload sizes are drawn uniformly from a configurable range:
+//! errors are padded, transactions receive more spans, and metrics more buckets. Sessions have a
+//! fixed size.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use bytes::Bytes;
+use chrono::Utc;
+use rand::distributions::{Alphanumeric, DistString, Distribution, WeightedIndex};
+use rand::Rng;
+use relay_common::Dsn;
+use serde_json::json;
+use tokio::sync::Semaphore;
+use tokio::time::{Instant, MissedTickBehavior};
+use uuid::Uuid;
+
+use crate::constants::CLIENT;
+
+/// The approximate size of a serialized span in bytes.
+const SPAN_SIZE: usize = 250;
+
+/// The maximum number of spans in a generated transaction.
+const MAX_SPANS: usize = 1000;
+
+/// The approximate size of a statsd metric line in bytes.
+const METRIC_SIZE: usize = 40;
+
+/// The kind of payload contained in a generated envelope.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LoadKind {
+    /// An error event with an exception and stack trace.
+    Error,
+    /// A transaction event with spans.
+    Transaction,
+    /// A session update.
+    Session,
+    /// Metrics in statsd format.
+    Metrics,
+}
+
+impl LoadKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Transaction => "transaction",
+            Self::Session => "session",
+            Self::Metrics => "metrics",
+        }
+    }
+}
+
+impl fmt::Display for LoadKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for LoadKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "transaction" => Ok(Self::Transaction),
+            "session" => Ok(Self::Session),
+            "metrics" => Ok(Self::Metrics),
+            _ => Err(anyhow::anyhow!("unknown envelope kind `{s}`")),
+        }
+    }
+}
+
+/// Relative weights of the kinds of generated envelopes.
+///
+/// Parses from a comma-separated list of `kind=weight` pairs, for example
+/// `error=4,transaction=4,session=1,metrics=1`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LoadMix(Vec<(LoadKind, u32)>);
+
+impl Default for LoadMix {
+    fn default() -> Self {
+        Self(vec![
+            (LoadKind::Error, 4),
+            (LoadKind::Transaction, 4),
+            (LoadKind::Session, 1),
+            (LoadKind::Metrics, 1),
+        ])
+    }
+}
+
+impl FromStr for LoadMix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let weights = s
+            .split(',')
+            .map(|pair| {
+                let (kind, weight) = pair
+                    .split_once('=')
+                    .with_context(|| format!("expected `kind=weight`, got `{pair}`"))?;
+                let weight = weight
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid weight for `{kind}`"))?;
+                Ok((kind.trim().parse()?, weight))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if weights.iter().all(|(_, weight)| *weight == 0) {
+            anyhow::bail!("at least one weight must be greater than zero");
+        }
+
+        Ok(Self(weights))
+    }
+}
+
+/// Options for [`loadgen`].
+#[derive(Clone, Debug)]
+pub struct LoadgenOptions {
+    /// The base URL of the Relay that receives envelopes.
+    pub target: String,
+    /// The DSN used to authenticate envelopes. Its host is ignored in favor of `target`.
+    pub dsn: String,
+    /// The number of envelopes sent per second.
+    pub rate: f64,
+    /// The duration of the load test.
+    pub duration: Duration,
+    /// The maximum number of requests in flight.
+    ///
+    /// If this is reached, the actual rate drops below the requested rate.
+    pub concurrency: usize,
+    /// The kinds of generated envelopes.
+    pub mix: LoadMix,
+    /// The minimum payload size in bytes.
+    pub min_size: usize,
+    /// The maximum payload size in bytes.
+    pub max_size: usize,
+}
+
+impl Default for LoadgenOptions {
+    fn default() -> Self {
+        Self {
+            target: String::new(),
+            dsn: String::new(),
+            rate: 10.0,
+            duration: Duration::from_secs(10),
+            concurrency: 16,
+            mix: LoadMix::default(),
+            min_size: 1024,
+            max_size: 16 * 1024,
+        }
+    }
+}
+
+/// The result of [`loadgen`].
+#[derive(Debug, Default)]
+pub struct LoadgenReport {
+    /// The number of envelopes sent.
+    pub sent: usize,
+    /// The number of responses per status code.
+    pub statuses: BTreeMap<u16, usize>,
+    /// The number of requests that failed without a response.
+    pub failed: usize,
+    /// The total duration of the load test, including pending requests.
+    pub elapsed: Duration,
+    /// Response latencies of all requests with a response, in ascending order.
+    pub latencies: Vec<Duration>,
+}
+
+impl LoadgenReport {
+    /// Returns the latency below which the given fraction of responses were received.
+    ///
+    /// The percentile must be between `0.0` and `1.0`. Returns `None` if there were no responses.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let max_index = self.latencies.len().checked_sub(1)?;
+        let index = (max_index as f64 * percentile.clamp(0.0, 1.0)).round() as usize;
+        self.latencies.get(index).copied()
+    }
+}
+
+/// Generates the envelope payload for an event item with the given padding.
+fn error_event(event_id: Uuid, size: usize) -> serde_json::Value {
+    let padding = Alphanumeric.sample_string(&mut rand::thread_rng(), size);
+
+    json!({
+        "event_id": event_id.simple().to_string(),
+        "timestamp": Utc::now().timestamp_millis() as f64 / 1000.0,
+        "platform": "python",
+        "level": "error",
+        "release": "loadgen@1.0.0",
+        "environment": "loadgen",
+        "exception": {
+            "values": [{
+                "type": "ValueError",
+                "value": "generated by relay loadgen",
+                "stacktrace": {
+                    "frames": [
+                        {"function": "main", "module": "loadgen", "lineno": 10},
+                        {"function": "run", "module": "loadgen.worker", "lineno": 42},
+                    ]
+                }
+            }]
+        },
+        "extra": {"padding": padding},
+    })
+}
+
+/// Generates a transaction with enough spans to reach approximately the given size.
+fn transaction_event(event_id: Uuid, size: usize) -> serde_json::Value {
+    let mut rng = rand::thread_rng();
+    let trace_id = Uuid::new_v4().simple().to_string();
+    let root_span_id = format!("{:016x}", rng.gen::<u64>());
+
+    let end = Utc::now().timestamp_millis() as f64 / 1000.0;
+    let start = end - rng.gen_range(0.05..2.0);
+
+    let span_count = (size / SPAN_SIZE).clamp(1, MAX_SPANS);
+    let spans = (0..span_count)
+        .map(|i| {
+            let span_start = start + (end - start) * rng.gen::<f64>();
+            json!({
+                "trace_id": trace_id,
+                "span_id": format!("{:016x}", rng.gen::<u64>()),
+                "parent_span_id": root_span_id,
+                "op": if i % 2 == 0 { "db.sql.query" } else { "http.client" },
+                "description": format!("loadgen span {i}"),
+                "start_timestamp": span_start,
+                "timestamp": span_start + (end - span_start) * rng.gen::<f64>(),
+                "status": "ok",
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "event_id": event_id.simple().to_string(),
+        "type": "transaction",
+        "transaction": "/loadgen/",
+        "transaction_info": {"source": "route"},
+        "start_timestamp": start,
+        "timestamp": end,
+        "platform": "python",
+        "release": "loadgen@1.0.0",
+        "environment": "loadgen",
+        "contexts": {
+            "trace": {
+                "trace_id": trace_id,
+                "span_id": root_span_id,
+                "op": "http.server",
+                "status": "ok",
+            }
+        },
+        "spans": spans,
+    })
+}
+
+/// Generates a session update for a new session.
+fn session_update() -> serde_json::Value {
+    json!({
+        "sid": Uuid::new_v4(),
+        "did": Uuid::new_v4(),
+        "started": Utc::now(),
+        "init": true,
+        "status": "exited",
+        "duration": rand::thread_rng().gen_range(1.0..600.0),
+        "attrs": {"release": "loadgen@1.0.0", "environment": "loadgen"},
+    })
+}
+
+/// Generates statsd lines with approximately the given size.
+fn statsd_metrics(size: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..(size / METRIC_SIZE).max(1))
+        .map(|i| match i % 3 {
+            0 => format!("loadgen.requests:{}|c|#route:/{i}", rng.gen_range(1..100)),
+            1 => format!("loadgen.duration@millisecond:{}|d", rng.gen_range(1..500)),
+            _ => format!("loadgen.users:{}|s", rng.gen_range(1..10_000)),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Generates a serialized envelope of the given kind.
+pub fn generate_envelope(kind: LoadKind, dsn: &Dsn, size: usize) -> Bytes {
+    let event_id = Uuid::new_v4();
+    let (item_type, payload, has_event) = match kind {
+        LoadKind::Error => ("event", error_event(event_id, size).to_string(), true),
+        LoadKind::Transaction => (
+            "transaction",
+            transaction_event(event_id, size).to_string(),
+            true,
+        ),
+        LoadKind::Session => ("session", session_update().to_string(), false),
+        LoadKind::Metrics => ("statsd", statsd_metrics(size), false),
+    };
+
+    let mut headers = json!({
+        "dsn": dsn.to_string(),
+        "sent_at": Utc::now(),
+    });
+    if has_event {
+        headers["event_id"] = json!(event_id.simple().to_string());
+    }
+
+    let item_headers = json!({"type": item_type, "length": payload.len()});
+    Bytes::from(format!("{headers}\n{item_headers}\n{payload}\n"))
+}
+
+/// Sends generated envelopes to a Relay and measures their acceptance latency.
+pub async fn loadgen(options: LoadgenOptions) -> anyhow::Result<LoadgenReport> {
+    let dsn = options.dsn.parse::<Dsn>().context("invalid DSN")?;
+    if !options.rate.is_finite() || options.rate <= 0.0 {
+        anyhow::bail!("rate must be a finite number greater than zero");
+    }
+
+    let url = format!(
+        "{}/api/{}/envelope/",
+        options.target.trim_end_matches('/'),
+        dsn.project_id()
+    );
+    let auth = format!(
+        "Sentry sentry_key={}, sentry_version=7, sentry_client={CLIENT}",
+        dsn.public_key()
+    );
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    let kinds = WeightedIndex::new(options.mix.0.iter().map(|(_, weight)| *weight))?;
+    let min_size = options.min_size.min(options.max_size);
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+
+    // Rates above one million per second are capped, since the interval period must not be zero.
+    let period = Duration::from_secs_f64(1.0 / options.rate).max(Duration::from_micros(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let started = Instant::now();
+    let deadline = started + options.duration;
+    let mut tasks = Vec::new();
+
+    while interval.tick().await < deadline {
+        let permit = semaphore.clone().acquire_owned().await?;
+
+        let body = {
+            let mut rng = rand::thread_rng();
+            let (kind, _) = options.mix.0[kinds.sample(&mut rng)];
+            let size = rng.gen_range(min_size..=options.max_size);
+            generate_envelope(kind, &dsn, size)
+        };
+
+        let request = client
+            .post(&url)
+            .header("content-type", "application/x-sentry-envelope")
+            .header("x-sentry-auth", &auth)
+            .body(body)
+            .send();
+
+        tasks.push(tokio::spawn(async move {
+            let start = Instant::now();
+            let result = request.await.map(|response| response.status().as_u16());
+            drop(permit);
+            (result, start.elapsed())
+        }));
+    }
+
+    let mut report = LoadgenReport::default();
+    for task in tasks {
+        let (result, latency) = task.await?;
+        report.sent += 1;
+
+        match result {
+            Ok(status) => {
+                *report.statuses.entry(status).or_default() += 1;
+                report.latencies.push(latency);
+            }
+            Err(error) => {
+                relay_log::debug!(
+                    error = &error as &dyn std::error::Error,
+                    "failed to send envelope"
+                );
+                report.failed += 1;
+            }
+        }
+    }
+
+    report.latencies.sort();
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::{Envelope, ItemType};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_mix() {
+        let mix = "error=1, metrics=3".parse::<LoadMix>().unwrap();
+        assert_eq!(mix.0, vec![(LoadKind::Error, 1), (LoadKind::Metrics, 3)]);
+
+        assert!("error".parse::<LoadMix>().is_err());
+        assert!("crash=1".parse::<LoadMix>().is_err());
+        assert!("error=0".parse::<LoadMix>().is_err());
+    }
+
+    #[test]
+    fn test_generate_envelopes() {
+        let dsn = "https://e12d836b15bb49d7bbf99e64295d995b@sentry.io/42"
+            .parse::<Dsn>()
+            .unwrap();
+
+        for (kind, ty) in [
+            (LoadKind::Error, ItemType::Event),
+            (LoadKind::Transaction, ItemType::Transaction),
+            (LoadKind::Session, ItemType::Session),
+            (LoadKind::Metrics, ItemType::Statsd),
+        ] {
+            let bytes = generate_envelope(kind, &dsn, 2048);
+            let envelope = Envelope::parse_bytes(bytes).unwrap();
+            let items = envelope.items().collect::<Vec<_>>();
+            assert_eq!(items.len(), 1, "{kind}");
+            assert_eq!(items[0].ty(), &ty, "{kind}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_rate() {
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let options = LoadgenOptions {
+                dsn: "https://e12d836b15bb49d7bbf99e64295d995b@sentry.io/42".to_owned(),
+                rate,
+                ..Default::default()
+            };
+            assert!(loadgen(options).await.is_err(), "{rate}");
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let report = LoadgenReport {
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(report.percentile(0.5), Some(Duration::from_millis(51)));
+        assert_eq!(report.percentile(1.0), Some(Duration::from_millis(100)));
+        assert_eq!(LoadgenReport::default().percentile(0.5), None);
+    }
+}
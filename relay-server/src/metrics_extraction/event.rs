@@ -59,6 +59,11 @@ pub fn extract_metrics(event: &Event, config: &MetricExtractionConfig) -> Vec<Bu
     metrics
 }
 
+/// Extracts metrics from a standalone [`Span`] that was not sent as part of a transaction.
+pub fn extract_span_metrics(span: &Span, config: &MetricExtractionConfig) -> Vec<Bucket> {
+    generic::extract_metrics(span, config)
+}
+
 #[cfg(test)]
 mod tests {
     use relay_dynamic_config::{Feature, FeatureSet, ProjectConfig};
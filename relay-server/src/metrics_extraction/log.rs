@@ -0,0 +1,154 @@
+use relay_common::time::UnixTimestamp;
+use relay_dynamic_config::{MetricExtractionConfig, MetricSpec, TagMapping, TagSource, TagSpec};
+use relay_event_schema::protocol::Log;
+use relay_metrics::Bucket;
+use relay_quotas::DataCategory;
+
+use crate::metrics_extraction::generic::{self, Extractable};
+
+impl Extractable for Log {
+    fn category(&self) -> DataCategory {
+        DataCategory::Log
+    }
+
+    fn timestamp(&self) -> Option<UnixTimestamp> {
+        self.timestamp
+            .value()
+            .and_then(|ts| UnixTimestamp::from_datetime(ts.0))
+    }
+}
+
+/// Fields of logs that metric tags can be extracted from.
+///
+/// The body and attributes of logs have unbounded cardinality and may contain PII, so they can only
+/// be used for metric values and conditions.
+const TAG_FIELDS: &[&str] = &["log.severity"];
+
+/// Returns `true` if the tag has a literal value or is extracted from one of [`TAG_FIELDS`].
+fn is_allowed_tag(tag: &TagSpec) -> bool {
+    match tag.source() {
+        TagSource::Literal(_) => true,
+        TagSource::Field(field) => TAG_FIELDS.contains(&field),
+        TagSource::Unknown => false,
+    }
+}
+
+fn allowed_tags(tags: &[TagSpec]) -> Vec<TagSpec> {
+    tags.iter()
+        .filter(|tag| is_allowed_tag(tag))
+        .cloned()
+        .collect()
+}
+
+/// Returns the `log` metric specs of the config with tags restricted to allowed fields.
+///
+/// The result should be computed once per envelope and passed to [`extract_metrics`].
+pub fn restrict_tags(config: &MetricExtractionConfig) -> MetricExtractionConfig {
+    let metrics = config
+        .metrics
+        .iter()
+        .filter(|spec| spec.category == DataCategory::Log)
+        .map(|spec| MetricSpec {
+            tags: allowed_tags(&spec.tags),
+            ..spec.clone()
+        })
+        .collect();
+
+    let tags = config
+        .tags
+        .iter()
+        .map(|mapping| TagMapping {
+            tags: allowed_tags(&mapping.tags),
+            ..mapping.clone()
+        })
+        .collect();
+
+    MetricExtractionConfig {
+        version: config.version,
+        metrics,
+        tags,
+        ..Default::default()
+    }
+}
+
+/// Extracts metrics from a [`Log`].
+///
+/// Only metric specs with the `log` category apply. Fields of logs are addressed with the `log.`
+/// prefix, for example `log.severity` or `log.attributes.duration`. Use [`restrict_tags`] to
+/// limit the tags of the config to allowed fields first.
+pub fn extract_metrics(log: &Log, config: &MetricExtractionConfig) -> Vec<Bucket> {
+    generic::extract_metrics(log, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use relay_protocol::Annotated;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_log_metrics() {
+        let json = r#"{
+            "timestamp": 1597976302.0,
+            "severity": "error",
+            "body": "request failed",
+            "attributes": {"duration": 12.5}
+        }"#;
+        let log = Annotated::<Log>::from_json(json).unwrap();
+
+        let config = serde_json::from_value(json!({
+            "version": 1,
+            "metrics": [
+                {
+                    "category": "log",
+                    "mri": "c:custom/logs@none",
+                    "tags": [
+                        {"key": "severity", "field": "log.severity"},
+                        {"key": "body", "field": "log.body"},
+                        {"key": "source", "value": "sdk"}
+                    ]
+                },
+                {
+                    "category": "log",
+                    "mri": "d:custom/request.duration@millisecond",
+                    "field": "log.attributes.duration"
+                },
+                {
+                    "category": "transaction",
+                    "mri": "c:transactions/count@none"
+                }
+            ]
+        }))
+        .unwrap();
+
+        let metrics = extract_metrics(log.value().unwrap(), &restrict_tags(&config));
+        insta::assert_debug_snapshot!(metrics, @r###"
+        [
+            Bucket {
+                timestamp: UnixTimestamp(1597976302),
+                width: 0,
+                name: "c:custom/logs@none",
+                value: Counter(
+                    1.0,
+                ),
+                tags: {
+                    "severity": "error",
+                    "source": "sdk",
+                },
+            },
+            Bucket {
+                timestamp: UnixTimestamp(1597976302),
+                width: 0,
+                name: "d:custom/request.duration@millisecond",
+                value: Distribution(
+                    [
+                        12.5,
+                    ],
+                ),
+                tags: {},
+            },
+        ]
+        "###);
+    }
+}
@@ -4,6 +4,8 @@ use relay_metrics::Bucket;
 mod generic;
 
 pub mod event;
+#[cfg(feature = "processing")]
+pub mod log;
 pub mod sessions;
 pub mod transactions;
 
@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 
 use relay_base_schema::events::EventType;
 use relay_common::time::UnixTimestamp;
-use relay_dynamic_config::{TagMapping, TransactionMetricsConfig};
+use relay_dynamic_config::{TagMapping, TransactionMetricsConfig, TransactionMetricsOverrides};
 use relay_event_normalization::utils as normalize_utils;
 use relay_event_schema::protocol::{
     AsPair, BrowserContext, Event, OsContext, TraceContext, TransactionSource,
@@ -117,7 +117,11 @@ fn track_transaction_name_stats(event: &Event) {
 }
 
 /// These are the tags that are added to all extracted metrics.
-fn extract_universal_tags(event: &Event, config: &TransactionMetricsConfig) -> CommonTags {
+fn extract_universal_tags(
+    event: &Event,
+    config: &TransactionMetricsConfig,
+    overrides: &TransactionMetricsOverrides,
+) -> CommonTags {
     let mut tags = BTreeMap::new();
     if let Some(release) = event.release.as_str() {
         tags.insert(CommonTag::Release, release.to_string());
@@ -176,14 +180,15 @@ fn extract_universal_tags(event: &Event, config: &TransactionMetricsConfig) -> C
     }
 
     let custom_tags = &config.extract_custom_tags;
-    if !custom_tags.is_empty() {
+    let override_tags = &overrides.extract_custom_tags;
+    if !custom_tags.is_empty() || !override_tags.is_empty() {
         // XXX(slow): event tags are a flat array
         if let Some(event_tags) = event.tags.value() {
             for tag_entry in &**event_tags {
                 if let Some(entry) = tag_entry.value() {
                     let (key, value) = entry.as_pair();
                     if let (Some(key), Some(value)) = (key.as_str(), value.as_str()) {
-                        if custom_tags.contains(key) {
+                        if custom_tags.contains(key) || override_tags.contains(key) {
                             tags.insert(CommonTag::Custom(key.to_string()), value.to_string());
                         }
                     }
@@ -221,6 +226,10 @@ impl ExtractedMetrics {
 pub struct TransactionExtractor<'a> {
     pub config: &'a TransactionMetricsConfig,
     pub generic_tags: &'a [TagMapping],
+    /// Local overrides from the static Relay config.
+    pub overrides: &'a TransactionMetricsOverrides,
+    /// Tag mappings converted from the conditional tagging rules in `overrides`.
+    pub override_tags: &'a [TagMapping],
     pub transaction_from_dsc: Option<&'a str>,
     pub sampling_result: &'a SamplingResult,
     pub has_profile: bool,
@@ -246,7 +255,7 @@ impl TransactionExtractor<'_> {
         };
 
         track_transaction_name_stats(event);
-        let tags = extract_universal_tags(event, self.config);
+        let tags = extract_universal_tags(event, self.config, self.overrides);
 
         // Measurements
         if let Some(measurements) = event.measurements.value() {
@@ -357,10 +366,19 @@ impl TransactionExtractor<'_> {
             }
         }
 
+        // Drop metrics that have been disabled through local overrides. Sampling metrics are
+        // required for dynamic sampling and cannot be disabled.
+        if !self.overrides.disabled_metrics.is_empty() {
+            metrics
+                .project_metrics
+                .retain(|bucket| self.overrides.is_metric_enabled(&bucket.name));
+        }
+
         // Apply shared tags from generic metric extraction. Transaction metrics will adopt generic
         // metric extraction, after which this is done automatically.
         generic::tmp_apply_tags(&mut metrics.project_metrics, event, self.generic_tags);
         generic::tmp_apply_tags(&mut metrics.sampling_metrics, event, self.generic_tags);
+        generic::tmp_apply_tags(&mut metrics.project_metrics, event, self.override_tags);
 
         Ok(metrics)
     }
@@ -494,6 +512,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -692,6 +712,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -795,6 +817,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -879,6 +903,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -946,6 +972,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -1035,6 +1063,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -1074,6 +1104,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -1142,6 +1174,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -1176,6 +1210,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -1212,6 +1248,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("root_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -1475,6 +1513,8 @@ mod tests {
         let extractor = TransactionExtractor {
             config: &config,
             generic_tags: &[],
+            overrides: &TransactionMetricsOverrides::default(),
+            override_tags: &[],
             transaction_from_dsc: Some("test_transaction"),
             sampling_result: &SamplingResult::Keep,
             has_profile: false,
@@ -1643,4 +1683,64 @@ mod tests {
         ]
         "###);
     }
+
+    #[test]
+    fn test_overrides() {
+        let event = Annotated::from_json(
+            r#"{
+                "type": "transaction",
+                "transaction": "foo",
+                "start_timestamp": "2021-04-26T08:00:00+0100",
+                "timestamp": "2021-04-26T08:00:02+0100",
+                "user": {"id": "user123"},
+                "tags": {"team": "ingest"},
+                "measurements": {
+                    "lcp": {"value": 41, "unit": "millisecond"}
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let overrides: TransactionMetricsOverrides = serde_json::from_value(serde_json::json!({
+            "extract_custom_tags": ["team"],
+            "disabled_metrics": [
+                "s:transactions/user@none",
+                "d:transactions/measurements.lcp@millisecond",
+                "c:transactions/count_per_root_project@none"
+            ],
+            "conditional_tagging": [{
+                "condition": {"op": "gte", "name": "event.duration", "value": 1000},
+                "targetMetrics": ["d:transactions/duration@millisecond"],
+                "targetTag": "satisfaction",
+                "tagValue": "frustrated"
+            }]
+        }))
+        .unwrap();
+
+        let config = TransactionMetricsConfig::new();
+        let extractor = TransactionExtractor {
+            config: &config,
+            generic_tags: &[],
+            overrides: &overrides,
+            override_tags: &overrides.tag_mappings(),
+            transaction_from_dsc: Some("test_transaction"),
+            sampling_result: &SamplingResult::Keep,
+            has_profile: false,
+        };
+
+        let extracted = extractor.extract(event.value().unwrap()).unwrap();
+        let names = extracted
+            .project_metrics
+            .iter()
+            .map(|bucket| bucket.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["d:transactions/duration@millisecond"]);
+
+        let duration = &extracted.project_metrics[0];
+        assert_eq!(duration.tags["team"], "ingest");
+        assert_eq!(duration.tags["satisfaction"], "frustrated");
+
+        // The metric for dynamic sampling cannot be disabled.
+        assert_eq!(extracted.sampling_metrics.len(), 1);
+    }
 }
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::RequestExt;
+use bytes::Bytes;
+use futures::StreamExt;
+use hyper::body::HttpBody;
+
+use crate::capture::{CapturedRequest, TrafficCapture};
+use crate::extractors::ForwardedFor;
+
+/// Returns `true` for paths of ingestion endpoints.
+///
+/// Relay-internal endpoints and endpoints for downstream Relays are not ingestion endpoints.
+pub(super) fn is_ingestion_path(path: &str) -> bool {
+    path.starts_with("/api/") && !path.starts_with("/api/0/") && !path.starts_with("/api/relay/")
+}
+
+/// A middleware that records requests to ingestion endpoints, see [`TrafficCapture`].
+///
+/// This must run before request decompression, so that the raw body is recorded. Use this with
+/// [`axum::middleware::from_fn_with_state`].
+pub async fn capture(
+    State(capture): State<Option<Arc<TrafficCapture>>>,
+    mut request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let Some(capture) = capture else {
+        return next.run(request).await;
+    };
+
+    if !is_ingestion_path(request.uri().path()) || !capture.is_active() {
+        return next.run(request).await;
+    }
+
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+
+    if content_length.map_or(false, |length| length > capture.max_body_size()) {
+        return next.run(request).await;
+    }
+
+    let forwarded_for = request
+        .extract_parts::<ForwardedFor>()
+        .await
+        .map(String::from)
+        .unwrap_or_default();
+
+    let uri = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str())
+        .to_owned();
+
+    // Chunked requests do not declare their size upfront, so the limit is enforced while reading.
+    let (parts, mut body) = request.into_parts();
+    let mut chunks = Vec::new();
+    let mut size = 0;
+    while let Some(chunk) = body.data().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                relay_log::debug!(
+                    error = &error as &dyn std::error::Error,
+                    "failed to read request body for capture"
+                );
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+        };
+
+        size += chunk.len();
+        chunks.push(chunk);
+
+        if size > capture.max_body_size() {
+            // Too large to record. Forward the chunks read so far followed by the remaining body.
+            let read = futures::stream::iter(chunks.into_iter().map(Ok::<_, hyper::Error>));
+            let body = Body::wrap_stream(read.chain(body));
+            return next.run(Request::from_parts(parts, body)).await;
+        }
+    }
+
+    let body = Bytes::from(chunks.concat());
+    let Some((seq, offset)) = capture.reserve() else {
+        return next.run(Request::from_parts(parts, Body::from(body))).await;
+    };
+
+    let record = CapturedRequest::new(
+        offset,
+        parts.method.as_str(),
+        &uri,
+        forwarded_for,
+        &parts.headers,
+    );
+
+    let captured_body = body.clone();
+    tokio::task::spawn_blocking(move || {
+        if let Err(error) = capture.write(seq, &record, &captured_body) {
+            relay_log::error!(
+                error = error.as_ref() as &dyn std::error::Error,
+                "failed to write captured request"
+            );
+        }
+    });
+
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ingestion_path() {
+        assert!(is_ingestion_path("/api/42/envelope/"));
+        assert!(is_ingestion_path("/api/store/"));
+        assert!(!is_ingestion_path("/api/0/relays/projectconfigs/"));
+        assert!(!is_ingestion_path("/api/relay/healthcheck/ready/"));
+        assert!(!is_ingestion_path("/dashboard/"));
+    }
+}
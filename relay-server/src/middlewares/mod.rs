@@ -7,16 +7,20 @@
 //! See the server startup in [`HttpServer`](crate::actors::server::HttpServer) for where these
 //! middlewares are registered.
 
+mod capture;
 mod cors;
 mod decompression;
 mod handle_panic;
 mod metrics;
 mod normalize_path;
+mod response_headers;
 mod trace;
 
+pub use self::capture::*;
 pub use self::cors::*;
 pub use self::decompression::*;
 pub use self::handle_panic::*;
 pub use self::metrics::*;
 pub use self::normalize_path::*;
+pub use self::response_headers::*;
 pub use self::trace::*;
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{HeaderName, HeaderValue, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use super::capture::is_ingestion_path;
+
+/// Custom headers added to responses of ingestion endpoints.
+///
+/// See [`Config::response_headers`](relay_config::Config::response_headers).
+#[derive(Clone, Debug, Default)]
+pub struct ResponseHeaders(Arc<[(HeaderName, HeaderValue)]>);
+
+impl ResponseHeaders {
+    /// Parses the configured headers.
+    ///
+    /// Invalid headers are skipped with an error, since they are already rejected when loading the
+    /// config file.
+    pub fn new(headers: &BTreeMap<String, String>) -> Self {
+        let headers = headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let parsed = HeaderName::from_bytes(name.as_bytes())
+                    .ok()
+                    .zip(HeaderValue::from_str(value).ok());
+
+                if parsed.is_none() {
+                    relay_log::error!("skipping invalid response header `{name}`");
+                }
+                parsed
+            })
+            .collect();
+
+        Self(headers)
+    }
+}
+
+/// A middleware that adds [`ResponseHeaders`] to responses of ingestion endpoints.
+///
+/// Existing headers with the same name are replaced. Use this with
+/// [`axum::middleware::from_fn_with_state`].
+pub async fn response_headers(
+    State(headers): State<ResponseHeaders>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if headers.0.is_empty() || !is_ingestion_path(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let mut response = next.run(request).await;
+    for (name, value) in headers.0.iter() {
+        response.headers_mut().insert(name.clone(), value.clone());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_invalid_headers() {
+        let headers = BTreeMap::from([
+            ("X-Relay-Instance".to_owned(), "pop-eu-1".to_owned()),
+            ("X Invalid".to_owned(), "value".to_owned()),
+        ]);
+
+        let parsed = ResponseHeaders::new(&headers);
+        assert_eq!(parsed.0.len(), 1);
+        assert_eq!(parsed.0[0].0, "x-relay-instance");
+    }
+}
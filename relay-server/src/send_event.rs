@@ -0,0 +1,166 @@
+//! End-to-end verification of a Relay deployment.
+//!
+//! The `relay send-event` command submits a single error event with [`send_event`] and waits for
+//! its result. The envelope carries the
+//! [`track_result`](crate::actors::test_store::TRACK_RESULT_HEADER) header, which makes the Relay
+//! that processes it record whether the event was sent to the upstream or dropped, and with which
+//! outcome. The result is polled from `/api/relay/events/<event_id>/result/`, which is part of the
+//! admin API and requires an admin token. Relays without the admin API do not track results.
+//!
+//! This covers authentication and cached rate limits when submitting the envelope, inbound
+//! filters, quotas, and dynamic sampling during processing, and the connection to the upstream.
+use std::time::Duration;
+
+use anyhow::Context;
+use bytes::Bytes;
+use chrono::Utc;
+use relay_common::Dsn;
+use relay_event_schema::protocol::EventId;
+use serde_json::json;
+use tokio::time::Instant;
+
+use crate::actors::test_store::{TrackedResult, TRACK_RESULT_HEADER};
+use crate::constants::CLIENT;
+
+/// The interval at which the result of the event is polled.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Options for [`send_event`].
+#[derive(Clone, Debug)]
+pub struct SendEventOptions {
+    /// The base URL of the Relay that receives the event.
+    pub target: String,
+    /// The DSN used to authenticate the event. Its host is ignored in favor of `target`.
+    pub dsn: String,
+    /// The message of the event.
+    pub message: String,
+    /// The maximum time to wait for the result of the event.
+    pub timeout: Duration,
+    /// The bearer token to query the result from the admin API.
+    pub admin_token: String,
+}
+
+/// The result of [`send_event`].
+#[derive(Debug)]
+pub enum SendEventReport {
+    /// The Relay did not accept the request.
+    ///
+    /// This is the case for invalid or unknown DSNs, disabled projects, and active rate limits.
+    Refused {
+        /// The status code of the response.
+        status: u16,
+        /// The response body, which usually contains a description of the error.
+        body: String,
+    },
+    /// The event was processed and either sent to the upstream or dropped.
+    Processed(TrackedResult),
+    /// The event was accepted, but there was no result before the timeout elapsed.
+    Pending,
+}
+
+/// Creates an envelope with an error event that requests tracking of its result.
+fn create_envelope(event_id: EventId, dsn: &Dsn, message: &str) -> Bytes {
+    let headers = json!({
+        "event_id": event_id,
+        "dsn": dsn.to_string(),
+        "sent_at": Utc::now(),
+        TRACK_RESULT_HEADER: true,
+    });
+
+    let payload = json!({
+        "event_id": event_id,
+        "timestamp": Utc::now().timestamp_millis() as f64 / 1000.0,
+        "platform": "other",
+        "level": "info",
+        "logger": "relay.send-event",
+        "message": message,
+    })
+    .to_string();
+
+    let item_headers = json!({"type": "event", "length": payload.len()});
+    Bytes::from(format!("{headers}\n{item_headers}\n{payload}\n"))
+}
+
+/// Submits a test event to a Relay and waits for its result.
+pub async fn send_event(options: SendEventOptions) -> anyhow::Result<(EventId, SendEventReport)> {
+    let dsn = options.dsn.parse::<Dsn>().context("invalid DSN")?;
+    let target = options.target.trim_end_matches('/');
+    let client = reqwest::Client::new();
+
+    let event_id = EventId::new();
+    let envelope = create_envelope(event_id, &dsn, &options.message);
+    let auth = format!(
+        "Sentry sentry_key={}, sentry_version=7, sentry_client={CLIENT}",
+        dsn.public_key()
+    );
+
+    let response = client
+        .post(format!("{target}/api/{}/envelope/", dsn.project_id()))
+        .header("content-type", "application/x-sentry-envelope")
+        .header("x-sentry-auth", auth)
+        .body(envelope)
+        .send()
+        .await
+        .with_context(|| format!("failed to send event to {target}"))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        let report = SendEventReport::Refused {
+            status: status.as_u16(),
+            body,
+        };
+        return Ok((event_id, report));
+    }
+
+    let url = format!("{target}/api/relay/events/{event_id}/result/");
+    let deadline = Instant::now() + options.timeout;
+
+    loop {
+        let response = client
+            .get(&url)
+            .bearer_auth(&options.admin_token)
+            .send()
+            .await
+            .with_context(|| format!("failed to query result from {target}"))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            anyhow::bail!("admin token rejected with status {status}");
+        }
+
+        if status.is_success() {
+            let result = response.json().await.context("invalid result response")?;
+            return Ok((event_id, SendEventReport::Processed(result)));
+        }
+
+        if Instant::now() + POLL_INTERVAL > deadline {
+            return Ok((event_id, SendEventReport::Pending));
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::test_store::TrackResult;
+    use crate::envelope::Envelope;
+
+    use super::*;
+
+    #[test]
+    fn test_create_envelope() {
+        let dsn = "https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42"
+            .parse::<Dsn>()
+            .unwrap();
+        let event_id = EventId::new();
+
+        let bytes = create_envelope(event_id, &dsn, "hello");
+        let envelope = Envelope::parse_bytes(bytes).unwrap();
+
+        assert_eq!(envelope.event_id(), Some(event_id));
+        assert!(TrackResult::is_tracked(&envelope));
+        assert_eq!(envelope.len(), 1);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::convert::Infallible;
 use std::fmt;
 use std::sync::Arc;
@@ -12,6 +13,8 @@ use relay_redis::RedisPool;
 use relay_system::{channel, Addr, Service};
 use tokio::runtime::Runtime;
 
+use crate::actors::audit::{AuditLog, AuditLogService};
+use crate::actors::config_reload::ConfigReloadService;
 use crate::actors::envelopes::{EnvelopeManager, EnvelopeManagerService};
 use crate::actors::global_config::{GlobalConfigManager, GlobalConfigService};
 use crate::actors::health_check::{HealthCheck, HealthCheckService};
@@ -19,11 +22,13 @@ use crate::actors::outcome::{OutcomeProducer, OutcomeProducerService, TrackOutco
 use crate::actors::outcome_aggregator::OutcomeAggregator;
 use crate::actors::processor::{EnvelopeProcessor, EnvelopeProcessorService};
 use crate::actors::project_cache::{ProjectCache, ProjectCacheService, Services};
+use crate::actors::project_shards::ProjectStates;
 use crate::actors::relays::{RelayCache, RelayCacheService};
 #[cfg(feature = "processing")]
 use crate::actors::store::StoreService;
 use crate::actors::test_store::{TestStore, TestStoreService};
 use crate::actors::upstream::{UpstreamRelay, UpstreamRelayService};
+use crate::capture::TrafficCapture;
 use crate::utils::BufferGuard;
 
 /// Indicates the type of failure of the server.
@@ -41,6 +46,11 @@ pub enum ServiceError {
     /// Initializing the Redis cluster client failed.
     #[error("could not initialize redis cluster client")]
     Redis,
+
+    /// Connecting to the memcached servers for rate limiting failed.
+    #[cfg(feature = "processing")]
+    #[error("could not connect to memcached")]
+    Memcached,
 }
 
 #[derive(Clone)]
@@ -56,6 +66,27 @@ pub struct Registry {
     pub global_config: Addr<GlobalConfigManager>,
     pub project_cache: Addr<ProjectCache>,
     pub upstream_relay: Addr<UpstreamRelay>,
+    pub audit_log: Addr<AuditLog>,
+}
+
+impl Registry {
+    /// Returns the number of messages waiting in the mailbox of each service.
+    pub fn queue_sizes(&self) -> BTreeMap<&'static str, u64> {
+        BTreeMap::from([
+            ("aggregator", self.aggregator.queue_size()),
+            ("envelope_manager", self.envelope_manager.queue_size()),
+            ("global_config", self.global_config.queue_size()),
+            ("health_check", self.health_check.queue_size()),
+            ("outcome_aggregator", self.outcome_aggregator.queue_size()),
+            ("outcome_producer", self.outcome_producer.queue_size()),
+            ("processor", self.processor.queue_size()),
+            ("project_cache", self.project_cache.queue_size()),
+            ("relay_cache", self.relay_cache.queue_size()),
+            ("test_store", self.test_store.queue_size()),
+            ("upstream_relay", self.upstream_relay.queue_size()),
+            ("audit_log", self.audit_log.queue_size()),
+        ])
+    }
 }
 
 impl fmt::Debug for Registry {
@@ -83,7 +114,9 @@ pub fn create_runtime(name: &str, threads: usize) -> Runtime {
 #[derive(Debug)]
 struct StateInner {
     config: Arc<Config>,
+    capture: Option<Arc<TrafficCapture>>,
     buffer_guard: Arc<BufferGuard>,
+    project_states: Arc<ProjectStates>,
     registry: Registry,
 }
 
@@ -95,9 +128,15 @@ pub struct ServiceState {
 
 impl ServiceState {
     /// Starts all services and returns addresses to all of them.
-    pub fn start(config: Arc<Config>, runtimes: &Runtimes) -> Result<Self> {
-        let upstream_relay = UpstreamRelayService::new(config.clone()).start_in(&runtimes.upstream);
+    ///
+    /// Fails if a service cannot be created, for example if the schemas of Kafka topics cannot be
+    /// loaded from the schema registry.
+    pub async fn start(config: Arc<Config>, runtimes: &Runtimes) -> Result<Self> {
+        let upstream_relay_service = UpstreamRelayService::new(config.clone())?;
+        let upstream_encodings = upstream_relay_service.encodings();
+        let upstream_relay = upstream_relay_service.start_in(&runtimes.upstream);
         let test_store = TestStoreService::new(config.clone()).start();
+        let audit_log = AuditLogService::new(config.clone())?.start();
 
         let redis_pool = match config.redis() {
             Some(redis_config) if config.processing_enabled() => {
@@ -106,7 +145,11 @@ impl ServiceState {
             _ => None,
         };
 
-        let buffer = Arc::new(BufferGuard::new(config.envelope_buffer_size()));
+        let mut buffer = BufferGuard::new(config.envelope_buffer_size());
+        if let Some(budget) = config.envelope_buffer_memory() {
+            buffer = buffer.with_memory_budget(budget);
+        }
+        let buffer = Arc::new(buffer);
 
         // Create an address for the `EnvelopeManagerService`, which can be injected into the
         // other services. This also solves the issue of circular dependencies with `EnvelopeProcessorService`.
@@ -124,7 +167,8 @@ impl ServiceState {
         // started. Messages like subscription requests to the global config
         // service fail if the service is not running.
         let global_config =
-            GlobalConfigService::new(config.clone(), upstream_relay.clone()).start();
+            GlobalConfigService::new(config.clone(), upstream_relay.clone(), audit_log.clone())
+                .start();
 
         let (project_cache, project_cache_rx) = channel(ProjectCacheService::name());
         let processor = EnvelopeProcessorService::new(
@@ -135,7 +179,7 @@ impl ServiceState {
             project_cache.clone(),
             global_config.clone(),
             upstream_relay.clone(),
-        )
+        )?
         .start();
 
         let aggregator = relay_metrics::RouterService::new(
@@ -153,12 +197,26 @@ impl ServiceState {
             project_cache.clone(),
             test_store.clone(),
             upstream_relay.clone(),
+            upstream_encodings,
         );
 
         #[cfg(feature = "processing")]
-        if let Some(ref rt) = runtimes.store {
-            let store = StoreService::create(config.clone())?.start_in(rt);
-            envelope_manager_service.set_store_forwarder(store);
+        let store = match runtimes.store {
+            Some(ref rt) => {
+                let mut store = StoreService::create(
+                    config.clone(),
+                    outcome_producer.clone(),
+                    test_store.clone(),
+                )?;
+                store.load_schemas().await?;
+                Some(store.start_in(rt))
+            }
+            None => None,
+        };
+
+        #[cfg(feature = "processing")]
+        if let Some(ref store) = store {
+            envelope_manager_service.set_store_forwarder(store.clone());
         }
 
         envelope_manager_service.spawn_handler(envelope_manager_rx);
@@ -172,26 +230,41 @@ impl ServiceState {
             project_cache.clone(),
             test_store.clone(),
             upstream_relay.clone(),
+            audit_log.clone(),
         );
+        let project_states = Arc::new(ProjectStates::new());
         let guard = runtimes.project.enter();
         ProjectCacheService::new(
             config.clone(),
             buffer.clone(),
+            project_states.clone(),
             project_cache_services,
-            redis_pool,
+            redis_pool.clone(),
         )
         .spawn_handler(project_cache_rx);
         drop(guard);
 
-        let health_check = HealthCheckService::new(
+        #[allow(unused_mut)]
+        let mut health_check_service = HealthCheckService::new(
             config.clone(),
             aggregator.clone(),
             upstream_relay.clone(),
             project_cache.clone(),
-        )
-        .start();
+            redis_pool,
+        );
+
+        #[cfg(feature = "processing")]
+        if let Some(store) = store {
+            health_check_service.set_store_forwarder(store);
+        }
+
+        let health_check = health_check_service.start();
         let relay_cache = RelayCacheService::new(config.clone(), upstream_relay.clone()).start();
 
+        if !config.path().as_os_str().is_empty() {
+            ConfigReloadService::new(config.clone(), audit_log.clone()).start();
+        }
+
         if let Some(aws_api) = config.aws_runtime_api() {
             if let Ok(aws_extension) = AwsExtension::new(aws_api) {
                 aws_extension.start();
@@ -210,11 +283,14 @@ impl ServiceState {
             global_config,
             project_cache,
             upstream_relay,
+            audit_log,
         };
 
         let state = StateInner {
+            capture: TrafficCapture::new(&config)?.map(Arc::new),
             buffer_guard: buffer,
             config,
+            project_states,
             registry,
         };
 
@@ -236,11 +312,23 @@ impl ServiceState {
         &self.inner.buffer_guard
     }
 
+    /// Returns the recorder of incoming requests, if `capture.path` is configured.
+    pub fn capture(&self) -> Option<&Arc<TrafficCapture>> {
+        self.inner.capture.as_ref()
+    }
+
     /// Returns the address of the [`ProjectCache`] service.
     pub fn project_cache(&self) -> &Addr<ProjectCache> {
         &self.inner.registry.project_cache
     }
 
+    /// Returns snapshots of the project states cached by the [`ProjectCache`] service.
+    ///
+    /// Reading from these snapshots does not require sending a message to the service.
+    pub fn project_states(&self) -> &ProjectStates {
+        &self.inner.project_states
+    }
+
     /// Returns the address of the [`RelayCache`] service.
     pub fn relay_cache(&self) -> &Addr<RelayCache> {
         &self.inner.registry.relay_cache
@@ -280,6 +368,16 @@ impl ServiceState {
     pub fn outcome_aggregator(&self) -> &Addr<TrackOutcome> {
         &self.inner.registry.outcome_aggregator
     }
+
+    /// Returns the address of the [`AuditLog`] service.
+    pub fn audit_log(&self) -> &Addr<AuditLog> {
+        &self.inner.registry.audit_log
+    }
+
+    /// Returns the number of messages waiting in the mailbox of each service.
+    pub fn queue_sizes(&self) -> BTreeMap<&'static str, u64> {
+        self.inner.registry.queue_sizes()
+    }
 }
 
 /// Contains secondary service runtimes.
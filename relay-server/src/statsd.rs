@@ -20,6 +20,31 @@ pub enum RelayGauges {
     ///
     /// The disk buffer size can be configured with `spool.envelopes.max_disk_size`.
     BufferEnvelopesDiskCount,
+    /// The percentage of free pages in the on-disk envelope spool.
+    ///
+    /// The spool is compacted when this exceeds `spool.envelopes.compaction_threshold`. This
+    /// metric is reported every `spool.envelopes.compaction_interval`.
+    BufferDiskFragmentation,
+    /// The age in seconds of the oldest envelope in the on-disk envelope spool.
+    ///
+    /// This metric is reported every `spool.envelopes.compaction_interval`.
+    BufferOldestEnvelopeAge,
+    /// The number of requests currently being sent to the upstream.
+    ///
+    /// This is bounded by `limits.max_concurrent_requests`.
+    UpstreamRequestsInFlight,
+    /// The number of messages waiting in a lane of the envelope processor.
+    ///
+    /// This metric is tagged with:
+    ///  - `lane`: The processor lane, such as `"normalization"` or `"metrics"`.
+    ProcessorLaneQueueSize,
+    /// The number of messages of a lane that are currently being processed.
+    ///
+    /// This is bounded by the lane's `max_concurrency` in `limits.processor_lanes`.
+    ///
+    /// This metric is tagged with:
+    ///  - `lane`: The processor lane, such as `"normalization"` or `"metrics"`.
+    ProcessorLaneActive,
 }
 
 impl GaugeMetric for RelayGauges {
@@ -29,6 +54,11 @@ impl GaugeMetric for RelayGauges {
             RelayGauges::ProjectCacheGarbageQueueSize => "project_cache.garbage.queue_size",
             RelayGauges::BufferEnvelopesMemoryCount => "buffer.envelopes_mem_count",
             RelayGauges::BufferEnvelopesDiskCount => "buffer.envelopes_disk_count",
+            RelayGauges::BufferDiskFragmentation => "buffer.disk_fragmentation",
+            RelayGauges::BufferOldestEnvelopeAge => "buffer.oldest_envelope_age",
+            RelayGauges::UpstreamRequestsInFlight => "upstream.requests.in_flight",
+            RelayGauges::ProcessorLaneQueueSize => "processor.lane.queue_size",
+            RelayGauges::ProcessorLaneActive => "processor.lane.active",
         }
     }
 }
@@ -56,6 +86,10 @@ pub enum RelayHistograms {
     ///
     /// The queue size can be configured with `cache.event_buffer_size`.
     EnvelopeQueueSize,
+    /// The number of envelope bytes in the processing queue, including this envelope.
+    ///
+    /// This metric is only emitted if `cache.envelope_buffer_memory` is configured.
+    EnvelopeBufferMemory,
     /// The estimated number of envelope bytes buffered in memory.
     ///
     /// The memory buffer size can be configured with `spool.envelopes.max_memory_size`.
@@ -64,6 +98,16 @@ pub enum RelayHistograms {
     ///
     /// This metric is computed by multiplying `page_count * page_size`.
     BufferDiskSize,
+    /// The file size of the store-and-forward spool on disk, in bytes.
+    ///
+    /// This metric is computed by multiplying `page_count * page_size`. It is only emitted if
+    /// `spool.forward.max_disk_size` is configured.
+    ForwardSpoolDiskSize,
+    /// The file size of the Kafka spool on disk, in bytes.
+    ///
+    /// This metric is computed by multiplying `page_count * page_size`.
+    #[cfg(feature = "processing")]
+    KafkaSpoolDiskSize,
     /// Number of attempts needed to dequeue spooled envelopes from disk.
     ///
     /// As long as there are enough permits in the [`crate::utils::BufferGuard`], this number should
@@ -157,8 +201,12 @@ impl HistogramMetric for RelayHistograms {
             RelayHistograms::EnvelopeQueueSizePct => "event.queue_size.pct",
             RelayHistograms::EnvelopeQueueSize => "event.queue_size",
             RelayHistograms::EventSpans => "event.spans",
+            RelayHistograms::EnvelopeBufferMemory => "event.queue_memory",
             RelayHistograms::BufferEnvelopesMemoryBytes => "buffer.envelopes_mem",
             RelayHistograms::BufferDiskSize => "buffer.disk_size",
+            RelayHistograms::ForwardSpoolDiskSize => "forward_spool.disk_size",
+            #[cfg(feature = "processing")]
+            RelayHistograms::KafkaSpoolDiskSize => "kafka_spool.disk_size",
             RelayHistograms::BufferDequeueAttempts => "buffer.dequeue_attempts",
             RelayHistograms::ProjectStatePending => "project_state.pending",
             RelayHistograms::ProjectStateAttempts => "project_state.attempts",
@@ -200,7 +248,6 @@ pub enum RelayTimers {
     /// Not all events reach this point. After an event is rate limited for the first time, the rate
     /// limit is cached. Events coming in after this will be discarded earlier in the request queue
     /// and do not reach the processing queue.
-    #[cfg(feature = "processing")]
     EventProcessingRateLimiting,
     /// Time in milliseconds spent in data scrubbing for the current event. Data scrubbing happens
     /// last before serializing the event back to JSON.
@@ -297,6 +344,18 @@ pub enum RelayTimers {
     ///   - `status-code`: The status code of the request when available, otherwise "-".
     ///   - `retries`: Number of retries bucket 0, 1, 2, few (3 - 10), many (more than 10).
     UpstreamRequestsDuration,
+    /// Time in milliseconds that upstream requests wait in the queue before they are sent.
+    ///
+    /// Requests wait if the maximum number of concurrent requests is reached, or during network
+    /// outages. For retried requests, this measures the time since the last attempt.
+    ///
+    /// This metric is tagged with:
+    ///   - `route`: The endpoint that was called on the upstream.
+    UpstreamRequestsQueueTime,
+    /// Time an upstream request was delayed to stay within `http.max_egress_rate`.
+    ///
+    /// This is only emitted for requests that were delayed.
+    UpstreamEgressDelay,
     /// The delay between the timestamp stated in a payload and the receive time.
     ///
     /// SDKs cannot transmit payloads immediately in all cases. Sometimes, crashes require that
@@ -318,6 +377,12 @@ pub enum RelayTimers {
     ReplayRecordingProcessing,
     /// Total time spent to send a request and receive the response from upstream.
     GlobalConfigRequestDuration,
+    /// Time in milliseconds spent writing envelopes to the on-disk envelope spool.
+    BufferWriteDuration,
+    /// Time in milliseconds spent reading a batch of envelopes from the on-disk envelope spool.
+    BufferReadDuration,
+    /// Time in milliseconds spent compacting the on-disk envelope spool.
+    BufferCompactionDuration,
 }
 
 impl TimerMetric for RelayTimers {
@@ -330,7 +395,6 @@ impl TimerMetric for RelayTimers {
             #[cfg(feature = "processing")]
             RelayTimers::EventProcessingProcess => "event_processing.process",
             RelayTimers::EventProcessingFiltering => "event_processing.filtering",
-            #[cfg(feature = "processing")]
             RelayTimers::EventProcessingRateLimiting => "event_processing.rate_limiting",
             RelayTimers::EventProcessingPii => "event_processing.pii",
             RelayTimers::EventProcessingSpanMetricsExtraction => {
@@ -348,10 +412,15 @@ impl TimerMetric for RelayTimers {
             RelayTimers::MinidumpScrubbing => "scrubbing.minidumps.duration",
             RelayTimers::AttachmentScrubbing => "scrubbing.attachments.duration",
             RelayTimers::UpstreamRequestsDuration => "upstream.requests.duration",
+            RelayTimers::UpstreamRequestsQueueTime => "upstream.requests.queue_time",
+            RelayTimers::UpstreamEgressDelay => "upstream.egress.delay",
             RelayTimers::TimestampDelay => "requests.timestamp_delay",
             RelayTimers::OutcomeAggregatorFlushTime => "outcomes.aggregator.flush_time",
             RelayTimers::ReplayRecordingProcessing => "replay.recording.process",
             RelayTimers::GlobalConfigRequestDuration => "global_config.requests.duration",
+            RelayTimers::BufferWriteDuration => "buffer.write.duration",
+            RelayTimers::BufferReadDuration => "buffer.read.duration",
+            RelayTimers::BufferCompactionDuration => "buffer.compaction.duration",
         }
     }
 }
@@ -384,6 +453,12 @@ pub enum RelayCounters {
     ///  - `handling`: Either `"success"` if the envelope was handled correctly, or `"failure"` if
     ///    there was an error or bug.
     EnvelopeRejected,
+    /// Number of envelopes rejected because they exceed the memory budget of the processing queue.
+    ///
+    /// This metric is tagged with:
+    ///  - `reason`: `"budget"` if the entire budget is exhausted, or `"fair_share"` if the
+    ///    project of the envelope exceeds its share of the contended budget.
+    EnvelopeBufferMemoryExceeded,
     /// Number times the envelope buffer spools to disk.
     BufferWrites,
     /// Number times the envelope buffer reads back from disk.
@@ -392,6 +467,32 @@ pub enum RelayCounters {
     BufferEnvelopesWritten,
     /// Number of _envelopes_ the envelope buffer reads back from disk.
     BufferEnvelopesRead,
+    /// Number of envelopes written to the store-and-forward spool while the upstream is
+    /// unreachable.
+    ForwardSpoolWritten,
+    /// Number of envelopes removed from the store-and-forward spool after replaying them.
+    ForwardSpoolRead,
+    /// Number of messages written to the Kafka spool because the producer failed to send them.
+    #[cfg(feature = "processing")]
+    KafkaSpoolWritten,
+    /// Number of messages removed from the Kafka spool after producing or dropping them.
+    #[cfg(feature = "processing")]
+    KafkaSpoolRead,
+    /// Number of spooled Kafka messages dropped after exhausting `spool.kafka.max_retries`, because
+    /// the spool was full, or because the producer rejected them with an error that is not
+    /// retryable.
+    ///
+    /// This metric is tagged with:
+    ///  - `reason`: Either `"retries_exhausted"`, `"spool_full"`, or `"invalid"`.
+    #[cfg(feature = "processing")]
+    KafkaSpoolDropped,
+    /// Number of messages produced to the Kafka dead-letter topic because they could not be
+    /// serialized or exceeded the maximum message size.
+    ///
+    /// This metric is tagged with:
+    ///  - `variant`: The type of the original message.
+    #[cfg(feature = "processing")]
+    KafkaDeadLetters,
     ///
     /// Number of outcomes and reasons for rejected Envelopes.
     ///
@@ -443,6 +544,11 @@ pub enum RelayCounters {
     /// sent to the sentry endpoint.
     #[cfg(feature = "processing")]
     ProjectStateRedis,
+    /// Number of times a project state is served from the in-process cache of Redis reads.
+    ///
+    /// These lookups are not counted in `project_state.redis.requests`.
+    #[cfg(feature = "processing")]
+    ProjectStateRedisCacheHit,
     /// Number of times a project is looked up from the cache.
     ///
     /// The cache may contain and outdated or expired project state. In that case, the project state
@@ -456,13 +562,23 @@ pub enum RelayCounters {
     ///
     /// Completion can be because a result was returned or because the config request was
     /// dropped after there still was no response after a timeout.  This metrics has tags
-    /// for `result` and `attempts` indicating whether it was succesful or a timeout and how
+    /// for `result` and `attempts` indicating whether it was succesful, unchanged, or a timeout and how
     /// many attempts were made respectively.
     ProjectUpstreamCompleted,
+    /// Number of project state requests answered by a concurrent upstream request.
+    ///
+    /// Requests for a project whose state is already being fetched from the upstream wait for the
+    /// result of that request instead of fetching the state again.
+    ProjectUpstreamDeduplicated,
     /// Number of Relay server starts.
     ///
     /// This can be used to track unwanted restarts due to crashes or termination.
     ServerStarting,
+    /// Number of times the static configuration was reloaded at runtime.
+    ///
+    /// This metric is tagged with:
+    ///  - `result`: `"ok"` if the config file was loaded, or `"error"` if it could not be read.
+    ConfigReload,
     /// Number of messages placed on the Kafka queues.
     ///
     /// When Relay operates as Sentry service and an Envelope item is successfully processed, each
@@ -550,6 +666,36 @@ pub enum RelayCounters {
     /// This metric is tagged with:
     ///  - `success`: whether deserializing the global config succeeded.
     GlobalConfigFetched,
+    /// Number of times Relay switched to a different upstream.
+    ///
+    /// This only happens if multiple upstreams are configured in `relay.upstream`.
+    ///
+    /// This metric is tagged with:
+    ///  - `direction`: Either `"failover"` when switching to an upstream with a lower priority
+    ///    during a network outage, or `"failback"` when switching back to an upstream with a higher
+    ///    priority.
+    UpstreamSwitched,
+    /// Number of times the circuit breaker for upstream requests opened.
+    ///
+    /// The circuit breaker opens after `http.circuit_breaker_threshold` consecutive 5xx responses
+    /// from the upstream. While open, queued requests fail without being sent.
+    UpstreamCircuitBreakerOpened,
+    /// Number of responses received from the upstream, by connection reuse.
+    ///
+    /// The connection reuse rate is the share of responses with `reused:true`.
+    ///
+    /// This metric is tagged with:
+    ///  - `reused`: `"true"` if the request was sent over a pooled connection, `"false"` if a new
+    ///    connection was established.
+    UpstreamConnections,
+    /// Number of messages published to a store sink other than Kafka.
+    ///
+    /// This metric is tagged with:
+    ///  - `sink`: The name of the sink, such as `"pubsub"`, `"kinesis"`, `"sqs"`, or `"nats"`.
+    ///  - `result`: `"published"`, `"retried"` if the message failed and is sent again, or
+    ///    `"failed"` if the message is dropped after exhausting its retries.
+    #[cfg(feature = "processing")]
+    SinkMessages,
 }
 
 impl CounterMetric for RelayCounters {
@@ -559,9 +705,20 @@ impl CounterMetric for RelayCounters {
             RelayCounters::EventCorrupted => "event.corrupted",
             RelayCounters::EnvelopeAccepted => "event.accepted",
             RelayCounters::EnvelopeRejected => "event.rejected",
+            RelayCounters::EnvelopeBufferMemoryExceeded => "event.queue_memory_exceeded",
             RelayCounters::BufferWrites => "buffer.writes",
             RelayCounters::BufferReads => "buffer.reads",
             RelayCounters::BufferEnvelopesWritten => "buffer.envelopes_written",
+            RelayCounters::ForwardSpoolWritten => "forward_spool.envelopes_written",
+            RelayCounters::ForwardSpoolRead => "forward_spool.envelopes_read",
+            #[cfg(feature = "processing")]
+            RelayCounters::KafkaSpoolWritten => "kafka_spool.messages_written",
+            #[cfg(feature = "processing")]
+            RelayCounters::KafkaSpoolRead => "kafka_spool.messages_read",
+            #[cfg(feature = "processing")]
+            RelayCounters::KafkaSpoolDropped => "kafka_spool.messages_dropped",
+            #[cfg(feature = "processing")]
+            RelayCounters::KafkaDeadLetters => "kafka.dead_letters",
             RelayCounters::BufferEnvelopesRead => "buffer.envelopes_read",
             RelayCounters::Outcomes => "events.outcomes",
             RelayCounters::ProjectStateGet => "project_state.get",
@@ -569,10 +726,14 @@ impl CounterMetric for RelayCounters {
             RelayCounters::ProjectStateNoCache => "project_state.no_cache",
             #[cfg(feature = "processing")]
             RelayCounters::ProjectStateRedis => "project_state.redis.requests",
+            #[cfg(feature = "processing")]
+            RelayCounters::ProjectStateRedisCacheHit => "project_state.redis.cache_hit",
             RelayCounters::ProjectUpstreamCompleted => "project_upstream.completed",
+            RelayCounters::ProjectUpstreamDeduplicated => "project_upstream.deduplicated",
             RelayCounters::ProjectCacheHit => "project_cache.hit",
             RelayCounters::ProjectCacheMiss => "project_cache.miss",
             RelayCounters::ServerStarting => "server.starting",
+            RelayCounters::ConfigReload => "config.reload",
             #[cfg(feature = "processing")]
             RelayCounters::ProcessingMessageProduced => "processing.event.produced",
             RelayCounters::EventProtocol => "event.protocol",
@@ -585,6 +746,11 @@ impl CounterMetric for RelayCounters {
             RelayCounters::MetricsTransactionNameExtracted => "metrics.transaction_name",
             RelayCounters::OpenTelemetryEvent => "event.opentelemetry",
             RelayCounters::GlobalConfigFetched => "global_config.fetch",
+            RelayCounters::UpstreamSwitched => "upstream.switched",
+            RelayCounters::UpstreamCircuitBreakerOpened => "upstream.circuit_breaker.opened",
+            RelayCounters::UpstreamConnections => "upstream.connections",
+            #[cfg(feature = "processing")]
+            RelayCounters::SinkMessages => "sink.messages",
         }
     }
 }
@@ -1,5 +1,10 @@
+#[cfg(feature = "processing")]
+use std::collections::BTreeMap;
+
 use bytes::Bytes;
 use relay_event_schema::protocol::EventId;
+#[cfg(feature = "processing")]
+use relay_kafka::{AvroValue, ClientError, Message};
 use relay_sampling::condition::RuleCondition;
 use relay_sampling::config::{
     DecayingFunction, RuleId, RuleType, SamplingMode, SamplingRule, SamplingValue,
@@ -101,3 +106,36 @@ pub fn empty_envelope() -> Box<Envelope> {
 
     Envelope::from_request(Some(EventId::new()), RequestMeta::new(dsn))
 }
+
+/// A message for sinks with a fixed key and the given payload.
+#[cfg(feature = "processing")]
+#[derive(Debug)]
+pub struct TestMessage(pub Vec<u8>);
+
+#[cfg(feature = "processing")]
+impl Message for TestMessage {
+    fn key(&self) -> [u8; 16] {
+        [1; 16]
+    }
+
+    fn variant(&self) -> &'static str {
+        "test"
+    }
+
+    fn headers(&self) -> Option<&BTreeMap<String, String>> {
+        None
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, ClientError> {
+        Ok(self.0.clone())
+    }
+
+    fn avro_value(&self) -> Result<AvroValue, ClientError> {
+        Err(ClientError::InvalidShard)
+    }
+}
+
+#[cfg(feature = "processing")]
+pub fn message(data: &[u8]) -> TestMessage {
+    TestMessage(data.to_vec())
+}
@@ -1,11 +1,14 @@
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::{Arc, Mutex};
 
+use relay_base_schema::project::ProjectKey;
 use relay_system::Addr;
 
 use crate::actors::outcome::TrackOutcome;
 use crate::actors::test_store::TestStore;
-use crate::envelope::Envelope;
-use crate::statsd::RelayHistograms;
+use crate::envelope::{Envelope, Item};
+use crate::statsd::{RelayCounters, RelayHistograms};
 use crate::utils::{ManagedEnvelope, Semaphore};
 
 /// An error returned by [`BufferGuard::enter`] indicating that the buffer capacity has been
@@ -21,17 +24,137 @@ impl fmt::Display for BufferError {
 
 impl std::error::Error for BufferError {}
 
+/// Buffered envelope bytes of all projects.
+#[derive(Debug, Default)]
+struct MemoryUsage {
+    total: usize,
+    projects: HashMap<ProjectKey, usize>,
+}
+
+/// Tracks the size of buffered envelopes per project against a global memory budget.
+///
+/// Below the high watermark, envelopes are admitted as long as they fit into the budget. Above the
+/// high watermark, the budget is contended and each project is limited to its fair share, which is
+/// the budget divided by the number of projects with buffered envelopes. This way, a single project
+/// sending large amounts of data hits backpressure first, while other projects can still use the
+/// remaining headroom.
+#[derive(Debug)]
+struct MemoryAccountant {
+    budget: usize,
+    high_watermark: f64,
+    usage: Mutex<MemoryUsage>,
+}
+
+impl MemoryAccountant {
+    fn new(budget: usize, high_watermark: f64) -> Self {
+        Self {
+            budget,
+            high_watermark,
+            usage: Mutex::default(),
+        }
+    }
+
+    /// Returns the number of buffered bytes across all projects.
+    fn used(&self) -> usize {
+        self.usage.lock().map(|u| u.total).unwrap_or_default()
+    }
+
+    /// Reserves `bytes` for the given project, or returns `None` if the budget does not allow it.
+    fn try_reserve(
+        self: &Arc<Self>,
+        project_key: ProjectKey,
+        bytes: usize,
+    ) -> Option<MemoryReservation> {
+        let mut usage = self.usage.lock().ok()?;
+
+        let total = usage.total.saturating_add(bytes);
+        if total > self.budget {
+            relay_statsd::metric!(
+                counter(RelayCounters::EnvelopeBufferMemoryExceeded) += 1,
+                reason = "budget"
+            );
+            return None;
+        }
+
+        let project_usage = usage
+            .projects
+            .get(&project_key)
+            .copied()
+            .unwrap_or_default();
+        if total as f64 > self.budget as f64 * self.high_watermark {
+            let is_new = project_usage == 0;
+            let active_projects = usage.projects.len() + usize::from(is_new);
+            let fair_share = self.budget / active_projects.max(1);
+
+            if project_usage.saturating_add(bytes) > fair_share {
+                relay_statsd::metric!(
+                    counter(RelayCounters::EnvelopeBufferMemoryExceeded) += 1,
+                    reason = "fair_share"
+                );
+                return None;
+            }
+        }
+
+        usage.total = total;
+        usage.projects.insert(project_key, project_usage + bytes);
+
+        relay_statsd::metric!(histogram(RelayHistograms::EnvelopeBufferMemory) = total as u64);
+
+        Some(MemoryReservation {
+            accountant: Arc::clone(self),
+            project_key,
+            bytes,
+        })
+    }
+
+    fn release(&self, project_key: ProjectKey, bytes: usize) {
+        let Ok(mut usage) = self.usage.lock() else {
+            return;
+        };
+
+        usage.total = usage.total.saturating_sub(bytes);
+        if let Some(project_usage) = usage.projects.get_mut(&project_key) {
+            *project_usage = project_usage.saturating_sub(bytes);
+            if *project_usage == 0 {
+                usage.projects.remove(&project_key);
+            }
+        }
+    }
+}
+
+/// RAII guard for envelope bytes reserved in the memory budget of the [`BufferGuard`].
+///
+/// The bytes are released when the reservation is dropped.
+#[must_use]
+#[derive(Debug)]
+pub struct MemoryReservation {
+    accountant: Arc<MemoryAccountant>,
+    project_key: ProjectKey,
+    bytes: usize,
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.accountant.release(self.project_key, self.bytes);
+    }
+}
+
 /// Access control for envelope processing.
 ///
 /// The buffer guard is basically a semaphore that ensures the buffer does not outgrow the maximum
 /// number of envelopes configured through `envelope_buffer_size`. To enter a new envelope
 /// into the processing pipeline, use [`BufferGuard::enter`].
+///
+/// Optionally, the guard also limits the total size of buffered envelopes with a memory budget
+/// configured through `envelope_buffer_memory`. See [`with_memory_budget`](Self::with_memory_budget)
+/// for how the budget is shared between projects.
 #[derive(Debug)]
 pub struct BufferGuard {
     inner: Semaphore,
     capacity: usize,
     high_watermark: f64,
     low_watermark: f64,
+    memory: Option<Arc<MemoryAccountant>>,
 }
 
 impl BufferGuard {
@@ -43,9 +166,25 @@ impl BufferGuard {
             capacity,
             high_watermark: 0.8,
             low_watermark: 0.5,
+            memory: None,
         }
     }
 
+    /// Limits the total size of buffered envelopes to `budget` bytes.
+    ///
+    /// Once buffered envelopes exceed the high watermark of the budget, each project can only
+    /// buffer up to its fair share of the budget. Envelopes of projects exceeding their share are
+    /// rejected, while other projects can still enter the buffer.
+    pub fn with_memory_budget(mut self, budget: usize) -> Self {
+        self.memory = Some(Arc::new(MemoryAccountant::new(budget, self.high_watermark)));
+        self
+    }
+
+    /// Returns the number of envelope bytes in the pipeline, if a memory budget is configured.
+    pub fn memory_used(&self) -> Option<usize> {
+        self.memory.as_ref().map(|memory| memory.used())
+    }
+
     /// Returns the current usage of `BufferGuard` permits.
     #[inline]
     fn usage(&self) -> f64 {
@@ -86,7 +225,8 @@ impl BufferGuard {
     /// resources. When the managed envelope is dropped, the slot is automatically reclaimed and can
     /// be reused by a subsequent call to `enter`.
     ///
-    /// If the buffer is full, this function returns `Err`.
+    /// If the buffer is full or the envelope exceeds the memory budget of its project, this
+    /// function returns `Err`.
     pub fn enter(
         &self,
         envelope: Box<Envelope>,
@@ -95,6 +235,15 @@ impl BufferGuard {
     ) -> Result<ManagedEnvelope, BufferError> {
         let permit = self.inner.try_acquire().ok_or(BufferError)?;
 
+        let reservation = match self.memory {
+            Some(ref memory) => {
+                let bytes = envelope.items().map(Item::len).sum();
+                let project_key = envelope.meta().public_key();
+                Some(memory.try_reserve(project_key, bytes).ok_or(BufferError)?)
+            }
+            None => None,
+        };
+
         relay_statsd::metric!(histogram(RelayHistograms::EnvelopeQueueSize) = self.used() as u64);
 
         relay_statsd::metric!(
@@ -104,11 +253,53 @@ impl BufferGuard {
             }
         );
 
-        Ok(ManagedEnvelope::new(
-            envelope,
-            permit,
-            outcome_aggregator,
-            test_store,
-        ))
+        let mut managed_envelope =
+            ManagedEnvelope::new(envelope, permit, outcome_aggregator, test_store);
+        if let Some(reservation) = reservation {
+            managed_envelope.set_memory_reservation(reservation);
+        }
+
+        Ok(managed_envelope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> ProjectKey {
+        ProjectKey::parse(key).unwrap()
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let accountant = Arc::new(MemoryAccountant::new(100, 0.8));
+        let project = key("a94ae32be2584e0bbd7a4cbb95971fee");
+
+        let reservation = accountant.try_reserve(project, 60).unwrap();
+        assert!(accountant.try_reserve(project, 50).is_none());
+        assert_eq!(accountant.used(), 60);
+
+        drop(reservation);
+        assert_eq!(accountant.used(), 0);
+        assert!(accountant.usage.lock().unwrap().projects.is_empty());
+    }
+
+    #[test]
+    fn test_memory_fair_share() {
+        let accountant = Arc::new(MemoryAccountant::new(100, 0.5));
+        let noisy = key("a94ae32be2584e0bbd7a4cbb95971fee");
+        let quiet = key("b94ae32be2584e0bbd7a4cbb95971fee");
+
+        // Below the watermark, a single project can use the budget freely.
+        let _noisy1 = accountant.try_reserve(noisy, 50).unwrap();
+        let _quiet1 = accountant.try_reserve(quiet, 10).unwrap();
+
+        // Above the watermark, the noisy project exceeds its fair share of 50 bytes.
+        assert!(accountant.try_reserve(noisy, 10).is_none());
+
+        // The quiet project is still within its fair share.
+        let _quiet2 = accountant.try_reserve(quiet, 30).unwrap();
+        assert_eq!(accountant.used(), 90);
     }
 }
@@ -1,9 +1,13 @@
 //! Functionality for calculating if a trace should be processed or dropped.
+use std::time::Instant;
+
 use chrono::{DateTime, Utc};
 use relay_base_schema::project::ProjectKey;
 use relay_event_schema::protocol::Event;
+use relay_sampling::adaptive::AdaptiveSampler;
 use relay_sampling::evaluation::{MatchedRuleIds, SamplingMatch};
 use relay_sampling::DynamicSamplingContext;
+use uuid::Uuid;
 
 use crate::actors::project::ProjectState;
 use crate::envelope::{Envelope, ItemType};
@@ -100,6 +104,23 @@ pub fn get_sampling_result(
     SamplingResult::determine_from_sampling_match(sampling_result)
 }
 
+/// Runs local adaptive sampling on an incoming event and returns whether it should be kept.
+///
+/// The sample rate is determined by the [`AdaptiveSampler`] based on the recent volume of the
+/// project. Dropped events are reported without any matched rule ids.
+pub fn get_adaptive_sampling_result(
+    sampler: &AdaptiveSampler,
+    project_key: ProjectKey,
+    seed: Uuid,
+) -> SamplingResult {
+    let sample_rate = sampler.sample_rate(project_key, Instant::now());
+    SamplingResult::determine_from_sampling_match(Some(SamplingMatch {
+        sample_rate,
+        seed,
+        matched_rule_ids: MatchedRuleIds(vec![]),
+    }))
+}
+
 /// Runs dynamic sampling if the dsc and root project state are not None and returns whether the
 /// transactions received with such dsc and project state would be kept or dropped by dynamic
 /// sampling.
@@ -343,6 +364,45 @@ mod tests {
         assert!(!result);
     }
 
+    #[test]
+    /// Tests that trace rules on custom baggage entries apply the same decision to all items of
+    /// the trace.
+    fn test_trace_rule_on_custom_dsc_entry() {
+        let root_project_state = project_state_with_config(SamplingConfig {
+            rules: vec![],
+            rules_v2: vec![SamplingRule {
+                condition: eq("trace.tier", &["free"], false),
+                sampling_value: SamplingValue::SampleRate { value: 0.0 },
+                ty: RuleType::Trace,
+                id: RuleId(7),
+                time_range: Default::default(),
+                decaying_fn: Default::default(),
+            }],
+            mode: SamplingMode::Received,
+        });
+
+        let mut dsc =
+            mocked_simple_dynamic_sampling_context(Some(1.0), Some("3.0"), None, None, Some(true));
+        dsc.other
+            .insert("tier".to_owned(), serde_json::Value::from("free"));
+
+        let result = get_sampling_result(true, None, Some(&root_project_state), Some(&dsc), None);
+        assert_eq!(
+            result,
+            SamplingResult::Drop(MatchedRuleIds(vec![RuleId(7)]))
+        );
+        let sampled = is_trace_fully_sampled(true, Some(&root_project_state), Some(&dsc));
+        assert_eq!(sampled, Some(false));
+
+        dsc.other
+            .insert("tier".to_owned(), serde_json::Value::from("paid"));
+
+        let result = get_sampling_result(true, None, Some(&root_project_state), Some(&dsc), None);
+        assert_eq!(result, SamplingResult::Keep);
+        let sampled = is_trace_fully_sampled(true, Some(&root_project_state), Some(&dsc));
+        assert_eq!(sampled, Some(true));
+    }
+
     #[test]
     /// Tests that a trace is not marked as fully sampled or not if inputs are invalid.
     fn test_is_trace_fully_sampled_with_invalid_inputs() {
@@ -0,0 +1,297 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use chrono::{DateTime, Utc};
+use relay_base_schema::project::{ProjectId, ProjectKey};
+use relay_config::Inspect;
+use relay_event_schema::protocol::EventId;
+use relay_quotas::RateLimits;
+use relay_sampling::config::RuleId;
+use relay_sampling::evaluation::MatchedRuleIds;
+use serde::Serialize;
+
+use crate::envelope::{ContentType, Envelope, Item, ItemType};
+
+/// A rate limit that applied to an inspected envelope.
+#[derive(Debug, Serialize)]
+struct InspectedRateLimit {
+    categories: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason_code: Option<String>,
+}
+
+/// Processing decisions recorded for a sampled envelope.
+///
+/// Created by [`EnvelopeInspector::sample`] and written with [`EnvelopeInspector::write`].
+#[derive(Debug, Default, Serialize)]
+pub struct Inspection {
+    filters: Vec<&'static str>,
+    sampling_rules: Vec<RuleId>,
+    rate_limits: Vec<InspectedRateLimit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcome: Option<String>,
+}
+
+impl Inspection {
+    /// Records an inbound filter that matched the event.
+    pub fn filter(&mut self, name: &'static str) {
+        self.filters.push(name);
+    }
+
+    /// Records dynamic sampling rules that matched the envelope.
+    pub fn sampling_rules(&mut self, rule_ids: &MatchedRuleIds) {
+        self.sampling_rules.extend_from_slice(&rule_ids.0);
+    }
+
+    /// Records all active rate limits.
+    pub fn rate_limits(&mut self, limits: &RateLimits) {
+        self.rate_limits
+            .extend(limits.iter().map(|limit| InspectedRateLimit {
+                categories: limit.categories.iter().map(|c| c.name()).collect(),
+                reason_code: limit.reason_code.as_ref().map(|r| r.as_str().to_owned()),
+            }));
+    }
+
+    /// Records the outcome of an envelope that was dropped during processing.
+    pub fn outcome(&mut self, outcome: impl ToString) {
+        self.outcome = Some(outcome.to_string());
+    }
+}
+
+/// An item of an inspected envelope.
+///
+/// The payload is included only for event items, which have passed data scrubbing, if it is valid
+/// JSON. All other items are listed with their size.
+#[derive(Debug, Serialize)]
+struct InspectedItem<'a> {
+    #[serde(rename = "type")]
+    ty: &'a ItemType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filename: Option<&'a str>,
+    length: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<serde_json::Value>,
+}
+
+impl<'a> InspectedItem<'a> {
+    fn new(item: &'a Item) -> Self {
+        let payload = item.payload();
+        let scrubbed = matches!(
+            item.ty(),
+            ItemType::Event | ItemType::Transaction | ItemType::Security
+        );
+        let json = match item.content_type() {
+            Some(ContentType::Json) | None if scrubbed => serde_json::from_slice(&payload).ok(),
+            _ => None,
+        };
+
+        Self {
+            ty: item.ty(),
+            content_type: item.content_type().map(ContentType::as_str),
+            filename: item.filename(),
+            length: payload.len(),
+            payload: json,
+        }
+    }
+}
+
+/// The record written for every inspected envelope.
+#[derive(Debug, Serialize)]
+struct InspectedEnvelope<'a> {
+    timestamp: DateTime<Utc>,
+    project_key: ProjectKey,
+    project_id: ProjectId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_id: Option<EventId>,
+    decisions: Inspection,
+    items: Vec<InspectedItem<'a>>,
+}
+
+/// Writes a sample of processed envelopes for debugging.
+///
+/// Sampling is deterministic: the inspector selects every N-th envelope passed to
+/// [`sample`](Self::sample), as configured in [`Inspect::sample_every`]. At most
+/// [`Inspect::max_files`] envelopes are written.
+#[derive(Debug)]
+pub struct EnvelopeInspector {
+    sample_every: u64,
+    path: PathBuf,
+    max_files: usize,
+    counter: AtomicU64,
+    written: AtomicUsize,
+}
+
+impl EnvelopeInspector {
+    /// Creates a new inspector and its output directory.
+    ///
+    /// Returns `None` if no output directory is configured or it cannot be created.
+    pub fn new(config: &Inspect) -> Option<Self> {
+        let Some(ref path) = config.path else {
+            relay_log::error!("envelope inspection requires `inspect.path`");
+            return None;
+        };
+
+        if let Err(error) = fs::create_dir_all(path) {
+            relay_log::error!(
+                error = &error as &dyn std::error::Error,
+                "failed to create inspect directory"
+            );
+            return None;
+        }
+
+        Some(Self {
+            sample_every: config.sample_every.max(1),
+            path: path.clone(),
+            max_files: config.max_files,
+            counter: AtomicU64::new(0),
+            written: AtomicUsize::new(0),
+        })
+    }
+
+    /// Returns an [`Inspection`] for every N-th call, and `None` otherwise.
+    pub fn sample(&self) -> Option<Inspection> {
+        let count = self.counter.fetch_add(1, Ordering::Relaxed);
+        (count % self.sample_every == 0).then(Inspection::default)
+    }
+
+    /// Writes the decisions and the processed envelope.
+    ///
+    /// Items are only written if `scrubbed` is `true`. Envelopes that were dropped during
+    /// processing may not have passed data scrubbing, so only their decisions are written.
+    pub fn write(
+        &self,
+        inspection: Inspection,
+        project_id: ProjectId,
+        envelope: &Envelope,
+        scrubbed: bool,
+    ) {
+        let seq = self.written.fetch_add(1, Ordering::Relaxed);
+        if seq >= self.max_files {
+            return;
+        }
+
+        let record = InspectedEnvelope {
+            timestamp: Utc::now(),
+            project_key: envelope.meta().public_key(),
+            project_id,
+            event_id: envelope.event_id(),
+            decisions: inspection,
+            items: match scrubbed {
+                true => envelope.items().map(InspectedItem::new).collect(),
+                false => Vec::new(),
+            },
+        };
+
+        let json = match serde_json::to_string_pretty(&record) {
+            Ok(json) => json,
+            Err(error) => {
+                relay_log::error!(
+                    error = &error as &dyn std::error::Error,
+                    "failed to serialize inspected envelope"
+                );
+                return;
+            }
+        };
+
+        // The sequence number keeps names unique within a run, the timestamp across restarts.
+        let name = format!("{}-{seq:06}.json", record.timestamp.timestamp_millis());
+        if let Err(error) = fs::write(self.path.join(name), json) {
+            relay_log::error!(
+                error = &error as &dyn std::error::Error,
+                "failed to write inspected envelope"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn inspector(path: &std::path::Path, sample_every: u64, max_files: usize) -> EnvelopeInspector {
+        EnvelopeInspector::new(&Inspect {
+            enabled: true,
+            sample_every,
+            path: Some(path.to_owned()),
+            max_files,
+        })
+        .unwrap()
+    }
+
+    fn event_envelope() -> Box<Envelope> {
+        let bytes = Bytes::from(
+            "\
+             {\"event_id\":\"9ec79c33ec9942ab8353589fcb2e04dc\",\"dsn\":\"https://e12d836b15bb49d7bbf99e64295d995b:@sentry.io/42\"}\n\
+             {\"type\":\"event\"}\n\
+             {\"message\":\"hello\"}\n\
+             {\"type\":\"user_report\"}\n\
+             {\"email\":\"jane@example.org\"}\n\
+             {\"type\":\"attachment\",\"filename\":\"data.bin\"}\n\
+             \x00\x01\n\
+             ",
+        );
+        Envelope::parse_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_requires_path() {
+        let inspector = EnvelopeInspector::new(&Inspect {
+            enabled: true,
+            ..Default::default()
+        });
+        assert!(inspector.is_none());
+    }
+
+    #[test]
+    fn test_sample_every() {
+        let directory = tempfile::tempdir().unwrap();
+        let inspector = inspector(directory.path(), 3, 10);
+
+        let sampled = (0..7).filter(|_| inspector.sample().is_some()).count();
+        assert_eq!(sampled, 3);
+    }
+
+    #[test]
+    fn test_write_envelope() {
+        let directory = tempfile::tempdir().unwrap();
+        let inspector = inspector(directory.path(), 1, 10);
+        let envelope = event_envelope();
+
+        let mut inspection = inspector.sample().unwrap();
+        inspection.filter("localhost");
+        inspector.write(inspection, ProjectId::new(42), &envelope, true);
+
+        let entry = fs::read_dir(directory.path()).unwrap().next().unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(&fs::read(entry.unwrap().path()).unwrap()).unwrap();
+
+        assert_eq!(json["project_id"], 42);
+        assert_eq!(json["decisions"]["filters"][0], "localhost");
+        assert_eq!(json["items"][0]["payload"]["message"], "hello");
+        // Only the event has passed data scrubbing.
+        assert_eq!(json["items"][1]["type"], "user_report");
+        assert!(json["items"][1].get("payload").is_none());
+        assert_eq!(json["items"][2]["length"], 2);
+        assert!(json["items"][2].get("payload").is_none());
+    }
+
+    #[test]
+    fn test_write_max_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let inspector = inspector(directory.path(), 1, 2);
+        let envelope = event_envelope();
+
+        // All envelopes share the event ID and are likely written within the same millisecond.
+        for _ in 0..3 {
+            let inspection = inspector.sample().unwrap();
+            inspector.write(inspection, ProjectId::new(42), &envelope, false);
+        }
+
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 2);
+    }
+}
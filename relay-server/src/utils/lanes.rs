@@ -0,0 +1,221 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+
+/// A queue of work items with its scheduling options and state.
+#[derive(Debug)]
+struct Lane<T> {
+    /// The relative share of workers.
+    weight: f64,
+    /// The maximum number of concurrently active work items.
+    limit: usize,
+    /// The number of currently active work items.
+    active: Arc<AtomicUsize>,
+    /// The virtual start time of the next work item.
+    ///
+    /// Every dispatched item advances the virtual time by the inverse of the lane's weight. The
+    /// lane with the lowest virtual time is served first.
+    vtime: f64,
+    /// Pending work items.
+    queue: VecDeque<T>,
+}
+
+/// Identifier of a lane in [`WeightedLanes`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LaneId(usize);
+
+/// Schedules work items from multiple lanes onto a shared, limited number of workers.
+///
+/// Every lane receives a share of the workers proportional to its weight when multiple lanes have
+/// pending work. Workers are never left idle while there is pending work: if a lane has no pending
+/// work, its share is taken by the other lanes. Additionally, the number of concurrently active work
+/// items can be limited per lane, so that a single lane can never occupy all workers.
+///
+/// Work items are dispatched with [`pop`](Self::pop), which returns a [`LanePermit`] alongside the
+/// item. The worker remains occupied until the permit is dropped. Use [`ready`](Self::ready) to
+/// wait for a worker to become available.
+#[derive(Debug)]
+pub struct WeightedLanes<T> {
+    lanes: Vec<Lane<T>>,
+    capacity: usize,
+    active: Arc<AtomicUsize>,
+    notify: Arc<Notify>,
+    /// The virtual time of the last dispatched work item.
+    vtime: f64,
+}
+
+impl<T> WeightedLanes<T> {
+    /// Creates an empty scheduler with the given number of workers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lanes: Vec::new(),
+            capacity: capacity.max(1),
+            active: Arc::new(AtomicUsize::new(0)),
+            notify: Arc::new(Notify::new()),
+            vtime: 0.0,
+        }
+    }
+
+    /// Adds a lane with the given weight and concurrency limit.
+    ///
+    /// Without a limit, the lane may occupy all workers.
+    pub fn add_lane(&mut self, weight: u32, limit: Option<usize>) -> LaneId {
+        self.lanes.push(Lane {
+            weight: f64::from(weight.max(1)),
+            limit: limit.unwrap_or(self.capacity).max(1),
+            active: Arc::new(AtomicUsize::new(0)),
+            vtime: self.vtime,
+            queue: VecDeque::new(),
+        });
+
+        LaneId(self.lanes.len() - 1)
+    }
+
+    /// Enqueues a work item into the given lane.
+    pub fn push(&mut self, lane: LaneId, item: T) {
+        let vtime = self.vtime;
+        let lane = &mut self.lanes[lane.0];
+
+        // A lane that was idle must not catch up on the share it did not use.
+        if lane.queue.is_empty() {
+            lane.vtime = lane.vtime.max(vtime);
+        }
+
+        lane.queue.push_back(item);
+    }
+
+    /// Dequeues the next work item if a worker is available.
+    ///
+    /// Returns `None` if there is no pending work, all workers are busy, or all lanes with pending
+    /// work have reached their concurrency limit.
+    pub fn pop(&mut self) -> Option<(T, LanePermit)> {
+        if self.active.load(Ordering::Acquire) >= self.capacity {
+            return None;
+        }
+
+        let lane = self
+            .lanes
+            .iter_mut()
+            .filter(|lane| !lane.queue.is_empty())
+            .filter(|lane| lane.active.load(Ordering::Acquire) < lane.limit)
+            .min_by(|a, b| a.vtime.total_cmp(&b.vtime))?;
+
+        let item = lane.queue.pop_front()?;
+        self.vtime = lane.vtime;
+        lane.vtime += 1.0 / lane.weight;
+
+        lane.active.fetch_add(1, Ordering::AcqRel);
+        self.active.fetch_add(1, Ordering::AcqRel);
+
+        let permit = LanePermit {
+            lane: lane.active.clone(),
+            total: self.active.clone(),
+            notify: self.notify.clone(),
+        };
+
+        Some((item, permit))
+    }
+
+    /// Returns the number of pending work items in the given lane.
+    pub fn len(&self, lane: LaneId) -> usize {
+        self.lanes[lane.0].queue.len()
+    }
+
+    /// Returns the number of active work items in the given lane.
+    pub fn active(&self, lane: LaneId) -> usize {
+        self.lanes[lane.0].active.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if there is no pending work in any lane.
+    pub fn is_empty(&self) -> bool {
+        self.lanes.iter().all(|lane| lane.queue.is_empty())
+    }
+
+    /// Waits until a [`LanePermit`] is released.
+    ///
+    /// Work items may become available for [`pop`](Self::pop) after this resolves.
+    pub async fn ready(&self) {
+        self.notify.notified().await
+    }
+}
+
+/// Occupies a worker of [`WeightedLanes`] until dropped.
+#[derive(Debug)]
+pub struct LanePermit {
+    lane: Arc<AtomicUsize>,
+    total: Arc<AtomicUsize>,
+    notify: Arc<Notify>,
+}
+
+impl Drop for LanePermit {
+    fn drop(&mut self) {
+        self.lane.fetch_sub(1, Ordering::AcqRel);
+        self.total.fetch_sub(1, Ordering::AcqRel);
+        self.notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_share() {
+        let mut lanes = WeightedLanes::new(1);
+        let heavy = lanes.add_lane(3, None);
+        let light = lanes.add_lane(1, None);
+
+        for _ in 0..8 {
+            lanes.push(heavy, "heavy");
+            lanes.push(light, "light");
+        }
+
+        let mut order = Vec::new();
+        for _ in 0..8 {
+            let (item, permit) = lanes.pop().unwrap();
+            assert!(lanes.pop().is_none());
+            order.push(item);
+            drop(permit);
+        }
+
+        let heavy_count = order.iter().filter(|item| *item == &"heavy").count();
+        assert_eq!(heavy_count, 6);
+    }
+
+    #[test]
+    fn test_steal_idle_share() {
+        let mut lanes = WeightedLanes::new(4);
+        let busy = lanes.add_lane(1, None);
+        let _idle = lanes.add_lane(10, None);
+
+        for _ in 0..4 {
+            lanes.push(busy, ());
+        }
+
+        let permits: Vec<_> = std::iter::from_fn(|| lanes.pop()).collect();
+        assert_eq!(permits.len(), 4);
+    }
+
+    #[test]
+    fn test_lane_limit() {
+        let mut lanes = WeightedLanes::new(4);
+        let limited = lanes.add_lane(1, Some(1));
+        let other = lanes.add_lane(1, None);
+
+        lanes.push(limited, "limited");
+        lanes.push(limited, "limited");
+        lanes.push(other, "other");
+
+        let (_, first) = lanes.pop().unwrap();
+        let (_, second) = lanes.pop().unwrap();
+        assert!(lanes.pop().is_none());
+        assert_eq!(lanes.len(limited), 1);
+        assert_eq!(lanes.active(limited), 1);
+
+        drop((first, second));
+        assert_eq!(lanes.active(limited), 0);
+        assert_eq!(lanes.pop().unwrap().0, "limited");
+    }
+}
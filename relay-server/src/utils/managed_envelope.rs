@@ -4,15 +4,16 @@ use std::mem::size_of;
 use std::time::Instant;
 
 use chrono::{DateTime, Utc};
+use relay_log::protocol::{Breadcrumb, Map, Value};
 use relay_quotas::{DataCategory, Scoping};
 use relay_system::Addr;
 
 use crate::actors::outcome::{DiscardReason, Outcome, TrackOutcome};
-use crate::actors::test_store::{Capture, TestStore};
+use crate::actors::test_store::{Capture, TestStore, TrackResult};
 use crate::envelope::{Envelope, Item};
 use crate::extractors::RequestMeta;
 use crate::statsd::{RelayCounters, RelayTimers};
-use crate::utils::{EnvelopeSummary, SemaphorePermit};
+use crate::utils::{EnvelopeSummary, MemoryReservation, SemaphorePermit};
 
 /// Denotes the success of handling an envelope.
 #[derive(Clone, Copy, Debug)]
@@ -59,6 +60,7 @@ struct EnvelopeContext {
     summary: EnvelopeSummary,
     scoping: Scoping,
     slot: Option<SemaphorePermit>,
+    memory: Option<MemoryReservation>,
     done: bool,
 }
 
@@ -77,12 +79,22 @@ struct EnvelopeContext {
 ///
 /// The managed envelope also holds a processing queue permit which is used for backpressure
 /// management. It is automatically reclaimed when the context is dropped along with the envelope.
+///
+/// Additionally, the managed envelope carries a tracing span that covers its lifecycle. Services
+/// handling the envelope record their work in child spans of [`span`](Self::span). Envelopes
+/// received by an endpoint are children of the span of the HTTP request.
+///
+/// Finally, the stages the envelope passes through are recorded as breadcrumbs with
+/// [`record_stage`](Self::record_stage). They are attached to internal errors reported to Sentry
+/// while handling the envelope, and are only recorded if error reporting to Sentry is enabled.
 #[derive(Debug)]
 pub struct ManagedEnvelope {
     envelope: Box<Envelope>,
     context: EnvelopeContext,
     outcome_aggregator: Addr<TrackOutcome>,
     test_store: Addr<TestStore>,
+    span: relay_log::Span,
+    breadcrumbs: Vec<Breadcrumb>,
 }
 
 impl ManagedEnvelope {
@@ -96,16 +108,27 @@ impl ManagedEnvelope {
         let meta = &envelope.meta();
         let summary = EnvelopeSummary::compute(envelope.as_ref());
         let scoping = meta.get_partial_scoping();
+        // The span of the HTTP request is current while endpoints create managed envelopes.
+        let span = relay_log::debug_span!(
+            parent: &relay_log::Span::current(),
+            "envelope",
+            project_key = %meta.public_key(),
+            items = envelope.len(),
+        );
+
         Self {
             envelope,
             context: EnvelopeContext {
                 summary,
                 scoping,
                 slot,
+                memory: None,
                 done: false,
             },
             outcome_aggregator,
             test_store,
+            span,
+            breadcrumbs: Vec::new(),
         }
     }
 
@@ -145,6 +168,96 @@ impl ManagedEnvelope {
         Self::new_internal(envelope, Some(slot), outcome_aggregator, test_store)
     }
 
+    /// Binds the envelope to bytes reserved in the memory budget of the processing queue.
+    ///
+    /// The reservation is released together with the queue permit.
+    pub(crate) fn set_memory_reservation(&mut self, reservation: MemoryReservation) {
+        self.context.memory = Some(reservation);
+    }
+
+    /// Records that the envelope was received on the given endpoint.
+    ///
+    /// This is the first stage in the [breadcrumbs](Self::breadcrumbs) of the envelope.
+    pub fn record_received(&mut self, endpoint: &str) {
+        if relay_log::sentry_enabled() {
+            let breadcrumb = self.received_breadcrumb(endpoint);
+            self.breadcrumbs.push(breadcrumb);
+        }
+    }
+
+    /// Records that the envelope entered the given stage, such as `"process"`.
+    pub fn record_stage(&mut self, stage: &str) {
+        if relay_log::sentry_enabled() {
+            let breadcrumb = self.stage_breadcrumb(stage);
+            self.breadcrumbs.push(breadcrumb);
+        }
+    }
+
+    /// Copies the breadcrumbs of another envelope, for example after splitting it.
+    pub fn copy_breadcrumbs(&mut self, other: &Self) {
+        self.breadcrumbs.extend_from_slice(&other.breadcrumbs);
+    }
+
+    /// Records the lifecycle of this envelope in the span of the envelope it was split from.
+    pub fn share_span(&mut self, other: &Self) {
+        self.span = other.span.clone();
+    }
+
+    /// Returns the recorded stages of this envelope as breadcrumbs for internal error reports.
+    pub fn breadcrumbs(&self) -> &[Breadcrumb] {
+        &self.breadcrumbs
+    }
+
+    /// Creates the breadcrumb for the first stage of the envelope, see [`Self::record_received`].
+    fn received_breadcrumb(&self, endpoint: &str) -> Breadcrumb {
+        let mut breadcrumb = self.stage_breadcrumb("received");
+        breadcrumb.data.insert("endpoint".into(), endpoint.into());
+        if let Some(sdk) = self.envelope.meta().client_name() {
+            breadcrumb.data.insert("sdk".into(), sdk.into());
+        }
+        breadcrumb
+    }
+
+    /// Creates a breadcrumb describing the current state of the envelope.
+    ///
+    /// To keep internal error reports free of PII, the breadcrumb only contains the project, the
+    /// event ID, as well as types and sizes of items. Payloads, headers, filenames, and the client
+    /// address are never included.
+    fn stage_breadcrumb(&self, stage: &str) -> Breadcrumb {
+        let items = self
+            .envelope
+            .items()
+            .map(|item| {
+                let mut data = Map::new();
+                data.insert("type".into(), item.ty().to_string().into());
+                data.insert("size".into(), item.len().into());
+                Value::Object(data)
+            })
+            .collect::<Vec<_>>();
+
+        let mut data = Map::new();
+        data.insert("items".into(), items.into());
+        data.insert(
+            "project_id".into(),
+            self.context.scoping.project_id.value().into(),
+        );
+        if let Some(event_id) = self.envelope.event_id() {
+            data.insert("event_id".into(), event_id.to_string().into());
+        }
+
+        Breadcrumb {
+            category: Some("envelope".into()),
+            message: Some(stage.into()),
+            data,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the span covering the lifecycle of this envelope.
+    pub fn span(&self) -> &relay_log::Span {
+        &self.span
+    }
+
     /// Returns a reference to the contained [`Envelope`].
     pub fn envelope(&self) -> &Envelope {
         self.envelope.as_ref()
@@ -161,17 +274,11 @@ impl ManagedEnvelope {
     /// to done so there is no rejection issued once the [`ManagedEnvelope`] is consumed.
     pub fn into_envelope(mut self) -> Box<Envelope> {
         self.context.slot.take();
+        self.context.memory.take();
         self.context.done = true;
         Box::new(self.envelope.take_items())
     }
 
-    /// Take the envelope out of the context and replace it with a dummy.
-    ///
-    /// Note that after taking out the envelope, the envelope summary is incorrect.
-    pub(crate) fn take_envelope(&mut self) -> Box<Envelope> {
-        Box::new(self.envelope.take_items())
-    }
-
     /// Update the context with envelope information.
     ///
     /// This updates the item summary as well as the event id.
@@ -310,6 +417,13 @@ impl ManagedEnvelope {
         self.test_store
             .send(Capture::rejected(self.envelope.event_id(), &outcome));
 
+        if let Some(event_id) = self.envelope.event_id() {
+            if TrackResult::is_tracked(&self.envelope) {
+                self.test_store
+                    .send(TrackResult::rejected(event_id, &outcome));
+            }
+        }
+
         if let Some(category) = self.event_category() {
             self.track_outcome(outcome.clone(), category, 1);
         }
@@ -322,6 +436,14 @@ impl ManagedEnvelope {
             );
         }
 
+        if self.context.summary.trace_attachment_quantity > 0 {
+            self.track_outcome(
+                outcome.clone(),
+                DataCategory::Attachment,
+                self.context.summary.trace_attachment_quantity,
+            );
+        }
+
         if self.context.summary.profile_quantity > 0 {
             self.track_outcome(
                 outcome,
@@ -379,6 +501,7 @@ impl ManagedEnvelope {
     /// Resets inner state to ensure there's no more logging.
     fn finish(&mut self, counter: RelayCounters, handling: Handling) {
         self.context.slot.take();
+        self.context.memory.take();
 
         relay_statsd::metric!(counter(counter) += 1, handling = handling.as_str());
         relay_statsd::metric!(timer(RelayTimers::EnvelopeTotalTime) = self.start_time().elapsed());
@@ -392,3 +515,74 @@ impl Drop for ManagedEnvelope {
         self.reject(Outcome::Invalid(DiscardReason::Internal));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::envelope::{ContentType, ItemType};
+    use crate::testutils::empty_envelope;
+
+    use super::*;
+
+    #[test]
+    fn test_breadcrumbs() {
+        let mut envelope = empty_envelope();
+        let mut item = Item::new(ItemType::Attachment);
+        item.set_filename("secret.txt");
+        item.set_payload(ContentType::OctetStream, Bytes::from_static(b"hello"));
+        envelope.add_item(item);
+
+        let mut managed_envelope =
+            ManagedEnvelope::untracked(envelope, Addr::custom().0, Addr::custom().0);
+
+        // Without a Sentry client, no breadcrumbs are recorded.
+        managed_envelope.record_received("envelope");
+        managed_envelope.record_stage("process");
+        assert!(managed_envelope.breadcrumbs().is_empty());
+
+        let breadcrumbs = [
+            managed_envelope.received_breadcrumb("envelope"),
+            managed_envelope.stage_breadcrumb("process"),
+        ];
+        assert_eq!(breadcrumbs[0].message.as_deref(), Some("received"));
+        assert_eq!(breadcrumbs[0].data["endpoint"], "envelope");
+        assert_eq!(breadcrumbs[1].message.as_deref(), Some("process"));
+        assert_eq!(breadcrumbs[1].data["project_id"], 42);
+        assert_eq!(
+            breadcrumbs[1].data["items"],
+            serde_json::json!([{"type": "attachment", "size": 5}])
+        );
+
+        // Neither filenames nor payloads end up in the breadcrumbs.
+        let serialized = serde_json::to_string(&breadcrumbs).unwrap();
+        assert!(!serialized.contains("secret"));
+        assert!(!serialized.contains("hello"));
+    }
+
+    #[test]
+    fn test_span_level() {
+        relay_test::setup();
+
+        let managed_envelope =
+            ManagedEnvelope::untracked(empty_envelope(), Addr::custom().0, Addr::custom().0);
+
+        // INFO spans would become Sentry transactions and appear in every log line.
+        let metadata = managed_envelope.span().metadata().expect("span is enabled");
+        assert_eq!(*metadata.level(), relay_log::Level::DEBUG);
+    }
+
+    #[test]
+    fn test_share_span() {
+        relay_test::setup();
+
+        let original =
+            ManagedEnvelope::untracked(empty_envelope(), Addr::custom().0, Addr::custom().0);
+        let mut split =
+            ManagedEnvelope::untracked(empty_envelope(), Addr::custom().0, Addr::custom().0);
+        assert_ne!(split.span().id(), original.span().id());
+
+        split.share_span(&original);
+        assert_eq!(split.span().id(), original.span().id());
+    }
+}
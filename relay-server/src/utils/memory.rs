@@ -0,0 +1,86 @@
+/// Memory usage of the system Relay runs on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Serialize)]
+pub struct MemoryStat {
+    /// Memory in use in bytes, excluding memory that can be reclaimed such as page caches.
+    pub used: u64,
+    /// Total memory of the system in bytes.
+    pub total: u64,
+}
+
+impl MemoryStat {
+    /// Reads the current memory usage of the system.
+    ///
+    /// Returns `None` if memory statistics are not available on this platform.
+    pub fn current() -> Option<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+            Self::parse_meminfo(&meminfo)
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            None
+        }
+    }
+
+    /// Returns the fraction of used memory in the range `[0, 1]`.
+    pub fn used_percent(&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+
+        (self.used as f64 / self.total as f64) as f32
+    }
+
+    /// Parses the contents of `/proc/meminfo`.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn parse_meminfo(meminfo: &str) -> Option<Self> {
+        let mut total = None;
+        let mut available = None;
+
+        for line in meminfo.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+
+            let kilobytes = value.trim().trim_end_matches("kB").trim().parse::<u64>();
+            match key {
+                "MemTotal" => total = kilobytes.ok(),
+                "MemAvailable" => available = kilobytes.ok(),
+                _ => continue,
+            }
+        }
+
+        let total = total? * 1024;
+        let available = available? * 1024;
+
+        Some(Self {
+            used: total.saturating_sub(available),
+            total,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let meminfo = "MemTotal:       16000000 kB\n\
+                       MemFree:         2000000 kB\n\
+                       MemAvailable:    4000000 kB\n\
+                       Buffers:          500000 kB\n";
+
+        let stat = MemoryStat::parse_meminfo(meminfo).unwrap();
+        assert_eq!(stat.total, 16_000_000 * 1024);
+        assert_eq!(stat.used, 12_000_000 * 1024);
+        assert_eq!(stat.used_percent(), 0.75);
+    }
+
+    #[test]
+    fn test_parse_meminfo_incomplete() {
+        assert_eq!(MemoryStat::parse_meminfo("MemTotal: 1000 kB\n"), None);
+    }
+}
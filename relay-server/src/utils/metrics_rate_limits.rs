@@ -1,4 +1,6 @@
 //! Quota and rate limiting helpers for metrics and metrics buckets.
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use relay_common::time::UnixTimestamp;
 use relay_metrics::{Bucket, MetricNamespace, MetricResourceIdentifier};
@@ -25,6 +27,9 @@ pub struct MetricsLimiter<Q: AsRef<Vec<Quota>> = Vec<Quota>> {
     /// Binary index of metrics/buckets that encode processed profiles.
     profile_buckets: Vec<bool>,
 
+    /// The namespace of every metric/bucket, or `None` if the name could not be parsed.
+    namespaces: Vec<Option<MetricNamespace>>,
+
     /// The number of transactions contributing to these metrics.
     transaction_count: usize,
 
@@ -37,37 +42,40 @@ const PROFILE_TAG: &str = "has_profile";
 impl<Q: AsRef<Vec<Quota>>> MetricsLimiter<Q> {
     /// Create a new limiter instance.
     ///
-    /// Returns Ok if `metrics` contain transaction metrics, `metrics` otherwise.
+    /// Returns Ok if `metrics` contain transaction metrics or metrics in a namespace for which a
+    /// quota is defined, `metrics` otherwise.
     pub fn create(buckets: Vec<Bucket>, quotas: Q, scoping: Scoping) -> Result<Self, Vec<Bucket>> {
-        let counts: Vec<_> = buckets
+        let (namespaces, counts): (Vec<_>, Vec<_>) = buckets
             .iter()
             .map(|metric| {
                 let mri = match MetricResourceIdentifier::parse(&metric.name) {
                     Ok(mri) => mri,
                     Err(_) => {
                         relay_log::error!("invalid MRI: {}", metric.name);
-                        return None;
+                        return (None, None);
                     }
                 };
 
                 // Keep all metrics that are not transaction related:
                 if mri.namespace != MetricNamespace::Transactions {
-                    return None;
+                    return (Some(mri.namespace), None);
                 }
 
-                if mri.name == "duration" {
+                let counts = if mri.name == "duration" {
                     // The "duration" metric is extracted exactly once for every processed
                     // transaction, so we can use it to count the number of transactions.
                     let count = metric.value.len();
                     let has_profile = metric.tag(PROFILE_TAG) == Some("true");
-                    Some((count, has_profile))
+                    (count, has_profile)
                 } else {
                     // For any other metric in the transaction namespace, we check the limit with
                     // quantity=0 so transactions are not double counted against the quota.
-                    Some((0, false))
-                }
+                    (0, false)
+                };
+
+                (Some(mri.namespace), Some(counts))
             })
-            .collect();
+            .unzip();
 
         // Accumulate the total transaction count:
         let mut total_counts: Option<(usize, usize)> = None;
@@ -79,27 +87,36 @@ impl<Q: AsRef<Vec<Quota>>> MetricsLimiter<Q> {
             }
         }
 
-        if let Some((transaction_count, profile_count)) = total_counts {
-            let transaction_buckets = counts.iter().map(Option::is_some).collect();
-            let profile_buckets = counts
-                .iter()
-                .map(|o| match o {
-                    Some((_, has_profile)) => *has_profile,
-                    None => false,
-                })
-                .collect();
-            Ok(Self {
-                metrics: buckets,
-                quotas,
-                scoping,
-                transaction_buckets,
-                profile_buckets,
-                transaction_count,
-                profile_count,
-            })
-        } else {
-            Err(buckets)
+        let has_namespace_quotas = quotas.as_ref().iter().any(|quota| {
+            quota
+                .namespace
+                .is_some_and(|namespace| namespaces.contains(&Some(namespace)))
+        });
+
+        if total_counts.is_none() && !has_namespace_quotas {
+            return Err(buckets);
         }
+
+        let (transaction_count, profile_count) = total_counts.unwrap_or_default();
+        let transaction_buckets = counts.iter().map(Option::is_some).collect();
+        let profile_buckets = counts
+            .iter()
+            .map(|o| match o {
+                Some((_, has_profile)) => *has_profile,
+                None => false,
+            })
+            .collect();
+
+        Ok(Self {
+            metrics: buckets,
+            quotas,
+            scoping,
+            transaction_buckets,
+            profile_buckets,
+            namespaces,
+            transaction_count,
+            profile_count,
+        })
     }
 
     #[allow(dead_code)]
@@ -117,17 +134,57 @@ impl<Q: AsRef<Vec<Quota>>> MetricsLimiter<Q> {
         self.transaction_count
     }
 
-    fn drop_with_outcome(&mut self, outcome: Outcome, outcome_aggregator: Addr<TrackOutcome>) {
-        // Drop transaction buckets:
+    /// Returns `true` if any of the contained buckets are in the transaction namespace.
+    #[allow(dead_code)]
+    pub fn has_transactions(&self) -> bool {
+        self.transaction_buckets.contains(&true)
+    }
+
+    /// Returns the number of buckets per namespace for which a quota is defined.
+    #[allow(dead_code)]
+    pub fn limited_namespaces(&self) -> BTreeMap<MetricNamespace, usize> {
+        let mut namespaces = BTreeMap::new();
+
+        for namespace in self.namespaces.iter().flatten() {
+            let has_quota = self
+                .quotas
+                .as_ref()
+                .iter()
+                .any(|quota| quota.namespace == Some(*namespace));
+
+            if has_quota {
+                *namespaces.entry(*namespace).or_default() += 1;
+            }
+        }
+
+        namespaces
+    }
+
+    /// Retains only the buckets for which `f` returns `true`, keeping all indexes in sync.
+    fn retain(&mut self, mut f: impl FnMut(Option<MetricNamespace>, bool) -> bool) {
         let metrics = std::mem::take(&mut self.metrics);
+        let transaction_buckets = std::mem::take(&mut self.transaction_buckets);
+        let profile_buckets = std::mem::take(&mut self.profile_buckets);
+        let namespaces = std::mem::take(&mut self.namespaces);
 
-        self.metrics = metrics
+        for (((bucket, is_transaction), has_profile), namespace) in metrics
             .into_iter()
-            .zip(self.transaction_buckets.iter())
-            .filter_map(|(bucket, is_transaction_bucket)| {
-                (!is_transaction_bucket).then_some(bucket)
-            })
-            .collect();
+            .zip(transaction_buckets)
+            .zip(profile_buckets)
+            .zip(namespaces)
+        {
+            if f(namespace, is_transaction) {
+                self.metrics.push(bucket);
+                self.transaction_buckets.push(is_transaction);
+                self.profile_buckets.push(has_profile);
+                self.namespaces.push(namespace);
+            }
+        }
+    }
+
+    fn drop_with_outcome(&mut self, outcome: Outcome, outcome_aggregator: Addr<TrackOutcome>) {
+        // Drop transaction buckets:
+        self.retain(|_, is_transaction_bucket| !is_transaction_bucket);
 
         // Track outcome for the transaction metrics we dropped here:
         if self.transaction_count > 0 {
@@ -173,13 +230,13 @@ impl<Q: AsRef<Vec<Quota>>> MetricsLimiter<Q> {
         }
     }
 
-    // Drop transaction-related metrics and create outcomes for any active rate limits.
-    //
-    // If rate limits could not be checked for some reason, pass an `Err` to this function.
-    // In this case, transaction-related metrics will also be dropped, and an "internal"
-    // outcome is generated.
-    //
-    // Returns true if any metrics were dropped.
+    /// Drops rate limited metrics and creates outcomes for any active rate limits.
+    ///
+    /// If rate limits could not be checked for some reason, pass an `Err` to this function. In this
+    /// case, transaction-related metrics are dropped with an "internal" outcome (fail closed), while
+    /// buckets limited by namespace quotas are kept (fail open).
+    ///
+    /// Returns `true` if any metrics were dropped.
     pub fn enforce_limits(
         &mut self,
         rate_limits: Result<&RateLimits, ()>,
@@ -188,9 +245,17 @@ impl<Q: AsRef<Vec<Quota>>> MetricsLimiter<Q> {
         let mut dropped_stuff = false;
         match rate_limits {
             Ok(rate_limits) => {
+                dropped_stuff |= self.enforce_namespace_limits(rate_limits, &outcome_aggregator);
+
+                if !self.has_transactions() {
+                    return dropped_stuff;
+                }
+
                 let item_scoping = ItemScoping {
                     category: DataCategory::Transaction,
                     scoping: &self.scoping,
+                    attributes: None,
+                    namespace: None,
                 };
                 let active_rate_limits =
                     rate_limits.check_with_quotas(self.quotas.as_ref(), item_scoping);
@@ -207,6 +272,8 @@ impl<Q: AsRef<Vec<Quota>>> MetricsLimiter<Q> {
                     let item_scoping = ItemScoping {
                         category: DataCategory::Profile,
                         scoping: &self.scoping,
+                        attributes: None,
+                        namespace: None,
                     };
                     let active_rate_limits =
                         rate_limits.check_with_quotas(self.quotas.as_ref(), item_scoping);
@@ -223,17 +290,62 @@ impl<Q: AsRef<Vec<Quota>>> MetricsLimiter<Q> {
             }
             Err(_) => {
                 // Error from rate limiter, drop transaction buckets.
+                dropped_stuff = self.has_transactions();
                 self.drop_with_outcome(
                     Outcome::Invalid(DiscardReason::Internal),
                     outcome_aggregator,
                 );
-                dropped_stuff = true;
             }
         };
 
         dropped_stuff
     }
 
+    /// Drops buckets in namespaces with active namespace-scoped rate limits.
+    ///
+    /// Only rate limits that explicitly define a namespace are considered, so that limits on other
+    /// data categories do not affect unrelated metrics. Returns `true` if any buckets were dropped.
+    fn enforce_namespace_limits(
+        &mut self,
+        rate_limits: &RateLimits,
+        outcome_aggregator: &Addr<TrackOutcome>,
+    ) -> bool {
+        let mut dropped_stuff = false;
+        let scoping = self.scoping;
+
+        for (namespace, quantity) in self.limited_namespaces() {
+            let item_scoping = scoping
+                .item(DataCategory::MetricBucket)
+                .with_namespace(namespace);
+
+            let active_rate_limits =
+                rate_limits.check_with_quotas(self.quotas.as_ref(), item_scoping);
+            let longest = active_rate_limits
+                .iter()
+                .filter(|limit| limit.namespace.is_some())
+                .max_by_key(|limit| limit.retry_after);
+
+            let Some(limit) = longest else {
+                continue;
+            };
+
+            self.retain(|bucket_namespace, _| bucket_namespace != Some(namespace));
+            dropped_stuff = true;
+
+            outcome_aggregator.send(TrackOutcome {
+                timestamp: UnixTimestamp::now().as_datetime().unwrap_or_else(Utc::now),
+                scoping,
+                outcome: Outcome::RateLimited(limit.reason_code.clone()),
+                event_id: None,
+                remote_addr: None,
+                category: DataCategory::MetricBucket,
+                quantity: quantity as u32,
+            });
+        }
+
+        dropped_stuff
+    }
+
     /// Consume this struct and return the contained metrics.
     pub fn into_metrics(self) -> Vec<Bucket> {
         self.metrics
@@ -244,7 +356,7 @@ impl<Q: AsRef<Vec<Quota>>> MetricsLimiter<Q> {
 mod tests {
     use relay_base_schema::project::{ProjectId, ProjectKey};
     use relay_metrics::{Bucket, BucketValue};
-    use relay_quotas::{Quota, QuotaScope};
+    use relay_quotas::{Quota, QuotaScope, ReasonCode};
     use smallvec::smallvec;
 
     use super::*;
@@ -285,6 +397,8 @@ mod tests {
             limit: Some(0),
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         }];
         let (outcome_sink, mut rx) = Addr::custom();
 
@@ -356,6 +470,8 @@ mod tests {
             limit: Some(0),
             window: None,
             reason_code: None,
+            attribute: None,
+            namespace: None,
         }];
         let (outcome_sink, mut rx) = Addr::custom();
 
@@ -396,4 +512,179 @@ mod tests {
             vec![(Outcome::RateLimited(None), DataCategory::Profile, 1)]
         );
     }
+
+    #[test]
+    fn namespace_quota_is_enforced() {
+        let metrics = vec![
+            Bucket {
+                timestamp: UnixTimestamp::now(),
+                width: 0,
+                name: "c:custom/clicks@none".to_string(),
+                tags: Default::default(),
+                value: BucketValue::counter(1.0),
+            },
+            Bucket {
+                timestamp: UnixTimestamp::now(),
+                width: 0,
+                name: "d:custom/load@millisecond".to_string(),
+                tags: Default::default(),
+                value: BucketValue::distribution(123.0),
+            },
+            Bucket {
+                timestamp: UnixTimestamp::now(),
+                width: 0,
+                name: "c:sessions/session@none".to_string(),
+                tags: Default::default(),
+                value: BucketValue::counter(1.0),
+            },
+        ];
+        let quotas = vec![Quota {
+            id: None,
+            categories: smallvec![DataCategory::MetricBucket],
+            scope: QuotaScope::Key,
+            scope_id: None,
+            limit: Some(0),
+            window: None,
+            reason_code: Some(ReasonCode::new("custom_metrics")),
+            attribute: None,
+            namespace: Some(MetricNamespace::Custom),
+        }];
+        let (outcome_sink, mut rx) = Addr::custom();
+
+        let mut limiter = MetricsLimiter::create(
+            metrics,
+            quotas,
+            Scoping {
+                organization_id: 1,
+                project_id: ProjectId::new(1),
+                project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+                key_id: None,
+            },
+        )
+        .unwrap();
+
+        assert!(limiter.enforce_limits(Ok(&RateLimits::new()), outcome_sink));
+        let metrics = limiter.into_metrics();
+
+        // Only the sessions bucket is preserved:
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].name, "c:sessions/session@none");
+
+        rx.close();
+
+        let outcomes: Vec<_> = (0..)
+            .map(|_| rx.blocking_recv())
+            .take_while(|o| o.is_some())
+            .flatten()
+            .map(|o| (o.outcome, o.category, o.quantity))
+            .collect();
+
+        assert_eq!(
+            outcomes,
+            vec![(
+                Outcome::RateLimited(Some(ReasonCode::new("custom_metrics"))),
+                DataCategory::MetricBucket,
+                2
+            )]
+        );
+    }
+
+    #[test]
+    fn rate_limiter_error() {
+        let transaction = Bucket {
+            timestamp: UnixTimestamp::now(),
+            width: 0,
+            name: "d:transactions/duration@millisecond".to_string(),
+            tags: Default::default(),
+            value: BucketValue::distribution(123.0),
+        };
+        let custom = Bucket {
+            timestamp: UnixTimestamp::now(),
+            width: 0,
+            name: "c:custom/clicks@none".to_string(),
+            tags: Default::default(),
+            value: BucketValue::counter(1.0),
+        };
+        let quotas = vec![Quota {
+            id: None,
+            categories: smallvec![DataCategory::MetricBucket],
+            scope: QuotaScope::Key,
+            scope_id: None,
+            limit: Some(0),
+            window: None,
+            reason_code: None,
+            attribute: None,
+            namespace: Some(MetricNamespace::Custom),
+        }];
+        let scoping = Scoping {
+            organization_id: 1,
+            project_id: ProjectId::new(1),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: None,
+        };
+        let (outcome_sink, mut rx) = Addr::custom();
+
+        // Transaction buckets are dropped, namespace-limited buckets are kept.
+        let mut limiter =
+            MetricsLimiter::create(vec![transaction, custom.clone()], quotas.clone(), scoping)
+                .unwrap();
+        assert!(limiter.enforce_limits(Err(()), outcome_sink.clone()));
+        let metrics = limiter.into_metrics();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].name, "c:custom/clicks@none");
+
+        // Without transaction buckets, nothing is dropped.
+        let mut limiter = MetricsLimiter::create(vec![custom], quotas, scoping).unwrap();
+        assert!(!limiter.enforce_limits(Err(()), outcome_sink));
+        assert_eq!(limiter.into_metrics().len(), 1);
+
+        rx.close();
+
+        let outcomes: Vec<_> = (0..)
+            .map(|_| rx.blocking_recv())
+            .take_while(|o| o.is_some())
+            .flatten()
+            .map(|o| (o.outcome, o.category, o.quantity))
+            .collect();
+
+        assert_eq!(
+            outcomes,
+            vec![(
+                Outcome::Invalid(DiscardReason::Internal),
+                DataCategory::Transaction,
+                1
+            )]
+        );
+    }
+
+    #[test]
+    fn namespace_quota_ignores_other_metrics() {
+        let metrics = vec![Bucket {
+            timestamp: UnixTimestamp::now(),
+            width: 0,
+            name: "c:sessions/session@none".to_string(),
+            tags: Default::default(),
+            value: BucketValue::counter(1.0),
+        }];
+        let quotas = vec![Quota {
+            id: None,
+            categories: smallvec![DataCategory::MetricBucket],
+            scope: QuotaScope::Key,
+            scope_id: None,
+            limit: Some(0),
+            window: None,
+            reason_code: None,
+            attribute: None,
+            namespace: Some(MetricNamespace::Custom),
+        }];
+
+        let scoping = Scoping {
+            organization_id: 1,
+            project_id: ProjectId::new(1),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: None,
+        };
+
+        assert!(MetricsLimiter::create(metrics, quotas, scoping).is_err());
+    }
 }
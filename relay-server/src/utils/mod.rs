@@ -2,7 +2,10 @@ mod api;
 mod buffer;
 mod dynamic_sampling;
 mod garbage;
+mod inspect;
+mod lanes;
 mod managed_envelope;
+mod memory;
 mod metrics_rate_limits;
 mod multipart;
 mod param_parser;
@@ -12,6 +15,7 @@ mod semaphore;
 mod sizes;
 mod sleep_handle;
 mod statsd;
+mod tls;
 
 #[cfg(feature = "processing")]
 mod native;
@@ -22,7 +26,10 @@ pub use self::api::*;
 pub use self::buffer::*;
 pub use self::dynamic_sampling::*;
 pub use self::garbage::*;
+pub use self::inspect::*;
+pub use self::lanes::*;
 pub use self::managed_envelope::*;
+pub use self::memory::*;
 pub use self::metrics_rate_limits::*;
 pub use self::multipart::*;
 #[cfg(feature = "processing")]
@@ -34,5 +41,6 @@ pub use self::semaphore::*;
 pub use self::sizes::*;
 pub use self::sleep_handle::*;
 pub use self::statsd::*;
+pub use self::tls::*;
 #[cfg(feature = "processing")]
 pub use self::unreal::*;
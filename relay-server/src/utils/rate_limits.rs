@@ -18,6 +18,12 @@ pub fn format_rate_limits(rate_limits: &RateLimits) -> String {
     let mut header = String::new();
 
     for rate_limit in rate_limits {
+        // Clients cannot enforce limits for individual attribute values, so such limits would
+        // block all of their data. Skip them, they are enforced again in Relay.
+        if rate_limit.attribute.is_some() {
+            continue;
+        }
+
         if !header.is_empty() {
             header.push_str(", ");
         }
@@ -35,6 +41,12 @@ pub fn format_rate_limits(rate_limits: &RateLimits) -> String {
 
         if let Some(ref reason_code) = rate_limit.reason_code {
             write!(header, ":{reason_code}").ok();
+        } else if rate_limit.namespace.is_some() {
+            header.push(':');
+        }
+
+        if let Some(namespace) = rate_limit.namespace {
+            write!(header, ":{namespace}").ok();
         }
     }
 
@@ -68,13 +80,23 @@ pub fn parse_rate_limits(scoping: &Scoping, string: &str) -> RateLimits {
         let quota_scope = QuotaScope::from_name(components.next().unwrap_or(""));
         let scope = RateLimitScope::for_quota(scoping, quota_scope);
 
-        let reason_code = components.next().map(ReasonCode::new);
+        let reason_code = components
+            .next()
+            .filter(|code| !code.is_empty())
+            .map(ReasonCode::new);
+
+        let namespace = components
+            .next()
+            .filter(|namespace| !namespace.is_empty())
+            .and_then(|namespace| namespace.parse().ok());
 
         rate_limits.add(RateLimit {
             categories,
             scope,
             reason_code,
             retry_after,
+            attribute: None,
+            namespace,
         });
     }
 
@@ -96,18 +118,24 @@ fn infer_event_category(item: &Item) -> Option<DataCategory> {
         ItemType::UnrealReport => Some(DataCategory::Error),
         ItemType::Attachment if item.creates_event() => Some(DataCategory::Error),
         ItemType::Attachment => None,
+        ItemType::TraceAttachment => None,
         ItemType::Session => None,
         ItemType::Sessions => None,
         ItemType::Statsd => None,
         ItemType::MetricBuckets => None,
+        ItemType::MetricMeta => None,
         ItemType::FormData => None,
         ItemType::UserReport => None,
+        ItemType::Feedback => None,
         ItemType::Profile => None,
+        ItemType::ProfileChunk => None,
         ItemType::ReplayEvent => None,
         ItemType::ReplayRecording => None,
+        ItemType::ReplayVideo => None,
         ItemType::ClientReport => None,
         ItemType::CheckIn => None,
         ItemType::Span => None,
+        ItemType::Log => None,
         ItemType::Unknown(_) => None,
     }
 }
@@ -125,18 +153,36 @@ pub struct EnvelopeSummary {
     /// The quantity of all attachments combined in bytes.
     pub attachment_quantity: usize,
 
+    /// The quantity of all trace attachments combined in bytes.
+    ///
+    /// Trace attachments do not belong to the event in the envelope, so they are rate limited
+    /// separately from other attachments.
+    pub trace_attachment_quantity: usize,
+
     /// The number of all session updates.
     pub session_quantity: usize,
 
     /// The number of profiles.
     pub profile_quantity: usize,
 
+    /// The duration of all profile chunks combined in milliseconds.
+    pub profile_duration_quantity: usize,
+
     /// The number of replays.
     pub replay_quantity: usize,
 
     /// The number of monitor check-ins.
     pub checkin_quantity: usize,
 
+    /// The number of standalone spans.
+    pub span_quantity: usize,
+
+    /// The size of all logs combined in bytes.
+    pub log_byte_quantity: usize,
+
+    /// The number of feedback items.
+    pub feedback_quantity: usize,
+
     /// Indicates that the envelope contains regular attachments that do not create event payloads.
     pub has_plain_attachments: bool,
 
@@ -185,11 +231,17 @@ impl EnvelopeSummary {
     fn set_quantity(&mut self, item: &Item) {
         let target_quantity = match item.ty() {
             ItemType::Attachment => &mut self.attachment_quantity,
+            ItemType::TraceAttachment => &mut self.trace_attachment_quantity,
             ItemType::Session => &mut self.session_quantity,
             ItemType::Profile => &mut self.profile_quantity,
+            ItemType::ProfileChunk => &mut self.profile_duration_quantity,
             ItemType::ReplayEvent => &mut self.replay_quantity,
             ItemType::ReplayRecording => &mut self.replay_quantity,
+            ItemType::ReplayVideo => &mut self.replay_quantity,
             ItemType::CheckIn => &mut self.checkin_quantity,
+            ItemType::Span if !item.from_transaction() => &mut self.span_quantity,
+            ItemType::Log => &mut self.log_byte_quantity,
+            ItemType::Feedback => &mut self.feedback_quantity,
             _ => return,
         };
         *target_quantity += item.quantity();
@@ -264,21 +316,30 @@ pub struct Enforcement {
     event: CategoryLimit,
     /// The combined attachment item rate limit.
     attachments: CategoryLimit,
+    /// The combined trace attachment item rate limit.
+    trace_attachments: CategoryLimit,
     /// The combined session item rate limit.
     sessions: CategoryLimit,
     /// The combined profile item rate limit.
     profiles: CategoryLimit,
+    /// The combined profile chunk rate limit.
+    profile_chunks: CategoryLimit,
     /// The combined replay item rate limit.
     replays: CategoryLimit,
     /// The combined check-in item rate limit.
     check_ins: CategoryLimit,
+    /// The combined span item rate limit.
+    spans: CategoryLimit,
+    /// The combined log item rate limit.
+    logs: CategoryLimit,
+    /// The combined feedback item rate limit.
+    feedback: CategoryLimit,
     /// Metrics extraction from a transaction is rate limited.
     event_metrics: CategoryLimit,
 }
 
 impl Enforcement {
     /// Returns `true` if the event should be rate limited.
-    #[cfg(feature = "processing")]
     pub fn event_active(&self) -> bool {
         self.event.is_active()
     }
@@ -297,19 +358,29 @@ impl Enforcement {
         let Self {
             event,
             attachments,
+            trace_attachments,
             sessions: _, // Do not report outcomes for sessions.
             profiles,
+            profile_chunks,
             replays,
             check_ins,
+            spans,
+            logs,
+            feedback,
             event_metrics,
         } = self;
 
         let limits = [
             event,
             attachments,
+            trace_attachments,
             profiles,
+            profile_chunks,
             replays,
             check_ins,
+            spans,
+            logs,
+            feedback,
             event_metrics,
         ];
 
@@ -379,7 +450,6 @@ where
     /// This ensures that rate limits for the given data category are checked even if there is no
     /// matching item in the envelope. Other items are handled according to the rules as if the
     /// event item were present.
-    #[cfg(feature = "processing")]
     pub fn assume_event(&mut self, category: DataCategory, metrics_extracted: bool) {
         self.event_category = Some((category, metrics_extracted));
     }
@@ -540,6 +610,18 @@ where
             }
         }
 
+        if summary.trace_attachment_quantity > 0 {
+            let item_scoping = scoping.item(DataCategory::Attachment);
+            let trace_attachment_limits =
+                (self.check)(item_scoping, summary.trace_attachment_quantity)?;
+            enforcement.trace_attachments = CategoryLimit::new(
+                DataCategory::Attachment,
+                summary.trace_attachment_quantity,
+                trace_attachment_limits.longest(),
+            );
+            rate_limits.merge(trace_attachment_limits);
+        }
+
         if summary.session_quantity > 0 {
             let item_scoping = scoping.item(DataCategory::Session);
             let session_limits = (self.check)(item_scoping, summary.session_quantity)?;
@@ -566,6 +648,17 @@ where
             rate_limits.merge(profile_limits);
         }
 
+        if summary.profile_duration_quantity > 0 {
+            let item_scoping = scoping.item(DataCategory::ProfileDuration);
+            let chunk_limits = (self.check)(item_scoping, summary.profile_duration_quantity)?;
+            enforcement.profile_chunks = CategoryLimit::new(
+                DataCategory::ProfileDuration,
+                summary.profile_duration_quantity,
+                chunk_limits.longest(),
+            );
+            rate_limits.merge(chunk_limits);
+        }
+
         if summary.replay_quantity > 0 {
             let item_scoping = scoping.item(DataCategory::Replay);
             let replay_limits = (self.check)(item_scoping, summary.replay_quantity)?;
@@ -588,6 +681,39 @@ where
             rate_limits.merge(checkin_limits);
         }
 
+        if summary.span_quantity > 0 {
+            let item_scoping = scoping.item(DataCategory::Span);
+            let span_limits = (self.check)(item_scoping, summary.span_quantity)?;
+            enforcement.spans = CategoryLimit::new(
+                DataCategory::Span,
+                summary.span_quantity,
+                span_limits.longest(),
+            );
+            rate_limits.merge(span_limits);
+        }
+
+        if summary.log_byte_quantity > 0 {
+            let item_scoping = scoping.item(DataCategory::LogByte);
+            let log_limits = (self.check)(item_scoping, summary.log_byte_quantity)?;
+            enforcement.logs = CategoryLimit::new(
+                DataCategory::LogByte,
+                summary.log_byte_quantity,
+                log_limits.longest(),
+            );
+            rate_limits.merge(log_limits);
+        }
+
+        if summary.feedback_quantity > 0 {
+            let item_scoping = scoping.item(DataCategory::Feedback);
+            let feedback_limits = (self.check)(item_scoping, summary.feedback_quantity)?;
+            enforcement.feedback = CategoryLimit::new(
+                DataCategory::Feedback,
+                summary.feedback_quantity,
+                feedback_limits.longest(),
+            );
+            rate_limits.merge(feedback_limits);
+        }
+
         Ok((enforcement, rate_limits))
     }
 
@@ -617,9 +743,17 @@ where
             return false;
         }
 
+        // Profile chunks are not associated with events.
+        if enforcement.profile_chunks.is_active() && item.ty() == &ItemType::ProfileChunk {
+            return false;
+        }
+
         // Remove replays independently of events.
         if enforcement.replays.is_active()
-            && matches!(item.ty(), ItemType::ReplayEvent | ItemType::ReplayRecording)
+            && matches!(
+                item.ty(),
+                ItemType::ReplayEvent | ItemType::ReplayRecording | ItemType::ReplayVideo
+            )
         {
             return false;
         }
@@ -628,6 +762,24 @@ where
             return false;
         }
 
+        // Standalone spans are not associated with events.
+        if enforcement.spans.is_active() && item.ty() == &ItemType::Span {
+            return false;
+        }
+
+        if enforcement.logs.is_active() && item.ty() == &ItemType::Log {
+            return false;
+        }
+
+        if enforcement.feedback.is_active() && item.ty() == &ItemType::Feedback {
+            return false;
+        }
+
+        // Trace attachments are not associated with events.
+        if enforcement.trace_attachments.is_active() && item.ty() == &ItemType::TraceAttachment {
+            return false;
+        }
+
         true
     }
 }
@@ -644,9 +796,10 @@ impl<F> fmt::Debug for EnvelopeLimiter<'_, F> {
 mod tests {
     use std::collections::BTreeMap;
 
+    use relay_base_schema::metrics::MetricNamespace;
     use relay_base_schema::project::{ProjectId, ProjectKey};
     use relay_dynamic_config::TransactionMetricsConfig;
-    use relay_quotas::{ItemScoping, RetryAfter};
+    use relay_quotas::{AttributePartition, ItemScoping, RetryAfter};
     use smallvec::smallvec;
 
     use super::*;
@@ -662,6 +815,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: Some(ReasonCode::new("my_limit")),
             retry_after: RetryAfter::from_secs(42),
+            attribute: None,
+            namespace: None,
         });
 
         // Add a more specific rate limit for just one category.
@@ -670,6 +825,8 @@ mod tests {
             scope: RateLimitScope::Project(ProjectId::new(21)),
             reason_code: None,
             retry_after: RetryAfter::from_secs(4711),
+            attribute: None,
+            namespace: None,
         });
 
         let formatted = format_rate_limits(&rate_limits);
@@ -677,6 +834,70 @@ mod tests {
         assert_eq!(formatted, expected);
     }
 
+    #[test]
+    fn test_format_rate_limits_skips_attributes() {
+        let mut rate_limits = RateLimits::new();
+
+        rate_limits.add(RateLimit {
+            categories: DataCategories::new(),
+            scope: RateLimitScope::Organization(42),
+            reason_code: Some(ReasonCode::new("per_release")),
+            retry_after: RetryAfter::from_secs(42),
+            attribute: Some(AttributePartition {
+                name: "event.release".to_owned(),
+                partition: 7,
+            }),
+            namespace: None,
+        });
+
+        assert_eq!(format_rate_limits(&rate_limits), "");
+    }
+
+    #[test]
+    fn test_format_rate_limits_namespace() {
+        let mut rate_limits = RateLimits::new();
+
+        rate_limits.add(RateLimit {
+            categories: smallvec![DataCategory::MetricBucket],
+            scope: RateLimitScope::Key(
+                ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            ),
+            reason_code: None,
+            retry_after: RetryAfter::from_secs(60),
+            attribute: None,
+            namespace: Some(MetricNamespace::Custom),
+        });
+
+        assert_eq!(
+            format_rate_limits(&rate_limits),
+            "60:metric_bucket:key::custom"
+        );
+    }
+
+    #[test]
+    fn test_parse_rate_limits_namespace() {
+        let scoping = Scoping {
+            organization_id: 42,
+            project_id: ProjectId::new(21),
+            project_key: ProjectKey::parse("a94ae32be2584e0bbd7a4cbb95971fee").unwrap(),
+            key_id: Some(17),
+        };
+
+        let formatted =
+            "60:metric_bucket:key::custom, 42:metric_bucket:organization:my_limit:custom";
+        let rate_limits: Vec<RateLimit> =
+            parse_rate_limits(&scoping, formatted).into_iter().collect();
+
+        assert_eq!(rate_limits.len(), 2);
+        assert_eq!(rate_limits[0].reason_code, None);
+        assert_eq!(rate_limits[0].namespace, Some(MetricNamespace::Custom));
+        assert_eq!(
+            rate_limits[1].reason_code,
+            Some(ReasonCode::new("my_limit"))
+        );
+        assert_eq!(rate_limits[1].namespace, Some(MetricNamespace::Custom));
+    }
+
     #[test]
     fn test_parse_invalid_rate_limits() {
         let scoping = Scoping {
@@ -714,6 +935,8 @@ mod tests {
                     scope: RateLimitScope::Organization(42),
                     reason_code: Some(ReasonCode::new("my_limit")),
                     retry_after: rate_limits[0].retry_after,
+                    attribute: None,
+                    namespace: None,
                 },
                 RateLimit {
                     categories: smallvec![
@@ -724,6 +947,8 @@ mod tests {
                     scope: RateLimitScope::Project(ProjectId::new(21)),
                     reason_code: None,
                     retry_after: rate_limits[1].retry_after,
+                    attribute: None,
+                    namespace: None,
                 }
             ]
         );
@@ -753,6 +978,8 @@ mod tests {
                 scope: RateLimitScope::Organization(42),
                 reason_code: None,
                 retry_after: rate_limits[0].retry_after,
+                attribute: None,
+                namespace: None,
             },]
         );
     }
@@ -794,6 +1021,8 @@ mod tests {
             scope: RateLimitScope::Organization(42),
             reason_code: None,
             retry_after: RetryAfter::from_secs(60),
+            attribute: None,
+            namespace: None,
         }
     }
 
@@ -940,6 +1169,124 @@ mod tests {
         assert_eq!(outcomes, vec![(DataCategory::Profile, 2),]);
     }
 
+    /// Limit profile chunks by their duration.
+    #[test]
+    fn test_enforce_limit_profile_chunks() {
+        let mut envelope = envelope![ProfileChunk, ProfileChunk, Profile];
+        let config = ProjectConfig::default();
+
+        let mut mock = MockLimiter::default().deny(DataCategory::ProfileDuration);
+        let (enforcement, limits) = EnvelopeLimiter::new(Some(&config), |s, q| mock.check(s, q))
+            .enforce(&mut envelope, &scoping())
+            .unwrap();
+
+        assert!(limits.is_limited());
+        assert_eq!(envelope.len(), 1);
+        mock.assert_call(DataCategory::ProfileDuration, Some(2));
+        mock.assert_call(DataCategory::Profile, Some(1));
+
+        let outcomes = enforcement
+            .get_outcomes(&envelope, &scoping())
+            .map(|outcome| (outcome.category, outcome.quantity))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes, vec![(DataCategory::ProfileDuration, 2)]);
+    }
+
+    /// Limit standalone spans.
+    #[test]
+    fn test_enforce_limit_spans() {
+        let mut envelope = envelope![Span, Span, CheckIn];
+        let config = ProjectConfig::default();
+
+        let mut mock = MockLimiter::default().deny(DataCategory::Span);
+        let (enforcement, limits) = EnvelopeLimiter::new(Some(&config), |s, q| mock.check(s, q))
+            .enforce(&mut envelope, &scoping())
+            .unwrap();
+
+        assert!(limits.is_limited());
+        assert_eq!(envelope.len(), 1);
+        mock.assert_call(DataCategory::Monitor, Some(1));
+        mock.assert_call(DataCategory::Span, Some(2));
+
+        let outcomes = enforcement
+            .get_outcomes(&envelope, &scoping())
+            .map(|outcome| (outcome.category, outcome.quantity))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes, vec![(DataCategory::Span, 2)]);
+    }
+
+    /// Limit logs by their size in bytes.
+    #[test]
+    fn test_enforce_limit_logs() {
+        let mut envelope = envelope![Log, Log];
+        let config = ProjectConfig::default();
+
+        let mut mock = MockLimiter::default().deny(DataCategory::LogByte);
+        let (enforcement, limits) = EnvelopeLimiter::new(Some(&config), |s, q| mock.check(s, q))
+            .enforce(&mut envelope, &scoping())
+            .unwrap();
+
+        assert!(limits.is_limited());
+        assert_eq!(envelope.len(), 0);
+        mock.assert_call(DataCategory::LogByte, Some(20));
+
+        let outcomes = enforcement
+            .get_outcomes(&envelope, &scoping())
+            .map(|outcome| (outcome.category, outcome.quantity))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes, vec![(DataCategory::LogByte, 20)]);
+    }
+
+    /// Limit trace attachments independently of the event in the same envelope.
+    #[test]
+    fn test_enforce_limit_trace_attachments() {
+        let mut envelope = envelope![Event, TraceAttachment];
+        let config = ProjectConfig::default();
+
+        let mut mock = MockLimiter::default()
+            .deny(DataCategory::Error)
+            .deny(DataCategory::Attachment);
+        let (enforcement, limits) = EnvelopeLimiter::new(Some(&config), |s, q| mock.check(s, q))
+            .enforce(&mut envelope, &scoping())
+            .unwrap();
+
+        assert!(limits.is_limited());
+        assert_eq!(envelope.len(), 0);
+        mock.assert_call(DataCategory::Error, Some(1));
+        mock.assert_call(DataCategory::Attachment, Some(10));
+
+        let outcomes = enforcement
+            .get_outcomes(&envelope, &scoping())
+            .map(|outcome| (outcome.category, outcome.quantity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            vec![(DataCategory::Error, 1), (DataCategory::Attachment, 10)]
+        );
+    }
+
+    /// Limit feedback independently of events.
+    #[test]
+    fn test_enforce_limit_feedback() {
+        let mut envelope = envelope![Feedback, Feedback];
+        let config = ProjectConfig::default();
+
+        let mut mock = MockLimiter::default().deny(DataCategory::Feedback);
+        let (enforcement, limits) = EnvelopeLimiter::new(Some(&config), |s, q| mock.check(s, q))
+            .enforce(&mut envelope, &scoping())
+            .unwrap();
+
+        assert!(limits.is_limited());
+        assert_eq!(envelope.len(), 0);
+        mock.assert_call(DataCategory::Feedback, Some(2));
+
+        let outcomes = enforcement
+            .get_outcomes(&envelope, &scoping())
+            .map(|outcome| (outcome.category, outcome.quantity))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes, vec![(DataCategory::Feedback, 2)]);
+    }
+
     /// Limit replays.
     #[test]
     fn test_enforce_limit_replays() {
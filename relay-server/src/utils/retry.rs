@@ -20,10 +20,25 @@ pub struct RetryBackoff {
 impl RetryBackoff {
     /// Creates a new retry backoff based on configured thresholds.
     pub fn new(max_interval: Duration) -> Self {
+        Self::with_params(
+            Duration::from_millis(INITIAL_INTERVAL),
+            max_interval,
+            DEFAULT_RANDOMIZATION,
+        )
+    }
+
+    /// Creates a new retry backoff with a custom initial interval and randomization factor.
+    ///
+    /// The randomization factor must be in the range `[0, 1]`.
+    pub fn with_params(
+        initial_interval: Duration,
+        max_interval: Duration,
+        randomization_factor: f64,
+    ) -> Self {
         let backoff = ExponentialBackoff {
-            current_interval: Duration::from_millis(INITIAL_INTERVAL),
-            initial_interval: Duration::from_millis(INITIAL_INTERVAL),
-            randomization_factor: DEFAULT_RANDOMIZATION,
+            current_interval: initial_interval,
+            initial_interval,
+            randomization_factor,
             multiplier: DEFAULT_MULTIPLIER,
             max_interval,
             max_elapsed_time: None,
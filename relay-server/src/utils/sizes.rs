@@ -1,5 +1,6 @@
 use relay_config::Config;
 
+use crate::actors::outcome::{DiscardReason, Outcome};
 use crate::envelope::{AttachmentType, Envelope, ItemType};
 use crate::utils::{ItemAction, ManagedEnvelope};
 
@@ -14,8 +15,10 @@ use crate::utils::{ItemAction, ManagedEnvelope};
 ///  - `max_event_size`
 ///  - `max_attachment_size`
 ///  - `max_attachments_size`
+///  - `max_trace_attachment_size`
 ///  - `max_session_count`
 ///  - `max_profile_size`
+///  - `max_replay_video_size`
 pub fn check_envelope_size_limits(config: &Config, envelope: &Envelope) -> bool {
     let mut event_size = 0;
     let mut attachments_size = 0;
@@ -39,18 +42,28 @@ pub fn check_envelope_size_limits(config: &Config, envelope: &Envelope) -> bool
 
                 attachments_size += item.len()
             }
+            ItemType::TraceAttachment => {
+                if item.len() > config.max_trace_attachment_size() {
+                    return false;
+                }
+            }
             ItemType::ReplayRecording => {
                 if item.len() > config.max_replay_compressed_size() {
                     return false;
                 }
             }
+            ItemType::ReplayVideo => {
+                if item.len() > config.max_replay_video_size() {
+                    return false;
+                }
+            }
             ItemType::Session | ItemType::Sessions => {
                 session_count += 1;
             }
             ItemType::ClientReport => {
                 client_reports_size += item.len();
             }
-            ItemType::Profile => {
+            ItemType::Profile | ItemType::ProfileChunk => {
                 if item.len() > config.max_profile_size() {
                     return false;
                 }
@@ -61,13 +74,20 @@ pub fn check_envelope_size_limits(config: &Config, envelope: &Envelope) -> bool
                 }
             }
             ItemType::UserReport => (),
+            ItemType::Feedback => (),
             ItemType::Statsd => (),
             ItemType::MetricBuckets => (),
+            ItemType::MetricMeta => (),
             ItemType::Span => {
                 if item.len() > config.max_span_size() {
                     return false;
                 }
             }
+            ItemType::Log => {
+                if item.len() > config.max_log_size() {
+                    return false;
+                }
+            }
             ItemType::Unknown(_) => (),
         }
     }
@@ -99,3 +119,23 @@ pub fn remove_unknown_items(config: &Config, envelope: &mut ManagedEnvelope) {
         });
     }
 }
+
+/// Removes items of types that have been disabled at runtime.
+///
+/// Item types can be disabled as an emergency measure through the admin API, see
+/// [`Config::runtime_overrides`]. Dropped items are reported as invalid outcomes.
+pub fn remove_disabled_items(config: &Config, envelope: &mut ManagedEnvelope) {
+    if config.runtime_overrides().disabled_item_types.is_empty() {
+        return;
+    }
+
+    envelope.retain_items(|item| {
+        let ty = item.ty().to_string();
+        if config.item_type_disabled(&ty) {
+            relay_log::debug!("dropping disabled item of type '{ty}'");
+            ItemAction::Drop(Outcome::Invalid(DiscardReason::ItemTypeDisabled))
+        } else {
+            ItemAction::Keep
+        }
+    });
+}
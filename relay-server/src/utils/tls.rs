@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{bail, Context};
+use relay_auth::RelayId;
+use relay_config::{Config, SpkiPin};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerName};
+use rustls_pemfile::Item;
+use sha2::{Digest, Sha256};
+
+/// Computes the SPKI pin of a DER-encoded certificate.
+fn spki_pin(der: &[u8]) -> Option<SpkiPin> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+    let hash = Sha256::digest(certificate.public_key().raw);
+    Some(SpkiPin::from_hash(hash.into()))
+}
+
+/// Loads all certificates from a PEM file.
+fn load_ca_file(config: &Config) -> anyhow::Result<Vec<Vec<u8>>> {
+    let Some(path) = config.http_ca_file() else {
+        return Ok(Vec::new());
+    };
+
+    let file =
+        File::open(path).with_context(|| format!("failed to open CA file {}", path.display()))?;
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(file))
+        .with_context(|| format!("failed to read CA file {}", path.display()))?;
+
+    if certificates.is_empty() {
+        bail!("no certificates found in CA file {}", path.display());
+    }
+
+    Ok(certificates)
+}
+
+/// Loads a client certificate chain and its private key from a PEM file.
+fn load_client_certificate(path: &Path) -> anyhow::Result<(Vec<Certificate>, PrivateKey)> {
+    let file = File::open(path)
+        .with_context(|| format!("failed to open client certificate {}", path.display()))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file))
+        .with_context(|| format!("failed to read client certificate {}", path.display()))?;
+
+    let mut certificates = Vec::new();
+    let mut key = None;
+    for item in items {
+        match item {
+            Item::X509Certificate(der) => certificates.push(Certificate(der)),
+            Item::RSAKey(der) | Item::PKCS8Key(der) | Item::ECKey(der) => {
+                key.get_or_insert(PrivateKey(der));
+            }
+            _ => (),
+        }
+    }
+
+    if certificates.is_empty() {
+        bail!(
+            "no certificates found in client certificate {}",
+            path.display()
+        );
+    }
+
+    match key {
+        Some(key) => Ok((certificates, key)),
+        None => bail!(
+            "no private key found in client certificate {}",
+            path.display()
+        ),
+    }
+}
+
+/// Verifies server certificates against trusted roots and a set of SPKI pins.
+///
+/// The certificate chain must be valid, and at least one certificate in the chain must match a
+/// configured pin.
+struct PinnedCertVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<SpkiPin>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|certificate| spki_pin(&certificate.0))
+            .any(|pin| self.pins.contains(&pin));
+
+        if !pinned {
+            return Err(rustls::Error::General(
+                "server certificate does not match any configured SPKI pin".to_owned(),
+            ));
+        }
+
+        Ok(verified)
+    }
+}
+
+/// Applies the custom CA bundle, SPKI pins, and client certificate from the configuration to a
+/// client builder.
+///
+/// Certificates from `http.ca_file` are trusted in addition to the system's root certificates. If
+/// `http.spki_pins` is set, the client uses rustls with a verifier that additionally requires the
+/// certificate chain to match one of the pins. If `http.client_certificate` is set, the client uses
+/// rustls and presents the certificate to the upstream.
+pub fn configure_tls(
+    mut builder: reqwest::ClientBuilder,
+    config: &Config,
+) -> anyhow::Result<reqwest::ClientBuilder> {
+    let ca_certificates = load_ca_file(config)?;
+    let pins = config.http_spki_pins().to_vec();
+    let client_certificate = config
+        .http_client_certificate()
+        .map(load_client_certificate)
+        .transpose()?;
+
+    if pins.is_empty() && client_certificate.is_none() {
+        for der in &ca_certificates {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_der(der)?);
+        }
+        return Ok(builder);
+    }
+
+    let mut roots = RootCertStore::empty();
+    let native_certificates =
+        rustls_native_certs::load_native_certs().context("failed to load root certificates")?;
+    let native_certificates: Vec<_> = native_certificates.into_iter().map(|c| c.0).collect();
+    roots.add_parsable_certificates(&native_certificates);
+    roots.add_parsable_certificates(&ca_certificates);
+
+    let inner = WebPkiVerifier::new(roots, None);
+    let verifier: Arc<dyn ServerCertVerifier> = if pins.is_empty() {
+        Arc::new(inner)
+    } else {
+        Arc::new(PinnedCertVerifier { inner, pins })
+    };
+
+    let tls = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier);
+
+    let tls = match client_certificate {
+        Some((certificates, key)) => tls
+            .with_client_auth_cert(certificates, key)
+            .context("invalid client certificate")?,
+        None => tls.with_no_client_auth(),
+    };
+
+    Ok(builder.use_preconfigured_tls(tls))
+}
+
+/// Returns the relay ID of a downstream Relay's client certificate.
+///
+/// The certificate is forwarded by a TLS-terminating proxy as URL-encoded PEM in the header
+/// configured in `auth.client_certificates.header`. Returns `None` if the certificate cannot be
+/// parsed or its public key does not match any pin in `auth.client_certificates.relays`. Callers
+/// must ensure that the header was set by one of `auth.client_certificates.trusted_proxies`.
+pub fn client_certificate_relay(config: &Config, header: &str) -> Option<RelayId> {
+    let pem = percent_encoding::percent_decode_str(header).collect::<Vec<_>>();
+    let der = rustls_pemfile::certs(&mut pem.as_slice())
+        .ok()?
+        .into_iter()
+        .next()?;
+    let pin = spki_pin(&der)?;
+
+    config.client_certificate_relays().get(&pin).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A self-signed certificate with its SPKI pin.
+    const CERTIFICATE: &str = "\
+-----BEGIN CERTIFICATE-----\n\
+MIIBdzCCAR2gAwIBAgIUf/+C+kaLNI7i9mQaxiJe5uyrGF8wCgYIKoZIzj0EAwIw\n\
+EDEOMAwGA1UEAwwFcmVsYXkwIBcNMjYxMDE2MDExMjU0WhgPMjEyNjA5MjIwMTEy\n\
+NTRaMBAxDjAMBgNVBAMMBXJlbGF5MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE\n\
+4fdufYV/zD7DCUkxdJuDVo1IcXYDTcSsJSp9ljpxk0dqVzCDPQ8rJFtNL1ngoioc\n\
+n2GzUCERfiakptJLdjxB86NTMFEwHQYDVR0OBBYEFCuiN3Am0zzMRWmHaAt0QKU1\n\
+hjA/MB8GA1UdIwQYMBaAFCuiN3Am0zzMRWmHaAt0QKU1hjA/MA8GA1UdEwEB/wQF\n\
+MAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIhAI+A6GzcouZFq/BGgWfiJ2i42FXcB5Q2\n\
+72+9TUdt/T1+AiAAvmBsPFiTwRV3mX7bAZmWl0Qe3+vnRBMjRfW8jvbNIg==\n\
+-----END CERTIFICATE-----\n";
+    const CERTIFICATE_PIN: &str = "sha256/ZdGIY5hbvimoEK4Eizt9epl8qVBf/RwB8UiTC4MaEVM=";
+
+    /// A CA certificate that signed [`SERVER_CERTIFICATE`].
+    const CA_CERTIFICATE: &str = "\
+-----BEGIN CERTIFICATE-----\n\
+MIIBjTCCATOgAwIBAgIUYYPFUORLjsFcu+erPqO/NWv8xcUwCgYIKoZIzj0EAwIw\n\
+EzERMA8GA1UEAwwIcmVsYXktY2EwIBcNMjYxMDE2MDkyMDEwWhgPMjEyNjA5MjIw\n\
+OTIwMTBaMBMxETAPBgNVBAMMCHJlbGF5LWNhMFkwEwYHKoZIzj0CAQYIKoZIzj0D\n\
+AQcDQgAE06n3NdK3SQOpamURPMnXAXCWZ4TORt0Jph+zUzqF0DQPQBdGxZojDjGn\n\
+/s0Ry71NBytGBJXHQiNuRXYlWCdagaNjMGEwHQYDVR0OBBYEFGnoRCkXjdOltDV8\n\
+XG0a8DqrUbXCMB8GA1UdIwQYMBaAFGnoRCkXjdOltDV8XG0a8DqrUbXCMA8GA1Ud\n\
+EwEB/wQFMAMBAf8wDgYDVR0PAQH/BAQDAgIEMAoGCCqGSM49BAMCA0gAMEUCIQD9\n\
+VWk8FwKrFReOP+f1NF6XE6eF4R/TwoAjSNSnkeeOhwIgBTQ0XfjwR0fYp3EzK9tt\n\
+d/9yKPgd+AKrO9DtsP61pxM=\n\
+-----END CERTIFICATE-----\n";
+    const CA_CERTIFICATE_PIN: &str = "sha256/Kd+kQveG7evlcp9pBWy0/ex7ReXnkd0zRYK5xUHmFfU=";
+
+    /// A server certificate for `relay.example.com`.
+    const SERVER_CERTIFICATE: &str = "\
+-----BEGIN CERTIFICATE-----\n\
+MIIByDCCAW6gAwIBAgIUfMpOH5eqsuWGljw9qkEIcpQQ1SQwCgYIKoZIzj0EAwIw\n\
+EzERMA8GA1UEAwwIcmVsYXktY2EwIBcNMjYxMDE2MDkyMDEwWhgPMjEyNjA5MjIw\n\
+OTIwMTBaMBwxGjAYBgNVBAMMEXJlbGF5LmV4YW1wbGUuY29tMFkwEwYHKoZIzj0C\n\
+AQYIKoZIzj0DAQcDQgAEB+upIpD1Ip9/8XlC8TFZDww5MpN+2PxubwH16THDPeRS\n\
+CLXGQgtAY2EZmvEKzIImAPCd3YSbuJEM1m9tUgrEy6OBlDCBkTAcBgNVHREEFTAT\n\
+ghFyZWxheS5leGFtcGxlLmNvbTAMBgNVHRMBAf8EAjAAMA4GA1UdDwEB/wQEAwIH\n\
+gDATBgNVHSUEDDAKBggrBgEFBQcDATAdBgNVHQ4EFgQUwEXHFV+cgi+34JEDO7fm\n\
+BnkwHsgwHwYDVR0jBBgwFoAUaehEKReN06W0NXxcbRrwOqtRtcIwCgYIKoZIzj0E\n\
+AwIDSAAwRQIhAOV7nh7gxwVrD0vhWiTjEXSprjRbsm8cJMoXafgjQvQdAiAk8CIf\n\
+9EtffL67IBHqW5s+M1PNYVt4moyhbZXKfO8h7w==\n\
+-----END CERTIFICATE-----\n";
+    const SERVER_CERTIFICATE_PIN: &str = "sha256/UgXk17f/QArjUvps5REURAxIeXhLg7OV5E+/xFz+XaE=";
+
+    fn der(pem: &str) -> Vec<u8> {
+        rustls_pemfile::certs(&mut pem.as_bytes())
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap()
+    }
+
+    /// Verifies the server certificate against a verifier that trusts the CA certificate.
+    fn verify_pinned(pins: &[&str]) -> Result<ServerCertVerified, rustls::Error> {
+        let mut roots = RootCertStore::empty();
+        roots.add_parsable_certificates(&[der(CA_CERTIFICATE)]);
+
+        let verifier = PinnedCertVerifier {
+            inner: WebPkiVerifier::new(roots, None),
+            pins: pins.iter().map(|pin| pin.parse().unwrap()).collect(),
+        };
+
+        // Both certificates are valid from 2026 until 2126.
+        let now = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_900_000_000);
+        verifier.verify_server_cert(
+            &Certificate(der(SERVER_CERTIFICATE)),
+            &[],
+            &ServerName::try_from("relay.example.com").unwrap(),
+            &mut std::iter::empty(),
+            &[],
+            now,
+        )
+    }
+
+    #[test]
+    fn test_pinned_cert_verifier() {
+        assert!(verify_pinned(&[SERVER_CERTIFICATE_PIN]).is_ok());
+        assert!(verify_pinned(&[CERTIFICATE_PIN, SERVER_CERTIFICATE_PIN]).is_ok());
+
+        let error = verify_pinned(&[CERTIFICATE_PIN]).unwrap_err();
+        assert!(matches!(error, rustls::Error::General(_)));
+
+        // The chain only contains the server certificate, so the root's pin does not match.
+        assert!(verify_pinned(&[CA_CERTIFICATE_PIN]).is_err());
+    }
+
+    #[test]
+    fn test_client_certificate_relay() {
+        let relay_id = RelayId::new_v4();
+        let config = Config::from_json_value(serde_json::json!({
+            "auth": {
+                "client_certificates": {
+                    "header": "x-ssl-client-cert",
+                    "trusted_proxies": ["10.0.0.0/8"],
+                    "relays": {CERTIFICATE_PIN: relay_id},
+                }
+            }
+        }))
+        .unwrap();
+
+        let header: String =
+            percent_encoding::utf8_percent_encode(CERTIFICATE, percent_encoding::NON_ALPHANUMERIC)
+                .collect();
+        assert_eq!(client_certificate_relay(&config, &header), Some(relay_id));
+        assert_eq!(
+            client_certificate_relay(&config, CERTIFICATE),
+            Some(relay_id)
+        );
+        assert_eq!(client_certificate_relay(&config, "invalid"), None);
+
+        let unknown = Config::default();
+        assert_eq!(client_certificate_relay(&unknown, &header), None);
+
+        assert!(config.is_client_certificate_proxy("10.1.2.3".parse().unwrap()));
+        assert!(!config.is_client_certificate_proxy("192.168.0.1".parse().unwrap()));
+        assert!(!unknown.is_client_certificate_proxy("10.1.2.3".parse().unwrap()));
+    }
+}
@@ -23,9 +23,12 @@
 //! ```no_run
 //! # use std::collections::BTreeMap;
 //!
-//! relay_statsd::init("myprefix", "localhost:8125", BTreeMap::new(), true, 1.0);
+//! relay_statsd::init("myprefix", "localhost:8125", BTreeMap::new(), true, 1.0, Vec::new());
 //! ```
 //!
+//! Metrics can be rewritten before they are sent using [`RelabelRule`]s, for example to drop
+//! high-cardinality tags.
+//!
 //! ## Macro Usage
 //!
 //! The recommended way to record metrics is by using the [`metric!`] macro. See the trait docs
@@ -59,6 +62,7 @@
 use std::collections::BTreeMap;
 use std::net::{ToSocketAddrs, UdpSocket};
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 use cadence::{
@@ -67,6 +71,12 @@ use cadence::{
 use parking_lot::RwLock;
 use rand::distributions::{Distribution, Uniform};
 
+use crate::relabel::RelabelingSink;
+
+mod relabel;
+
+pub use self::relabel::RelabelRule;
+
 /// Maximum number of metric events that can be queued before we start dropping them
 const METRICS_MAX_QUEUE_SIZE: usize = 100_000;
 
@@ -124,9 +134,14 @@ impl MetricsClient {
     }
 
     fn _should_send(&self) -> bool {
-        if self.sample_rate <= 0.0 {
+        let sample_rate = match f32::from_bits(SAMPLE_RATE_OVERRIDE.load(Ordering::Relaxed)) {
+            rate if rate.is_nan() => self.sample_rate,
+            rate => rate,
+        };
+
+        if sample_rate <= 0.0 {
             false
-        } else if self.sample_rate >= 1.0 {
+        } else if sample_rate >= 1.0 {
             true
         } else {
             // Using thread local RNG and uniform distribution here because Rng::gen_range is
@@ -135,13 +150,22 @@ impl MetricsClient {
             // details.
             let mut rng = rand::thread_rng();
             RNG_UNIFORM_DISTRIBUTION
-                .with(|uniform_dist| uniform_dist.sample(&mut rng) <= self.sample_rate)
+                .with(|uniform_dist| uniform_dist.sample(&mut rng) <= sample_rate)
         }
     }
 }
 
 static METRICS_CLIENT: RwLock<Option<Arc<MetricsClient>>> = RwLock::new(None);
 
+/// Bits of the sample rate that overrides the sample rate of all clients.
+///
+/// Clients are cached per thread, so the override is stored separately. `NaN` means that there is
+/// no override.
+static SAMPLE_RATE_OVERRIDE: AtomicU32 = AtomicU32::new(NO_SAMPLE_RATE_OVERRIDE);
+
+/// A `NaN` bit pattern that marks the absence of a sample rate override.
+const NO_SAMPLE_RATE_OVERRIDE: u32 = u32::MAX;
+
 thread_local! {
     static CURRENT_CLIENT: std::cell::RefCell<Option<Arc<MetricsClient>>>  = METRICS_CLIENT.read().clone().into();
     static RNG_UNIFORM_DISTRIBUTION: Uniform<f32> = Uniform::new(0.0, 1.0);
@@ -227,6 +251,7 @@ pub fn init<A: ToSocketAddrs>(
     default_tags: BTreeMap<String, String>,
     buffering: bool,
     sample_rate: f32,
+    relabel_rules: Vec<RelabelRule>,
 ) {
     let addrs: Vec<_> = host.to_socket_addrs().unwrap().collect();
     if !addrs.is_empty() {
@@ -249,11 +274,14 @@ pub fn init<A: ToSocketAddrs>(
 
     let statsd_client = if buffering {
         let udp_sink = BufferedUdpMetricSink::from(host, socket).unwrap();
-        let queuing_sink = QueuingMetricSink::with_capacity(udp_sink, METRICS_MAX_QUEUE_SIZE);
+        let relabeling_sink = RelabelingSink::new(udp_sink, prefix, relabel_rules);
+        let queuing_sink =
+            QueuingMetricSink::with_capacity(relabeling_sink, METRICS_MAX_QUEUE_SIZE);
         StatsdClient::from_sink(prefix, queuing_sink)
     } else {
         let simple_sink = UdpMetricSink::from(host, socket).unwrap();
-        StatsdClient::from_sink(prefix, simple_sink)
+        let relabeling_sink = RelabelingSink::new(simple_sink, prefix, relabel_rules);
+        StatsdClient::from_sink(prefix, relabeling_sink)
     };
     relay_log::debug!(
         "metrics buffering is {}",
@@ -268,6 +296,19 @@ pub fn init<A: ToSocketAddrs>(
     });
 }
 
+/// Overrides the sample rate of the metrics client at runtime.
+///
+/// The override applies to all threads immediately. Pass `None` to restore the sample rate the
+/// client was initialized with.
+pub fn set_sample_rate(sample_rate: Option<f32>) {
+    let bits = match sample_rate {
+        Some(rate) => rate.clamp(0., 1.).to_bits(),
+        None => NO_SAMPLE_RATE_OVERRIDE,
+    };
+
+    SAMPLE_RATE_OVERRIDE.store(bits, Ordering::Relaxed);
+}
+
 /// Invoke a callback with the current statsd client.
 ///
 /// If statsd is not configured the callback is not invoked.  For the most part
@@ -0,0 +1,206 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io;
+
+use cadence::MetricSink;
+#[cfg(feature = "jsonschema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A rule that rewrites metrics emitted by Relay before they are sent to statsd.
+///
+/// All rules matching the original name of a metric are applied in order. This allows to drop
+/// high-cardinality tags, rename metrics, and add static tags such as the region of a Relay
+/// without changing the code that emits the metrics.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "jsonschema", derive(JsonSchema))]
+#[serde(default)]
+pub struct RelabelRule {
+    /// Names of the metrics this rule applies to, without the metrics prefix.
+    ///
+    /// A trailing `*` matches all metrics starting with the given name. Rules without names
+    /// apply to all metrics.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub metrics: Vec<String>,
+
+    /// The new name of matching metrics, without the metrics prefix.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rename: Option<String>,
+
+    /// Tags to remove from matching metrics.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub drop_tags: Vec<String>,
+
+    /// Static tags to add to matching metrics.
+    ///
+    /// Existing tags with the same name are replaced.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub add_tags: BTreeMap<String, String>,
+}
+
+impl RelabelRule {
+    /// Returns `true` if this rule applies to the metric with the given name.
+    fn matches(&self, name: &str) -> bool {
+        self.metrics.is_empty()
+            || self
+                .metrics
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => name.starts_with(prefix),
+                    None => name == pattern,
+                })
+    }
+}
+
+/// Applies [`RelabelRule`]s to metrics in the statsd line format.
+#[derive(Debug)]
+struct Relabeler {
+    prefix: String,
+    rules: Vec<RelabelRule>,
+}
+
+impl Relabeler {
+    fn new(prefix: &str, rules: Vec<RelabelRule>) -> Self {
+        // The statsd client separates the prefix from metric names with a dot.
+        let prefix = if prefix.is_empty() || prefix.ends_with('.') {
+            prefix.to_owned()
+        } else {
+            format!("{prefix}.")
+        };
+
+        Self { prefix, rules }
+    }
+
+    /// Returns the metric line with all matching rules applied.
+    ///
+    /// Lines are formatted as `<name>:<value>|<type>[|@<rate>][|#<tags>]`, where tags are a
+    /// comma-separated list of `<key>:<value>` pairs.
+    fn relabel<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let Some((full_name, rest)) = line.split_once(':') else {
+            return Cow::Borrowed(line);
+        };
+
+        let original_name = full_name
+            .strip_prefix(self.prefix.as_str())
+            .unwrap_or(full_name);
+
+        let mut rules = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(original_name))
+            .peekable();
+
+        if rules.peek().is_none() {
+            return Cow::Borrowed(line);
+        }
+
+        let mut segments = Vec::new();
+        let mut tags = Vec::new();
+        for segment in rest.split('|') {
+            match segment.strip_prefix('#') {
+                Some(tag_list) => tags.extend(
+                    tag_list
+                        .split(',')
+                        .map(|tag| tag.split_once(':').unwrap_or((tag, ""))),
+                ),
+                None => segments.push(segment),
+            }
+        }
+
+        let mut name = original_name;
+        for rule in rules {
+            if let Some(ref rename) = rule.rename {
+                name = rename;
+            }
+
+            tags.retain(|(key, _)| {
+                !rule.drop_tags.iter().any(|t| t == key) && !rule.add_tags.contains_key(*key)
+            });
+            tags.extend(
+                rule.add_tags
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str())),
+            );
+        }
+
+        let mut relabeled = format!("{}{}:{}", self.prefix, name, segments.join("|"));
+        for (index, (key, value)) in tags.iter().enumerate() {
+            relabeled.push_str(if index == 0 { "|#" } else { "," });
+            relabeled.push_str(key);
+            if !value.is_empty() {
+                relabeled.push(':');
+                relabeled.push_str(value);
+            }
+        }
+
+        Cow::Owned(relabeled)
+    }
+}
+
+/// A [`MetricSink`] that applies [`RelabelRule`]s before passing metrics to the inner sink.
+pub(crate) struct RelabelingSink<S> {
+    inner: S,
+    relabeler: Relabeler,
+}
+
+impl<S> RelabelingSink<S> {
+    /// Wraps a sink of a client with the given metrics prefix.
+    pub fn new(inner: S, prefix: &str, rules: Vec<RelabelRule>) -> Self {
+        Self {
+            inner,
+            relabeler: Relabeler::new(prefix, rules),
+        }
+    }
+}
+
+impl<S: MetricSink> MetricSink for RelabelingSink<S> {
+    fn emit(&self, metric: &str) -> io::Result<usize> {
+        self.inner.emit(&self.relabeler.relabel(metric))
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relabeler(rules: serde_json::Value) -> Relabeler {
+        Relabeler::new("sentry.relay", serde_json::from_value(rules).unwrap())
+    }
+
+    #[test]
+    fn test_relabel_tags() {
+        let relabeler = relabeler(serde_json::json!([
+            {"metrics": ["event.*"], "drop_tags": ["sdk"], "add_tags": {"region": "eu"}},
+            {"add_tags": {"pop": "fra"}},
+        ]));
+
+        assert_eq!(
+            relabeler.relabel("sentry.relay.event.accepted:1|c|#sdk:python,region:us,handling"),
+            "sentry.relay.event.accepted:1|c|#handling,region:eu,pop:fra"
+        );
+        assert_eq!(
+            relabeler.relabel("sentry.relay.requests:5|ms|@0.5|#sdk:python"),
+            "sentry.relay.requests:5|ms|@0.5|#sdk:python,pop:fra"
+        );
+    }
+
+    #[test]
+    fn test_relabel_rename() {
+        let relabeler = relabeler(serde_json::json!([
+            {"metrics": ["event.accepted"], "rename": "events.accepted"},
+        ]));
+
+        assert_eq!(
+            relabeler.relabel("sentry.relay.event.accepted:1|c"),
+            "sentry.relay.events.accepted:1|c"
+        );
+
+        // Other metrics are passed through unchanged.
+        let line = "sentry.relay.event.rejected:1|c|#reason:invalid";
+        assert!(matches!(relabeler.relabel(line), Cow::Borrowed(l) if l == line));
+    }
+}
@@ -737,6 +737,11 @@ impl<I: Interface> Addr<I> {
         rx
     }
 
+    /// Returns the number of messages that have been sent but not yet received by the service.
+    pub fn queue_size(&self) -> u64 {
+        self.queue_size.load(Ordering::Relaxed)
+    }
+
     /// Returns a handle that can receive a given message independent of the interface.
     ///
     /// See [`Recipient`] for more information and examples.
@@ -1,4 +1,6 @@
+use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, io};
 
 use anyhow::{anyhow, bail, Result};
@@ -6,8 +8,10 @@ use clap::ArgMatches;
 use clap_complete::Shell;
 use dialoguer::{Confirm, Select};
 use relay_config::{
-    Config, ConfigError, ConfigErrorKind, Credentials, MinimalConfig, OverridableConfig, RelayMode,
+    ByteSize, Config, ConfigError, ConfigErrorKind, Credentials, MinimalConfig, OverridableConfig,
+    RelayMode,
 };
+use relay_server::{LoadgenOptions, SendEventOptions, SendEventReport, TrackedResult};
 use uuid::Uuid;
 
 use crate::cliapp::make_app;
@@ -41,6 +45,10 @@ pub fn execute() -> Result<()> {
     if let Some(matches) = matches.subcommand_matches("config") {
         if let Some(matches) = matches.subcommand_matches("init") {
             return init_config(config_path, matches);
+        } else if let Some(matches) = matches.subcommand_matches("schema") {
+            return print_config_schema(matches);
+        } else if let Some(matches) = matches.subcommand_matches("validate") {
+            return validate_config(config_path, matches);
         }
     } else if let Some(matches) = matches.subcommand_matches("generate-completions") {
         return generate_completions(matches);
@@ -52,7 +60,8 @@ pub fn execute() -> Result<()> {
     let env_config = extract_config_env_vars();
     config.apply_override(env_config)?;
 
-    relay_log::init(config.logging(), config.sentry());
+    // Logging settings changed through the admin API take precedence over the file.
+    relay_log::init(&config.effective_logging(), config.sentry());
 
     if let Some(matches) = matches.subcommand_matches("config") {
         manage_config(&config, matches)
@@ -63,6 +72,12 @@ pub fn execute() -> Result<()> {
         let arg_config = extract_config_args(matches);
         config.apply_override(arg_config)?;
         run(config, matches)
+    } else if let Some(matches) = matches.subcommand_matches("replay") {
+        replay(&config, matches)
+    } else if let Some(matches) = matches.subcommand_matches("loadgen") {
+        loadgen(&config, matches)
+    } else if let Some(matches) = matches.subcommand_matches("send-event") {
+        send_event(&config, matches)
     } else {
         unreachable!();
     }
@@ -93,6 +108,7 @@ pub fn extract_config_args(matches: &ArgMatches) -> OverridableConfig {
         outcome_source: matches.get_one("source_id").cloned(),
         shutdown_timeout: matches.get_one("shutdown_timeout").cloned(),
         aws_runtime_api: matches.get_one("aws_runtime_api").cloned(),
+        inspect: matches.get_flag("inspect").then(|| "true".to_owned()),
     }
 }
 
@@ -113,6 +129,7 @@ pub fn extract_config_env_vars() -> OverridableConfig {
         outcome_source: None, //already extracted in params
         shutdown_timeout: env::var("SHUTDOWN_TIMEOUT").ok(),
         aws_runtime_api: None,
+        inspect: env::var("RELAY_INSPECT").ok(),
     }
 }
 
@@ -138,7 +155,7 @@ pub fn manage_credentials(mut config: Config, matches: &ArgMatches) -> Result<()
                     .parse()
                     .map_err(|_| anyhow!("invalid secret key supplied"))?,
             ),
-            None => config.credentials().map(|x| x.secret_key.clone()),
+            None => config.credentials().map(|x| x.secret_key),
         };
         let public_key = match matches.get_one::<String>("public_key") {
             Some(value) => Some(
@@ -146,7 +163,7 @@ pub fn manage_credentials(mut config: Config, matches: &ArgMatches) -> Result<()
                     .parse()
                     .map_err(|_| anyhow!("invalid public key supplied"))?,
             ),
-            None => config.credentials().map(|x| x.public_key.clone()),
+            None => config.credentials().map(|x| x.public_key),
         };
         let id = match matches.get_one::<String>("id").map(String::as_str) {
             Some("random") => Some(Uuid::new_v4()),
@@ -209,6 +226,33 @@ pub fn manage_credentials(mut config: Config, matches: &ArgMatches) -> Result<()
         } else {
             println!("No credentials");
         }
+    } else if let Some(matches) = matches.subcommand_matches("rotate") {
+        let Some(previous) = config.credentials() else {
+            bail!("no stored credentials, generate them with `relay credentials generate`");
+        };
+
+        if !matches.get_flag("yes")
+            && !Confirm::with_theme(get_theme())
+                .with_prompt("Replace the stored key pair?")
+                .interact()?
+        {
+            return Ok(());
+        }
+
+        let credentials = previous.rotate();
+        let payload = serde_json::json!({
+            previous.id.to_string(): {
+                "public_key": credentials.public_key,
+                "secondary_public_keys": [previous.public_key],
+            }
+        });
+
+        config.replace_credentials(Some(credentials))?;
+        println!("Stored rotated credentials:");
+        setup::dump_credentials(&config);
+        println!();
+        println!("Add this entry to `auth.static_relays` of the upstream to register the new key:");
+        println!("{}", serde_json::to_string_pretty(&payload)?);
     } else if matches.subcommand_matches("show").is_some() {
         if !config.has_credentials() {
             bail!("no stored credentials");
@@ -238,6 +282,46 @@ pub fn manage_config(config: &Config, matches: &ArgMatches) -> Result<()> {
     }
 }
 
+pub fn print_config_schema(matches: &ArgMatches) -> Result<()> {
+    let schema = Config::json_schema();
+
+    match matches.get_one::<PathBuf>("output") {
+        Some(path) => serde_json::to_writer_pretty(File::create(path)?, &schema)?,
+        None => println!("{}", serde_json::to_string_pretty(&schema)?),
+    }
+
+    Ok(())
+}
+
+pub fn validate_config<P: AsRef<Path>>(config_path: P, matches: &ArgMatches) -> Result<()> {
+    let diagnostics = Config::validate(config_path)?;
+
+    match matches.get_one("format").map(String::as_str).unwrap() {
+        "text" => {
+            for diagnostic in &diagnostics {
+                println!("{diagnostic}");
+            }
+        }
+        "json" => println!("{}", serde_json::to_string_pretty(&diagnostics)?),
+        _ => unreachable!(),
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+
+    if errors > 0 || (warnings > 0 && matches.get_flag("strict")) {
+        bail!("config is invalid: {errors} error(s), {warnings} warning(s)");
+    }
+
+    if diagnostics.is_empty() {
+        eprintln!("config is valid");
+    } else {
+        eprintln!("config is valid: {warnings} warning(s)");
+    }
+
+    Ok(())
+}
+
 pub fn init_config<P: AsRef<Path>>(config_path: P, _matches: &ArgMatches) -> Result<()> {
     let mut done_something = false;
     let config_path = env::current_dir()?.join(config_path.as_ref());
@@ -353,9 +437,127 @@ pub fn generate_completions(matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+pub fn replay(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let path = matches.get_one::<PathBuf>("path").unwrap();
+    let target = match matches.get_one::<String>("target") {
+        Some(target) => target.clone(),
+        None => format!("http://{}", config.listen_addr()),
+    };
+
+    let report = relay_server::replay(path, &target, matches.get_flag("realtime"))?;
+
+    println!("Replayed {} requests to {target}", report.sent);
+    for (status, count) in &report.statuses {
+        println!("  {status}: {count}");
+    }
+    if report.failed > 0 {
+        println!("  failed: {}", report.failed);
+    }
+
+    Ok(())
+}
+
+pub fn loadgen(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let parse_size = |name: &str| -> Result<usize> {
+        let value = matches.get_one::<String>(name).unwrap();
+        let size = value
+            .parse::<ByteSize>()
+            .map_err(|_| anyhow!("invalid size `{value}`"))?;
+        Ok(size.as_bytes())
+    };
+
+    let options = LoadgenOptions {
+        target: match matches.get_one::<String>("target") {
+            Some(target) => target.clone(),
+            None => format!("http://{}", config.listen_addr()),
+        },
+        dsn: matches.get_one::<String>("dsn").unwrap().clone(),
+        rate: *matches.get_one("rate").unwrap(),
+        duration: Duration::from_secs(*matches.get_one("duration").unwrap()),
+        concurrency: *matches.get_one("concurrency").unwrap(),
+        mix: matches.get_one::<String>("mix").unwrap().parse()?,
+        min_size: parse_size("min_size")?,
+        max_size: parse_size("max_size")?,
+    };
+
+    println!(
+        "Sending {} envelopes per second to {} for {}s",
+        options.rate,
+        options.target,
+        options.duration.as_secs()
+    );
+    let report = relay_server::loadgen(options)?;
+
+    let secs = report.elapsed.as_secs_f64();
+    println!(
+        "Sent {} envelopes in {secs:.1}s ({:.1}/s)",
+        report.sent,
+        report.sent as f64 / secs
+    );
+    for (status, count) in &report.statuses {
+        println!("  {status}: {count}");
+    }
+    if report.failed > 0 {
+        println!("  failed: {}", report.failed);
+    }
+
+    println!("Latency:");
+    for (name, percentile) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("max", 1.0)] {
+        if let Some(latency) = report.percentile(percentile) {
+            println!("  {name}: {:.1}ms", latency.as_secs_f64() * 1000.0);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn send_event(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let admin_token = match matches.get_one::<String>("admin_token") {
+        Some(token) => token.clone(),
+        None => match config.admin_token() {
+            Some(token) => token.to_owned(),
+            None => bail!("an admin token is required, pass --admin-token or set admin.token"),
+        },
+    };
+
+    let options = SendEventOptions {
+        target: match matches.get_one::<String>("target") {
+            Some(target) => target.clone(),
+            None => format!("http://{}", config.listen_addr()),
+        },
+        dsn: matches.get_one::<String>("dsn").unwrap().clone(),
+        message: matches.get_one::<String>("message").unwrap().clone(),
+        timeout: Duration::from_secs(*matches.get_one("timeout").unwrap()),
+        admin_token,
+    };
+
+    println!("Sending test event to {}", options.target);
+    let timeout = options.timeout.as_secs();
+    let (event_id, report) = relay_server::send_event(options)?;
+    println!("  event id: {event_id}");
+
+    match report {
+        SendEventReport::Refused { status, body } => {
+            bail!("event refused with status {status}: {body}")
+        }
+        SendEventReport::Processed(TrackedResult::Accepted) => {
+            println!("Event accepted and sent upstream");
+            Ok(())
+        }
+        SendEventReport::Processed(TrackedResult::Rejected { reason }) => {
+            bail!("event dropped: {reason}")
+        }
+        SendEventReport::Pending => bail!(
+            "no result for event after {timeout}s, check that the admin API of the relay is enabled"
+        ),
+    }
+}
+
 pub fn run(config: Config, _matches: &ArgMatches) -> Result<()> {
     setup::dump_spawn_infos(&config);
     setup::check_config(&config)?;
+    #[cfg(feature = "crash-handler")]
+    setup::init_crash_context(&config);
     setup::init_metrics(&config)?;
     relay_server::run(config)?;
     Ok(())
@@ -144,6 +144,16 @@ pub fn make_app() -> Command {
                             the AWS_LAMBDA_RUNTIME_API environment variable. This integrates Relay \
                             with the lambda execution environment lifecycle.",
                         ),
+                )
+                .arg(
+                    Arg::new("inspect")
+                        .long("inspect")
+                        .help(
+                            "Write a sample of processed envelopes with their processing \
+                            decisions. Requires `inspect.path` in the config, see the `inspect` \
+                            section of the config for the sample rate and output directory.",
+                        )
+                        .action(ArgAction::SetTrue),
                 ),
         )
         .subcommand(
@@ -238,6 +248,24 @@ pub fn make_app() -> Command {
                                 .short('i')
                                 .help("The relay ID to set"),
                         ),
+                )
+                .subcommand(
+                    Command::new("rotate")
+                        .about("Rotate the key pair of the stored credentials")
+                        .after_help(
+                            "This generates a new key pair for the stored relay ID and \
+                             stores it.  The command prints the entry for the upstream's \
+                             static relays, which accepts the previous public key as \
+                             secondary key until all relays sharing the credentials have \
+                             been updated.  Afterwards, the secondary key can be removed \
+                             from the upstream.",
+                        )
+                        .arg(
+                            Arg::new("yes")
+                                .long("yes")
+                                .action(ArgAction::SetTrue)
+                                .help("Do not prompt for confirmation"),
+                        ),
                 ),
         )
         .subcommand(
@@ -278,6 +306,204 @@ pub fn make_app() -> Command {
                                 .default_value("yaml")
                                 .help("The output format"),
                         ),
+                )
+                .subcommand(
+                    Command::new("schema")
+                        .about("Print the JSON Schema of the config file")
+                        .after_help(
+                            "This prints a JSON Schema describing all options of the \
+                             config file, including their documentation and default \
+                             values.  It can be used to validate config files in \
+                             editors and CI.",
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .short('o')
+                                .long("output")
+                                .value_name("PATH")
+                                .value_parser(ValueParser::path_buf())
+                                .value_hint(ValueHint::FilePath)
+                                .help("Write the schema to a file instead of stdout"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("validate")
+                        .about("Validate the config without starting the relay")
+                        .after_help(
+                            "This parses the config file and credentials and checks \
+                             them for unknown fields, invalid values, and settings \
+                             that conflict with each other.  Every problem is \
+                             printed with the path of the affected field.  The \
+                             command exits with a nonzero status code if there are \
+                             errors, which makes it suitable for use in CI.",
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .short('f')
+                                .long("format")
+                                .value_parser(["text", "json"])
+                                .default_value("text")
+                                .help("The output format"),
+                        )
+                        .arg(
+                            Arg::new("strict")
+                                .long("strict")
+                                .action(ArgAction::SetTrue)
+                                .help("Also fail if there are warnings"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("replay")
+                .about("Replay captured requests against a relay")
+                .after_help(
+                    "This sends requests recorded via the `capture` section of the \
+                     config to a relay in the order they were recorded.  Use this to \
+                     reproduce processing issues or to compare config changes \
+                     against real traffic.  By default, requests are sent to the \
+                     address this relay listens on.",
+                )
+                .arg(
+                    Arg::new("path")
+                        .value_name("DIR")
+                        .required(true)
+                        .value_parser(ValueParser::path_buf())
+                        .value_hint(ValueHint::DirPath)
+                        .help("The directory containing captured requests"),
+                )
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("URL")
+                        .value_hint(ValueHint::Url)
+                        .help("The URL of the relay to send requests to"),
+                )
+                .arg(
+                    Arg::new("realtime")
+                        .long("realtime")
+                        .action(ArgAction::SetTrue)
+                        .help("Preserve the timing of the captured requests"),
+                ),
+        )
+        .subcommand(
+            Command::new("loadgen")
+                .about("Send generated envelopes to a relay for load testing")
+                .after_help(
+                    "This sends envelopes with errors, transactions, sessions, and \
+                     metrics to a relay at a fixed rate and reports the latency \
+                     until they are accepted.  Use this for capacity planning.  By \
+                     default, envelopes are sent to the address this relay listens \
+                     on.",
+                )
+                .arg(
+                    Arg::new("dsn")
+                        .long("dsn")
+                        .value_name("DSN")
+                        .required(true)
+                        .help("The DSN of the project that receives the envelopes"),
+                )
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("URL")
+                        .value_hint(ValueHint::Url)
+                        .help("The URL of the relay to send envelopes to"),
+                )
+                .arg(
+                    Arg::new("rate")
+                        .long("rate")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(f64))
+                        .default_value("10")
+                        .help("Envelopes per second"),
+                )
+                .arg(
+                    Arg::new("duration")
+                        .long("duration")
+                        .value_name("SECONDS")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("10")
+                        .help("Duration of the load test"),
+                )
+                .arg(
+                    Arg::new("concurrency")
+                        .long("concurrency")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("16")
+                        .help("Maximum number of requests in flight"),
+                )
+                .arg(
+                    Arg::new("mix")
+                        .long("mix")
+                        .value_name("WEIGHTS")
+                        .default_value("error=4,transaction=4,session=1,metrics=1")
+                        .help("Relative weights of the generated envelope kinds"),
+                )
+                .arg(
+                    Arg::new("min_size")
+                        .long("min-size")
+                        .value_name("SIZE")
+                        .default_value("1KB")
+                        .help("Minimum payload size"),
+                )
+                .arg(
+                    Arg::new("max_size")
+                        .long("max-size")
+                        .value_name("SIZE")
+                        .default_value("16KB")
+                        .help("Maximum payload size"),
+                ),
+        )
+        .subcommand(
+            Command::new("send-event")
+                .about("Send a test event through a relay and wait for its result")
+                .after_help(
+                    "This submits a single error event for the given DSN and waits \
+                     until the relay has sent it upstream or dropped it.  The \
+                     command fails if the event is refused, filtered, rate limited, \
+                     or cannot be sent upstream.  Use this to verify a deployment.  \
+                     By default, the event is sent to the address this relay listens \
+                     on.  The result is read from the admin API of the relay, which \
+                     requires an admin token.",
+                )
+                .arg(
+                    Arg::new("dsn")
+                        .long("dsn")
+                        .value_name("DSN")
+                        .required(true)
+                        .help("The DSN of the project that receives the event"),
+                )
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("URL")
+                        .value_hint(ValueHint::Url)
+                        .help("The URL of the relay to send the event to"),
+                )
+                .arg(
+                    Arg::new("message")
+                        .long("message")
+                        .value_name("MESSAGE")
+                        .default_value("Test event sent by relay send-event")
+                        .help("The message of the test event"),
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .value_name("SECONDS")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("30")
+                        .help("Maximum time to wait for the result"),
+                )
+                .arg(
+                    Arg::new("admin_token")
+                        .long("admin-token")
+                        .value_name("TOKEN")
+                        .help(
+                            "The token to query the result from the admin API. \
+                             Defaults to the admin token of this relay's config.",
+                        ),
                 ),
         )
         .subcommand(
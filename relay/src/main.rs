@@ -23,8 +23,9 @@
 //!
 //! - `processing`: Includes event ingestion and processing functionality. This should only be
 //!   specified when compiling Relay as Sentry service. Standalone Relays do not need this feature.
-//! - `crash-handler`: Allows native crash reporting for segfaults and out-of-memory situations when
-//!   internal error reporting to Sentry is enabled.
+//! - `crash-handler`: Allows native crash reporting for segfaults and out-of-memory situations.
+//!   Minidumps and last-breath reports are written to `sentry.crash_dir` and uploaded on the next
+//!   start when internal error reporting to Sentry is enabled.
 //!
 //! # Workspace Crates
 //!
@@ -131,6 +132,12 @@ use relay_log::Hub;
 #[global_allocator]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+/// Enables heap profiling in jemalloc, which remains inactive until requested via the admin API.
+#[cfg(all(target_os = "linux", feature = "profiling"))]
+#[allow(non_upper_case_globals)]
+#[export_name = "_rjem_malloc_conf"]
+pub static malloc_conf: &[u8] = b"prof:true,prof_active:false,lg_prof_sample:19\0";
+
 pub fn main() {
     let exit_code = match cli::execute() {
         Ok(()) => 0,
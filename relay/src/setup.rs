@@ -2,7 +2,11 @@ use anyhow::Result;
 use relay_config::{Config, RelayMode};
 
 pub fn check_config(config: &Config) -> Result<()> {
-    if config.relay_mode() == RelayMode::Managed && config.credentials().is_none() {
+    // Credentials may be fetched from the secrets provider when the server starts.
+    if config.relay_mode() == RelayMode::Managed
+        && config.credentials().is_none()
+        && config.secrets_provider().is_none()
+    {
         anyhow::bail!(
             "relay has no credentials, which are required in managed mode. \
              Generate some with \"relay credentials generate\" first.",
@@ -32,7 +36,7 @@ pub fn dump_spawn_infos(config: &Config) {
         Some(key) => relay_log::info!("  public key: {key}"),
         None => relay_log::info!("  public key: -"),
     };
-    relay_log::info!("  log level: {}", config.logging().level);
+    relay_log::info!("  log level: {}", config.log_level());
 }
 
 /// Dumps out credential info.
@@ -47,6 +51,21 @@ pub fn dump_credentials(config: &Config) {
     };
 }
 
+/// Adds information on the active config to crash reports.
+#[cfg(feature = "crash-handler")]
+pub fn init_crash_context(config: &Config) {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    if let Ok(yaml) = config.to_yaml_string() {
+        let mut hasher = DefaultHasher::new();
+        yaml.hash(&mut hasher);
+        relay_log::crash::set_context("config_hash", format!("{:016x}", hasher.finish()));
+    }
+
+    relay_log::crash::set_context("mode", config.relay_mode().to_string());
+}
+
 /// Initialize the metric system.
 pub fn init_metrics(config: &Config) -> Result<()> {
     let addrs = config.statsd_addrs()?;
@@ -66,6 +85,7 @@ pub fn init_metrics(config: &Config) -> Result<()> {
         default_tags,
         config.metrics_buffering(),
         config.metrics_sample_rate(),
+        config.metrics_relabel_rules().to_vec(),
     );
 
     Ok(())
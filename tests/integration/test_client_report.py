@@ -1,3 +1,4 @@
+import json
 import pytest
 from queue import Empty
 from datetime import datetime, timezone, timedelta
@@ -105,3 +106,48 @@ def test_client_reports_bad_timestamps(relay, mini_sentry):
     # we should not have received any outcomes because they are too far into the future
     with pytest.raises(Empty):
         mini_sentry.captured_outcomes.get(timeout=1.5)["outcomes"]
+
+
+def test_client_reports_fold_downstream(relay, mini_sentry):
+    config = {
+        "outcomes": {
+            "emit_outcomes": "as_client_reports",
+            "emit_client_outcomes": False,
+            "aggregator": {
+                "bucket_interval": 1,
+                "flush_interval": 1,
+            },
+            "client_reports": {
+                "group_by_timestamp": False,
+                "fold_downstream": True,
+            },
+        }
+    }
+
+    relay = relay(mini_sentry, config)
+
+    project_id = 42
+    mini_sentry.add_full_project_config(project_id)
+
+    report_payload = {
+        "timestamp": datetime.now(tz=timezone.utc).isoformat(),
+        "discarded_events": [
+            {"reason": "queue_overflow", "category": "error", "quantity": 42},
+        ],
+    }
+
+    relay.send_client_report(project_id, report_payload)
+    relay.send_client_report(project_id, report_payload)
+
+    # Both reports are folded into a single client report of this Relay
+    envelope = mini_sentry.captured_events.get(timeout=3)
+    assert mini_sentry.captured_events.qsize() == 0
+
+    items = envelope.items
+    assert len(items) == 1
+    assert items[0].headers["type"] == "client_report"
+
+    payload = json.loads(items[0].payload.bytes)
+    assert payload["discarded_events"] == [
+        {"reason": "queue_overflow", "category": "error", "quantity": 84}
+    ]